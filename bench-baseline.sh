@@ -0,0 +1,51 @@
+#!/bin/bash
+# Criterion baselines for the Rust hot paths.
+#
+#   ./bench-baseline.sh save [name]      record a baseline (default: main)
+#   ./bench-baseline.sh compare [name]   compare against it; exits 1 on a
+#                                        statistically significant regression
+#
+# Typical use: `save` on main before a performance refactor, then `compare`
+# on the branch. Baselines live under target/criterion, so save and compare
+# on the same machine.
+#
+# BENCH_PACKAGES overrides which crates are benchmarked; extra arguments
+# after the name go to criterion (e.g. a filter such as `transform_event`).
+
+set -euo pipefail
+
+cd "$(dirname "$0")"
+
+MODE=${1:-}
+NAME=${2:-main}
+shift $(( $# < 2 ? $# : 2 ))
+PACKAGES=${BENCH_PACKAGES:-"event-ingestion-service extension-runtime-service"}
+
+case "$MODE" in
+    save) FLAG=--save-baseline ;;
+    compare) FLAG=--baseline ;;
+    *)
+        echo "usage: $0 save|compare [baseline-name] [criterion args...]" >&2
+        exit 2
+        ;;
+esac
+
+LOG=$(mktemp)
+trap 'rm -f "$LOG"' EXIT
+
+for package in $PACKAGES; do
+    echo "== $package"
+    cargo bench -p "$package" --bench '*' -- "$FLAG" "$NAME" "$@" 2>&1 | tee -a "$LOG"
+done
+
+if [ "$MODE" = compare ]; then
+    # Benchmark names start a line; criterion's verdict follows indented
+    if ! awk '/^[^ ]/ { name = $1 } /Performance has regressed/ { print "  " name; n++ } END { exit n > 0 }' "$LOG" > "$LOG.regressed"; then
+        echo "Regressed against baseline '$NAME':" >&2
+        cat "$LOG.regressed" >&2
+        rm -f "$LOG.regressed"
+        exit 1
+    fi
+    rm -f "$LOG.regressed"
+    echo "No regressions against baseline '$NAME'"
+fi
@@ -0,0 +1,274 @@
+//! Signed audit trail of admin and configuration changes.
+//!
+//! Services record every change an operator makes through their admin APIs
+//! or tooling (plan changes, contract proposals, module uploads) as an
+//! [`AuditEvent`], published to the audit topic by an [`AuditTrail`]. Each
+//! event is signed with HMAC-SHA256 under the shared `AUDIT_SIGNING_KEY`, so
+//! the stored trail can be checked for tampering. The event ingestion
+//! service stores the topic in ClickHouse and serves it from `GET /audit`,
+//! verifying signatures as it reads.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use crm_config::{ConfigLoader, Configurable};
+use prometheus::IntCounterVec;
+use rdkafka::config::ClientConfig;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{error, info};
+use warp::Filter;
+
+/// Carries the acting user; the gateway sets it from the caller's token.
+pub const ACTOR_HEADER: &str = "x-user-id";
+
+static RECORDED: OnceLock<IntCounterVec> = OnceLock::new();
+
+#[derive(Debug)]
+pub enum AuditError {
+    Kafka(rdkafka::error::KafkaError),
+    Config(crm_config::ConfigError),
+}
+
+impl std::fmt::Display for AuditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditError::Kafka(e) => write!(f, "audit Kafka error: {}", e),
+            AuditError::Config(e) => write!(f, "audit configuration: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AuditError {}
+
+impl From<rdkafka::error::KafkaError> for AuditError {
+    fn from(e: rdkafka::error::KafkaError) -> Self {
+        AuditError::Kafka(e)
+    }
+}
+
+/// One change made by an operator.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub id: String,
+    /// Unix milliseconds.
+    pub timestamp: i64,
+    /// The service the change was made through; set by the trail.
+    pub service: String,
+    /// Who made it, from [`ACTOR_HEADER`].
+    pub actor: String,
+    /// What was done, e.g. `tenant.plan_changed`.
+    pub action: String,
+    /// What it was done to, e.g. the tenant id or event type.
+    pub resource: String,
+    pub tenant_id: Option<String>,
+    /// Parameters of the change, such as the new plan.
+    pub details: Value,
+    /// Base64 HMAC-SHA256 over the other fields; empty until recorded.
+    #[serde(default)]
+    pub signature: String,
+}
+
+impl AuditEvent {
+    pub fn new(actor: impl Into<String>, action: impl Into<String>, resource: impl Into<String>) -> Self {
+        AuditEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as i64)
+                .unwrap_or_default(),
+            service: String::new(),
+            actor: actor.into(),
+            action: action.into(),
+            resource: resource.into(),
+            tenant_id: None,
+            details: Value::Object(Default::default()),
+            signature: String::new(),
+        }
+    }
+
+    pub fn tenant(mut self, tenant_id: impl Into<String>) -> Self {
+        self.tenant_id = Some(tenant_id.into());
+        self
+    }
+
+    pub fn details(mut self, details: Value) -> Self {
+        self.details = details;
+        self
+    }
+
+    pub fn sign(&mut self, key: &SigningKey) {
+        self.signature = STANDARD.encode(hmac::sign(&key.0, self.signed_content().as_bytes()));
+    }
+
+    /// Whether the signature matches the event as it is now.
+    pub fn verify(&self, key: &SigningKey) -> bool {
+        match STANDARD.decode(&self.signature) {
+            Ok(signature) => hmac::verify(&key.0, self.signed_content().as_bytes(), &signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// Every field but the signature, as a JSON array so no field's content
+    /// can pass for another's.
+    fn signed_content(&self) -> String {
+        json!([
+            self.id,
+            self.timestamp,
+            self.service,
+            self.actor,
+            self.action,
+            self.resource,
+            self.tenant_id,
+            self.details,
+        ])
+        .to_string()
+    }
+}
+
+/// The shared key audit events are signed with.
+pub struct SigningKey(hmac::Key);
+
+impl SigningKey {
+    pub fn new(secret: &str) -> Self {
+        SigningKey(hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()))
+    }
+}
+
+/// Audit settings; loaded from `AUDIT_*` variables.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    pub kafka_brokers: String,
+    pub topic: String,
+    /// HMAC key shared by every service; unset disables the trail. Usually
+    /// a `secret://` reference.
+    pub signing_key: String,
+    /// How long recording a change may wait for Kafka to acknowledge it.
+    pub delivery_timeout_ms: u64,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig {
+            kafka_brokers: "localhost:9092".to_string(),
+            topic: "admin-audit".to_string(),
+            signing_key: String::new(),
+            delivery_timeout_ms: 5000,
+        }
+    }
+}
+
+impl Configurable for AuditConfig {
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if self.topic.is_empty() {
+            problems.push("topic must be set".to_string());
+        }
+        if self.delivery_timeout_ms == 0 {
+            problems.push("delivery_timeout_ms must be positive".to_string());
+        }
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+}
+
+impl AuditConfig {
+    /// Defaults, then `AUDIT_CONFIG_FILE`, then `AUDIT_*` variables (e.g.
+    /// `AUDIT_SIGNING_KEY`).
+    pub fn from_env() -> Result<Self, AuditError> {
+        ConfigLoader::new()
+            .file_from_env("AUDIT_CONFIG_FILE")
+            .env_prefix("AUDIT_")
+            .load()
+            .map_err(AuditError::Config)
+    }
+
+    pub fn signing_key(&self) -> Option<SigningKey> {
+        (!self.signing_key.is_empty()).then(|| SigningKey::new(&self.signing_key))
+    }
+}
+
+/// Signs and publishes a service's audit events, keyed by tenant so each
+/// tenant's changes stay ordered.
+pub struct AuditTrail {
+    service: String,
+    key: Option<SigningKey>,
+    producer: Option<FutureProducer>,
+    topic: String,
+    delivery_timeout: Duration,
+}
+
+impl AuditTrail {
+    /// Disabled, recording nothing, while no signing key is configured.
+    pub fn new(service: &str, config: &AuditConfig) -> Result<Self, AuditError> {
+        let key = config.signing_key();
+        let producer = match &key {
+            Some(_) => Some(
+                ClientConfig::new()
+                    .set("bootstrap.servers", &config.kafka_brokers)
+                    .set("enable.idempotence", "true")
+                    .create()?,
+            ),
+            None => {
+                info!("Audit trail disabled: AUDIT_SIGNING_KEY is not set");
+                None
+            }
+        };
+
+        Ok(AuditTrail {
+            service: service.to_string(),
+            key,
+            producer,
+            topic: config.topic.clone(),
+            delivery_timeout: Duration::from_millis(config.delivery_timeout_ms),
+        })
+    }
+
+    /// Signs `event` as coming from this service and waits for Kafka to
+    /// take it. Failures are logged and counted rather than returned: the
+    /// change has already been made.
+    pub async fn record(&self, mut event: AuditEvent) {
+        let (Some(key), Some(producer)) = (&self.key, &self.producer) else {
+            return;
+        };
+        event.service = self.service.clone();
+        event.sign(key);
+
+        let payload = match serde_json::to_string(&event) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("Failed to encode audit event {}: {}", event.action, e);
+                recorded().with_label_values(&["failed"]).inc();
+                return;
+            }
+        };
+        let key = event.tenant_id.as_deref().unwrap_or(&event.resource);
+        let record = FutureRecord::to(&self.topic).key(key).payload(&payload);
+        match producer.send(record, self.delivery_timeout).await {
+            Ok(_) => recorded().with_label_values(&["published"]).inc(),
+            Err((e, _)) => {
+                error!("Failed to record audit event {} on {} by {}: {}", event.action, event.resource, event.actor, e);
+                recorded().with_label_values(&["failed"]).inc();
+            }
+        }
+    }
+}
+
+/// The acting user from [`ACTOR_HEADER`], or `unknown`.
+pub fn actor() -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>(ACTOR_HEADER)
+        .map(|actor: Option<String>| actor.filter(|actor| !actor.is_empty()).unwrap_or_else(|| "unknown".to_string()))
+}
+
+fn recorded() -> &'static IntCounterVec {
+    RECORDED.get_or_init(|| {
+        prometheus::register_int_counter_vec!(
+            "audit_events_total",
+            "Audit events recorded, by result (published or failed)",
+            &["result"]
+        ).unwrap()
+    })
+}
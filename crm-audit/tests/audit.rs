@@ -0,0 +1,67 @@
+use crm_audit::{actor, AuditConfig, AuditEvent, AuditTrail, SigningKey, ACTOR_HEADER};
+use crm_config::Configurable;
+use serde_json::json;
+
+fn signed(key: &SigningKey) -> AuditEvent {
+    let mut event = AuditEvent::new("u-42", "tenant.plan_changed", "t1")
+        .tenant("t1")
+        .details(json!({ "plan_id": "enterprise", "effective_at": 1_700_000_000 }));
+    event.service = "metering-service".to_string();
+    event.sign(key);
+    event
+}
+
+#[test]
+fn signatures_cover_every_field() {
+    let key = SigningKey::new("audit-key");
+    let event = signed(&key);
+    assert!(!event.signature.is_empty());
+    assert!(event.verify(&key));
+    assert!(!event.verify(&SigningKey::new("other-key")));
+
+    let tampered: Vec<AuditEvent> = vec![
+        AuditEvent { actor: "u-7".to_string(), ..event.clone() },
+        AuditEvent { timestamp: event.timestamp + 1, ..event.clone() },
+        AuditEvent { tenant_id: None, ..event.clone() },
+        AuditEvent { details: json!({ "plan_id": "free", "effective_at": 1_700_000_000 }), ..event.clone() },
+        AuditEvent { signature: "not base64!".to_string(), ..event.clone() },
+    ];
+    for event in tampered {
+        assert!(!event.verify(&key), "{:?}", event);
+    }
+
+    // Content can't be shifted between fields
+    let mut shifted = AuditEvent { action: "tenant.plan".to_string(), resource: "_changedt1".to_string(), ..event.clone() };
+    shifted.signature = event.signature.clone();
+    assert!(!shifted.verify(&key));
+}
+
+#[test]
+fn survives_a_round_trip() {
+    let key = SigningKey::new("audit-key");
+    let event = signed(&key);
+    let stored: AuditEvent = serde_json::from_str(&serde_json::to_string(&event).unwrap()).unwrap();
+    assert_eq!(stored, event);
+    assert!(stored.verify(&key));
+}
+
+#[tokio::test]
+async fn disabled_without_a_signing_key() {
+    let config = AuditConfig::default();
+    assert!(config.validate().is_ok());
+    assert!(config.signing_key().is_none());
+    // Records nothing and doesn't wait on Kafka
+    let trail = AuditTrail::new("test", &config).unwrap();
+    trail.record(AuditEvent::new("u-1", "module.uploaded", "m.wasm")).await;
+
+    let config = AuditConfig { topic: String::new(), delivery_timeout_ms: 0, ..AuditConfig::default() };
+    assert_eq!(config.validate().unwrap_err().len(), 2);
+}
+
+#[tokio::test]
+async fn actor_comes_from_the_gateway_header() {
+    let filter = actor();
+    let actor = warp::test::request().header(ACTOR_HEADER, "u-42").filter(&filter).await.unwrap();
+    assert_eq!(actor, "u-42");
+    assert_eq!(warp::test::request().filter(&filter).await.unwrap(), "unknown");
+}
@@ -0,0 +1,206 @@
+//! Fault injection for resilience testing.
+//!
+//! Services call [`inject`] at the points where a dependency can fail:
+//!
+//! - [`CLICKHOUSE`] before each batch insert
+//! - [`REDIS`] before each real-time metrics update
+//! - [`KAFKA`] before each consumer receive, failing as a disconnect
+//! - [`WASM`] before a plugin function runs, failing as a trap
+//!
+//! Faults are set at runtime through the admin API from [`routes`]:
+//!
+//! ```text
+//! PUT    /admin/chaos/clickhouse  {"probability": 0.2, "latency_ms": 2000}
+//! PUT    /admin/chaos/redis       {"probability": 0.5, "error": true}
+//! GET    /admin/chaos
+//! DELETE /admin/chaos/redis
+//! DELETE /admin/chaos
+//! ```
+//!
+//! All of this is compiled out unless the `enabled` feature is on (services
+//! expose it as their `chaos` feature): [`inject`] returns immediately and
+//! the admin API answers 404.
+
+use prometheus::IntCounterVec;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+use std::time::Duration;
+use tracing::warn;
+use warp::http::StatusCode;
+use warp::Filter;
+
+/// Whether fault injection was compiled in.
+pub const ENABLED: bool = cfg!(feature = "enabled");
+
+pub const CLICKHOUSE: &str = "clickhouse";
+pub const REDIS: &str = "redis";
+pub const KAFKA: &str = "kafka";
+pub const WASM: &str = "wasm";
+
+/// Longest latency that can be injected, so a typo can't wedge a service.
+const MAX_LATENCY_MS: u64 = 600_000;
+
+static FAULTS: OnceLock<RwLock<HashMap<String, Fault>>> = OnceLock::new();
+static INJECTED: OnceLock<IntCounterVec> = OnceLock::new();
+
+/// What happens at an injection point.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Fault {
+    /// Chance that a call is affected, from 0 to 1.
+    pub probability: f64,
+    /// Delay added to affected calls.
+    #[serde(default)]
+    pub latency_ms: u64,
+    /// Affected calls fail, after any delay.
+    #[serde(default)]
+    pub error: bool,
+}
+
+impl Fault {
+    fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.probability) {
+            return Err("probability must be between 0 and 1".to_string());
+        }
+        if self.latency_ms > MAX_LATENCY_MS {
+            return Err(format!("latency_ms must be at most {}", MAX_LATENCY_MS));
+        }
+        if self.latency_ms == 0 && !self.error {
+            return Err("a fault needs latency_ms or error".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// The error an injection point fails with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InjectedFault(pub String);
+
+impl std::fmt::Display for InjectedFault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "injected fault at {}", self.0)
+    }
+}
+
+impl std::error::Error for InjectedFault {}
+
+/// Applies the fault set for `point`, if any: sleeps for its latency, then
+/// fails if it is an error fault.
+pub async fn inject(point: &str) -> Result<(), InjectedFault> {
+    if !ENABLED {
+        return Ok(());
+    }
+    let Some(fault) = faults().get(point).copied() else {
+        return Ok(());
+    };
+    if rand::random::<f64>() >= fault.probability {
+        return Ok(());
+    }
+
+    injected().with_label_values(&[point]).inc();
+    if fault.latency_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(fault.latency_ms)).await;
+    }
+    if fault.error {
+        return Err(InjectedFault(point.to_string()));
+    }
+    Ok(())
+}
+
+/// Sets the fault for `point`, replacing any previous one.
+pub fn set(point: &str, fault: Fault) -> Result<(), String> {
+    if !ENABLED {
+        return Err("fault injection is not compiled in; build with the chaos feature".to_string());
+    }
+    if point.is_empty() {
+        return Err("point must not be empty".to_string());
+    }
+    fault.validate()?;
+    warn!("Injecting faults at {}: {:?}", point, fault);
+    registry().write().unwrap_or_else(|e| e.into_inner()).insert(point.to_string(), fault);
+    Ok(())
+}
+
+/// Removes the fault for `point`, or every fault when `None`.
+pub fn clear(point: Option<&str>) {
+    let mut faults = registry().write().unwrap_or_else(|e| e.into_inner());
+    match point {
+        Some(point) => {
+            if faults.remove(point).is_some() {
+                warn!("Stopped injecting faults at {}", point);
+            }
+        }
+        None => {
+            if !faults.is_empty() {
+                warn!("Stopped injecting all faults");
+            }
+            faults.clear();
+        }
+    }
+}
+
+/// The faults currently set, by point.
+pub fn faults() -> HashMap<String, Fault> {
+    registry().read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// The `/admin/chaos` API; every route rejects as not found when fault
+/// injection is compiled out.
+pub fn routes() -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let base = warp::path("admin")
+        .and(warp::path("chaos"))
+        .and(warp::any().and_then(require_enabled).untuple_one());
+
+    let list = base.and(warp::path::end()).and(warp::get()).map(|| listing(StatusCode::OK));
+    let put = base
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::put())
+        .and(warp::body::content_length_limit(4 * 1024))
+        .and(warp::body::json())
+        .map(|point: String, fault: Fault| match set(&point, fault) {
+            Ok(()) => listing(StatusCode::OK),
+            Err(e) => warp::reply::with_status(warp::reply::json(&json!({ "error": e })), StatusCode::BAD_REQUEST),
+        });
+    let delete_one = base
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::delete())
+        .map(|point: String| {
+            clear(Some(&point));
+            listing(StatusCode::OK)
+        });
+    let delete_all = base.and(warp::path::end()).and(warp::delete()).map(|| {
+        clear(None);
+        listing(StatusCode::OK)
+    });
+
+    list.or(put).unify().or(delete_one).unify().or(delete_all).unify()
+}
+
+async fn require_enabled() -> Result<(), warp::Rejection> {
+    if ENABLED {
+        Ok(())
+    } else {
+        Err(warp::reject::not_found())
+    }
+}
+
+fn listing(status: StatusCode) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(warp::reply::json(&faults()), status)
+}
+
+fn registry() -> &'static RwLock<HashMap<String, Fault>> {
+    FAULTS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+fn injected() -> &'static IntCounterVec {
+    INJECTED.get_or_init(|| {
+        prometheus::register_int_counter_vec!(
+            "chaos_faults_injected_total",
+            "Faults injected by point",
+            &["point"]
+        ).unwrap()
+    })
+}
@@ -0,0 +1,108 @@
+use crm_chaos::{inject, Fault};
+
+#[cfg(not(feature = "enabled"))]
+#[tokio::test]
+async fn compiled_out_by_default() {
+    let fault = Fault {
+        probability: 1.0,
+        latency_ms: 0,
+        error: true,
+    };
+    assert!(crm_chaos::set(crm_chaos::REDIS, fault).is_err());
+    assert_eq!(inject(crm_chaos::REDIS).await, Ok(()));
+
+    let response = warp::test::request().path("/admin/chaos").reply(&crm_chaos::routes()).await;
+    assert_eq!(response.status(), 404);
+}
+
+/// The fault registry is process-wide, so tests that change it take turns.
+#[cfg(feature = "enabled")]
+static SERIAL: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+#[cfg(feature = "enabled")]
+#[tokio::test]
+async fn injects_errors_and_latency() {
+    let _serial = SERIAL.lock().await;
+    crm_chaos::clear(None);
+    assert_eq!(inject(crm_chaos::REDIS).await, Ok(()));
+
+    let error = Fault {
+        probability: 1.0,
+        latency_ms: 0,
+        error: true,
+    };
+    crm_chaos::set(crm_chaos::REDIS, error).unwrap();
+    assert_eq!(inject(crm_chaos::REDIS).await, Err(crm_chaos::InjectedFault("redis".to_string())));
+    // Other points are unaffected
+    assert_eq!(inject(crm_chaos::KAFKA).await, Ok(()));
+
+    let slow = Fault {
+        probability: 1.0,
+        latency_ms: 50,
+        error: false,
+    };
+    crm_chaos::set(crm_chaos::CLICKHOUSE, slow).unwrap();
+    let started = std::time::Instant::now();
+    assert_eq!(inject(crm_chaos::CLICKHOUSE).await, Ok(()));
+    assert!(started.elapsed() >= std::time::Duration::from_millis(50));
+
+    let never = Fault {
+        probability: 0.0,
+        ..error
+    };
+    crm_chaos::set(crm_chaos::WASM, never).unwrap();
+    for _ in 0..100 {
+        assert_eq!(inject(crm_chaos::WASM).await, Ok(()));
+    }
+
+    crm_chaos::clear(Some(crm_chaos::REDIS));
+    assert_eq!(inject(crm_chaos::REDIS).await, Ok(()));
+    crm_chaos::clear(None);
+    assert!(crm_chaos::faults().is_empty());
+}
+
+#[cfg(feature = "enabled")]
+#[tokio::test]
+async fn admin_api_sets_and_clears_faults() {
+    let _serial = SERIAL.lock().await;
+    crm_chaos::clear(None);
+    let routes = crm_chaos::routes();
+
+    let response = warp::test::request()
+        .method("PUT")
+        .path("/admin/chaos/kafka")
+        .json(&serde_json::json!({ "probability": 0.25, "error": true }))
+        .reply(&routes)
+        .await;
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["kafka"], serde_json::json!({ "probability": 0.25, "latency_ms": 0, "error": true }));
+
+    // Rejected faults leave the current ones alone
+    for invalid in [
+        serde_json::json!({ "probability": 1.5, "error": true }),
+        serde_json::json!({ "probability": 1.0 }),
+        serde_json::json!({ "probability": 1.0, "latency_ms": 3_600_000 }),
+    ] {
+        let response = warp::test::request()
+            .method("PUT")
+            .path("/admin/chaos/redis")
+            .json(&invalid)
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 400, "{}", invalid);
+    }
+    assert_eq!(crm_chaos::faults().len(), 1);
+
+    let response = warp::test::request().path("/admin/chaos").reply(&routes).await;
+    assert_eq!(response.status(), 200);
+
+    let response = warp::test::request().method("DELETE").path("/admin/chaos/kafka").reply(&routes).await;
+    assert_eq!(response.status(), 200);
+    assert!(crm_chaos::faults().is_empty());
+
+    crm_chaos::set(crm_chaos::WASM, Fault { probability: 1.0, latency_ms: 0, error: true }).unwrap();
+    let response = warp::test::request().method("DELETE").path("/admin/chaos").reply(&routes).await;
+    assert_eq!(response.status(), 200);
+    assert!(crm_chaos::faults().is_empty());
+}
@@ -0,0 +1,37 @@
+use serde_json::{Map, Value};
+
+/// Parses a string setting into the JSON shape of the field's default:
+/// numbers and booleans are parsed, lists are comma-separated and maps are
+/// `key=value` pairs separated by commas. Fields without a default (`None`)
+/// are taken as strings.
+pub(crate) fn parse(raw: &str, default: &Value) -> Result<Value, String> {
+    match default {
+        Value::Bool(_) => match raw.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => Ok(Value::Bool(true)),
+            "false" | "0" | "no" | "off" | "" => Ok(Value::Bool(false)),
+            other => Err(format!("expected a boolean, got '{}'", other)),
+        },
+        Value::Number(_) => match serde_json::from_str::<Value>(raw.trim()) {
+            Ok(Value::Number(n)) => Ok(Value::Number(n)),
+            _ => Err(format!("expected a number, got '{}'", raw)),
+        },
+        Value::Array(_) => Ok(Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| Value::String(item.to_string()))
+                .collect(),
+        )),
+        Value::Object(_) => {
+            let mut map = Map::new();
+            for pair in raw.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+                let (key, value) = pair
+                    .split_once('=')
+                    .ok_or_else(|| format!("expected key=value pairs, got '{}'", pair))?;
+                map.insert(key.trim().to_string(), Value::String(value.trim().to_string()));
+            }
+            Ok(Value::Object(map))
+        }
+        Value::String(_) | Value::Null => Ok(Value::String(raw.to_string())),
+    }
+}
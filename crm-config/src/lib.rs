@@ -0,0 +1,222 @@
+//! Layered configuration for the CRM Rust services.
+//!
+//! A service describes its settings as a serde struct with defaults, then
+//! loads it through [`ConfigLoader`], which applies, in order:
+//!
+//! 1. the struct's [`Default`] values,
+//! 2. an optional JSON or TOML file,
+//! 3. environment variables named after the fields (`batch_size` →
+//!    `BATCH_SIZE`, optionally prefixed),
+//! 4. runtime overrides passed as `key=value`.
+//!
+//! String values of the form `secret://env/NAME` or `secret://file/PATH` are
+//! resolved after merging (`vault`, `aws-ssm` and `env-file` references are
+//! left for `crm-secrets`), and the result is checked with
+//! [`Configurable::validate`]. [`redacted`] renders a loaded config with
+//! secrets masked, for startup logs.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+mod env;
+mod redact;
+mod secrets;
+
+pub use redact::redacted;
+
+pub trait Configurable: Serialize + DeserializeOwned + Default {
+    /// Fields masked by [`redacted`] in addition to the ones whose names
+    /// look secret (`password`, `secret`, `token`, `key`).
+    const SECRET_FIELDS: &'static [&'static str] = &[];
+
+    /// Returns every problem found, not just the first.
+    fn validate(&self) -> Result<(), Vec<String>> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    File { path: PathBuf, reason: String },
+    Env { var: String, reason: String },
+    Override { entry: String, reason: String },
+    Secret { key: String, reason: String },
+    Deserialize(serde_json::Error),
+    Invalid(Vec<String>),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::File { path, reason } => write!(f, "config file {}: {}", path.display(), reason),
+            ConfigError::Env { var, reason } => write!(f, "environment variable {}: {}", var, reason),
+            ConfigError::Override { entry, reason } => write!(f, "override '{}': {}", entry, reason),
+            ConfigError::Secret { key, reason } => write!(f, "secret for {}: {}", key, reason),
+            ConfigError::Deserialize(e) => write!(f, "invalid configuration: {}", e),
+            ConfigError::Invalid(problems) => write!(f, "invalid configuration: {}", problems.join("; ")),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Default)]
+pub struct ConfigLoader {
+    file: Option<PathBuf>,
+    env_prefix: Option<String>,
+    skip_env: bool,
+    overrides: Vec<String>,
+}
+
+impl ConfigLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn file(mut self, path: impl AsRef<Path>) -> Self {
+        self.file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Reads the config file path from an environment variable, if set.
+    pub fn file_from_env(self, var: &str) -> Self {
+        match std::env::var(var) {
+            Ok(path) if !path.is_empty() => self.file(path),
+            _ => self,
+        }
+    }
+
+    /// Prefix for environment variables, e.g. `RUNTIME_` → `RUNTIME_FUEL_LIMIT`.
+    pub fn env_prefix(mut self, prefix: &str) -> Self {
+        self.env_prefix = Some(prefix.to_string());
+        self
+    }
+
+    /// Skips the environment layer; mostly useful in tests.
+    pub fn without_env(mut self) -> Self {
+        self.skip_env = true;
+        self
+    }
+
+    /// Adds a `key=value` override applied after every other layer.
+    pub fn set(mut self, key: &str, value: &str) -> Self {
+        self.overrides.push(format!("{}={}", key, value));
+        self
+    }
+
+    pub fn overrides<I, S>(mut self, entries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.overrides.extend(entries.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn load<T: Configurable>(self) -> Result<T, ConfigError> {
+        let mut values = match serde_json::to_value(T::default()).map_err(ConfigError::Deserialize)? {
+            Value::Object(values) => values,
+            _ => Map::new(),
+        };
+        // Field types come from the defaults; later layers are parsed to match
+        let defaults = values.clone();
+
+        if let Some(path) = &self.file {
+            merge(&mut values, read_file(path)?);
+        }
+
+        if !self.skip_env {
+            let prefix = self.env_prefix.as_deref().unwrap_or("");
+            for key in defaults.keys() {
+                let var = format!("{}{}", prefix, key.to_uppercase());
+                if let Ok(raw) = std::env::var(&var) {
+                    let value = env::parse(&raw, &defaults[key])
+                        .map_err(|reason| ConfigError::Env { var: var.clone(), reason })?;
+                    values.insert(key.clone(), value);
+                }
+            }
+        }
+
+        for entry in &self.overrides {
+            let (key, raw) = entry.split_once('=').ok_or_else(|| ConfigError::Override {
+                entry: entry.clone(),
+                reason: "expected key=value".to_string(),
+            })?;
+            let key = key.trim();
+            let default = defaults.get(key).ok_or_else(|| ConfigError::Override {
+                entry: entry.clone(),
+                reason: "unknown key".to_string(),
+            })?;
+            let value = env::parse(raw.trim(), default).map_err(|reason| ConfigError::Override {
+                entry: entry.clone(),
+                reason,
+            })?;
+            values.insert(key.to_string(), value);
+        }
+
+        for (key, value) in values.iter_mut() {
+            secrets::resolve(key, value)?;
+        }
+
+        let config: T = deserialize(values, &defaults)?;
+        config.validate().map_err(ConfigError::Invalid)?;
+
+        Ok(config)
+    }
+}
+
+/// Optional fields have no default to take a type from, so their settings
+/// arrive as strings; when one of those is rejected, a numeric or boolean
+/// string is retried as such.
+fn deserialize<T: DeserializeOwned>(mut values: Map<String, Value>, defaults: &Map<String, Value>) -> Result<T, ConfigError> {
+    loop {
+        let err = match serde_path_to_error::deserialize(Value::Object(values.clone())) {
+            Ok(config) => return Ok(config),
+            Err(err) => err,
+        };
+
+        let key = match err.path().iter().next() {
+            Some(serde_path_to_error::Segment::Map { key }) => key.clone(),
+            _ => return Err(ConfigError::Deserialize(err.into_inner())),
+        };
+        let coerced = match values.get(&key) {
+            Some(Value::String(raw)) if defaults.get(&key).is_some_and(Value::is_null) => {
+                match serde_json::from_str::<Value>(raw.trim()) {
+                    Ok(scalar @ (Value::Number(_) | Value::Bool(_))) => scalar,
+                    _ => return Err(ConfigError::Deserialize(err.into_inner())),
+                }
+            }
+            _ => return Err(ConfigError::Deserialize(err.into_inner())),
+        };
+        values.insert(key, coerced);
+    }
+}
+
+fn read_file(path: &Path) -> Result<Map<String, Value>, ConfigError> {
+    let file_error = |reason: String| ConfigError::File { path: path.to_path_buf(), reason };
+
+    let contents = std::fs::read_to_string(path).map_err(|e| file_error(e.to_string()))?;
+    let value: Value = match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => toml::from_str(&contents).map_err(|e| file_error(e.to_string()))?,
+        _ => serde_json::from_str(&contents).map_err(|e| file_error(e.to_string()))?,
+    };
+
+    match value {
+        Value::Object(values) => Ok(values),
+        _ => Err(file_error("expected a table of settings".to_string())),
+    }
+}
+
+/// Nested tables merge key by key; anything else replaces the lower layer.
+fn merge(base: &mut Map<String, Value>, layer: Map<String, Value>) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Object(base_table)), Value::Object(layer_table)) => merge(base_table, layer_table),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use crate::ConfigError;
+use serde_json::Value;
+
+const SECRET_SCHEME: &str = "secret://";
+
+/// Backends that need network access; their references are left in place
+/// for `crm-secrets` to resolve once the service's runtime is up.
+const DEFERRED_BACKENDS: [&str; 3] = ["vault", "aws-ssm", "env-file"];
+
+/// Replaces secret references anywhere inside a value:
+///
+/// - `secret://env/NAME` reads the environment variable `NAME`
+/// - `secret://file/PATH` reads `PATH` (e.g. a mounted Kubernetes secret),
+///   trimming the trailing newline
+pub(crate) fn resolve(key: &str, value: &mut Value) -> Result<(), ConfigError> {
+    match value {
+        Value::String(s) => {
+            if let Some(reference) = s.strip_prefix(SECRET_SCHEME) {
+                let lookup = lookup(reference).map_err(|reason| ConfigError::Secret {
+                    key: key.to_string(),
+                    reason,
+                })?;
+                if let Some(resolved) = lookup {
+                    *s = resolved;
+                }
+            }
+            Ok(())
+        }
+        Value::Array(items) => items.iter_mut().try_for_each(|item| resolve(key, item)),
+        Value::Object(map) => map
+            .iter_mut()
+            .try_for_each(|(nested, item)| resolve(&format!("{}.{}", key, nested), item)),
+        _ => Ok(()),
+    }
+}
+
+/// `None` for deferred backends.
+fn lookup(reference: &str) -> Result<Option<String>, String> {
+    match reference.split_once('/') {
+        Some(("env", name)) => std::env::var(name)
+            .map(Some)
+            .map_err(|_| format!("environment variable {} is not set", name)),
+        Some(("file", path)) => std::fs::read_to_string(path)
+            .map(|contents| Some(contents.trim_end_matches(['\n', '\r']).to_string()))
+            .map_err(|e| format!("cannot read {}: {}", path, e)),
+        Some((backend, _)) if DEFERRED_BACKENDS.contains(&backend) => Ok(None),
+        _ => Err(format!("unsupported secret reference '{}{}'", SECRET_SCHEME, reference)),
+    }
+}
@@ -0,0 +1,18 @@
+-- Event contract registry: a JSON Schema per event type and version.
+-- Applied on connect; every statement is idempotent.
+
+CREATE TABLE IF NOT EXISTS event_contracts (
+    event_type TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    -- JSON Schema for the event payload
+    schema TEXT NOT NULL,
+    -- proposed, accepted or rejected
+    status TEXT NOT NULL DEFAULT 'proposed',
+    -- Why a version was rejected
+    problems TEXT[] NOT NULL DEFAULT '{}',
+    proposed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    reviewed_at TIMESTAMPTZ,
+    PRIMARY KEY (event_type, version)
+);
+
+CREATE INDEX IF NOT EXISTS event_contracts_proposed ON event_contracts (event_type) WHERE status = 'proposed';
@@ -0,0 +1,263 @@
+//! Event contract registry: a JSON Schema for the payload of each event type,
+//! versioned, in Postgres ([`CONTRACTS_SCHEMA`]).
+//!
+//! Producers propose a new version with [`ContractRegistry::propose`]. It
+//! stays proposed until [`ContractRegistry::review`] accepts it, which only
+//! happens when it is backward compatible with the latest accepted version:
+//! every payload valid under that version must stay valid under the new one
+//! ([`check_compatible`]). Otherwise it is rejected with the reasons. The
+//! event ingestion service runs the reviews and validates payloads against
+//! the latest accepted version of their type.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio_postgres::{Client, NoTls, Row};
+use tracing::{error, info};
+
+pub mod schema;
+
+pub use schema::{check_compatible, check_supported, validate};
+
+/// The registry table; idempotent, applied on connect.
+pub const CONTRACTS_SCHEMA: &str = include_str!("../schema.sql");
+
+#[derive(Debug)]
+pub enum ContractError {
+    Postgres(tokio_postgres::Error),
+    /// A stored schema that no longer parses.
+    Encoding(serde_json::Error),
+    /// A proposed schema outside the supported subset; never stored.
+    Unsupported(Vec<String>),
+}
+
+impl std::fmt::Display for ContractError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContractError::Postgres(e) => write!(f, "contract registry database error: {}", e),
+            ContractError::Encoding(e) => write!(f, "invalid stored contract: {}", e),
+            ContractError::Unsupported(problems) => write!(f, "unsupported schema: {}", problems.join("; ")),
+        }
+    }
+}
+
+impl std::error::Error for ContractError {}
+
+impl From<tokio_postgres::Error> for ContractError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        ContractError::Postgres(e)
+    }
+}
+
+impl From<serde_json::Error> for ContractError {
+    fn from(e: serde_json::Error) -> Self {
+        ContractError::Encoding(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractStatus {
+    Proposed,
+    Accepted,
+    Rejected,
+}
+
+impl ContractStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ContractStatus::Proposed => "proposed",
+            ContractStatus::Accepted => "accepted",
+            ContractStatus::Rejected => "rejected",
+        }
+    }
+
+    fn parse(status: &str) -> Self {
+        match status {
+            "accepted" => ContractStatus::Accepted,
+            "rejected" => ContractStatus::Rejected,
+            _ => ContractStatus::Proposed,
+        }
+    }
+}
+
+/// One version of an event type's contract.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Contract {
+    pub event_type: String,
+    pub version: i32,
+    pub schema: Value,
+    pub status: ContractStatus,
+    /// Why it was rejected.
+    pub problems: Vec<String>,
+}
+
+impl Contract {
+    fn from_row(row: &Row) -> Result<Self, ContractError> {
+        Ok(Contract {
+            event_type: row.get("event_type"),
+            version: row.get("version"),
+            schema: serde_json::from_str(row.get("schema"))?,
+            status: ContractStatus::parse(row.get("status")),
+            problems: row.get("problems"),
+        })
+    }
+
+    /// Why `payload` violates this contract; empty when it doesn't.
+    pub fn validate(&self, payload: &Value) -> Vec<String> {
+        validate(&self.schema, payload)
+    }
+}
+
+const COLUMNS: &str = "event_type, version, schema, status, problems";
+
+pub struct ContractRegistry {
+    client: Client,
+}
+
+impl ContractRegistry {
+    /// Connects and applies the registry schema.
+    pub async fn connect(database_url: &str) -> Result<Self, ContractError> {
+        let (client, connection) = tokio_postgres::connect(database_url, NoTls).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                error!("Contract registry connection closed: {}", e);
+            }
+        });
+        client.batch_execute(CONTRACTS_SCHEMA).await?;
+        Ok(ContractRegistry { client })
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.client.is_closed()
+    }
+
+    /// Stores `schema` as the next version of `event_type`, pending review.
+    pub async fn propose(&mut self, event_type: &str, schema: &Value) -> Result<Contract, ContractError> {
+        let problems = check_supported(schema);
+        if !problems.is_empty() {
+            return Err(ContractError::Unsupported(problems));
+        }
+
+        let transaction = self.client.transaction().await?;
+        // Serializes version numbering and reviews per event type
+        transaction
+            .execute("SELECT pg_advisory_xact_lock(hashtext($1))", &[&event_type])
+            .await?;
+        let row = transaction
+            .query_one(
+                &format!(
+                    "INSERT INTO event_contracts (event_type, version, schema) \
+                     SELECT $1, COALESCE(MAX(version), 0) + 1, $2 FROM event_contracts WHERE event_type = $1 \
+                     RETURNING {}",
+                    COLUMNS
+                ),
+                &[&event_type, &schema.to_string()],
+            )
+            .await?;
+        transaction.commit().await?;
+
+        let contract = Contract::from_row(&row)?;
+        info!("Contract {} v{} proposed", contract.event_type, contract.version);
+        Ok(contract)
+    }
+
+    /// Accepts or rejects the proposed versions of `event_type`, oldest
+    /// first, each against the latest version accepted before it. Returns
+    /// the versions reviewed.
+    pub async fn review(&mut self, event_type: &str) -> Result<Vec<Contract>, ContractError> {
+        let transaction = self.client.transaction().await?;
+        transaction
+            .execute("SELECT pg_advisory_xact_lock(hashtext($1))", &[&event_type])
+            .await?;
+
+        let latest = transaction
+            .query_opt(
+                &format!(
+                    "SELECT {} FROM event_contracts WHERE event_type = $1 AND status = 'accepted' \
+                     ORDER BY version DESC LIMIT 1",
+                    COLUMNS
+                ),
+                &[&event_type],
+            )
+            .await?;
+        let mut latest = latest.as_ref().map(Contract::from_row).transpose()?;
+        let proposed = transaction
+            .query(
+                &format!(
+                    "SELECT {} FROM event_contracts WHERE event_type = $1 AND status = 'proposed' ORDER BY version",
+                    COLUMNS
+                ),
+                &[&event_type],
+            )
+            .await?;
+
+        let mut reviewed = Vec::with_capacity(proposed.len());
+        for row in &proposed {
+            let mut contract = Contract::from_row(row)?;
+            contract.problems = check_supported(&contract.schema);
+            if let (true, Some(latest)) = (contract.problems.is_empty(), &latest) {
+                contract.problems = check_compatible(&latest.schema, &contract.schema);
+            }
+            contract.status = if contract.problems.is_empty() {
+                ContractStatus::Accepted
+            } else {
+                ContractStatus::Rejected
+            };
+
+            transaction
+                .execute(
+                    "UPDATE event_contracts SET status = $3, problems = $4, reviewed_at = now() \
+                     WHERE event_type = $1 AND version = $2",
+                    &[&contract.event_type, &contract.version, &contract.status.as_str(), &contract.problems],
+                )
+                .await?;
+            info!("Contract {} v{} {}", contract.event_type, contract.version, contract.status.as_str());
+            if contract.status == ContractStatus::Accepted {
+                latest = Some(contract.clone());
+            }
+            reviewed.push(contract);
+        }
+        transaction.commit().await?;
+        Ok(reviewed)
+    }
+
+    /// Event types with versions waiting for review.
+    pub async fn pending(&self) -> Result<Vec<String>, ContractError> {
+        let rows = self
+            .client
+            .query("SELECT DISTINCT event_type FROM event_contracts WHERE status = 'proposed'", &[])
+            .await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    /// Every version of `event_type`, oldest first.
+    pub async fn versions(&self, event_type: &str) -> Result<Vec<Contract>, ContractError> {
+        let rows = self
+            .client
+            .query(
+                &format!("SELECT {} FROM event_contracts WHERE event_type = $1 ORDER BY version", COLUMNS),
+                &[&event_type],
+            )
+            .await?;
+        rows.iter().map(Contract::from_row).collect()
+    }
+
+    /// The latest accepted version of each event type.
+    pub async fn accepted(&self) -> Result<HashMap<String, Contract>, ContractError> {
+        let rows = self
+            .client
+            .query(
+                &format!(
+                    "SELECT DISTINCT ON (event_type) {} FROM event_contracts WHERE status = 'accepted' \
+                     ORDER BY event_type, version DESC",
+                    COLUMNS
+                ),
+                &[],
+            )
+            .await?;
+        rows.iter()
+            .map(|row| Contract::from_row(row).map(|contract| (contract.event_type.clone(), contract)))
+            .collect()
+    }
+}
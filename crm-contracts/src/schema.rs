@@ -0,0 +1,234 @@
+//! The JSON Schema subset contracts are written in.
+//!
+//! Supported keywords are `type` (a name or a list of names), `properties`,
+//! `required`, `additionalProperties` (a boolean), `items` and `enum`, plus
+//! the annotations `$schema`, `$id`, `title`, `description` and `format`,
+//! which are ignored. Anything else is refused, since the compatibility of
+//! a change to it couldn't be checked. Problems are reported with the path
+//! of the offending value or keyword, `$` being the payload itself.
+
+use serde_json::{Map, Value};
+
+const KEYWORDS: &[&str] = &["type", "properties", "required", "additionalProperties", "items", "enum"];
+const ANNOTATIONS: &[&str] = &["$schema", "$id", "title", "description", "format"];
+const TYPES: &[&str] = &["object", "array", "string", "number", "integer", "boolean", "null"];
+
+/// Why `schema` can't be used as a contract; empty when it can.
+pub fn check_supported(schema: &Value) -> Vec<String> {
+    let mut problems = Vec::new();
+    supported(schema, "$", &mut problems);
+    problems
+}
+
+fn supported(schema: &Value, path: &str, problems: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        problems.push(format!("{}: schema must be an object", path));
+        return;
+    };
+    for keyword in schema.keys() {
+        if !KEYWORDS.contains(&keyword.as_str()) && !ANNOTATIONS.contains(&keyword.as_str()) {
+            problems.push(format!("{}: unsupported keyword {}", path, keyword));
+        }
+    }
+
+    match schema.get("type") {
+        None => {}
+        Some(Value::String(name)) if TYPES.contains(&name.as_str()) => {}
+        Some(Value::Array(names)) if names.iter().all(|name| name.as_str().is_some_and(|name| TYPES.contains(&name))) => {}
+        Some(other) => problems.push(format!("{}: invalid type {}", path, other)),
+    }
+    match schema.get("properties") {
+        None => {}
+        Some(Value::Object(properties)) => {
+            for (name, property) in properties {
+                supported(property, &format!("{}.{}", path, name), problems);
+            }
+        }
+        Some(_) => problems.push(format!("{}: properties must be an object", path)),
+    }
+    if let Some(required) = schema.get("required") {
+        if !required.as_array().is_some_and(|names| names.iter().all(Value::is_string)) {
+            problems.push(format!("{}: required must be a list of names", path));
+        }
+    }
+    if schema.get("additionalProperties").is_some_and(|allowed| !allowed.is_boolean()) {
+        problems.push(format!("{}: additionalProperties must be a boolean", path));
+    }
+    if let Some(items) = schema.get("items") {
+        supported(items, &format!("{}[]", path), problems);
+    }
+    if schema.get("enum").is_some_and(|values| !values.is_array()) {
+        problems.push(format!("{}: enum must be a list", path));
+    }
+}
+
+/// Why `value` doesn't satisfy `schema`; empty when it does. `schema` must
+/// pass [`check_supported`].
+pub fn validate(schema: &Value, value: &Value) -> Vec<String> {
+    let mut problems = Vec::new();
+    validate_at(schema, value, "$", &mut problems);
+    problems
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str, problems: &mut Vec<String>) {
+    if let Some(types) = types(schema) {
+        if !types.iter().any(|name| type_matches(name, value)) {
+            problems.push(format!("{}: expected {}, got {}", path, types.join(" or "), type_name(value)));
+            return;
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            problems.push(format!("{}: {} is not one of the allowed values", path, value));
+        }
+    }
+
+    match value {
+        Value::Object(fields) => {
+            for name in required(schema) {
+                if !fields.contains_key(name) {
+                    problems.push(format!("{}: missing required {}", path, name));
+                }
+            }
+            let properties = properties(schema);
+            for (name, field) in fields {
+                match properties.and_then(|properties| properties.get(name)) {
+                    Some(property) => validate_at(property, field, &format!("{}.{}", path, name), problems),
+                    None if !additional_allowed(schema) => {
+                        problems.push(format!("{}: unexpected property {}", path, name));
+                    }
+                    None => {}
+                }
+            }
+        }
+        Value::Array(elements) => {
+            if let Some(items) = schema.get("items") {
+                for (i, element) in elements.iter().enumerate() {
+                    validate_at(items, element, &format!("{}[{}]", path, i), problems);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Why `next` is not backward compatible with `current`, i.e. which
+/// payloads valid under `current` it would refuse; empty when none. Both
+/// must pass [`check_supported`].
+pub fn check_compatible(current: &Value, next: &Value) -> Vec<String> {
+    let mut problems = Vec::new();
+    compatible_at(current, next, "$", &mut problems);
+    problems
+}
+
+fn compatible_at(current: &Value, next: &Value, path: &str, problems: &mut Vec<String>) {
+    if let Some(next_types) = types(next) {
+        match types(current) {
+            Some(current_types) => {
+                for name in current_types {
+                    if !next_types.iter().any(|next_name| type_includes(next_name, name)) {
+                        problems.push(format!("{}: no longer accepts {}", path, name));
+                    }
+                }
+            }
+            None => problems.push(format!("{}: now restricted to {}", path, next_types.join(" or "))),
+        }
+    }
+
+    if let Some(next_values) = next.get("enum").and_then(Value::as_array) {
+        match current.get("enum").and_then(Value::as_array) {
+            Some(current_values) => {
+                for value in current_values.iter().filter(|value| !next_values.contains(value)) {
+                    problems.push(format!("{}: no longer allows {}", path, value));
+                }
+            }
+            None => problems.push(format!("{}: now restricted to a list of values", path)),
+        }
+    }
+
+    let current_required = required(current);
+    for name in required(next) {
+        if !current_required.contains(&name) {
+            problems.push(format!("{}: {} is now required", path, name));
+        }
+    }
+
+    let current_properties = properties(current);
+    let any = Value::Object(Map::new());
+    if let Some(next_properties) = properties(next) {
+        for (name, next_property) in next_properties {
+            let current_property = match current_properties.and_then(|properties| properties.get(name)) {
+                Some(property) => property,
+                // Couldn't appear before, so anything goes
+                None if !additional_allowed(current) => continue,
+                None => &any,
+            };
+            compatible_at(current_property, next_property, &format!("{}.{}", path, name), problems);
+        }
+    }
+
+    if !additional_allowed(next) {
+        if additional_allowed(current) {
+            problems.push(format!("{}: no longer allows additional properties", path));
+        } else {
+            for name in current_properties.into_iter().flat_map(Map::keys) {
+                if !properties(next).is_some_and(|properties| properties.contains_key(name)) {
+                    problems.push(format!("{}: no longer allows {}", path, name));
+                }
+            }
+        }
+    }
+
+    if let Some(next_items) = next.get("items") {
+        compatible_at(current.get("items").unwrap_or(&any), next_items, &format!("{}[]", path), problems);
+    }
+}
+
+/// `None` when any type is allowed.
+fn types(schema: &Value) -> Option<Vec<&str>> {
+    match schema.get("type")? {
+        Value::String(name) => Some(vec![name.as_str()]),
+        Value::Array(names) => Some(names.iter().filter_map(Value::as_str).collect()),
+        _ => None,
+    }
+}
+
+fn required(schema: &Value) -> Vec<&str> {
+    schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|names| names.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default()
+}
+
+fn properties(schema: &Value) -> Option<&Map<String, Value>> {
+    schema.get("properties").and_then(Value::as_object)
+}
+
+fn additional_allowed(schema: &Value) -> bool {
+    schema.get("additionalProperties").and_then(Value::as_bool).unwrap_or(true)
+}
+
+fn type_matches(name: &str, value: &Value) -> bool {
+    match name {
+        "integer" => value.as_i64().is_some() || value.as_u64().is_some(),
+        "number" => value.is_number(),
+        name => type_name(value) == name,
+    }
+}
+
+/// Whether values of type `other` are all of type `name`.
+fn type_includes(name: &str, other: &str) -> bool {
+    name == other || (name == "number" && other == "integer")
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
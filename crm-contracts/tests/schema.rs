@@ -0,0 +1,118 @@
+use crm_contracts::{check_compatible, check_supported, validate, CONTRACTS_SCHEMA};
+use serde_json::json;
+
+fn deal_updated() -> serde_json::Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "type": "object",
+        "properties": {
+            "deal_id": { "type": "string" },
+            "amount": { "type": "number" },
+            "stage": { "enum": ["open", "won", "lost"] },
+            "tags": { "type": "array", "items": { "type": "string" } }
+        },
+        "required": ["deal_id"]
+    })
+}
+
+#[test]
+fn validates_payloads() {
+    let schema = deal_updated();
+    assert!(check_supported(&schema).is_empty());
+    assert!(validate(&schema, &json!({ "deal_id": "d1", "amount": 12, "stage": "won", "extra": true })).is_empty());
+
+    let problems = validate(&schema, &json!({ "amount": "12", "stage": "pending", "tags": ["a", 1] }));
+    assert_eq!(
+        problems,
+        vec![
+            "$: missing required deal_id",
+            "$.amount: expected number, got string",
+            "$.stage: \"pending\" is not one of the allowed values",
+            "$.tags[1]: expected string, got number",
+        ]
+    );
+
+    let closed = json!({ "type": "object", "properties": { "id": { "type": "integer" } }, "additionalProperties": false });
+    assert!(validate(&closed, &json!({ "id": 1 })).is_empty());
+    assert_eq!(validate(&closed, &json!({ "id": 1.5, "x": 1 })).len(), 2);
+}
+
+#[test]
+fn refuses_unsupported_schemas() {
+    assert!(!check_supported(&json!("object")).is_empty());
+    assert_eq!(
+        check_supported(&json!({ "type": "object", "properties": { "n": { "type": "number", "minimum": 0 } } })),
+        vec!["$.n: unsupported keyword minimum"]
+    );
+    assert_eq!(check_supported(&json!({ "type": "date" })), vec!["$: invalid type \"date\""]);
+    assert_eq!(check_supported(&json!({ "additionalProperties": {} })).len(), 1);
+}
+
+#[test]
+fn accepts_backward_compatible_changes() {
+    let current = deal_updated();
+    let mut next = deal_updated();
+    // Widened type and enum, relaxed requirement, unconstrained new property
+    next["properties"]["notes"] = json!({ "description": "Free text" });
+    next["properties"]["amount"] = json!({ "type": ["number", "string"] });
+    next["properties"]["stage"] = json!({ "enum": ["open", "won", "lost", "stalled"] });
+    next["required"] = json!([]);
+    assert_eq!(check_compatible(&current, &next), Vec::<String>::new());
+
+    // integer payloads are all numbers
+    assert!(check_compatible(&json!({ "type": "integer" }), &json!({ "type": "number" })).is_empty());
+}
+
+#[test]
+fn rejects_breaking_changes() {
+    let current = deal_updated();
+
+    let mut next = deal_updated();
+    next["required"] = json!(["deal_id", "amount"]);
+    assert_eq!(check_compatible(&current, &next), vec!["$: amount is now required"]);
+
+    let mut next = deal_updated();
+    next["properties"]["amount"] = json!({ "type": "integer" });
+    next["properties"]["stage"] = json!({ "enum": ["open", "won"] });
+    next["properties"]["tags"]["items"] = json!({ "type": "integer" });
+    assert_eq!(
+        check_compatible(&current, &next),
+        vec![
+            "$.amount: no longer accepts number",
+            "$.stage: no longer allows \"lost\"",
+            "$.tags[]: no longer accepts string",
+        ]
+    );
+
+    // Properties that were free-form before can't be constrained now
+    let mut next = deal_updated();
+    next["properties"]["owner"] = json!({ "type": "string" });
+    assert_eq!(check_compatible(&current, &next), vec!["$.owner: now restricted to string"]);
+
+    let mut next = deal_updated();
+    next["additionalProperties"] = json!(false);
+    assert_eq!(check_compatible(&current, &next), vec!["$: no longer allows additional properties"]);
+}
+
+#[test]
+fn closed_objects_may_gain_properties() {
+    let current = json!({ "type": "object", "properties": { "a": { "type": "string" } }, "additionalProperties": false });
+    let mut next = current.clone();
+    next["properties"]["b"] = json!({ "type": "integer" });
+    assert!(check_compatible(&current, &next).is_empty());
+    assert_eq!(check_compatible(&next, &current), vec!["$: no longer allows b"]);
+}
+
+#[test]
+fn schema_can_be_reapplied() {
+    let sql: String = CONTRACTS_SCHEMA
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("--"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let statements: Vec<&str> = sql.split(';').map(str::trim).filter(|s| !s.is_empty()).collect();
+    assert_eq!(statements.len(), 2);
+    for statement in statements {
+        assert!(statement.starts_with("CREATE") && statement.contains("IF NOT EXISTS"), "{}", statement);
+    }
+}
@@ -0,0 +1,119 @@
+//! Shared CRM event definitions.
+//!
+//! `CrmEvent` is the envelope every producer publishes to Kafka. Well-known
+//! event types have strongly typed payloads implementing [`EventPayload`], so
+//! consumers can read `event.payload_as::<DealUpdated>()` instead of poking at
+//! `serde_json::Value` by string key.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod payloads;
+
+pub use payloads::*;
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct CrmEvent {
+    pub tenant_id: String,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub timestamp: i64,
+    pub source: Option<String>,
+    pub user_id: Option<String>,
+}
+
+/// A typed payload for a well-known event type.
+pub trait EventPayload: Serialize + DeserializeOwned {
+    const EVENT_TYPE: &'static str;
+}
+
+#[derive(Debug)]
+pub enum PayloadError {
+    /// The event's type does not match the requested payload type.
+    WrongEventType { expected: &'static str, actual: String },
+    /// The payload does not match the typed schema.
+    Invalid(serde_json::Error),
+}
+
+impl std::fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PayloadError::WrongEventType { expected, actual } => {
+                write!(f, "expected {} event, got {}", expected, actual)
+            }
+            PayloadError::Invalid(e) => write!(f, "invalid payload: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PayloadError {}
+
+impl CrmEvent {
+    pub fn builder(tenant_id: impl Into<String>, event_type: impl Into<String>) -> CrmEventBuilder {
+        CrmEventBuilder {
+            event: CrmEvent {
+                tenant_id: tenant_id.into(),
+                event_type: event_type.into(),
+                payload: serde_json::Value::Object(Default::default()),
+                timestamp: now_secs(),
+                source: None,
+                user_id: None,
+            },
+        }
+    }
+
+    /// Starts a builder whose event type and payload come from a typed payload.
+    pub fn typed<P: EventPayload>(tenant_id: impl Into<String>, payload: &P) -> Result<CrmEventBuilder, PayloadError> {
+        let payload = serde_json::to_value(payload).map_err(PayloadError::Invalid)?;
+        Ok(Self::builder(tenant_id, P::EVENT_TYPE).payload(payload))
+    }
+
+    /// Decodes the payload as `P`, checking that the event type matches.
+    pub fn payload_as<P: EventPayload>(&self) -> Result<P, PayloadError> {
+        if self.event_type != P::EVENT_TYPE {
+            return Err(PayloadError::WrongEventType {
+                expected: P::EVENT_TYPE,
+                actual: self.event_type.clone(),
+            });
+        }
+        serde_json::from_value(self.payload.clone()).map_err(PayloadError::Invalid)
+    }
+}
+
+pub struct CrmEventBuilder {
+    event: CrmEvent,
+}
+
+impl CrmEventBuilder {
+    pub fn payload(mut self, payload: serde_json::Value) -> Self {
+        self.event.payload = payload;
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: i64) -> Self {
+        self.event.timestamp = timestamp;
+        self
+    }
+
+    pub fn source(mut self, source: impl Into<String>) -> Self {
+        self.event.source = Some(source.into());
+        self
+    }
+
+    pub fn user_id(mut self, user_id: impl Into<String>) -> Self {
+        self.event.user_id = Some(user_id.into());
+        self
+    }
+
+    pub fn build(self) -> CrmEvent {
+        self.event
+    }
+}
+
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,180 @@
+use crate::EventPayload;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserLogin {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip_address: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+
+impl EventPayload for UserLogin {
+    const EVENT_TYPE: &'static str = "user_login";
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LeadCreated {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub campaign_id: Option<String>,
+}
+
+impl EventPayload for LeadCreated {
+    const EVENT_TYPE: &'static str = "lead_created";
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DealUpdated {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage: Option<String>,
+    /// Stage before this update, when it changed the stage.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_stage: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount: Option<f64>,
+    /// Win probability in percent (0-100).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probability: Option<f64>,
+}
+
+impl EventPayload for DealUpdated {
+    const EVENT_TYPE: &'static str = "deal_updated";
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmailSent {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub campaign_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recipient_count: Option<u64>,
+}
+
+impl EventPayload for EmailSent {
+    const EVENT_TYPE: &'static str = "email_sent";
+}
+
+/// A recipient's mail client loaded the tracking pixel.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmailOpened {
+    pub message_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub campaign_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recipient: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+
+impl EventPayload for EmailOpened {
+    const EVENT_TYPE: &'static str = "email_opened";
+}
+
+/// A recipient followed a tracked link.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmailClicked {
+    pub message_id: String,
+    pub link_url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub campaign_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recipient: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+
+impl EventPayload for EmailClicked {
+    const EVENT_TYPE: &'static str = "email_clicked";
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PageView {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referrer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_duration: Option<f64>,
+    /// Browser-side id for visitors who haven't identified yet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anonymous_id: Option<String>,
+}
+
+impl EventPayload for PageView {
+    const EVENT_TYPE: &'static str = "page_view";
+}
+
+/// Links an anonymous visitor to the known user in the envelope's `user_id`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Identify {
+    pub anonymous_id: String,
+}
+
+impl EventPayload for Identify {
+    const EVENT_TYPE: &'static str = "identify";
+}
+
+/// Asks the notification service to notify a recipient on one channel.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationRequested {
+    /// `email`, `sms` or `push`.
+    pub channel: String,
+    /// Email address, phone number or device token, depending on the channel.
+    pub recipient: String,
+    pub template_id: String,
+    /// Values substituted into the template's `{{name}}` placeholders.
+    #[serde(default)]
+    pub data: serde_json::Map<String, serde_json::Value>,
+}
+
+impl EventPayload for NotificationRequested {
+    const EVENT_TYPE: &'static str = "notification_requested";
+}
+
+/// Published by the reporting service once a scheduled report is uploaded.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportReady {
+    pub report_id: String,
+    pub name: String,
+    /// `csv` or `xlsx`.
+    pub format: String,
+    /// Object storage URL of the rendered file.
+    pub location: String,
+    pub row_count: u64,
+    /// Email addresses to notify.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recipients: Vec<String>,
+}
+
+impl EventPayload for ReportReady {
+    const EVENT_TYPE: &'static str = "report_ready";
+}
+
+/// Published by the scoring service when a contact's RFM scores or segment
+/// change.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreUpdated {
+    pub contact_id: String,
+    /// Quintiles within the tenant, 1 (lowest) to 5 (highest).
+    pub recency: u8,
+    pub frequency: u8,
+    pub monetary: u8,
+    /// 0-100, decaying with time since the last interaction.
+    pub engagement: f64,
+    pub segment: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_segment: Option<String>,
+}
+
+impl EventPayload for ScoreUpdated {
+    const EVENT_TYPE: &'static str = "score_updated";
+}
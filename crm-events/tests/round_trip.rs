@@ -0,0 +1,134 @@
+use crm_events::{CrmEvent, DealUpdated, EmailClicked, EmailSent, Identify, LeadCreated, PageView, PayloadError, ScoreUpdated, UserLogin};
+use serde_json::json;
+
+fn round_trip(event: &CrmEvent) -> CrmEvent {
+    let encoded = serde_json::to_string(event).unwrap();
+    serde_json::from_str(&encoded).unwrap()
+}
+
+#[test]
+fn envelope_round_trips() {
+    let event = CrmEvent::builder("tenant_001", "custom_event")
+        .payload(json!({ "anything": [1, 2, 3] }))
+        .timestamp(1640995200)
+        .source("crm-backend")
+        .user_id("user_123")
+        .build();
+
+    assert_eq!(round_trip(&event), event);
+}
+
+#[test]
+fn decodes_producer_json() {
+    let event: CrmEvent = serde_json::from_value(json!({
+        "tenant_id": "tenant_002",
+        "event_type": "deal_updated",
+        "user_id": "user_789",
+        "timestamp": 1640995320,
+        "payload": { "stage": "negotiation", "amount": 50000.0, "probability": 75.0 }
+    }))
+    .unwrap();
+
+    assert_eq!(event.source, None);
+    assert_eq!(
+        event.payload_as::<DealUpdated>().unwrap(),
+        DealUpdated {
+            stage: Some("negotiation".to_string()),
+            amount: Some(50000.0),
+            probability: Some(75.0),
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn typed_payloads_round_trip() {
+    let login = UserLogin {
+        ip_address: Some("192.168.1.100".to_string()),
+        user_agent: Some("Mozilla/5.0".to_string()),
+    };
+    let event = round_trip(&CrmEvent::typed("tenant_001", &login).unwrap().build());
+    assert_eq!(event.event_type, "user_login");
+    assert_eq!(event.payload_as::<UserLogin>().unwrap(), login);
+
+    let lead = LeadCreated {
+        source: Some("website".to_string()),
+        score: Some(85.5),
+        campaign_id: None,
+    };
+    let event = round_trip(&CrmEvent::typed("tenant_001", &lead).unwrap().build());
+    assert_eq!(event.payload_as::<LeadCreated>().unwrap(), lead);
+
+    let email = EmailSent {
+        campaign_id: Some("camp_002".to_string()),
+        template_id: Some("temp_001".to_string()),
+        recipient_count: Some(150),
+    };
+    let event = round_trip(&CrmEvent::typed("tenant_001", &email).unwrap().build());
+    assert_eq!(event.payload_as::<EmailSent>().unwrap(), email);
+
+    let view = PageView {
+        page_url: Some("/pricing".to_string()),
+        referrer: None,
+        session_duration: Some(42.0),
+        anonymous_id: Some("anon_42".to_string()),
+    };
+    let event = round_trip(&CrmEvent::typed("tenant_001", &view).unwrap().build());
+    assert_eq!(event.payload_as::<PageView>().unwrap(), view);
+
+    let identify = Identify {
+        anonymous_id: "anon_42".to_string(),
+    };
+    let event = round_trip(&CrmEvent::typed("tenant_001", &identify).unwrap().user_id("user_123").build());
+    assert_eq!(event.event_type, "identify");
+    assert_eq!(event.payload_as::<Identify>().unwrap(), identify);
+
+    let click = EmailClicked {
+        message_id: "msg_1".to_string(),
+        link_url: "https://example.com/pricing".to_string(),
+        campaign_id: Some("spring_launch".to_string()),
+        ..Default::default()
+    };
+    let event = round_trip(&CrmEvent::typed("tenant_001", &click).unwrap().build());
+    assert_eq!(event.event_type, "email_clicked");
+    assert_eq!(event.payload_as::<EmailClicked>().unwrap(), click);
+
+    let score = ScoreUpdated {
+        contact_id: "contact_7".to_string(),
+        recency: 5,
+        frequency: 4,
+        monetary: 4,
+        engagement: 72.5,
+        segment: "champion".to_string(),
+        previous_segment: Some("loyal".to_string()),
+    };
+    let event = round_trip(&CrmEvent::typed("tenant_001", &score).unwrap().build());
+    assert_eq!(event.event_type, "score_updated");
+    assert_eq!(event.payload_as::<ScoreUpdated>().unwrap(), score);
+}
+
+#[test]
+fn unset_fields_are_omitted() {
+    let event = CrmEvent::typed("tenant_001", &PageView::default()).unwrap().build();
+    assert_eq!(event.payload, json!({}));
+}
+
+#[test]
+fn rejects_mismatched_event_type() {
+    let event = CrmEvent::builder("tenant_001", "page_view").build();
+    match event.payload_as::<DealUpdated>() {
+        Err(PayloadError::WrongEventType { expected, actual }) => {
+            assert_eq!(expected, "deal_updated");
+            assert_eq!(actual, "page_view");
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn rejects_invalid_payload() {
+    let event = CrmEvent::builder("tenant_001", "lead_created")
+        .payload(json!({ "score": "high" }))
+        .build();
+    assert!(matches!(event.payload_as::<LeadCreated>(), Err(PayloadError::Invalid(_))));
+}
@@ -0,0 +1,181 @@
+//! Per-tenant feature flags backed by Redis.
+//!
+//! Flags live in two hashes, field = flag name:
+//!
+//! - `feature_flags:{tenant_id}` for tenant overrides
+//! - `feature_flags:default` for everyone else
+//!
+//! A value is `on`/`off` (or `true`/`false`) or a rollout percentage such as
+//! `25%`, which enables the flag for a stable quarter of tenants. Flags not
+//! set anywhere, and every flag while Redis is unreachable, fall back to the
+//! default given at the call site, so a flag outage never changes behavior.
+//!
+//! Every evaluation is counted in `feature_flag_evaluations_total`.
+
+use prometheus::IntCounterVec;
+use redis::aio::Connection;
+use redis::AsyncCommands;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+const DEFAULT_SCOPE: &str = "default";
+
+static EVALUATIONS: OnceLock<IntCounterVec> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlagValue {
+    On,
+    Off,
+    /// Enabled for this percentage of tenants (0-100).
+    Rollout(u8),
+}
+
+impl FlagValue {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "on" | "true" | "1" => Some(FlagValue::On),
+            "off" | "false" | "0" => Some(FlagValue::Off),
+            other => other
+                .strip_suffix('%')
+                .and_then(|percent| percent.trim().parse::<u8>().ok())
+                .map(|percent| FlagValue::Rollout(percent.min(100))),
+        }
+    }
+
+    pub fn enabled_for(&self, tenant_id: &str, flag: &str) -> bool {
+        match self {
+            FlagValue::On => true,
+            FlagValue::Off => false,
+            FlagValue::Rollout(percent) => rollout_bucket(tenant_id, flag) < u64::from(*percent),
+        }
+    }
+}
+
+/// Stable 0-99 bucket per tenant and flag, so each flag rolls out to a
+/// different slice of tenants.
+fn rollout_bucket(tenant_id: &str, flag: &str) -> u64 {
+    // FNV-1a: stable across builds, unlike the std hasher
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in flag.bytes().chain([b':']).chain(tenant_id.bytes()) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash % 100
+}
+
+type FlagSet = HashMap<String, FlagValue>;
+
+pub struct FlagClient {
+    client: Option<redis::Client>,
+    connection: Mutex<Option<Connection>>,
+    cache_ttl: Duration,
+    cache: Mutex<HashMap<String, (FlagSet, Instant)>>,
+}
+
+impl FlagClient {
+    /// Connects lazily, so a service can start while Redis is down.
+    pub fn new(redis_url: &str, cache_ttl: Duration) -> Self {
+        let client = redis::Client::open(redis_url)
+            .map_err(|e| warn!("Invalid feature flag Redis URL, using defaults: {}", e))
+            .ok();
+
+        FlagClient {
+            client,
+            connection: Mutex::new(None),
+            cache_ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// A client that always returns the call-site default.
+    pub fn disabled() -> Self {
+        FlagClient {
+            client: None,
+            connection: Mutex::new(None),
+            cache_ttl: Duration::MAX,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn is_enabled(&self, tenant_id: &str, flag: &str, default: bool) -> bool {
+        let tenant_value = self.flags(tenant_id).await.get(flag).copied();
+        let (value, source) = match tenant_value {
+            Some(value) => (Some(value), "tenant"),
+            None => match self.flags(DEFAULT_SCOPE).await.get(flag).copied() {
+                Some(value) => (Some(value), "default"),
+                None => (None, "fallback"),
+            },
+        };
+        let enabled = value.map_or(default, |value| value.enabled_for(tenant_id, flag));
+
+        evaluations()
+            .with_label_values(&[flag, if enabled { "on" } else { "off" }, source])
+            .inc();
+        enabled
+    }
+
+    async fn flags(&self, scope: &str) -> FlagSet {
+        if let Some((flags, fetched_at)) = self.cache.lock().await.get(scope) {
+            if fetched_at.elapsed() < self.cache_ttl {
+                return flags.clone();
+            }
+        }
+
+        let flags = match self.fetch(scope).await {
+            Ok(flags) => flags,
+            Err(e) => {
+                warn!("Failed to load feature flags for {}: {}", scope, e);
+                // Drop the connection so the next refresh reconnects
+                *self.connection.lock().await = None;
+                FlagSet::new()
+            }
+        };
+
+        self.cache
+            .lock()
+            .await
+            .insert(scope.to_string(), (flags.clone(), Instant::now()));
+        flags
+    }
+
+    async fn fetch(&self, scope: &str) -> Result<FlagSet, redis::RedisError> {
+        let client = match &self.client {
+            Some(client) => client,
+            None => return Ok(FlagSet::new()),
+        };
+
+        let mut connection = self.connection.lock().await;
+        if connection.is_none() {
+            *connection = Some(client.get_async_connection().await?);
+        }
+        let raw: HashMap<String, String> = connection
+            .as_mut()
+            .expect("connection was just set")
+            .hgetall(format!("feature_flags:{}", scope))
+            .await?;
+
+        Ok(raw
+            .into_iter()
+            .filter_map(|(flag, value)| match FlagValue::parse(&value) {
+                Some(parsed) => Some((flag, parsed)),
+                None => {
+                    debug!("Ignoring unparseable value {:?} for flag {}", value, flag);
+                    None
+                }
+            })
+            .collect())
+    }
+}
+
+fn evaluations() -> &'static IntCounterVec {
+    EVALUATIONS.get_or_init(|| {
+        prometheus::register_int_counter_vec!(
+            "feature_flag_evaluations_total",
+            "Feature flag evaluations by result and where the value came from",
+            &["flag", "result", "source"]
+        ).unwrap()
+    })
+}
@@ -0,0 +1,38 @@
+use crm_flags::{FlagClient, FlagValue};
+use std::time::Duration;
+
+#[test]
+fn parses_flag_values() {
+    assert_eq!(FlagValue::parse("on"), Some(FlagValue::On));
+    assert_eq!(FlagValue::parse(" TRUE "), Some(FlagValue::On));
+    assert_eq!(FlagValue::parse("off"), Some(FlagValue::Off));
+    assert_eq!(FlagValue::parse("25%"), Some(FlagValue::Rollout(25)));
+    assert_eq!(FlagValue::parse("250%"), Some(FlagValue::Rollout(100)));
+    assert_eq!(FlagValue::parse("sometimes"), None);
+}
+
+#[test]
+fn rollout_is_stable_and_proportional() {
+    let tenants: Vec<String> = (0..1000).map(|i| format!("tenant_{}", i)).collect();
+    let half = FlagValue::Rollout(50);
+
+    let enabled = tenants.iter().filter(|t| half.enabled_for(t, "new_stage")).count();
+    assert!((400..=600).contains(&enabled), "{} of 1000 enabled", enabled);
+
+    for tenant in &tenants {
+        assert_eq!(half.enabled_for(tenant, "new_stage"), half.enabled_for(tenant, "new_stage"));
+        assert!(!FlagValue::Rollout(0).enabled_for(tenant, "new_stage"));
+        assert!(FlagValue::Rollout(100).enabled_for(tenant, "new_stage"));
+    }
+}
+
+#[tokio::test]
+async fn unreachable_redis_falls_back_to_defaults() {
+    // Nothing listens on port 1
+    let flags = FlagClient::new("redis://127.0.0.1:1", Duration::from_secs(30));
+    assert!(flags.is_enabled("tenant_001", "new_stage", true).await);
+    assert!(!flags.is_enabled("tenant_001", "new_stage", false).await);
+
+    let disabled = FlagClient::disabled();
+    assert!(disabled.is_enabled("tenant_001", "new_stage", true).await);
+}
@@ -0,0 +1,68 @@
+use prometheus::{Encoder, HistogramVec, IntCounterVec, TextEncoder};
+use std::sync::OnceLock;
+use tracing::info;
+use warp::Filter;
+
+static HTTP_REQUESTS: OnceLock<IntCounterVec> = OnceLock::new();
+static HTTP_DURATION: OnceLock<HistogramVec> = OnceLock::new();
+
+/// `GET /metrics` in the Prometheus text format, for services that expose
+/// metrics on their main port.
+pub fn metrics_route() -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    warp::path("metrics").and(warp::get()).map(gather)
+}
+
+/// Serves `/metrics` on its own port.
+pub async fn serve_metrics(port: u16) {
+    info!("Metrics server running on http://0.0.0.0:{}/metrics", port);
+    warp::serve(metrics_route()).run(([0, 0, 0, 0], port)).await;
+}
+
+/// Warp log filter recording `http_requests_total` and
+/// `http_request_duration_seconds` by method, route and status.
+pub fn http_metrics() -> warp::log::Log<impl Fn(warp::log::Info<'_>) + Clone + Send> {
+    let requests = HTTP_REQUESTS.get_or_init(|| {
+        prometheus::register_int_counter_vec!(
+            "http_requests_total",
+            "HTTP requests handled",
+            &["method", "route", "status"]
+        ).unwrap()
+    });
+    let duration = HTTP_DURATION.get_or_init(|| {
+        prometheus::register_histogram_vec!(
+            "http_request_duration_seconds",
+            "HTTP request latency in seconds",
+            &["method", "route"],
+            vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]
+        ).unwrap()
+    });
+
+    warp::log::custom(move |info| {
+        let method = info.method().as_str();
+        let route = route_label(info.path());
+        requests
+            .with_label_values(&[method, &route, info.status().as_str()])
+            .inc();
+        duration
+            .with_label_values(&[method, &route])
+            .observe(info.elapsed().as_secs_f64());
+    })
+}
+
+fn gather() -> String {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer).unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+/// Collapses id-like path segments so per-entity URLs share one series.
+pub(crate) fn route_label(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            let id_like = segment.len() >= 16 && segment.chars().all(|c| c.is_ascii_hexdigit() || c == '-')
+                || (!segment.is_empty() && segment.chars().all(|c| c.is_ascii_digit()));
+            if id_like { ":id" } else { segment }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
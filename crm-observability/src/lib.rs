@@ -0,0 +1,106 @@
+//! Shared logging, tracing and metrics setup for the CRM Rust services.
+//!
+//! ```ignore
+//! let _observability = crm_observability::init("event-ingestion-service")?;
+//! tokio::spawn(crm_observability::serve_metrics(config.metrics_port));
+//! let routes = routes.with(crm_observability::http_metrics()).with(crm_observability::traced());
+//! ```
+//!
+//! Logs go to stdout (`LOG_FORMAT=json` for JSON lines, `RUST_LOG` for
+//! filtering). Spans are exported over OTLP when `OTEL_EXPORTER_OTLP_ENDPOINT`
+//! is set; `traced` continues the trace of callers sending a W3C
+//! `traceparent` header, and `continue_trace` does it for other transports.
+//! Panics are logged and counted before the default hook runs.
+
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::Resource;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+mod http;
+mod panic;
+mod propagation;
+
+pub use http::{http_metrics, metrics_route, serve_metrics};
+pub use propagation::{continue_trace, traced};
+
+#[derive(Debug)]
+pub enum InitError {
+    Exporter(opentelemetry::trace::TraceError),
+    Subscriber(tracing_subscriber::util::TryInitError),
+}
+
+impl std::fmt::Display for InitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InitError::Exporter(e) => write!(f, "failed to create OTLP exporter: {}", e),
+            InitError::Subscriber(e) => write!(f, "failed to install tracing subscriber: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for InitError {}
+
+/// Flushes pending spans when dropped; keep it alive for the life of `main`.
+pub struct ObservabilityGuard {
+    tracer_provider: Option<TracerProvider>,
+}
+
+impl Drop for ObservabilityGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.tracer_provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("failed to flush traces: {}", e);
+            }
+        }
+    }
+}
+
+/// Installs the tracing subscriber, the OTLP exporter (if configured) and the
+/// panic hook. Must be called from within a Tokio runtime.
+pub fn init(service_name: &str) -> Result<ObservabilityGuard, InitError> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let fmt_layer = match std::env::var("LOG_FORMAT").as_deref() {
+        Ok("json") => tracing_subscriber::fmt::layer().json().boxed(),
+        _ => tracing_subscriber::fmt::layer().boxed(),
+    };
+
+    let tracer_provider = match std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+        Ok(endpoint) => Some(tracer_provider(service_name, &endpoint)?),
+        Err(_) => None,
+    };
+    let otel_layer = tracer_provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(service_name.to_string())));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer)
+        .with(otel_layer)
+        .try_init()
+        .map_err(InitError::Subscriber)?;
+
+    panic::install_hook();
+
+    Ok(ObservabilityGuard { tracer_provider })
+}
+
+fn tracer_provider(service_name: &str, endpoint: &str) -> Result<TracerProvider, InitError> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(InitError::Exporter)?;
+
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+        .with_resource(Resource::new(vec![KeyValue::new("service.name", service_name.to_string())]))
+        .build();
+    opentelemetry::global::set_tracer_provider(provider.clone());
+
+    Ok(provider)
+}
@@ -0,0 +1,31 @@
+use prometheus::IntCounter;
+use std::sync::OnceLock;
+
+static PANICS: OnceLock<IntCounter> = OnceLock::new();
+
+/// Logs panics through tracing (so they reach the log pipeline with the
+/// active span) and counts them, then defers to the previous hook.
+pub(crate) fn install_hook() {
+    let panics = PANICS.get_or_init(|| {
+        prometheus::register_int_counter!("panics_total", "Panics caught by the process-wide panic hook").unwrap()
+    });
+
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        panics.inc();
+
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| info.payload().downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("<non-string panic payload>");
+        let location = info
+            .location()
+            .map(|l| format!("{}:{}", l.file(), l.line()))
+            .unwrap_or_default();
+        tracing::error!(panic.location = %location, "panic: {}", message);
+
+        previous(info);
+    }));
+}
@@ -0,0 +1,36 @@
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use std::collections::HashMap;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Makes `span` part of the trace a caller's W3C `traceparent` (and
+/// `tracestate`) header names, so it shows up under the caller's span.
+/// Does nothing without a valid `traceparent` or without an exporter.
+pub fn continue_trace(span: &Span, traceparent: Option<&str>, tracestate: Option<&str>) {
+    let Some(traceparent) = traceparent else {
+        return;
+    };
+    let mut carrier = HashMap::from([("traceparent".to_string(), traceparent.to_string())]);
+    if let Some(tracestate) = tracestate {
+        carrier.insert("tracestate".to_string(), tracestate.to_string());
+    }
+    span.set_parent(TraceContextPropagator::new().extract(&carrier));
+}
+
+/// Warp filter wrapping every request in a `request` span, continuing the
+/// caller's trace when it sends a `traceparent` header.
+pub fn traced() -> warp::trace::Trace<impl Fn(warp::trace::Info<'_>) -> Span + Clone> {
+    warp::trace(|info: warp::trace::Info<'_>| {
+        let span = tracing::info_span!(
+            "request",
+            otel.name = %format!("{} {}", info.method(), crate::http::route_label(info.path())),
+            otel.kind = "server",
+            http.method = %info.method(),
+            http.route = %crate::http::route_label(info.path()),
+        );
+        let header = |name: &str| info.request_headers().get(name).and_then(|value| value.to_str().ok());
+        continue_trace(&span, header("traceparent"), header("tracestate"));
+        span
+    })
+}
@@ -0,0 +1,16 @@
+-- Transactional outbox. Services insert rows in the same transaction as the
+-- change they describe; the relay publishes them to Kafka. Applied by the
+-- relay at startup; every statement is idempotent.
+
+CREATE TABLE IF NOT EXISTS outbox (
+    id BIGSERIAL PRIMARY KEY,
+    topic TEXT NOT NULL,
+    -- Kafka message key; the tenant id, so a tenant's events stay ordered
+    message_key TEXT NOT NULL,
+    -- Serialized CrmEvent
+    payload TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    published_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS outbox_unpublished ON outbox (id) WHERE published_at IS NULL;
@@ -0,0 +1,246 @@
+use crate::{Checkpoint, OutboxError, RelayConfig, OUTBOX_ID_HEADER, OUTBOX_SCHEMA};
+use prometheus::IntCounter;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::error::{KafkaError, KafkaResult};
+use rdkafka::message::{Header, Message, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+use rdkafka::{Offset, TopicPartitionList};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio_postgres::{Client, NoTls};
+use tracing::{debug, error, info, warn};
+
+/// Advisory lock held by the active relay for as long as its session lives.
+const RELAY_LOCK_ID: i64 = 0x6f75_7462_6f78;
+
+/// How often published rows past their retention are deleted.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Limit for Kafka calls outside a transaction, and for reading the
+/// checkpoint topic.
+const KAFKA_TIMEOUT: Duration = Duration::from_secs(30);
+
+static PUBLISHED: OnceLock<IntCounter> = OnceLock::new();
+static FAILURES: OnceLock<IntCounter> = OnceLock::new();
+
+/// Publishes outbox rows to Kafka; see the crate docs for the protocol.
+pub struct OutboxRelay {
+    config: RelayConfig,
+    client: Client,
+    /// `None` until recovery has run, and again after a failed batch.
+    producer: Option<FutureProducer>,
+}
+
+impl OutboxRelay {
+    /// Connects and applies the outbox schema.
+    pub async fn connect(config: &RelayConfig) -> Result<Self, OutboxError> {
+        let (client, connection) = tokio_postgres::connect(&config.database_url, NoTls).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                error!("Postgres connection closed: {}", e);
+            }
+        });
+        client.batch_execute(OUTBOX_SCHEMA).await?;
+
+        Ok(OutboxRelay {
+            config: config.clone(),
+            client,
+            producer: None,
+        })
+    }
+
+    /// Relays until the database connection is lost, which also releases
+    /// the advisory lock; the caller should exit and let a standby take over.
+    pub async fn run(mut self) -> Result<(), OutboxError> {
+        // Standbys wait here until the active relay's session ends
+        info!("Waiting for the outbox relay lock");
+        self.client.execute("SELECT pg_advisory_lock($1)", &[&RELAY_LOCK_ID]).await?;
+        info!("Outbox relay active, publishing with transactional id {}", self.config.transactional_id);
+
+        let poll_interval = Duration::from_millis(self.config.poll_interval_ms);
+        let mut last_cleanup: Option<Instant> = None;
+        loop {
+            let result = match self.producer.is_some() {
+                true => self.relay_batch().await,
+                false => self.recover().await.map(|()| 1),
+            };
+            match result {
+                Ok(0) => tokio::time::sleep(poll_interval).await,
+                Ok(_) => {}
+                Err(e) => {
+                    failures().inc();
+                    if self.client.is_closed() {
+                        return Err(e);
+                    }
+                    error!("Outbox relay failed, recovering: {}", e);
+                    self.producer = None;
+                    tokio::time::sleep(poll_interval).await;
+                }
+            }
+
+            if last_cleanup.is_none_or(|at| at.elapsed() >= CLEANUP_INTERVAL) {
+                last_cleanup = Some(Instant::now());
+                match self
+                    .client
+                    .execute(
+                        "DELETE FROM outbox WHERE published_at < now() - make_interval(hours => $1)",
+                        &[&self.config.retention_hours],
+                    )
+                    .await
+                {
+                    Ok(0) => {}
+                    Ok(deleted) => info!("Deleted {} published outbox rows", deleted),
+                    Err(e) => warn!("Outbox cleanup failed: {}", e),
+                }
+            }
+        }
+    }
+
+    /// Starts a fresh transactional producer, which aborts anything a
+    /// previous relay left open and fences it off, then marks the rows of
+    /// the last committed batch published in case that never happened.
+    async fn recover(&mut self) -> Result<(), OutboxError> {
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", &self.config.kafka_brokers)
+            .set("transactional.id", &self.config.transactional_id)
+            .set("transaction.timeout.ms", self.config.transaction_timeout_ms.to_string())
+            .set("enable.idempotence", "true")
+            .create()?;
+        let init = producer.clone();
+        blocking(move || init.init_transactions(KAFKA_TIMEOUT)).await?;
+
+        let config = self.config.clone();
+        let checkpoint = tokio::task::spawn_blocking(move || read_checkpoint(&config))
+            .await
+            .expect("checkpoint reader panicked")?;
+        if let Some(checkpoint) = checkpoint {
+            let marked = self
+                .client
+                .execute(
+                    "UPDATE outbox SET published_at = now() WHERE id = ANY($1) AND published_at IS NULL",
+                    &[&checkpoint.ids],
+                )
+                .await?;
+            if marked > 0 {
+                info!("Marked {} outbox rows published from the relay checkpoint", marked);
+            }
+        }
+
+        self.producer = Some(producer);
+        Ok(())
+    }
+
+    /// Publishes the next batch in one Kafka transaction; the number of rows.
+    async fn relay_batch(&mut self) -> Result<usize, OutboxError> {
+        let rows = self
+            .client
+            .query(
+                "SELECT id, topic, message_key, payload FROM outbox \
+                 WHERE published_at IS NULL ORDER BY id LIMIT $1",
+                &[&self.config.batch_size],
+            )
+            .await?;
+        if rows.is_empty() {
+            return Ok(0);
+        }
+        let producer = self.producer.as_ref().expect("relay_batch runs after recovery");
+
+        let ids: Vec<i64> = rows.iter().map(|row| row.get(0)).collect();
+        let id_headers: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+        let checkpoint = serde_json::to_string(&Checkpoint { ids: ids.clone() })?;
+
+        producer.begin_transaction()?;
+        let sends = rows.iter().zip(&id_headers).map(|(row, id)| {
+            let record = FutureRecord::to(row.get::<_, &str>(1))
+                .key(row.get::<_, &str>(2))
+                .payload(row.get::<_, &str>(3))
+                .headers(OwnedHeaders::new().insert(Header { key: OUTBOX_ID_HEADER, value: Some(id) }));
+            producer.send(record, Duration::from_secs(0))
+        });
+        for result in futures::future::join_all(sends).await {
+            result.map_err(|(e, _)| e)?;
+        }
+        let record = FutureRecord::to(&self.config.checkpoint_topic)
+            .key(&self.config.transactional_id)
+            .payload(&checkpoint);
+        producer.send(record, Duration::from_secs(0)).await.map_err(|(e, _)| e)?;
+
+        // An unfinished transaction is aborted by the next recovery
+        let commit = producer.clone();
+        let timeout = Duration::from_millis(self.config.transaction_timeout_ms);
+        blocking(move || commit.commit_transaction(timeout)).await?;
+
+        self.client
+            .execute("UPDATE outbox SET published_at = now() WHERE id = ANY($1)", &[&ids])
+            .await?;
+        published().inc_by(ids.len() as u64);
+        debug!("Published {} outbox rows up to id {}", ids.len(), ids[ids.len() - 1]);
+        Ok(ids.len())
+    }
+}
+
+/// Runs one of librdkafka's blocking transaction calls off the runtime.
+async fn blocking<F>(call: F) -> KafkaResult<()>
+where
+    F: FnOnce() -> KafkaResult<()> + Send + 'static,
+{
+    tokio::task::spawn_blocking(call).await.expect("Kafka call panicked")
+}
+
+/// The last checkpoint this relay committed, reading the topic to its end
+/// with `read_committed` so aborted batches are skipped. `None` when there
+/// is none yet.
+fn read_checkpoint(config: &RelayConfig) -> Result<Option<Checkpoint>, OutboxError> {
+    let consumer: BaseConsumer = ClientConfig::new()
+        .set("bootstrap.servers", &config.kafka_brokers)
+        .set("group.id", format!("{}-recovery", config.transactional_id))
+        .set("enable.auto.commit", "false")
+        .set("enable.partition.eof", "true")
+        .set("isolation.level", "read_committed")
+        .create()?;
+
+    let metadata = consumer.fetch_metadata(Some(&config.checkpoint_topic), KAFKA_TIMEOUT)?;
+    let partitions = match metadata.topics().first() {
+        Some(topic) if topic.error().is_none() => topic.partitions().len(),
+        _ => 0,
+    };
+    if partitions == 0 {
+        return Ok(None);
+    }
+    let mut assignment = TopicPartitionList::new();
+    for partition in 0..partitions as i32 {
+        assignment.add_partition_offset(&config.checkpoint_topic, partition, Offset::Beginning)?;
+    }
+    consumer.assign(&assignment)?;
+
+    let deadline = Instant::now() + KAFKA_TIMEOUT;
+    let mut remaining = partitions;
+    let mut latest = None;
+    while remaining > 0 {
+        match consumer.poll(Duration::from_millis(500)) {
+            Some(Ok(message)) if message.key() == Some(config.transactional_id.as_bytes()) => {
+                latest = message.payload().map(serde_json::from_slice).transpose()?;
+            }
+            Some(Ok(_)) => {}
+            Some(Err(KafkaError::PartitionEOF(_))) => remaining -= 1,
+            Some(Err(e)) => return Err(e.into()),
+            None if Instant::now() >= deadline => return Err(OutboxError::CheckpointTimeout),
+            None => {}
+        }
+    }
+    Ok(latest)
+}
+
+fn published() -> &'static IntCounter {
+    PUBLISHED.get_or_init(|| {
+        prometheus::register_int_counter!("outbox_published_total", "Outbox rows published to Kafka").unwrap()
+    })
+}
+
+fn failures() -> &'static IntCounter {
+    FAILURES.get_or_init(|| {
+        prometheus::register_int_counter!("outbox_relay_failures_total", "Failed outbox relay batches and recoveries")
+            .unwrap()
+    })
+}
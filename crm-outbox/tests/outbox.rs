@@ -0,0 +1,38 @@
+use crm_config::Configurable;
+use crm_outbox::{Checkpoint, RelayConfig, OUTBOX_SCHEMA};
+
+#[test]
+fn checkpoints_round_trip() {
+    let checkpoint = Checkpoint { ids: vec![41, 42, 44] };
+    let encoded = serde_json::to_string(&checkpoint).unwrap();
+    assert_eq!(encoded, r#"{"ids":[41,42,44]}"#);
+    assert_eq!(serde_json::from_str::<Checkpoint>(&encoded).unwrap(), checkpoint);
+}
+
+#[test]
+fn config_rejects_unusable_settings() {
+    assert!(RelayConfig::default().validate().is_ok());
+
+    let config = RelayConfig {
+        transactional_id: String::new(),
+        batch_size: 0,
+        transaction_timeout_ms: 10,
+        ..RelayConfig::default()
+    };
+    let problems = config.validate().unwrap_err();
+    assert_eq!(problems.len(), 3, "{:?}", problems);
+}
+
+#[test]
+fn schema_can_be_reapplied() {
+    let sql: String = OUTBOX_SCHEMA
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("--"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let statements: Vec<&str> = sql.split(';').map(str::trim).filter(|s| !s.is_empty()).collect();
+    assert_eq!(statements.len(), 2);
+    for statement in statements {
+        assert!(statement.starts_with("CREATE") && statement.contains("IF NOT EXISTS"), "{}", statement);
+    }
+}
@@ -0,0 +1,953 @@
+// This file is @generated by prost-build.
+/// Asks a service to report its status.
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct StatusRequest {}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ServiceStatus {
+    #[prost(string, tag = "1")]
+    pub service: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub version: ::prost::alloc::string::String,
+    #[prost(enumeration = "service_status::State", tag = "3")]
+    pub state: i32,
+    /// Unix seconds.
+    #[prost(int64, tag = "4")]
+    pub started_at: i64,
+    /// Free-form details such as consumer lag or open circuit breakers.
+    #[prost(map = "string, string", tag = "5")]
+    pub details: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ::prost::alloc::string::String,
+    >,
+}
+/// Nested message and enum types in `ServiceStatus`.
+pub mod service_status {
+    #[derive(
+        Clone,
+        Copy,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash,
+        PartialOrd,
+        Ord,
+        ::prost::Enumeration
+    )]
+    #[repr(i32)]
+    pub enum State {
+        Unspecified = 0,
+        Serving = 1,
+        /// Finishing in-flight work and taking no new work.
+        Draining = 2,
+        /// Serving, but a dependency is failing.
+        Degraded = 3,
+    }
+    impl State {
+        /// String value of the enum field names used in the ProtoBuf definition.
+        ///
+        /// The values are not transformed in any way and thus are considered stable
+        /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+        pub fn as_str_name(&self) -> &'static str {
+            match self {
+                Self::Unspecified => "STATE_UNSPECIFIED",
+                Self::Serving => "STATE_SERVING",
+                Self::Draining => "STATE_DRAINING",
+                Self::Degraded => "STATE_DEGRADED",
+            }
+        }
+        /// Creates an enum from field names used in the ProtoBuf definition.
+        pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+            match value {
+                "STATE_UNSPECIFIED" => Some(Self::Unspecified),
+                "STATE_SERVING" => Some(Self::Serving),
+                "STATE_DRAINING" => Some(Self::Draining),
+                "STATE_DEGRADED" => Some(Self::Degraded),
+                _ => None,
+            }
+        }
+    }
+}
+/// Asks a service to stop taking new work, e.g. before a deploy.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DrainRequest {
+    /// How long in-flight work may take before it is abandoned.
+    #[prost(uint32, optional, tag = "1")]
+    pub deadline_seconds: ::core::option::Option<u32>,
+    #[prost(string, tag = "2")]
+    pub reason: ::prost::alloc::string::String,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct DrainResponse {
+    /// Work still in flight when the request was accepted.
+    #[prost(uint64, tag = "1")]
+    pub in_flight: u64,
+}
+/// Asks a service to re-read its configuration and secrets.
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ReloadConfigRequest {}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReloadConfigResponse {
+    #[prost(bool, tag = "1")]
+    pub changed: bool,
+    /// Validation problems; the previous configuration stays in effect.
+    #[prost(string, repeated, tag = "2")]
+    pub problems: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// The envelope every producer publishes; mirrors crm_events::CrmEvent.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CrmEvent {
+    #[prost(string, tag = "1")]
+    pub tenant_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub event_type: ::prost::alloc::string::String,
+    /// JSON-encoded payload. Typed payloads are defined by crm-events.
+    #[prost(bytes = "vec", tag = "3")]
+    pub payload_json: ::prost::alloc::vec::Vec<u8>,
+    /// Unix seconds.
+    #[prost(int64, tag = "4")]
+    pub timestamp: i64,
+    #[prost(string, optional, tag = "5")]
+    pub source: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "6")]
+    pub user_id: ::core::option::Option<::prost::alloc::string::String>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExecuteRequest {
+    /// Relative to the runtime's module directory.
+    #[prost(string, tag = "1")]
+    pub module_path: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub function_name: ::prost::alloc::string::String,
+    /// JSON array of arguments.
+    #[prost(string, tag = "3")]
+    pub params_json: ::prost::alloc::string::String,
+    /// Capped at 300 by the runtime; 30 when unset.
+    #[prost(uint64, optional, tag = "4")]
+    pub timeout_seconds: ::core::option::Option<u64>,
+    /// Tenant the plugin runs for; used for feature flags, audit and secrets.
+    #[prost(string, optional, tag = "5")]
+    pub tenant_id: ::core::option::Option<::prost::alloc::string::String>,
+    /// Names of the tenant's plugin secrets to expose as environment variables.
+    #[prost(string, repeated, tag = "6")]
+    pub secrets: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// Return the lines the plugin logged through the crm.log_* host functions.
+    #[prost(bool, tag = "7")]
+    pub include_logs: bool,
+    /// "string" or "bytes" when the function returns a (ptr, len) pair
+    /// pointing into its memory; plain values when empty.
+    #[prost(string, tag = "8")]
+    pub result_encoding: ::prost::alloc::string::String,
+    /// "json" when the plugin exports handle(ptr, len) -> (ptr, len) and
+    /// takes params_json as one document.
+    #[prost(string, tag = "9")]
+    pub calling_convention: ::prost::alloc::string::String,
+    /// Environment variables for the plugin, as the runtime's request_env
+    /// allows.
+    #[prost(map = "string, string", tag = "10")]
+    pub env: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ::prost::alloc::string::String,
+    >,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExecuteResponse {
+    #[prost(bool, tag = "1")]
+    pub success: bool,
+    /// JSON result of a successful call.
+    #[prost(string, optional, tag = "2")]
+    pub result_json: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "3")]
+    pub error: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(uint64, tag = "4")]
+    pub execution_time_ms: u64,
+    /// Peak linear memory of the plugin's instances, initial memory included.
+    #[prost(uint64, tag = "5")]
+    pub memory_used_bytes: u64,
+    #[prost(uint64, tag = "6")]
+    pub fuel_consumed: u64,
+    /// What the plugin wrote, each cut to the runtime's max_output_bytes.
+    #[prost(string, tag = "7")]
+    pub stdout: ::prost::alloc::string::String,
+    #[prost(string, tag = "8")]
+    pub stderr: ::prost::alloc::string::String,
+    #[prost(bool, tag = "9")]
+    pub output_truncated: bool,
+    /// Tags the plugin's log lines and the runtime's own for this call.
+    #[prost(string, tag = "10")]
+    pub execution_id: ::prost::alloc::string::String,
+    /// Set when the request asked for them, up to the runtime's max_plugin_logs.
+    #[prost(message, repeated, tag = "11")]
+    pub logs: ::prost::alloc::vec::Vec<PluginLog>,
+    /// Why a call failed, as the execute API's error codes, e.g. FUEL_EXHAUSTED.
+    #[prost(string, optional, tag = "12")]
+    pub error_code: ::core::option::Option<::prost::alloc::string::String>,
+    /// Whether the same call may succeed if made again later.
+    #[prost(bool, tag = "13")]
+    pub retryable: bool,
+}
+/// A line a plugin logged through the crm.log_* host functions.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PluginLog {
+    #[prost(enumeration = "plugin_log::Level", tag = "1")]
+    pub level: i32,
+    #[prost(string, tag = "2")]
+    pub message: ::prost::alloc::string::String,
+    /// JSON object of the line's fields.
+    #[prost(string, tag = "3")]
+    pub fields_json: ::prost::alloc::string::String,
+}
+/// Nested message and enum types in `PluginLog`.
+pub mod plugin_log {
+    #[derive(
+        Clone,
+        Copy,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash,
+        PartialOrd,
+        Ord,
+        ::prost::Enumeration
+    )]
+    #[repr(i32)]
+    pub enum Level {
+        Unspecified = 0,
+        Debug = 1,
+        Info = 2,
+        Warn = 3,
+        Error = 4,
+    }
+    impl Level {
+        /// String value of the enum field names used in the ProtoBuf definition.
+        ///
+        /// The values are not transformed in any way and thus are considered stable
+        /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+        pub fn as_str_name(&self) -> &'static str {
+            match self {
+                Self::Unspecified => "LEVEL_UNSPECIFIED",
+                Self::Debug => "LEVEL_DEBUG",
+                Self::Info => "LEVEL_INFO",
+                Self::Warn => "LEVEL_WARN",
+                Self::Error => "LEVEL_ERROR",
+            }
+        }
+        /// Creates an enum from field names used in the ProtoBuf definition.
+        pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+            match value {
+                "LEVEL_UNSPECIFIED" => Some(Self::Unspecified),
+                "LEVEL_DEBUG" => Some(Self::Debug),
+                "LEVEL_INFO" => Some(Self::Info),
+                "LEVEL_WARN" => Some(Self::Warn),
+                "LEVEL_ERROR" => Some(Self::Error),
+                _ => None,
+            }
+        }
+    }
+}
+/// Exactly one field is set.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExecuteEvent {
+    #[prost(message, optional, tag = "1")]
+    pub log: ::core::option::Option<LogLine>,
+    #[prost(message, optional, tag = "2")]
+    pub response: ::core::option::Option<ExecuteResponse>,
+}
+/// One line of plugin output.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct LogLine {
+    #[prost(enumeration = "log_line::Stream", tag = "1")]
+    pub stream: i32,
+    #[prost(string, tag = "2")]
+    pub line: ::prost::alloc::string::String,
+}
+/// Nested message and enum types in `LogLine`.
+pub mod log_line {
+    #[derive(
+        Clone,
+        Copy,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash,
+        PartialOrd,
+        Ord,
+        ::prost::Enumeration
+    )]
+    #[repr(i32)]
+    pub enum Stream {
+        Unspecified = 0,
+        Stdout = 1,
+        Stderr = 2,
+    }
+    impl Stream {
+        /// String value of the enum field names used in the ProtoBuf definition.
+        ///
+        /// The values are not transformed in any way and thus are considered stable
+        /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+        pub fn as_str_name(&self) -> &'static str {
+            match self {
+                Self::Unspecified => "STREAM_UNSPECIFIED",
+                Self::Stdout => "STREAM_STDOUT",
+                Self::Stderr => "STREAM_STDERR",
+            }
+        }
+        /// Creates an enum from field names used in the ProtoBuf definition.
+        pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+            match value {
+                "STREAM_UNSPECIFIED" => Some(Self::Unspecified),
+                "STREAM_STDOUT" => Some(Self::Stdout),
+                "STREAM_STDERR" => Some(Self::Stderr),
+                _ => None,
+            }
+        }
+    }
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PrecompileRequest {
+    #[prost(string, repeated, tag = "1")]
+    pub module_paths: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PrecompileResponse {
+    /// In the order requested.
+    #[prost(message, repeated, tag = "1")]
+    pub modules: ::prost::alloc::vec::Vec<PrecompiledModule>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PrecompiledModule {
+    #[prost(string, tag = "1")]
+    pub module_path: ::prost::alloc::string::String,
+    /// Where the compiled module came from: "memory", "disk" or "compiled".
+    #[prost(string, optional, tag = "2")]
+    pub cache: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "3")]
+    pub error: ::core::option::Option<::prost::alloc::string::String>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct InspectModuleRequest {
+    #[prost(string, tag = "1")]
+    pub module_path: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct InspectModuleResponse {
+    /// Hex SHA-256 of the module's bytes.
+    #[prost(string, tag = "1")]
+    pub sha256: ::prost::alloc::string::String,
+    #[prost(message, repeated, tag = "2")]
+    pub exports: ::prost::alloc::vec::Vec<ModuleExport>,
+    #[prost(message, repeated, tag = "3")]
+    pub imports: ::prost::alloc::vec::Vec<ModuleImport>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ModuleExport {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "2")]
+    pub kind: ::core::option::Option<ExternType>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ModuleImport {
+    #[prost(string, tag = "1")]
+    pub module: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "3")]
+    pub kind: ::core::option::Option<ExternType>,
+}
+/// Exactly one field is set.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExternType {
+    #[prost(message, optional, tag = "1")]
+    pub function: ::core::option::Option<FunctionType>,
+    #[prost(message, optional, tag = "2")]
+    pub memory: ::core::option::Option<MemoryType>,
+    #[prost(message, optional, tag = "3")]
+    pub table: ::core::option::Option<TableType>,
+    #[prost(message, optional, tag = "4")]
+    pub global: ::core::option::Option<GlobalType>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FunctionType {
+    /// Value types such as "i32" or "f64".
+    #[prost(string, repeated, tag = "1")]
+    pub params: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(string, repeated, tag = "2")]
+    pub results: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct MemoryType {
+    /// In 64 KiB pages.
+    #[prost(uint64, tag = "1")]
+    pub minimum_pages: u64,
+    #[prost(uint64, optional, tag = "2")]
+    pub maximum_pages: ::core::option::Option<u64>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TableType {
+    /// Element type, e.g. "funcref".
+    #[prost(string, tag = "1")]
+    pub element: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "2")]
+    pub minimum: u32,
+    #[prost(uint32, optional, tag = "3")]
+    pub maximum: ::core::option::Option<u32>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GlobalType {
+    /// Value type, e.g. "i64".
+    #[prost(string, tag = "1")]
+    pub content: ::prost::alloc::string::String,
+    #[prost(bool, tag = "2")]
+    pub mutable: bool,
+}
+/// Generated client implementations.
+pub mod extension_runtime_client {
+    #![allow(
+        unused_variables,
+        dead_code,
+        missing_docs,
+        clippy::wildcard_imports,
+        clippy::let_unit_value,
+    )]
+    use tonic::codegen::*;
+    use tonic::codegen::http::Uri;
+    /// Runs WASM plugin modules for tenants.
+    #[derive(Debug, Clone)]
+    pub struct ExtensionRuntimeClient<T> {
+        inner: tonic::client::Grpc<T>,
+    }
+    impl ExtensionRuntimeClient<tonic::transport::Channel> {
+        /// Attempt to create a new client by connecting to a given endpoint.
+        pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+        where
+            D: TryInto<tonic::transport::Endpoint>,
+            D::Error: Into<StdError>,
+        {
+            let conn = tonic::transport::Endpoint::new(dst)?.connect().await?;
+            Ok(Self::new(conn))
+        }
+    }
+    impl<T> ExtensionRuntimeClient<T>
+    where
+        T: tonic::client::GrpcService<tonic::body::BoxBody>,
+        T::Error: Into<StdError>,
+        T::ResponseBody: Body<Data = Bytes> + std::marker::Send + 'static,
+        <T::ResponseBody as Body>::Error: Into<StdError> + std::marker::Send,
+    {
+        pub fn new(inner: T) -> Self {
+            let inner = tonic::client::Grpc::new(inner);
+            Self { inner }
+        }
+        pub fn with_origin(inner: T, origin: Uri) -> Self {
+            let inner = tonic::client::Grpc::with_origin(inner, origin);
+            Self { inner }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> ExtensionRuntimeClient<InterceptedService<T, F>>
+        where
+            F: tonic::service::Interceptor,
+            T::ResponseBody: Default,
+            T: tonic::codegen::Service<
+                http::Request<tonic::body::BoxBody>,
+                Response = http::Response<
+                    <T as tonic::client::GrpcService<tonic::body::BoxBody>>::ResponseBody,
+                >,
+            >,
+            <T as tonic::codegen::Service<
+                http::Request<tonic::body::BoxBody>,
+            >>::Error: Into<StdError> + std::marker::Send + std::marker::Sync,
+        {
+            ExtensionRuntimeClient::new(InterceptedService::new(inner, interceptor))
+        }
+        /// Compress requests with the given encoding.
+        ///
+        /// This requires the server to support it otherwise it might respond with an
+        /// error.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.send_compressed(encoding);
+            self
+        }
+        /// Enable decompressing responses.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.accept_compressed(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_decoding_message_size(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_encoding_message_size(limit);
+            self
+        }
+        /// Runs one exported function of a module in the runtime's module directory.
+        pub async fn execute(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ExecuteRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ExecuteResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/crm.v1.ExtensionRuntime/Execute",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new(
+                        "crm.v1.ExtensionRuntime",
+                        "Execute",
+                    ),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        /// Execute, streaming what the plugin writes to stdout and stderr while it
+        /// runs; the last event is the response.
+        pub async fn execute_stream(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ExecuteRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::ExecuteEvent>>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/crm.v1.ExtensionRuntime/ExecuteStream",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("crm.v1.ExtensionRuntime", "ExecuteStream"));
+            self.inner.server_streaming(req, path, codec).await
+        }
+        /// Compiles modules ahead of their first execution.
+        pub async fn precompile(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PrecompileRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::PrecompileResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/crm.v1.ExtensionRuntime/Precompile",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("crm.v1.ExtensionRuntime", "Precompile"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// Describes a module's imports and exports without running it.
+        pub async fn inspect_module(
+            &mut self,
+            request: impl tonic::IntoRequest<super::InspectModuleRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::InspectModuleResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/crm.v1.ExtensionRuntime/InspectModule",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("crm.v1.ExtensionRuntime", "InspectModule"));
+            self.inner.unary(req, path, codec).await
+        }
+    }
+}
+/// Generated server implementations.
+pub mod extension_runtime_server {
+    #![allow(
+        unused_variables,
+        dead_code,
+        missing_docs,
+        clippy::wildcard_imports,
+        clippy::let_unit_value,
+    )]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with ExtensionRuntimeServer.
+    #[async_trait]
+    pub trait ExtensionRuntime: std::marker::Send + std::marker::Sync + 'static {
+        /// Runs one exported function of a module in the runtime's module directory.
+        async fn execute(
+            &self,
+            request: tonic::Request<super::ExecuteRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ExecuteResponse>,
+            tonic::Status,
+        >;
+        /// Server streaming response type for the ExecuteStream method.
+        type ExecuteStreamStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::ExecuteEvent, tonic::Status>,
+            >
+            + std::marker::Send
+            + 'static;
+        /// Execute, streaming what the plugin writes to stdout and stderr while it
+        /// runs; the last event is the response.
+        async fn execute_stream(
+            &self,
+            request: tonic::Request<super::ExecuteRequest>,
+        ) -> std::result::Result<
+            tonic::Response<Self::ExecuteStreamStream>,
+            tonic::Status,
+        >;
+        /// Compiles modules ahead of their first execution.
+        async fn precompile(
+            &self,
+            request: tonic::Request<super::PrecompileRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::PrecompileResponse>,
+            tonic::Status,
+        >;
+        /// Describes a module's imports and exports without running it.
+        async fn inspect_module(
+            &self,
+            request: tonic::Request<super::InspectModuleRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::InspectModuleResponse>,
+            tonic::Status,
+        >;
+    }
+    /// Runs WASM plugin modules for tenants.
+    #[derive(Debug)]
+    pub struct ExtensionRuntimeServer<T> {
+        inner: Arc<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    impl<T> ExtensionRuntimeServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with the given encoding.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.accept_compression_encodings.enable(encoding);
+            self
+        }
+        /// Compress responses with the given encoding, if the client supports it.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.send_compression_encodings.enable(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.max_decoding_message_size = Some(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.max_encoding_message_size = Some(limit);
+            self
+        }
+    }
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for ExtensionRuntimeServer<T>
+    where
+        T: ExtensionRuntime,
+        B: Body + std::marker::Send + 'static,
+        B::Error: Into<StdError> + std::marker::Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            match req.uri().path() {
+                "/crm.v1.ExtensionRuntime/Execute" => {
+                    #[allow(non_camel_case_types)]
+                    struct ExecuteSvc<T: ExtensionRuntime>(pub Arc<T>);
+                    impl<
+                        T: ExtensionRuntime,
+                    > tonic::server::UnaryService<super::ExecuteRequest>
+                    for ExecuteSvc<T> {
+                        type Response = super::ExecuteResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ExecuteRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ExtensionRuntime>::execute(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ExecuteSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/crm.v1.ExtensionRuntime/ExecuteStream" => {
+                    #[allow(non_camel_case_types)]
+                    struct ExecuteStreamSvc<T: ExtensionRuntime>(pub Arc<T>);
+                    impl<
+                        T: ExtensionRuntime,
+                    > tonic::server::ServerStreamingService<super::ExecuteRequest>
+                    for ExecuteStreamSvc<T> {
+                        type Response = super::ExecuteEvent;
+                        type ResponseStream = T::ExecuteStreamStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ExecuteRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ExtensionRuntime>::execute_stream(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ExecuteStreamSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/crm.v1.ExtensionRuntime/Precompile" => {
+                    #[allow(non_camel_case_types)]
+                    struct PrecompileSvc<T: ExtensionRuntime>(pub Arc<T>);
+                    impl<
+                        T: ExtensionRuntime,
+                    > tonic::server::UnaryService<super::PrecompileRequest>
+                    for PrecompileSvc<T> {
+                        type Response = super::PrecompileResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PrecompileRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ExtensionRuntime>::precompile(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = PrecompileSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/crm.v1.ExtensionRuntime/InspectModule" => {
+                    #[allow(non_camel_case_types)]
+                    struct InspectModuleSvc<T: ExtensionRuntime>(pub Arc<T>);
+                    impl<
+                        T: ExtensionRuntime,
+                    > tonic::server::UnaryService<super::InspectModuleRequest>
+                    for InspectModuleSvc<T> {
+                        type Response = super::InspectModuleResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::InspectModuleRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ExtensionRuntime>::inspect_module(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = InspectModuleSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => {
+                    Box::pin(async move {
+                        let mut response = http::Response::new(empty_body());
+                        let headers = response.headers_mut();
+                        headers
+                            .insert(
+                                tonic::Status::GRPC_STATUS,
+                                (tonic::Code::Unimplemented as i32).into(),
+                            );
+                        headers
+                            .insert(
+                                http::header::CONTENT_TYPE,
+                                tonic::metadata::GRPC_CONTENT_TYPE,
+                            );
+                        Ok(response)
+                    })
+                }
+            }
+        }
+    }
+    impl<T> Clone for ExtensionRuntimeServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self {
+                inner,
+                accept_compression_encodings: self.accept_compression_encodings,
+                send_compression_encodings: self.send_compression_encodings,
+                max_decoding_message_size: self.max_decoding_message_size,
+                max_encoding_message_size: self.max_encoding_message_size,
+            }
+        }
+    }
+    /// Generated gRPC service name
+    pub const SERVICE_NAME: &str = "crm.v1.ExtensionRuntime";
+    impl<T> tonic::server::NamedService for ExtensionRuntimeServer<T> {
+        const NAME: &'static str = SERVICE_NAME;
+    }
+}
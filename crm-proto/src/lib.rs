@@ -0,0 +1,54 @@
+//! Protobuf contracts shared by the CRM Rust services.
+//!
+//! The `.proto` files under `proto/` are the source of truth. Each package
+//! version (`crm.v1`) is a module here; a breaking change gets a new package
+//! rather than an edit to an existing message.
+//!
+//! The prost/tonic output is checked in as `src/crm.v1.rs` so services build
+//! without `protoc`. Regenerate it with prost-build/tonic-build after editing
+//! a `.proto`; `tests/compat.rs` fails when the two disagree on a field's
+//! number or type, and pins the encoding of existing messages.
+
+pub use prost;
+pub use tonic;
+
+/// `content-type` of protobuf-encoded Kafka messages; JSON is the default.
+pub const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+
+pub mod v1 {
+    include!("crm.v1.rs");
+}
+
+impl From<&crm_events::CrmEvent> for v1::CrmEvent {
+    fn from(event: &crm_events::CrmEvent) -> Self {
+        v1::CrmEvent {
+            tenant_id: event.tenant_id.clone(),
+            event_type: event.event_type.clone(),
+            payload_json: serde_json::to_vec(&event.payload).unwrap_or_default(),
+            timestamp: event.timestamp,
+            source: event.source.clone(),
+            user_id: event.user_id.clone(),
+        }
+    }
+}
+
+impl TryFrom<v1::CrmEvent> for crm_events::CrmEvent {
+    type Error = serde_json::Error;
+
+    fn try_from(event: v1::CrmEvent) -> Result<Self, Self::Error> {
+        let payload = if event.payload_json.is_empty() {
+            serde_json::Value::Object(Default::default())
+        } else {
+            serde_json::from_slice(&event.payload_json)?
+        };
+
+        Ok(crm_events::CrmEvent {
+            tenant_id: event.tenant_id,
+            event_type: event.event_type,
+            payload,
+            timestamp: event.timestamp,
+            source: event.source,
+            user_id: event.user_id,
+        })
+    }
+}
@@ -0,0 +1,274 @@
+use crm_proto::prost::Message;
+use crm_proto::tonic::{self, Request, Response, Status};
+use crm_proto::v1::extension_runtime_client::ExtensionRuntimeClient;
+use crm_proto::v1::extension_runtime_server::{ExtensionRuntime, ExtensionRuntimeServer};
+use crm_proto::v1::{self, ExecuteRequest, ExecuteResponse};
+use std::collections::BTreeSet;
+
+/// (message, field, kind, tag) for every field, e.g.
+/// `("ExecuteRequest", "secrets", "repeated string", 6)`.
+type Fields = BTreeSet<(String, String, String, u32)>;
+
+fn proto_fields() -> Fields {
+    let mut fields = Fields::new();
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/proto/crm/v1");
+    let sources: Vec<String> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+        .collect();
+    // Fields may refer to messages declared further down or in another file
+    let messages: BTreeSet<&str> = sources
+        .iter()
+        .flat_map(|source| source.lines())
+        .filter_map(|line| line.trim().strip_prefix("message "))
+        .filter_map(|rest| rest.split_whitespace().next())
+        .collect();
+    for source in &sources {
+        // Innermost open block: Some(name) for messages, None for enums/services
+        let mut blocks: Vec<Option<String>> = Vec::new();
+        let mut enums = BTreeSet::new();
+        for line in source.lines().map(str::trim).filter(|l| !l.starts_with("//")) {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["message", _, "{}"] => {}
+                ["message", name, "{"] => blocks.push(Some(name.to_string())),
+                ["enum", name, "{"] => {
+                    enums.insert(name.to_string());
+                    blocks.push(None)
+                }
+                ["service", _, "{"] => blocks.push(None),
+                ["}"] => {
+                    blocks.pop();
+                }
+                _ => {
+                    let Some(Some(message)) = blocks.last() else { continue };
+                    let Some((decl, tag)) = line.trim_end_matches(';').split_once('=') else { continue };
+                    let mut decl: Vec<&str> = decl.split_whitespace().collect();
+                    let name = decl.pop().unwrap().to_string();
+                    let kind = if decl[0].starts_with("map<") {
+                        "map".to_string()
+                    } else {
+                        let kind = decl
+                            .iter()
+                            .map(|word| match *word {
+                                word if enums.contains(word) => "enumeration",
+                                word if messages.contains(word) => "message",
+                                word => word,
+                            })
+                            .collect::<Vec<_>>()
+                            .join(" ");
+                        // prost makes singular message fields optional
+                        if kind == "message" { "optional message".to_string() } else { kind }
+                    };
+                    fields.insert((message.clone(), name, kind, tag.trim().parse().unwrap()));
+                }
+            }
+        }
+    }
+    fields
+}
+
+fn generated_fields() -> Fields {
+    let mut fields = Fields::new();
+    let source = include_str!("../src/crm.v1.rs");
+    let mut message = String::new();
+    let mut attribute: Option<(String, u32)> = None;
+    for line in source.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix("pub struct ") {
+            message = name.split(|c: char| !c.is_alphanumeric()).next().unwrap().to_string();
+        } else if let Some(attr) = line.strip_prefix("#[prost(").and_then(|a| a.strip_suffix(")]")) {
+            let parts: Vec<&str> = attr.split(", ").collect();
+            let tag = parts.iter().find_map(|p| p.strip_prefix("tag = \"")).unwrap();
+            let base = parts[0].split(" = ").next().unwrap();
+            let kind = match parts.get(1).copied().filter(|p| !p.starts_with("tag")) {
+                // Maps carry their key type after a comma inside the quotes
+                _ if base == "map" => "map".to_string(),
+                Some(label) => format!("{} {}", label, base),
+                None => base.to_string(),
+            };
+            attribute = Some((kind, tag.trim_end_matches('"').parse().unwrap()));
+        } else if let Some(field) = line.strip_prefix("pub ").filter(|_| attribute.is_some()) {
+            let (kind, tag) = attribute.take().unwrap();
+            let name = field.split(':').next().unwrap().to_string();
+            fields.insert((message.clone(), name, kind, tag));
+        }
+    }
+    fields
+}
+
+#[test]
+fn generated_code_matches_proto_files() {
+    let proto = proto_fields();
+    let generated = generated_fields();
+    assert!(proto.contains(&("ExecuteRequest".into(), "secrets".into(), "repeated string".into(), 6)));
+    assert!(proto.contains(&("ServiceStatus".into(), "state".into(), "enumeration".into(), 3)));
+    assert!(proto.contains(&("ExecuteEvent".into(), "log".into(), "optional message".into(), 1)));
+    assert_eq!(
+        proto, generated,
+        "src/crm.v1.rs is out of date with proto/; regenerate it with prost-build"
+    );
+}
+
+fn sample_event() -> v1::CrmEvent {
+    v1::CrmEvent {
+        tenant_id: "t1".to_string(),
+        event_type: "deal_updated".to_string(),
+        payload_json: br#"{"deal_id":"d1"}"#.to_vec(),
+        timestamp: 1_700_000_000,
+        source: Some("crm".to_string()),
+        user_id: None,
+    }
+}
+
+/// Existing encodings must not change; a new field gets a new number.
+#[test]
+fn encodings_are_stable() {
+    assert_eq!(
+        sample_event().encode_to_vec(),
+        b"\n\x02t1\x12\x0cdeal_updated\x1a\x10{\"deal_id\":\"d1\"} \x80\xe2\xcf\xaa\x06*\x03crm".to_vec()
+    );
+
+    let request = ExecuteRequest {
+        module_path: "m.wasm".to_string(),
+        function_name: "run".to_string(),
+        params_json: "[1]".to_string(),
+        timeout_seconds: Some(5),
+        tenant_id: Some("t1".to_string()),
+        secrets: vec!["API_KEY".to_string()],
+        include_logs: false,
+        result_encoding: String::new(),
+        calling_convention: String::new(),
+        env: Default::default(),
+    };
+    assert_eq!(
+        request.encode_to_vec(),
+        b"\n\x06m.wasm\x12\x03run\x1a\x03[1] \x05*\x02t12\x07API_KEY".to_vec()
+    );
+
+    let response = ExecuteResponse {
+        success: false,
+        error: Some("boom".to_string()),
+        fuel_consumed: 7,
+        execution_id: "e1".to_string(),
+        error_code: Some("TRAP".to_string()),
+        retryable: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        response.encode_to_vec(),
+        b"\x1a\x04boom0\x07R\x02e1b\x04TRAPh\x01".to_vec()
+    );
+}
+
+#[test]
+fn old_and_new_writers_are_readable() {
+    // A request from before tenants and secrets existed
+    let old = b"\n\x06m.wasm\x12\x03run\x1a\x02[]";
+    let request = ExecuteRequest::decode(&old[..]).unwrap();
+    assert_eq!(request.module_path, "m.wasm");
+    assert_eq!(request.tenant_id, None);
+    assert!(request.secrets.is_empty());
+
+    // An event from a writer that knows a field we don't (tag 15)
+    let mut newer = sample_event().encode_to_vec();
+    newer.extend_from_slice(b"\x7a\x05extra");
+    assert_eq!(v1::CrmEvent::decode(newer.as_slice()).unwrap(), sample_event());
+}
+
+#[test]
+fn converts_events_both_ways() {
+    let event = crm_events::CrmEvent::builder("t1", "deal_updated")
+        .payload(serde_json::json!({ "deal_id": "d1" }))
+        .source("crm")
+        .build();
+
+    let wire = v1::CrmEvent::from(&event);
+    assert_eq!(crm_events::CrmEvent::try_from(wire).unwrap(), event);
+
+    // An omitted payload reads as an empty object
+    let empty = v1::CrmEvent {
+        payload_json: Vec::new(),
+        ..sample_event()
+    };
+    assert_eq!(crm_events::CrmEvent::try_from(empty).unwrap().payload, serde_json::json!({}));
+}
+
+struct Echo;
+
+#[tonic::async_trait]
+impl ExtensionRuntime for Echo {
+    async fn execute(&self, request: Request<ExecuteRequest>) -> Result<Response<ExecuteResponse>, Status> {
+        let request = request.into_inner();
+        Ok(Response::new(ExecuteResponse {
+            success: true,
+            result_json: Some(request.params_json),
+            error: None,
+            execution_time_ms: 1,
+            memory_used_bytes: 0,
+            fuel_consumed: 42,
+            ..Default::default()
+        }))
+    }
+
+    type ExecuteStreamStream = tonic::codegen::BoxStream<v1::ExecuteEvent>;
+
+    async fn execute_stream(&self, request: Request<ExecuteRequest>) -> Result<Response<Self::ExecuteStreamStream>, Status> {
+        let log = v1::LogLine {
+            stream: v1::log_line::Stream::Stdout as i32,
+            line: request.get_ref().function_name.clone(),
+        };
+        let response = self.execute(request).await?.into_inner();
+        let events = [
+            v1::ExecuteEvent { log: Some(log), response: None },
+            v1::ExecuteEvent { log: None, response: Some(response) },
+        ];
+        Ok(Response::new(Box::pin(tonic::codegen::tokio_stream::iter(events.map(Ok)))))
+    }
+
+    async fn precompile(&self, _: Request<v1::PrecompileRequest>) -> Result<Response<v1::PrecompileResponse>, Status> {
+        Err(Status::unimplemented("precompile"))
+    }
+
+    async fn inspect_module(&self, _: Request<v1::InspectModuleRequest>) -> Result<Response<v1::InspectModuleResponse>, Status> {
+        Err(Status::unimplemented("inspect_module"))
+    }
+}
+
+#[tokio::test]
+async fn runtime_service_round_trips_over_grpc() {
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let addr = ([127, 0, 0, 1], port).into();
+    tokio::spawn(tonic::transport::Server::builder().add_service(ExtensionRuntimeServer::new(Echo)).serve(addr));
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+    let mut client = ExtensionRuntimeClient::connect(format!("http://127.0.0.1:{}", port)).await.unwrap();
+    let response = client
+        .execute(ExecuteRequest {
+            module_path: "m.wasm".to_string(),
+            function_name: "run".to_string(),
+            params_json: "[1,2]".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert!(response.success);
+    assert_eq!(response.result_json.as_deref(), Some("[1,2]"));
+    assert_eq!(response.fuel_consumed, 42);
+
+    let mut events = client
+        .execute_stream(ExecuteRequest {
+            module_path: "m.wasm".to_string(),
+            function_name: "run".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let log = events.message().await.unwrap().unwrap().log.unwrap();
+    assert_eq!((log.stream(), log.line.as_str()), (v1::log_line::Stream::Stdout, "run"));
+    let last = events.message().await.unwrap().unwrap();
+    assert_eq!(last.response.map(|response| response.fuel_consumed), Some(42));
+    assert!(events.message().await.unwrap().is_none());
+}
@@ -0,0 +1,312 @@
+use crate::envelope::{DataKey, KeyWrapper};
+use crate::{Secret, SecretsError, SecretsProvider};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::Duration;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Static credentials from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+/// optionally `AWS_SESSION_TOKEN`.
+#[derive(Clone)]
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl Credentials {
+    fn from_env() -> Result<Self, SecretsError> {
+        let var = |name: &str| {
+            std::env::var(name).map_err(|_| SecretsError::Backend(format!("{} is not set", name)))
+        };
+        Ok(Credentials {
+            access_key_id: var("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+}
+
+/// Calls AWS JSON 1.1 APIs (SSM, KMS) with SigV4-signed requests.
+struct AwsJsonClient {
+    http: reqwest::Client,
+    credentials: Credentials,
+    region: String,
+    service: &'static str,
+    endpoint: String,
+}
+
+impl AwsJsonClient {
+    fn new(service: &'static str, region: &str, endpoint: Option<&str>) -> Result<Self, SecretsError> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|e| SecretsError::Backend(e.to_string()))?;
+
+        Ok(AwsJsonClient {
+            http,
+            credentials: Credentials::from_env()?,
+            region: region.to_string(),
+            service,
+            endpoint: endpoint
+                .map(|e| e.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| format!("https://{}.{}.amazonaws.com", service, region)),
+        })
+    }
+
+    /// `Ok(None)` when AWS reports the target as missing.
+    async fn call(&self, target: &str, body: &Value, missing: &str) -> Result<Option<Value>, SecretsError> {
+        let body = body.to_string();
+        let host = self
+            .endpoint
+            .split_once("://")
+            .map_or(self.endpoint.as_str(), |(_, rest)| rest)
+            .to_string();
+        let amz_date = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+        let mut headers = vec![
+            ("content-type".to_string(), "application/x-amz-json-1.1".to_string()),
+            ("host".to_string(), host),
+            ("x-amz-date".to_string(), amz_date.clone()),
+            ("x-amz-target".to_string(), target.to_string()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        let authorization = sign(&self.credentials, &self.region, self.service, &amz_date, "POST", "/", "", &headers, &body);
+
+        let mut request = self.http.post(format!("{}/", self.endpoint)).header("authorization", authorization);
+        for (name, value) in headers.iter().filter(|(name, _)| name != "host") {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        let response = request.body(body).send().await.map_err(|e| SecretsError::Backend(e.to_string()))?;
+
+        let status = response.status();
+        let payload: Value = response.json().await.unwrap_or_default();
+        if status.is_success() {
+            return Ok(Some(payload));
+        }
+        let error_type = payload.get("__type").and_then(Value::as_str).unwrap_or_default();
+        if error_type.ends_with(missing) {
+            return Ok(None);
+        }
+        Err(SecretsError::Backend(format!(
+            "{} returned {} {}: {}",
+            target,
+            status.as_u16(),
+            error_type,
+            payload.get("message").or_else(|| payload.get("Message")).and_then(Value::as_str).unwrap_or_default()
+        )))
+    }
+}
+
+/// SigV4 `Authorization` header for a request with the given (lowercase)
+/// headers, all of which are signed.
+#[allow(clippy::too_many_arguments)]
+fn sign(
+    credentials: &Credentials,
+    region: &str,
+    service: &str,
+    amz_date: &str,
+    method: &str,
+    path: &str,
+    query: &str,
+    headers: &[(String, String)],
+    body: &str,
+) -> String {
+    let mut headers: Vec<&(String, String)> = headers.iter().collect();
+    headers.sort_by(|a, b| a.0.cmp(&b.0));
+    let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+    let signed_headers = headers.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(";");
+
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method,
+        path,
+        query,
+        canonical_headers,
+        signed_headers,
+        hex::encode(Sha256::digest(body.as_bytes()))
+    );
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let key = [date, region, service, "aws4_request"]
+        .iter()
+        .fold(format!("AWS4{}", credentials.secret_access_key).into_bytes(), |key, part| {
+            hmac(&key, part.as_bytes())
+        });
+    let signature = hex::encode(hmac(&key, string_to_sign.as_bytes()));
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key_id, scope, signed_headers, signature
+    )
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// AWS SSM Parameter Store, decrypting `SecureString`s. Hierarchical names
+/// may omit the leading slash (`crm/clickhouse/password`).
+pub struct SsmProvider {
+    client: AwsJsonClient,
+}
+
+impl SsmProvider {
+    pub fn new(region: &str, endpoint: Option<&str>) -> Result<Self, SecretsError> {
+        Ok(SsmProvider {
+            client: AwsJsonClient::new("ssm", region, endpoint)?,
+        })
+    }
+}
+
+#[async_trait]
+impl SecretsProvider for SsmProvider {
+    fn backend(&self) -> &'static str {
+        "aws-ssm"
+    }
+
+    async fn get(&self, name: &str) -> Result<Secret, SecretsError> {
+        let parameter = if name.contains('/') && !name.starts_with('/') {
+            format!("/{}", name)
+        } else {
+            name.to_string()
+        };
+        let body = json!({ "Name": parameter, "WithDecryption": true });
+
+        let response = self
+            .client
+            .call("AmazonSSM.GetParameter", &body, "ParameterNotFound")
+            .await?
+            .ok_or_else(|| SecretsError::NotFound(name.to_string()))?;
+        let value = response
+            .pointer("/Parameter/Value")
+            .and_then(Value::as_str)
+            .ok_or_else(|| SecretsError::Backend(format!("SSM returned no value for {}", parameter)))?;
+
+        Ok(Secret {
+            value: value.to_string(),
+            version: response.pointer("/Parameter/Version").map(|v| v.to_string()),
+            lease: None,
+        })
+    }
+}
+
+/// Wraps another provider whose values are base64 KMS ciphertext and
+/// decrypts them. Keeps plaintext out of env files and parameter history.
+pub struct KmsDecrypt {
+    inner: Arc<dyn SecretsProvider>,
+    client: AwsJsonClient,
+}
+
+impl KmsDecrypt {
+    pub fn new(inner: Arc<dyn SecretsProvider>, region: &str, endpoint: Option<&str>) -> Result<Self, SecretsError> {
+        Ok(KmsDecrypt {
+            inner,
+            client: AwsJsonClient::new("kms", region, endpoint)?,
+        })
+    }
+}
+
+#[async_trait]
+impl SecretsProvider for KmsDecrypt {
+    fn backend(&self) -> &'static str {
+        self.inner.backend()
+    }
+
+    async fn get(&self, name: &str) -> Result<Secret, SecretsError> {
+        let encrypted = self.inner.get(name).await?;
+        let body = json!({ "CiphertextBlob": encrypted.value.trim() });
+
+        let response = self
+            .client
+            .call("TrentService.Decrypt", &body, "NotFoundException")
+            .await?
+            .ok_or_else(|| SecretsError::NotFound(name.to_string()))?;
+        let plaintext = response
+            .get("Plaintext")
+            .and_then(Value::as_str)
+            .and_then(|p| STANDARD.decode(p).ok())
+            .and_then(|p| String::from_utf8(p).ok())
+            .ok_or_else(|| SecretsError::Backend(format!("KMS returned no UTF-8 plaintext for {}", name)))?;
+
+        Ok(Secret {
+            value: plaintext,
+            ..encrypted
+        })
+    }
+}
+
+/// Data keys from AWS KMS `GenerateDataKey`, bound to the tenant through the
+/// encryption context so a wrapped key only unwraps for its own tenant.
+pub struct KmsKeys {
+    client: AwsJsonClient,
+    key_id: String,
+}
+
+impl KmsKeys {
+    pub fn new(key_id: &str, region: &str, endpoint: Option<&str>) -> Result<Self, SecretsError> {
+        Ok(KmsKeys {
+            client: AwsJsonClient::new("kms", region, endpoint)?,
+            key_id: key_id.to_string(),
+        })
+    }
+}
+
+fn decode_blob(response: &Value, field: &str) -> Result<Vec<u8>, SecretsError> {
+    response
+        .get(field)
+        .and_then(Value::as_str)
+        .and_then(|blob| STANDARD.decode(blob).ok())
+        .ok_or_else(|| SecretsError::Backend(format!("KMS returned no {}", field)))
+}
+
+#[async_trait]
+impl KeyWrapper for KmsKeys {
+    async fn generate(&self, tenant_id: &str) -> Result<DataKey, SecretsError> {
+        let body = json!({
+            "KeyId": self.key_id,
+            "KeySpec": "AES_256",
+            "EncryptionContext": { "tenant_id": tenant_id },
+        });
+        let response = self
+            .client
+            .call("TrentService.GenerateDataKey", &body, "NotFoundException")
+            .await?
+            .ok_or_else(|| SecretsError::NotFound(self.key_id.clone()))?;
+
+        Ok(DataKey {
+            plaintext: decode_blob(&response, "Plaintext")?,
+            wrapped: decode_blob(&response, "CiphertextBlob")?,
+        })
+    }
+
+    async fn unwrap(&self, tenant_id: &str, wrapped: &[u8]) -> Result<Vec<u8>, SecretsError> {
+        let body = json!({
+            "CiphertextBlob": STANDARD.encode(wrapped),
+            "EncryptionContext": { "tenant_id": tenant_id },
+        });
+        let response = self
+            .client
+            .call("TrentService.Decrypt", &body, "NotFoundException")
+            .await?
+            .ok_or_else(|| SecretsError::NotFound(self.key_id.clone()))?;
+        decode_blob(&response, "Plaintext")
+    }
+}
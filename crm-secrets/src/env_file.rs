@@ -0,0 +1,66 @@
+use crate::{Secret, SecretsError, SecretsProvider};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use tokio::sync::Mutex;
+
+/// Secrets from a `NAME=value` file, such as one rendered by a Vault agent
+/// or mounted from a Kubernetes secret. Blank lines and `#` comments are
+/// skipped and values may be quoted. The file is re-read whenever its
+/// modification time changes, which is how rotation reaches this backend.
+pub struct EnvFileProvider {
+    path: PathBuf,
+    loaded: Mutex<Option<(SystemTime, HashMap<String, String>)>>,
+}
+
+impl EnvFileProvider {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        EnvFileProvider {
+            path: path.into(),
+            loaded: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl SecretsProvider for EnvFileProvider {
+    fn backend(&self) -> &'static str {
+        "env-file"
+    }
+
+    async fn get(&self, name: &str) -> Result<Secret, SecretsError> {
+        let unreadable = |e: std::io::Error| SecretsError::Backend(format!("cannot read {}: {}", self.path.display(), e));
+        let modified = tokio::fs::metadata(&self.path).await.map_err(unreadable)?.modified().map_err(unreadable)?;
+
+        let mut loaded = self.loaded.lock().await;
+        if !matches!(&*loaded, Some((at, _)) if *at == modified) {
+            let contents = tokio::fs::read_to_string(&self.path).await.map_err(unreadable)?;
+            *loaded = Some((modified, parse(&contents)));
+        }
+
+        let (_, values) = loaded.as_ref().expect("loaded above");
+        values
+            .get(name)
+            .map(Secret::new)
+            .ok_or_else(|| SecretsError::NotFound(name.to_string()))
+    }
+}
+
+fn parse(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (name, value) = line.strip_prefix("export ").unwrap_or(line).split_once('=')?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+                .unwrap_or(value);
+            Some((name.trim().to_string(), value.to_string()))
+        })
+        .collect()
+}
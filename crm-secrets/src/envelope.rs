@@ -0,0 +1,351 @@
+//! Envelope encryption of tenant data at rest.
+//!
+//! Each tenant gets its own AES-256 data key, generated and wrapped by a
+//! [`KeyWrapper`] (AWS KMS in production). Values are sealed with AES-256-GCM
+//! under the data key and stored together with the wrapped key:
+//!
+//! ```text
+//! enc:v1:<base64 wrapped data key>:<base64 nonce + ciphertext>
+//! ```
+//!
+//! so reading one back needs the key service, not just the database. The
+//! tenant id is authenticated with every value and bound to every wrapped
+//! key, so ciphertext copied between tenants doesn't decrypt. Data keys are
+//! reused for a while to keep KMS calls off the hot path.
+
+use crate::{KmsKeys, SecretsError};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use crm_config::{ConfigLoader, Configurable};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Prefix of every encrypted value.
+pub const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+const KEY_LEN: usize = 32;
+
+/// Unwrapped data keys kept for decryption before the cache is reset.
+const MAX_UNWRAPPED_KEYS: usize = 10_000;
+
+/// Whether `value` was produced by [`EnvelopeCipher::encrypt`].
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// A data key as returned by the key service.
+pub struct DataKey {
+    pub plaintext: Vec<u8>,
+    /// The key encrypted under the master key; safe to store.
+    pub wrapped: Vec<u8>,
+}
+
+/// Generates and unwraps per-tenant data keys under a master key that never
+/// leaves the key service.
+#[async_trait]
+pub trait KeyWrapper: Send + Sync {
+    async fn generate(&self, tenant_id: &str) -> Result<DataKey, SecretsError>;
+
+    async fn unwrap(&self, tenant_id: &str, wrapped: &[u8]) -> Result<Vec<u8>, SecretsError>;
+}
+
+/// Wraps data keys under a master key held in memory. For development and
+/// tests; production uses [`KmsKeys`].
+pub struct LocalKeys {
+    master: LessSafeKey,
+}
+
+impl LocalKeys {
+    pub fn new(master_key: &[u8]) -> Result<Self, SecretsError> {
+        Ok(LocalKeys { master: aead_key(master_key)? })
+    }
+
+    /// A master key given as base64, as in `ENCRYPTION_LOCAL_MASTER_KEY`.
+    pub fn from_base64(master_key: &str) -> Result<Self, SecretsError> {
+        let bytes = STANDARD
+            .decode(master_key.trim())
+            .map_err(|e| SecretsError::Crypto(format!("master key is not base64: {}", e)))?;
+        LocalKeys::new(&bytes)
+    }
+}
+
+#[async_trait]
+impl KeyWrapper for LocalKeys {
+    async fn generate(&self, tenant_id: &str) -> Result<DataKey, SecretsError> {
+        let mut plaintext = vec![0u8; KEY_LEN];
+        SystemRandom::new()
+            .fill(&mut plaintext)
+            .map_err(|_| SecretsError::Crypto("no randomness available".to_string()))?;
+        let wrapped = seal(&self.master, tenant_id, &plaintext)?;
+        Ok(DataKey { plaintext, wrapped })
+    }
+
+    async fn unwrap(&self, tenant_id: &str, wrapped: &[u8]) -> Result<Vec<u8>, SecretsError> {
+        open(&self.master, tenant_id, wrapped)
+    }
+}
+
+/// Which key service to use; loaded from `ENCRYPTION_*` variables.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncryptionConfig {
+    /// `none`, `kms` or `local`.
+    pub provider: String,
+    /// KMS key id, ARN or alias that wraps the data keys.
+    pub kms_key_id: String,
+    pub aws_region: String,
+    /// Overrides the regional KMS endpoint, e.g. for LocalStack.
+    pub aws_endpoint: Option<String>,
+    /// Base64 32-byte master key for `local`. Usually a `secret://` reference.
+    pub local_master_key: String,
+    /// How long a tenant's data key is used before a new one is generated.
+    pub data_key_ttl_secs: u64,
+}
+
+impl Default for EncryptionConfig {
+    fn default() -> Self {
+        EncryptionConfig {
+            provider: "none".to_string(),
+            kms_key_id: "".to_string(),
+            aws_region: "us-east-1".to_string(),
+            aws_endpoint: None,
+            local_master_key: "".to_string(),
+            data_key_ttl_secs: 3600,
+        }
+    }
+}
+
+impl Configurable for EncryptionConfig {
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        match self.provider.as_str() {
+            "none" => {}
+            "kms" if self.kms_key_id.is_empty() => problems.push("kms_key_id must be set for kms".to_string()),
+            "kms" => {}
+            "local" if self.local_master_key.is_empty() => {
+                problems.push("local_master_key must be set for local".to_string())
+            }
+            "local" => {}
+            other => problems.push(format!("unknown encryption provider '{}'", other)),
+        }
+        if self.data_key_ttl_secs == 0 {
+            problems.push("data_key_ttl_secs must be positive".to_string());
+        }
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+}
+
+impl EncryptionConfig {
+    /// Defaults, then `ENCRYPTION_CONFIG_FILE`, then `ENCRYPTION_*` variables
+    /// (e.g. `ENCRYPTION_PROVIDER=kms`, `ENCRYPTION_KMS_KEY_ID`).
+    pub fn from_env() -> Result<Self, SecretsError> {
+        ConfigLoader::new()
+            .file_from_env("ENCRYPTION_CONFIG_FILE")
+            .env_prefix("ENCRYPTION_")
+            .load()
+            .map_err(SecretsError::Config)
+    }
+}
+
+struct CurrentKey {
+    key: Arc<LessSafeKey>,
+    wrapped: String,
+    created: Instant,
+}
+
+/// Encrypts and decrypts tenant values with per-tenant data keys.
+pub struct EnvelopeCipher {
+    keys: Arc<dyn KeyWrapper>,
+    data_key_ttl: Duration,
+    current: Mutex<HashMap<String, CurrentKey>>,
+    /// Unwrapped keys by tenant and wrapped key, so reads don't call the key
+    /// service per value.
+    unwrapped: Mutex<HashMap<(String, String), Arc<LessSafeKey>>>,
+}
+
+impl EnvelopeCipher {
+    pub fn new(keys: Arc<dyn KeyWrapper>, data_key_ttl: Duration) -> Self {
+        EnvelopeCipher {
+            keys,
+            data_key_ttl,
+            current: Mutex::new(HashMap::new()),
+            unwrapped: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The configured cipher; `None` for `provider = "none"`. Resolve the
+    /// config through a [`crate::SecretStore`] first if it holds references.
+    pub fn from_config(config: &EncryptionConfig) -> Result<Option<Self>, SecretsError> {
+        let keys: Arc<dyn KeyWrapper> = match config.provider.as_str() {
+            "none" => return Ok(None),
+            "kms" => Arc::new(KmsKeys::new(&config.kms_key_id, &config.aws_region, config.aws_endpoint.as_deref())?),
+            "local" => Arc::new(LocalKeys::from_base64(&config.local_master_key)?),
+            other => return Err(SecretsError::Crypto(format!("unknown encryption provider '{}'", other))),
+        };
+        Ok(Some(EnvelopeCipher::new(keys, Duration::from_secs(config.data_key_ttl_secs))))
+    }
+
+    /// `plaintext` sealed under the tenant's current data key.
+    pub async fn encrypt(&self, tenant_id: &str, plaintext: &[u8]) -> Result<String, SecretsError> {
+        let (key, wrapped) = self.current_key(tenant_id).await?;
+        let sealed = seal(&key, tenant_id, plaintext)?;
+        Ok(format!("{}{}:{}", ENCRYPTED_PREFIX, wrapped, STANDARD.encode(sealed)))
+    }
+
+    /// The plaintext of a value from [`EnvelopeCipher::encrypt`]. Fails if it
+    /// was encrypted for another tenant or has been altered.
+    pub async fn decrypt(&self, tenant_id: &str, value: &str) -> Result<Vec<u8>, SecretsError> {
+        let (wrapped, sealed) = value
+            .strip_prefix(ENCRYPTED_PREFIX)
+            .and_then(|rest| rest.split_once(':'))
+            .ok_or_else(|| SecretsError::Crypto("not an encrypted value".to_string()))?;
+        let sealed = STANDARD
+            .decode(sealed)
+            .map_err(|e| SecretsError::Crypto(format!("malformed ciphertext: {}", e)))?;
+        let key = self.unwrapped_key(tenant_id, wrapped).await?;
+        open(&key, tenant_id, &sealed)
+    }
+
+    /// A JSON value replaced by its encrypted serialization.
+    pub async fn encrypt_value(&self, tenant_id: &str, value: &Value) -> Result<Value, SecretsError> {
+        let plaintext = serde_json::to_vec(value).map_err(|e| SecretsError::Crypto(e.to_string()))?;
+        Ok(Value::String(self.encrypt(tenant_id, &plaintext).await?))
+    }
+
+    /// Replaces every encrypted string anywhere in `value` with the JSON
+    /// value it was encrypted from, including inside strings that hold a
+    /// JSON document (such as a stored `properties` column).
+    pub async fn decrypt_json(&self, tenant_id: &str, value: &mut Value) -> Result<(), SecretsError> {
+        let mut encrypted = Vec::new();
+        collect_encrypted(value, &mut encrypted, true);
+        for slot in encrypted {
+            let text = slot.as_str().unwrap_or_default();
+            *slot = if is_encrypted(text) {
+                self.decrypt_to_json(tenant_id, text).await?
+            } else {
+                Value::String(self.decrypt_text(tenant_id, text).await?)
+            };
+        }
+        Ok(())
+    }
+
+    /// `text` with its encrypted values decrypted, where `text` is a JSON
+    /// document such as a stored `properties` column. Anything else comes
+    /// back unchanged.
+    pub async fn decrypt_text(&self, tenant_id: &str, text: &str) -> Result<String, SecretsError> {
+        if !text.contains(ENCRYPTED_PREFIX) {
+            return Ok(text.to_string());
+        }
+        let Ok(mut document) = serde_json::from_str::<Value>(text) else {
+            return Ok(text.to_string());
+        };
+        let mut encrypted = Vec::new();
+        collect_encrypted(&mut document, &mut encrypted, false);
+        for slot in encrypted {
+            *slot = self.decrypt_to_json(tenant_id, slot.as_str().unwrap_or_default()).await?;
+        }
+        Ok(document.to_string())
+    }
+
+    async fn decrypt_to_json(&self, tenant_id: &str, value: &str) -> Result<Value, SecretsError> {
+        let plaintext = self.decrypt(tenant_id, value).await?;
+        serde_json::from_slice(&plaintext).map_err(|e| SecretsError::Crypto(e.to_string()))
+    }
+
+    async fn current_key(&self, tenant_id: &str) -> Result<(Arc<LessSafeKey>, String), SecretsError> {
+        let mut current = self.current.lock().await;
+        if let Some(entry) = current.get(tenant_id).filter(|entry| entry.created.elapsed() < self.data_key_ttl) {
+            return Ok((entry.key.clone(), entry.wrapped.clone()));
+        }
+
+        let data_key = self.keys.generate(tenant_id).await?;
+        let entry = CurrentKey {
+            key: Arc::new(aead_key(&data_key.plaintext)?),
+            wrapped: STANDARD.encode(&data_key.wrapped),
+            created: Instant::now(),
+        };
+        let result = (entry.key.clone(), entry.wrapped.clone());
+        current.insert(tenant_id.to_string(), entry);
+        Ok(result)
+    }
+
+    async fn unwrapped_key(&self, tenant_id: &str, wrapped: &str) -> Result<Arc<LessSafeKey>, SecretsError> {
+        let cache_key = (tenant_id.to_string(), wrapped.to_string());
+        if let Some(key) = self.unwrapped.lock().await.get(&cache_key) {
+            return Ok(key.clone());
+        }
+
+        let wrapped_bytes = STANDARD
+            .decode(wrapped)
+            .map_err(|e| SecretsError::Crypto(format!("malformed wrapped key: {}", e)))?;
+        let key = Arc::new(aead_key(&self.keys.unwrap(tenant_id, &wrapped_bytes).await?)?);
+
+        let mut unwrapped = self.unwrapped.lock().await;
+        if unwrapped.len() >= MAX_UNWRAPPED_KEYS {
+            unwrapped.clear();
+        }
+        unwrapped.insert(cache_key, key.clone());
+        Ok(key)
+    }
+}
+
+/// Encrypted strings in `value`, and with `embedded` also strings that
+/// contain encrypted values.
+fn collect_encrypted<'a>(value: &'a mut Value, out: &mut Vec<&'a mut Value>, embedded: bool) {
+    if value
+        .as_str()
+        .is_some_and(|s| is_encrypted(s) || (embedded && s.contains(ENCRYPTED_PREFIX)))
+    {
+        out.push(value);
+        return;
+    }
+    match value {
+        Value::Array(items) => items.iter_mut().for_each(|item| collect_encrypted(item, out, embedded)),
+        Value::Object(map) => map.values_mut().for_each(|item| collect_encrypted(item, out, embedded)),
+        _ => {}
+    }
+}
+
+fn aead_key(bytes: &[u8]) -> Result<LessSafeKey, SecretsError> {
+    if bytes.len() != KEY_LEN {
+        return Err(SecretsError::Crypto(format!("keys must be {} bytes, got {}", KEY_LEN, bytes.len())));
+    }
+    let key = UnboundKey::new(&AES_256_GCM, bytes).map_err(|_| SecretsError::Crypto("invalid key".to_string()))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// A random nonce followed by the ciphertext and tag, authenticating
+/// `tenant_id`.
+fn seal(key: &LessSafeKey, tenant_id: &str, plaintext: &[u8]) -> Result<Vec<u8>, SecretsError> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| SecretsError::Crypto("no randomness available".to_string()))?;
+    let mut buffer = plaintext.to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(tenant_id.as_bytes()), &mut buffer)
+        .map_err(|_| SecretsError::Crypto("encryption failed".to_string()))?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&buffer);
+    Ok(sealed)
+}
+
+fn open(key: &LessSafeKey, tenant_id: &str, sealed: &[u8]) -> Result<Vec<u8>, SecretsError> {
+    if sealed.len() < NONCE_LEN {
+        return Err(SecretsError::Crypto("ciphertext too short".to_string()));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| SecretsError::Crypto("invalid nonce".to_string()))?;
+    let mut buffer = ciphertext.to_vec();
+    let plaintext = key
+        .open_in_place(nonce, Aad::from(tenant_id.as_bytes()), &mut buffer)
+        .map_err(|_| SecretsError::Crypto("decryption failed: wrong tenant, key or altered data".to_string()))?;
+    Ok(plaintext.to_vec())
+}
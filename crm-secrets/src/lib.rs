@@ -0,0 +1,364 @@
+//! Secrets for the CRM Rust services.
+//!
+//! A [`SecretsProvider`] fetches named secrets from one backend:
+//!
+//! - `env-file`: a `NAME=value` file, re-read when it changes
+//! - `vault`: HashiCorp Vault KV v2, names written `path#field`
+//! - `aws-ssm`: AWS SSM Parameter Store `SecureString`s
+//!
+//! Any of them can hold KMS ciphertext instead of plaintext with
+//! `kms_decrypt`, in which case values are decrypted through AWS KMS.
+//!
+//! Services reference secrets from their configuration as
+//! `secret://<backend>/<name>` (e.g. `secret://vault/crm/clickhouse#password`);
+//! `crm-config` leaves these in place and [`SecretStore::resolve`] fills them
+//! in. [`SecretStore::watch`] re-resolves on an interval so rotated
+//! credentials reach the service without a redeploy.
+//!
+//! [`EnvelopeCipher`] encrypts tenant data at rest under per-tenant data
+//! keys from KMS.
+
+use async_trait::async_trait;
+use crm_config::{ConfigLoader, Configurable};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, Mutex};
+use tracing::{info, warn};
+
+mod aws;
+mod env_file;
+pub mod envelope;
+mod vault;
+
+pub use aws::{KmsDecrypt, KmsKeys, SsmProvider};
+pub use env_file::EnvFileProvider;
+pub use envelope::{EncryptionConfig, EnvelopeCipher};
+pub use vault::VaultProvider;
+
+const SECRET_SCHEME: &str = "secret://";
+
+#[derive(Debug)]
+pub enum SecretsError {
+    NotFound(String),
+    /// The backend failed or returned something unusable.
+    Backend(String),
+    /// A reference names a backend that isn't configured.
+    Unconfigured(String),
+    Config(crm_config::ConfigError),
+    /// Encryption or decryption failed.
+    Crypto(String),
+}
+
+impl std::fmt::Display for SecretsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecretsError::NotFound(name) => write!(f, "secret {} not found", name),
+            SecretsError::Backend(reason) => write!(f, "secrets backend error: {}", reason),
+            SecretsError::Unconfigured(reference) => write!(f, "no secrets provider configured for {}", reference),
+            SecretsError::Config(e) => write!(f, "secrets configuration: {}", e),
+            SecretsError::Crypto(reason) => write!(f, "encryption error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SecretsError {}
+
+/// A fetched secret.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Secret {
+    pub value: String,
+    /// Backend version, when it has one; changes on rotation.
+    pub version: Option<String>,
+    /// Lease length for dynamic secrets; the value is refetched well before.
+    pub lease: Option<Duration>,
+}
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret {
+            value: value.into(),
+            version: None,
+            lease: None,
+        }
+    }
+}
+
+#[async_trait]
+pub trait SecretsProvider: Send + Sync {
+    /// The backend segment in `secret://<backend>/<name>` references.
+    fn backend(&self) -> &'static str;
+
+    async fn get(&self, name: &str) -> Result<Secret, SecretsError>;
+}
+
+/// Which provider to use; loaded from `SECRETS_*` variables.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecretsConfig {
+    /// `none`, `env-file`, `vault` or `aws-ssm`.
+    pub provider: String,
+    pub env_file: String,
+    pub vault_addr: String,
+    pub vault_token: String,
+    pub vault_mount: String,
+    pub vault_namespace: Option<String>,
+    pub aws_region: String,
+    /// Overrides the regional AWS endpoints, e.g. for LocalStack.
+    pub aws_endpoint: Option<String>,
+    /// Values are base64 KMS ciphertext to decrypt.
+    pub kms_decrypt: bool,
+    /// How long fetched secrets are cached, and how often watched configs
+    /// are re-resolved.
+    pub refresh_interval_secs: u64,
+}
+
+impl Default for SecretsConfig {
+    fn default() -> Self {
+        SecretsConfig {
+            provider: "none".to_string(),
+            env_file: "/run/secrets/crm.env".to_string(),
+            vault_addr: "http://localhost:8200".to_string(),
+            vault_token: "".to_string(),
+            vault_mount: "secret".to_string(),
+            vault_namespace: None,
+            aws_region: "us-east-1".to_string(),
+            aws_endpoint: None,
+            kms_decrypt: false,
+            refresh_interval_secs: 300,
+        }
+    }
+}
+
+impl Configurable for SecretsConfig {
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        match self.provider.as_str() {
+            "none" | "env-file" | "aws-ssm" => {}
+            "vault" if self.vault_token.is_empty() => problems.push("vault_token must be set for vault".to_string()),
+            "vault" => {}
+            other => problems.push(format!("unknown secrets provider '{}'", other)),
+        }
+        if self.refresh_interval_secs == 0 {
+            problems.push("refresh_interval_secs must be positive".to_string());
+        }
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+}
+
+/// Builds the configured provider; `None` for `provider = "none"`.
+pub fn provider(config: &SecretsConfig) -> Result<Option<Arc<dyn SecretsProvider>>, SecretsError> {
+    let base: Arc<dyn SecretsProvider> = match config.provider.as_str() {
+        "none" => return Ok(None),
+        "env-file" => Arc::new(EnvFileProvider::new(&config.env_file)),
+        "vault" => Arc::new(VaultProvider::new(
+            &config.vault_addr,
+            &config.vault_token,
+            &config.vault_mount,
+            config.vault_namespace.as_deref(),
+        )?),
+        "aws-ssm" => Arc::new(SsmProvider::new(&config.aws_region, config.aws_endpoint.as_deref())?),
+        other => return Err(SecretsError::Backend(format!("unknown secrets provider '{}'", other))),
+    };
+
+    if config.kms_decrypt {
+        return Ok(Some(Arc::new(KmsDecrypt::new(base, &config.aws_region, config.aws_endpoint.as_deref())?)));
+    }
+    Ok(Some(base))
+}
+
+/// Caches fetched secrets and resolves `secret://` references in configs.
+pub struct SecretStore {
+    provider: Option<Arc<dyn SecretsProvider>>,
+    ttl: Duration,
+    cache: Mutex<HashMap<String, (Secret, Instant)>>,
+}
+
+impl SecretStore {
+    pub fn new(provider: Arc<dyn SecretsProvider>, ttl: Duration) -> Self {
+        SecretStore {
+            provider: Some(provider),
+            ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// A store with no backend; configs without references pass through.
+    pub fn disabled() -> Self {
+        SecretStore {
+            provider: None,
+            ttl: Duration::from_secs(300),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Defaults, then `SECRETS_CONFIG_FILE`, then `SECRETS_*` variables
+    /// (e.g. `SECRETS_PROVIDER=vault`, `SECRETS_VAULT_ADDR`).
+    pub fn from_env() -> Result<Self, SecretsError> {
+        let config: SecretsConfig = ConfigLoader::new()
+            .file_from_env("SECRETS_CONFIG_FILE")
+            .env_prefix("SECRETS_")
+            .load()
+            .map_err(SecretsError::Config)?;
+        info!("Secrets provider: {}", config.provider);
+
+        Ok(match provider(&config)? {
+            Some(provider) => SecretStore::new(provider, Duration::from_secs(config.refresh_interval_secs)),
+            None => SecretStore::disabled(),
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.provider.is_some()
+    }
+
+    /// A secret by name from the configured backend, cached for the refresh
+    /// interval or two thirds of its lease, whichever is shorter.
+    pub async fn get(&self, name: &str) -> Result<String, SecretsError> {
+        if let Some((secret, fetched_at)) = self.cache.lock().await.get(name) {
+            if fetched_at.elapsed() < self.cache_ttl(secret) {
+                return Ok(secret.value.clone());
+            }
+        }
+        self.fetch(name).await
+    }
+
+    /// Resolves `value` if it is a `secret://` reference, else returns it.
+    pub async fn resolve_str(&self, value: &str) -> Result<String, SecretsError> {
+        match parse_reference(value) {
+            Some((backend, name)) => {
+                self.check_backend(backend, value)?;
+                self.get(name).await
+            }
+            None => Ok(value.to_string()),
+        }
+    }
+
+    /// A copy of `config` with every `secret://<backend>/<name>` string
+    /// replaced by the secret's value.
+    pub async fn resolve<T: Configurable>(&self, config: &T) -> Result<T, SecretsError> {
+        self.resolve_with(config, false).await
+    }
+
+    /// Resolves `template` now and again every refresh interval, publishing
+    /// a new config whenever a referenced secret changed. Without a provider
+    /// the receiver never changes.
+    pub async fn watch<T>(self: &Arc<Self>, template: T) -> Result<watch::Receiver<T>, SecretsError>
+    where
+        T: Configurable + Send + Sync + 'static,
+    {
+        let resolved = self.resolve(&template).await?;
+        let mut last = serde_json::to_value(&resolved).unwrap_or_default();
+        let (sender, receiver) = watch::channel(resolved);
+        if !self.enabled() {
+            return Ok(receiver);
+        }
+
+        let store = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(store.ttl);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let resolved = match store.resolve_with(&template, true).await {
+                    Ok(resolved) => resolved,
+                    Err(e) => {
+                        // Keep the current credentials until the backend recovers
+                        warn!("Failed to refresh secrets: {}", e);
+                        continue;
+                    }
+                };
+                let current = serde_json::to_value(&resolved).unwrap_or_default();
+                if current != last {
+                    info!("Referenced secrets changed");
+                    last = current;
+                    if sender.send(resolved).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(receiver)
+    }
+
+    async fn resolve_with<T: Configurable>(&self, config: &T, fresh: bool) -> Result<T, SecretsError> {
+        let mut value = serde_json::to_value(config).map_err(|e| SecretsError::Backend(e.to_string()))?;
+
+        let mut references = Vec::new();
+        collect_references(&value, &mut references);
+
+        let mut resolved = HashMap::new();
+        for reference in references {
+            if resolved.contains_key(&reference) {
+                continue;
+            }
+            let (backend, name) = parse_reference(&reference).expect("collected references parse");
+            self.check_backend(backend, &reference)?;
+            let secret = if fresh { self.fetch(name).await? } else { self.get(name).await? };
+            resolved.insert(reference, secret);
+        }
+
+        replace_references(&mut value, &resolved);
+        serde_json::from_value(value).map_err(|e| SecretsError::Backend(e.to_string()))
+    }
+
+    async fn fetch(&self, name: &str) -> Result<String, SecretsError> {
+        let provider = self
+            .provider
+            .as_ref()
+            .ok_or_else(|| SecretsError::Unconfigured(name.to_string()))?;
+        let secret = provider.get(name).await?;
+        let value = secret.value.clone();
+        self.cache.lock().await.insert(name.to_string(), (secret, Instant::now()));
+        Ok(value)
+    }
+
+    fn check_backend(&self, backend: &str, reference: &str) -> Result<(), SecretsError> {
+        match &self.provider {
+            Some(provider) if provider.backend() == backend => Ok(()),
+            _ => Err(SecretsError::Unconfigured(reference.to_string())),
+        }
+    }
+
+    fn cache_ttl(&self, secret: &Secret) -> Duration {
+        match secret.lease {
+            Some(lease) => self.ttl.min(lease * 2 / 3),
+            None => self.ttl,
+        }
+    }
+}
+
+/// `(backend, name)` for `secret://<backend>/<name>`, skipping the `env` and
+/// `file` references `crm-config` already resolved.
+fn parse_reference(value: &str) -> Option<(&str, &str)> {
+    let (backend, name) = value.strip_prefix(SECRET_SCHEME)?.split_once('/')?;
+    match backend {
+        "env" | "file" => None,
+        _ => Some((backend, name)),
+    }
+}
+
+fn collect_references(value: &Value, references: &mut Vec<String>) {
+    match value {
+        Value::String(s) if parse_reference(s).is_some() => references.push(s.clone()),
+        Value::Array(items) => items.iter().for_each(|item| collect_references(item, references)),
+        Value::Object(map) => map.values().for_each(|item| collect_references(item, references)),
+        _ => {}
+    }
+}
+
+fn replace_references(value: &mut Value, resolved: &HashMap<String, String>) {
+    match value {
+        Value::String(s) => {
+            if let Some(secret) = resolved.get(s.as_str()) {
+                *s = secret.clone();
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| replace_references(item, resolved)),
+        Value::Object(map) => map.values_mut().for_each(|item| replace_references(item, resolved)),
+        _ => {}
+    }
+}
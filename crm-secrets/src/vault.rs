@@ -0,0 +1,79 @@
+use crate::{Secret, SecretsError, SecretsProvider};
+use async_trait::async_trait;
+use serde_json::Value;
+use std::time::Duration;
+
+/// Field read when a name has no `#field` suffix.
+const DEFAULT_FIELD: &str = "value";
+
+/// HashiCorp Vault KV v2. Names are `path#field`, e.g. `crm/clickhouse#password`
+/// reads field `password` of `secret/data/crm/clickhouse`. The secret's
+/// version is reported so rotations show up as changes.
+pub struct VaultProvider {
+    http: reqwest::Client,
+    addr: String,
+    token: String,
+    mount: String,
+    namespace: Option<String>,
+}
+
+impl VaultProvider {
+    pub fn new(addr: &str, token: &str, mount: &str, namespace: Option<&str>) -> Result<Self, SecretsError> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|e| SecretsError::Backend(e.to_string()))?;
+
+        Ok(VaultProvider {
+            http,
+            addr: addr.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            mount: mount.trim_matches('/').to_string(),
+            namespace: namespace.map(str::to_string),
+        })
+    }
+}
+
+#[async_trait]
+impl SecretsProvider for VaultProvider {
+    fn backend(&self) -> &'static str {
+        "vault"
+    }
+
+    async fn get(&self, name: &str) -> Result<Secret, SecretsError> {
+        let (path, field) = name.split_once('#').unwrap_or((name, DEFAULT_FIELD));
+        let url = format!("{}/v1/{}/data/{}", self.addr, self.mount, path.trim_start_matches('/'));
+
+        let mut request = self.http.get(&url).header("X-Vault-Token", &self.token);
+        if let Some(namespace) = &self.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+        let response = request.send().await.map_err(|e| SecretsError::Backend(e.to_string()))?;
+
+        match response.status().as_u16() {
+            200 => {}
+            404 => return Err(SecretsError::NotFound(name.to_string())),
+            status => {
+                let body = response.text().await.unwrap_or_default();
+                return Err(SecretsError::Backend(format!("Vault returned {} for {}: {}", status, path, body.trim())));
+            }
+        }
+
+        let body: Value = response.json().await.map_err(|e| SecretsError::Backend(e.to_string()))?;
+        let value = match body.pointer("/data/data").and_then(|data| data.get(field)) {
+            Some(Value::String(s)) => s.clone(),
+            Some(other) => other.to_string(),
+            None => return Err(SecretsError::NotFound(name.to_string())),
+        };
+
+        Ok(Secret {
+            value,
+            version: body.pointer("/data/metadata/version").map(|v| v.to_string()),
+            lease: body
+                .get("lease_duration")
+                .and_then(Value::as_u64)
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+        })
+    }
+}
@@ -0,0 +1,119 @@
+use crm_config::Configurable;
+use crm_secrets::envelope::{is_encrypted, LocalKeys};
+use crm_secrets::{EncryptionConfig, EnvelopeCipher, SecretsError};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn cipher(ttl: Duration) -> EnvelopeCipher {
+    EnvelopeCipher::new(Arc::new(LocalKeys::new(&[7u8; 32]).unwrap()), ttl)
+}
+
+/// The wrapped data key segment of an encrypted value.
+fn wrapped_key(value: &str) -> &str {
+    value.split(':').nth(2).unwrap()
+}
+
+#[tokio::test]
+async fn round_trips_values_per_tenant() {
+    let cipher = cipher(Duration::from_secs(3600));
+
+    let encrypted = cipher.encrypt("t1", b"jane@example.com").await.unwrap();
+    assert!(is_encrypted(&encrypted));
+    assert!(!encrypted.contains("jane"));
+    assert_eq!(cipher.decrypt("t1", &encrypted).await.unwrap(), b"jane@example.com");
+
+    // Bound to the tenant it was encrypted for
+    assert!(matches!(cipher.decrypt("t2", &encrypted).await, Err(SecretsError::Crypto(_))));
+
+    // A different master key can't unwrap the data key
+    let other = EnvelopeCipher::new(Arc::new(LocalKeys::new(&[8u8; 32]).unwrap()), Duration::from_secs(3600));
+    assert!(other.decrypt("t1", &encrypted).await.is_err());
+
+    let mut tampered = encrypted.clone();
+    let last = tampered.pop().unwrap();
+    tampered.push(if last == 'A' { 'B' } else { 'A' });
+    assert!(cipher.decrypt("t1", &tampered).await.is_err());
+}
+
+#[tokio::test]
+async fn reuses_data_keys_until_they_expire() {
+    let cipher = cipher(Duration::from_secs(3600));
+    let first = cipher.encrypt("t1", b"a").await.unwrap();
+    let second = cipher.encrypt("t1", b"a").await.unwrap();
+    assert_eq!(wrapped_key(&first), wrapped_key(&second));
+    // Fresh nonce per value
+    assert_ne!(first, second);
+    let other_tenant = cipher.encrypt("t2", b"a").await.unwrap();
+    assert_ne!(wrapped_key(&first), wrapped_key(&other_tenant));
+
+    let rotating = self::cipher(Duration::ZERO);
+    let first = rotating.encrypt("t1", b"a").await.unwrap();
+    let second = rotating.encrypt("t1", b"b").await.unwrap();
+    assert_ne!(wrapped_key(&first), wrapped_key(&second));
+    // Values under older keys still decrypt
+    assert_eq!(rotating.decrypt("t1", &first).await.unwrap(), b"a");
+}
+
+#[tokio::test]
+async fn decrypts_json_in_place() {
+    let cipher = cipher(Duration::from_secs(3600));
+    let mut properties = json!({
+        "email": cipher.encrypt_value("t1", &json!("jane@example.com")).await.unwrap(),
+        "address": cipher.encrypt_value("t1", &json!({ "city": "Berlin" })).await.unwrap(),
+        "plan": "pro",
+        "history": [cipher.encrypt_value("t1", &json!(42)).await.unwrap()],
+    });
+    assert!(!properties.to_string().contains("Berlin"));
+
+    cipher.decrypt_json("t1", &mut properties).await.unwrap();
+    assert_eq!(
+        properties,
+        json!({
+            "email": "jane@example.com",
+            "address": { "city": "Berlin" },
+            "plan": "pro",
+            "history": [42],
+        })
+    );
+}
+
+#[test]
+fn config_requires_key_material() {
+    assert!(EncryptionConfig::default().validate().is_ok());
+    let kms = EncryptionConfig {
+        provider: "kms".to_string(),
+        ..EncryptionConfig::default()
+    };
+    assert!(kms.validate().is_err());
+
+    let local = EncryptionConfig {
+        provider: "local".to_string(),
+        local_master_key: "c2hvcnQ=".to_string(),
+        ..EncryptionConfig::default()
+    };
+    assert!(local.validate().is_ok());
+    // Not 32 bytes
+    assert!(EnvelopeCipher::from_config(&local).is_err());
+    assert!(EnvelopeCipher::from_config(&EncryptionConfig::default()).unwrap().is_none());
+}
+
+#[tokio::test]
+async fn decrypts_stored_property_documents() {
+    let cipher = cipher(Duration::from_secs(3600));
+    let stored = json!({
+        "email": cipher.encrypt_value("t1", &json!("jane@example.com")).await.unwrap(),
+        "plan": "pro",
+    })
+    .to_string();
+
+    let decrypted: serde_json::Value = serde_json::from_str(&cipher.decrypt_text("t1", &stored).await.unwrap()).unwrap();
+    assert_eq!(decrypted, json!({ "email": "jane@example.com", "plan": "pro" }));
+    assert_eq!(cipher.decrypt_text("t1", "not json").await.unwrap(), "not json");
+
+    // A query cell holding the whole column
+    let mut cell = json!(stored);
+    cipher.decrypt_json("t1", &mut cell).await.unwrap();
+    let decrypted: serde_json::Value = serde_json::from_str(cell.as_str().unwrap()).unwrap();
+    assert_eq!(decrypted["email"], "jane@example.com");
+}
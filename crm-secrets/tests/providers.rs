@@ -0,0 +1,86 @@
+use crm_config::Configurable;
+use crm_secrets::{EnvFileProvider, SecretStore, SecretsError, SecretsProvider};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct TestConfig {
+    clickhouse_password: String,
+    redis_url: String,
+    brokers: Vec<String>,
+}
+
+impl Configurable for TestConfig {}
+
+fn write_file(name: &str, contents: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("crm-secrets-{}-{}", std::process::id(), name));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[tokio::test]
+async fn env_file_parses_and_rereads() {
+    let path = write_file(
+        "parse.env",
+        "# rendered by the agent\nexport CLICKHOUSE_PASSWORD=\"hunter2\"\nplugins/t1/API_KEY='abc=123'\n\n",
+    );
+    let provider = EnvFileProvider::new(&path);
+
+    assert_eq!(provider.get("CLICKHOUSE_PASSWORD").await.unwrap().value, "hunter2");
+    assert_eq!(provider.get("plugins/t1/API_KEY").await.unwrap().value, "abc=123");
+    assert!(matches!(provider.get("MISSING").await, Err(SecretsError::NotFound(_))));
+
+    // Make sure the modification time moves
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    std::fs::write(&path, "CLICKHOUSE_PASSWORD=rotated\n").unwrap();
+    assert_eq!(provider.get("CLICKHOUSE_PASSWORD").await.unwrap().value, "rotated");
+}
+
+#[tokio::test]
+async fn resolves_references_in_configs() {
+    let path = write_file("resolve.env", "CH_PASSWORD=hunter2\nREDIS_URL=redis://:pw@redis:6379\n");
+    let store = SecretStore::new(Arc::new(EnvFileProvider::new(&path)), Duration::from_secs(300));
+
+    let template = TestConfig {
+        clickhouse_password: "secret://env-file/CH_PASSWORD".to_string(),
+        redis_url: "secret://env-file/REDIS_URL".to_string(),
+        brokers: vec!["kafka:9092".to_string()],
+    };
+    let config = store.resolve(&template).await.unwrap();
+    assert_eq!(config.clickhouse_password, "hunter2");
+    assert_eq!(config.redis_url, "redis://:pw@redis:6379");
+    assert_eq!(config.brokers, template.brokers);
+
+    assert_eq!(store.resolve_str("plain").await.unwrap(), "plain");
+    let err = store.resolve_str("secret://vault/crm/redis#password").await.unwrap_err();
+    assert!(matches!(err, SecretsError::Unconfigured(_)));
+
+    // Without a provider, only reference-free configs resolve
+    let disabled = SecretStore::disabled();
+    assert!(disabled.resolve(&template).await.is_err());
+    let plain = TestConfig::default();
+    assert_eq!(disabled.resolve(&plain).await.unwrap(), plain);
+}
+
+#[tokio::test]
+async fn watch_publishes_rotated_secrets() {
+    let path = write_file("watch.env", "CH_PASSWORD=first\n");
+    let store = Arc::new(SecretStore::new(Arc::new(EnvFileProvider::new(&path)), Duration::from_millis(50)));
+
+    let template = TestConfig {
+        clickhouse_password: "secret://env-file/CH_PASSWORD".to_string(),
+        ..Default::default()
+    };
+    let mut config = store.watch(template).await.unwrap();
+    assert_eq!(config.borrow().clickhouse_password, "first");
+
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    std::fs::write(&path, "CH_PASSWORD=second\n").unwrap();
+    tokio::time::timeout(Duration::from_secs(2), config.changed())
+        .await
+        .expect("rotation was published")
+        .unwrap();
+    assert_eq!(config.borrow().clickhouse_password, "second");
+}
@@ -0,0 +1,167 @@
+use crate::{retry, Dependency, StartupConfig, StartupError};
+use prometheus::IntGaugeVec;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+use warp::http::StatusCode;
+use warp::Filter;
+
+static DEPENDENCY_UP: OnceLock<IntGaugeVec> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// Waiting for critical dependencies; not serving work yet.
+    Starting,
+    Ready,
+    /// Started, but a dependency is down. The service keeps serving what
+    /// doesn't need it.
+    Degraded,
+}
+
+/// A service's dependencies and their last probe results.
+pub struct Health {
+    service: String,
+    config: StartupConfig,
+    dependencies: Vec<Dependency>,
+    /// Last error by dependency; `None` when up, absent until first probed.
+    results: RwLock<HashMap<String, Option<String>>>,
+    started: AtomicBool,
+}
+
+impl Health {
+    pub fn new(service: &str, config: &StartupConfig, dependencies: Vec<Dependency>) -> Arc<Self> {
+        Arc::new(Health {
+            service: service.to_string(),
+            config: config.clone(),
+            dependencies,
+            results: RwLock::new(HashMap::new()),
+            started: AtomicBool::new(false),
+        })
+    }
+
+    /// Waits for every critical dependency with bounded backoff, then marks
+    /// the service started and keeps probing in the background. Optional
+    /// dependencies that are down leave it degraded rather than failing.
+    pub async fn start(self: &Arc<Self>) -> Result<(), StartupError> {
+        retry("critical dependencies", &self.config.retry_policy(), || async {
+            self.probe().await;
+            match self.failing(true) {
+                failing if failing.is_empty() => Ok(()),
+                failing => Err(StartupError::Unavailable(failing.join(", "))),
+            }
+        })
+        .await?;
+
+        self.started.store(true, Ordering::Relaxed);
+        match self.failing(false) {
+            failing if failing.is_empty() => info!("{} ready", self.service),
+            failing => warn!("{} started degraded; waiting on {}", self.service, failing.join(", ")),
+        }
+
+        let health = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(health.config.probe_interval_secs));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                health.probe().await;
+            }
+        });
+        Ok(())
+    }
+
+    /// Probes every dependency once, recording and logging changes.
+    pub async fn probe(&self) {
+        let limit = Duration::from_millis(self.config.probe_timeout_ms);
+        for dependency in &self.dependencies {
+            let result = dependency.check(limit).await.err();
+            let name = dependency.name();
+            dependency_up().with_label_values(&[name]).set(i64::from(result.is_none()));
+
+            let mut results = self.results.write().unwrap_or_else(|e| e.into_inner());
+            match (results.get(name), &result) {
+                (Some(Some(_)), None) => info!("Dependency {} recovered", name),
+                (Some(None) | None, Some(e)) => warn!("Dependency {} is down: {}", name, e),
+                _ => {}
+            }
+            results.insert(name.to_string(), result);
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        if !self.started.load(Ordering::Relaxed) {
+            Status::Starting
+        } else if self.failing(false).is_empty() {
+            Status::Ready
+        } else {
+            Status::Degraded
+        }
+    }
+
+    /// Whether `name` answered its last probe.
+    pub fn is_up(&self, name: &str) -> bool {
+        matches!(self.results.read().unwrap_or_else(|e| e.into_inner()).get(name), Some(None))
+    }
+
+    /// The `/healthz` body.
+    pub fn report(&self) -> serde_json::Value {
+        let results = self.results.read().unwrap_or_else(|e| e.into_inner());
+        let dependencies: serde_json::Map<_, _> = self
+            .dependencies
+            .iter()
+            .map(|dependency| {
+                let result = results.get(dependency.name());
+                let detail = json!({
+                    "up": matches!(result, Some(None)),
+                    "critical": dependency.is_critical(),
+                    "error": result.cloned().flatten(),
+                });
+                (dependency.name().to_string(), detail)
+            })
+            .collect();
+        json!({
+            "service": self.service,
+            "status": self.status(),
+            "dependencies": dependencies,
+        })
+    }
+
+    /// `GET /healthz`: 503 while starting, 200 when ready or degraded.
+    pub fn route(self: &Arc<Self>) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+        let health = Arc::clone(self);
+        warp::path("healthz").and(warp::get()).map(move || {
+            let code = match health.status() {
+                Status::Starting => StatusCode::SERVICE_UNAVAILABLE,
+                Status::Ready | Status::Degraded => StatusCode::OK,
+            };
+            warp::reply::with_status(warp::reply::json(&health.report()), code)
+        })
+    }
+
+    /// Dependencies that are down or not yet probed, optionally only the
+    /// critical ones.
+    fn failing(&self, critical_only: bool) -> Vec<String> {
+        let results = self.results.read().unwrap_or_else(|e| e.into_inner());
+        self.dependencies
+            .iter()
+            .filter(|dependency| dependency.is_critical() || !critical_only)
+            .filter(|dependency| !matches!(results.get(dependency.name()), Some(None)))
+            .map(|dependency| dependency.name().to_string())
+            .collect()
+    }
+}
+
+fn dependency_up() -> &'static IntGaugeVec {
+    DEPENDENCY_UP.get_or_init(|| {
+        prometheus::register_int_gauge_vec!(
+            "dependency_up",
+            "Whether each dependency answered its last health probe",
+            &["dependency"]
+        ).unwrap()
+    })
+}
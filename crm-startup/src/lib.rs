@@ -0,0 +1,112 @@
+//! Dependency-checked startup for the CRM Rust services.
+//!
+//! A service lists the backends it needs as [`Dependency`] probes (HTTP,
+//! Redis, Kafka brokers, a directory) and hands them to a [`Health`]:
+//!
+//! - critical dependencies are waited for with bounded exponential backoff
+//!   ([`Health::start`]); the service exits if they stay down past
+//!   `timeout_secs` and lets its supervisor restart it
+//! - optional dependencies never block startup; while one is down the
+//!   service is `degraded` and keeps serving what it can
+//! - every dependency is re-probed on an interval afterwards, so recovery
+//!   (or a later outage) shows up in `/healthz` and `dependency_up`
+//!
+//! `/healthz` answers 503 while `starting` and 200 once `ready` or
+//! `degraded`, with per-dependency detail in the body. [`retry`] is the same
+//! backoff for one-off startup steps such as the first secrets fetch.
+
+use crm_config::{ConfigLoader, Configurable};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+mod health;
+mod probe;
+mod retry;
+
+pub use health::{Health, Status};
+pub use probe::Dependency;
+pub use retry::{retry, RetryPolicy};
+
+#[derive(Debug)]
+pub enum StartupError {
+    /// Critical dependencies were still down when the startup timeout ran out.
+    Unavailable(String),
+    Config(crm_config::ConfigError),
+}
+
+impl std::fmt::Display for StartupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StartupError::Unavailable(reason) => write!(f, "dependencies unavailable: {}", reason),
+            StartupError::Config(e) => write!(f, "startup configuration: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StartupError {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StartupConfig {
+    /// First retry delay; doubled after each failure.
+    pub retry_initial_delay_ms: u64,
+    /// Cap on a single retry delay.
+    pub retry_max_delay_ms: u64,
+    /// How long startup waits for critical dependencies before giving up.
+    pub timeout_secs: u64,
+    /// How often dependencies are re-probed once started.
+    pub probe_interval_secs: u64,
+    /// Per-probe timeout.
+    pub probe_timeout_ms: u64,
+}
+
+impl Default for StartupConfig {
+    fn default() -> Self {
+        StartupConfig {
+            retry_initial_delay_ms: 500,
+            retry_max_delay_ms: 30_000,
+            timeout_secs: 300,
+            probe_interval_secs: 15,
+            probe_timeout_ms: 3_000,
+        }
+    }
+}
+
+impl Configurable for StartupConfig {
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if self.retry_initial_delay_ms == 0 {
+            problems.push("retry_initial_delay_ms must be positive".to_string());
+        }
+        if self.retry_max_delay_ms < self.retry_initial_delay_ms {
+            problems.push("retry_max_delay_ms must be at least retry_initial_delay_ms".to_string());
+        }
+        if self.probe_interval_secs == 0 {
+            problems.push("probe_interval_secs must be positive".to_string());
+        }
+        if self.probe_timeout_ms == 0 {
+            problems.push("probe_timeout_ms must be positive".to_string());
+        }
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+}
+
+impl StartupConfig {
+    /// Defaults, then `STARTUP_CONFIG_FILE`, then `STARTUP_*` variables
+    /// (e.g. `STARTUP_TIMEOUT_SECS`).
+    pub fn from_env() -> Result<Self, StartupError> {
+        ConfigLoader::new()
+            .file_from_env("STARTUP_CONFIG_FILE")
+            .env_prefix("STARTUP_")
+            .load()
+            .map_err(StartupError::Config)
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            initial_delay: Duration::from_millis(self.retry_initial_delay_ms),
+            max_delay: Duration::from_millis(self.retry_max_delay_ms),
+            max_elapsed: Duration::from_secs(self.timeout_secs),
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+#[derive(Clone)]
+enum Probe {
+    /// GET must answer 2xx, e.g. ClickHouse's `/ping`.
+    Http(String),
+    /// Connect and `PING`.
+    Redis(String),
+    /// Any of the addresses accepts a TCP connection.
+    Tcp(Vec<String>),
+    /// The directory exists.
+    Path(PathBuf),
+}
+
+/// A backend a service needs, and how to tell whether it is reachable.
+/// Connection strings are never logged or reported, only `name`.
+#[derive(Clone)]
+pub struct Dependency {
+    name: String,
+    probe: Probe,
+    critical: bool,
+}
+
+impl Dependency {
+    fn new(name: &str, probe: Probe) -> Self {
+        Dependency {
+            name: name.to_string(),
+            probe,
+            critical: true,
+        }
+    }
+
+    pub fn http(name: &str, url: &str) -> Self {
+        Self::new(name, Probe::Http(url.to_string()))
+    }
+
+    pub fn redis(name: &str, url: &str) -> Self {
+        Self::new(name, Probe::Redis(url.to_string()))
+    }
+
+    /// Kafka is checked at the TCP level against `bootstrap.servers`;
+    /// librdkafka handles the protocol and retries once connected.
+    pub fn kafka(name: &str, brokers: &str) -> Self {
+        Self::tcp(name, brokers.split(',').map(str::trim).filter(|b| !b.is_empty()))
+    }
+
+    pub fn tcp<I: IntoIterator<Item = S>, S: Into<String>>(name: &str, addrs: I) -> Self {
+        Self::new(name, Probe::Tcp(addrs.into_iter().map(Into::into).collect()))
+    }
+
+    pub fn path(name: &str, path: impl Into<PathBuf>) -> Self {
+        Self::new(name, Probe::Path(path.into()))
+    }
+
+    /// Doesn't block startup; the service runs degraded while it is down.
+    pub fn optional(mut self) -> Self {
+        self.critical = false;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_critical(&self) -> bool {
+        self.critical
+    }
+
+    /// One probe, failing with a reason when it doesn't answer in time.
+    pub async fn check(&self, limit: Duration) -> Result<(), String> {
+        match timeout(limit, self.probe()).await {
+            Ok(result) => result,
+            Err(_) => Err(format!("no answer within {:?}", limit)),
+        }
+    }
+
+    async fn probe(&self) -> Result<(), String> {
+        match &self.probe {
+            Probe::Http(url) => {
+                let response = reqwest::get(url).await.map_err(|e| e.without_url().to_string())?;
+                if response.status().is_success() {
+                    Ok(())
+                } else {
+                    Err(format!("HTTP {}", response.status()))
+                }
+            }
+            Probe::Redis(url) => {
+                let client = redis::Client::open(url.as_str()).map_err(|e| e.to_string())?;
+                let mut conn = client.get_async_connection().await.map_err(|e| e.to_string())?;
+                redis::cmd("PING").query_async::<_, String>(&mut conn).await.map_err(|e| e.to_string())?;
+                Ok(())
+            }
+            Probe::Tcp(addrs) => {
+                let mut last = "no addresses configured".to_string();
+                for addr in addrs {
+                    match TcpStream::connect(addr.as_str()).await {
+                        Ok(_) => return Ok(()),
+                        Err(e) => last = format!("{}: {}", addr, e),
+                    }
+                }
+                Err(last)
+            }
+            Probe::Path(path) => match tokio::fs::metadata(path).await {
+                Ok(meta) if meta.is_dir() => Ok(()),
+                Ok(_) => Err(format!("{} is not a directory", path.display())),
+                Err(e) => Err(format!("{}: {}", path.display(), e)),
+            },
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Exponential backoff with jitter, bounded by total elapsed time.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    /// No attempt starts after this long; the last error is returned.
+    pub max_elapsed: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        crate::StartupConfig::default().retry_policy()
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before retry `n` (from 0): doubling from `initial_delay`,
+    /// capped at `max_delay`, then jittered down by up to half so restarted
+    /// replicas don't retry in lockstep.
+    pub fn delay(&self, n: u32) -> Duration {
+        let full = self.initial_delay.saturating_mul(2u32.saturating_pow(n)).min(self.max_delay);
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+        full - full.mul_f64(f64::from(nanos % 1000) / 2000.0)
+    }
+}
+
+/// Runs `attempt` until it succeeds or `policy.max_elapsed` runs out,
+/// logging each failure against `what`.
+pub async fn retry<T, E, F, Fut>(what: &str, policy: &RetryPolicy, mut attempt: F) -> Result<T, E>
+where
+    E: Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let started = Instant::now();
+    let mut n = 0;
+    loop {
+        let e = match attempt().await {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+        let delay = policy.delay(n);
+        if started.elapsed() + delay > policy.max_elapsed {
+            warn!("{} unavailable after {} attempts, giving up: {}", what, n + 1, e);
+            return Err(e);
+        }
+        warn!("{} unavailable (attempt {}), retrying in {:?}: {}", what, n + 1, delay, e);
+        tokio::time::sleep(delay).await;
+        n += 1;
+    }
+}
@@ -0,0 +1,122 @@
+use crm_startup::{retry, Dependency, Health, RetryPolicy, StartupConfig, Status};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+fn fast() -> StartupConfig {
+    StartupConfig {
+        retry_initial_delay_ms: 20,
+        retry_max_delay_ms: 100,
+        timeout_secs: 1,
+        probe_interval_secs: 60,
+        probe_timeout_ms: 500,
+    }
+}
+
+fn closed_port() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+#[test]
+fn delays_double_up_to_the_cap() {
+    let policy = RetryPolicy {
+        initial_delay: Duration::from_millis(100),
+        max_delay: Duration::from_secs(1),
+        max_elapsed: Duration::from_secs(60),
+    };
+    for (n, full) in [(0, 100), (1, 200), (3, 800), (4, 1000), (40, 1000)] {
+        let delay = policy.delay(n);
+        assert!(delay <= Duration::from_millis(full), "retry {}: {:?}", n, delay);
+        assert!(delay >= Duration::from_millis(full / 2), "retry {}: {:?}", n, delay);
+    }
+}
+
+#[tokio::test]
+async fn retries_until_success() {
+    let attempts = AtomicU32::new(0);
+    let result = retry("flaky", &fast().retry_policy(), || async {
+        match attempts.fetch_add(1, Ordering::SeqCst) {
+            0..=2 => Err("not yet"),
+            _ => Ok("connected"),
+        }
+    })
+    .await;
+
+    assert_eq!(result, Ok("connected"));
+    assert_eq!(attempts.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn gives_up_with_the_last_error() {
+    let started = Instant::now();
+    let attempts = AtomicU32::new(0);
+    let result: Result<(), String> = retry("down", &fast().retry_policy(), || async {
+        Err(format!("attempt {}", attempts.fetch_add(1, Ordering::SeqCst)))
+    })
+    .await;
+
+    let last = attempts.load(Ordering::SeqCst) - 1;
+    assert_eq!(result, Err(format!("attempt {}", last)));
+    assert!(last > 2);
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[tokio::test]
+async fn probes_report_reachability() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let open = listener.local_addr().unwrap().to_string();
+    let limit = Duration::from_millis(500);
+
+    // Any reachable bootstrap server will do
+    let brokers = format!("{}, {}", closed_port(), open);
+    assert_eq!(Dependency::kafka("kafka", &brokers).check(limit).await, Ok(()));
+    assert!(Dependency::kafka("kafka", &closed_port()).check(limit).await.is_err());
+    assert!(Dependency::redis("redis", &format!("redis://{}", closed_port())).check(limit).await.is_err());
+    assert!(Dependency::http("clickhouse", &format!("http://{}/ping", closed_port())).check(limit).await.is_err());
+    assert_eq!(Dependency::path("modules", std::env::temp_dir()).check(limit).await, Ok(()));
+}
+
+#[tokio::test]
+async fn missing_critical_dependency_fails_startup() {
+    let health = Health::new("test", &fast(), vec![Dependency::kafka("kafka", &closed_port())]);
+
+    assert!(health.start().await.is_err());
+    assert_eq!(health.status(), Status::Starting);
+    assert!(!health.is_up("kafka"));
+}
+
+#[tokio::test]
+async fn optional_dependency_degrades_until_it_recovers() {
+    let dir = std::env::temp_dir().join(format!("crm-startup-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let health = Health::new(
+        "test",
+        &fast(),
+        vec![
+            Dependency::tcp("kafka", [listener.local_addr().unwrap().to_string()]),
+            Dependency::path("modules", &dir).optional(),
+        ],
+    );
+
+    // Not serving work until started
+    let route = health.route();
+    let response = warp::test::request().path("/healthz").reply(&route).await;
+    assert_eq!(response.status(), 503);
+
+    health.start().await.unwrap();
+    assert_eq!(health.status(), Status::Degraded);
+    let response = warp::test::request().path("/healthz").reply(&route).await;
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["status"], "degraded");
+    assert_eq!(body["dependencies"]["kafka"]["up"], true);
+    assert_eq!(body["dependencies"]["modules"]["up"], false);
+    assert_eq!(body["dependencies"]["modules"]["critical"], false);
+
+    std::fs::create_dir_all(&dir).unwrap();
+    health.probe().await;
+    assert_eq!(health.status(), Status::Ready);
+    assert!(health.is_up("modules"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
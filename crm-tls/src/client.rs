@@ -0,0 +1,110 @@
+use crate::MtlsContext;
+use hyper::client::connect::{Connected, Connection};
+use hyper::client::HttpConnector;
+use hyper::service::Service;
+use hyper::Uri;
+use rustls::pki_types::ServerName;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// hyper client connector speaking mTLS to `https://` upstreams and plain
+/// TCP to `http://` ones, so upstreams can be moved over one at a time.
+#[derive(Clone)]
+pub struct MtlsConnector {
+    http: HttpConnector,
+    tls: Option<TlsConnector>,
+}
+
+impl MtlsConnector {
+    /// Without a context only `http://` upstreams can be reached.
+    pub fn new(context: Option<&Arc<MtlsContext>>) -> Self {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+
+        MtlsConnector {
+            http,
+            tls: context.map(|context| TlsConnector::from(context.client_config())),
+        }
+    }
+}
+
+impl Service<Uri> for MtlsConnector {
+    type Response = MaybeTlsStream;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<MaybeTlsStream, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let https = uri.scheme_str() == Some("https");
+        let host = uri.host().unwrap_or_default().to_string();
+        let tls = self.tls.clone();
+        let connecting = self.http.call(uri);
+
+        Box::pin(async move {
+            let stream = connecting.await?;
+            if !https {
+                return Ok(MaybeTlsStream::Plain(stream));
+            }
+            let tls = tls.ok_or("https upstream but mTLS is not configured")?;
+            let name = ServerName::try_from(host)?;
+            Ok(MaybeTlsStream::Tls(Box::new(tls.connect(name, stream).await?)))
+        })
+    }
+}
+
+pub enum MaybeTlsStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl Connection for MaybeTlsStream {
+    fn connected(&self) -> Connected {
+        match self {
+            MaybeTlsStream::Plain(stream) => stream.connected(),
+            MaybeTlsStream::Tls(stream) => stream.get_ref().0.connected(),
+        }
+    }
+}
+
+impl AsyncRead for MaybeTlsStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for MaybeTlsStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! Mutual TLS between the CRM Rust services.
+//!
+//! Each service holds an X.509 SVID in the SPIFFE style: a certificate whose
+//! URI SAN is the service's identity (e.g.
+//! `spiffe://crm.internal/extension-runtime`), its private key, and the
+//! trust bundle of CAs that sign its peers. These are plain PEM files, as
+//! written by spiffe-helper, cert-manager or a Vault agent, and are re-read
+//! when they change so rotated certificates are picked up without a
+//! restart.
+//!
+//! Peers are authenticated by certificate chain and SPIFFE ID rather than
+//! hostname. [`serve`] runs a hyper service (a warp filter or axum router)
+//! behind mTLS, [`accept`] hands authenticated connections to servers with
+//! their own HTTP stack such as tonic, and [`MtlsConnector`] is the matching
+//! hyper client connector. [`PublicTls`] serves listeners reached from
+//! outside the mesh with an ordinary certificate.
+
+use crm_config::{ConfigLoader, Configurable};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::danger::ClientCertVerifier;
+use rustls::server::WebPkiClientVerifier;
+use rustls::sign::CertifiedKey;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+mod client;
+mod public;
+mod server;
+mod spiffe;
+mod verify;
+
+pub use client::{MaybeTlsStream, MtlsConnector};
+pub use public::PublicTls;
+pub use server::{accept, serve, ServerStream};
+pub use spiffe::spiffe_ids;
+
+use spiffe::Authorizer;
+
+#[derive(Debug)]
+pub enum TlsError {
+    Io(PathBuf, std::io::Error),
+    /// A PEM file held nothing usable.
+    Pem(String),
+    Rustls(rustls::Error),
+    Config(crm_config::ConfigError),
+}
+
+impl std::fmt::Display for TlsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsError::Io(path, e) => write!(f, "cannot read {}: {}", path.display(), e),
+            TlsError::Pem(reason) => write!(f, "invalid PEM: {}", reason),
+            TlsError::Rustls(e) => write!(f, "TLS error: {}", e),
+            TlsError::Config(e) => write!(f, "TLS configuration: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {}
+
+impl From<rustls::Error> for TlsError {
+    fn from(e: rustls::Error) -> Self {
+        TlsError::Rustls(e)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf first. mTLS is off while unset.
+    pub cert_file: Option<String>,
+    pub key_file: Option<String>,
+    /// PEM trust bundle that peers' certificates must chain to.
+    pub ca_file: Option<String>,
+    /// Peers must present a `spiffe://<trust_domain>/...` ID.
+    pub trust_domain: String,
+    /// SPIFFE IDs allowed to connect to this service; empty allows the
+    /// whole trust domain.
+    pub allowed_ids: Vec<String>,
+    /// How often the files are checked for rotation.
+    pub reload_interval_secs: u64,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            cert_file: None,
+            key_file: None,
+            ca_file: None,
+            trust_domain: "crm.internal".to_string(),
+            allowed_ids: Vec::new(),
+            reload_interval_secs: 30,
+        }
+    }
+}
+
+impl Configurable for TlsConfig {
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        let set = [&self.cert_file, &self.key_file, &self.ca_file].iter().filter(|f| f.is_some()).count();
+        if set != 0 && set != 3 {
+            problems.push("cert_file, key_file and ca_file must be set together".to_string());
+        }
+        if self.trust_domain.is_empty() || self.trust_domain.contains('/') {
+            problems.push("trust_domain must be a bare domain".to_string());
+        }
+        let prefix = format!("spiffe://{}/", self.trust_domain);
+        for id in self.allowed_ids.iter().filter(|id| !id.starts_with(&prefix)) {
+            problems.push(format!("allowed id {} is outside {}", id, prefix));
+        }
+        if self.reload_interval_secs == 0 {
+            problems.push("reload_interval_secs must be positive".to_string());
+        }
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+}
+
+impl TlsConfig {
+    /// Defaults, then `TLS_CONFIG_FILE`, then `TLS_*` variables
+    /// (e.g. `TLS_CERT_FILE`, `TLS_ALLOWED_IDS`).
+    pub fn from_env() -> Result<Self, TlsError> {
+        ConfigLoader::new()
+            .file_from_env("TLS_CONFIG_FILE")
+            .env_prefix("TLS_")
+            .load()
+            .map_err(TlsError::Config)
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.cert_file.is_some()
+    }
+}
+
+/// The current certificate, key and trust bundle.
+#[derive(Debug)]
+struct Material {
+    key: Arc<CertifiedKey>,
+    roots: Arc<RootCertStore>,
+    client_verifier: Arc<dyn ClientCertVerifier>,
+    identity: Option<String>,
+    modified: Vec<Option<SystemTime>>,
+}
+
+/// Loaded mTLS material shared by every server and client config built
+/// from it, so a reload reaches all of them at once.
+#[derive(Debug)]
+pub struct MtlsContext {
+    files: [PathBuf; 3],
+    provider: Arc<CryptoProvider>,
+    authorizer: Authorizer,
+    reload_interval: Duration,
+    material: RwLock<Arc<Material>>,
+}
+
+impl MtlsContext {
+    /// Loads the configured files; `None` when mTLS is off.
+    pub fn load(config: &TlsConfig) -> Result<Option<Arc<Self>>, TlsError> {
+        let (Some(cert), Some(key), Some(ca)) = (&config.cert_file, &config.key_file, &config.ca_file) else {
+            return Ok(None);
+        };
+        let files = [PathBuf::from(cert), PathBuf::from(key), PathBuf::from(ca)];
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let material = load_material(&files, &provider)?;
+
+        Ok(Some(Arc::new(MtlsContext {
+            files,
+            provider,
+            authorizer: Authorizer {
+                trust_domain: config.trust_domain.clone(),
+                allowed_ids: config.allowed_ids.clone(),
+            },
+            reload_interval: Duration::from_secs(config.reload_interval_secs),
+            material: RwLock::new(Arc::new(material)),
+        })))
+    }
+
+    /// [`TlsConfig::from_env`], loaded and watched for rotation.
+    pub fn from_env() -> Result<Option<Arc<Self>>, TlsError> {
+        let Some(context) = Self::load(&TlsConfig::from_env()?)? else {
+            info!("mTLS disabled");
+            return Ok(None);
+        };
+        info!("mTLS enabled as {}", context.identity().as_deref().unwrap_or("<no SPIFFE ID>"));
+        context.spawn_reload();
+        Ok(Some(context))
+    }
+
+    /// This service's own SPIFFE ID.
+    pub fn identity(&self) -> Option<String> {
+        self.current().identity.clone()
+    }
+
+    /// Requires and verifies client certificates. Offers h2 and http/1.1
+    /// so gRPC clients can share the listener.
+    pub fn server_config(self: &Arc<Self>) -> Arc<ServerConfig> {
+        let mut config = ServerConfig::builder_with_provider(self.provider.clone())
+            .with_safe_default_protocol_versions()
+            .expect("ring supports the default protocol versions")
+            .with_client_cert_verifier(Arc::new(verify::PeerClientVerifier(self.clone())))
+            .with_cert_resolver(Arc::new(verify::Resolver(self.clone())));
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Arc::new(config)
+    }
+
+    /// Presents this service's certificate and verifies the server's.
+    pub fn client_config(self: &Arc<Self>) -> Arc<ClientConfig> {
+        let config = ClientConfig::builder_with_provider(self.provider.clone())
+            .with_safe_default_protocol_versions()
+            .expect("ring supports the default protocol versions")
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verify::PeerServerVerifier(self.clone())))
+            .with_client_cert_resolver(Arc::new(verify::Resolver(self.clone())));
+        Arc::new(config)
+    }
+
+    /// Re-reads the files on an interval, keeping the current material when
+    /// a rotation is half-written or invalid.
+    pub fn spawn_reload(self: &Arc<Self>) {
+        let context = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(context.reload_interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(e) = context.reload() {
+                    warn!("Keeping current mTLS certificates: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Reloads if any file changed since the last load.
+    pub fn reload(&self) -> Result<bool, TlsError> {
+        if modified_times(&self.files) == self.current().modified {
+            return Ok(false);
+        }
+        let material = load_material(&self.files, &self.provider)?;
+        info!("Reloaded mTLS certificates for {}", material.identity.as_deref().unwrap_or("<no SPIFFE ID>"));
+        *self.material.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(material);
+        Ok(true)
+    }
+
+    fn current(&self) -> Arc<Material> {
+        self.material.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+fn modified_times(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+fn load_material(files: &[PathBuf; 3], provider: &CryptoProvider) -> Result<Material, TlsError> {
+    // Read the times first so a write racing the load is seen next time
+    let modified = modified_times(files);
+    let [cert_file, key_file, ca_file] = files;
+
+    let certs = read_certs(cert_file)?;
+    let key = read_key(key_file)?;
+    let identity = spiffe_ids(&certs[0]).into_iter().next();
+    let key = Arc::new(CertifiedKey::new(certs, provider.key_provider.load_private_key(key)?));
+    key.keys_match().map_err(TlsError::Rustls)?;
+
+    let mut roots = RootCertStore::empty();
+    for ca in read_certs(ca_file)? {
+        roots.add(ca)?;
+    }
+    let roots = Arc::new(roots);
+    let client_verifier = WebPkiClientVerifier::builder_with_provider(roots.clone(), Arc::new(provider.clone()))
+        .build()
+        .map_err(|e| TlsError::Pem(format!("{}: {}", ca_file.display(), e)))?;
+
+    Ok(Material {
+        key,
+        roots,
+        client_verifier,
+        identity,
+        modified,
+    })
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>, TlsError> {
+    std::fs::read(path).map_err(|e| TlsError::Io(path.to_path_buf(), e))
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let pem = read_pem(path)?;
+    let certs = rustls_pemfile::certs(&mut pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| TlsError::Io(path.to_path_buf(), e))?;
+    if certs.is_empty() {
+        return Err(TlsError::Pem(format!("no certificates in {}", path.display())));
+    }
+    Ok(certs)
+}
+
+fn read_key(path: &Path) -> Result<PrivateKeyDer<'static>, TlsError> {
+    let pem = read_pem(path)?;
+    rustls_pemfile::private_key(&mut pem.as_slice())
+        .map_err(|e| TlsError::Io(path.to_path_buf(), e))?
+        .ok_or_else(|| TlsError::Pem(format!("no private key in {}", path.display())))
+}
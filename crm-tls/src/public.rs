@@ -0,0 +1,190 @@
+//! TLS for listeners reached from outside the mesh, where callers have no
+//! SVID: the service presents an ordinary certificate (e.g. issued by
+//! cert-manager or an ACME client) and, when a client CA is configured,
+//! requires client certificates chaining to it, without any SPIFFE ID
+//! checks. Files are re-read when they change, like [`MtlsContext`]'s.
+//!
+//! [`MtlsContext`]: crate::MtlsContext
+
+use crate::{modified_times, read_certs, read_key, TlsError};
+use rustls::client::danger::HandshakeSignatureValid;
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, UnixTime};
+use rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{DigitallySignedStruct, DistinguishedName, Error, RootCertStore, ServerConfig, SignatureScheme};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+#[derive(Debug)]
+struct PublicMaterial {
+    key: Arc<CertifiedKey>,
+    client_verifier: Option<Arc<dyn ClientCertVerifier>>,
+    modified: Vec<Option<SystemTime>>,
+}
+
+/// A listener's certificate and, optionally, the CA its clients'
+/// certificates must chain to.
+#[derive(Debug)]
+pub struct PublicTls {
+    /// Certificate, key and, when client certificates are required, CA.
+    files: Vec<PathBuf>,
+    provider: Arc<CryptoProvider>,
+    material: RwLock<Arc<PublicMaterial>>,
+}
+
+impl PublicTls {
+    pub fn load(cert_file: &str, key_file: &str, client_ca_file: Option<&str>) -> Result<Arc<Self>, TlsError> {
+        let files: Vec<PathBuf> = [Some(cert_file), Some(key_file), client_ca_file]
+            .into_iter()
+            .flatten()
+            .map(PathBuf::from)
+            .collect();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let material = load_public(&files, &provider)?;
+        Ok(Arc::new(PublicTls {
+            files,
+            provider,
+            material: RwLock::new(Arc::new(material)),
+        }))
+    }
+
+    pub fn requires_client_certs(&self) -> bool {
+        self.files.len() == 3
+    }
+
+    /// Offers h2 and http/1.1, like [`crate::MtlsContext::server_config`].
+    pub fn server_config(self: &Arc<Self>) -> Arc<ServerConfig> {
+        let builder = ServerConfig::builder_with_provider(self.provider.clone())
+            .with_safe_default_protocol_versions()
+            .expect("ring supports the default protocol versions");
+        let builder = if self.requires_client_certs() {
+            builder.with_client_cert_verifier(Arc::new(ClientVerifier(self.clone())))
+        } else {
+            builder.with_no_client_auth()
+        };
+        let mut config = builder.with_cert_resolver(Arc::new(Resolver(self.clone())));
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Arc::new(config)
+    }
+
+    /// Re-reads the files on `interval`, keeping the current material when
+    /// a rotation is half-written or invalid.
+    pub fn spawn_reload(self: &Arc<Self>, interval: Duration) {
+        let tls = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(e) = tls.reload() {
+                    warn!("Keeping current TLS certificate: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Reloads if any file changed since the last load.
+    pub fn reload(&self) -> Result<bool, TlsError> {
+        if modified_times(&self.files) == self.current().modified {
+            return Ok(false);
+        }
+        let material = load_public(&self.files, &self.provider)?;
+        info!("Reloaded TLS certificate from {}", self.files[0].display());
+        *self.material.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(material);
+        Ok(true)
+    }
+
+    fn current(&self) -> Arc<PublicMaterial> {
+        self.material.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+fn load_public(files: &[PathBuf], provider: &CryptoProvider) -> Result<PublicMaterial, TlsError> {
+    // Read the times first so a write racing the load is seen next time
+    let modified = modified_times(files);
+    let certs = read_certs(&files[0])?;
+    let key = read_key(&files[1])?;
+    let key = Arc::new(CertifiedKey::new(certs, provider.key_provider.load_private_key(key)?));
+    key.keys_match().map_err(TlsError::Rustls)?;
+
+    let client_verifier = match files.get(2) {
+        Some(ca_file) => {
+            let mut roots = RootCertStore::empty();
+            for ca in read_certs(ca_file)? {
+                roots.add(ca)?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), Arc::new(provider.clone()))
+                .build()
+                .map_err(|e| TlsError::Pem(format!("{}: {}", ca_file.display(), e)))?;
+            Some(verifier)
+        }
+        None => None,
+    };
+
+    Ok(PublicMaterial {
+        key,
+        client_verifier,
+        modified,
+    })
+}
+
+#[derive(Debug)]
+struct Resolver(Arc<PublicTls>);
+
+impl ResolvesServerCert for Resolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.0.current().key.clone())
+    }
+}
+
+/// Requires a client certificate chaining to the current client CA.
+#[derive(Debug)]
+struct ClientVerifier(Arc<PublicTls>);
+
+impl ClientCertVerifier for ClientVerifier {
+    fn client_auth_mandatory(&self) -> bool {
+        true
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<ClientCertVerified, Error> {
+        match &self.0.current().client_verifier {
+            Some(verifier) => verifier.verify_client_cert(end_entity, intermediates, now),
+            None => Err(Error::General("no client CA loaded".to_string())),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls12_signature(message, cert, dss, &self.0.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls13_signature(message, cert, dss, &self.0.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
@@ -0,0 +1,84 @@
+use crate::TlsError;
+use hyper::body::HttpBody;
+use hyper::server::conn::Http;
+use hyper::service::Service;
+use hyper::{Body, Request, Response};
+use rustls::ServerConfig;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, info, warn};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An accepted connection whose peer passed verification.
+pub type ServerStream = tokio_rustls::server::TlsStream<TcpStream>;
+
+/// Accepts connections on `addr` and completes their handshakes in the
+/// background, yielding only authenticated ones. For servers that bring
+/// their own HTTP stack, such as tonic's `serve_with_incoming`.
+pub async fn accept(addr: impl Into<SocketAddr>, tls: Arc<ServerConfig>) -> Result<mpsc::Receiver<ServerStream>, TlsError> {
+    let addr = addr.into();
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| TlsError::Io(addr.to_string().into(), e))?;
+    let acceptor = TlsAcceptor::from(tls);
+    let (sender, receiver) = mpsc::channel(128);
+    info!("Serving mTLS on {}", addr);
+
+    tokio::spawn(async move {
+        while !sender.is_closed() {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            let acceptor = acceptor.clone();
+            let sender = sender.clone();
+
+            tokio::spawn(async move {
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = sender.send(stream).await;
+                    }
+                    Ok(Err(e)) => debug!("TLS handshake with {} failed: {}", peer, e),
+                    Err(_) => debug!("TLS handshake with {} timed out", peer),
+                }
+            });
+        }
+    });
+
+    Ok(receiver)
+}
+
+/// Serves `service` (e.g. `warp::service(routes)` or an axum `Router`) on
+/// `addr`, accepting only peers that pass `tls`. Failed handshakes are
+/// logged and dropped without affecting other connections.
+pub async fn serve<S, B>(addr: impl Into<SocketAddr>, tls: Arc<ServerConfig>, service: S) -> Result<(), TlsError>
+where
+    S: Service<Request<Body>, Response = Response<B>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let mut connections = accept(addr, tls).await?;
+
+    while let Some(stream) = connections.recv().await {
+        let service = service.clone();
+        tokio::spawn(async move {
+            let peer = stream.get_ref().0.peer_addr().ok();
+            if let Err(e) = Http::new().serve_connection(stream, service).await {
+                debug!("Connection from {:?} ended with error: {}", peer, e);
+            }
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,93 @@
+//! Just enough DER to read the URI SANs that carry SPIFFE IDs.
+
+const SEQUENCE: u8 = 0x30;
+const OCTET_STRING: u8 = 0x04;
+const OID: u8 = 0x06;
+const EXTENSIONS: u8 = 0xa3;
+const URI_NAME: u8 = 0x86;
+/// 2.5.29.17
+const SUBJECT_ALT_NAME: [u8; 3] = [0x55, 0x1d, 0x11];
+
+/// Splits one tag-length-value off the front of `input`.
+fn tlv(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let octets = (first & 0x7f) as usize;
+        if octets == 0 || octets > 4 || rest.len() < octets {
+            return None;
+        }
+        let len = rest[..octets].iter().fold(0usize, |len, b| (len << 8) | *b as usize);
+        (len, &rest[octets..])
+    };
+    if rest.len() < len {
+        return None;
+    }
+    Some((tag, &rest[..len], &rest[len..]))
+}
+
+fn children(mut input: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    std::iter::from_fn(move || {
+        let (tag, content, rest) = tlv(input)?;
+        input = rest;
+        Some((tag, content))
+    })
+}
+
+fn subject_alt_names(cert: &[u8]) -> Option<&[u8]> {
+    let (SEQUENCE, certificate, _) = tlv(cert)? else { return None };
+    let (SEQUENCE, tbs, _) = tlv(certificate)? else { return None };
+    let (_, extensions) = children(tbs).find(|(tag, _)| *tag == EXTENSIONS)?;
+    let (SEQUENCE, extensions, _) = tlv(extensions)? else { return None };
+
+    children(extensions).find_map(|(_, extension)| {
+        let mut fields = children(extension);
+        match fields.next()? {
+            (OID, oid) if oid == SUBJECT_ALT_NAME => {}
+            _ => return None,
+        }
+        let (_, value) = fields.find(|(tag, _)| *tag == OCTET_STRING)?;
+        let (SEQUENCE, names, _) = tlv(value)? else { return None };
+        Some(names)
+    })
+}
+
+/// The `spiffe://` URI SANs of a DER certificate.
+pub fn spiffe_ids(cert: &[u8]) -> Vec<String> {
+    subject_alt_names(cert)
+        .map(|names| {
+            children(names)
+                .filter(|(tag, _)| *tag == URI_NAME)
+                .filter_map(|(_, uri)| std::str::from_utf8(uri).ok())
+                .filter(|uri| uri.starts_with("spiffe://"))
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Decides which peers may talk to this service.
+#[derive(Debug, Clone)]
+pub(crate) struct Authorizer {
+    pub trust_domain: String,
+    pub allowed_ids: Vec<String>,
+}
+
+impl Authorizer {
+    /// The peer's SPIFFE ID, if it belongs to the trust domain and, for
+    /// inbound connections, is allowed in.
+    pub fn check(&self, cert: &[u8], inbound: bool) -> Result<String, String> {
+        let prefix = format!("spiffe://{}/", self.trust_domain);
+        let id = spiffe_ids(cert)
+            .into_iter()
+            .find(|id| id.starts_with(&prefix))
+            .ok_or_else(|| format!("peer has no SPIFFE ID in trust domain {}", self.trust_domain))?;
+
+        if inbound && !self.allowed_ids.is_empty() && !self.allowed_ids.contains(&id) {
+            return Err(format!("{} is not allowed to connect", id));
+        }
+        Ok(id)
+    }
+}
@@ -0,0 +1,148 @@
+use crm_tls::{spiffe_ids, MtlsConnector, MtlsContext, PublicTls, TlsConfig};
+use hyper::service::service_fn;
+use hyper::{Body, Client, Request, Response};
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn context(identity: &str, allowed_ids: &[&str]) -> Arc<MtlsContext> {
+    let config = TlsConfig {
+        cert_file: Some(fixture(&format!("{}.pem", identity))),
+        key_file: Some(fixture(&format!("{}-key.pem", identity))),
+        ca_file: Some(fixture("ca.pem")),
+        allowed_ids: allowed_ids.iter().map(|id| id.to_string()).collect(),
+        ..Default::default()
+    };
+    MtlsContext::load(&config).unwrap().unwrap()
+}
+
+/// Serves "hello" with `server` on a free local port.
+async fn start_server(server: Arc<rustls::ServerConfig>) -> u16 {
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let service = service_fn(|_: Request<Body>| async { Ok::<_, Infallible>(Response::new(Body::from("hello"))) });
+    tokio::spawn(crm_tls::serve(([127, 0, 0, 1], port), server, service));
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    port
+}
+
+async fn get(client: Arc<MtlsContext>, port: u16) -> Result<String, hyper::Error> {
+    let client: Client<MtlsConnector> = Client::builder().build(MtlsConnector::new(Some(&client)));
+    let response = client.get(format!("https://localhost:{}/", port).parse().unwrap()).await?;
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    Ok(String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[test]
+fn reads_spiffe_id_from_certificate() {
+    let pem = std::fs::read(fixture("runtime.pem")).unwrap();
+    let cert = rustls_pemfile::certs(&mut pem.as_slice()).next().unwrap().unwrap();
+
+    assert_eq!(spiffe_ids(&cert), vec!["spiffe://crm.internal/extension-runtime".to_string()]);
+    assert_eq!(context("runtime", &[]).identity().as_deref(), Some("spiffe://crm.internal/extension-runtime"));
+}
+
+#[test]
+fn config_requires_all_files() {
+    use crm_config::Configurable;
+
+    assert!(TlsConfig::default().validate().is_ok());
+    assert!(!TlsConfig::default().enabled());
+
+    let partial = TlsConfig {
+        cert_file: Some(fixture("runtime.pem")),
+        ..Default::default()
+    };
+    assert!(partial.validate().is_err());
+
+    let outside = TlsConfig {
+        allowed_ids: vec!["spiffe://example.org/gateway".to_string()],
+        ..Default::default()
+    };
+    assert!(outside.validate().is_err());
+}
+
+#[tokio::test]
+async fn allowed_peer_is_served() {
+    let port = start_server(context("runtime", &["spiffe://crm.internal/gateway"]).server_config()).await;
+
+    assert_eq!(get(context("gateway", &[]), port).await.unwrap(), "hello");
+}
+
+#[tokio::test]
+async fn unlisted_and_foreign_peers_are_rejected() {
+    let port = start_server(context("runtime", &["spiffe://crm.internal/gateway"]).server_config()).await;
+
+    // Same trust domain but not on the allow list
+    assert!(get(context("tracking", &[]), port).await.is_err());
+
+    // Right SPIFFE ID, signed by a CA outside the bundle
+    let foreign = TlsConfig {
+        cert_file: Some(fixture("foreign.pem")),
+        key_file: Some(fixture("foreign-key.pem")),
+        ca_file: Some(fixture("ca.pem")),
+        ..Default::default()
+    };
+    assert!(get(MtlsContext::load(&foreign).unwrap().unwrap(), port).await.is_err());
+}
+
+#[tokio::test]
+async fn public_listener_serves_clients_without_certificates_by_default() {
+    let tls = PublicTls::load(&fixture("runtime.pem"), &fixture("runtime-key.pem"), None).unwrap();
+    assert!(!tls.requires_client_certs());
+    let port = start_server(tls.server_config()).await;
+
+    // Not on any allow list, which only mesh listeners check
+    assert_eq!(get(context("tracking", &[]), port).await.unwrap(), "hello");
+}
+
+#[tokio::test]
+async fn public_listener_requires_client_certificates_from_its_ca() {
+    let tls = PublicTls::load(&fixture("runtime.pem"), &fixture("runtime-key.pem"), Some(&fixture("ca.pem"))).unwrap();
+    let port = start_server(tls.server_config()).await;
+
+    assert_eq!(get(context("tracking", &[]), port).await.unwrap(), "hello");
+
+    let foreign = TlsConfig {
+        cert_file: Some(fixture("foreign.pem")),
+        key_file: Some(fixture("foreign-key.pem")),
+        ca_file: Some(fixture("ca.pem")),
+        ..Default::default()
+    };
+    assert!(get(MtlsContext::load(&foreign).unwrap().unwrap(), port).await.is_err());
+}
+
+#[tokio::test]
+async fn reload_picks_up_rotated_certificate() {
+    let dir = std::env::temp_dir().join(format!("crm-tls-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let copy = |from: &str, to: &str| -> PathBuf {
+        let path = dir.join(to);
+        std::fs::copy(fixture(from), &path).unwrap();
+        path
+    };
+    let config = TlsConfig {
+        cert_file: Some(copy("gateway.pem", "svid.pem").display().to_string()),
+        key_file: Some(copy("gateway-key.pem", "svid-key.pem").display().to_string()),
+        ca_file: Some(copy("ca.pem", "bundle.pem").display().to_string()),
+        ..Default::default()
+    };
+    let context = MtlsContext::load(&config).unwrap().unwrap();
+    assert!(!context.reload().unwrap());
+
+    // Make sure the modification times move
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    copy("tracking.pem", "svid.pem");
+    copy("tracking-key.pem", "svid-key.pem");
+    assert!(context.reload().unwrap());
+    assert_eq!(context.identity().as_deref(), Some("spiffe://crm.internal/tracking"));
+
+    // A half-written rotation keeps the current certificate
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    std::fs::write(dir.join("svid.pem"), "").unwrap();
+    assert!(context.reload().is_err());
+    assert_eq!(context.identity().as_deref(), Some("spiffe://crm.internal/tracking"));
+}
@@ -0,0 +1,49 @@
+use super::create_consumer;
+use crate::CliResult;
+use rdkafka::consumer::Consumer;
+use rdkafka::Message;
+use serde_json::Value;
+use tokio::time::{timeout, Duration};
+
+/// Prints dead-letter records, e.g. those written by the webhook delivery
+/// service (`tenant_id`, `url`, `attempts`, `error`, `event`).
+pub async fn inspect(brokers: &str, topic: &str, tenant: Option<&str>, limit: usize, idle_timeout_secs: u64) -> CliResult {
+    let consumer = create_consumer(brokers, true)?;
+    consumer.subscribe(&[topic])?;
+
+    let idle = Duration::from_secs(idle_timeout_secs);
+    let mut shown = 0;
+
+    while shown < limit {
+        let message = match timeout(idle, consumer.recv()).await {
+            Ok(message) => message?,
+            Err(_) => break,
+        };
+        let record: Value = match message.payload().map(serde_json::from_slice) {
+            Some(Ok(record)) => record,
+            _ => {
+                eprintln!("undecodable record at {}:{}", message.partition(), message.offset());
+                continue;
+            }
+        };
+
+        let record_tenant = record["tenant_id"].as_str().or_else(|| record["event"]["tenant_id"].as_str());
+        if tenant.is_some() && record_tenant != tenant {
+            continue;
+        }
+
+        println!(
+            "[{}:{}] tenant={} attempts={} error={}",
+            message.partition(),
+            message.offset(),
+            record_tenant.unwrap_or("-"),
+            record["attempts"],
+            record["error"].as_str().unwrap_or("-"),
+        );
+        println!("{}", serde_json::to_string_pretty(&record)?);
+        shown += 1;
+    }
+
+    println!("{} record(s) shown from {}", shown, topic);
+    Ok(())
+}
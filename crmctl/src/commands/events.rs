@@ -0,0 +1,93 @@
+use super::create_consumer;
+use crate::CliResult;
+use crm_events::CrmEvent;
+use rdkafka::consumer::Consumer;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::{ClientConfig, Message};
+use tokio::time::{timeout, Duration};
+
+pub struct Filter {
+    pub tenant: Option<String>,
+    pub event_type: Option<String>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+}
+
+impl Filter {
+    fn matches(&self, event: &CrmEvent) -> bool {
+        self.tenant.as_ref().is_none_or(|t| *t == event.tenant_id)
+            && self.event_type.as_ref().is_none_or(|t| *t == event.event_type)
+            && self.since.is_none_or(|since| event.timestamp >= since)
+            && self.until.is_none_or(|until| event.timestamp < until)
+    }
+}
+
+pub async fn tail(brokers: &str, topic: &str, filter: &Filter, from_beginning: bool) -> CliResult {
+    let consumer = create_consumer(brokers, from_beginning)?;
+    consumer.subscribe(&[topic])?;
+
+    loop {
+        let message = consumer.recv().await?;
+        let payload = match message.payload() {
+            Some(payload) => payload,
+            None => continue,
+        };
+
+        match serde_json::from_slice::<CrmEvent>(payload) {
+            Ok(event) if filter.matches(&event) => println!("{}", serde_json::to_string(&event)?),
+            Ok(_) => {}
+            Err(e) => eprintln!("undecodable message at {}:{}: {}", message.partition(), message.offset(), e),
+        }
+    }
+}
+
+/// Reads `from` from the beginning and republishes matching events to `to`,
+/// stopping when the topic has been idle for `idle_timeout_secs`.
+pub async fn replay(
+    brokers: &str,
+    from: &str,
+    to: &str,
+    filter: &Filter,
+    limit: Option<usize>,
+    idle_timeout_secs: u64,
+    dry_run: bool,
+) -> CliResult {
+    let consumer = create_consumer(brokers, true)?;
+    consumer.subscribe(&[from])?;
+
+    let producer: FutureProducer = ClientConfig::new()
+        .set("bootstrap.servers", brokers)
+        .set("message.timeout.ms", "5000")
+        .create()?;
+
+    let idle = Duration::from_secs(idle_timeout_secs);
+    let mut scanned = 0;
+    let mut replayed = 0;
+
+    while limit.is_none_or(|limit| replayed < limit) {
+        let message = match timeout(idle, consumer.recv()).await {
+            Ok(message) => message?,
+            Err(_) => break,
+        };
+        scanned += 1;
+
+        let event = match message.payload().map(serde_json::from_slice::<CrmEvent>) {
+            Some(Ok(event)) if filter.matches(&event) => event,
+            _ => continue,
+        };
+
+        if !dry_run {
+            let body = serde_json::to_string(&event)?;
+            let record = FutureRecord::to(to).key(&event.tenant_id).payload(&body);
+            producer
+                .send(record, Duration::from_secs(5))
+                .await
+                .map_err(|(e, _)| e)?;
+        }
+        replayed += 1;
+    }
+
+    let verb = if dry_run { "would replay" } else { "replayed" };
+    println!("{} {} of {} events from {} to {}", verb, replayed, scanned, from, to);
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use crate::CliResult;
+use redis::aio::Connection;
+use redis::AsyncCommands;
+use std::collections::BTreeMap;
+
+async fn connect(redis_url: &str) -> Result<Connection, Box<dyn std::error::Error>> {
+    Ok(redis::Client::open(redis_url)?.get_async_connection().await?)
+}
+
+/// Reads the `metrics:{tenant}:{event_type}` counters kept by event ingestion.
+pub async fn counters(redis_url: &str, tenant: &str) -> CliResult {
+    let mut conn = connect(redis_url).await?;
+    let prefix = format!("metrics:{}:", tenant);
+
+    let keys: Vec<String> = {
+        let mut iter = conn.scan_match::<_, String>(format!("{}*", prefix)).await?;
+        let mut keys = Vec::new();
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+        keys
+    };
+
+    let mut counts = BTreeMap::new();
+    for key in keys {
+        let count: Option<u64> = conn.get(&key).await?;
+        counts.insert(key[prefix.len()..].to_string(), count.unwrap_or_default());
+    }
+
+    if counts.is_empty() {
+        println!("no counters for tenant {}", tenant);
+    }
+    for (event_type, count) in counts {
+        println!("{:<32} {}", event_type, count);
+    }
+    Ok(())
+}
+
+pub async fn campaign(redis_url: &str, tenant: &str, campaign: &str) -> CliResult {
+    let mut conn = connect(redis_url).await?;
+    let counts: BTreeMap<String, u64> = conn.hgetall(format!("campaign:{}:{}", tenant, campaign)).await?;
+
+    if counts.is_empty() {
+        println!("no engagement recorded for campaign {}", campaign);
+    }
+    for (event_type, count) in counts {
+        println!("{:<32} {}", event_type, count);
+    }
+    Ok(())
+}
+
+pub async fn activity(redis_url: &str, tenant: &str, user: &str) -> CliResult {
+    let mut conn = connect(redis_url).await?;
+    let last_seen: Option<i64> = conn.get(format!("activity:{}:{}", tenant, user)).await?;
+
+    match last_seen {
+        Some(timestamp) => println!("{} last active at {}", user, timestamp),
+        None => println!("no activity for {} in the last 24h", user),
+    }
+    Ok(())
+}
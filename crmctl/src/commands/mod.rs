@@ -0,0 +1,20 @@
+use rdkafka::consumer::StreamConsumer;
+use rdkafka::ClientConfig;
+
+pub mod dlq;
+pub mod events;
+pub mod metrics;
+pub mod module;
+
+/// A throwaway consumer group so the CLI never moves a service's offsets.
+fn create_consumer(brokers: &str, from_beginning: bool) -> Result<StreamConsumer, Box<dyn std::error::Error>> {
+    let consumer: StreamConsumer = ClientConfig::new()
+        .set("group.id", format!("crmctl-{}", uuid::Uuid::new_v4()))
+        .set("bootstrap.servers", brokers)
+        .set("enable.partition.eof", "false")
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", if from_beginning { "earliest" } else { "latest" })
+        .create()?;
+
+    Ok(consumer)
+}
@@ -0,0 +1,170 @@
+use crate::CliResult;
+use crm_audit::{AuditConfig, AuditEvent, AuditTrail};
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::{ObjectStore, PutPayload};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+use wasmparser::{Parser, Payload, Validator};
+
+/// Registry metadata sits next to each module under this suffix.
+const METADATA_SUFFIX: &str = ".meta.json";
+
+/// Checks the runtime at `runtime_url` would accept the module, with its
+/// `POST /validate`: the same import, manifest and capability checks as at
+/// execution time, so they're never out of step with the runtime. Returns
+/// the exported functions.
+async fn check(bytes: &[u8], runtime_url: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    Validator::new().validate_all(bytes)?;
+
+    let report: Value = reqwest::Client::new()
+        .post(format!("{}/validate", runtime_url.trim_end_matches('/')))
+        .body(bytes.to_vec())
+        .send()
+        .await?
+        .json()
+        .await?;
+    if report["valid"].as_bool() != Some(true) {
+        let violations: Vec<String> = report["violations"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|violation| format!("{}: {}", violation["check"].as_str().unwrap_or("?"), violation["message"].as_str().unwrap_or("")))
+            .collect();
+        return Err(format!("rejected by the runtime: {}", violations.join("; ")).into());
+    }
+
+    let mut exports = Vec::new();
+    for payload in Parser::new(0).parse_all(bytes) {
+        if let Payload::ExportSection(reader) = payload? {
+            for export in reader {
+                let export = export?;
+                if export.kind == wasmparser::ExternalKind::Func {
+                    exports.push(export.name.to_string());
+                }
+            }
+        }
+    }
+
+    Ok(exports)
+}
+
+pub async fn validate(file: &str, runtime_url: &str) -> CliResult {
+    let bytes = std::fs::read(file)?;
+    let exports = check(&bytes, runtime_url).await?;
+
+    println!("{}: ok ({} bytes)", file, bytes.len());
+    println!("exported functions: {}", exports.join(", "));
+    Ok(())
+}
+
+/// The module's name in the runtime: `name`, or the source file name.
+fn module_name(file: &str, name: Option<&str>) -> Result<String, Box<dyn std::error::Error>> {
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => std::path::Path::new(file)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or("cannot determine module file name")?
+            .to_string(),
+    };
+    if name.contains('/') || name.contains("..") || name.ends_with(METADATA_SUFFIX) {
+        return Err(format!("invalid module name: {}", name).into());
+    }
+    Ok(name)
+}
+
+pub async fn upload(file: &str, name: Option<&str>, module_dir: &str, runtime_url: &str) -> CliResult {
+    let bytes = std::fs::read(file)?;
+    check(&bytes, runtime_url).await?;
+    let name = module_name(file, name)?;
+
+    let target = std::path::Path::new(module_dir).join(&name);
+    std::fs::write(&target, &bytes)?;
+    record_upload(&name, &bytes, &target.display().to_string()).await?;
+
+    println!("uploaded {} -> {}", file, target.display());
+    Ok(())
+}
+
+/// Records the upload on the admin audit trail, as the local user.
+async fn record_upload(name: &str, bytes: &[u8], destination: &str) -> CliResult {
+    let actor = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+    let trail = AuditTrail::new("crmctl", &AuditConfig::from_env()?)?;
+    trail
+        .record(AuditEvent::new(actor, "module.uploaded", name).details(json!({
+            "sha256": hex::encode(Sha256::digest(bytes)),
+            "size": bytes.len(),
+            "destination": destination,
+        })))
+        .await;
+    Ok(())
+}
+
+/// Uploads to the runtime's primary registry: the content-addressed blob
+/// first, then the metadata that makes it visible. The runtime replicates it
+/// to the secondary region.
+pub async fn upload_to_registry(file: &str, name: Option<&str>, registry_url: &str, runtime_url: &str) -> CliResult {
+    let bytes = std::fs::read(file)?;
+    check(&bytes, runtime_url).await?;
+    let name = module_name(file, name)?;
+
+    let url = Url::parse(registry_url)?;
+    let (store, root): (Box<dyn ObjectStore>, Path) = match url.scheme() {
+        "s3" | "s3a" => (
+            Box::new(AmazonS3Builder::from_env().with_url(registry_url).build()?),
+            Path::from_url_path(url.path())?,
+        ),
+        _ => object_store::parse_url(&url)?,
+    };
+    let sha256 = hex::encode(Sha256::digest(&bytes));
+    let metadata = json!({
+        "name": name,
+        "sha256": sha256,
+        "size": bytes.len(),
+        "uploaded_at": SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    });
+    let size = bytes.len();
+    store.put(&root.child("blobs").child(sha256.as_str()), PutPayload::from(bytes.clone())).await?;
+    store
+        .put(&root.child(format!("{}{}", name, METADATA_SUFFIX)), PutPayload::from(serde_json::to_vec(&metadata)?))
+        .await?;
+    record_upload(&name, &bytes, registry_url).await?;
+
+    println!("uploaded {} -> {}/{} ({} bytes)", file, registry_url.trim_end_matches('/'), name, size);
+    Ok(())
+}
+
+pub async fn exec(
+    runtime_url: &str,
+    module_path: &str,
+    function_name: &str,
+    params: &str,
+    timeout_seconds: Option<u64>,
+) -> CliResult {
+    let params: Value = serde_json::from_str(params)?;
+    let request = json!({
+        "module_path": module_path,
+        "function_name": function_name,
+        "params": params,
+        "timeout_seconds": timeout_seconds,
+    });
+
+    // Failed executions come with an error status, and are printed too
+    let response: Value = reqwest::Client::new()
+        .post(format!("{}/execute", runtime_url.trim_end_matches('/')))
+        .json(&request)
+        .send()
+        .await?
+        .json()
+        .await?;
+
+    println!("{}", serde_json::to_string_pretty(&response)?);
+
+    if response["success"].as_bool() != Some(true) {
+        return Err("execution failed".into());
+    }
+    Ok(())
+}
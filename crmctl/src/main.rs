@@ -0,0 +1,238 @@
+//! `crmctl` — operations CLI for the CRM Rust services.
+//!
+//! Talks to the extension runtime over HTTP, to Kafka for event tailing,
+//! replay and dead-letter inspection, and to Redis for real-time metrics.
+
+use clap::{Args, Parser, Subcommand};
+
+mod commands;
+
+type CliResult = Result<(), Box<dyn std::error::Error>>;
+
+#[derive(Parser)]
+#[command(name = "crmctl", version, about = "Operate CRM services and plugins")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Validate, upload and execute WASM plugin modules
+    #[command(subcommand)]
+    Module(ModuleCommand),
+    /// Tail and replay CRM events on Kafka
+    #[command(subcommand)]
+    Events(EventsCommand),
+    /// Inspect dead-letter topics
+    #[command(subcommand)]
+    Dlq(DlqCommand),
+    /// Query real-time metrics kept in Redis
+    #[command(subcommand)]
+    Metrics(MetricsCommand),
+}
+
+#[derive(Subcommand)]
+enum ModuleCommand {
+    /// Check the runtime would accept a module, with its POST /validate
+    Validate {
+        file: String,
+        #[arg(long, env = "CRM_RUNTIME_URL", default_value = "http://localhost:8080")]
+        runtime_url: String,
+    },
+    /// Validate a module and copy it into the runtime's module directory,
+    /// or its registry when --registry-url is given
+    Upload {
+        file: String,
+        /// File name in the module directory; defaults to the source name
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long, env = "WASM_MODULE_DIR")]
+        module_dir: Option<String>,
+        /// The runtime's primary registry (its RUNTIME_REGISTRY_URL)
+        #[arg(long, env = "WASM_REGISTRY_URL")]
+        registry_url: Option<String>,
+        /// Runtime that validates the module first
+        #[arg(long, env = "CRM_RUNTIME_URL", default_value = "http://localhost:8080")]
+        runtime_url: String,
+    },
+    /// Execute a function on the runtime service
+    Exec {
+        /// Module path relative to the runtime's module directory
+        module_path: String,
+        function_name: String,
+        /// JSON array of arguments
+        #[arg(long, default_value = "[]")]
+        params: String,
+        #[arg(long)]
+        timeout_seconds: Option<u64>,
+        #[arg(long, env = "CRM_RUNTIME_URL", default_value = "http://localhost:8080")]
+        runtime_url: String,
+    },
+}
+
+#[derive(Args)]
+struct KafkaArgs {
+    #[arg(long, env = "KAFKA_BROKERS", default_value = "localhost:9092")]
+    brokers: String,
+}
+
+#[derive(Args)]
+struct EventFilter {
+    #[arg(long)]
+    tenant: Option<String>,
+    #[arg(long)]
+    event_type: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum EventsCommand {
+    /// Print events as they arrive
+    Tail {
+        #[command(flatten)]
+        kafka: KafkaArgs,
+        #[command(flatten)]
+        filter: EventFilter,
+        #[arg(long, default_value = "crm-events")]
+        topic: String,
+        /// Start from the oldest retained message instead of new ones
+        #[arg(long)]
+        from_beginning: bool,
+    },
+    /// Re-publish retained events from one topic to another
+    Replay {
+        #[command(flatten)]
+        kafka: KafkaArgs,
+        #[command(flatten)]
+        filter: EventFilter,
+        #[arg(long)]
+        from: String,
+        #[arg(long, default_value = "crm-events")]
+        to: String,
+        /// Only events with timestamp >= this (unix seconds)
+        #[arg(long)]
+        since: Option<i64>,
+        /// Only events with timestamp < this (unix seconds)
+        #[arg(long)]
+        until: Option<i64>,
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Stop once no message arrives for this long
+        #[arg(long, default_value_t = 5)]
+        idle_timeout_secs: u64,
+        /// Count matching events without publishing
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum DlqCommand {
+    /// Print dead-lettered records, oldest first
+    Inspect {
+        #[command(flatten)]
+        kafka: KafkaArgs,
+        #[arg(long, default_value = "webhooks-dlq")]
+        topic: String,
+        #[arg(long)]
+        tenant: Option<String>,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        #[arg(long, default_value_t = 5)]
+        idle_timeout_secs: u64,
+    },
+}
+
+#[derive(Args)]
+struct RedisArgs {
+    #[arg(long, env = "REDIS_URL", default_value = "redis://localhost:6379")]
+    redis_url: String,
+}
+
+#[derive(Subcommand)]
+enum MetricsCommand {
+    /// Hourly event counters per event type
+    Counters {
+        #[command(flatten)]
+        redis: RedisArgs,
+        #[arg(long)]
+        tenant: String,
+    },
+    /// Engagement counters for an email campaign
+    Campaign {
+        #[command(flatten)]
+        redis: RedisArgs,
+        #[arg(long)]
+        tenant: String,
+        #[arg(long)]
+        campaign: String,
+    },
+    /// Last activity timestamp for a user
+    Activity {
+        #[command(flatten)]
+        redis: RedisArgs,
+        #[arg(long)]
+        tenant: String,
+        #[arg(long)]
+        user: String,
+    },
+}
+
+#[tokio::main]
+async fn main() -> CliResult {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Command::Module(ModuleCommand::Validate { file, runtime_url }) => commands::module::validate(&file, &runtime_url).await,
+        Command::Module(ModuleCommand::Upload { file, name, module_dir, registry_url, runtime_url }) => match (registry_url, module_dir) {
+            (Some(registry_url), _) => {
+                commands::module::upload_to_registry(&file, name.as_deref(), &registry_url, &runtime_url).await
+            }
+            (None, Some(module_dir)) => commands::module::upload(&file, name.as_deref(), &module_dir, &runtime_url).await,
+            (None, None) => Err("either --registry-url or --module-dir is required".into()),
+        },
+        Command::Module(ModuleCommand::Exec { module_path, function_name, params, timeout_seconds, runtime_url }) => {
+            commands::module::exec(&runtime_url, &module_path, &function_name, &params, timeout_seconds).await
+        }
+        Command::Events(EventsCommand::Tail { kafka, filter, topic, from_beginning }) => {
+            commands::events::tail(&kafka.brokers, &topic, &filter.into(), from_beginning).await
+        }
+        Command::Events(EventsCommand::Replay {
+            kafka,
+            filter,
+            from,
+            to,
+            since,
+            until,
+            limit,
+            idle_timeout_secs,
+            dry_run,
+        }) => {
+            let filter = commands::events::Filter { since, until, ..filter.into() };
+            commands::events::replay(&kafka.brokers, &from, &to, &filter, limit, idle_timeout_secs, dry_run).await
+        }
+        Command::Dlq(DlqCommand::Inspect { kafka, topic, tenant, limit, idle_timeout_secs }) => {
+            commands::dlq::inspect(&kafka.brokers, &topic, tenant.as_deref(), limit, idle_timeout_secs).await
+        }
+        Command::Metrics(MetricsCommand::Counters { redis, tenant }) => {
+            commands::metrics::counters(&redis.redis_url, &tenant).await
+        }
+        Command::Metrics(MetricsCommand::Campaign { redis, tenant, campaign }) => {
+            commands::metrics::campaign(&redis.redis_url, &tenant, &campaign).await
+        }
+        Command::Metrics(MetricsCommand::Activity { redis, tenant, user }) => {
+            commands::metrics::activity(&redis.redis_url, &tenant, &user).await
+        }
+    }
+}
+
+impl From<EventFilter> for commands::events::Filter {
+    fn from(filter: EventFilter) -> Self {
+        commands::events::Filter {
+            tenant: filter.tenant,
+            event_type: filter.event_type,
+            since: None,
+            until: None,
+        }
+    }
+}
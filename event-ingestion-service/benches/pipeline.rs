@@ -0,0 +1,83 @@
+//! `cargo bench -p event-ingestion-service --bench pipeline`; see
+//! bench-baseline.sh for comparing against a saved baseline.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use event_ingestion_service::processors::event_processor::{ClickHouseEvent, ProcessedEvent};
+use event_ingestion_service::processors::snapshot::BufferSnapshot;
+use event_ingestion_service::sinks::delta_sink::write_parquet;
+use event_ingestion_service::transformers::data_transformer::DataTransformer;
+use event_ingestion_service::CrmEvent;
+use serde_json::json;
+
+fn sample_events() -> Vec<CrmEvent> {
+    let event = |event_type: &str, payload: serde_json::Value| {
+        CrmEvent::builder("tenant-1", event_type)
+            .payload(payload)
+            .user_id("user-42")
+            .source("crm")
+            .timestamp(1_700_000_000)
+            .build()
+    };
+    vec![
+        event("lead_created", json!({ "source": "webinar", "score": 72.5, "campaign_id": "c-9", "company": "Acme" })),
+        event("deal_updated", json!({ "deal_id": "d-1", "stage": "negotiation", "amount": 48000.0, "probability": 60.0 })),
+        event("page_view", json!({ "page_url": "https://example.com/pricing?plan=pro", "referrer": "https://google.com", "session_duration": 93.0 })),
+        event("email_sent", json!({ "campaign_id": "c-9", "template_id": "welcome", "recipient_count": 1200 })),
+        event("custom_event", json!({ "a": 1, "b": "two", "c": [1, 2, 3], "d": { "nested": true } })),
+    ]
+}
+
+fn transform_event(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let transformer = DataTransformer::new();
+    let mut group = c.benchmark_group("transform_event");
+    for event in sample_events() {
+        group.bench_with_input(BenchmarkId::from_parameter(&event.event_type), &event, |b, event| {
+            b.to_async(&runtime).iter(|| async { transformer.transform_event(event.clone()).await.unwrap() })
+        });
+    }
+    group.finish();
+}
+
+fn processed_batch(size: usize) -> Vec<ProcessedEvent> {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let transformer = DataTransformer::new();
+    let samples = sample_events();
+    (0..size)
+        .map(|i| runtime.block_on(transformer.transform_event(samples[i % samples.len()].clone())).unwrap())
+        .collect()
+}
+
+fn batch_serialization(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batch");
+    for size in [100, 1000] {
+        let events = processed_batch(size);
+        group.throughput(Throughput::Elements(size as u64));
+
+        group.bench_with_input(BenchmarkId::new("clickhouse_rows", size), &events, |b, events| {
+            b.iter(|| {
+                events
+                    .iter()
+                    .cloned()
+                    .map(ClickHouseEvent::try_from)
+                    .collect::<Result<Vec<_>, _>>()
+                    .unwrap()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("parquet", size), &events, |b, events| {
+            b.iter(|| write_parquet(events).unwrap())
+        });
+
+        let snapshot = BufferSnapshot {
+            events,
+            windows: Vec::new(),
+        };
+        group.bench_with_input(BenchmarkId::new("snapshot_json", size), &snapshot, |b, snapshot| {
+            b.iter(|| serde_json::to_vec(snapshot).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, transform_event, batch_serialization);
+criterion_main!(benches);
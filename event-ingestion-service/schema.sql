@@ -9,12 +9,36 @@ CREATE TABLE IF NOT EXISTS events (
     event_type String,
     user_id String,
     timestamp Int64,
+    ingested_at Int64,
+    retention_days UInt16,
     properties String,
     metrics String,
     date Date DEFAULT toDate(timestamp)
 ) ENGINE = MergeTree()
 PARTITION BY toYYYYMM(toDate(timestamp))
 ORDER BY (tenant_id, event_type, timestamp)
+TTL toDate(timestamp) + toIntervalDay(if(retention_days = 0, 365, retention_days))
+SETTINGS index_granularity = 8192;
+
+-- Dedicated table for page_view events (routed via TABLE_ROUTES).
+-- Inserts use the generic event row; typed columns are derived on write.
+CREATE TABLE IF NOT EXISTS page_views (
+    tenant_id String,
+    event_type String,
+    user_id String,
+    timestamp Int64,
+    ingested_at Int64,
+    retention_days UInt16,
+    properties String,
+    metrics String,
+    page_url String MATERIALIZED JSONExtractString(properties, 'page_url'),
+    referrer String MATERIALIZED JSONExtractString(properties, 'referrer'),
+    session_duration Float64 MATERIALIZED JSONExtractFloat(metrics, 'session_duration'),
+    date Date DEFAULT toDate(timestamp)
+) ENGINE = MergeTree()
+PARTITION BY toYYYYMM(toDate(timestamp))
+ORDER BY (tenant_id, page_url, timestamp)
+TTL toDate(timestamp) + toIntervalDay(if(retention_days = 0, 365, retention_days))
 SETTINGS index_granularity = 8192;
 
 -- Materialized view for real-time aggregations
@@ -47,4 +71,137 @@ CREATE TABLE IF NOT EXISTS daily_stats (
     unique_users UInt64,
     avg_events_per_user Float64
 ) ENGINE = ReplacingMergeTree()
-ORDER BY (tenant_id, date);
\ No newline at end of file
+ORDER BY (tenant_id, date);
+
+-- Campaign-level email engagement rollup
+CREATE TABLE IF NOT EXISTS campaign_performance (
+    tenant_id String,
+    campaign_id String,
+    date Date,
+    emails_sent UInt64,
+    emails_opened UInt64,
+    emails_clicked UInt64,
+    emails_bounced UInt64,
+    emails_unsubscribed UInt64
+) ENGINE = SummingMergeTree()
+ORDER BY (tenant_id, campaign_id, date);
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS campaign_performance_mv
+TO campaign_performance
+AS SELECT
+    tenant_id,
+    JSONExtractString(properties, 'campaign_id') as campaign_id,
+    toDate(timestamp) as date,
+    countIf(event_type = 'email_sent') as emails_sent,
+    countIf(event_type = 'email_opened') as emails_opened,
+    countIf(event_type = 'email_clicked') as emails_clicked,
+    countIf(event_type = 'email_bounced') as emails_bounced,
+    countIf(event_type = 'email_unsubscribed') as emails_unsubscribed
+FROM events
+WHERE event_type LIKE 'email_%' AND campaign_id != ''
+GROUP BY tenant_id, campaign_id, date;
+
+-- Monthly revenue rollup from deal_won events, and deal_updated events moving
+-- a deal into closed_won
+CREATE TABLE IF NOT EXISTS revenue_monthly (
+    tenant_id String,
+    month Date,
+    deals_won UInt64,
+    new_mrr Float64,
+    new_arr Float64,
+    one_time_revenue Float64
+) ENGINE = SummingMergeTree()
+ORDER BY (tenant_id, month);
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS revenue_monthly_mv
+TO revenue_monthly
+AS SELECT
+    tenant_id,
+    toStartOfMonth(toDate(timestamp)) as month,
+    count() as deals_won,
+    sum(JSONExtractFloat(metrics, 'mrr')) as new_mrr,
+    sum(JSONExtractFloat(metrics, 'arr')) as new_arr,
+    sum(JSONExtractFloat(metrics, 'one_time_revenue')) as one_time_revenue
+FROM events
+WHERE JSONHas(metrics, 'deals_won')
+GROUP BY tenant_id, month;
+
+-- anonymous_id -> user_id mappings recorded from identify events; the latest
+-- identify for an anonymous id wins
+CREATE TABLE IF NOT EXISTS identity_map (
+    tenant_id String,
+    anonymous_id String,
+    user_id String,
+    identified_at Int64
+) ENGINE = ReplacingMergeTree(identified_at)
+ORDER BY (tenant_id, anonymous_id);
+
+-- Page views with anonymous visits attributed to the user they later
+-- identified as, including views recorded before the identify arrived
+CREATE VIEW IF NOT EXISTS page_views_resolved AS
+SELECT
+    p.*,
+    if(p.user_id != '', p.user_id, ids.user_id) AS resolved_user_id
+FROM page_views AS p
+LEFT JOIN (
+    SELECT tenant_id, anonymous_id, argMax(user_id, identified_at) AS user_id
+    FROM identity_map
+    GROUP BY tenant_id, anonymous_id
+) AS ids
+ON p.tenant_id = ids.tenant_id
+AND JSONExtractString(p.properties, 'anonymous_id') = ids.anonymous_id;
+
+-- Per-contact daily activity for the scoring service. The contact is the
+-- event's contact_id property, falling back to its user_id
+CREATE TABLE IF NOT EXISTS contact_activity_daily (
+    tenant_id String,
+    contact_id String,
+    date Date,
+    interactions UInt64,
+    engagements UInt64,
+    revenue Float64
+) ENGINE = SummingMergeTree()
+ORDER BY (tenant_id, contact_id, date);
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS contact_activity_daily_mv
+TO contact_activity_daily
+AS SELECT
+    tenant_id,
+    if(JSONExtractString(properties, 'contact_id') != '', JSONExtractString(properties, 'contact_id'), user_id) AS contact_id,
+    toDate(timestamp) AS date,
+    count() AS interactions,
+    countIf(event_type IN ('email_opened', 'email_clicked', 'meeting_completed', 'call_logged')) AS engagements,
+    sum(JSONExtractFloat(metrics, 'contract_value')) AS revenue
+FROM events
+WHERE contact_id != '' AND event_type != 'score_updated'
+GROUP BY tenant_id, contact_id, date;
+
+-- Page views are routed to their own table, so they feed the rollup separately
+CREATE MATERIALIZED VIEW IF NOT EXISTS contact_activity_page_views_mv
+TO contact_activity_daily
+AS SELECT
+    tenant_id,
+    if(JSONExtractString(properties, 'contact_id') != '', JSONExtractString(properties, 'contact_id'), user_id) AS contact_id,
+    toDate(timestamp) AS date,
+    count() AS interactions,
+    count() AS engagements,
+    toFloat64(0) AS revenue
+FROM page_views
+WHERE contact_id != ''
+GROUP BY tenant_id, contact_id, date;
+
+-- Signed admin and configuration changes from every service's audit trail
+-- (crm-audit), kept without a TTL as compliance evidence
+CREATE TABLE IF NOT EXISTS admin_audit (
+    id String,
+    timestamp Int64,
+    service String,
+    actor String,
+    action String,
+    resource String,
+    tenant_id Nullable(String),
+    details String,
+    signature String
+) ENGINE = ReplacingMergeTree()
+PARTITION BY toYYYYMM(toDate(intDiv(timestamp, 1000)))
+ORDER BY (timestamp, id);
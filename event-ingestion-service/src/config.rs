@@ -1,49 +1,219 @@
-use std::env;
+use crm_config::{ConfigLoader, Configurable};
+use rdkafka::ClientConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub kafka_brokers: String,
     pub kafka_group_id: String,
     pub kafka_topics: Vec<String>,
+    /// SASL credentials; when set, Kafka is reached over SASL_SSL.
+    pub kafka_username: Option<String>,
+    pub kafka_password: Option<String>,
+    pub kafka_sasl_mechanism: String,
     pub clickhouse_url: String,
     pub clickhouse_user: String,
     pub clickhouse_password: String,
     pub clickhouse_database: String,
+    pub table_routes: HashMap<String, String>,
     pub redis_url: String,
     pub batch_size: usize,
     pub flush_interval_ms: u64,
+    /// Adaptive batching bounds; each defaults to the fixed value above.
+    pub batch_size_min: Option<usize>,
+    pub batch_size_max: Option<usize>,
+    pub flush_interval_min_ms: Option<u64>,
+    pub flush_interval_max_ms: Option<u64>,
+    pub flush_latency_target_ms: u64,
+    pub aggregation_window_secs: u64,
+    pub aggregation_grace_secs: u64,
+    pub metrics_out_topic: String,
+    pub ordering_check_enabled: bool,
+    pub debug_trace_enabled: bool,
+    pub debug_sample_rate: f64,
+    pub debug_topic: String,
+    pub default_tenant_plan: String,
+    pub free_tier_sample_rate: f64,
+    pub free_tier_retention_days: u16,
+    pub paid_tier_retention_days: u16,
+    pub plan_cache_ttl_secs: u64,
+    pub feature_flag_cache_ttl_secs: u64,
+    pub identity_cache_ttl_secs: u64,
+    pub snapshot_path: Option<String>,
+    pub delta_table_url: Option<String>,
+    pub delta_commit_interval_secs: u64,
+    /// Events the delta sink holds before ingestion waits for a commit.
+    pub delta_max_buffered_events: usize,
+    pub metrics_port: u16,
+    pub mqtt_broker: Option<String>,
+    pub mqtt_client_id: String,
+    pub mqtt_topics: Vec<String>,
+    pub mqtt_username: Option<String>,
+    pub mqtt_password: Option<String>,
+    /// Event properties encrypted per tenant before they are stored, e.g.
+    /// `email`, `phone`. Needs `ENCRYPTION_PROVIDER` to be set.
+    pub sensitive_properties: Vec<String>,
+    /// Next-generation table written alongside each listed table during a
+    /// schema migration, e.g. `events: events_v2`.
+    pub dual_write_tables: HashMap<String, String>,
+    /// Unix seconds at which dual writes stop; unset keeps them going until
+    /// the table is removed from `dual_write_tables`.
+    pub dual_write_until: Option<i64>,
+    /// Length of the `ingested_at` windows the two tables are compared over.
+    pub dual_write_compare_interval_secs: u64,
+    /// Postgres holding the event contract registry; unset disables
+    /// contract validation and the `/contracts` API.
+    pub contracts_database_url: Option<String>,
+    /// `warn` counts and logs payloads that violate their event type's
+    /// contract; `reject` also drops them.
+    pub contract_enforcement: String,
+    /// How often proposed contracts are reviewed and accepted ones reloaded.
+    pub contract_refresh_interval_secs: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            kafka_brokers: "localhost:9092".to_string(),
+            kafka_group_id: "event-ingestion-group".to_string(),
+            kafka_topics: vec![
+                "crm-events".to_string(),
+                "user-events".to_string(),
+                "analytics-events".to_string(),
+            ],
+            kafka_username: None,
+            kafka_password: None,
+            kafka_sasl_mechanism: "SCRAM-SHA-512".to_string(),
+            clickhouse_url: "http://localhost:8123".to_string(),
+            clickhouse_user: "default".to_string(),
+            clickhouse_password: "".to_string(),
+            clickhouse_database: "crm_analytics".to_string(),
+            table_routes: HashMap::from([("page_view".to_string(), "page_views".to_string())]),
+            redis_url: "redis://localhost:6379".to_string(),
+            batch_size: 1000,
+            flush_interval_ms: 5000,
+            batch_size_min: None,
+            batch_size_max: None,
+            flush_interval_min_ms: None,
+            flush_interval_max_ms: None,
+            flush_latency_target_ms: 1000,
+            aggregation_window_secs: 60,
+            aggregation_grace_secs: 10,
+            metrics_out_topic: "metrics-out".to_string(),
+            ordering_check_enabled: false,
+            debug_trace_enabled: false,
+            debug_sample_rate: 0.0,
+            debug_topic: "pipeline-debug".to_string(),
+            default_tenant_plan: "pro".to_string(),
+            free_tier_sample_rate: 0.1,
+            free_tier_retention_days: 30,
+            paid_tier_retention_days: 365,
+            plan_cache_ttl_secs: 300,
+            feature_flag_cache_ttl_secs: 30,
+            identity_cache_ttl_secs: 300,
+            snapshot_path: None,
+            delta_table_url: None,
+            delta_commit_interval_secs: 60,
+            delta_max_buffered_events: 100_000,
+            metrics_port: 8080,
+            mqtt_broker: None,
+            mqtt_client_id: "event-ingestion-service".to_string(),
+            mqtt_topics: vec!["crm/+/+".to_string()],
+            mqtt_username: None,
+            mqtt_password: None,
+            sensitive_properties: Vec::new(),
+            dual_write_tables: HashMap::new(),
+            dual_write_until: None,
+            dual_write_compare_interval_secs: 300,
+            contracts_database_url: None,
+            contract_enforcement: "warn".to_string(),
+            contract_refresh_interval_secs: 30,
+        }
+    }
+}
+
+impl Configurable for Config {
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+
+        if self.kafka_topics.is_empty() {
+            problems.push("kafka_topics must not be empty".to_string());
+        }
+        if self.batch_size == 0 {
+            problems.push("batch_size must be positive".to_string());
+        }
+        if self.flush_interval_ms == 0 {
+            problems.push("flush_interval_ms must be positive".to_string());
+        }
+        if let (Some(min), Some(max)) = (self.batch_size_min, self.batch_size_max) {
+            if min > max {
+                problems.push("batch_size_min must not exceed batch_size_max".to_string());
+            }
+        }
+        for (name, rate) in [
+            ("debug_sample_rate", self.debug_sample_rate),
+            ("free_tier_sample_rate", self.free_tier_sample_rate),
+        ] {
+            if !(0.0..=1.0).contains(&rate) {
+                problems.push(format!("{} must be between 0 and 1", name));
+            }
+        }
+        if self.kafka_username.is_some() != self.kafka_password.is_some() {
+            problems.push("kafka_username and kafka_password must be set together".to_string());
+        }
+        if self.delta_max_buffered_events == 0 {
+            problems.push("delta_max_buffered_events must be positive".to_string());
+        }
+        if self.aggregation_window_secs == 0 {
+            problems.push("aggregation_window_secs must be positive".to_string());
+        }
+        for (table, next) in &self.dual_write_tables {
+            if table == next {
+                problems.push(format!("dual_write_tables must name a different table for {}", table));
+            }
+        }
+        if self.dual_write_compare_interval_secs == 0 {
+            problems.push("dual_write_compare_interval_secs must be positive".to_string());
+        }
+        if !["warn", "reject"].contains(&self.contract_enforcement.as_str()) {
+            problems.push("contract_enforcement must be warn or reject".to_string());
+        }
+        if self.contract_refresh_interval_secs == 0 {
+            problems.push("contract_refresh_interval_secs must be positive".to_string());
+        }
+
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
 }
 
 impl Config {
+    /// Defaults, then the file named by `CONFIG_FILE`, then environment
+    /// variables named after the fields (e.g. `BATCH_SIZE`).
     pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
-        Ok(Config {
-            kafka_brokers: env::var("KAFKA_BROKERS")
-                .unwrap_or_else(|_| "localhost:9092".to_string()),
-            kafka_group_id: env::var("KAFKA_GROUP_ID")
-                .unwrap_or_else(|_| "event-ingestion-group".to_string()),
-            kafka_topics: env::var("KAFKA_TOPICS")
-                .unwrap_or_else(|_| "crm-events,user-events,analytics-events".to_string())
-                .split(',')
-                .map(|s| s.trim().to_string())
-                .collect(),
-            clickhouse_url: env::var("CLICKHOUSE_URL")
-                .unwrap_or_else(|_| "http://localhost:8123".to_string()),
-            clickhouse_user: env::var("CLICKHOUSE_USER")
-                .unwrap_or_else(|_| "default".to_string()),
-            clickhouse_password: env::var("CLICKHOUSE_PASSWORD")
-                .unwrap_or_else(|_| "".to_string()),
-            clickhouse_database: env::var("CLICKHOUSE_DATABASE")
-                .unwrap_or_else(|_| "crm_analytics".to_string()),
-            redis_url: env::var("REDIS_URL")
-                .unwrap_or_else(|_| "redis://localhost:6379".to_string()),
-            batch_size: env::var("BATCH_SIZE")
-                .unwrap_or_else(|_| "1000".to_string())
-                .parse()
-                .unwrap_or(1000),
-            flush_interval_ms: env::var("FLUSH_INTERVAL_MS")
-                .unwrap_or_else(|_| "5000".to_string())
-                .parse()
-                .unwrap_or(5000),
-        })
+        Ok(ConfigLoader::new().file_from_env("CONFIG_FILE").load()?)
+    }
+
+    /// Kafka client settings shared by the consumer and every producer.
+    pub fn kafka_client_config(&self) -> ClientConfig {
+        let mut client = ClientConfig::new();
+        client.set("bootstrap.servers", &self.kafka_brokers);
+        if let (Some(username), Some(password)) = (&self.kafka_username, &self.kafka_password) {
+            client
+                .set("security.protocol", "SASL_SSL")
+                .set("sasl.mechanism", &self.kafka_sasl_mechanism)
+                .set("sasl.username", username)
+                .set("sasl.password", password);
+        }
+        client
     }
-}
\ No newline at end of file
+
+    pub fn clickhouse_client(&self) -> clickhouse::Client {
+        clickhouse::Client::default()
+            .with_url(&self.clickhouse_url)
+            .with_user(&self.clickhouse_user)
+            .with_password(&self.clickhouse_password)
+            .with_database(&self.clickhouse_database)
+    }
+}
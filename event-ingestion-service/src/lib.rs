@@ -0,0 +1,11 @@
+//! The ingestion pipeline. `main.rs` wires it to Kafka and MQTT; it is a
+//! library so the benches under `benches/` can drive the hot paths directly.
+
+pub mod config;
+pub mod metrics;
+pub mod processors;
+pub mod sinks;
+pub mod sources;
+pub mod transformers;
+
+pub use crm_events::CrmEvent;
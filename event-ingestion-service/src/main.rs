@@ -1,37 +1,114 @@
 use rdkafka::consumer::{Consumer, StreamConsumer};
-use rdkafka::{ClientConfig, Message};
-use serde::{Deserialize, Serialize};
+use rdkafka::message::Headers;
+use crm_proto::prost::Message as _;
+use crm_secrets::{EncryptionConfig, EnvelopeCipher, SecretStore};
+use crm_startup::{retry, Dependency, Health, StartupConfig};
+use rdkafka::Message;
+use std::sync::Arc;
 use tracing::{info, error, warn};
+use warp::Filter;
 
-mod config;
-mod processors;
-mod transformers;
-
-use config::Config;
-use processors::event_processor::EventProcessor;
-
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct CrmEvent {
-    pub tenant_id: String,
-    pub event_type: String,
-    pub payload: serde_json::Value,
-    pub timestamp: i64,
-    pub source: Option<String>,
-    pub user_id: Option<String>,
-}
+use crm_audit::{AuditConfig, AuditTrail};
+use event_ingestion_service::config::Config;
+use event_ingestion_service::metrics;
+use event_ingestion_service::processors::audit_store::{self, AuditStore};
+use event_ingestion_service::processors::contract_validator::{self, ContractValidator};
+use event_ingestion_service::processors::event_processor::EventProcessor;
+use event_ingestion_service::sources::mqtt_source::MqttSource;
+use event_ingestion_service::CrmEvent;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize tracing
-    tracing_subscriber::fmt::init();
+    // Logs, traces and panic capture
+    let _observability = crm_observability::init("event-ingestion-service")?;
     
     info!("Starting Event Ingestion Service");
     
-    // Load configuration
-    let config = Config::from_env()?;
+    // Load configuration, resolving secret:// references and watching them
+    // for rotation. The secrets backend may still be coming up with us.
+    let startup = StartupConfig::from_env()?;
+    let secrets = Arc::new(SecretStore::from_env()?);
+    let template = Config::from_env()?;
+    let mut config_rx = retry("secrets backend", &startup.retry_policy(), || secrets.watch(template.clone())).await?;
+    let config = config_rx.borrow_and_update().clone();
+    info!("Configuration: {}", crm_config::redacted(&config));
+
+    // Nothing is consumed until ClickHouse, Redis and Kafka answer
+    let health = Health::new(
+        "event-ingestion-service",
+        &startup,
+        vec![
+            Dependency::http("clickhouse", &format!("{}/ping", config.clickhouse_url.trim_end_matches('/'))),
+            Dependency::redis("redis", &config.redis_url),
+            Dependency::kafka("kafka", &config.kafka_brokers),
+        ],
+    );
     
+    // Event contracts, when a registry is configured
+    let contracts = ContractValidator::new(&config);
+    if let Some(contracts) = &contracts {
+        Arc::clone(contracts).start_refresh_task();
+    }
+
+    // The admin audit trail: contract proposals are recorded on it, and
+    // every service's events are stored and served from /audit
+    let audit_config = secrets.resolve(&AuditConfig::from_env()?).await?;
+    let audit = Arc::new(AuditTrail::new("event-ingestion-service", &audit_config)?);
+    let audit_store = AuditStore::new(config.clickhouse_client(), audit_config);
+
+    // Expose Prometheus metrics and /healthz while waiting, the /contracts
+    // and /audit APIs (and /admin/chaos in chaos builds), behind mTLS when
+    // configured
+    metrics::register();
+    let routes = crm_observability::metrics_route()
+        .or(health.route())
+        .or(contract_validator::routes(contracts.clone(), Arc::clone(&audit)))
+        .or(audit_store::routes(audit_store.clone()))
+        .or(crm_chaos::routes());
+    match crm_tls::MtlsContext::from_env()? {
+        Some(tls) => {
+            tokio::spawn(crm_tls::serve(([0, 0, 0, 0], config.metrics_port), tls.server_config(), warp::service(routes)));
+        }
+        None => {
+            info!("Metrics server running on http://0.0.0.0:{}/metrics", config.metrics_port);
+            tokio::spawn(warp::serve(routes).run(([0, 0, 0, 0], config.metrics_port)));
+        }
+    }
+    health.start().await?;
+
+    if let Some(audit_store) = audit_store {
+        let group_id = config.kafka_group_id.clone();
+        tokio::spawn(async move {
+            if let Err(e) = audit_store.run_consumer(&group_id).await {
+                error!("Audit consumer stopped: {}", e);
+            }
+        });
+    }
+
+    // Per-tenant encryption of sensitive properties; refuse to store them in
+    // the clear when no key service is configured
+    let encryption = secrets.resolve(&EncryptionConfig::from_env()?).await?;
+    let cipher = EnvelopeCipher::from_config(&encryption)?.map(Arc::new);
+    if cipher.is_none() && !config.sensitive_properties.is_empty() {
+        return Err("sensitive_properties is set but ENCRYPTION_PROVIDER is none".into());
+    }
+    if cipher.is_some() {
+        info!("Encrypting properties {:?} with {} tenant keys", config.sensitive_properties, encryption.provider);
+    }
+
     // Initialize event processor
-    let processor = EventProcessor::new(&config).await?;
+    let processor = Arc::new(
+        retry("event processor", &startup.retry_policy(), || {
+            EventProcessor::new(&config, cipher.clone(), contracts.clone())
+        })
+        .await?,
+    );
+
+    // Start MQTT source for device/telephony integrations
+    if let Some(broker) = &config.mqtt_broker {
+        let mqtt_source = MqttSource::new(&config, broker)?;
+        tokio::spawn(mqtt_source.run(Arc::clone(&processor)));
+    }
     
     // Create Kafka consumer
     let consumer = create_consumer(&config)?;
@@ -40,26 +117,62 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     info!("Connected to Kafka, starting message processing...");
     
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
     // Process messages
     loop {
-        match consumer.recv().await {
-            Ok(message) => {
-                if let Err(e) = process_message(&processor, message).await {
-                    error!("Error processing message: {}", e);
+        // Injected disconnects look like a failed receive
+        if let Err(e) = crm_chaos::inject(crm_chaos::KAFKA).await {
+            error!("Error receiving message: {}", e);
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+            continue;
+        }
+        tokio::select! {
+            received = consumer.recv() => match received {
+                Ok(message) => {
+                    if let Err(e) = process_message(&processor, message).await {
+                        error!("Error processing message: {}", e);
+                    }
                 }
+                Err(e) => {
+                    error!("Error receiving message: {}", e);
+                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                }
+            },
+            _ = &mut shutdown => {
+                info!("Shutdown signal received, saving in-flight state");
+                break;
             }
-            Err(e) => {
-                error!("Error receiving message: {}", e);
-                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+            Ok(()) = config_rx.changed() => {
+                // Clients hold their credentials for life, so hand over to a
+                // fresh process rather than rebuilding them in place
+                warn!("Credentials rotated, saving in-flight state and restarting");
+                processor.shutdown().await?;
+                return Err("credentials rotated".into());
             }
         }
     }
+
+    processor.shutdown().await?;
+
+    Ok(())
+}
+
+async fn shutdown_signal() {
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .expect("failed to install SIGTERM handler");
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
 }
 
 fn create_consumer(config: &Config) -> Result<StreamConsumer, Box<dyn std::error::Error>> {
-    let consumer: StreamConsumer = ClientConfig::new()
+    let consumer: StreamConsumer = config
+        .kafka_client_config()
         .set("group.id", &config.kafka_group_id)
-        .set("bootstrap.servers", &config.kafka_brokers)
         .set("enable.partition.eof", "false")
         .set("session.timeout.ms", "6000")
         .set("enable.auto.commit", "true")
@@ -81,13 +194,33 @@ async fn process_message(
         }
     };
     
-    // Parse the event
-    let event: CrmEvent = serde_json::from_slice(payload)?;
+    // Parse the event; JSON unless the producer says it sent protobuf
+    let protobuf = message
+        .headers()
+        .map(|headers| {
+            headers.iter().any(|header| {
+                header.key == "content-type" && header.value == Some(crm_proto::PROTOBUF_CONTENT_TYPE.as_bytes())
+            })
+        })
+        .unwrap_or(false);
+    let event: CrmEvent = if protobuf {
+        crm_proto::v1::CrmEvent::decode(payload)?.try_into()?
+    } else {
+        serde_json::from_slice(payload)?
+    };
     
     info!("Processing event: {} for tenant: {}", event.event_type, event.tenant_id);
+
+    processor.check_ordering(&event, message.partition()).await;
     
+    // The x-debug header forces a pipeline trace for this event
+    let force_debug = message
+        .headers()
+        .map(|headers| headers.iter().any(|header| header.key == "x-debug"))
+        .unwrap_or(false);
+
     // Process the event
-    processor.process_event(event).await?;
+    processor.process_event_traced(event, force_debug).await?;
     
     Ok(())
 }
\ No newline at end of file
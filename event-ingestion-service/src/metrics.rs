@@ -0,0 +1,148 @@
+use crate::processors::event_processor::ProcessedEvent;
+use prometheus::{Histogram, IntCounterVec, IntGauge, IntGaugeVec};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+static EVENT_TIME_SKEW: OnceLock<Histogram> = OnceLock::new();
+static FLUSH_LATENCY: OnceLock<Histogram> = OnceLock::new();
+static BATCH_SIZE: OnceLock<IntGauge> = OnceLock::new();
+static ORDERING_VIOLATIONS: OnceLock<IntCounterVec> = OnceLock::new();
+static PARTITION_MISMATCHES: OnceLock<IntCounterVec> = OnceLock::new();
+static DUAL_WRITE_ROWS: OnceLock<IntGaugeVec> = OnceLock::new();
+static DUAL_WRITE_WINDOWS: OnceLock<IntCounterVec> = OnceLock::new();
+static DUAL_WRITE_FAILURES: OnceLock<IntCounterVec> = OnceLock::new();
+static CONTRACT_VIOLATIONS: OnceLock<IntCounterVec> = OnceLock::new();
+static AUDIT_EVENTS: OnceLock<IntCounterVec> = OnceLock::new();
+
+pub fn register() {
+    EVENT_TIME_SKEW.get_or_init(|| {
+        prometheus::register_histogram!(
+            "event_ingestion_skew_seconds",
+            "Delay between the producer timestamp and ingestion time in seconds",
+            vec![0.0, 1.0, 5.0, 15.0, 30.0, 60.0, 300.0, 900.0, 3600.0, 86400.0]
+        ).unwrap()
+    });
+    FLUSH_LATENCY.get_or_init(|| {
+        prometheus::register_histogram!(
+            "event_ingestion_flush_duration_seconds",
+            "Duration of ClickHouse batch flushes in seconds",
+            vec![0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]
+        ).unwrap()
+    });
+    BATCH_SIZE.get_or_init(|| {
+        prometheus::register_int_gauge!(
+            "event_ingestion_batch_size",
+            "Current target batch size chosen by the adaptive tuner"
+        ).unwrap()
+    });
+    ORDERING_VIOLATIONS.get_or_init(|| {
+        prometheus::register_int_counter_vec!(
+            "event_ingestion_ordering_violations_total",
+            "Events that arrived with a lower sequence than the previous event for the same key",
+            &["tenant_id"]
+        ).unwrap()
+    });
+    PARTITION_MISMATCHES.get_or_init(|| {
+        prometheus::register_int_counter_vec!(
+            "event_ingestion_partition_mismatches_total",
+            "Events whose (tenant, user_id) key was previously seen on a different partition",
+            &["tenant_id"]
+        ).unwrap()
+    });
+    DUAL_WRITE_ROWS.get_or_init(|| {
+        prometheus::register_int_gauge_vec!(
+            "event_ingestion_dual_write_rows",
+            "Rows in the last compared dual-write window, by table and generation (current or next)",
+            &["table", "generation"]
+        ).unwrap()
+    });
+    DUAL_WRITE_WINDOWS.get_or_init(|| {
+        prometheus::register_int_counter_vec!(
+            "event_ingestion_dual_write_windows_total",
+            "Dual-write windows compared, by table and result (match or mismatch)",
+            &["table", "result"]
+        ).unwrap()
+    });
+    DUAL_WRITE_FAILURES.get_or_init(|| {
+        prometheus::register_int_counter_vec!(
+            "event_ingestion_dual_write_failures_total",
+            "Batches that could not be written to the next-generation table",
+            &["table"]
+        ).unwrap()
+    });
+    CONTRACT_VIOLATIONS.get_or_init(|| {
+        prometheus::register_int_counter_vec!(
+            "event_ingestion_contract_violations_total",
+            "Payloads that violate the accepted contract for their event type",
+            &["event_type"]
+        ).unwrap()
+    });
+    AUDIT_EVENTS.get_or_init(|| {
+        prometheus::register_int_counter_vec!(
+            "event_ingestion_audit_events_total",
+            "Audit events consumed, by result (stored, malformed or failed)",
+            &["result"]
+        ).unwrap()
+    });
+}
+
+/// Records ingested_at - timestamp. Negative skew (producer clock ahead) is clamped to zero.
+pub fn observe_skew(event: &ProcessedEvent) {
+    if let Some(histogram) = EVENT_TIME_SKEW.get() {
+        histogram.observe((event.ingested_at - event.timestamp).max(0) as f64);
+    }
+}
+
+pub fn observe_flush_latency(latency: Duration) {
+    if let Some(histogram) = FLUSH_LATENCY.get() {
+        histogram.observe(latency.as_secs_f64());
+    }
+}
+
+pub fn set_batch_size(batch_size: usize) {
+    if let Some(gauge) = BATCH_SIZE.get() {
+        gauge.set(batch_size as i64);
+    }
+}
+
+pub fn inc_ordering_violation(tenant_id: &str) {
+    if let Some(counter) = ORDERING_VIOLATIONS.get() {
+        counter.with_label_values(&[tenant_id]).inc();
+    }
+}
+
+pub fn inc_partition_mismatch(tenant_id: &str) {
+    if let Some(counter) = PARTITION_MISMATCHES.get() {
+        counter.with_label_values(&[tenant_id]).inc();
+    }
+}
+
+pub fn set_dual_write_rows(table: &str, generation: &str, rows: u64) {
+    if let Some(gauge) = DUAL_WRITE_ROWS.get() {
+        gauge.with_label_values(&[table, generation]).set(rows as i64);
+    }
+}
+
+pub fn inc_dual_write_window(table: &str, result: &str) {
+    if let Some(counter) = DUAL_WRITE_WINDOWS.get() {
+        counter.with_label_values(&[table, result]).inc();
+    }
+}
+
+pub fn inc_dual_write_failure(table: &str) {
+    if let Some(counter) = DUAL_WRITE_FAILURES.get() {
+        counter.with_label_values(&[table]).inc();
+    }
+}
+
+pub fn inc_contract_violation(event_type: &str) {
+    if let Some(counter) = CONTRACT_VIOLATIONS.get() {
+        counter.with_label_values(&[event_type]).inc();
+    }
+}
+
+pub fn inc_audit_event(result: &str) {
+    if let Some(counter) = AUDIT_EVENTS.get() {
+        counter.with_label_values(&[result]).inc();
+    }
+}
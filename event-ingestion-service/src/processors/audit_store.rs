@@ -0,0 +1,215 @@
+//! Storage for the admin audit trail.
+//!
+//! Every service publishes signed [`AuditEvent`]s to the audit topic through
+//! `crm_audit::AuditTrail`. This consumer writes them to the ClickHouse
+//! `admin_audit` table as they arrive, unchanged and whatever their
+//! signature, and `GET /audit` serves them back with each signature checked
+//! against the shared key, so tampering with the stored rows shows.
+
+use crate::metrics;
+use clickhouse::Client;
+use crm_audit::{AuditConfig, AuditEvent, SigningKey};
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::{ClientConfig, Message};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use warp::http::StatusCode;
+use warp::Filter;
+
+const AUDIT_TABLE: &str = "admin_audit";
+const DEFAULT_LIMIT: u64 = 100;
+const MAX_LIMIT: u64 = 1000;
+
+#[derive(Debug, Serialize, Deserialize, clickhouse::Row)]
+struct AuditRow {
+    id: String,
+    timestamp: i64,
+    service: String,
+    actor: String,
+    action: String,
+    resource: String,
+    tenant_id: Option<String>,
+    /// The event's details as JSON text.
+    details: String,
+    signature: String,
+}
+
+impl AuditRow {
+    fn from_event(event: &AuditEvent) -> Self {
+        AuditRow {
+            id: event.id.clone(),
+            timestamp: event.timestamp,
+            service: event.service.clone(),
+            actor: event.actor.clone(),
+            action: event.action.clone(),
+            resource: event.resource.clone(),
+            tenant_id: event.tenant_id.clone(),
+            details: event.details.to_string(),
+            signature: event.signature.clone(),
+        }
+    }
+
+    fn into_event(self) -> AuditEvent {
+        AuditEvent {
+            id: self.id,
+            timestamp: self.timestamp,
+            service: self.service,
+            actor: self.actor,
+            action: self.action,
+            resource: self.resource,
+            tenant_id: self.tenant_id,
+            details: serde_json::from_str(&self.details).unwrap_or(Value::String(self.details)),
+            signature: self.signature,
+        }
+    }
+}
+
+/// Filters for `GET /audit`; timestamps are Unix milliseconds.
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    pub tenant_id: Option<String>,
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub service: Option<String>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub limit: Option<u64>,
+}
+
+/// A stored event and whether its signature still matches it.
+#[derive(Debug, Serialize)]
+pub struct AuditEntry {
+    #[serde(flatten)]
+    pub event: AuditEvent,
+    pub verified: bool,
+}
+
+pub struct AuditStore {
+    client: Client,
+    key: SigningKey,
+    audit: AuditConfig,
+}
+
+impl AuditStore {
+    /// `None` while the audit trail is disabled (no signing key).
+    pub fn new(client: Client, audit: AuditConfig) -> Option<Arc<Self>> {
+        let key = audit.signing_key()?;
+        Some(Arc::new(AuditStore { client, key, audit }))
+    }
+
+    /// Consumes the audit topic into `admin_audit` until the consumer
+    /// fails. Offsets are committed only after a row is written, so a
+    /// restart stores anything that was in flight again; the table
+    /// collapses the duplicates by id.
+    pub async fn run_consumer(self: Arc<Self>, group_id: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", &self.audit.kafka_brokers)
+            .set("group.id", format!("{}-audit", group_id))
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "earliest")
+            .create()?;
+        consumer.subscribe(&[self.audit.topic.as_str()])?;
+        info!("Storing audit events from {}", self.audit.topic);
+
+        loop {
+            let message = consumer.recv().await?;
+            let Some(Ok(event)) = message.payload().map(serde_json::from_slice::<AuditEvent>) else {
+                warn!("Skipping malformed audit event at offset {}", message.offset());
+                metrics::inc_audit_event("malformed");
+                consumer.commit_message(&message, CommitMode::Async)?;
+                continue;
+            };
+
+            // Audit events must not be lost, so keep retrying the write
+            while let Err(e) = self.store(&event).await {
+                error!("Failed to store audit event {}: {}", event.id, e);
+                metrics::inc_audit_event("failed");
+                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+            }
+            metrics::inc_audit_event("stored");
+            consumer.commit_message(&message, CommitMode::Async)?;
+        }
+    }
+
+    async fn store(&self, event: &AuditEvent) -> Result<(), clickhouse::error::Error> {
+        let mut insert = self.client.insert(AUDIT_TABLE)?;
+        insert.write(&AuditRow::from_event(event)).await?;
+        insert.end().await
+    }
+
+    /// Matching events, newest first.
+    pub async fn query(&self, filter: &AuditQuery) -> Result<Vec<AuditEntry>, clickhouse::error::Error> {
+        let mut sql = format!(
+            "SELECT ?fields FROM {} FINAL WHERE timestamp >= ? AND timestamp < ?",
+            AUDIT_TABLE
+        );
+        let columns = [
+            ("tenant_id", &filter.tenant_id),
+            ("actor", &filter.actor),
+            ("action", &filter.action),
+            ("service", &filter.service),
+        ];
+        for (column, value) in &columns {
+            if value.is_some() {
+                sql.push_str(&format!(" AND {} = ?", column));
+            }
+        }
+        sql.push_str(" ORDER BY timestamp DESC LIMIT ?");
+
+        let mut query = self
+            .client
+            .query(&sql)
+            .bind(filter.since.unwrap_or(0))
+            .bind(filter.until.unwrap_or(i64::MAX));
+        for value in columns.iter().filter_map(|(_, value)| value.as_deref()) {
+            query = query.bind(value);
+        }
+        let rows = query
+            .bind(filter.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT))
+            .fetch_all::<AuditRow>()
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let event = row.into_event();
+                let verified = event.verify(&self.key);
+                if !verified {
+                    warn!("Audit event {} fails signature verification", event.id);
+                }
+                AuditEntry { event, verified }
+            })
+            .collect())
+    }
+}
+
+/// `GET /audit?tenant_id=&actor=&action=&service=&since=&until=&limit=`:
+/// stored audit events, newest first, each with `verified` telling whether
+/// its signature still matches. Not found while the trail is disabled.
+pub fn routes(
+    store: Option<Arc<AuditStore>>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("audit")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::any().map(move || store.clone()).and_then(require_store))
+        .and(warp::query::<AuditQuery>())
+        .then(|store: Arc<AuditStore>, filter: AuditQuery| async move {
+            match store.query(&filter).await {
+                Ok(entries) => warp::reply::with_status(warp::reply::json(&json!({ "events": entries })), StatusCode::OK),
+                Err(e) => {
+                    warn!("Audit query failed: {}", e);
+                    warp::reply::with_status(
+                        warp::reply::json(&json!({ "error": "could not query the audit trail" })),
+                        StatusCode::SERVICE_UNAVAILABLE,
+                    )
+                }
+            }
+        })
+}
+
+async fn require_store(store: Option<Arc<AuditStore>>) -> Result<Arc<AuditStore>, warp::Rejection> {
+    store.ok_or_else(warp::reject::not_found)
+}
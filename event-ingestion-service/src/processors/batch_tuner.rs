@@ -0,0 +1,95 @@
+use crate::config::Config;
+use crate::metrics;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::time::Duration;
+use tracing::{info, warn};
+
+const LATENCY_WINDOW: usize = 50;
+
+/// Adjusts batch size and flush interval within configured bounds to keep
+/// p99 flush latency under the target. With equal min/max bounds the batch
+/// size stays fixed.
+pub struct BatchTuner {
+    min_batch_size: usize,
+    max_batch_size: usize,
+    min_flush_interval_ms: u64,
+    max_flush_interval_ms: u64,
+    target_latency_ms: u64,
+    state: Mutex<TunerState>,
+}
+
+struct TunerState {
+    batch_size: usize,
+    latencies_ms: VecDeque<u64>,
+}
+
+impl BatchTuner {
+    pub fn new(config: &Config) -> Self {
+        let min_batch_size = config.batch_size_min.unwrap_or(config.batch_size).max(1);
+        let max_batch_size = config.batch_size_max.unwrap_or(config.batch_size).max(min_batch_size);
+        let min_flush_interval_ms = config.flush_interval_min_ms.unwrap_or(config.flush_interval_ms);
+        let max_flush_interval_ms = config.flush_interval_max_ms.unwrap_or(config.flush_interval_ms);
+
+        BatchTuner {
+            min_batch_size,
+            max_batch_size,
+            min_flush_interval_ms,
+            max_flush_interval_ms: max_flush_interval_ms.max(min_flush_interval_ms),
+            target_latency_ms: config.flush_latency_target_ms,
+            state: Mutex::new(TunerState {
+                batch_size: config.batch_size.clamp(min_batch_size, max_batch_size),
+                latencies_ms: VecDeque::with_capacity(LATENCY_WINDOW),
+            }),
+        }
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.state.lock().unwrap().batch_size
+    }
+
+    /// Larger batches are given proportionally more time to fill.
+    pub fn flush_interval(&self) -> Duration {
+        let batch_size = self.batch_size();
+        let span = (self.max_batch_size - self.min_batch_size) as u64;
+        let position = (batch_size - self.min_batch_size) as u64;
+        let interval_ms = ((self.max_flush_interval_ms - self.min_flush_interval_ms) * position)
+            .checked_div(span)
+            .map(|offset| self.min_flush_interval_ms + offset)
+            .unwrap_or(self.max_flush_interval_ms);
+        Duration::from_millis(interval_ms)
+    }
+
+    pub fn record_flush(&self, latency: Duration) {
+        let latency_ms = latency.as_millis() as u64;
+        metrics::observe_flush_latency(latency);
+
+        if latency_ms > self.target_latency_ms {
+            warn!("Slow sink: flush took {}ms (target {}ms)", latency_ms, self.target_latency_ms);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if state.latencies_ms.len() == LATENCY_WINDOW {
+            state.latencies_ms.pop_front();
+        }
+        state.latencies_ms.push_back(latency_ms);
+
+        let mut sorted: Vec<u64> = state.latencies_ms.iter().copied().collect();
+        sorted.sort_unstable();
+        let p99 = sorted[(sorted.len() * 99 / 100).min(sorted.len() - 1)];
+
+        let previous = state.batch_size;
+        if p99 > self.target_latency_ms {
+            state.batch_size = (previous * 3 / 4).max(self.min_batch_size);
+        } else if p99 < self.target_latency_ms / 2 {
+            state.batch_size = (previous + previous / 4).max(previous + 1).min(self.max_batch_size);
+        }
+
+        if state.batch_size != previous {
+            info!("Adjusted batch size {} -> {} (p99 flush latency {}ms)", previous, state.batch_size, p99);
+            // Judge the new size on fresh measurements only
+            state.latencies_ms.clear();
+        }
+        metrics::set_batch_size(state.batch_size);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::config::Config;
+use crate::CrmEvent;
+use crm_audit::{AuditEvent, AuditTrail};
+use crm_contracts::{Contract, ContractError, ContractRegistry, ContractStatus};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tokio::sync::Mutex;
+use tokio::time::{interval, Duration};
+use tracing::{info, warn};
+use warp::http::StatusCode;
+use warp::Filter;
+
+/// How a payload fared against its event type's contract.
+pub enum ContractCheck {
+    /// No version of the event type has been accepted.
+    Uncontracted,
+    Valid { version: i32 },
+    Invalid { version: i32, problems: Vec<String> },
+}
+
+/// Validates payloads against the latest accepted contracts, kept in memory,
+/// and reviews proposed versions so only backward compatible ones are
+/// accepted. The registry is connected lazily, like the audit log.
+pub struct ContractValidator {
+    database_url: String,
+    registry: Mutex<Option<ContractRegistry>>,
+    contracts: RwLock<HashMap<String, Contract>>,
+    reject: bool,
+    refresh_interval: Duration,
+}
+
+impl ContractValidator {
+    /// `None` without `contracts_database_url`.
+    pub fn new(config: &Config) -> Option<Arc<Self>> {
+        let database_url = config.contracts_database_url.clone()?;
+        Some(Arc::new(ContractValidator {
+            database_url,
+            registry: Mutex::new(None),
+            contracts: RwLock::new(HashMap::new()),
+            reject: config.contract_enforcement == "reject",
+            refresh_interval: Duration::from_secs(config.contract_refresh_interval_secs),
+        }))
+    }
+
+    /// Whether violating payloads are dropped rather than only counted.
+    pub fn rejects(&self) -> bool {
+        self.reject
+    }
+
+    pub fn check(&self, event: &CrmEvent) -> ContractCheck {
+        let contracts = self.contracts.read().unwrap_or_else(|e| e.into_inner());
+        let Some(contract) = contracts.get(&event.event_type) else {
+            return ContractCheck::Uncontracted;
+        };
+        let problems = contract.validate(&event.payload);
+        match problems.is_empty() {
+            true => ContractCheck::Valid { version: contract.version },
+            false => ContractCheck::Invalid { version: contract.version, problems },
+        }
+    }
+
+    /// Reviews pending proposals and reloads accepted contracts on the
+    /// refresh interval; the previous contracts stay in force while the
+    /// registry is unavailable.
+    pub fn start_refresh_task(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut ticker = interval(self.refresh_interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.refresh().await {
+                    warn!("Contract refresh failed: {}", e);
+                }
+            }
+        });
+    }
+
+    async fn refresh(&self) -> Result<(), ContractError> {
+        let mut registry = self.registry().await?;
+        let result = async {
+            let registry = registry.as_mut().expect("registry was just connected");
+            for event_type in registry.pending().await? {
+                registry.review(&event_type).await?;
+            }
+            registry.accepted().await
+        }
+        .await;
+        let accepted = self.keep_connection(&mut registry, result)?;
+
+        let mut contracts = self.contracts.write().unwrap_or_else(|e| e.into_inner());
+        for (event_type, contract) in &accepted {
+            if contracts.get(event_type).is_none_or(|current| current.version != contract.version) {
+                info!("Validating {} payloads against contract v{}", event_type, contract.version);
+            }
+        }
+        *contracts = accepted;
+        Ok(())
+    }
+
+    /// Stores `schema` as the next version of `event_type` and reviews it
+    /// right away, so the producer learns whether it was accepted.
+    async fn propose(&self, event_type: &str, schema: &Value) -> Result<Contract, ContractError> {
+        let mut registry = self.registry().await?;
+        let result = async {
+            let registry = registry.as_mut().expect("registry was just connected");
+            let proposed = registry.propose(event_type, schema).await?;
+            let reviewed = registry.review(event_type).await?;
+            Ok(reviewed.into_iter().find(|contract| contract.version == proposed.version).unwrap_or(proposed))
+        }
+        .await;
+        let contract = self.keep_connection(&mut registry, result)?;
+
+        if contract.status == ContractStatus::Accepted {
+            self.contracts
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(event_type.to_string(), contract.clone());
+        }
+        Ok(contract)
+    }
+
+    async fn versions(&self, event_type: &str) -> Result<Vec<Contract>, ContractError> {
+        let mut registry = self.registry().await?;
+        let result = registry.as_ref().expect("registry was just connected").versions(event_type).await;
+        self.keep_connection(&mut registry, result)
+    }
+
+    async fn registry(&self) -> Result<tokio::sync::MutexGuard<'_, Option<ContractRegistry>>, ContractError> {
+        let mut registry = self.registry.lock().await;
+        if registry.is_none() {
+            *registry = Some(ContractRegistry::connect(&self.database_url).await?);
+        }
+        Ok(registry)
+    }
+
+    /// Drops a connection that has closed so the next call reconnects.
+    fn keep_connection<T>(
+        &self,
+        registry: &mut Option<ContractRegistry>,
+        result: Result<T, ContractError>,
+    ) -> Result<T, ContractError> {
+        if registry.as_ref().is_some_and(ContractRegistry::is_closed) {
+            *registry = None;
+        }
+        result
+    }
+}
+
+/// The `/contracts/{event_type}` API: `GET` lists the versions, `POST` with
+/// a JSON Schema proposes the next one. Answers 201 when it was accepted,
+/// 409 with the reasons when it isn't backward compatible and 400 when the
+/// schema isn't supported. Proposals are recorded on the audit trail.
+pub fn routes(
+    validator: Option<Arc<ContractValidator>>,
+    audit: Arc<AuditTrail>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let base = warp::path("contracts")
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::any().map(move || validator.clone()).and_then(require_validator));
+
+    let list = base.clone().and(warp::get()).then(|event_type: String, validator: Arc<ContractValidator>| async move {
+        match validator.versions(&event_type).await {
+            Ok(versions) => warp::reply::with_status(warp::reply::json(&versions), StatusCode::OK),
+            Err(e) => error_reply(e),
+        }
+    });
+    let propose = base
+        .and(warp::post())
+        .and(warp::body::content_length_limit(256 * 1024))
+        .and(warp::body::json())
+        .and(crm_audit::actor())
+        .and(warp::any().map(move || Arc::clone(&audit)))
+        .then(|event_type: String, validator: Arc<ContractValidator>, schema: Value, actor: String, audit: Arc<AuditTrail>| async move {
+            match validator.propose(&event_type, &schema).await {
+                Ok(contract) => {
+                    audit
+                        .record(AuditEvent::new(actor, "contract.proposed", &event_type).details(json!({
+                            "version": contract.version,
+                            "status": contract.status,
+                            "problems": contract.problems,
+                        })))
+                        .await;
+                    let status = match contract.status {
+                        ContractStatus::Accepted => StatusCode::CREATED,
+                        _ => StatusCode::CONFLICT,
+                    };
+                    warp::reply::with_status(warp::reply::json(&contract), status)
+                }
+                Err(e) => error_reply(e),
+            }
+        });
+
+    list.or(propose).unify()
+}
+
+async fn require_validator(validator: Option<Arc<ContractValidator>>) -> Result<Arc<ContractValidator>, warp::Rejection> {
+    validator.ok_or_else(warp::reject::not_found)
+}
+
+fn error_reply(error: ContractError) -> warp::reply::WithStatus<warp::reply::Json> {
+    let status = match &error {
+        ContractError::Unsupported(_) => StatusCode::BAD_REQUEST,
+        _ => {
+            warn!("Contract registry request failed: {}", error);
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+    };
+    warp::reply::with_status(warp::reply::json(&json!({ "error": error.to_string() })), status)
+}
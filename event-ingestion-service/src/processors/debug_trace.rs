@@ -0,0 +1,122 @@
+use crate::config::Config;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use tokio::time::Duration;
+use tracing::warn;
+
+#[derive(Debug, Serialize)]
+struct StageTiming {
+    stage: &'static str,
+    micros: u64,
+}
+
+/// Per-stage timings and decisions for one event travelling through the pipeline.
+#[derive(Debug, Serialize)]
+pub struct PipelineTrace {
+    tenant_id: String,
+    event_type: String,
+    stages: Vec<StageTiming>,
+    decisions: serde_json::Map<String, Value>,
+    #[serde(skip)]
+    stage_started: Option<Instant>,
+}
+
+impl PipelineTrace {
+    pub fn new(tenant_id: &str, event_type: &str) -> Self {
+        PipelineTrace {
+            tenant_id: tenant_id.to_string(),
+            event_type: event_type.to_string(),
+            stages: Vec::new(),
+            decisions: serde_json::Map::new(),
+            stage_started: Some(Instant::now()),
+        }
+    }
+
+    /// Closes the running stage under `stage` and starts timing the next one.
+    pub fn stage(&mut self, stage: &'static str) {
+        let now = Instant::now();
+        if let Some(started) = self.stage_started.replace(now) {
+            self.stages.push(StageTiming {
+                stage,
+                micros: now.duration_since(started).as_micros() as u64,
+            });
+        }
+    }
+
+    pub fn decision(&mut self, name: &str, value: impl Into<Value>) {
+        self.decisions.insert(name.to_string(), value.into());
+    }
+}
+
+/// Publishes traces for forced (`x-debug` header) or sampled events to a
+/// shadow topic, so pipeline behaviour can be diagnosed in production.
+pub struct DebugTracer {
+    producer: Option<FutureProducer>,
+    topic: String,
+    sample_every: u64,
+    counter: AtomicU64,
+}
+
+impl DebugTracer {
+    pub fn new(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let producer = if config.debug_trace_enabled {
+            Some(
+                config
+                    .kafka_client_config()
+                    .set("message.timeout.ms", "5000")
+                    .create()?,
+            )
+        } else {
+            None
+        };
+
+        let sample_every = if config.debug_sample_rate > 0.0 {
+            (1.0 / config.debug_sample_rate.min(1.0)).round() as u64
+        } else {
+            0
+        };
+
+        Ok(DebugTracer {
+            producer,
+            topic: config.debug_topic.clone(),
+            sample_every,
+            counter: AtomicU64::new(0),
+        })
+    }
+
+    pub fn should_trace(&self, forced: bool) -> bool {
+        if self.producer.is_none() {
+            return false;
+        }
+        if forced {
+            return true;
+        }
+        self.counter.fetch_add(1, Ordering::Relaxed).checked_rem(self.sample_every) == Some(0)
+    }
+
+    /// Best effort: tracing must never fail the event itself.
+    pub async fn publish(&self, trace: PipelineTrace) {
+        let producer = match &self.producer {
+            Some(producer) => producer,
+            None => return,
+        };
+
+        let payload = match serde_json::to_string(&trace) {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("Failed to serialize pipeline trace: {}", e);
+                return;
+            }
+        };
+
+        let record = FutureRecord::to(&self.topic)
+            .key(&trace.tenant_id)
+            .payload(&payload);
+        if let Err((e, _)) = producer.send(record, Duration::from_secs(5)).await {
+            warn!("Failed to publish pipeline trace: {}", e);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Blue/green dual writes for ClickHouse schema migrations.
+//!
+//! While a migration runs, every batch written to a table listed in
+//! `dual_write_tables` is also written to its next-generation table, until
+//! `dual_write_until`. The new table must accept the generic event row (new
+//! columns derived on write, like the routed tables in `schema.sql`).
+//! Writes to it never fail a batch; failures are logged and counted.
+//!
+//! A background task compares the two tables over closed `ingested_at`
+//! windows: row count and an order-independent checksum of the generic
+//! columns. Once every window since the migration started matches, reads
+//! can move to the new table and the old one can be dropped.
+
+use crate::config::Config;
+use crate::metrics;
+use clickhouse::sql::Identifier;
+use clickhouse::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::{interval, Duration};
+use tracing::{debug, info, warn};
+
+/// Identical rows give identical sums whatever order they were inserted in.
+const FINGERPRINT_QUERY: &str = "SELECT count() AS rows, \
+    sum(cityHash64(tenant_id, event_type, user_id, timestamp, ingested_at, retention_days, properties, metrics)) AS checksum \
+    FROM ? WHERE ingested_at >= ? AND ingested_at < ?";
+
+pub struct DualWrite {
+    tables: HashMap<String, String>,
+    until: Option<i64>,
+    /// Rows ingested before this were only written once, so comparison
+    /// starts at the first window after it.
+    started_at: i64,
+}
+
+#[derive(Debug, PartialEq, Deserialize, clickhouse::Row)]
+struct Fingerprint {
+    rows: u64,
+    checksum: u64,
+}
+
+impl DualWrite {
+    /// `None` unless `dual_write_tables` names at least one table.
+    pub fn new(config: &Config) -> Option<Arc<Self>> {
+        if config.dual_write_tables.is_empty() {
+            return None;
+        }
+        for (table, next) in &config.dual_write_tables {
+            info!("Dual-writing {} to {}", table, next);
+        }
+        Some(Arc::new(DualWrite {
+            tables: config.dual_write_tables.clone(),
+            until: config.dual_write_until,
+            started_at: now(),
+        }))
+    }
+
+    /// The table that also receives rows written to `table`, while the
+    /// dual-write period lasts.
+    pub fn next_table(&self, table: &str) -> Option<&str> {
+        if self.until.is_some_and(|until| now() >= until) {
+            return None;
+        }
+        self.tables.get(table).map(|next| next.as_str())
+    }
+
+    /// Compares each closed window once it is a full window old, leaving
+    /// time for buffered batches to land. Stops after the last window of
+    /// the dual-write period.
+    pub fn start_comparison_task(self: Arc<Self>, client: Client, interval_secs: u64) {
+        tokio::spawn(async move {
+            let window = interval_secs as i64;
+            let mut ticker = interval(Duration::from_secs(interval_secs));
+            // Aligned so every replica reports the same windows
+            let mut from = (self.started_at + window - 1) / window * window;
+
+            loop {
+                ticker.tick().await;
+                let settled = now() - window;
+                let end = self.until.unwrap_or(i64::MAX);
+
+                while from < end {
+                    let to = (from + window).min(end);
+                    if to > settled {
+                        break;
+                    }
+                    match self.compare(&client, from, to).await {
+                        Ok(_) => from = to,
+                        Err(e) => {
+                            // Retried on the next tick
+                            warn!("Dual-write comparison for window {}..{} failed: {}", from, to, e);
+                            break;
+                        }
+                    }
+                }
+
+                if from >= end {
+                    info!("Dual-write period is over and every window has been compared");
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Compares both generations of every table over `from..to` of
+    /// `ingested_at`; whether they all match.
+    pub async fn compare(&self, client: &Client, from: i64, to: i64) -> Result<bool, clickhouse::error::Error> {
+        let mut matched = true;
+        for (table, next) in &self.tables {
+            let current = fingerprint(client, table, from, to).await?;
+            let upcoming = fingerprint(client, next, from, to).await?;
+            metrics::set_dual_write_rows(table, "current", current.rows);
+            metrics::set_dual_write_rows(table, "next", upcoming.rows);
+
+            if current == upcoming {
+                metrics::inc_dual_write_window(table, "match");
+                debug!("Dual-write window {}..{} matches for {}: {} rows", from, to, table, current.rows);
+            } else {
+                matched = false;
+                metrics::inc_dual_write_window(table, "mismatch");
+                warn!(
+                    "Dual-write window {}..{} differs: {} has {} rows (checksum {}), {} has {} rows (checksum {})",
+                    from, to, table, current.rows, current.checksum, next, upcoming.rows, upcoming.checksum
+                );
+            }
+        }
+        Ok(matched)
+    }
+}
+
+async fn fingerprint(client: &Client, table: &str, from: i64, to: i64) -> Result<Fingerprint, clickhouse::error::Error> {
+    client
+        .query(FINGERPRINT_QUERY)
+        .bind(Identifier(table))
+        .bind(from)
+        .bind(to)
+        .fetch_one()
+        .await
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
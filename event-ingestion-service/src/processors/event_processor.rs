@@ -1,41 +1,85 @@
-use crate::{CrmEvent, config::Config};
+use crate::{CrmEvent, config::Config, metrics};
+use crate::processors::batch_tuner::BatchTuner;
+use crate::processors::contract_validator::{ContractCheck, ContractValidator};
+use crate::processors::debug_trace::{DebugTracer, PipelineTrace};
+use crate::processors::dual_write::DualWrite;
+use crate::processors::identity_resolver::IdentityResolver;
+use crate::processors::ordering_checker::OrderingChecker;
+use crate::processors::plan_policy::PlanPolicies;
+use crate::processors::rollup_publisher::RollupPublisher;
+use crate::processors::snapshot::BufferSnapshot;
+use crate::processors::window_aggregator::WindowAggregator;
+use crate::sinks::delta_sink::DeltaSink;
 use crate::transformers::data_transformer::DataTransformer;
 use clickhouse::Client;
+use crm_flags::FlagClient;
+use crm_secrets::EnvelopeCipher;
 use redis::aio::Connection;
 use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tokio::time::{interval, Duration};
-use tracing::{info, error, debug};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, sleep, Duration};
+use tracing::{info, error, debug, warn};
+
+const DEFAULT_EVENTS_TABLE: &str = "events";
+
+// Per-tenant switches for the newer pipeline stages; both default to on
+const WINDOW_AGGREGATION_FLAG: &str = "ingestion.window_aggregation";
+const DELTA_SINK_FLAG: &str = "ingestion.delta_sink";
+const IDENTITY_RESOLUTION_FLAG: &str = "ingestion.identity_resolution";
 
 pub struct EventProcessor {
     clickhouse_client: Client,
     redis_connection: Arc<Mutex<Connection>>,
     transformer: DataTransformer,
     batch_buffer: Arc<Mutex<Vec<ProcessedEvent>>>,
+    aggregator: Arc<Mutex<WindowAggregator>>,
+    rollup_publisher: RollupPublisher,
+    batch_tuner: Arc<BatchTuner>,
+    ordering_checker: Option<Mutex<OrderingChecker>>,
+    delta_sink: Option<Arc<DeltaSink>>,
+    plan_policies: PlanPolicies,
+    identity_resolver: IdentityResolver,
+    flags: FlagClient,
+    debug_tracer: DebugTracer,
+    /// Encrypts `sensitive_properties` before events are stored.
+    cipher: Option<Arc<EnvelopeCipher>>,
+    dual_write: Option<Arc<DualWrite>>,
+    contracts: Option<Arc<ContractValidator>>,
+    /// Set on shutdown; the flush tasks stop between flushes.
+    stop_flushing: watch::Sender<bool>,
+    flush_tasks: Mutex<Vec<JoinHandle<()>>>,
     config: Config,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessedEvent {
     pub tenant_id: String,
     pub event_type: String,
     pub user_id: Option<String>,
     pub timestamp: i64,
+    #[serde(default)]
+    pub ingested_at: i64,
+    /// Set from the tenant's plan; 0 means the table default applies.
+    #[serde(default)]
+    pub retention_days: u16,
     pub properties: HashMap<String, Value>,
     pub metrics: HashMap<String, f64>,
 }
 
 impl EventProcessor {
-    pub async fn new(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+    pub async fn new(
+        config: &Config,
+        cipher: Option<Arc<EnvelopeCipher>>,
+        contracts: Option<Arc<ContractValidator>>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         // Initialize ClickHouse client
-        let clickhouse_client = Client::default()
-            .with_url(&config.clickhouse_url)
-            .with_user(&config.clickhouse_user)
-            .with_password(&config.clickhouse_password)
-            .with_database(&config.clickhouse_database);
+        let clickhouse_client = config.clickhouse_client();
 
         // Test ClickHouse connection
         clickhouse_client.query("SELECT 1").fetch_all::<u8>().await?;
@@ -46,45 +90,290 @@ impl EventProcessor {
         let redis_connection = Arc::new(Mutex::new(redis_client.get_async_connection().await?));
         info!("Connected to Redis");
 
+        let rollup_publisher = RollupPublisher::new(config)?;
+
+        // Optional raw event lake for the data-science team
+        let delta_sink = match &config.delta_table_url {
+            Some(table_url) => {
+                let sink = Arc::new(DeltaSink::new(table_url, config.delta_max_buffered_events).await?);
+                Arc::clone(&sink).start_commit_task(Duration::from_secs(config.delta_commit_interval_secs));
+                Some(sink)
+            }
+            None => None,
+        };
+
+        // Schema migrations in progress write to the next-generation tables too
+        let dual_write = DualWrite::new(config);
+        if let Some(dual_write) = &dual_write {
+            Arc::clone(dual_write)
+                .start_comparison_task(clickhouse_client.clone(), config.dual_write_compare_interval_secs);
+        }
+
         let processor = EventProcessor {
             clickhouse_client,
             redis_connection,
             transformer: DataTransformer::new(),
             batch_buffer: Arc::new(Mutex::new(Vec::new())),
+            aggregator: Arc::new(Mutex::new(WindowAggregator::new(config.aggregation_window_secs))),
+            rollup_publisher,
+            batch_tuner: Arc::new(BatchTuner::new(config)),
+            ordering_checker: config
+                .ordering_check_enabled
+                .then(|| Mutex::new(OrderingChecker::new())),
+            delta_sink,
+            plan_policies: PlanPolicies::new(config),
+            identity_resolver: IdentityResolver::new(config),
+            flags: FlagClient::new(&config.redis_url, Duration::from_secs(config.feature_flag_cache_ttl_secs)),
+            debug_tracer: DebugTracer::new(config)?,
+            cipher,
+            dual_write,
+            contracts,
+            stop_flushing: watch::channel(false).0,
+            flush_tasks: Mutex::new(Vec::new()),
             config: config.clone(),
         };
 
+        // Restore state left behind by a previous graceful shutdown
+        if let Some(path) = &config.snapshot_path {
+            if let Some(snapshot) = BufferSnapshot::take(path)? {
+                processor.batch_buffer.lock().await.extend(snapshot.events);
+                processor.aggregator.lock().await.restore(snapshot.windows);
+            }
+        }
+
         // Start batch flush task
         processor.start_batch_flush_task().await;
 
+        // Start window rollup task
+        processor.start_window_flush_task().await;
+
         Ok(processor)
     }
 
     pub async fn process_event(&self, event: CrmEvent) -> Result<(), Box<dyn std::error::Error>> {
+        self.process_event_traced(event, false).await
+    }
+
+    /// Processes the event, publishing a pipeline trace when `force_debug` is
+    /// set or the event falls into the debug sample.
+    pub async fn process_event_traced(&self, event: CrmEvent, force_debug: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let mut trace = self
+            .debug_tracer
+            .should_trace(force_debug)
+            .then(|| PipelineTrace::new(&event.tenant_id, &event.event_type));
+
+        if trace.is_none() {
+            return self.run_pipeline(event, &mut trace).await;
+        }
+
+        // Errors are not Send, so keep only the message across the publish
+        let error = self.run_pipeline(event, &mut trace).await.err().map(|e| e.to_string());
+
+        if let Some(mut trace) = trace {
+            if let Some(e) = &error {
+                trace.decision("error", e.as_str());
+            }
+            self.debug_tracer.publish(trace).await;
+        }
+
+        match error {
+            Some(e) => Err(e.into()),
+            None => Ok(()),
+        }
+    }
+
+    async fn run_pipeline(
+        &self,
+        event: CrmEvent,
+        trace: &mut Option<PipelineTrace>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         debug!("Processing event: {:?}", event);
 
+        // Check the payload against its event type's contract
+        if let Some(contracts) = &self.contracts {
+            let verdict = match contracts.check(&event) {
+                ContractCheck::Uncontracted => "none".to_string(),
+                ContractCheck::Valid { version } => format!("v{}", version),
+                ContractCheck::Invalid { version, problems } => {
+                    metrics::inc_contract_violation(&event.event_type);
+                    let message = format!(
+                        "{} payload violates contract v{}: {}",
+                        event.event_type,
+                        version,
+                        problems.join("; ")
+                    );
+                    if contracts.rejects() {
+                        return Err(message.into());
+                    }
+                    warn!("{}", message);
+                    format!("v{} violated", version)
+                }
+            };
+            if let Some(trace) = trace.as_mut() {
+                trace.stage("contract");
+                trace.decision("contract", verdict);
+            }
+        }
+
         // Transform the event
-        let processed_event = self.transformer.transform_event(event).await?;
+        let mut processed_event = self.transformer.transform_event(event).await?;
+        if let Some(trace) = trace.as_mut() {
+            trace.stage("transform");
+        }
+
+        // Attribute anonymous events to identified users
+        if self
+            .flags
+            .is_enabled(&processed_event.tenant_id, IDENTITY_RESOLUTION_FLAG, true)
+            .await
+        {
+            let resolution = self
+                .identity_resolver
+                .apply(&mut processed_event, &self.redis_connection, &self.clickhouse_client)
+                .await?;
+            if let Some(trace) = trace.as_mut() {
+                trace.stage("identity");
+                trace.decision("identity", resolution.as_str());
+            }
+        }
+
+        // Track pipeline delay between the producer and ingestion clocks
+        metrics::observe_skew(&processed_event);
+
+        // Apply plan-tier sampling and retention
+        let keep = self.plan_policies.apply(&mut processed_event, &self.redis_connection).await?;
+        if let Some(trace) = trace.as_mut() {
+            trace.stage("plan_policy");
+            trace.decision("sampled_out", !keep);
+            trace.decision("retention_days", processed_event.retention_days);
+        }
+
+        // Accumulate into the current aggregation window
+        let aggregate = self
+            .flags
+            .is_enabled(&processed_event.tenant_id, WINDOW_AGGREGATION_FLAG, true)
+            .await;
+        if aggregate {
+            self.aggregator.lock().await.record(&processed_event);
+        }
+        if let Some(trace) = trace.as_mut() {
+            trace.stage("aggregate");
+            trace.decision(WINDOW_AGGREGATION_FLAG, aggregate);
+        }
+
+        if !keep {
+            debug!("Sampled out event {} for tenant {}", processed_event.event_type, processed_event.tenant_id);
+            self.update_real_time_metrics(&processed_event).await?;
+            if let Some(trace) = trace.as_mut() {
+                trace.stage("real_time_metrics");
+            }
+            return Ok(());
+        }
+
+        // Only the stored copy is encrypted; metrics below read plaintext
+        let stored_event = self.seal_sensitive(&processed_event).await?;
+        if let Some(trace) = trace.as_mut() {
+            trace.stage("encrypt");
+        }
+
+        let mut delta_appended = false;
+        if let Some(delta_sink) = &self.delta_sink {
+            if self.flags.is_enabled(&processed_event.tenant_id, DELTA_SINK_FLAG, true).await {
+                delta_sink.append(std::slice::from_ref(&stored_event)).await;
+                delta_appended = true;
+            }
+        }
 
         // Add to batch buffer
         {
             let mut buffer = self.batch_buffer.lock().await;
-            buffer.push(processed_event.clone());
+            buffer.push(stored_event);
 
             // Flush if batch is full
-            if buffer.len() >= self.config.batch_size {
+            if buffer.len() >= self.batch_tuner.batch_size() {
                 let events_to_flush = buffer.drain(..).collect();
                 drop(buffer); // Release lock early
+                if let Some(trace) = trace.as_mut() {
+                    trace.decision("triggered_flush", true);
+                }
                 self.flush_events(events_to_flush).await?;
             }
         }
+        if let Some(trace) = trace.as_mut() {
+            let table = self
+                .config
+                .table_routes
+                .get(&processed_event.event_type)
+                .map(|t| t.as_str())
+                .unwrap_or(DEFAULT_EVENTS_TABLE);
+            trace.stage("buffer");
+            trace.decision("table", table);
+            trace.decision("delta_sink", delta_appended);
+        }
 
         // Update real-time metrics in Redis
         self.update_real_time_metrics(&processed_event).await?;
+        if let Some(trace) = trace.as_mut() {
+            trace.stage("real_time_metrics");
+        }
 
         Ok(())
     }
 
+    /// A copy of the event with its sensitive properties encrypted under the
+    /// tenant's data key, so ClickHouse and the lake never hold them in the
+    /// clear. Fails rather than storing plaintext when the key service does.
+    async fn seal_sensitive(&self, event: &ProcessedEvent) -> Result<ProcessedEvent, Box<dyn std::error::Error>> {
+        let mut stored = event.clone();
+        let Some(cipher) = &self.cipher else {
+            return Ok(stored);
+        };
+        for name in &self.config.sensitive_properties {
+            if let Some(value) = stored.properties.get_mut(name).filter(|value| !value.is_null()) {
+                *value = cipher.encrypt_value(&event.tenant_id, value).await?;
+            }
+        }
+        Ok(stored)
+    }
+
+    /// Records the event against the per-key ordering checker, if enabled.
+    pub async fn check_ordering(&self, event: &CrmEvent, partition: i32) {
+        if let Some(checker) = &self.ordering_checker {
+            checker.lock().await.check(event, partition);
+        }
+    }
+
+    /// Persists the batch buffer and open windows so a restart can resume them.
+    pub async fn shutdown(&self) -> Result<(), Box<dyn std::error::Error>> {
+        // A flush in progress finishes first, so the events and windows it
+        // took are written before the rest are saved
+        self.stop_flushing.send_replace(true);
+        for task in self.flush_tasks.lock().await.drain(..) {
+            if let Err(e) = task.await {
+                error!("Flush task failed: {}", e);
+            }
+        }
+
+        if let Some(delta_sink) = &self.delta_sink {
+            delta_sink.commit().await?;
+        }
+
+        let path = match &self.config.snapshot_path {
+            Some(path) => path,
+            None => {
+                // Without a snapshot location, flush what we can before exiting
+                let events = self.batch_buffer.lock().await.drain(..).collect();
+                return self.flush_events(events).await;
+            }
+        };
+
+        let snapshot = BufferSnapshot {
+            events: self.batch_buffer.lock().await.drain(..).collect(),
+            windows: self.aggregator.lock().await.snapshot(),
+        };
+        snapshot.save(path)
+    }
+
     async fn flush_events(&self, events: Vec<ProcessedEvent>) -> Result<(), Box<dyn std::error::Error>> {
         if events.is_empty() {
             return Ok(());
@@ -92,27 +381,17 @@ impl EventProcessor {
 
         info!("Flushing {} events to ClickHouse", events.len());
 
-        // Prepare bulk insert query
-        let mut insert = self.clickhouse_client.insert("events")?;
-
-        for event in events {
-            insert.write(&ClickHouseEvent {
-                tenant_id: event.tenant_id,
-                event_type: event.event_type,
-                user_id: event.user_id.unwrap_or_default(),
-                timestamp: event.timestamp,
-                properties: serde_json::to_string(&event.properties)?,
-                metrics: serde_json::to_string(&event.metrics)?,
-            }).await?;
-        }
-
-        insert.end().await?;
+        let started = Instant::now();
+        Self::flush_events_static(&self.clickhouse_client, &self.config.table_routes, self.dual_write.as_deref(), events)
+            .await?;
+        self.batch_tuner.record_flush(started.elapsed());
         info!("Successfully flushed events to ClickHouse");
 
         Ok(())
     }
 
     async fn update_real_time_metrics(&self, event: &ProcessedEvent) -> Result<(), Box<dyn std::error::Error>> {
+        crm_chaos::inject(crm_chaos::REDIS).await?;
         let mut conn = self.redis_connection.lock().await;
         
         // Update event counters
@@ -127,20 +406,34 @@ impl EventProcessor {
             let _: () = conn.expire(&user_key, 86400).await?; // 24 hours TTL
         }
 
+        // Update campaign engagement counters
+        if event.event_type.starts_with("email_") {
+            if let Some(campaign_id) = event.properties.get("campaign_id").and_then(|v| v.as_str()) {
+                let campaign_key = format!("campaign:{}:{}", event.tenant_id, campaign_id);
+                let _: () = conn.hincr(&campaign_key, &event.event_type, 1).await?;
+                let _: () = conn.expire(&campaign_key, 30 * 86400).await?; // 30 days TTL
+            }
+        }
+
         Ok(())
     }
 
     async fn start_batch_flush_task(&self) {
         let batch_buffer = Arc::clone(&self.batch_buffer);
-        let flush_interval = Duration::from_millis(self.config.flush_interval_ms);
+        let batch_tuner = Arc::clone(&self.batch_tuner);
         let clickhouse_client = self.clickhouse_client.clone();
+        let table_routes = self.config.table_routes.clone();
+        let dual_write = self.dual_write.clone();
+        let mut stop = self.stop_flushing.subscribe();
 
-        tokio::spawn(async move {
-            let mut interval = interval(flush_interval);
-            
+        let task = tokio::spawn(async move {
             loop {
-                interval.tick().await;
-                
+                // Interval follows the tuned batch size
+                tokio::select! {
+                    _ = sleep(batch_tuner.flush_interval()) => {}
+                    _ = stop.changed() => break,
+                }
+
                 let events_to_flush = {
                     let mut buffer = batch_buffer.lock().await;
                     if buffer.is_empty() {
@@ -149,45 +442,126 @@ impl EventProcessor {
                     buffer.drain(..).collect()
                 };
 
-                if let Err(e) = Self::flush_events_static(&clickhouse_client, events_to_flush).await {
+                let started = Instant::now();
+                if let Err(e) = Self::flush_events_static(&clickhouse_client, &table_routes, dual_write.as_deref(), events_to_flush).await
+                {
                     error!("Error in batch flush task: {}", e);
                 }
+                batch_tuner.record_flush(started.elapsed());
             }
         });
+        self.flush_tasks.lock().await.push(task);
     }
 
-    async fn flush_events_static(
+    async fn start_window_flush_task(&self) {
+        let aggregator = Arc::clone(&self.aggregator);
+        let publisher = self.rollup_publisher.clone();
+        let flush_interval = Duration::from_millis(self.config.flush_interval_ms);
+        let grace_secs = self.config.aggregation_grace_secs as i64;
+        let mut stop = self.stop_flushing.subscribe();
+
+        let task = tokio::spawn(async move {
+            let mut interval = interval(flush_interval);
+
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = stop.changed() => break,
+                }
+
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs() as i64)
+                    .unwrap_or_default();
+                let rollups = aggregator.lock().await.drain_closed(now - grace_secs);
+
+                if let Err(e) = publisher.publish(rollups).await {
+                    error!("Error in window rollup task: {}", e);
+                }
+            }
+        });
+        self.flush_tasks.lock().await.push(task);
+    }
+
+    /// Writes `events` to their tables, and to the next generation of those
+    /// being migrated.
+    pub async fn flush_events_static(
         clickhouse_client: &Client,
+        table_routes: &HashMap<String, String>,
+        dual_write: Option<&DualWrite>,
         events: Vec<ProcessedEvent>
     ) -> Result<(), Box<dyn std::error::Error>> {
         if events.is_empty() {
             return Ok(());
         }
+        crm_chaos::inject(crm_chaos::CLICKHOUSE).await?;
 
-        let mut insert = clickhouse_client.insert("events")?;
-
+        // High-volume event types go to dedicated tables, the rest to the generic one
+        let mut events_by_table: HashMap<&str, Vec<ProcessedEvent>> = HashMap::new();
         for event in events {
-            insert.write(&ClickHouseEvent {
-                tenant_id: event.tenant_id,
-                event_type: event.event_type,
-                user_id: event.user_id.unwrap_or_default(),
-                timestamp: event.timestamp,
-                properties: serde_json::to_string(&event.properties)?,
-                metrics: serde_json::to_string(&event.metrics)?,
-            }).await?;
+            let table = table_routes
+                .get(&event.event_type)
+                .map(|t| t.as_str())
+                .unwrap_or(DEFAULT_EVENTS_TABLE);
+            events_by_table.entry(table).or_default().push(event);
+        }
+
+        for (table, events) in events_by_table {
+            debug!("Writing {} events to table {}", events.len(), table);
+
+            let rows = events
+                .into_iter()
+                .map(ClickHouseEvent::try_from)
+                .collect::<Result<Vec<_>, _>>()?;
+            Self::write_rows(clickhouse_client, table, &rows).await?;
+
+            // The batch stands on the current table alone; a gap in the next
+            // one shows up in the dual-write comparison
+            if let Some(next) = dual_write.and_then(|dual_write| dual_write.next_table(table)) {
+                if let Err(e) = Self::write_rows(clickhouse_client, next, &rows).await {
+                    warn!("Dual write of {} events to {} failed: {}", rows.len(), next, e);
+                    metrics::inc_dual_write_failure(table);
+                }
+            }
         }
 
-        insert.end().await?;
         Ok(())
     }
+
+    async fn write_rows(clickhouse_client: &Client, table: &str, rows: &[ClickHouseEvent]) -> Result<(), clickhouse::error::Error> {
+        let mut insert = clickhouse_client.insert(table)?;
+        for row in rows {
+            insert.write(row).await?;
+        }
+        insert.end().await
+    }
 }
 
 #[derive(Debug, serde::Serialize, clickhouse::Row)]
-struct ClickHouseEvent {
+/// A row of the events tables.
+pub struct ClickHouseEvent {
     tenant_id: String,
     event_type: String,
     user_id: String,
     timestamp: i64,
+    ingested_at: i64,
+    retention_days: u16,
     properties: String,
     metrics: String,
-}
\ No newline at end of file
+}
+impl TryFrom<ProcessedEvent> for ClickHouseEvent {
+    type Error = serde_json::Error;
+
+    fn try_from(event: ProcessedEvent) -> Result<Self, Self::Error> {
+        Ok(ClickHouseEvent {
+            tenant_id: event.tenant_id,
+            event_type: event.event_type,
+            user_id: event.user_id.unwrap_or_default(),
+            timestamp: event.timestamp,
+            ingested_at: event.ingested_at,
+            retention_days: event.retention_days,
+            properties: serde_json::to_string(&event.properties)?,
+            metrics: serde_json::to_string(&event.metrics)?,
+        })
+    }
+}
@@ -0,0 +1,173 @@
+use crate::config::Config;
+use crate::processors::event_processor::ProcessedEvent;
+use clickhouse::Client;
+use redis::aio::Connection;
+use redis::AsyncCommands;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+const IDENTITY_TABLE: &str = "identity_map";
+/// Anonymous ids are unbounded, so the local cache is simply reset past this.
+const MAX_CACHED_IDS: usize = 100_000;
+
+/// What the resolver did with an event, for pipeline traces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resolution {
+    /// An `identify` event; the mapping was recorded.
+    Identified,
+    /// An anonymous event attributed to a previously identified user.
+    Stitched,
+    /// Anonymous and not identified yet.
+    Unresolved,
+    /// Already carried a user id, or no anonymous id.
+    Skipped,
+}
+
+impl Resolution {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Resolution::Identified => "identified",
+            Resolution::Stitched => "stitched",
+            Resolution::Unresolved => "unresolved",
+            Resolution::Skipped => "skipped",
+        }
+    }
+}
+
+/// `(tenant_id, anonymous_id)` → user id, `None` while not identified yet.
+type IdentityCache = HashMap<(String, String), (Option<String>, Instant)>;
+
+/// Maintains anonymous_id → user_id mappings from `identify` events.
+///
+/// Mappings live in the Redis hash `identity_map:{tenant_id}` so later
+/// anonymous events can be attributed as they arrive, and in the ClickHouse
+/// `identity_map` table, which `page_views_resolved` joins against to
+/// attribute page views recorded before the visitor identified.
+pub struct IdentityResolver {
+    cache_ttl: Duration,
+    cache: Mutex<IdentityCache>,
+}
+
+#[derive(Debug, serde::Serialize, clickhouse::Row)]
+struct IdentityRow {
+    tenant_id: String,
+    anonymous_id: String,
+    user_id: String,
+    identified_at: i64,
+}
+
+impl IdentityResolver {
+    pub fn new(config: &Config) -> Self {
+        IdentityResolver {
+            cache_ttl: Duration::from_secs(config.identity_cache_ttl_secs),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn apply(
+        &self,
+        event: &mut ProcessedEvent,
+        conn: &Mutex<Connection>,
+        clickhouse: &Client,
+    ) -> Result<Resolution, Box<dyn std::error::Error>> {
+        let anonymous_id = match event.properties.get("anonymous_id").and_then(Value::as_str) {
+            Some(id) if !id.is_empty() => id.to_string(),
+            _ => return Ok(Resolution::Skipped),
+        };
+
+        if event.event_type == "identify" {
+            let user_id = match &event.user_id {
+                Some(user_id) if !user_id.is_empty() => user_id.clone(),
+                _ => {
+                    debug!("identify event for {} without a user_id", anonymous_id);
+                    return Ok(Resolution::Skipped);
+                }
+            };
+            self.record(event, &anonymous_id, &user_id, conn, clickhouse).await?;
+            return Ok(Resolution::Identified);
+        }
+
+        if event.user_id.as_deref().is_some_and(|id| !id.is_empty()) {
+            return Ok(Resolution::Skipped);
+        }
+
+        match self.lookup(&event.tenant_id, &anonymous_id, conn).await? {
+            Some(user_id) => {
+                event.user_id = Some(user_id);
+                event.properties.insert("identity_stitched".to_string(), Value::Bool(true));
+                Ok(Resolution::Stitched)
+            }
+            None => Ok(Resolution::Unresolved),
+        }
+    }
+
+    async fn record(
+        &self,
+        event: &ProcessedEvent,
+        anonymous_id: &str,
+        user_id: &str,
+        conn: &Mutex<Connection>,
+        clickhouse: &Client,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let _: () = conn
+            .lock()
+            .await
+            .hset(identity_key(&event.tenant_id), anonymous_id, user_id)
+            .await?;
+
+        // identify events are rare next to page views, so insert directly
+        let mut insert = clickhouse.insert(IDENTITY_TABLE)?;
+        insert
+            .write(&IdentityRow {
+                tenant_id: event.tenant_id.clone(),
+                anonymous_id: anonymous_id.to_string(),
+                user_id: user_id.to_string(),
+                identified_at: event.timestamp,
+            })
+            .await?;
+        insert.end().await?;
+
+        self.remember(&event.tenant_id, anonymous_id, Some(user_id.to_string())).await;
+        info!("Identified {} as {} for tenant {}", anonymous_id, user_id, event.tenant_id);
+
+        Ok(())
+    }
+
+    async fn lookup(
+        &self,
+        tenant_id: &str,
+        anonymous_id: &str,
+        conn: &Mutex<Connection>,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let key = (tenant_id.to_string(), anonymous_id.to_string());
+        if let Some((user_id, fetched_at)) = self.cache.lock().await.get(&key) {
+            if fetched_at.elapsed() < self.cache_ttl {
+                return Ok(user_id.clone());
+            }
+        }
+
+        let user_id: Option<String> = conn
+            .lock()
+            .await
+            .hget(identity_key(tenant_id), anonymous_id)
+            .await?;
+        self.remember(tenant_id, anonymous_id, user_id.clone()).await;
+
+        Ok(user_id)
+    }
+
+    async fn remember(&self, tenant_id: &str, anonymous_id: &str, user_id: Option<String>) {
+        let mut cache = self.cache.lock().await;
+        if cache.len() >= MAX_CACHED_IDS {
+            cache.clear();
+        }
+        cache.insert((tenant_id.to_string(), anonymous_id.to_string()), (user_id, Instant::now()));
+    }
+}
+
+fn identity_key(tenant_id: &str) -> String {
+    format!("identity_map:{}", tenant_id)
+}
@@ -1 +1,12 @@
-pub mod event_processor;
\ No newline at end of file
+pub mod audit_store;
+pub mod batch_tuner;
+pub mod contract_validator;
+pub mod debug_trace;
+pub mod dual_write;
+pub mod event_processor;
+pub mod identity_resolver;
+pub mod ordering_checker;
+pub mod plan_policy;
+pub mod rollup_publisher;
+pub mod snapshot;
+pub mod window_aggregator;
\ No newline at end of file
@@ -0,0 +1,64 @@
+use crate::{CrmEvent, metrics};
+use std::collections::HashMap;
+use tracing::warn;
+
+/// Upper bound on tracked keys; the map is reset once exceeded.
+const MAX_TRACKED_KEYS: usize = 100_000;
+
+struct KeyState {
+    partition: i32,
+    sequence: i64,
+}
+
+/// Verifies that events arrive in order per (tenant, user_id) key.
+///
+/// Producers that partition by the wrong key show up as the same key being
+/// seen on several partitions, usually together with ordering violations.
+#[derive(Default)]
+pub struct OrderingChecker {
+    keys: HashMap<(String, String), KeyState>,
+}
+
+impl OrderingChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn check(&mut self, event: &CrmEvent, partition: i32) {
+        let user_id = match &event.user_id {
+            Some(user_id) => user_id.clone(),
+            None => return,
+        };
+
+        // Prefer an explicit producer sequence number over the timestamp
+        let sequence = event
+            .payload
+            .get("sequence")
+            .and_then(|v| v.as_i64())
+            .unwrap_or(event.timestamp);
+
+        if self.keys.len() >= MAX_TRACKED_KEYS {
+            self.keys.clear();
+        }
+
+        let key = (event.tenant_id.clone(), user_id);
+        if let Some(previous) = self.keys.get(&key) {
+            if previous.partition != partition {
+                metrics::inc_partition_mismatch(&event.tenant_id);
+                warn!(
+                    "Key {}/{} moved from partition {} to {}",
+                    key.0, key.1, previous.partition, partition
+                );
+            }
+            if sequence < previous.sequence {
+                metrics::inc_ordering_violation(&event.tenant_id);
+                warn!(
+                    "Ordering violation for {}/{}: {} after {} (partition {})",
+                    key.0, key.1, sequence, previous.sequence, partition
+                );
+            }
+        }
+
+        self.keys.insert(key, KeyState { partition, sequence });
+    }
+}
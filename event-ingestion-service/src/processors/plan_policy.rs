@@ -0,0 +1,106 @@
+use crate::config::Config;
+use crate::processors::event_processor::ProcessedEvent;
+use redis::aio::Connection;
+use redis::AsyncCommands;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::Instant;
+use tokio::sync::Mutex;
+use tokio::time::Duration;
+use tracing::debug;
+
+/// Storage fidelity a tenant's plan entitles it to.
+#[derive(Debug, Clone, Copy)]
+pub struct PlanPolicy {
+    pub sample_rate: f64,
+    pub retention_days: u16,
+}
+
+/// Resolves tenant plans from Redis (`tenant_plan:{tenant_id}`) with a local
+/// cache, and applies the matching sampling/retention policy to events.
+pub struct PlanPolicies {
+    free: PlanPolicy,
+    paid: PlanPolicy,
+    default_plan: String,
+    cache_ttl: Duration,
+    cache: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl PlanPolicies {
+    pub fn new(config: &Config) -> Self {
+        PlanPolicies {
+            free: PlanPolicy {
+                sample_rate: config.free_tier_sample_rate.clamp(0.0, 1.0),
+                retention_days: config.free_tier_retention_days,
+            },
+            paid: PlanPolicy {
+                sample_rate: 1.0,
+                retention_days: config.paid_tier_retention_days,
+            },
+            default_plan: config.default_tenant_plan.clone(),
+            cache_ttl: Duration::from_secs(config.plan_cache_ttl_secs),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn plan_for(&self, tenant_id: &str, conn: &Mutex<Connection>) -> Result<String, Box<dyn std::error::Error>> {
+        if let Some((plan, fetched_at)) = self.cache.lock().await.get(tenant_id) {
+            if fetched_at.elapsed() < self.cache_ttl {
+                return Ok(plan.clone());
+            }
+        }
+
+        let plan: Option<String> = conn
+            .lock()
+            .await
+            .get(format!("tenant_plan:{}", tenant_id))
+            .await?;
+        let plan = plan.unwrap_or_else(|| self.default_plan.clone());
+        debug!("Resolved plan {} for tenant {}", plan, tenant_id);
+
+        self.cache
+            .lock()
+            .await
+            .insert(tenant_id.to_string(), (plan.clone(), Instant::now()));
+
+        Ok(plan)
+    }
+
+    /// Stamps retention and sample weight onto the event and returns whether
+    /// it should be stored.
+    pub async fn apply(
+        &self,
+        event: &mut ProcessedEvent,
+        conn: &Mutex<Connection>,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let policy = match self.plan_for(&event.tenant_id, conn).await?.as_str() {
+            "free" => self.free,
+            _ => self.paid,
+        };
+
+        event.retention_days = policy.retention_days;
+        if policy.sample_rate >= 1.0 {
+            return Ok(true);
+        }
+
+        // Sample whole user journeys rather than individual events
+        let mut hasher = DefaultHasher::new();
+        event.tenant_id.hash(&mut hasher);
+        match &event.user_id {
+            Some(user_id) => user_id.hash(&mut hasher),
+            None => event.timestamp.hash(&mut hasher),
+        }
+        let bucket = (hasher.finish() % 10_000) as f64 / 10_000.0;
+        if bucket >= policy.sample_rate {
+            return Ok(false);
+        }
+
+        // Lets analytics scale sampled counts back up
+        if policy.sample_rate > 0.0 {
+            event.metrics.insert("sample_weight".to_string(), 1.0 / policy.sample_rate);
+        }
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,52 @@
+use crate::config::Config;
+use crate::processors::window_aggregator::MetricRollup;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use tokio::time::Duration;
+use tracing::{info, debug};
+
+/// Publishes window rollups so downstream services can consume
+/// pre-aggregated metrics instead of querying ClickHouse.
+#[derive(Clone)]
+pub struct RollupPublisher {
+    producer: FutureProducer,
+    topic: String,
+}
+
+impl RollupPublisher {
+    pub fn new(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let producer: FutureProducer = config
+            .kafka_client_config()
+            .set("message.timeout.ms", "5000")
+            .create()?;
+
+        Ok(RollupPublisher {
+            producer,
+            topic: config.metrics_out_topic.clone(),
+        })
+    }
+
+    pub async fn publish(&self, rollups: Vec<MetricRollup>) -> Result<(), Box<dyn std::error::Error>> {
+        if rollups.is_empty() {
+            return Ok(());
+        }
+
+        info!("Publishing {} rollups to {}", rollups.len(), self.topic);
+
+        for rollup in rollups {
+            let payload = serde_json::to_string(&rollup)?;
+            // Key by tenant so all rollups of a tenant land on one partition
+            let record = FutureRecord::to(&self.topic)
+                .key(&rollup.tenant_id)
+                .payload(&payload);
+
+            self.producer
+                .send(record, Duration::from_secs(5))
+                .await
+                .map_err(|(e, _)| e)?;
+
+            debug!("Published rollup for {}/{} at {}", rollup.tenant_id, rollup.event_type, rollup.window_start);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+use crate::processors::event_processor::ProcessedEvent;
+use crate::processors::window_aggregator::WindowSnapshot;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::info;
+
+/// In-memory pipeline state persisted across planned restarts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BufferSnapshot {
+    pub events: Vec<ProcessedEvent>,
+    pub windows: Vec<WindowSnapshot>,
+}
+
+impl BufferSnapshot {
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = Path::new(path).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // Write to a temp file first so a crash mid-write never leaves a torn snapshot
+        let tmp_path = format!("{}.tmp", path);
+        std::fs::write(&tmp_path, serde_json::to_vec(self)?)?;
+        std::fs::rename(&tmp_path, path)?;
+
+        info!("Saved snapshot with {} events and {} windows to {}", self.events.len(), self.windows.len(), path);
+
+        Ok(())
+    }
+
+    /// Loads and removes the snapshot so it is only ever restored once.
+    pub fn take(path: &str) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        if !Path::new(path).exists() {
+            return Ok(None);
+        }
+
+        let snapshot: BufferSnapshot = serde_json::from_slice(&std::fs::read(path)?)?;
+        std::fs::remove_file(path)?;
+
+        info!("Restored snapshot with {} events and {} windows from {}", snapshot.events.len(), snapshot.windows.len(), path);
+
+        Ok(Some(snapshot))
+    }
+}
@@ -0,0 +1,107 @@
+use crate::processors::event_processor::ProcessedEvent;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Rollup of all events for one tenant/event type within a tumbling window.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricRollup {
+    pub tenant_id: String,
+    pub event_type: String,
+    pub window_start: i64,
+    pub window_end: i64,
+    pub event_count: u64,
+    pub unique_users: u64,
+    pub metrics: HashMap<String, f64>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct WindowState {
+    event_count: u64,
+    users: HashSet<String>,
+    metrics: HashMap<String, f64>,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowKey {
+    tenant_id: String,
+    event_type: String,
+    window_start: i64,
+}
+
+/// Serializable form of an open window, used for restart snapshots.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowSnapshot {
+    key: WindowKey,
+    state: WindowState,
+}
+
+pub struct WindowAggregator {
+    window_secs: i64,
+    windows: HashMap<WindowKey, WindowState>,
+}
+
+impl WindowAggregator {
+    pub fn new(window_secs: u64) -> Self {
+        WindowAggregator {
+            window_secs: window_secs.max(1) as i64,
+            windows: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, event: &ProcessedEvent) {
+        let key = WindowKey {
+            tenant_id: event.tenant_id.clone(),
+            event_type: event.event_type.clone(),
+            window_start: event.timestamp - event.timestamp.rem_euclid(self.window_secs),
+        };
+
+        let state = self.windows.entry(key).or_default();
+        state.event_count += 1;
+        if let Some(user_id) = &event.user_id {
+            state.users.insert(user_id.clone());
+        }
+        for (name, value) in &event.metrics {
+            *state.metrics.entry(name.clone()).or_insert(0.0) += value;
+        }
+    }
+
+    /// Removes and returns every window that ended at or before `watermark`.
+    pub fn drain_closed(&mut self, watermark: i64) -> Vec<MetricRollup> {
+        let window_secs = self.window_secs;
+        let closed: Vec<WindowKey> = self
+            .windows
+            .keys()
+            .filter(|key| key.window_start + window_secs <= watermark)
+            .cloned()
+            .collect();
+
+        closed
+            .into_iter()
+            .filter_map(|key| {
+                let state = self.windows.remove(&key)?;
+                Some(MetricRollup {
+                    window_end: key.window_start + window_secs,
+                    tenant_id: key.tenant_id,
+                    event_type: key.event_type,
+                    window_start: key.window_start,
+                    event_count: state.event_count,
+                    unique_users: state.users.len() as u64,
+                    metrics: state.metrics,
+                })
+            })
+            .collect()
+    }
+
+    pub fn snapshot(&mut self) -> Vec<WindowSnapshot> {
+        self.windows
+            .drain()
+            .map(|(key, state)| WindowSnapshot { key, state })
+            .collect()
+    }
+
+    pub fn restore(&mut self, windows: Vec<WindowSnapshot>) {
+        for window in windows {
+            self.windows.insert(window.key, window.state);
+        }
+    }
+}
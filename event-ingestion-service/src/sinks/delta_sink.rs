@@ -0,0 +1,349 @@
+use crate::processors::event_processor::ProcessedEvent;
+use arrow_array::{ArrayRef, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+use futures::TryStreamExt;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::{ObjectStore, PutMode, PutOptions, PutPayload};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, Notify};
+use tokio::time::{interval, Duration};
+use tracing::{info, error, warn, debug};
+use url::Url;
+
+const MAX_COMMIT_ATTEMPTS: u32 = 10;
+
+/// Writes flushed events as Parquet files into a Delta Lake table on object
+/// storage so the raw event lake can be queried with Spark/Trino.
+///
+/// The buffer holds about `max_buffered` events: once it is full, appends
+/// wait for a commit to empty it, so a lake that is down slows ingestion
+/// rather than filling memory.
+pub struct DeltaSink {
+    store: Arc<dyn ObjectStore>,
+    table_root: Path,
+    buffer: Mutex<Vec<ProcessedEvent>>,
+    max_buffered: usize,
+    /// Signalled when a commit empties the buffer.
+    room: Notify,
+    table: Mutex<TableState>,
+}
+
+struct TableState {
+    next_version: u64,
+    table_id: String,
+    schema_string: Option<String>,
+}
+
+impl DeltaSink {
+    pub async fn new(table_url: &str, max_buffered: usize) -> Result<Self, Box<dyn std::error::Error>> {
+        let url = Url::parse(table_url)?;
+        let (store, table_root): (Arc<dyn ObjectStore>, Path) = match url.scheme() {
+            "s3" | "s3a" => {
+                let store = AmazonS3Builder::from_env().with_url(table_url).build()?;
+                (Arc::new(store), Path::from_url_path(url.path())?)
+            }
+            _ => {
+                let (store, path) = object_store::parse_url(&url)?;
+                (Arc::from(store), path)
+            }
+        };
+
+        let sink = Self::with_store(store, table_root, max_buffered).await?;
+        info!("Delta sink writing to {}", table_url);
+
+        Ok(sink)
+    }
+
+    /// A sink for the table at `table_root` in `store`, picking up its log.
+    pub async fn with_store(
+        store: Arc<dyn ObjectStore>,
+        table_root: Path,
+        max_buffered: usize,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let sink = DeltaSink {
+            store,
+            table_root,
+            buffer: Mutex::new(Vec::new()),
+            max_buffered,
+            room: Notify::new(),
+            table: Mutex::new(TableState {
+                next_version: 0,
+                table_id: uuid::Uuid::new_v4().to_string(),
+                schema_string: None,
+            }),
+        };
+        {
+            let mut table = sink.table.lock().await;
+            sink.replay_log(&mut table).await?;
+            debug!("Delta table at version {}", table.next_version);
+        }
+
+        Ok(sink)
+    }
+
+    /// Buffers `events` for the next commit, waiting while the buffer is full.
+    pub async fn append(&self, events: &[ProcessedEvent]) {
+        loop {
+            // Created before the check so a commit in between still wakes it
+            let room = self.room.notified();
+            {
+                let mut buffer = self.buffer.lock().await;
+                if buffer.len() < self.max_buffered {
+                    buffer.extend_from_slice(events);
+                    return;
+                }
+            }
+            warn!("Delta sink buffer is full, waiting for a commit");
+            room.await;
+        }
+    }
+
+    /// Number of events waiting for a commit.
+    pub async fn buffered(&self) -> usize {
+        self.buffer.lock().await.len()
+    }
+
+    pub fn start_commit_task(self: Arc<Self>, commit_interval: Duration) {
+        tokio::spawn(async move {
+            let mut interval = interval(commit_interval);
+
+            loop {
+                interval.tick().await;
+
+                if let Err(e) = self.commit().await {
+                    error!("Error in delta commit task: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Writes the buffered events as one Parquet file and commits it to the log.
+    /// Events whose commit fails go back to the buffer for the next one; a
+    /// Parquet file written without its log entry is never read.
+    pub async fn commit(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let events: Vec<ProcessedEvent> = self.buffer.lock().await.drain(..).collect();
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let result = self.write_commit(&events).await.map_err(|e| e.to_string());
+        match result {
+            Ok(()) => self.room.notify_waiters(),
+            Err(_) => {
+                // Ahead of what was appended since, to keep the order
+                self.buffer.lock().await.splice(0..0, events);
+            }
+        }
+        Ok(result?)
+    }
+
+    async fn write_commit(&self, events: &[ProcessedEvent]) -> Result<(), Box<dyn std::error::Error>> {
+        let data = write_parquet(events)?;
+        let size = data.len();
+        let file_name = format!("part-{}.snappy.parquet", uuid::Uuid::new_v4());
+        self.store
+            .put(&self.table_root.child(file_name.as_str()), PutPayload::from(data))
+            .await?;
+
+        let now_ms = now_millis();
+        let add = json!({
+            "add": {
+                "path": file_name,
+                "partitionValues": {},
+                "size": size,
+                "modificationTime": now_ms,
+                "dataChange": true,
+            }
+        });
+
+        let mut table = self.table.lock().await;
+        let schema_string = delta_schema_string();
+
+        // Optimistic concurrency: the log entry for a version may only be
+        // created once. Losing a version to another writer means replaying
+        // its commits, which may have changed the schema, before retrying.
+        for _ in 0..MAX_COMMIT_ATTEMPTS {
+            // Schema evolution: publish new metadata whenever our columns changed
+            let mut actions = vec![json!({ "commitInfo": { "timestamp": now_ms, "operation": "WRITE" } })];
+            if table.next_version == 0 {
+                actions.push(json!({ "protocol": { "minReaderVersion": 1, "minWriterVersion": 2 } }));
+            }
+            if table.schema_string.as_deref() != Some(schema_string.as_str()) {
+                actions.push(json!({
+                    "metaData": {
+                        "id": table.table_id,
+                        "format": { "provider": "parquet", "options": {} },
+                        "schemaString": schema_string,
+                        "partitionColumns": [],
+                        "configuration": {},
+                        "createdTime": now_ms,
+                    }
+                }));
+            }
+            actions.push(add.clone());
+
+            let body = actions
+                .iter()
+                .map(|action| action.to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            let version = table.next_version;
+            let options = PutOptions {
+                mode: PutMode::Create,
+                ..Default::default()
+            };
+
+            match self.store.put_opts(&self.log_path(version), PutPayload::from(body), options).await {
+                Ok(_) => {
+                    info!("Committed {} events to delta version {}", events.len(), version);
+                    table.next_version += 1;
+                    table.schema_string = Some(schema_string);
+                    return Ok(());
+                }
+                Err(object_store::Error::AlreadyExists { .. }) => {
+                    warn!("Delta version {} already exists, reloading the log", version);
+                    self.replay_log(&mut table).await?;
+                    // Even if the listing doesn't show the new version yet
+                    table.next_version = table.next_version.max(version + 1);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Err(format!("Failed to commit delta log after {} attempts", MAX_COMMIT_ATTEMPTS).into())
+    }
+
+    /// Replays the log entries from `table.next_version` on, to find the
+    /// next version and the current schema.
+    async fn replay_log(&self, table: &mut TableState) -> Result<(), Box<dyn std::error::Error>> {
+        let log_dir = self.table_root.child("_delta_log");
+        let from = table.next_version;
+        let mut commits: Vec<(u64, Path)> = self
+            .store
+            .list(Some(&log_dir))
+            .try_filter_map(|meta| async move {
+                let version = meta
+                    .location
+                    .filename()
+                    .and_then(|name| name.strip_suffix(".json"))
+                    .and_then(|version| version.parse::<u64>().ok())
+                    .filter(|version| *version >= from);
+                Ok(version.map(|version| (version, meta.location)))
+            })
+            .try_collect()
+            .await?;
+        commits.sort_by_key(|(version, _)| *version);
+
+        for (version, path) in commits {
+            let bytes = self.store.get(&path).await?.bytes().await?;
+            for line in String::from_utf8_lossy(&bytes).lines() {
+                let action: Value = match serde_json::from_str(line) {
+                    Ok(action) => action,
+                    Err(_) => continue,
+                };
+                if let Some(metadata) = action.get("metaData") {
+                    if let Some(id) = metadata.get("id").and_then(|v| v.as_str()) {
+                        table.table_id = id.to_string();
+                    }
+                    table.schema_string = metadata
+                        .get("schemaString")
+                        .and_then(|v| v.as_str())
+                        .map(|s| s.to_string());
+                }
+            }
+            table.next_version = version + 1;
+        }
+
+        Ok(())
+    }
+
+    fn log_path(&self, version: u64) -> Path {
+        self.table_root
+            .child("_delta_log")
+            .child(format!("{:020}.json", version).as_str())
+    }
+}
+
+fn arrow_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("tenant_id", DataType::Utf8, false),
+        Field::new("event_type", DataType::Utf8, false),
+        Field::new("user_id", DataType::Utf8, true),
+        Field::new("timestamp", DataType::Int64, false),
+        Field::new("ingested_at", DataType::Int64, false),
+        Field::new("properties", DataType::Utf8, false),
+        Field::new("metrics", DataType::Utf8, false),
+    ])
+}
+
+/// Spark StructType JSON for the arrow schema, as required by Delta metadata.
+fn delta_schema_string() -> String {
+    let fields: Vec<Value> = arrow_schema()
+        .fields()
+        .iter()
+        .map(|field| {
+            let data_type = match field.data_type() {
+                DataType::Int64 => "long",
+                _ => "string",
+            };
+            json!({
+                "name": field.name(),
+                "type": data_type,
+                "nullable": field.is_nullable(),
+                "metadata": {},
+            })
+        })
+        .collect();
+
+    json!({ "type": "struct", "fields": fields }).to_string()
+}
+
+/// One snappy-compressed Parquet file holding `events`.
+pub fn write_parquet(events: &[ProcessedEvent]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let schema = Arc::new(arrow_schema());
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(events.iter().map(|e| e.tenant_id.as_str()))),
+        Arc::new(StringArray::from_iter_values(events.iter().map(|e| e.event_type.as_str()))),
+        Arc::new(StringArray::from_iter(events.iter().map(|e| e.user_id.as_deref()))),
+        Arc::new(Int64Array::from_iter_values(events.iter().map(|e| e.timestamp))),
+        Arc::new(Int64Array::from_iter_values(events.iter().map(|e| e.ingested_at))),
+        Arc::new(StringArray::from(
+            events
+                .iter()
+                .map(|e| serde_json::to_string(&e.properties))
+                .collect::<Result<Vec<_>, _>>()?,
+        )),
+        Arc::new(StringArray::from(
+            events
+                .iter()
+                .map(|e| serde_json::to_string(&e.metrics))
+                .collect::<Result<Vec<_>, _>>()?,
+        )),
+    ];
+
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    let mut data = Vec::new();
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(&mut data, schema, Some(properties))?;
+    writer.write(&batch)?;
+    writer.close()?;
+
+    Ok(data)
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
@@ -0,0 +1 @@
+pub mod delta_sink;
\ No newline at end of file
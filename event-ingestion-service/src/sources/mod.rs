@@ -0,0 +1 @@
+pub mod mqtt_source;
\ No newline at end of file
@@ -0,0 +1,118 @@
+use crate::{CrmEvent, config::Config};
+use crate::processors::event_processor::EventProcessor;
+use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Packet, QoS};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::Duration;
+use tracing::{info, error, warn, debug};
+
+pub struct MqttSource {
+    client: AsyncClient,
+    eventloop: EventLoop,
+    topics: Vec<String>,
+}
+
+impl MqttSource {
+    pub fn new(config: &Config, broker: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let (host, port) = match broker.rsplit_once(':') {
+            Some((host, port)) => (host.to_string(), port.parse()?),
+            None => (broker.to_string(), 1883),
+        };
+
+        let mut options = MqttOptions::new(&config.mqtt_client_id, host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if let (Some(username), Some(password)) = (&config.mqtt_username, &config.mqtt_password) {
+            options.set_credentials(username, password);
+        }
+
+        let (client, eventloop) = AsyncClient::new(options, 100);
+
+        Ok(MqttSource {
+            client,
+            eventloop,
+            topics: config.mqtt_topics.clone(),
+        })
+    }
+
+    pub async fn run(mut self, processor: Arc<EventProcessor>) {
+        for topic in &self.topics {
+            if let Err(e) = self.client.subscribe(topic, QoS::AtLeastOnce).await {
+                error!("Failed to subscribe to MQTT topic {}: {}", topic, e);
+            }
+        }
+
+        info!("Connected to MQTT, subscribed to {:?}", self.topics);
+
+        loop {
+            match self.eventloop.poll().await {
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    let event = match Self::translate(&publish.topic, &publish.payload) {
+                        Some(event) => event,
+                        None => continue,
+                    };
+
+                    info!("Processing MQTT event: {} for tenant: {}", event.event_type, event.tenant_id);
+
+                    if let Err(e) = processor.process_event(event).await {
+                        error!("Error processing MQTT message: {}", e);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    // The event loop reconnects on the next poll
+                    error!("MQTT connection error: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+
+    /// Maps `<prefix>/<tenant_id>/<event_type>` topics onto a CrmEvent.
+    pub fn translate(topic: &str, payload: &[u8]) -> Option<CrmEvent> {
+        let mut segments = topic.rsplit('/');
+        let event_type = segments.next().filter(|s| !s.is_empty());
+        let tenant_id = segments.next().filter(|s| !s.is_empty());
+
+        let (tenant_id, event_type) = match (tenant_id, event_type) {
+            (Some(tenant_id), Some(event_type)) => (tenant_id.to_string(), event_type.to_string()),
+            _ => {
+                warn!("Ignoring MQTT message on unmapped topic: {}", topic);
+                return None;
+            }
+        };
+
+        let payload: Value = match serde_json::from_slice(payload) {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("Ignoring non-JSON MQTT message on {}: {}", topic, e);
+                return None;
+            }
+        };
+
+        debug!("Translated MQTT topic {} to {}/{}", topic, tenant_id, event_type);
+
+        let timestamp = payload
+            .get("timestamp")
+            .and_then(|v| v.as_i64())
+            .unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs() as i64)
+                    .unwrap_or_default()
+            });
+        let user_id = payload
+            .get("user_id")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+
+        Some(CrmEvent {
+            tenant_id,
+            event_type,
+            payload,
+            timestamp,
+            source: Some("mqtt".to_string()),
+            user_id,
+        })
+    }
+}
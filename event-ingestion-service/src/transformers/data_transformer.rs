@@ -1,8 +1,11 @@
 use crate::{CrmEvent, processors::event_processor::ProcessedEvent};
+use crm_events::{DealUpdated, EmailSent, Identify, LeadCreated, PageView, UserLogin};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::{debug, warn};
 
+#[derive(Default)]
 pub struct DataTransformer {
     // Add any transformation rules or configuration here
 }
@@ -15,6 +18,11 @@ impl DataTransformer {
     pub async fn transform_event(&self, event: CrmEvent) -> Result<ProcessedEvent, Box<dyn std::error::Error>> {
         debug!("Transforming event: {}", event.event_type);
 
+        let ingested_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+
         let mut properties = HashMap::new();
         let mut metrics = HashMap::new();
 
@@ -39,8 +47,17 @@ impl DataTransformer {
             "user_login" => self.transform_user_login(&event, &mut properties, &mut metrics)?,
             "lead_created" => self.transform_lead_created(&event, &mut properties, &mut metrics)?,
             "deal_updated" => self.transform_deal_updated(&event, &mut properties, &mut metrics)?,
+            "deal_won" => self.transform_deal_won(&event, &mut properties, &mut metrics)?,
             "email_sent" => self.transform_email_sent(&event, &mut properties, &mut metrics)?,
+            "email_opened" | "email_clicked" | "email_bounced" | "email_unsubscribed" => {
+                self.transform_email_engagement(&event, &mut properties, &mut metrics)?
+            }
             "page_view" => self.transform_page_view(&event, &mut properties, &mut metrics)?,
+            "identify" => self.transform_identify(&event, &mut properties)?,
+            "call_logged" => self.transform_call_logged(&event, &mut properties, &mut metrics)?,
+            "meeting_scheduled" | "meeting_completed" => {
+                self.transform_meeting(&event, &mut properties, &mut metrics)?
+            }
             _ => {
                 warn!("Unknown event type: {}", event.event_type);
                 // Default transformation - just copy payload
@@ -52,6 +69,8 @@ impl DataTransformer {
             event_type: event.event_type,
             user_id: event.user_id,
             timestamp: event.timestamp,
+            ingested_at,
+            retention_days: 0,
             properties,
             metrics,
         })
@@ -64,12 +83,14 @@ impl DataTransformer {
         metrics: &mut HashMap<String, f64>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         // Extract login-specific data
-        if let Some(ip_address) = event.payload.get("ip_address") {
-            properties.insert("ip_address".to_string(), ip_address.clone());
+        let login: UserLogin = event.payload_as()?;
+
+        if let Some(ip_address) = login.ip_address {
+            properties.insert("ip_address".to_string(), Value::String(ip_address));
         }
 
-        if let Some(user_agent) = event.payload.get("user_agent") {
-            properties.insert("user_agent".to_string(), user_agent.clone());
+        if let Some(user_agent) = login.user_agent {
+            properties.insert("user_agent".to_string(), Value::String(user_agent));
         }
 
         // Add login success metric
@@ -85,11 +106,13 @@ impl DataTransformer {
         metrics: &mut HashMap<String, f64>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         // Extract lead data
-        if let Some(lead_source) = event.payload.get("source") {
-            properties.insert("lead_source".to_string(), lead_source.clone());
+        let lead: LeadCreated = event.payload_as()?;
+
+        if let Some(lead_source) = lead.source {
+            properties.insert("lead_source".to_string(), Value::String(lead_source));
         }
 
-        if let Some(lead_score) = event.payload.get("score").and_then(|v| v.as_f64()) {
+        if let Some(lead_score) = lead.score {
             metrics.insert("lead_score".to_string(), lead_score);
         }
 
@@ -106,15 +129,17 @@ impl DataTransformer {
         metrics: &mut HashMap<String, f64>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         // Extract deal data
-        if let Some(stage) = event.payload.get("stage") {
-            properties.insert("deal_stage".to_string(), stage.clone());
+        let deal: DealUpdated = event.payload_as()?;
+
+        if let Some(stage) = &deal.stage {
+            properties.insert("deal_stage".to_string(), Value::String(stage.clone()));
         }
 
-        if let Some(amount) = event.payload.get("amount").and_then(|v| v.as_f64()) {
+        if let Some(amount) = deal.amount {
             metrics.insert("deal_amount".to_string(), amount);
         }
 
-        if let Some(probability) = event.payload.get("probability").and_then(|v| v.as_f64()) {
+        if let Some(probability) = deal.probability {
             metrics.insert("deal_probability".to_string(), probability);
         }
 
@@ -126,6 +151,14 @@ impl DataTransformer {
             metrics.insert("expected_value".to_string(), amount * (probability / 100.0));
         }
 
+        // A deal moved to closed-won is a derived deal_won; later edits of a
+        // won deal, or updates not saying where it came from, aren't, so
+        // its revenue is counted once
+        let won = |stage: Option<&str>| stage == Some("closed_won");
+        if won(deal.stage.as_deref()) && deal.previous_stage.is_some() && !won(deal.previous_stage.as_deref()) {
+            self.transform_deal_won(event, properties, metrics)?;
+        }
+
         Ok(())
     }
 
@@ -136,12 +169,14 @@ impl DataTransformer {
         metrics: &mut HashMap<String, f64>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         // Extract email data
-        if let Some(campaign_id) = event.payload.get("campaign_id") {
-            properties.insert("campaign_id".to_string(), campaign_id.clone());
+        let email: EmailSent = event.payload_as()?;
+
+        if let Some(campaign_id) = email.campaign_id {
+            properties.insert("campaign_id".to_string(), Value::String(campaign_id));
         }
 
-        if let Some(template_id) = event.payload.get("template_id") {
-            properties.insert("template_id".to_string(), template_id.clone());
+        if let Some(template_id) = email.template_id {
+            properties.insert("template_id".to_string(), Value::String(template_id));
         }
 
         // Email metrics
@@ -157,21 +192,206 @@ impl DataTransformer {
         metrics: &mut HashMap<String, f64>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         // Extract page view data
-        if let Some(page_url) = event.payload.get("page_url") {
-            properties.insert("page_url".to_string(), page_url.clone());
+        let view: PageView = event.payload_as()?;
+
+        if let Some(page_url) = view.page_url {
+            properties.insert("page_url".to_string(), Value::String(page_url));
         }
 
-        if let Some(referrer) = event.payload.get("referrer") {
-            properties.insert("referrer".to_string(), referrer.clone());
+        if let Some(referrer) = view.referrer {
+            properties.insert("referrer".to_string(), Value::String(referrer));
         }
 
-        if let Some(session_duration) = event.payload.get("session_duration").and_then(|v| v.as_f64()) {
+        if let Some(session_duration) = view.session_duration {
             metrics.insert("session_duration".to_string(), session_duration);
         }
 
+        if let Some(anonymous_id) = view.anonymous_id {
+            properties.insert("anonymous_id".to_string(), Value::String(anonymous_id));
+        }
+
         // Page view metrics
         metrics.insert("page_views".to_string(), 1.0);
 
         Ok(())
     }
+
+    fn transform_identify(
+        &self,
+        event: &CrmEvent,
+        properties: &mut HashMap<String, Value>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Validates the payload; the identity stage records the mapping
+        let identify: Identify = event.payload_as()?;
+        properties.insert("anonymous_id".to_string(), Value::String(identify.anonymous_id));
+
+        Ok(())
+    }
+
+    fn transform_email_engagement(
+        &self,
+        event: &CrmEvent,
+        properties: &mut HashMap<String, Value>,
+        metrics: &mut HashMap<String, f64>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Shared email identifiers for campaign-level rollups
+        for key in ["campaign_id", "template_id", "message_id", "recipient"] {
+            if let Some(value) = event.payload.get(key) {
+                properties.insert(key.to_string(), value.clone());
+            }
+        }
+
+        match event.event_type.as_str() {
+            "email_opened" => {
+                metrics.insert("emails_opened".to_string(), 1.0);
+            }
+            "email_clicked" => {
+                if let Some(link_url) = event.payload.get("link_url") {
+                    properties.insert("link_url".to_string(), link_url.clone());
+                }
+                metrics.insert("emails_clicked".to_string(), 1.0);
+            }
+            "email_bounced" => {
+                if let Some(bounce_type) = event.payload.get("bounce_type") {
+                    properties.insert("bounce_type".to_string(), bounce_type.clone());
+                }
+                metrics.insert("emails_bounced".to_string(), 1.0);
+            }
+            "email_unsubscribed" => {
+                if let Some(reason) = event.payload.get("reason") {
+                    properties.insert("unsubscribe_reason".to_string(), reason.clone());
+                }
+                metrics.insert("emails_unsubscribed".to_string(), 1.0);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn transform_call_logged(
+        &self,
+        event: &CrmEvent,
+        properties: &mut HashMap<String, Value>,
+        metrics: &mut HashMap<String, f64>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.attribute_rep(event, properties);
+
+        // Extract call data
+        if let Some(outcome) = event.payload.get("outcome") {
+            properties.insert("call_outcome".to_string(), outcome.clone());
+        }
+
+        if let Some(direction) = event.payload.get("direction") {
+            properties.insert("call_direction".to_string(), direction.clone());
+        }
+
+        if let Some(duration) = event.payload.get("duration_seconds").and_then(|v| v.as_f64()) {
+            metrics.insert("call_duration_seconds".to_string(), duration);
+        }
+
+        // Call metrics
+        metrics.insert("calls_logged".to_string(), 1.0);
+        if event.payload.get("outcome").and_then(|v| v.as_str()) == Some("connected") {
+            metrics.insert("calls_connected".to_string(), 1.0);
+        }
+
+        Ok(())
+    }
+
+    fn transform_meeting(
+        &self,
+        event: &CrmEvent,
+        properties: &mut HashMap<String, Value>,
+        metrics: &mut HashMap<String, f64>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.attribute_rep(event, properties);
+
+        // Extract meeting data
+        if let Some(meeting_type) = event.payload.get("meeting_type") {
+            properties.insert("meeting_type".to_string(), meeting_type.clone());
+        }
+
+        if let Some(deal_id) = event.payload.get("deal_id") {
+            properties.insert("deal_id".to_string(), deal_id.clone());
+        }
+
+        if event.event_type == "meeting_completed" {
+            if let Some(outcome) = event.payload.get("outcome") {
+                properties.insert("meeting_outcome".to_string(), outcome.clone());
+            }
+
+            if let Some(duration) = event.payload.get("duration_minutes").and_then(|v| v.as_f64()) {
+                metrics.insert("meeting_duration_minutes".to_string(), duration);
+            }
+
+            metrics.insert("meetings_completed".to_string(), 1.0);
+        } else {
+            if let Some(scheduled_for) = event.payload.get("scheduled_for") {
+                properties.insert("scheduled_for".to_string(), scheduled_for.clone());
+            }
+
+            metrics.insert("meetings_scheduled".to_string(), 1.0);
+        }
+
+        Ok(())
+    }
+
+    /// Credits sales activity to the owning rep, falling back to the acting user.
+    fn attribute_rep(&self, event: &CrmEvent, properties: &mut HashMap<String, Value>) {
+        let rep_id = event
+            .payload
+            .get("rep_id")
+            .or_else(|| event.payload.get("owner_id"))
+            .cloned()
+            .or_else(|| event.user_id.clone().map(Value::String));
+
+        if let Some(rep_id) = rep_id {
+            properties.insert("rep_id".to_string(), rep_id);
+        }
+    }
+
+    fn transform_deal_won(
+        &self,
+        event: &CrmEvent,
+        properties: &mut HashMap<String, Value>,
+        metrics: &mut HashMap<String, f64>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let amount = match event.payload.get("amount").and_then(|v| v.as_f64()) {
+            Some(amount) => amount,
+            None => {
+                warn!("deal won without amount for tenant: {}", event.tenant_id);
+                return Ok(());
+            }
+        };
+
+        // Amount is the total contract value over the term
+        let term_months = event
+            .payload
+            .get("term_months")
+            .and_then(|v| v.as_f64())
+            .filter(|term| *term > 0.0)
+            .unwrap_or(12.0);
+        let billing_frequency = event
+            .payload
+            .get("billing_frequency")
+            .and_then(|v| v.as_str())
+            .unwrap_or("annual");
+
+        properties.insert("billing_frequency".to_string(), Value::String(billing_frequency.to_string()));
+        metrics.insert("contract_value".to_string(), amount);
+        metrics.insert("term_months".to_string(), term_months);
+        metrics.insert("deals_won".to_string(), 1.0);
+
+        // One-time fees are recognized immediately and do not recur
+        if billing_frequency == "one_time" {
+            metrics.insert("one_time_revenue".to_string(), amount);
+        } else {
+            let mrr = amount / term_months;
+            metrics.insert("mrr".to_string(), mrr);
+            metrics.insert("arr".to_string(), mrr * 12.0);
+        }
+
+        Ok(())
+    }
 }
\ No newline at end of file
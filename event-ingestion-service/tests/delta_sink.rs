@@ -0,0 +1,169 @@
+use bytes::Bytes;
+use event_ingestion_service::processors::event_processor::ProcessedEvent;
+use event_ingestion_service::sinks::delta_sink::DeltaSink;
+use object_store::local::LocalFileSystem;
+use object_store::memory::InMemory;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn event(user: &str) -> ProcessedEvent {
+    ProcessedEvent {
+        tenant_id: "t1".to_string(),
+        event_type: "page_view".to_string(),
+        user_id: Some(user.to_string()),
+        timestamp: 1_700_000_000_000,
+        ingested_at: 1_700_000_000_500,
+        retention_days: 0,
+        properties: HashMap::new(),
+        metrics: HashMap::new(),
+    }
+}
+
+fn table() -> Path {
+    Path::from("lake/events")
+}
+
+async fn log_entry(store: &dyn ObjectStore, version: u64) -> Vec<Value> {
+    let path = table().child("_delta_log").child(format!("{:020}.json", version).as_str());
+    let bytes = store.get(&path).await.unwrap().bytes().await.unwrap();
+    String::from_utf8(bytes.to_vec())
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+/// Users of the events in the file added by a log entry.
+async fn added_users(store: &dyn ObjectStore, actions: &[Value]) -> Vec<String> {
+    let file = actions.iter().find_map(|action| action["add"]["path"].as_str()).unwrap();
+    let data: Bytes = store.get(&table().child(file)).await.unwrap().bytes().await.unwrap();
+    let mut users = Vec::new();
+    for batch in ParquetRecordBatchReaderBuilder::try_new(data).unwrap().build().unwrap() {
+        let batch = batch.unwrap();
+        let column = batch.column_by_name("user_id").unwrap();
+        let column = column.as_any().downcast_ref::<arrow_array::StringArray>().unwrap();
+        users.extend(column.iter().map(|user| user.unwrap().to_string()));
+    }
+    users
+}
+
+fn has(actions: &[Value], kind: &str) -> bool {
+    actions.iter().any(|action| action.get(kind).is_some())
+}
+
+#[tokio::test]
+async fn commits_buffered_events_to_the_log() {
+    let store = Arc::new(InMemory::new());
+    let sink = DeltaSink::with_store(store.clone(), table(), 100).await.unwrap();
+
+    // Nothing buffered, nothing written
+    sink.commit().await.unwrap();
+    assert!(store.get(&table().child("_delta_log").child("00000000000000000000.json")).await.is_err());
+
+    sink.append(&[event("u1"), event("u2")]).await;
+    sink.append(&[event("u3")]).await;
+    sink.commit().await.unwrap();
+    assert_eq!(sink.buffered().await, 0);
+
+    let first = log_entry(store.as_ref(), 0).await;
+    assert!(has(&first, "protocol"));
+    assert!(has(&first, "metaData"));
+    assert_eq!(added_users(store.as_ref(), &first).await, ["u1", "u2", "u3"]);
+
+    // The schema is unchanged, so later versions only add files
+    sink.append(&[event("u4")]).await;
+    sink.commit().await.unwrap();
+    let second = log_entry(store.as_ref(), 1).await;
+    assert!(!has(&second, "protocol"));
+    assert!(!has(&second, "metaData"));
+    assert_eq!(added_users(store.as_ref(), &second).await, ["u4"]);
+}
+
+#[tokio::test]
+async fn continues_an_existing_table() {
+    let store = Arc::new(InMemory::new());
+    let first = DeltaSink::with_store(store.clone(), table(), 100).await.unwrap();
+    first.append(&[event("u1")]).await;
+    first.commit().await.unwrap();
+
+    let second = DeltaSink::with_store(store.clone(), table(), 100).await.unwrap();
+    second.append(&[event("u2")]).await;
+    second.commit().await.unwrap();
+
+    let actions = log_entry(store.as_ref(), 1).await;
+    assert!(!has(&actions, "metaData"));
+    assert_eq!(added_users(store.as_ref(), &actions).await, ["u2"]);
+}
+
+#[tokio::test]
+async fn replays_versions_committed_by_another_writer() {
+    let store = Arc::new(InMemory::new());
+    // Both start from an empty table
+    let ours = DeltaSink::with_store(store.clone(), table(), 100).await.unwrap();
+    let theirs = DeltaSink::with_store(store.clone(), table(), 100).await.unwrap();
+
+    theirs.append(&[event("u1")]).await;
+    theirs.commit().await.unwrap();
+
+    // Version 0 is taken: ours goes to version 1, and as the other writer
+    // already published the schema it doesn't publish it (or the protocol) again
+    ours.append(&[event("u2")]).await;
+    ours.commit().await.unwrap();
+
+    let winner = log_entry(store.as_ref(), 0).await;
+    assert_eq!(added_users(store.as_ref(), &winner).await, ["u1"]);
+    let retried = log_entry(store.as_ref(), 1).await;
+    assert!(!has(&retried, "protocol"));
+    assert!(!has(&retried, "metaData"));
+    assert_eq!(added_users(store.as_ref(), &retried).await, ["u2"]);
+}
+
+#[tokio::test]
+async fn keeps_events_whose_commit_failed() {
+    let root = std::env::temp_dir().join(format!("delta-sink-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(root.join("lake/events")).unwrap();
+    let store = Arc::new(LocalFileSystem::new_with_prefix(&root).unwrap());
+    let sink = DeltaSink::with_store(store.clone(), table(), 100).await.unwrap();
+
+    // A file where the log directory should be fails the commit
+    let log_dir = root.join("lake/events/_delta_log");
+    std::fs::write(&log_dir, b"").unwrap();
+    sink.append(&[event("u1"), event("u2")]).await;
+    assert!(sink.commit().await.is_err());
+    assert_eq!(sink.buffered().await, 2);
+
+    // Retried with what was appended since, in order
+    sink.append(&[event("u3")]).await;
+    std::fs::remove_file(&log_dir).unwrap();
+    sink.commit().await.unwrap();
+    assert_eq!(sink.buffered().await, 0);
+
+    let actions = log_entry(store.as_ref(), 0).await;
+    assert_eq!(added_users(store.as_ref(), &actions).await, ["u1", "u2", "u3"]);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn appends_wait_while_the_buffer_is_full() {
+    let store = Arc::new(InMemory::new());
+    let sink = Arc::new(DeltaSink::with_store(store, table(), 2).await.unwrap());
+    sink.append(&[event("u1"), event("u2")]).await;
+
+    let waiting = tokio::spawn({
+        let sink = Arc::clone(&sink);
+        async move { sink.append(&[event("u3")]).await }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!waiting.is_finished());
+    assert_eq!(sink.buffered().await, 2);
+
+    sink.commit().await.unwrap();
+    tokio::time::timeout(Duration::from_secs(1), waiting).await.unwrap().unwrap();
+    assert_eq!(sink.buffered().await, 1);
+}
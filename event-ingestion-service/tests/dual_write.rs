@@ -0,0 +1,155 @@
+use event_ingestion_service::config::Config;
+use event_ingestion_service::processors::dual_write::DualWrite;
+use event_ingestion_service::processors::event_processor::{EventProcessor, ProcessedEvent};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use warp::http::StatusCode;
+use warp::Filter;
+
+fn config(until: Option<i64>) -> Config {
+    Config {
+        dual_write_tables: HashMap::from([("events".to_string(), "events_v2".to_string())]),
+        dual_write_until: until,
+        ..Config::default()
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+}
+
+fn event(user: &str) -> ProcessedEvent {
+    ProcessedEvent {
+        tenant_id: "t1".to_string(),
+        event_type: "page_view".to_string(),
+        user_id: Some(user.to_string()),
+        timestamp: 1_700_000_000_000,
+        ingested_at: 1_700_000_000,
+        retention_days: 30,
+        properties: HashMap::new(),
+        metrics: HashMap::new(),
+    }
+}
+
+/// What a fake ClickHouse was asked, and how it answers.
+#[derive(Default)]
+struct ClickHouse {
+    /// Tables whose queries fail.
+    failing: Vec<&'static str>,
+    /// Row count and checksum per table, for fingerprint queries.
+    fingerprints: HashMap<&'static str, (u64, u64)>,
+    queries: Mutex<Vec<String>>,
+}
+
+impl ClickHouse {
+    fn inserts(&self) -> Vec<String> {
+        let queries = self.queries.lock().unwrap();
+        queries.iter().filter_map(|query| query.strip_prefix("INSERT INTO ")).map(table_name).collect()
+    }
+}
+
+fn table_name(query: &str) -> String {
+    query.split(['(', ' ']).next().unwrap().trim_matches('`').to_string()
+}
+
+async fn serve(clickhouse: Arc<ClickHouse>) -> clickhouse::Client {
+    let route = warp::any().and(warp::query::<HashMap<String, String>>()).and(warp::body::bytes()).map(
+        move |params: HashMap<String, String>, body: bytes::Bytes| {
+            let query = params.get("query").cloned().unwrap_or_else(|| String::from_utf8_lossy(&body).to_string());
+            clickhouse.queries.lock().unwrap().push(query.clone());
+            // The table follows INSERT INTO, or FROM for a fingerprint
+            let table = match query.strip_prefix("INSERT INTO ") {
+                Some(rest) => table_name(rest),
+                None => table_name(query.split(" FROM ").nth(1).unwrap_or_default()),
+            };
+            if clickhouse.failing.contains(&table.as_str()) {
+                let error = format!("Code: 60. DB::Exception: Table {} doesn't exist", table);
+                return warp::reply::with_status(error.into_bytes(), StatusCode::NOT_FOUND);
+            }
+            // RowBinary: the count and the checksum as little-endian u64s
+            let (rows, checksum) = clickhouse.fingerprints.get(table.as_str()).copied().unwrap_or_default();
+            let mut row = rows.to_le_bytes().to_vec();
+            row.extend(checksum.to_le_bytes());
+            let reply = if query.starts_with("INSERT") { Vec::new() } else { row };
+            warp::reply::with_status(reply, StatusCode::OK)
+        },
+    );
+    let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    clickhouse::Client::default()
+        .with_url(format!("http://{}", address))
+        .with_compression(clickhouse::Compression::None)
+}
+
+#[test]
+fn only_listed_tables_are_dual_written_and_only_until_the_end() {
+    assert!(DualWrite::new(&Config::default()).is_none());
+
+    let dual_write = DualWrite::new(&config(None)).unwrap();
+    assert_eq!(dual_write.next_table("events"), Some("events_v2"));
+    assert_eq!(dual_write.next_table("page_views"), None);
+
+    let dual_write = DualWrite::new(&config(Some(now() + 3600))).unwrap();
+    assert_eq!(dual_write.next_table("events"), Some("events_v2"));
+    let dual_write = DualWrite::new(&config(Some(now() - 1))).unwrap();
+    assert_eq!(dual_write.next_table("events"), None);
+}
+
+#[tokio::test]
+async fn batches_go_to_both_generations() {
+    let clickhouse = Arc::new(ClickHouse::default());
+    let client = serve(clickhouse.clone()).await;
+    let dual_write = DualWrite::new(&config(None)).unwrap();
+
+    EventProcessor::flush_events_static(&client, &HashMap::new(), Some(&dual_write), vec![event("u1"), event("u2")])
+        .await
+        .unwrap();
+    assert_eq!(clickhouse.inserts(), ["events", "events_v2"]);
+}
+
+#[tokio::test]
+async fn a_failed_write_to_the_next_table_doesnt_fail_the_batch() {
+    let clickhouse = Arc::new(ClickHouse { failing: vec!["events_v2"], ..ClickHouse::default() });
+    let client = serve(clickhouse.clone()).await;
+    let dual_write = DualWrite::new(&config(None)).unwrap();
+
+    EventProcessor::flush_events_static(&client, &HashMap::new(), Some(&dual_write), vec![event("u1")])
+        .await
+        .unwrap();
+    assert_eq!(clickhouse.inserts(), ["events", "events_v2"]);
+}
+
+#[tokio::test]
+async fn a_failed_write_to_the_current_table_skips_the_next_one() {
+    let clickhouse = Arc::new(ClickHouse { failing: vec!["events"], ..ClickHouse::default() });
+    let client = serve(clickhouse.clone()).await;
+    let dual_write = DualWrite::new(&config(None)).unwrap();
+
+    let routes = HashMap::new();
+    let flushed = EventProcessor::flush_events_static(&client, &routes, Some(&dual_write), vec![event("u1")]).await;
+    assert!(flushed.is_err());
+    assert_eq!(clickhouse.inserts(), ["events"]);
+}
+
+#[tokio::test]
+async fn windows_match_when_both_generations_have_the_same_rows() {
+    let dual_write = DualWrite::new(&config(None)).unwrap();
+
+    let same = HashMap::from([("events", (3, 42)), ("events_v2", (3, 42))]);
+    let clickhouse = Arc::new(ClickHouse { fingerprints: same, ..ClickHouse::default() });
+    assert!(dual_write.compare(&serve(clickhouse.clone()).await, 0, 60).await.unwrap());
+    // Over the same window of both tables
+    let queries = clickhouse.queries.lock().unwrap().clone();
+    assert_eq!(queries.len(), 2);
+    assert!(queries.iter().all(|query| query.contains("ingested_at >= 0 AND ingested_at < 60")), "{:?}", queries);
+
+    // Same count, different rows
+    let different = HashMap::from([("events", (3, 42)), ("events_v2", (3, 41))]);
+    let clickhouse = Arc::new(ClickHouse { fingerprints: different, ..ClickHouse::default() });
+    assert!(!dual_write.compare(&serve(clickhouse).await, 0, 60).await.unwrap());
+
+    // The next table isn't there yet: nothing to conclude, retried later
+    let clickhouse = Arc::new(ClickHouse { failing: vec!["events_v2"], ..ClickHouse::default() });
+    assert!(dual_write.compare(&serve(clickhouse).await, 0, 60).await.is_err());
+}
@@ -0,0 +1,40 @@
+use event_ingestion_service::sources::mqtt_source::MqttSource;
+use serde_json::json;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[test]
+fn maps_the_topic_onto_tenant_and_event_type() {
+    let payload = json!({ "timestamp": 1_700_000_000, "user_id": "u-7", "duration_secs": 42 });
+    let event = MqttSource::translate("crm/t1/call_ended", payload.to_string().as_bytes()).unwrap();
+
+    assert_eq!(event.tenant_id, "t1");
+    assert_eq!(event.event_type, "call_ended");
+    assert_eq!(event.timestamp, 1_700_000_000);
+    assert_eq!(event.user_id.as_deref(), Some("u-7"));
+    assert_eq!(event.source.as_deref(), Some("mqtt"));
+    assert_eq!(event.payload, payload);
+
+    // Only the last two segments count
+    let event = MqttSource::translate("site/a/devices/t2/door_opened", b"{}").unwrap();
+    assert_eq!((event.tenant_id.as_str(), event.event_type.as_str()), ("t2", "door_opened"));
+}
+
+#[test]
+fn stamps_events_without_a_timestamp_on_arrival() {
+    let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+    for payload in [json!({}), json!({ "timestamp": "yesterday" }), json!({ "user_id": 7 })] {
+        let event = MqttSource::translate("crm/t1/badge_scan", payload.to_string().as_bytes()).unwrap();
+        assert!(event.timestamp >= before, "{}", payload);
+        assert_eq!(event.user_id, None, "{}", payload);
+    }
+}
+
+#[test]
+fn ignores_unmapped_topics_and_malformed_payloads() {
+    for topic in ["call_ended", "crm/t1/", "crm//call_ended", ""] {
+        assert!(MqttSource::translate(topic, b"{}").is_none(), "{:?}", topic);
+    }
+    for payload in [&b"not json"[..], b"", b"{\"truncated\":"] {
+        assert!(MqttSource::translate("crm/t1/call_ended", payload).is_none(), "{:?}", payload);
+    }
+}
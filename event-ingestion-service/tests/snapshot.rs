@@ -0,0 +1,64 @@
+use event_ingestion_service::processors::event_processor::ProcessedEvent;
+use event_ingestion_service::processors::snapshot::BufferSnapshot;
+use event_ingestion_service::processors::window_aggregator::WindowAggregator;
+use serde_json::json;
+use std::collections::HashMap;
+
+fn event(user: &str, timestamp: i64) -> ProcessedEvent {
+    ProcessedEvent {
+        tenant_id: "t1".to_string(),
+        event_type: "page_view".to_string(),
+        user_id: Some(user.to_string()),
+        timestamp,
+        ingested_at: timestamp + 1,
+        retention_days: 30,
+        properties: HashMap::from([("url".to_string(), json!("/pricing"))]),
+        metrics: HashMap::from([("duration_ms".to_string(), 250.0)]),
+    }
+}
+
+#[test]
+fn restores_buffered_events_and_open_windows_once() {
+    let dir = std::env::temp_dir().join(format!("ingestion-snapshot-{}", uuid::Uuid::new_v4()));
+    let path = dir.join("state").join("snapshot.json").display().to_string();
+
+    let mut aggregator = WindowAggregator::new(60);
+    aggregator.record(&event("u1", 100));
+    aggregator.record(&event("u2", 110));
+    let snapshot = BufferSnapshot {
+        events: vec![event("u1", 100), event("u3", 130)],
+        windows: aggregator.snapshot(),
+    };
+    snapshot.save(&path).unwrap();
+    assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+
+    let restored = BufferSnapshot::take(&path).unwrap().unwrap();
+    let users: Vec<_> = restored.events.iter().map(|e| e.user_id.as_deref().unwrap()).collect();
+    assert_eq!(users, ["u1", "u3"]);
+    assert_eq!(restored.events[1].retention_days, 30);
+    assert_eq!(restored.events[1].properties["url"], "/pricing");
+
+    // The windows carry on where they were
+    let mut aggregator = WindowAggregator::new(60);
+    aggregator.restore(restored.windows);
+    aggregator.record(&event("u3", 115));
+    let rollups = aggregator.drain_closed(120);
+    assert_eq!(rollups.len(), 1);
+    assert_eq!((rollups[0].event_count, rollups[0].unique_users), (3, 3));
+    assert_eq!(rollups[0].metrics["duration_ms"], 750.0);
+
+    // Taken, so a second restart doesn't replay it
+    assert!(BufferSnapshot::take(&path).unwrap().is_none());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn refuses_a_corrupt_snapshot() {
+    let path = std::env::temp_dir().join(format!("ingestion-snapshot-{}.json", uuid::Uuid::new_v4()));
+    std::fs::write(&path, b"{\"events\": [").unwrap();
+
+    let path = path.display().to_string();
+    assert!(BufferSnapshot::take(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,102 @@
+use event_ingestion_service::processors::event_processor::ProcessedEvent;
+use event_ingestion_service::processors::window_aggregator::{MetricRollup, WindowAggregator};
+use serde_json::json;
+use std::collections::HashMap;
+
+fn event(event_type: &str, user: Option<&str>, timestamp: i64, metrics: &[(&str, f64)]) -> ProcessedEvent {
+    ProcessedEvent {
+        tenant_id: "t1".to_string(),
+        event_type: event_type.to_string(),
+        user_id: user.map(|user| user.to_string()),
+        timestamp,
+        ingested_at: timestamp,
+        retention_days: 0,
+        properties: HashMap::new(),
+        metrics: metrics.iter().map(|(name, value)| (name.to_string(), *value)).collect(),
+    }
+}
+
+fn sorted(mut rollups: Vec<MetricRollup>) -> Vec<MetricRollup> {
+    rollups.sort_by(|a, b| (a.window_start, &a.event_type).cmp(&(b.window_start, &b.event_type)));
+    rollups
+}
+
+#[test]
+fn windows_start_on_multiples_of_their_length() {
+    let mut aggregator = WindowAggregator::new(60);
+    // 120 opens the [120, 180) window; 119 and 179 are the last seconds
+    // of theirs
+    for timestamp in [119, 120, 150, 179, 180] {
+        aggregator.record(&event("page_view", None, timestamp, &[]));
+    }
+
+    let rollups = sorted(aggregator.drain_closed(240));
+    let windows: Vec<(i64, i64, u64)> = rollups.iter().map(|r| (r.window_start, r.window_end, r.event_count)).collect();
+    assert_eq!(windows, [(60, 120, 1), (120, 180, 3), (180, 240, 1)]);
+}
+
+#[test]
+fn windows_close_once_the_watermark_reaches_their_end() {
+    let mut aggregator = WindowAggregator::new(60);
+    aggregator.record(&event("page_view", None, 130, &[]));
+
+    assert!(aggregator.drain_closed(179).is_empty());
+    let rollups = aggregator.drain_closed(180);
+    assert_eq!(rollups.len(), 1);
+    assert_eq!((rollups[0].window_start, rollups[0].window_end), (120, 180));
+
+    // Drained windows aren't published twice
+    assert!(aggregator.drain_closed(1_000).is_empty());
+}
+
+#[test]
+fn late_events_count_until_their_window_is_drained() {
+    let mut aggregator = WindowAggregator::new(60);
+    aggregator.record(&event("page_view", Some("u1"), 170, &[]));
+    aggregator.record(&event("page_view", Some("u3"), 185, &[]));
+    // Arrives after the next window opened, within the grace period the
+    // watermark lags behind by
+    aggregator.record(&event("page_view", Some("u2"), 175, &[]));
+
+    let rollups = aggregator.drain_closed(180);
+    assert_eq!(rollups.len(), 1);
+    assert_eq!((rollups[0].window_start, rollups[0].event_count, rollups[0].unique_users), (120, 2, 2));
+
+    // Later than that, they make a rollup of their own for the window
+    aggregator.record(&event("page_view", Some("u4"), 178, &[]));
+    let rollups = sorted(aggregator.drain_closed(240));
+    let windows: Vec<(i64, u64)> = rollups.iter().map(|r| (r.window_start, r.event_count)).collect();
+    assert_eq!(windows, [(120, 1), (180, 1)]);
+}
+
+#[test]
+fn rollups_count_users_and_sum_metrics_per_tenant_and_event_type() {
+    let mut aggregator = WindowAggregator::new(60);
+    aggregator.record(&event("deal_won", Some("u1"), 0, &[("amount", 1200.0)]));
+    aggregator.record(&event("deal_won", Some("u1"), 10, &[("amount", 800.0), ("seats", 5.0)]));
+    aggregator.record(&event("deal_won", Some("u2"), 20, &[("amount", 500.0)]));
+    aggregator.record(&event("deal_won", None, 30, &[]));
+    aggregator.record(&event("page_view", Some("u1"), 40, &[]));
+    aggregator.record(&ProcessedEvent { tenant_id: "t2".to_string(), ..event("deal_won", Some("u9"), 50, &[]) });
+
+    let rollups = aggregator.drain_closed(60);
+    assert_eq!(rollups.len(), 3);
+    let won = rollups.iter().find(|r| r.tenant_id == "t1" && r.event_type == "deal_won").unwrap();
+    assert_eq!(won.event_count, 4);
+    assert_eq!(won.unique_users, 2);
+    assert_eq!(won.metrics, HashMap::from([("amount".to_string(), 2500.0), ("seats".to_string(), 5.0)]));
+
+    // As published to the metrics-out topic
+    assert_eq!(
+        serde_json::to_value(won).unwrap(),
+        json!({
+            "tenant_id": "t1",
+            "event_type": "deal_won",
+            "window_start": 0,
+            "window_end": 60,
+            "event_count": 4,
+            "unique_users": 2,
+            "metrics": { "amount": 2500.0, "seats": 5.0 },
+        })
+    );
+}
@@ -0,0 +1,40 @@
+//! `cargo bench -p extension-runtime-service --bench params`; see
+//! bench-baseline.sh for comparing against a saved baseline.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use extension_runtime_service::params::{json_to_wasm_params, wasm_results_to_json};
+use serde_json::json;
+use wasmtime::{Val, ValType};
+
+fn json_to_wasm(c: &mut Criterion) {
+    let mut group = c.benchmark_group("json_to_wasm_params");
+    let cases = [
+        ("i32x2", json!([1, 2]), vec![ValType::I32, ValType::I32]),
+        ("mixed4", json!([7, 9_000_000_000_i64, 1.5, 2.25]), vec![ValType::I32, ValType::I64, ValType::F32, ValType::F64]),
+        ("f64x16", json!(vec![0.5; 16]), vec![ValType::F64; 16]),
+    ];
+    for (name, params, types) in &cases {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &(params, types), |b, (params, types)| {
+            b.iter(|| json_to_wasm_params(params, types).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn wasm_to_json(c: &mut Criterion) {
+    let mut group = c.benchmark_group("wasm_results_to_json");
+    let cases = [
+        ("i32", vec![Val::I32(42)]),
+        ("mixed4", vec![Val::I32(1), Val::I64(2), Val::F32(1.5f32.to_bits()), Val::F64(2.25f64.to_bits())]),
+        ("f64x16", vec![Val::F64(0.5f64.to_bits()); 16]),
+    ];
+    for (name, results) in &cases {
+        group.bench_with_input(BenchmarkId::from_parameter(name), results, |b, results| {
+            b.iter(|| wasm_results_to_json(results).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, json_to_wasm, wasm_to_json);
+criterion_main!(benches);
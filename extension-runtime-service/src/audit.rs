@@ -0,0 +1,115 @@
+use redis::aio::Connection;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// One plugin invocation, as metering and audit consumers see it.
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+    pub execution_id: String,
+    pub tenant_id: String,
+    pub module_path: String,
+    pub function_name: String,
+    pub success: bool,
+    pub fuel_consumed: u64,
+    pub execution_time_ms: u64,
+    /// Peak linear memory of the plugin, initial memory included.
+    pub memory_peak_bytes: u64,
+    /// The params, as JSON.
+    pub bytes_in: u64,
+    /// The result, as JSON, and the plugin's output.
+    pub bytes_out: u64,
+    pub timestamp: i64,
+}
+
+/// Appends invocations to a Redis stream (`runtime_audit` by default), one
+/// entry per execution, for the metering service and auditors to read with
+/// consumer groups. The stream is capped at roughly `max_len` entries.
+///
+/// Writes happen off the request path and failures are only logged: an
+/// unavailable Redis must not fail plugin executions.
+pub struct AuditLog {
+    client: Option<redis::Client>,
+    connection: Arc<Mutex<Option<Connection>>>,
+    stream: String,
+    max_len: usize,
+}
+
+impl AuditLog {
+    /// Connects lazily, like the flag client.
+    pub fn new(redis_url: &str, stream: &str, max_len: usize) -> Self {
+        let client = redis::Client::open(redis_url)
+            .map_err(|e| warn!("Invalid audit Redis URL, invocations won't be audited: {}", e))
+            .ok();
+
+        AuditLog {
+            client,
+            connection: Arc::new(Mutex::new(None)),
+            stream: stream.to_string(),
+            max_len,
+        }
+    }
+
+    pub fn record(&self, record: AuditRecord) {
+        let client = match &self.client {
+            Some(client) => client.clone(),
+            None => return,
+        };
+        let connection = Arc::clone(&self.connection);
+        let stream = self.stream.clone();
+        let max_len = self.max_len;
+
+        tokio::spawn(async move {
+            if let Err(e) = append(&client, &connection, &stream, max_len, &record).await {
+                warn!("Failed to audit execution for tenant {}: {}", record.tenant_id, e);
+                // Drop the connection so the next write reconnects
+                *connection.lock().await = None;
+            }
+        });
+    }
+}
+
+async fn append(
+    client: &redis::Client,
+    connection: &Mutex<Option<Connection>>,
+    stream: &str,
+    max_len: usize,
+    record: &AuditRecord,
+) -> Result<(), redis::RedisError> {
+    let mut connection = connection.lock().await;
+    if connection.is_none() {
+        *connection = Some(client.get_async_connection().await?);
+    }
+
+    let _: String = redis::cmd("XADD")
+        .arg(stream)
+        .arg("MAXLEN")
+        .arg("~")
+        .arg(max_len)
+        .arg("*")
+        .arg("execution_id")
+        .arg(&record.execution_id)
+        .arg("tenant_id")
+        .arg(&record.tenant_id)
+        .arg("module_path")
+        .arg(&record.module_path)
+        .arg("function_name")
+        .arg(&record.function_name)
+        .arg("success")
+        .arg(if record.success { "1" } else { "0" })
+        .arg("fuel_consumed")
+        .arg(record.fuel_consumed)
+        .arg("execution_time_ms")
+        .arg(record.execution_time_ms)
+        .arg("memory_peak_bytes")
+        .arg(record.memory_peak_bytes)
+        .arg("bytes_in")
+        .arg(record.bytes_in)
+        .arg("bytes_out")
+        .arg(record.bytes_out)
+        .arg("timestamp")
+        .arg(record.timestamp)
+        .query_async(connection.as_mut().expect("connection was just set"))
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,245 @@
+//! Bearer JWT authentication for executions.
+//!
+//! With `jwks_url` set, every execute call (HTTP `Authorization` header or
+//! gRPC `authorization` metadata) must carry an RS256 token signed by a key
+//! from that JWKS, unexpired, from `jwt_issuer` and for `jwt_audience` when
+//! those are set, and granting `jwt_required_scope` in its space-separated
+//! `scope` claim, as the OAuth2 service issues them. The token's
+//! `tenant_id` claim becomes the execution's tenant; a request naming
+//! another tenant is refused. Without `jwks_url` calls are not
+//! authenticated, which is only fit for a runtime bound to localhost.
+//!
+//! Tokens granting `jwt_admin_scope` are the runtime's administrators':
+//! they may execute plugins too, and manage system modules and other
+//! tenants' modules; see `Authenticator::namespace`. They alone may call
+//! the `/admin/*` routes, which answer 401 without a valid token and 403
+//! without the scope; see `admin_guard`.
+//!
+//! Keys are cached by `kid` and fetched again every `jwks_refresh_secs`, or
+//! sooner when a token names an unknown key, e.g. after a rotation.
+
+use crate::{RuntimeConfig, ServiceState};
+use anyhow::{Context, Result};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+use warp::http::StatusCode;
+use warp::Filter;
+
+/// Least time between fetches, so tokens with made-up key ids or an
+/// unavailable JWKS endpoint don't turn every call into a fetch.
+const MIN_REFETCH_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: String,
+    tenant_id: String,
+    #[serde(default)]
+    scope: String,
+}
+
+/// Who is calling, from a verified token.
+#[derive(Debug, Clone)]
+pub struct AuthContext {
+    pub subject: String,
+    pub tenant_id: String,
+    /// Whether the token grants `jwt_admin_scope`.
+    pub admin: bool,
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    MissingToken,
+    InvalidToken(String),
+    MissingScope(String),
+    /// The request named a different tenant than the token was issued for.
+    TenantMismatch,
+}
+
+impl AuthError {
+    /// Whether the caller is known but not allowed, rather than unknown.
+    pub fn is_forbidden(&self) -> bool {
+        matches!(self, AuthError::MissingScope(_) | AuthError::TenantMismatch)
+    }
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::MissingToken => write!(f, "No token provided"),
+            AuthError::InvalidToken(reason) => write!(f, "Invalid token: {}", reason),
+            AuthError::MissingScope(scope) => write!(f, "Token lacks the {} scope", scope),
+            AuthError::TenantMismatch => write!(f, "Token does not grant access to this tenant"),
+        }
+    }
+}
+
+struct Keys {
+    by_id: HashMap<String, DecodingKey>,
+    fetched_at: Option<Instant>,
+    attempted_at: Option<Instant>,
+}
+
+pub struct Authenticator {
+    jwks_url: String,
+    http: reqwest::Client,
+    validation: Validation,
+    required_scope: String,
+    admin_scope: String,
+    refresh: Duration,
+    keys: Mutex<Keys>,
+}
+
+impl Authenticator {
+    /// `None` while `jwks_url` is unset.
+    pub fn from_config(config: &RuntimeConfig) -> Result<Option<Self>> {
+        let Some(jwks_url) = &config.jwks_url else {
+            warn!("jwks_url is not set; executions are not authenticated");
+            return Ok(None);
+        };
+        let mut validation = Validation::new(Algorithm::RS256);
+        if let Some(issuer) = &config.jwt_issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &config.jwt_audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .context("Failed to build JWKS client")?;
+
+        Ok(Some(Authenticator {
+            jwks_url: jwks_url.clone(),
+            http,
+            validation,
+            required_scope: config.jwt_required_scope.clone(),
+            admin_scope: config.jwt_admin_scope.clone(),
+            refresh: Duration::from_secs(config.jwks_refresh_secs),
+            keys: Mutex::new(Keys { by_id: HashMap::new(), fetched_at: None, attempted_at: None }),
+        }))
+    }
+
+    /// Verifies the `Bearer` token in `authorization`. `tenant_id` is the
+    /// tenant the request itself names, if any, and must match the token.
+    pub async fn authenticate(&self, authorization: Option<&str>, tenant_id: Option<&str>) -> Result<AuthContext, AuthError> {
+        let token = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(AuthError::MissingToken)?;
+        let kid = decode_header(token)
+            .map_err(|e| AuthError::InvalidToken(e.to_string()))?
+            .kid
+            .ok_or_else(|| AuthError::InvalidToken("token has no kid".to_string()))?;
+        let key = self.key(&kid).await?;
+        let claims = decode::<Claims>(token, &key, &self.validation)
+            .map_err(|e| AuthError::InvalidToken(e.to_string()))?
+            .claims;
+
+        let grants = |wanted: &str| claims.scope.split_whitespace().any(|scope| scope == wanted);
+        let admin = grants(&self.admin_scope);
+        if !admin && !grants(&self.required_scope) {
+            return Err(AuthError::MissingScope(self.required_scope.clone()));
+        }
+        if tenant_id.is_some_and(|tenant_id| tenant_id != claims.tenant_id) {
+            return Err(AuthError::TenantMismatch);
+        }
+        debug!("Authenticated {} for tenant {}", claims.sub, claims.tenant_id);
+        Ok(AuthContext {
+            subject: claims.sub,
+            tenant_id: claims.tenant_id,
+            admin,
+        })
+    }
+
+    /// The module namespace a call with the token in `authorization` acts
+    /// on: the token's tenant's, which `tenant_id` may name. Administrators
+    /// may name any tenant, and without one act on the system modules.
+    pub async fn namespace(&self, authorization: Option<&str>, tenant_id: Option<&str>) -> Result<Option<String>, AuthError> {
+        let caller = self.authenticate(authorization, None).await?;
+        match tenant_id {
+            Some(tenant_id) if caller.admin || tenant_id == caller.tenant_id => Ok(Some(tenant_id.to_string())),
+            Some(_) => Err(AuthError::TenantMismatch),
+            None if caller.admin => Ok(None),
+            None => Ok(Some(caller.tenant_id)),
+        }
+    }
+
+    /// Verifies the token in `authorization` and that it grants
+    /// `jwt_admin_scope`.
+    pub async fn authenticate_admin(&self, authorization: Option<&str>) -> Result<AuthContext, AuthError> {
+        let caller = self.authenticate(authorization, None).await?;
+        if !caller.admin {
+            return Err(AuthError::MissingScope(self.admin_scope.clone()));
+        }
+        Ok(caller)
+    }
+
+    async fn key(&self, kid: &str) -> Result<DecodingKey, AuthError> {
+        let mut keys = self.keys.lock().await;
+        let stale = keys.fetched_at.is_none_or(|fetched_at| fetched_at.elapsed() >= self.refresh);
+        let unknown = !keys.by_id.contains_key(kid);
+        let due = keys.attempted_at.is_none_or(|attempted_at| attempted_at.elapsed() >= MIN_REFETCH_INTERVAL);
+        if (stale || unknown) && due {
+            keys.attempted_at = Some(Instant::now());
+            // Keeps the old keys while the JWKS endpoint is unavailable
+            match self.fetch().await {
+                Ok(by_id) => {
+                    keys.by_id = by_id;
+                    keys.fetched_at = Some(Instant::now());
+                }
+                Err(e) => warn!("Failed to fetch JWKS from {}: {:#}", self.jwks_url, e),
+            }
+        }
+        keys.by_id
+            .get(kid)
+            .cloned()
+            .ok_or_else(|| AuthError::InvalidToken(format!("unknown key {}", kid)))
+    }
+
+    async fn fetch(&self) -> Result<HashMap<String, DecodingKey>> {
+        let jwks: JwkSet = self.http.get(&self.jwks_url).send().await?.error_for_status()?.json().await?;
+        let mut keys = HashMap::new();
+        for jwk in &jwks.keys {
+            let Some(kid) = &jwk.common.key_id else { continue };
+            match DecodingKey::from_jwk(jwk) {
+                Ok(key) => {
+                    keys.insert(kid.clone(), key);
+                }
+                Err(e) => warn!("Skipping JWKS key {}: {}", kid, e),
+            }
+        }
+        Ok(keys)
+    }
+}
+
+/// Refuses `/admin/*` calls from anyone but an administrator, ahead of the
+/// admin routes; the calls it lets through, and every other one, are
+/// rejected as not found so the routes after it answer them.
+pub fn admin_guard(
+    state: Arc<ServiceState>,
+) -> impl Filter<Extract = (warp::reply::WithStatus<warp::reply::Json>,), Error = warp::Rejection> + Clone {
+    warp::path("admin")
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(move |authorization: Option<String>| {
+            let state = state.clone();
+            async move {
+                let Some(auth) = &state.auth else {
+                    return Err(warp::reject::not_found());
+                };
+                match auth.authenticate_admin(authorization.as_deref()).await {
+                    Ok(_) => Err(warp::reject::not_found()),
+                    Err(e) => {
+                        let status = if e.is_forbidden() { StatusCode::FORBIDDEN } else { StatusCode::UNAUTHORIZED };
+                        Ok(warp::reply::with_status(warp::reply::json(&json!({ "error": e.to_string() })), status))
+                    }
+                }
+            }
+        })
+}
@@ -0,0 +1,169 @@
+//! Batch execution, for bulk jobs such as scoring thousands of leads, that
+//! would otherwise pay for an HTTP round trip and an instantiation per
+//! call.
+//!
+//! `POST /execute/batch` runs invocations of one module, of the same or
+//! different functions:
+//!
+//! ```json
+//! {"module_path": "lead-scoring@^2", "max_parallel": 8,
+//!  "items": [{"function_name": "score", "params": [{"lead_id": 1}]},
+//!            {"function_name": "explain", "params": [{"lead_id": 1}], "timeout_seconds": 5}]}
+//! ```
+//!
+//! Items take the fields of an execute request but `module_path`, which is
+//! the batch's: resolved once (see `versions` and `rollouts`) and compiled
+//! once, for the one tenant every item runs for, so every item runs the
+//! same version, from instances of the warm pool when there is one. Up to
+//! `max_parallel` items run at once, capped by `batch_max_parallel`, and
+//! each still goes through the tenant's quota and plan pool like a single
+//! execution would.
+//!
+//! The response lists each item's execute response, in order, with how
+//! many succeeded; one item failing doesn't fail the others. Batches are at
+//! most `batch_max_items` long.
+
+use crate::errors::{self, ErrorCode, ExecuteError};
+use crate::{authorize, compiled, execute, resolve_module, ExecuteRequest, ExecuteResponse, ServiceState, TENANT_HEADER};
+use futures::StreamExt;
+use metrics::counter;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use tracing::warn;
+use warp::http::StatusCode;
+use warp::Filter;
+
+#[derive(Debug, Deserialize)]
+struct BatchRequest {
+    module_path: String,
+    items: Vec<Map<String, Value>>,
+    #[serde(default)]
+    max_parallel: Option<usize>,
+    #[serde(default)]
+    tenant_id: Option<String>,
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+fn reply(status: StatusCode, body: Value) -> Reply {
+    warp::reply::with_status(warp::reply::json(&body), status)
+}
+
+pub fn routes(
+    state: Arc<ServiceState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("execute" / "batch")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(16 * 1024 * 1024))
+        .and(warp::body::json())
+        .and(warp::header::optional::<String>(TENANT_HEADER))
+        .and(warp::header::optional::<String>("authorization"))
+        .then(move |batch: BatchRequest, tenant_id: Option<String>, authorization: Option<String>| {
+            let state = state.clone();
+            async move { run(state, batch, tenant_id, authorization.as_deref()).await }
+        })
+}
+
+async fn run(state: Arc<ServiceState>, batch: BatchRequest, tenant_header: Option<String>, authorization: Option<&str>) -> Reply {
+    let config = state.config();
+    if batch.items.is_empty() {
+        return reply(StatusCode::BAD_REQUEST, json!({ "error": "A batch needs at least one item" }));
+    }
+    if batch.items.len() > config.batch_max_items {
+        let error = format!("A batch has at most {} items", config.batch_max_items);
+        return reply(StatusCode::PAYLOAD_TOO_LARGE, json!({ "error": error }));
+    }
+
+    let mut items = Vec::with_capacity(batch.items.len());
+    for mut item in batch.items {
+        if item.contains_key("module_path") {
+            items.push(Err(ExecuteError::new(ErrorCode::InvalidRequest, "Batch items run the batch's module_path")));
+            continue;
+        }
+        item.insert("module_path".to_string(), json!(batch.module_path));
+        if let Some(tenant_id) = &batch.tenant_id {
+            item.entry("tenant_id").or_insert_with(|| json!(tenant_id));
+        }
+        let mut req: ExecuteRequest = match serde_json::from_value(Value::Object(item)) {
+            Ok(req) => req,
+            Err(e) => {
+                items.push(Err(ExecuteError::new(ErrorCode::InvalidRequest, format!("Invalid item: {}", e))));
+                continue;
+            }
+        };
+        // Every item has the same caller, so one refused is all refused
+        if let Err(error) = authorize(&state, &mut req, tenant_header.clone(), authorization).await {
+            return reply(error.status(), json!({ "success": false, "error": error }));
+        }
+        items.push(Ok(req));
+    }
+
+    // The module is resolved among one tenant's modules
+    let tenant_id = items.iter().find_map(|item| item.as_ref().ok()).and_then(|req| req.tenant_id.clone());
+    for item in &mut items {
+        if item.as_ref().is_ok_and(|req| req.tenant_id != tenant_id) {
+            *item = Err(ExecuteError::new(ErrorCode::InvalidRequest, "Batch items run for one tenant"));
+        }
+    }
+
+    // Resolved and compiled once for every item, as the first one runs
+    let shared = match items.iter_mut().find_map(|item| item.as_mut().ok()) {
+        Some(first) => match resolve_module(&state, first).await {
+            Ok((routed, resolved_version)) => match compiled::load(&state, first.tenant_id.as_deref(), &first.module_path).await {
+                Ok(compiled) => Ok((first.module_path.clone(), compiled, routed, resolved_version)),
+                Err(e) => Err(ExecuteError::new(errors::classify(&e), format!("Execution error: {:#}", e))),
+            },
+            Err(e) => Err(ExecuteError::new(errors::classify(&e), format!("{:#}", e))),
+        },
+        None => Err(ExecuteError::new(ErrorCode::InvalidRequest, "No valid item")),
+    };
+    let (routed, resolved_version) = match &shared {
+        Ok((_, _, routed, resolved_version)) => (routed.clone(), resolved_version.clone()),
+        Err(e) => {
+            warn!("Batch of {} not run: {}", batch.module_path, e);
+            (None, None)
+        }
+    };
+
+    let max_parallel = batch.max_parallel.unwrap_or(config.batch_max_parallel).clamp(1, config.batch_max_parallel);
+    let total = items.len();
+    let responses: Vec<ExecuteResponse> = futures::stream::iter(items)
+        .map(|item| {
+            let state = state.clone();
+            let shared = &shared;
+            async move {
+                let mut req = match item {
+                    Ok(req) => req,
+                    Err(error) => return ExecuteResponse::failed(error),
+                };
+                match shared {
+                    Ok((module_path, compiled, _, _)) => {
+                        req.module_path = module_path.clone();
+                        req.compiled = Some(compiled.clone());
+                        execute(&state, req).await
+                    }
+                    Err(error) => ExecuteResponse::failed(error.clone()),
+                }
+            }
+        })
+        .buffered(max_parallel)
+        .map(|mut response| {
+            if let Some(routed) = &routed {
+                routed.record(&response);
+            }
+            response.resolved_version = resolved_version.clone();
+            response
+        })
+        .collect()
+        .await;
+
+    let succeeded = responses.iter().filter(|response| response.success).count();
+    counter!("plugin_batch_executions_total").increment(1);
+    counter!("plugin_batch_items_total", "status" => "success").increment(succeeded as u64);
+    counter!("plugin_batch_items_total", "status" => "failure").increment((total - succeeded) as u64);
+    reply(
+        StatusCode::OK,
+        json!({ "module_path": batch.module_path, "succeeded": succeeded, "failed": total - succeeded, "results": responses }),
+    )
+}
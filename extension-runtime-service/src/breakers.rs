@@ -0,0 +1,288 @@
+//! Circuit breakers, so a plugin that keeps failing stops burning instances
+//! and fuel.
+//!
+//! Every module, down to its content hash, has a breaker per tenant, so
+//! one tenant's failing calls don't disable a plugin for the others.
+//! Executions failing because of the plugin itself, as it trapped, ran out
+//! of fuel or time or went over a limit, count against it; the caller's
+//! mistakes and throttled requests don't. Once `breaker_failure_percent`
+//! of at least `breaker_min_executions` executions over the last
+//! `breaker_window_secs` failed, the breaker opens: executions fail at once
+//! with `PLUGIN_DISABLED`, without a quota or plan pool slot. After
+//! `breaker_open_secs` it's half-open and lets one execution through as a
+//! probe, closing when it succeeds and opening again when it fails.
+//!
+//! - `GET /admin/breakers`: the breakers on this replica that aren't
+//!   closed, with module, tenant and since when.
+//! - `DELETE /admin/breakers/{module_path}`: closes the module's breakers
+//!   on this replica, of one tenant with `?tenant_id=`, e.g. once whatever
+//!   made it fail is fixed. Recorded on the admin audit trail.
+//!
+//! Each replica keeps its own breakers, like its running executions. A new
+//! version of a module has another hash, so it starts out closed. Breakers
+//! opening count towards `plugin_breaker_trips_total`, executions refused
+//! towards `plugin_breaker_rejections_total`.
+
+use crate::errors::{ErrorCode, ExecuteError};
+use crate::{compiled, ExecuteResponse, RuntimeConfig, ServiceState};
+use crm_audit::AuditEvent;
+use metrics::counter;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+use warp::http::StatusCode;
+use warp::Filter;
+
+/// Steps the failure window rolls on in.
+const BUCKETS: usize = 10;
+
+/// A module, by content hash, and the tenant it runs for.
+type BreakerKey = (String, Option<String>);
+
+/// Executions and failures over a breaker's window, a bucket per step.
+#[derive(Default)]
+struct Window {
+    /// Step, executions and failures of each bucket.
+    buckets: [(u64, u64, u64); BUCKETS],
+}
+
+impl Window {
+    fn record(&mut self, step: u64, failed: bool) {
+        let bucket = &mut self.buckets[(step % BUCKETS as u64) as usize];
+        if bucket.0 != step {
+            *bucket = (step, 0, 0);
+        }
+        bucket.1 += 1;
+        bucket.2 += u64::from(failed);
+    }
+
+    /// Executions and failures over the window ending at `step`.
+    fn totals(&self, step: u64) -> (u64, u64) {
+        self.buckets
+            .iter()
+            .filter(|(bucket, _, _)| *bucket <= step && step - bucket < BUCKETS as u64)
+            .fold((0, 0), |(executions, failures), (_, e, f)| (executions + e, failures + f))
+    }
+}
+
+enum State {
+    /// Boxed, so open breakers don't take a window's room.
+    Closed(Box<Window>),
+    /// Failing fast until `until`, then letting a probe through; `probing`
+    /// once one is.
+    Open { until: Instant, opened_at: SystemTime, probing: bool },
+}
+
+struct Breaker {
+    /// As last executed, for operators to tell which it is.
+    module_path: String,
+    state: State,
+    used_at: Instant,
+}
+
+/// An execution a breaker let through.
+pub struct Admitted {
+    probe: bool,
+}
+
+pub struct Breakers {
+    breakers: Mutex<HashMap<BreakerKey, Breaker>>,
+    started: Instant,
+}
+
+impl Breakers {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Breakers { breakers: Mutex::new(HashMap::new()), started: Instant::now() })
+    }
+
+    /// Lets an execution of the module with `sha256` for `tenant_id`
+    /// through, as the probe once an open breaker's time is up; refuses it
+    /// while the breaker is open.
+    pub fn admit(&self, config: &RuntimeConfig, sha256: &str, tenant_id: Option<&str>) -> Result<Admitted, ExecuteError> {
+        if config.breaker_failure_percent == 0 {
+            return Ok(Admitted { probe: false });
+        }
+        let mut breakers = self.breakers.lock().unwrap();
+        let Some(breaker) = breakers.get_mut(&(sha256.to_string(), tenant_id.map(str::to_string))) else {
+            return Ok(Admitted { probe: false });
+        };
+        let State::Open { until, probing, .. } = &mut breaker.state else {
+            return Ok(Admitted { probe: false });
+        };
+        let now = Instant::now();
+        if now < *until {
+            counter!("plugin_breaker_rejections_total").increment(1);
+            let error = format!(
+                "Plugin {} is disabled after repeated failures; retry in {}s",
+                breaker.module_path,
+                until.duration_since(now).as_secs() + 1
+            );
+            return Err(ExecuteError::new(ErrorCode::PluginDisabled, error));
+        }
+        // Others keep failing fast while it runs, and a probe that never
+        // reports back makes way for another
+        *until = now + Duration::from_secs(config.breaker_open_secs);
+        *probing = true;
+        Ok(Admitted { probe: true })
+    }
+
+    /// Counts how an execution of `module_path` the breaker admitted went.
+    pub fn record(
+        &self,
+        config: &RuntimeConfig,
+        module_path: &str,
+        sha256: &str,
+        tenant_id: Option<&str>,
+        admitted: Admitted,
+        response: &ExecuteResponse,
+    ) {
+        if config.breaker_failure_percent == 0 || response.cached {
+            return;
+        }
+        let failed = response.error.as_ref().is_some_and(|error| error.code.plugin_failed());
+        // Says nothing about the plugin, e.g. throttled
+        if !response.success && !failed {
+            return;
+        }
+        let now = Instant::now();
+        let open = || State::Open {
+            until: now + Duration::from_secs(config.breaker_open_secs),
+            opened_at: SystemTime::now(),
+            probing: false,
+        };
+
+        let mut breakers = self.breakers.lock().unwrap();
+        let key = (sha256.to_string(), tenant_id.map(str::to_string));
+        if !breakers.contains_key(&key) {
+            // Plugins that never fail need no breaker
+            if !failed {
+                return;
+            }
+            let window = Duration::from_secs(config.breaker_window_secs);
+            breakers.retain(|_, breaker| matches!(breaker.state, State::Open { .. }) || breaker.used_at.elapsed() < window);
+        }
+        let breaker = breakers.entry(key).or_insert_with(|| Breaker {
+            module_path: module_path.to_string(),
+            state: State::Closed(Box::default()),
+            used_at: now,
+        });
+        breaker.module_path = module_path.to_string();
+        breaker.used_at = now;
+        match &mut breaker.state {
+            State::Closed(window) => {
+                let step = self.step(config, now);
+                window.record(step, failed);
+                let (executions, failures) = window.totals(step);
+                if executions >= u64::from(config.breaker_min_executions)
+                    && failures * 100 >= executions * u64::from(config.breaker_failure_percent)
+                {
+                    counter!("plugin_breaker_trips_total").increment(1);
+                    warn!(
+                        "Disabling plugin {} for {}s: {} of {} executions failed",
+                        module_path, config.breaker_open_secs, failures, executions
+                    );
+                    breaker.state = open();
+                }
+            }
+            // Only the probe's outcome counts; executions admitted before
+            // the breaker opened don't
+            State::Open { .. } if !admitted.probe => {}
+            State::Open { .. } if failed => {
+                warn!("Plugin {} failed its probe; disabled for another {}s", module_path, config.breaker_open_secs);
+                breaker.state = open();
+            }
+            State::Open { .. } => {
+                info!("Plugin {} passed its probe; enabled again", module_path);
+                breaker.state = State::Closed(Box::default());
+            }
+        }
+    }
+
+    /// Which step of the failure window `now` is in.
+    fn step(&self, config: &RuntimeConfig, now: Instant) -> u64 {
+        let step_ms = (config.breaker_window_secs * 1000 / BUCKETS as u64).max(1);
+        now.duration_since(self.started).as_millis() as u64 / step_ms
+    }
+
+    /// The breakers that aren't closed.
+    fn list(&self) -> Vec<Value> {
+        let now = Instant::now();
+        self.breakers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|((sha256, tenant_id), breaker)| {
+                let State::Open { until, opened_at, probing } = &breaker.state else {
+                    return None;
+                };
+                let opened_at = opened_at.duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+                Some(json!({
+                    "module_path": breaker.module_path,
+                    "sha256": sha256,
+                    "tenant_id": tenant_id,
+                    "state": if *probing || now >= *until { "half_open" } else { "open" },
+                    "opened_at": opened_at,
+                }))
+            })
+            .collect()
+    }
+
+    /// Closes the breakers of the module with `sha256`, of `tenant_id` or
+    /// every tenant; how many were open.
+    fn reset(&self, sha256: &str, tenant_id: Option<&str>) -> usize {
+        let mut reset = 0;
+        self.breakers.lock().unwrap().retain(|(hash, tenant), breaker| {
+            let matches = hash == sha256 && (tenant_id.is_none() || tenant.as_deref() == tenant_id);
+            if matches && matches!(breaker.state, State::Open { .. }) {
+                reset += 1;
+            }
+            !matches
+        });
+        reset
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ResetQuery {
+    #[serde(default)]
+    tenant_id: Option<String>,
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+fn reply(status: StatusCode, body: Value) -> Reply {
+    warp::reply::with_status(warp::reply::json(&body), status)
+}
+
+pub fn routes(
+    state: Arc<ServiceState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let list_state = state.clone();
+    let list = warp::path!("admin" / "breakers").and(warp::get()).map(move || {
+        let breakers = list_state.breakers.list();
+        reply(StatusCode::OK, json!({ "count": breakers.len(), "breakers": breakers }))
+    });
+    let reset = warp::path!("admin" / "breakers" / String)
+        .and(warp::delete())
+        .and(warp::query::<ResetQuery>())
+        .and(crm_audit::actor())
+        .and(warp::any().map(move || state.clone()))
+        .then(reset);
+    list.or(reset).unify()
+}
+
+async fn reset(module_path: String, query: ResetQuery, actor: String, state: Arc<ServiceState>) -> Reply {
+    // The module the tenant runs as `module_path`
+    let compiled = match compiled::load(&state, query.tenant_id.as_deref(), &module_path).await {
+        Ok(compiled) => compiled,
+        Err(e) => return reply(StatusCode::NOT_FOUND, json!({ "error": format!("{:#}", e) })),
+    };
+    let reset = state.breakers.reset(&compiled.sha256, query.tenant_id.as_deref());
+    info!("Closed {} circuit breakers of plugin {}", reset, module_path);
+    let details = json!({ "sha256": compiled.sha256, "tenant_id": query.tenant_id, "reset": reset });
+    state.admin_audit.record(AuditEvent::new(actor, "breaker.reset", &module_path).details(details)).await;
+    reply(StatusCode::OK, json!({ "module_path": module_path, "sha256": compiled.sha256, "reset": reset }))
+}
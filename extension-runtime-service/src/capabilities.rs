@@ -0,0 +1,151 @@
+//! Capabilities: what a plugin may do besides compute.
+//!
+//! - `http`: `crm.http_fetch`, for plugins whose security profile allows
+//!   the network; see `profiles`.
+//! - `kv`: `crm.kv_get`, `crm.kv_set` and `crm.kv_delete`.
+//! - `crm_read`: `crm.crm_query`.
+//! - `fs_tmp`: the scratch directory of the module's `wasi_grants`; see
+//!   `scratch`.
+//! - `clock`: the WASI clocks; without it they stay at zero.
+//! - `plugins`: `crm.call_plugin`, to run other plugins; see
+//!   `host::plugins`.
+//!
+//! A plugin lists the capabilities it needs in its manifest (see
+//! `manifest`), or gets `default_capabilities` when it lists none, and is
+//! linked with just those: importing a host function it didn't ask for
+//! fails like importing one that doesn't exist. It only runs for tenants
+//! granted all of them, by `granted_capabilities` or the tenant's own
+//! `tenant_quotas`, e.g.
+//!
+//! ```toml
+//! granted_capabilities = ["kv", "clock"]
+//!
+//! [tenant_quotas.acme]
+//! capabilities = ["http", "kv", "crm_read", "fs_tmp", "clock", "plugins"]
+//! ```
+//!
+//! Logging, settings and WASI stdio need no capability.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use wasmtime_wasi::preview2::{HostMonotonicClock, HostWallClock, WasiCtxBuilder};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Http,
+    Kv,
+    CrmRead,
+    FsTmp,
+    Clock,
+    Plugins,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 6] = [
+        Capability::Http,
+        Capability::Kv,
+        Capability::CrmRead,
+        Capability::FsTmp,
+        Capability::Clock,
+        Capability::Plugins,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Http => "http",
+            Capability::Kv => "kv",
+            Capability::CrmRead => "crm_read",
+            Capability::FsTmp => "fs_tmp",
+            Capability::Clock => "clock",
+            Capability::Plugins => "plugins",
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// A set of capabilities; a list in configuration and manifests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "Vec<Capability>", into = "Vec<Capability>")]
+pub struct Capabilities(u8);
+
+impl Capabilities {
+    pub fn all() -> Self {
+        Capability::ALL.into_iter().collect()
+    }
+
+    pub fn has(self, capability: Capability) -> bool {
+        self.0 & capability.bit() != 0
+    }
+
+    /// Those of `self` that `granted` lacks.
+    pub fn missing_from(self, granted: Capabilities) -> Capabilities {
+        Capabilities(self.0 & !granted.0)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = Capability> {
+        Capability::ALL.into_iter().filter(move |capability| self.has(*capability))
+    }
+}
+
+impl FromIterator<Capability> for Capabilities {
+    fn from_iter<I: IntoIterator<Item = Capability>>(capabilities: I) -> Self {
+        Capabilities(capabilities.into_iter().fold(0, |bits, capability| bits | capability.bit()))
+    }
+}
+
+impl From<Vec<Capability>> for Capabilities {
+    fn from(capabilities: Vec<Capability>) -> Self {
+        capabilities.into_iter().collect()
+    }
+}
+
+impl From<Capabilities> for Vec<Capability> {
+    fn from(capabilities: Capabilities) -> Self {
+        capabilities.iter().collect()
+    }
+}
+
+impl std::fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<_> = self.iter().map(Capability::name).collect();
+        write!(f, "{}", names.join(", "))
+    }
+}
+
+/// Keeps the WASI features `capabilities` lacks from a plugin's context.
+pub fn restrict(capabilities: Capabilities, builder: &mut WasiCtxBuilder) {
+    if !capabilities.has(Capability::Clock) {
+        builder.wall_clock(Stopped).monotonic_clock(Stopped);
+    }
+}
+
+/// A clock stuck at zero. Sleeping still takes real time.
+struct Stopped;
+
+impl HostWallClock for Stopped {
+    fn resolution(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+
+    fn now(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+impl HostMonotonicClock for Stopped {
+    fn resolution(&self) -> u64 {
+        1_000_000_000
+    }
+
+    fn now(&self) -> u64 {
+        0
+    }
+}
@@ -0,0 +1,316 @@
+//! Compiled modules, so executions skip parsing and compiling modules the
+//! runtime has seen before.
+//!
+//! Plugins are either core modules or, for WASI Preview 2 worlds such as
+//! `wasi:cli/command`, components; either is called a module here. Modules
+//! are keyed by the SHA-256 of their bytes. The most recently used
+//! `compiled_cache_entries` are kept in memory; every compiled module is
+//! also written to `{module_cache_dir}/compiled/{sha256}.cwasm` with
+//! `Module::serialize` or `Component::serialize`, so restarts and other replicas sharing the
+//! directory load native code instead of compiling. An artifact the engine
+//! can't load, e.g. after a wasmtime upgrade, is compiled again. Modules
+//! are compiled with the engine of the security profile their manifest
+//! asks for; see `profiles`, or its deterministic variant; see
+//! `determinism`. Core modules are checked against the capabilities their
+//! manifest declares; see `capabilities`.
+//!
+//! `POST /precompile` with `{"module_paths": [...]}`, or the gRPC
+//! `Precompile` call, fetches and compiles modules ahead of their first
+//! execution: the tenant's, or system modules, decided as for `/modules`
+//! by the token or `X-Tenant-ID` (`x-tenant-id` metadata).
+
+use crate::capabilities::Capabilities;
+use crate::errors::{self, ErrorCode};
+use crate::manifest::PluginManifest;
+use crate::modules;
+use crate::profiles::{Engines, Profile};
+use crate::{validate_module_safety, ServiceState, MAX_MODULE_BYTES, TENANT_HEADER};
+use anyhow::{Context, Result};
+use metrics::counter;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, instrument, warn};
+use warp::http::StatusCode;
+use warp::Filter;
+use wasmtime::component::Component;
+use wasmtime::{Engine, Module};
+
+/// Where a compiled module came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Origin {
+    Memory,
+    Disk,
+    Compiled,
+}
+
+impl Origin {
+    pub fn name(self) -> &'static str {
+        match self {
+            Origin::Memory => "memory",
+            Origin::Disk => "disk",
+            Origin::Compiled => "compiled",
+        }
+    }
+}
+
+/// A compiled core module or component.
+#[derive(Clone)]
+pub enum Code {
+    Module(Module),
+    Component(Component),
+}
+
+impl Code {
+    fn compile(engine: &Engine, bytes: &[u8], capabilities: Capabilities) -> Result<Code> {
+        if is_component(bytes) {
+            let component = Component::from_binary(engine, bytes).context("Failed to parse WASM component")?;
+            return Ok(Code::Component(component));
+        }
+        let module = Module::from_binary(engine, bytes).context("Failed to parse WASM module")?;
+        validate_module_safety(&module, capabilities)?;
+        Ok(Code::Module(module))
+    }
+
+    fn serialize(&self) -> Result<Vec<u8>> {
+        match self {
+            Code::Module(module) => module.serialize(),
+            Code::Component(component) => component.serialize(),
+        }
+    }
+}
+
+/// Whether `bytes` are a component rather than a core module: the two share
+/// the `\0asm` magic and differ in the layer field after the version.
+pub fn is_component(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"\0asm") && bytes.get(6..8) == Some(&[1, 0])
+}
+
+/// A compiled module and the hash it is cached by.
+#[derive(Clone)]
+pub struct Compiled {
+    pub code: Code,
+    pub sha256: String,
+    pub origin: Origin,
+    /// What its manifest asks for, and the engine it was compiled with.
+    pub profile: Profile,
+    /// What its manifest asks for, or the runtime's default.
+    pub capabilities: Capabilities,
+    /// Compiled and run reproducibly, as its manifest asks.
+    pub deterministic: bool,
+    /// How long its results may be reused, as its manifest says.
+    pub cache_ttl: Option<Duration>,
+}
+
+impl std::fmt::Debug for Compiled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Compiled")
+            .field("sha256", &self.sha256)
+            .field("origin", &self.origin)
+            .field("profile", &self.profile)
+            .field("capabilities", &self.capabilities)
+            .field("deterministic", &self.deterministic)
+            .field("cache_ttl", &self.cache_ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+pub struct CompiledCache {
+    engines: Engines,
+    dir: PathBuf,
+    capacity: usize,
+    /// For modules whose manifest lists none.
+    default_capabilities: Capabilities,
+    /// Module and when it was last used, by content hash.
+    entries: Mutex<HashMap<String, (Code, u64)>>,
+    clock: AtomicU64,
+}
+
+impl CompiledCache {
+    pub fn new(engines: Engines, dir: PathBuf, capacity: usize, default_capabilities: Capabilities) -> Result<Self> {
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create compiled module cache {}", dir.display()))?;
+        Ok(CompiledCache {
+            engines,
+            dir,
+            capacity,
+            default_capabilities,
+            entries: Mutex::new(HashMap::new()),
+            clock: AtomicU64::new(0),
+        })
+    }
+
+    pub fn engine(&self, profile: Profile, deterministic: bool) -> &Engine {
+        self.engines.get(profile, deterministic)
+    }
+
+    /// The compiled, safety-checked module for `bytes`.
+    pub async fn get(&self, bytes: &[u8]) -> Result<Compiled> {
+        if bytes.len() > MAX_MODULE_BYTES {
+            anyhow::bail!("Module too large");
+        }
+        let hash = hex::encode(Sha256::digest(bytes));
+        // The manifest is part of the bytes, so the hash decides the engine
+        // and the capabilities
+        let manifest = PluginManifest::from_module(bytes)?;
+        let (profile, deterministic) = (manifest.profile, manifest.deterministic);
+        let capabilities = manifest.capabilities.unwrap_or(self.default_capabilities);
+        let cache_ttl = manifest.cache_ttl_secs.map(Duration::from_secs);
+        let engine = self.engines.get(profile, deterministic);
+        let compiled =
+            |code, origin| Compiled { code, sha256: hash.clone(), origin, profile, capabilities, deterministic, cache_ttl };
+        if let Some(code) = self.remember(&hash, None) {
+            counter!("compiled_module_cache_total", "result" => "memory").increment(1);
+            return Ok(compiled(code, Origin::Memory));
+        }
+
+        let path = self.dir.join(format!("{}.cwasm", hash));
+        if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+            // Only this runtime writes the directory, and wasmtime refuses
+            // artifacts built for another engine configuration
+            let artifact = if is_component(bytes) {
+                unsafe { Component::deserialize_file(engine, &path) }.map(Code::Component)
+            } else {
+                unsafe { Module::deserialize_file(engine, &path) }.map(Code::Module)
+            };
+            match artifact {
+                Ok(code) => {
+                    counter!("compiled_module_cache_total", "result" => "disk").increment(1);
+                    self.remember(&hash, Some(code.clone()));
+                    return Ok(compiled(code, Origin::Disk));
+                }
+                Err(e) => warn!("Recompiling {}: cached artifact unusable: {}", hash, e),
+            }
+        }
+
+        counter!("compiled_module_cache_total", "result" => "miss").increment(1);
+        let code = Code::compile(engine, bytes, capabilities)?;
+        self.store(&path, &code).await;
+        self.remember(&hash, Some(code.clone()));
+        Ok(compiled(code, Origin::Compiled))
+    }
+
+    /// The compiled, safety-checked module for `bytes`, kept nowhere, for
+    /// modules that run once; its results aren't cached either.
+    pub fn compile(&self, bytes: &[u8]) -> Result<Compiled> {
+        if bytes.len() > MAX_MODULE_BYTES {
+            anyhow::bail!("Module too large");
+        }
+        let manifest = PluginManifest::from_module(bytes)?;
+        let (profile, deterministic) = (manifest.profile, manifest.deterministic);
+        let capabilities = manifest.capabilities.unwrap_or(self.default_capabilities);
+        let code = Code::compile(self.engines.get(profile, deterministic), bytes, capabilities)?;
+        Ok(Compiled {
+            code,
+            sha256: hex::encode(Sha256::digest(bytes)),
+            origin: Origin::Compiled,
+            profile,
+            capabilities,
+            deterministic,
+            cache_ttl: None,
+        })
+    }
+
+    /// Looks `hash` up, or inserts `code` under it, marking it used and
+    /// evicting the least recently used entry past capacity.
+    fn remember(&self, hash: &str, code: Option<Code>) -> Option<Code> {
+        let now = self.clock.fetch_add(1, Ordering::Relaxed);
+        let mut entries = self.entries.lock().unwrap();
+        let Some(code) = code else {
+            let (code, used) = entries.get_mut(hash)?;
+            *used = now;
+            return Some(code.clone());
+        };
+        entries.insert(hash.to_string(), (code.clone(), now));
+        if entries.len() > self.capacity {
+            let oldest = entries.iter().min_by_key(|(_, (_, used))| *used).map(|(hash, _)| hash.clone());
+            if let Some(oldest) = oldest {
+                debug!("Evicting compiled module {}", oldest);
+                entries.remove(&oldest);
+            }
+        }
+        Some(code)
+    }
+
+    /// Best effort: a failed write only means the next start compiles again.
+    async fn store(&self, path: &std::path::Path, code: &Code) {
+        // Written aside and renamed so readers never see a partial artifact
+        let partial = path.with_extension(format!("{}.partial", std::process::id()));
+        let written = async {
+            let artifact = code.serialize().map_err(std::io::Error::other)?;
+            tokio::fs::write(&partial, artifact).await?;
+            tokio::fs::rename(&partial, path).await
+        };
+        if let Err(e) = written.await {
+            warn!("Failed to cache compiled module {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PrecompileRequest {
+    module_paths: Vec<String>,
+}
+
+pub fn routes(
+    state: Arc<ServiceState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("precompile")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(warp::header::optional::<String>(TENANT_HEADER))
+        .and(warp::header::optional::<String>("authorization"))
+        .then(move |request: PrecompileRequest, tenant_id: Option<String>, authorization: Option<String>| {
+            let state = state.clone();
+            async move {
+                // Loads modules as the tenant the caller may act for runs them
+                match modules::namespace(&state, tenant_id, authorization).await {
+                    Ok(tenant_id) => precompile(&state, tenant_id.as_deref(), request).await,
+                    Err(refused) => refused,
+                }
+            }
+        })
+}
+
+/// Fetches `module_path` as `tenant_id` runs it, checks its signature and
+/// compiles it, or takes it from the cache.
+#[instrument(name = "plugin.compile", skip(state), fields(cache = tracing::field::Empty))]
+pub async fn load(state: &ServiceState, tenant_id: Option<&str>, module_path: &str) -> Result<Compiled> {
+    let bytes = state.modules.fetch(tenant_id, module_path).await?;
+    if let Some(signatures) = &state.signatures {
+        signatures
+            .verify(state.modules.as_ref(), tenant_id, module_path, &bytes)
+            .await
+            .map_err(|e| errors::or_coded(e, ErrorCode::NotPermitted))?;
+    }
+    let compiled = state.compiled.get(&bytes).await.map_err(|e| errors::or_coded(e, ErrorCode::InvalidModule))?;
+    tracing::Span::current().record("cache", compiled.origin.name());
+    Ok(compiled)
+}
+
+async fn precompile(
+    state: &ServiceState,
+    tenant_id: Option<&str>,
+    request: PrecompileRequest,
+) -> warp::reply::WithStatus<warp::reply::Json> {
+    if request.module_paths.is_empty() {
+        return warp::reply::with_status(
+            warp::reply::json(&json!({ "error": "module_paths is empty" })),
+            StatusCode::BAD_REQUEST,
+        );
+    }
+
+    let mut modules = Vec::with_capacity(request.module_paths.len());
+    for module_path in request.module_paths {
+        modules.push(match load(state, tenant_id, &module_path).await {
+            Ok(compiled) => json!({ "module_path": module_path, "cache": compiled.origin.name() }),
+            Err(e) => json!({ "module_path": module_path, "error": format!("{:#}", e) }),
+        });
+    }
+    warp::reply::with_status(warp::reply::json(&json!({ "modules": modules })), StatusCode::OK)
+}
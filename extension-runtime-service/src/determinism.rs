@@ -0,0 +1,73 @@
+//! Deterministic execution, so a plugin run can be replayed, e.g. for a
+//! billing dispute: the same module, input and seed give the same result
+//! and consume the same fuel.
+//!
+//! A plugin asks for it with `"deterministic": true` in its manifest (see
+//! `manifest`). It is then compiled with NaN canonicalization and without
+//! SIMD, by an engine of its own per security profile, and its WASI
+//! context is virtualized:
+//!
+//! - `random`: both the secure and the insecure source are a ChaCha8
+//!   stream seeded with the request's `seed`, 0 by default.
+//! - `clock`: the wall clock starts at the Unix epoch and the monotonic
+//!   clock at zero, and each read moves them on by `CLOCK_STEP`. Plugins
+//!   without the `clock` capability still see them stopped; see
+//!   `capabilities`.
+//!
+//! What host functions return, such as `http_fetch` responses, is up to the
+//! outside world and isn't replayed.
+
+use crate::capabilities::{Capabilities, Capability};
+use rand_chacha::rand_core::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use wasmtime_wasi::preview2::{HostMonotonicClock, HostWallClock, WasiCtxBuilder};
+
+/// How far the virtual clocks move each time they're read.
+const CLOCK_STEP: Duration = Duration::from_millis(1);
+
+/// Replaces the nondeterministic parts of a plugin's WASI context with
+/// ones seeded by `seed`.
+pub fn apply(seed: u64, capabilities: Capabilities, builder: &mut WasiCtxBuilder) {
+    builder
+        .secure_random(ChaCha8Rng::seed_from_u64(seed))
+        .insecure_random(ChaCha8Rng::seed_from_u64(seed))
+        .insecure_random_seed(u128::from(seed));
+    if capabilities.has(Capability::Clock) {
+        builder.wall_clock(VirtualClock::default()).monotonic_clock(VirtualClock::default());
+    }
+}
+
+/// A clock that only moves when read.
+#[derive(Default)]
+struct VirtualClock {
+    reads: AtomicU64,
+}
+
+impl VirtualClock {
+    fn tick(&self) -> Duration {
+        let reads = self.reads.fetch_add(1, Ordering::Relaxed);
+        Duration::from_nanos(CLOCK_STEP.as_nanos() as u64 * reads)
+    }
+}
+
+impl HostWallClock for VirtualClock {
+    fn resolution(&self) -> Duration {
+        CLOCK_STEP
+    }
+
+    fn now(&self) -> Duration {
+        self.tick()
+    }
+}
+
+impl HostMonotonicClock for VirtualClock {
+    fn resolution(&self) -> u64 {
+        CLOCK_STEP.as_nanos() as u64
+    }
+
+    fn now(&self) -> u64 {
+        self.tick().as_nanos() as u64
+    }
+}
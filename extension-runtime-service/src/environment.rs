@@ -0,0 +1,83 @@
+//! Environment variables plugins see through WASI.
+//!
+//! Plugins start with an empty environment. `plugin_env` names variables of
+//! the runtime's own environment every plugin gets, when they're set, and
+//! `request_env` those an execute request may set through `env`, with the
+//! values each may take, e.g. in the config file:
+//!
+//! ```toml
+//! plugin_env = ["LANG", "TZ"]
+//!
+//! [request_env.PLUGIN_ENV]
+//! values = ["production", "staging"]
+//!
+//! [request_env.REPORT_TITLE]
+//! max_bytes = 128
+//! ```
+//!
+//! Requests setting anything else are refused. A request's variables
+//! override the runtime's, and can't share a name with the secrets it asks
+//! for.
+
+use crate::{ExecuteRequest, RuntimeConfig};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Values one request variable may take.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvSchema {
+    /// Any value, when empty.
+    pub values: Vec<String>,
+    pub max_bytes: usize,
+}
+
+impl Default for EnvSchema {
+    fn default() -> Self {
+        EnvSchema { values: Vec::new(), max_bytes: 256 }
+    }
+}
+
+impl EnvSchema {
+    fn allows(&self, value: &str) -> bool {
+        value.len() <= self.max_bytes && (self.values.is_empty() || self.values.iter().any(|allowed| allowed == value))
+    }
+}
+
+/// Whether `name` can be an environment variable's name.
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['=', '\0'])
+}
+
+/// The `plugin_env` variables set in the runtime's environment.
+pub fn passthrough(config: &RuntimeConfig) -> Vec<(String, String)> {
+    config
+        .plugin_env
+        .iter()
+        .filter_map(|name| std::env::var(name).ok().map(|value| (name.clone(), value)))
+        .collect()
+}
+
+/// The variables a request sets, checked against `request_env`.
+pub fn request_env(config: &RuntimeConfig, req: &ExecuteRequest, wasi_enabled: bool) -> Result<Vec<(String, String)>> {
+    if req.env.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !wasi_enabled {
+        anyhow::bail!("Environment variables require WASI");
+    }
+    let mut env = Vec::with_capacity(req.env.len());
+    for (name, value) in &req.env {
+        let Some(schema) = config.request_env.get(name) else {
+            anyhow::bail!("Environment variable {} may not be set", name);
+        };
+        if !schema.allows(value) {
+            anyhow::bail!("Value not allowed for environment variable {}", name);
+        }
+        if req.secrets.contains(name) {
+            anyhow::bail!("Environment variable {} is also a requested secret", name);
+        }
+        env.push((name.clone(), value.clone()));
+    }
+    Ok(env)
+}
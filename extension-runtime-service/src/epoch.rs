@@ -0,0 +1,79 @@
+//! Wall-clock deadlines and cancellation for plugin code.
+//!
+//! The engine calls back into each store running wasm on every epoch
+//! tick, and a thread bumps the epoch every `epoch_interval_ms`. The
+//! callback interrupts the plugin once its execution is cancelled or past
+//! its deadline. Unlike the execution timeout, this stops a plugin looping
+//! inside a blocking call; it lands within one interval. Host functions
+//! aren't interrupted, they keep to the execution's deadline themselves.
+//! Profiled executions are sampled on the same ticks; see `profiling`.
+
+use crate::host::Host;
+use crate::profiles::Engines;
+use crate::profiling;
+use crate::MAX_EXECUTION_TIMEOUT;
+use std::time::{Duration, Instant};
+use tracing::info;
+use wasmtime::{AsContextMut, StoreContextMut, Trap, UpdateDeadline};
+
+/// Why a cancelled execution's plugin stopped.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "execution cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Bumps each engine's epoch every `interval` for as long as the process
+/// runs.
+pub fn spawn_ticker(engines: Engines, interval: Duration) -> std::io::Result<()> {
+    info!("Interrupting plugins past their deadline every {:?}", interval);
+    std::thread::Builder::new().name("epoch-ticker".to_string()).spawn(move || loop {
+        std::thread::sleep(interval);
+        engines.all().for_each(|engine| engine.increment_epoch());
+    })?;
+    Ok(())
+}
+
+/// The store's epoch callback: keeps the plugin going a tick at a time
+/// until its execution is cancelled, due or over its scratch quota, noting
+/// the fuel it has left.
+/// Start functions, run outside an execution, get as long as the longest
+/// execution.
+pub fn on_tick(mut store: StoreContextMut<'_, Host>) -> anyhow::Result<UpdateDeadline> {
+    profiling::sample(store.as_context_mut());
+    let fuel = store.get_fuel();
+    let host = store.data_mut();
+    let deadline = match &mut host.call {
+        Some(call) if call.progress.is_cancelled() => return Err(Cancelled.into()),
+        Some(call) => {
+            if let Ok(fuel) = fuel {
+                call.progress.record_fuel(fuel);
+            }
+            if let Some(scratch) = &mut call.scratch {
+                scratch.check()?;
+            }
+            call.deadline
+        }
+        None => host.created_at + MAX_EXECUTION_TIMEOUT,
+    };
+    if Instant::now() >= deadline {
+        return Err(Trap::Interrupt.into());
+    }
+    Ok(UpdateDeadline::Continue(1))
+}
+
+/// Whether `error` is a plugin interrupted at its deadline.
+pub fn is_interrupt(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<Trap>() == Some(&Trap::Interrupt)
+}
+
+/// Whether `error` is a plugin interrupted because its execution was
+/// cancelled.
+pub fn is_cancelled(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<Cancelled>().is_some()
+}
@@ -0,0 +1,205 @@
+//! Why an execution failed, as a code callers can branch on.
+//!
+//! Failed execute responses carry an `error` object rather than a bare
+//! message:
+//!
+//! ```json
+//! {"success": false,
+//!  "error": {"code": "FUEL_EXHAUSTED", "message": "Execution error: …", "retryable": false}}
+//! ```
+//!
+//! The message is for people and may change; the code is for programs and
+//! won't. `retryable` says whether the same request may succeed if sent
+//! again later, e.g. once a quota window passes. Over HTTP the response's
+//! status follows the code, see `ErrorCode::status`: 200 only for
+//! successes, 429 for throttled requests, 4xx for what the caller or the
+//! plugin did wrong, and 5xx for the runtime. Batch items and pipeline
+//! steps carry their errors the same way; a batch answers 200 whatever its
+//! items did, a pipeline with the status of the step that failed it.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use warp::http::StatusCode;
+use wasmtime::Trap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// No module by that path, or no version matching the reference.
+    ModuleNotFound,
+    /// The module has no such export.
+    FunctionNotFound,
+    /// The module doesn't compile, or fails validation.
+    InvalidModule,
+    /// The params don't fit the function's signature.
+    ParamMismatch,
+    /// The request itself is malformed.
+    InvalidRequest,
+    /// No valid credentials.
+    Unauthenticated,
+    /// The caller, tenant or module isn't allowed to run this: a security
+    /// profile or capability it isn't granted, or a missing signature.
+    NotPermitted,
+    /// The tenant's execution quota is used up.
+    QuotaExceeded,
+    /// Every instance of the tenant's plan pool is busy, queue included.
+    CapacityExceeded,
+    /// The plugin burned all of its fuel.
+    FuelExhausted,
+    /// The plugin accessed memory or a table out of its bounds.
+    TrapOob,
+    /// The plugin trapped otherwise, e.g. on `unreachable`.
+    Trap,
+    /// The plugin ran into its deadline.
+    Timeout,
+    /// The execution was cancelled.
+    Cancelled,
+    /// The plugin went over a resource limit other than fuel, e.g. its
+    /// scratch space.
+    LimitExceeded,
+    /// Another request holds what this one needs, e.g. its idempotency
+    /// key.
+    Conflict,
+    /// A dependency of the runtime, e.g. module storage, is unavailable.
+    Unavailable,
+    /// The plugin failed so often that its circuit breaker is open; see
+    /// `breakers`.
+    PluginDisabled,
+    /// Anything else.
+    ExecutionFailed,
+}
+
+impl ErrorCode {
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCode::ModuleNotFound => "MODULE_NOT_FOUND",
+            ErrorCode::FunctionNotFound => "FUNCTION_NOT_FOUND",
+            ErrorCode::InvalidModule => "INVALID_MODULE",
+            ErrorCode::ParamMismatch => "PARAM_MISMATCH",
+            ErrorCode::InvalidRequest => "INVALID_REQUEST",
+            ErrorCode::Unauthenticated => "UNAUTHENTICATED",
+            ErrorCode::NotPermitted => "NOT_PERMITTED",
+            ErrorCode::QuotaExceeded => "QUOTA_EXCEEDED",
+            ErrorCode::CapacityExceeded => "CAPACITY_EXCEEDED",
+            ErrorCode::FuelExhausted => "FUEL_EXHAUSTED",
+            ErrorCode::TrapOob => "TRAP_OOB",
+            ErrorCode::Trap => "TRAP",
+            ErrorCode::Timeout => "TIMEOUT",
+            ErrorCode::Cancelled => "CANCELLED",
+            ErrorCode::LimitExceeded => "LIMIT_EXCEEDED",
+            ErrorCode::Conflict => "CONFLICT",
+            ErrorCode::Unavailable => "UNAVAILABLE",
+            ErrorCode::PluginDisabled => "PLUGIN_DISABLED",
+            ErrorCode::ExecutionFailed => "EXECUTION_FAILED",
+        }
+    }
+
+    /// The HTTP status of a response failing with this code.
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::ModuleNotFound | ErrorCode::FunctionNotFound => StatusCode::NOT_FOUND,
+            ErrorCode::ParamMismatch | ErrorCode::InvalidRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthenticated => StatusCode::UNAUTHORIZED,
+            ErrorCode::NotPermitted => StatusCode::FORBIDDEN,
+            ErrorCode::QuotaExceeded | ErrorCode::CapacityExceeded => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::InvalidModule
+            | ErrorCode::FuelExhausted
+            | ErrorCode::TrapOob
+            | ErrorCode::Trap
+            | ErrorCode::LimitExceeded => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ErrorCode::Cancelled | ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::Unavailable | ErrorCode::PluginDisabled => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::ExecutionFailed => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Whether the same request may succeed later. A plugin that timed
+    /// out or trapped would most likely do it again.
+    pub fn retryable(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::QuotaExceeded | ErrorCode::CapacityExceeded | ErrorCode::Conflict | ErrorCode::Unavailable
+        )
+    }
+
+    /// Whether the plugin itself failed, rather than the caller or the
+    /// runtime; what circuit breakers count.
+    pub fn plugin_failed(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::FuelExhausted | ErrorCode::TrapOob | ErrorCode::Trap | ErrorCode::Timeout | ErrorCode::LimitExceeded
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecuteError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub retryable: bool,
+}
+
+impl ExecuteError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        ExecuteError { code, message: message.into(), retryable: code.retryable() }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.code.status()
+    }
+}
+
+impl fmt::Display for ExecuteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// An error tagged with its code, which it keeps through the context added
+/// to it on the way up. Shows as the error it tags, causes included.
+#[derive(Debug)]
+pub struct Coded {
+    pub code: ErrorCode,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for Coded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for Coded {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+/// `error` tagged with `code`.
+pub fn coded(code: ErrorCode, error: impl Into<anyhow::Error>) -> anyhow::Error {
+    Coded { code, error: error.into() }.into()
+}
+
+/// `error` tagged with `code`, unless it already has one.
+pub fn or_coded(error: anyhow::Error, code: ErrorCode) -> anyhow::Error {
+    match error.downcast_ref::<Coded>() {
+        Some(_) => error,
+        None => coded(code, error),
+    }
+}
+
+/// The code of `error`, from running a plugin: its tag, or what the
+/// plugin trapped on.
+pub fn classify(error: &anyhow::Error) -> ErrorCode {
+    if let Some(coded) = error.downcast_ref::<Coded>() {
+        return coded.code;
+    }
+    match error.downcast_ref::<Trap>() {
+        Some(Trap::OutOfFuel) => ErrorCode::FuelExhausted,
+        Some(Trap::Interrupt) => ErrorCode::Timeout,
+        Some(Trap::MemoryOutOfBounds | Trap::TableOutOfBounds | Trap::HeapMisaligned) => ErrorCode::TrapOob,
+        Some(_) => ErrorCode::Trap,
+        None => ErrorCode::ExecutionFailed,
+    }
+}
@@ -0,0 +1,219 @@
+//! Executions running on this replica, so a misbehaving plugin can be
+//! found and stopped without waiting for its timeout.
+//!
+//! - `GET /admin/executions`: what's running, longest first, with module,
+//!   tenant, plan tier, time elapsed and fuel consumed so far; for finding
+//!   who fills the execution slots. Fuel is sampled every epoch tick, as
+//!   of the plugin's latest function call.
+//! - `POST /executions/{id}/cancel`, with the `execution_id` an execute
+//!   response, log line or the list carries: interrupts that execution's
+//!   plugin at the next epoch tick; the execution answers "Execution
+//!   cancelled". 202 once it's asked to stop, 404 when it isn't running
+//!   here.
+//!
+//! An execution is running once it has a slot. A plugin waiting in a host
+//! function is interrupted when the call returns. Executions nobody waits
+//! for any more, such as cancelled jobs, are interrupted the same way.
+//! While authentication is on, callers only cancel their own tenant's
+//! executions; the list, like the other admin APIs, is for operators.
+
+use crate::pools::Tier;
+use crate::{ExecuteRequest, ServiceState, TENANT_HEADER};
+use metrics::{counter, gauge};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::info;
+use warp::http::StatusCode;
+use warp::Filter;
+
+struct Execution {
+    module_path: String,
+    function_name: String,
+    tenant_id: Option<String>,
+    tier: Tier,
+    fuel_limit: u64,
+    started_at: Instant,
+    progress: Arc<Progress>,
+}
+
+/// What the running plugin and the rest of the service share about an
+/// execution.
+pub struct Progress {
+    cancelled: AtomicBool,
+    fuel_remaining: AtomicU64,
+}
+
+impl Progress {
+    /// Whether the plugin should stop.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Notes the store's fuel, as of the latest tick.
+    pub fn record_fuel(&self, remaining: u64) {
+        self.fuel_remaining.store(remaining, Ordering::Relaxed);
+    }
+}
+
+/// A running execution, as `GET /admin/executions` lists it.
+#[derive(Debug, Serialize)]
+struct Listed {
+    execution_id: String,
+    module_path: String,
+    function_name: String,
+    tenant_id: Option<String>,
+    tier: &'static str,
+    elapsed_ms: u64,
+    fuel_consumed: u64,
+}
+
+#[derive(Default)]
+pub struct Executions {
+    running: Mutex<HashMap<String, Execution>>,
+}
+
+impl Executions {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Executions::default())
+    }
+
+    /// Tracks `req` as running under `execution_id` until the returned
+    /// guard drops.
+    pub fn start(self: &Arc<Self>, execution_id: &str, req: &ExecuteRequest, tier: Tier, fuel_limit: u64) -> Running {
+        let progress = Arc::new(Progress { cancelled: AtomicBool::new(false), fuel_remaining: AtomicU64::new(fuel_limit) });
+        self.running.lock().unwrap().insert(
+            execution_id.to_string(),
+            Execution {
+                module_path: req.module_path.clone(),
+                function_name: req.function_name.clone(),
+                tenant_id: req.tenant_id.clone(),
+                tier,
+                fuel_limit,
+                started_at: Instant::now(),
+                progress: progress.clone(),
+            },
+        );
+        gauge!("active_plugin_instances").increment(1.0);
+        Running { executions: self.clone(), execution_id: execution_id.to_string(), progress }
+    }
+
+    /// Running executions, longest running first.
+    fn list(&self) -> Vec<Listed> {
+        let running = self.running.lock().unwrap();
+        let mut listed: Vec<Listed> = running
+            .iter()
+            .map(|(execution_id, execution)| Listed {
+                execution_id: execution_id.clone(),
+                module_path: execution.module_path.clone(),
+                function_name: execution.function_name.clone(),
+                tenant_id: execution.tenant_id.clone(),
+                tier: execution.tier.name(),
+                elapsed_ms: execution.started_at.elapsed().as_millis() as u64,
+                fuel_consumed: execution.fuel_limit.saturating_sub(execution.progress.fuel_remaining.load(Ordering::Relaxed)),
+            })
+            .collect();
+        listed.sort_by_key(|listed| std::cmp::Reverse(listed.elapsed_ms));
+        listed
+    }
+
+    /// Asks the execution to stop; false when it isn't running here, or
+    /// runs for another tenant than `tenant_id`.
+    pub fn cancel(&self, execution_id: &str, tenant_id: Option<&str>) -> bool {
+        let running = self.running.lock().unwrap();
+        let Some(execution) = running.get(execution_id) else {
+            return false;
+        };
+        if tenant_id.is_some_and(|tenant_id| execution.tenant_id.as_deref() != Some(tenant_id)) {
+            return false;
+        }
+        execution.progress.cancel();
+        info!(
+            "Cancelling execution {}: module={}, function={}, running for {:?}",
+            execution_id,
+            execution.module_path,
+            execution.function_name,
+            execution.started_at.elapsed()
+        );
+        true
+    }
+}
+
+/// An execution in [`Executions`]; dropping it interrupts the plugin if
+/// it's still running.
+pub struct Running {
+    executions: Arc<Executions>,
+    execution_id: String,
+    progress: Arc<Progress>,
+}
+
+impl Running {
+    /// For the plugin's store, which checks it every tick.
+    pub fn progress(&self) -> Arc<Progress> {
+        self.progress.clone()
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.progress.cancel();
+        self.executions.running.lock().unwrap().remove(&self.execution_id);
+        gauge!("active_plugin_instances").decrement(1.0);
+    }
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+fn reply(status: StatusCode, body: Value) -> Reply {
+    warp::reply::with_status(warp::reply::json(&body), status)
+}
+
+pub fn routes(
+    state: Arc<ServiceState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let list_state = state.clone();
+    let list = warp::path!("admin" / "executions").and(warp::get()).map(move || {
+        let executions = list_state.executions.list();
+        reply(StatusCode::OK, json!({ "count": executions.len(), "executions": executions }))
+    });
+    let cancel = warp::path("executions")
+        .and(warp::path::param::<String>())
+        .and(warp::path("cancel"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::header::optional::<String>(TENANT_HEADER))
+        .and(warp::header::optional::<String>("authorization"))
+        .then(move |execution_id: String, tenant_id: Option<String>, authorization: Option<String>| {
+            let state = state.clone();
+            async move { cancel(&state, &execution_id, tenant_id, authorization.as_deref()).await }
+        });
+    list.or(cancel).unify()
+}
+
+/// With authentication on, only the token's tenant's executions can be
+/// cancelled; otherwise `X-Tenant-ID`, when given, narrows them the same
+/// way. Other tenants' executions are reported missing.
+async fn cancel(state: &ServiceState, execution_id: &str, tenant_id: Option<String>, authorization: Option<&str>) -> Reply {
+    let tenant_id = match &state.auth {
+        Some(auth) => match auth.authenticate(authorization, tenant_id.as_deref()).await {
+            Ok(caller) => Some(caller.tenant_id),
+            Err(e) => {
+                let status = if e.is_forbidden() { StatusCode::FORBIDDEN } else { StatusCode::UNAUTHORIZED };
+                return reply(status, json!({ "error": e.to_string() }));
+            }
+        },
+        None => tenant_id,
+    };
+    if !state.executions.cancel(execution_id, tenant_id.as_deref()) {
+        return reply(StatusCode::NOT_FOUND, json!({ "error": format!("Execution not running: {}", execution_id) }));
+    }
+    counter!("plugin_execution_cancellations_total").increment(1);
+    reply(StatusCode::ACCEPTED, json!({ "execution_id": execution_id, "cancelled": true }))
+}
@@ -0,0 +1,94 @@
+//! WASI capabilities granted to individual plugins.
+//!
+//! Plugins get a WASI context with their environment and captured stdio
+//! and nothing else: no files, no sockets. `wasi_grants` opens more, by
+//! module path, e.g. in the config file:
+//!
+//! ```toml
+//! [wasi_grants."invoice-export.wasm"]
+//! preopens = [{ host = "/srv/plugin-data/templates", guest = "/templates" }]
+//! network = ["smtp.internal:587"]
+//! ip_name_lookup = true
+//! scratch = { guest = "/tmp", max_bytes = 10485760 }
+//! ```
+//!
+//! Preopened directories are read-only unless `writable` is set. `network`
+//! lists the `host:port` addresses `wasi:sockets` may connect to, resolved
+//! when the plugin's store is created; preview1 modules have no sockets to
+//! use them with. `scratch` gives each execution an empty directory of its
+//! own, for plugins with the `fs_tmp` capability; see `scratch`. Grants
+//! only apply while WASI is enabled for the tenant.
+
+use crate::scratch::ScratchGrant;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use wasmtime_wasi::preview2::{DirPerms, FilePerms, WasiCtxBuilder};
+use wasmtime_wasi::sync::{ambient_authority, Dir};
+
+/// What one module may reach beyond its stdio and environment.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WasiGrant {
+    pub preopens: Vec<Preopen>,
+    pub network: Vec<String>,
+    /// Allows `wasi:sockets/ip-name-lookup`.
+    pub ip_name_lookup: bool,
+    /// A writable directory per execution.
+    pub scratch: Option<ScratchGrant>,
+}
+
+/// A host directory the plugin sees at `guest`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preopen {
+    pub host: String,
+    pub guest: String,
+    #[serde(default)]
+    pub writable: bool,
+}
+
+impl WasiGrant {
+    pub fn validate(&self, module_path: &str, problems: &mut Vec<String>) {
+        for preopen in &self.preopens {
+            if !std::path::Path::new(&preopen.host).is_absolute() {
+                problems.push(format!("wasi_grants.{}: preopen {} must be an absolute path", module_path, preopen.host));
+            }
+            if preopen.guest.is_empty() {
+                problems.push(format!("wasi_grants.{}: preopen {} needs a guest path", module_path, preopen.host));
+            }
+        }
+        if let Some(scratch) = &self.scratch {
+            if scratch.guest.is_empty() {
+                problems.push(format!("wasi_grants.{}: scratch needs a guest path", module_path));
+            }
+            if scratch.max_bytes == 0 {
+                problems.push(format!("wasi_grants.{}: scratch max_bytes must be positive", module_path));
+            }
+        }
+        for address in &self.network {
+            if address.rsplit_once(':').is_none_or(|(_, port)| port.parse::<u16>().is_err()) {
+                problems.push(format!("wasi_grants.{}: network address {} must be host:port", module_path, address));
+            }
+        }
+    }
+
+    /// Adds the grant's directories and addresses to `builder`.
+    pub fn apply(&self, builder: &mut WasiCtxBuilder) -> Result<()> {
+        for preopen in &self.preopens {
+            let dir = Dir::open_ambient_dir(&preopen.host, ambient_authority())
+                .with_context(|| format!("Failed to open preopened directory {}", preopen.host))?;
+            let (perms, file_perms) = if preopen.writable {
+                (DirPerms::READ | DirPerms::MUTATE, FilePerms::READ | FilePerms::WRITE)
+            } else {
+                (DirPerms::READ, FilePerms::READ)
+            };
+            builder.preopened_dir(dir, perms, file_perms, &preopen.guest);
+        }
+        for address in &self.network {
+            builder
+                .insert_addr(address.as_str())
+                .with_context(|| format!("Failed to resolve granted address {}", address))?;
+        }
+        builder.allow_ip_name_lookup(self.ip_name_lookup);
+        Ok(())
+    }
+}
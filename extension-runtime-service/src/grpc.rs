@@ -0,0 +1,325 @@
+//! `crm.v1.ExtensionRuntime` over gRPC, for callers that want the typed
+//! contract from crm-proto instead of the JSON endpoints. Calls share the
+//! HTTP API's executor and compiled module cache; `ExecuteStream` also
+//! sends the plugin's output line by line while it runs.
+
+use crate::compiled;
+use crate::host::log::{Level, PluginLog};
+use crate::inspect::{inspect, Extern};
+use crate::logs::{self, LogLine};
+use crate::{ExecuteRequest, ExecuteResponse, ServiceState, TENANT_HEADER};
+use crm_proto::tonic::transport::server::Connected;
+use crm_proto::tonic::transport::Server;
+use crm_proto::tonic::codegen::BoxStream;
+use crm_proto::tonic::{self, Request, Response, Status};
+use crm_proto::v1;
+use crm_proto::v1::extension_runtime_server::{ExtensionRuntime, ExtensionRuntimeServer};
+use crm_tls::{MtlsContext, ServerStream};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+use tracing::{error, info, Instrument, Span};
+
+struct GrpcRuntime(Arc<ServiceState>);
+
+/// The span of a call to `method`, continuing the caller's trace when its
+/// metadata carries a `traceparent`.
+fn call_span<T>(request: &Request<T>, method: &str) -> Span {
+    let span = tracing::info_span!("grpc", otel.name = %format!("crm.v1.ExtensionRuntime/{}", method), otel.kind = "server");
+    let header = |name: &str| request.metadata().get(name).and_then(|value| value.to_str().ok());
+    crm_observability::continue_trace(&span, header("traceparent"), header("tracestate"));
+    span
+}
+
+impl GrpcRuntime {
+    /// The tenant whose modules a call without a request naming one loads,
+    /// `None` for the system modules: from the token with authentication
+    /// on, as for `/modules`, otherwise the call's `x-tenant-id` metadata.
+    async fn namespace<T>(&self, request: &Request<T>) -> Result<Option<String>, Status> {
+        let metadata = |name: &str| request.metadata().get(name).and_then(|value| value.to_str().ok());
+        let tenant_id = metadata(TENANT_HEADER);
+        match &self.0.auth {
+            Some(auth) => match auth.namespace(metadata("authorization"), tenant_id).await {
+                Ok(tenant_id) => Ok(tenant_id),
+                Err(e) if e.is_forbidden() => Err(Status::permission_denied(e.to_string())),
+                Err(e) => Err(Status::unauthenticated(e.to_string())),
+            },
+            None => Ok(tenant_id.map(str::to_string)),
+        }
+    }
+
+    /// Authenticates the call and turns it into the runtime's own request.
+    async fn prepare(&self, request: Request<v1::ExecuteRequest>) -> Result<ExecuteRequest, Status> {
+        let authorization = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let mut request = request.into_inner();
+        // A verified token decides the tenant
+        let caller = match &self.0.auth {
+            Some(auth) => match auth.authenticate(authorization.as_deref(), request.tenant_id.as_deref()).await {
+                Ok(caller) => {
+                    request.tenant_id = Some(caller.tenant_id.clone());
+                    Some(caller)
+                }
+                Err(e) if e.is_forbidden() => return Err(Status::permission_denied(e.to_string())),
+                Err(e) => return Err(Status::unauthenticated(e.to_string())),
+            },
+            None => None,
+        };
+        let params = match request.params_json.as_str() {
+            "" => serde_json::Value::Array(Vec::new()),
+            json => serde_json::from_str(json)
+                .map_err(|e| Status::invalid_argument(format!("params_json is not JSON: {}", e)))?,
+        };
+
+        Ok(ExecuteRequest {
+            module_path: request.module_path,
+            function_name: request.function_name,
+            params,
+            timeout_seconds: request.timeout_seconds,
+            tenant_id: request.tenant_id,
+            secrets: request.secrets,
+            env: request.env,
+            include_logs: request.include_logs,
+            result_encoding: request
+                .result_encoding
+                .parse()
+                .map_err(|e: anyhow::Error| Status::invalid_argument(e.to_string()))?,
+            calling_convention: request
+                .calling_convention
+                .parse()
+                .map_err(|e: anyhow::Error| Status::invalid_argument(e.to_string()))?,
+            caller,
+            started: None,
+            logs: None,
+            compiled: None,
+            profile: false,
+            seed: None,
+            fuel_limit: None,
+            nesting: None,
+        })
+    }
+}
+
+/// Throttled executions are errors to gRPC callers, not responses.
+fn throttled(response: &ExecuteResponse) -> Option<Status> {
+    response
+        .throttled
+        .then(|| Status::resource_exhausted(response.error.as_ref().map(|error| error.message.as_str()).unwrap_or_default()))
+}
+
+fn to_proto(response: ExecuteResponse) -> v1::ExecuteResponse {
+    v1::ExecuteResponse {
+        success: response.success,
+        result_json: response.result.map(|result| result.to_string()),
+        error_code: response.error.as_ref().map(|error| error.code.name().to_string()),
+        retryable: response.error.as_ref().is_some_and(|error| error.retryable),
+        error: response.error.map(|error| error.message),
+        execution_time_ms: response.execution_time_ms,
+        memory_used_bytes: response.memory_used_bytes,
+        fuel_consumed: response.fuel_consumed,
+        stdout: response.stdout,
+        stderr: response.stderr,
+        output_truncated: response.output_truncated,
+        execution_id: response.execution_id,
+        logs: response.logs.into_iter().map(plugin_log).collect(),
+    }
+}
+
+fn plugin_log(log: PluginLog) -> v1::PluginLog {
+    let level = match log.level {
+        Level::Debug => v1::plugin_log::Level::Debug,
+        Level::Info => v1::plugin_log::Level::Info,
+        Level::Warn => v1::plugin_log::Level::Warn,
+        Level::Error => v1::plugin_log::Level::Error,
+    };
+    v1::PluginLog {
+        level: level as i32,
+        message: log.message,
+        fields_json: serde_json::Value::Object(log.fields).to_string(),
+    }
+}
+
+fn log_event(line: LogLine) -> v1::ExecuteEvent {
+    let stream = match line.stream {
+        logs::Stream::Stdout => v1::log_line::Stream::Stdout,
+        logs::Stream::Stderr => v1::log_line::Stream::Stderr,
+    };
+    v1::ExecuteEvent {
+        log: Some(v1::LogLine { stream: stream as i32, line: line.line }),
+        response: None,
+    }
+}
+
+fn extern_type(kind: Extern) -> v1::ExternType {
+    let mut ty = v1::ExternType::default();
+    match kind {
+        Extern::Function { params, results } => ty.function = Some(v1::FunctionType { params, results }),
+        Extern::Memory { minimum_pages, maximum_pages } => {
+            ty.memory = Some(v1::MemoryType { minimum_pages, maximum_pages })
+        }
+        Extern::Table { element, minimum, maximum } => ty.table = Some(v1::TableType { element, minimum, maximum }),
+        Extern::Global { content, mutable } => ty.global = Some(v1::GlobalType { content, mutable }),
+    }
+    ty
+}
+
+#[tonic::async_trait]
+impl ExtensionRuntime for GrpcRuntime {
+    async fn execute(&self, request: Request<v1::ExecuteRequest>) -> Result<Response<v1::ExecuteResponse>, Status> {
+        let span = call_span(&request, "Execute");
+        let request = self.prepare(request).await?;
+        let response = crate::execute(&self.0, request).instrument(span).await;
+        if let Some(status) = throttled(&response) {
+            return Err(status);
+        }
+        Ok(Response::new(to_proto(response)))
+    }
+
+    type ExecuteStreamStream = BoxStream<v1::ExecuteEvent>;
+
+    async fn execute_stream(
+        &self,
+        request: Request<v1::ExecuteRequest>,
+    ) -> Result<Response<Self::ExecuteStreamStream>, Status> {
+        let span = call_span(&request, "ExecuteStream");
+        let mut request = self.prepare(request).await?;
+        let (logs, mut lines) = mpsc::unbounded_channel();
+        request.logs = Some(logs);
+        let (events, stream) = mpsc::channel(16);
+
+        let state = self.0.clone();
+        tokio::spawn(async move {
+            let execution = crate::execute(&state, request);
+            tokio::pin!(execution);
+            // A caller that hangs up doesn't stop the execution
+            let response = loop {
+                tokio::select! {
+                    response = &mut execution => break response,
+                    Some(line) = lines.recv() => {
+                        let _ = events.send(Ok(log_event(line))).await;
+                    }
+                }
+            };
+            while let Ok(line) = lines.try_recv() {
+                let _ = events.send(Ok(log_event(line))).await;
+            }
+            let last = match throttled(&response) {
+                Some(status) => Err(status),
+                None => Ok(v1::ExecuteEvent { log: None, response: Some(to_proto(response)) }),
+            };
+            let _ = events.send(last).await;
+        }.instrument(span));
+        Ok(Response::new(Box::pin(ReceiverStream::new(stream))))
+    }
+
+    async fn precompile(
+        &self,
+        request: Request<v1::PrecompileRequest>,
+    ) -> Result<Response<v1::PrecompileResponse>, Status> {
+        let tenant_id = self.namespace(&request).await?;
+        let module_paths = request.into_inner().module_paths;
+        if module_paths.is_empty() {
+            return Err(Status::invalid_argument("module_paths is empty"));
+        }
+
+        let mut modules = Vec::with_capacity(module_paths.len());
+        for module_path in module_paths {
+            let (cache, error) = match compiled::load(&self.0, tenant_id.as_deref(), &module_path).await {
+                Ok(compiled) => (Some(compiled.origin.name().to_string()), None),
+                Err(e) => (None, Some(format!("{:#}", e))),
+            };
+            modules.push(v1::PrecompiledModule { module_path, cache, error });
+        }
+        Ok(Response::new(v1::PrecompileResponse { modules }))
+    }
+
+    async fn inspect_module(
+        &self,
+        request: Request<v1::InspectModuleRequest>,
+    ) -> Result<Response<v1::InspectModuleResponse>, Status> {
+        let tenant_id = self.namespace(&request).await?;
+        let module_path = request.into_inner().module_path;
+        let compiled = compiled::load(&self.0, tenant_id.as_deref(), &module_path)
+            .await
+            .map_err(|e| Status::invalid_argument(format!("{:#}", e)))?;
+        let info = inspect(&compiled).map_err(|e| Status::failed_precondition(e.to_string()))?;
+
+        Ok(Response::new(v1::InspectModuleResponse {
+            sha256: info.sha256,
+            exports: info
+                .exports
+                .into_iter()
+                .map(|export| v1::ModuleExport { name: export.name, kind: Some(extern_type(export.kind)) })
+                .collect(),
+            imports: info
+                .imports
+                .into_iter()
+                .map(|import| v1::ModuleImport {
+                    module: import.module,
+                    name: import.name,
+                    kind: Some(extern_type(import.kind)),
+                })
+                .collect(),
+        }))
+    }
+}
+
+/// Serves gRPC on `port`, behind mTLS when configured.
+pub async fn serve(port: u16, state: Arc<ServiceState>, tls: Option<Arc<MtlsContext>>) {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let service = ExtensionRuntimeServer::new(GrpcRuntime(state));
+
+    let result = match tls {
+        Some(tls) => match crm_tls::accept(addr, tls.server_config()).await {
+            Ok(connections) => {
+                let incoming = ReceiverStream::new(connections).map(|stream| Ok::<_, std::io::Error>(TlsConnection(stream)));
+                Server::builder().add_service(service).serve_with_incoming(incoming).await.map_err(|e| e.to_string())
+            }
+            Err(e) => Err(e.to_string()),
+        },
+        None => {
+            info!("gRPC server running on {}", addr);
+            Server::builder().add_service(service).serve(addr).await.map_err(|e| e.to_string())
+        }
+    };
+    if let Err(e) = result {
+        error!("gRPC server stopped: {}", e);
+    }
+}
+
+/// An authenticated connection, in the shape tonic accepts.
+struct TlsConnection(ServerStream);
+
+impl Connected for TlsConnection {
+    type ConnectInfo = ();
+
+    fn connect_info(&self) -> Self::ConnectInfo {}
+}
+
+impl AsyncRead for TlsConnection {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsConnection {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.get_mut().0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_shutdown(cx)
+    }
+}
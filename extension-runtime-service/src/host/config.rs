@@ -0,0 +1,111 @@
+//! `crm.config_get` and `crm.secret_get`: the settings the tenant
+//! configured for the plugin, and the secrets they reference.
+//!
+//! ```text
+//! config_get(value_ptr: i32, value_cap: i32) -> i32
+//! secret_get(key_ptr: i32, key_len: i32, value_ptr: i32, value_cap: i32) -> i32
+//! ```
+//!
+//! Writes the settings, a JSON object, to `value_ptr` and returns its
+//! length, or one of the negative codes of `kv`: `NOT_FOUND` when the
+//! tenant set none, `BUFFER_TOO_SMALL`, `UNAVAILABLE` or `NO_TENANT`.
+//! Settings are at most `plugin_settings_max_bytes`, so a buffer that large
+//! always fits. They're set through the settings API, under the module
+//! path the plugin executes as; see `settings`.
+//!
+//! `secret_get` writes the value of the secret the setting `key` references
+//! as `secret://{name}` and returns its length; `NOT_FOUND` when there's no
+//! such setting or it isn't a reference, `DENIED` for modules not in
+//! `secret_settings_modules`, `INVALID_KEY` for keys over 256 bytes or not
+//! UTF-8, and otherwise the codes of `config_get`.
+
+use super::kv::{BUFFER_TOO_SMALL, INVALID_KEY, NOT_FOUND, NO_TENANT, UNAVAILABLE};
+use super::{block_on, call, memory, Host};
+use anyhow::Result;
+use metrics::counter;
+use std::time::{Duration, Instant};
+use tracing::warn;
+use wasmtime::Caller;
+
+/// How long a call may wait on Redis, at most.
+const CONFIG_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long `secret_get` may wait on Redis and the secrets backend, at most.
+const SECRET_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The module may not read secrets.
+pub const DENIED: i32 = -7;
+
+/// Settings keys are short, like KV keys.
+const MAX_KEY_BYTES: usize = 256;
+
+fn code(op: &'static str, code: i32) -> Result<i32> {
+    let result = match code {
+        NOT_FOUND => "not_found",
+        INVALID_KEY => "invalid_key",
+        DENIED => "denied",
+        BUFFER_TOO_SMALL => "buffer_too_small",
+        UNAVAILABLE => "unavailable",
+        NO_TENANT => "no_tenant",
+        _ => "ok",
+    };
+    counter!("plugin_config_reads_total", "op" => op, "result" => result).increment(1);
+    Ok(code)
+}
+
+pub fn config_get(mut caller: Caller<'_, Host>, value_ptr: i32, value_cap: i32) -> Result<i32> {
+    let call = call(&caller, "config_get")?;
+    let Some(tenant_id) = call.tenant_id.clone() else {
+        return code("config", NO_TENANT);
+    };
+    let (settings, module_path) = (call.settings.clone(), call.module_path.clone());
+    let timeout = call.deadline.saturating_duration_since(Instant::now()).min(CONFIG_TIMEOUT);
+
+    let value = match block_on(settings.get(&tenant_id, &module_path, timeout)) {
+        Ok(Some(value)) => value,
+        Ok(None) => return code("config", NOT_FOUND),
+        Err(e) => {
+            warn!("Plugin config_get failed: {:#}", e);
+            return code("config", UNAVAILABLE);
+        }
+    };
+    if value.len() > value_cap.max(0) as usize {
+        return code("config", BUFFER_TOO_SMALL);
+    }
+    memory(&mut caller)?.write(&mut caller, value_ptr as u32 as usize, value.as_bytes())?;
+    code("config", value.len() as i32)
+}
+
+pub fn secret_get(mut caller: Caller<'_, Host>, key_ptr: i32, key_len: i32, value_ptr: i32, value_cap: i32) -> Result<i32> {
+    let call = call(&caller, "secret_get")?;
+    let Some(tenant_id) = call.tenant_id.clone() else {
+        return code("secret", NO_TENANT);
+    };
+    let (settings, module_path) = (call.settings.clone(), call.module_path.clone());
+    if !settings.reads_secrets(&module_path) {
+        return code("secret", DENIED);
+    }
+    let timeout = call.deadline.saturating_duration_since(Instant::now()).min(SECRET_TIMEOUT);
+    if key_len <= 0 || key_len as usize > MAX_KEY_BYTES {
+        return code("secret", INVALID_KEY);
+    }
+    let mut key = vec![0; key_len as usize];
+    memory(&mut caller)?.read(&caller, key_ptr as u32 as usize, &mut key)?;
+    let Ok(key) = String::from_utf8(key) else {
+        return code("secret", INVALID_KEY);
+    };
+
+    let value = match block_on(settings.secret(&tenant_id, &module_path, &key, timeout)) {
+        Ok(Some(value)) => value,
+        Ok(None) => return code("secret", NOT_FOUND),
+        Err(e) => {
+            warn!("Plugin secret_get failed: {:#}", e);
+            return code("secret", UNAVAILABLE);
+        }
+    };
+    if value.len() > value_cap.max(0) as usize {
+        return code("secret", BUFFER_TOO_SMALL);
+    }
+    memory(&mut caller)?.write(&mut caller, value_ptr as u32 as usize, value.as_bytes())?;
+    code("secret", value.len() as i32)
+}
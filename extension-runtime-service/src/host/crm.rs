@@ -0,0 +1,213 @@
+//! `crm.crm_query`: read access to the tenant's CRM records.
+//!
+//! ```text
+//! crm_query(request_ptr: i32, request_len: i32, response_ptr: i32, response_cap: i32) -> i32
+//! ```
+//!
+//! The request is JSON, `{"entity": "contacts", "search": "acme", "limit":
+//! 20, "offset": 0}` for a page of records or `{"entity": "deals", "id":
+//! "…"}` for one, with `entity` one of `contacts`, `deals` and `companies`.
+//! The response, `{"records": […], "total": 42}`, is written to
+//! `response_ptr` and its length returned, or one of the negative codes
+//! below.
+//!
+//! Queries go to the core CRM API at `crm_api_url` with the runtime's
+//! `crm_api_token` and the execution's tenant in `X-Tenant-ID`, so plugins
+//! only ever see their tenant's records. An execution reads at most
+//! `crm_query_max_rows` records in all, and each costs
+//! `crm_query_fuel_per_record` fuel.
+
+use super::{block_on, call, memory, Host};
+use crate::{RuntimeConfig, TENANT_HEADER};
+use anyhow::{Context, Result};
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::debug;
+use wasmtime::{Caller, Trap};
+
+/// No record with the requested id.
+pub const NOT_FOUND: i32 = -1;
+/// The request isn't valid JSON or names an unknown entity.
+pub const INVALID_REQUEST: i32 = -2;
+/// The execution already read `crm_query_max_rows` records.
+pub const ROW_LIMIT: i32 = -3;
+/// The CRM API is not configured, failed or timed out.
+pub const FAILED: i32 = -4;
+/// The response doesn't fit in `response_cap` bytes.
+pub const BUFFER_TOO_SMALL: i32 = -5;
+/// The execution has no tenant to read records of.
+pub const NO_TENANT: i32 = -6;
+
+const MAX_REQUEST_BYTES: usize = 4 * 1024;
+
+/// Largest page the CRM API serves.
+const MAX_PAGE: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+struct QueryRequest {
+    entity: String,
+    id: Option<String>,
+    search: Option<String>,
+    limit: Option<usize>,
+    #[serde(default)]
+    offset: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct QueryResponse {
+    records: Vec<Value>,
+    total: u64,
+}
+
+pub struct CrmApi {
+    client: reqwest::Client,
+    url: Option<String>,
+    token: Option<String>,
+    max_rows: usize,
+    fuel_per_record: u64,
+    timeout: Duration,
+}
+
+impl CrmApi {
+    pub fn from_config(config: &RuntimeConfig) -> Result<Arc<Self>> {
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .context("Failed to build crm_query client")?;
+
+        Ok(Arc::new(CrmApi {
+            client,
+            url: config.crm_api_url.as_ref().map(|url| url.trim_end_matches('/').to_string()),
+            token: config.crm_api_token.clone(),
+            max_rows: config.crm_query_max_rows,
+            fuel_per_record: config.crm_query_fuel_per_record,
+            timeout: Duration::from_millis(config.crm_query_timeout_ms),
+        }))
+    }
+
+    /// At most `limit` records for `tenant_id`.
+    async fn query(
+        &self,
+        tenant_id: &str,
+        request: QueryRequest,
+        limit: usize,
+        timeout: Duration,
+    ) -> Result<QueryResponse, i32> {
+        let Some(url) = &self.url else {
+            return Err(FAILED);
+        };
+        // Deals are opportunities to the CRM API; its list responses name
+        // the records after the collection
+        let collection = match request.entity.as_str() {
+            "contacts" => "contacts",
+            "companies" => "companies",
+            "deals" => "opportunities",
+            _ => return Err(INVALID_REQUEST),
+        };
+        let mut builder = match &request.id {
+            Some(id) => {
+                let id = url::form_urlencoded::byte_serialize(id.as_bytes()).collect::<String>();
+                self.client.get(format!("{}/{}/{}", url, collection, id))
+            }
+            None => {
+                let page = limit.min(MAX_PAGE);
+                let mut query = vec![("skip", request.offset.to_string()), ("limit", page.to_string())];
+                if let Some(search) = &request.search {
+                    query.push(("search", search.clone()));
+                }
+                self.client.get(format!("{}/{}/", url, collection)).query(&query)
+            }
+        };
+        builder = builder.header(TENANT_HEADER, tenant_id).timeout(timeout);
+        if let Some(token) = &self.token {
+            builder = builder.bearer_auth(token);
+        }
+
+        let response = builder.send().await.map_err(|e| {
+            debug!("crm_query for tenant {} failed: {}", tenant_id, e);
+            FAILED
+        })?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND && request.id.is_some() {
+            return Err(NOT_FOUND);
+        }
+        if !response.status().is_success() {
+            debug!("crm_query for tenant {} got {}", tenant_id, response.status());
+            return Err(FAILED);
+        }
+        let mut body: Value = response.json().await.map_err(|_| FAILED)?;
+
+        if request.id.is_some() {
+            return Ok(QueryResponse { records: vec![body], total: 1 });
+        }
+        let total = body["total"].as_u64().unwrap_or_default();
+        let Value::Array(mut records) = body[collection].take() else {
+            return Err(FAILED);
+        };
+        records.truncate(limit);
+        Ok(QueryResponse { records, total })
+    }
+}
+
+pub fn crm_query(
+    mut caller: Caller<'_, Host>,
+    request_ptr: i32,
+    request_len: i32,
+    response_ptr: i32,
+    response_cap: i32,
+) -> Result<i32> {
+    let call = call(&caller, "crm_query")?;
+    let (crm, tenant_id, deadline, rows_read) =
+        (call.crm.clone(), call.tenant_id.clone(), call.deadline, call.crm_rows_read);
+    let memory = memory(&mut caller)?;
+
+    let code = |result: &'static str, code: i32| {
+        counter!("plugin_crm_queries_total", "result" => result).increment(1);
+        Ok(code)
+    };
+    let Some(tenant_id) = tenant_id else {
+        return code("no_tenant", NO_TENANT);
+    };
+    if request_len < 0 || request_len as usize > MAX_REQUEST_BYTES {
+        return code("invalid", INVALID_REQUEST);
+    }
+    let mut request = vec![0; request_len as usize];
+    memory.read(&caller, request_ptr as u32 as usize, &mut request)?;
+    let Ok(request) = serde_json::from_slice::<QueryRequest>(&request) else {
+        return code("invalid", INVALID_REQUEST);
+    };
+    let rows_left = crm.max_rows.saturating_sub(rows_read);
+    if rows_left == 0 {
+        return code("row_limit", ROW_LIMIT);
+    }
+    let limit = request.limit.unwrap_or(rows_left).clamp(1, rows_left);
+
+    let timeout = deadline.saturating_duration_since(Instant::now()).min(crm.timeout);
+    let response = match block_on(crm.query(&tenant_id, request, limit, timeout)) {
+        Ok(response) => response,
+        Err(NOT_FOUND) => return code("not_found", NOT_FOUND),
+        Err(INVALID_REQUEST) => return code("invalid", INVALID_REQUEST),
+        Err(other) => return code("failed", other),
+    };
+
+    let records = response.records.len();
+    if let Some(call) = caller.data_mut().call.as_mut() {
+        call.crm_rows_read += records;
+    }
+    let cost = records as u64 * crm.fuel_per_record;
+    let fuel = caller.get_fuel()?;
+    if fuel < cost {
+        caller.set_fuel(0)?;
+        return Err(Trap::OutOfFuel.into());
+    }
+    caller.set_fuel(fuel - cost)?;
+
+    let response = serde_json::to_vec(&response)?;
+    if response.len() > response_cap.max(0) as usize {
+        return code("buffer_too_small", BUFFER_TOO_SMALL);
+    }
+    memory.write(&mut caller, response_ptr as u32 as usize, &response)?;
+    code("ok", response.len() as i32)
+}
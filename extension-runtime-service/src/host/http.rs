@@ -0,0 +1,205 @@
+//! `crm.http_fetch`: outbound HTTP for plugins.
+//!
+//! ```text
+//! http_fetch(request_ptr: i32, request_len: i32, response_ptr: i32, response_cap: i32) -> i32
+//! ```
+//!
+//! The request is JSON, `{"method": "POST", "url": "https://…", "headers":
+//! {…}, "body": "…"}`, of which only `url` is required. The response,
+//! `{"status": 200, "headers": {…}, "body": "…"}`, is written to
+//! `response_ptr` and its length returned, or one of the negative codes
+//! below. Redirects are returned rather than followed.
+//!
+//! A module may only reach the hosts `http_fetch_allowlists` lists for its
+//! module path, e.g. in the config file:
+//!
+//! ```toml
+//! [http_fetch_allowlists]
+//! "enrich.wasm" = ["api.clearbit.com", "*.hubspot.com"]
+//! ```
+//!
+//! Requests and responses are capped at `http_fetch_max_request_bytes` and
+//! `http_fetch_max_response_bytes`, each call at `http_fetch_timeout_ms`
+//! or what is left of the execution's timeout, and every response byte
+//! costs the plugin `http_fetch_fuel_per_byte` fuel.
+
+use super::{block_on, call, memory, Host};
+use crate::RuntimeConfig;
+use anyhow::{Context, Result};
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+use url::Url;
+use wasmtime::{Caller, Trap};
+
+/// The host isn't on the module's allowlist.
+pub const DENIED: i32 = -1;
+/// The request isn't valid JSON, a valid URL or method, or is too large.
+pub const INVALID_REQUEST: i32 = -2;
+/// The request failed or timed out.
+pub const FAILED: i32 = -3;
+/// The response is larger than `http_fetch_max_response_bytes`.
+pub const RESPONSE_TOO_LARGE: i32 = -4;
+/// The response doesn't fit in `response_cap` bytes.
+pub const BUFFER_TOO_SMALL: i32 = -5;
+
+#[derive(Debug, Deserialize)]
+struct FetchRequest {
+    #[serde(default = "default_method")]
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    body: Option<String>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+#[derive(Debug, Serialize)]
+struct FetchResponse {
+    status: u16,
+    headers: HashMap<String, String>,
+    body: String,
+}
+
+pub struct HttpFetch {
+    client: reqwest::Client,
+    allowlists: HashMap<String, Vec<String>>,
+    max_request_bytes: usize,
+    max_response_bytes: usize,
+    timeout: Duration,
+    fuel_per_byte: u64,
+}
+
+impl HttpFetch {
+    pub fn from_config(config: &RuntimeConfig) -> Result<Arc<Self>> {
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .context("Failed to build http_fetch client")?;
+
+        Ok(Arc::new(HttpFetch {
+            client,
+            allowlists: config.http_fetch_allowlists.clone(),
+            max_request_bytes: config.http_fetch_max_request_bytes,
+            max_response_bytes: config.http_fetch_max_response_bytes,
+            timeout: Duration::from_millis(config.http_fetch_timeout_ms),
+            fuel_per_byte: config.http_fetch_fuel_per_byte,
+        }))
+    }
+
+    /// Whether `module_path` may reach `host`; `*.example.com` matches
+    /// subdomains of example.com.
+    fn allowed(&self, module_path: &str, host: &str) -> bool {
+        let Some(allowlist) = self.allowlists.get(module_path) else {
+            return false;
+        };
+        allowlist.iter().any(|pattern| match pattern.strip_prefix("*.") {
+            Some(domain) => host.strip_suffix(domain).is_some_and(|sub| sub.ends_with('.')),
+            None => host.eq_ignore_ascii_case(pattern),
+        })
+    }
+
+    async fn fetch(&self, module_path: &str, request: FetchRequest, timeout: Duration) -> Result<FetchResponse, i32> {
+        let url = Url::parse(&request.url).map_err(|_| INVALID_REQUEST)?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(INVALID_REQUEST);
+        }
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        if !self.allowed(module_path, &host) {
+            warn!("Plugin {} may not fetch from {}", module_path, host);
+            return Err(DENIED);
+        }
+        let method = reqwest::Method::from_bytes(request.method.as_bytes()).map_err(|_| INVALID_REQUEST)?;
+
+        let mut builder = self.client.request(method, url).timeout(timeout);
+        // The allowlist is checked against the URL, so it names the host too
+        for (name, value) in request.headers.iter().filter(|(name, _)| !name.eq_ignore_ascii_case("host")) {
+            builder = builder.header(name, value);
+        }
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+        let mut response = builder.send().await.map_err(|e| {
+            debug!("http_fetch from {} failed: {}", module_path, e);
+            FAILED
+        })?;
+
+        if response.content_length().is_some_and(|len| len > self.max_response_bytes as u64) {
+            return Err(RESPONSE_TOO_LARGE);
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|_| FAILED)? {
+            body.extend_from_slice(&chunk);
+            if body.len() > self.max_response_bytes {
+                return Err(RESPONSE_TOO_LARGE);
+            }
+        }
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+
+        Ok(FetchResponse {
+            status: response.status().as_u16(),
+            headers,
+            body: String::from_utf8_lossy(&body).into_owned(),
+        })
+    }
+}
+
+pub fn http_fetch(
+    mut caller: Caller<'_, Host>,
+    request_ptr: i32,
+    request_len: i32,
+    response_ptr: i32,
+    response_cap: i32,
+) -> Result<i32> {
+    let call = call(&caller, "http_fetch")?;
+    let (http, module_path, deadline) = (call.http.clone(), call.module_path.clone(), call.deadline);
+    let memory = memory(&mut caller)?;
+
+    let code = |result: &'static str, code: i32| {
+        counter!("plugin_http_fetches_total", "result" => result).increment(1);
+        Ok(code)
+    };
+    if request_len < 0 || request_len as usize > http.max_request_bytes {
+        return code("invalid", INVALID_REQUEST);
+    }
+    let mut request = vec![0; request_len as usize];
+    memory.read(&caller, request_ptr as u32 as usize, &mut request)?;
+    let Ok(request) = serde_json::from_slice::<FetchRequest>(&request) else {
+        return code("invalid", INVALID_REQUEST);
+    };
+
+    let timeout = deadline.saturating_duration_since(Instant::now()).min(http.timeout);
+    let response = match block_on(http.fetch(&module_path, request, timeout)) {
+        Ok(response) => response,
+        Err(DENIED) => return code("denied", DENIED),
+        Err(RESPONSE_TOO_LARGE) => return code("too_large", RESPONSE_TOO_LARGE),
+        Err(INVALID_REQUEST) => return code("invalid", INVALID_REQUEST),
+        Err(other) => return code("failed", other),
+    };
+
+    let cost = response.body.len() as u64 * http.fuel_per_byte;
+    let fuel = caller.get_fuel()?;
+    if fuel < cost {
+        caller.set_fuel(0)?;
+        return Err(Trap::OutOfFuel.into());
+    }
+    caller.set_fuel(fuel - cost)?;
+
+    let response = serde_json::to_vec(&response)?;
+    if response.len() > response_cap.max(0) as usize {
+        return code("ok", BUFFER_TOO_SMALL);
+    }
+    memory.write(&mut caller, response_ptr as u32 as usize, &response)?;
+    counter!("plugin_http_fetches_total", "result" => "ok").increment(1);
+    Ok(response.len() as i32)
+}
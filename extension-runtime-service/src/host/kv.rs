@@ -0,0 +1,180 @@
+//! `crm.kv_get`, `crm.kv_set` and `crm.kv_delete`: state that outlives an
+//! execution.
+//!
+//! ```text
+//! kv_get(key_ptr: i32, key_len: i32, value_ptr: i32, value_cap: i32) -> i32
+//! kv_set(key_ptr: i32, key_len: i32, value_ptr: i32, value_len: i32, ttl_secs: i64) -> i32
+//! kv_delete(key_ptr: i32, key_len: i32) -> i32
+//! ```
+//!
+//! `kv_get` writes the value to `value_ptr` and returns its length,
+//! `kv_set` and `kv_delete` return 0, or each one of the negative codes
+//! below. Keys are 1 to 256 letters, digits and `_-./`.
+//!
+//! Values live in Redis under `plugin_kv:{tenant_id}:{module_path}:{key}`,
+//! so plugins only see their own keys, and only for the tenant they run
+//! for; executions without a tenant have no store. Values are capped at
+//! `kv_max_value_bytes` and expire after `ttl_secs`, or
+//! `kv_max_ttl_secs` when that is 0 or longer.
+
+use super::{block_on, call, memory, Host};
+use crate::redis_store::RedisStore;
+use crate::RuntimeConfig;
+use anyhow::Result;
+use metrics::counter;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+use wasmtime::Caller;
+
+/// No value under the key.
+pub const NOT_FOUND: i32 = -1;
+/// The key is empty, too long or has other characters, or the TTL is negative.
+pub const INVALID_KEY: i32 = -2;
+/// The value is larger than `kv_max_value_bytes`.
+pub const VALUE_TOO_LARGE: i32 = -3;
+/// The value doesn't fit in `value_cap` bytes.
+pub const BUFFER_TOO_SMALL: i32 = -4;
+/// Redis is unavailable or didn't answer in time.
+pub const UNAVAILABLE: i32 = -5;
+/// The execution has no tenant to keep values for.
+pub const NO_TENANT: i32 = -6;
+
+const MAX_KEY_BYTES: usize = 256;
+
+/// How long a call may wait on Redis, at most.
+const KV_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub struct KvStore {
+    redis: Arc<RedisStore>,
+    max_value_bytes: usize,
+    max_ttl_secs: u64,
+}
+
+impl KvStore {
+    pub fn new(redis_url: &str, config: &RuntimeConfig) -> Arc<Self> {
+        Arc::new(KvStore {
+            redis: RedisStore::new(redis_url),
+            max_value_bytes: config.kv_max_value_bytes,
+            max_ttl_secs: config.kv_max_ttl_secs,
+        })
+    }
+
+    /// Runs `command` on the store's Redis connection, by the execution's
+    /// `deadline`.
+    async fn run<T: redis::FromRedisValue>(&self, command: &redis::Cmd, deadline: Instant) -> Result<T, i32> {
+        let timeout = deadline.saturating_duration_since(Instant::now()).min(KV_TIMEOUT);
+        self.redis.run(command, timeout).await.map_err(|e| {
+            warn!("Plugin KV call failed: {}", e);
+            UNAVAILABLE
+        })
+    }
+}
+
+/// The Redis key for the calling execution's `key`.
+fn redis_key(
+    caller: &mut Caller<'_, Host>,
+    function: &str,
+    key_ptr: i32,
+    key_len: i32,
+) -> Result<Result<String, i32>> {
+    let call = call(caller, function)?;
+    let Some(tenant_id) = call.tenant_id.clone() else {
+        return Ok(Err(NO_TENANT));
+    };
+    let module_path = call.module_path.clone();
+    if key_len <= 0 || key_len as usize > MAX_KEY_BYTES {
+        return Ok(Err(INVALID_KEY));
+    }
+    let memory = memory(caller)?;
+    let mut key = vec![0; key_len as usize];
+    memory.read(&*caller, key_ptr as u32 as usize, &mut key)?;
+    // Without `:` in keys, neither can pass for another module's
+    let valid = key.iter().all(|&b| b.is_ascii_alphanumeric() || b"_-./".contains(&b));
+    if !valid {
+        return Ok(Err(INVALID_KEY));
+    }
+    Ok(Ok(format!("plugin_kv:{}:{}:{}", tenant_id, module_path, String::from_utf8_lossy(&key))))
+}
+
+fn code(op: &'static str, code: i32) -> Result<i32> {
+    let result = match code {
+        NOT_FOUND => "not_found",
+        INVALID_KEY => "invalid_key",
+        VALUE_TOO_LARGE => "too_large",
+        BUFFER_TOO_SMALL => "buffer_too_small",
+        UNAVAILABLE => "unavailable",
+        NO_TENANT => "no_tenant",
+        _ => "ok",
+    };
+    counter!("plugin_kv_operations_total", "op" => op, "result" => result).increment(1);
+    Ok(code)
+}
+
+pub fn kv_get(mut caller: Caller<'_, Host>, key_ptr: i32, key_len: i32, value_ptr: i32, value_cap: i32) -> Result<i32> {
+    let key = match redis_key(&mut caller, "kv_get", key_ptr, key_len)? {
+        Ok(key) => key,
+        Err(error) => return code("get", error),
+    };
+    let call = call(&caller, "kv_get")?;
+    let (kv, deadline) = (call.kv.clone(), call.deadline);
+
+    let value = match block_on(kv.run::<Option<Vec<u8>>>(redis::cmd("GET").arg(&key), deadline)) {
+        Ok(Some(value)) => value,
+        Ok(None) => return code("get", NOT_FOUND),
+        Err(error) => return code("get", error),
+    };
+    if value.len() > value_cap.max(0) as usize {
+        return code("get", BUFFER_TOO_SMALL);
+    }
+    memory(&mut caller)?.write(&mut caller, value_ptr as u32 as usize, &value)?;
+    code("get", value.len() as i32)
+}
+
+pub fn kv_set(
+    mut caller: Caller<'_, Host>,
+    key_ptr: i32,
+    key_len: i32,
+    value_ptr: i32,
+    value_len: i32,
+    ttl_secs: i64,
+) -> Result<i32> {
+    let key = match redis_key(&mut caller, "kv_set", key_ptr, key_len)? {
+        Ok(key) => key,
+        Err(error) => return code("set", error),
+    };
+    let call = call(&caller, "kv_set")?;
+    let (kv, deadline) = (call.kv.clone(), call.deadline);
+    if ttl_secs < 0 || value_len < 0 {
+        return code("set", INVALID_KEY);
+    }
+    if value_len as usize > kv.max_value_bytes {
+        return code("set", VALUE_TOO_LARGE);
+    }
+    let mut value = vec![0; value_len as usize];
+    memory(&mut caller)?.read(&caller, value_ptr as u32 as usize, &mut value)?;
+
+    let ttl = match ttl_secs as u64 {
+        0 => kv.max_ttl_secs,
+        ttl => ttl.min(kv.max_ttl_secs),
+    };
+    match block_on(kv.run::<()>(redis::cmd("SET").arg(&key).arg(value).arg("EX").arg(ttl), deadline)) {
+        Ok(()) => code("set", 0),
+        Err(error) => code("set", error),
+    }
+}
+
+pub fn kv_delete(mut caller: Caller<'_, Host>, key_ptr: i32, key_len: i32) -> Result<i32> {
+    let key = match redis_key(&mut caller, "kv_delete", key_ptr, key_len)? {
+        Ok(key) => key,
+        Err(error) => return code("delete", error),
+    };
+    let call = call(&caller, "kv_delete")?;
+    let (kv, deadline) = (call.kv.clone(), call.deadline);
+
+    match block_on(kv.run::<u64>(redis::cmd("DEL").arg(&key), deadline)) {
+        Ok(0) => code("delete", NOT_FOUND),
+        Ok(_) => code("delete", 0),
+        Err(error) => code("delete", error),
+    }
+}
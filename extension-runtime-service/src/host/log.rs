@@ -0,0 +1,137 @@
+//! `crm.log_debug`, `crm.log_info`, `crm.log_warn` and `crm.log_error`:
+//! structured logging for plugins.
+//!
+//! ```text
+//! log_info(message_ptr: i32, message_len: i32, fields_ptr: i32, fields_len: i32)
+//! ```
+//!
+//! `fields` is an optional JSON object, `fields_len` 0 for none. Lines go
+//! through the runtime's tracing under the `plugin` target, tagged with the
+//! module path, tenant and execution id, and back to callers that set
+//! `include_logs`, up to `max_plugin_logs` per execution. Messages are cut
+//! to 8 KiB.
+
+use super::{call, memory, Host};
+use anyhow::Result;
+use metrics::counter;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, error, info, warn};
+use wasmtime::Caller;
+
+const MAX_MESSAGE_BYTES: usize = 8 * 1024;
+/// Larger fields are logged without them.
+const MAX_FIELDS_BYTES: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginLog {
+    pub level: Level,
+    pub message: String,
+    pub fields: Map<String, Value>,
+}
+
+/// The lines one execution logged, for its response.
+#[derive(Clone)]
+pub struct PluginLogs {
+    /// 0 when the caller didn't ask for them.
+    limit: usize,
+    lines: Arc<Mutex<Vec<PluginLog>>>,
+}
+
+impl PluginLogs {
+    pub fn new(limit: usize) -> Self {
+        PluginLogs { limit, lines: Arc::default() }
+    }
+
+    pub fn take(&self) -> Vec<PluginLog> {
+        std::mem::take(&mut *self.lines.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn push(&self, line: PluginLog) {
+        let mut lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        if lines.len() < self.limit {
+            lines.push(line);
+        }
+    }
+}
+
+fn log(
+    level: Level,
+    mut caller: Caller<'_, Host>,
+    message_ptr: i32,
+    message_len: i32,
+    fields_ptr: i32,
+    fields_len: i32,
+) -> Result<()> {
+    let memory = memory(&mut caller)?;
+    let read = |ptr: i32, len: i32, max: usize| -> Result<Vec<u8>> {
+        let mut bytes = vec![0; (len.max(0) as usize).min(max)];
+        memory.read(&caller, ptr as u32 as usize, &mut bytes)?;
+        Ok(bytes)
+    };
+    let message = String::from_utf8_lossy(&read(message_ptr, message_len, MAX_MESSAGE_BYTES)?).into_owned();
+    let fields = match fields_len {
+        0 => Map::new(),
+        len if len as usize > MAX_FIELDS_BYTES => Map::new(),
+        len => serde_json::from_slice(&read(fields_ptr, len, MAX_FIELDS_BYTES)?).unwrap_or_default(),
+    };
+
+    let call = call(&caller, "log")?;
+    let (module_path, tenant_id, execution_id) =
+        (&call.module_path, call.tenant_id.as_deref().unwrap_or_default(), &call.execution_id);
+    let plugin_fields = Value::Object(fields.clone());
+    match level {
+        Level::Debug => {
+            debug!(target: "plugin", module_path = %module_path, tenant_id, execution_id = %execution_id, fields = %plugin_fields, "{}", message)
+        }
+        Level::Info => {
+            info!(target: "plugin", module_path = %module_path, tenant_id, execution_id = %execution_id, fields = %plugin_fields, "{}", message)
+        }
+        Level::Warn => {
+            warn!(target: "plugin", module_path = %module_path, tenant_id, execution_id = %execution_id, fields = %plugin_fields, "{}", message)
+        }
+        Level::Error => {
+            error!(target: "plugin", module_path = %module_path, tenant_id, execution_id = %execution_id, fields = %plugin_fields, "{}", message)
+        }
+    }
+    counter!("plugin_log_lines_total", "level" => level.name()).increment(1);
+    call.logs.push(PluginLog { level, message, fields });
+    Ok(())
+}
+
+pub fn log_debug(caller: Caller<'_, Host>, message_ptr: i32, message_len: i32, fields_ptr: i32, fields_len: i32) -> Result<()> {
+    log(Level::Debug, caller, message_ptr, message_len, fields_ptr, fields_len)
+}
+
+pub fn log_info(caller: Caller<'_, Host>, message_ptr: i32, message_len: i32, fields_ptr: i32, fields_len: i32) -> Result<()> {
+    log(Level::Info, caller, message_ptr, message_len, fields_ptr, fields_len)
+}
+
+pub fn log_warn(caller: Caller<'_, Host>, message_ptr: i32, message_len: i32, fields_ptr: i32, fields_len: i32) -> Result<()> {
+    log(Level::Warn, caller, message_ptr, message_len, fields_ptr, fields_len)
+}
+
+pub fn log_error(caller: Caller<'_, Host>, message_ptr: i32, message_len: i32, fields_ptr: i32, fields_len: i32) -> Result<()> {
+    log(Level::Error, caller, message_ptr, message_len, fields_ptr, fields_len)
+}
@@ -0,0 +1,195 @@
+//! Functions the runtime provides to plugins, imported from the `crm`
+//! module. They are linked into module instances with or without WASI, and
+//! act on behalf of the execution in the store's [`Call`]. Those behind a
+//! capability are only linked for plugins that have it, and `http_fetch`
+//! only for those whose security profile allows the network; see
+//! `capabilities`. Components only get WASI; the host functions work on a
+//! core module's exported memory.
+
+pub mod config;
+pub mod crm;
+pub mod http;
+pub mod kv;
+pub mod log;
+pub mod plugins;
+
+use crate::capabilities::{Capabilities, Capability};
+use crate::executions::Progress;
+use crate::logs::Stdio;
+use crate::profiles::Profile;
+use crate::profiling::Profiler;
+use crate::quotas::Limiter;
+use crate::scratch::Scratch;
+use crate::settings::PluginSettings;
+use crate::traps::Coredump;
+use anyhow::{Context, Result};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+use wasmtime::{Caller, Linker, Memory};
+use wasmtime_wasi::preview2::preview1::{WasiPreview1Adapter, WasiPreview1View};
+use wasmtime_wasi::preview2::{Table, WasiCtx, WasiView};
+
+/// Import module of the host functions.
+pub const HOST_MODULE: &str = "crm";
+
+/// Host functions plugins may import from [`HOST_MODULE`].
+pub const FUNCTIONS: &[&str] = &[
+    "http_fetch",
+    "kv_get",
+    "kv_set",
+    "kv_delete",
+    "config_get",
+    "secret_get",
+    "log_debug",
+    "log_info",
+    "log_warn",
+    "log_error",
+    "crm_query",
+    "call_plugin",
+];
+
+/// A store's data.
+pub struct Host {
+    /// WASI Preview 2, which preview1 modules reach through the adapter.
+    wasi: WasiCtx,
+    table: Table,
+    adapter: WasiPreview1Adapter,
+    /// Where the WASI context's stdout and stderr go.
+    pub stdio: Stdio,
+    /// The execution being served; unset while the instance is warm.
+    pub call: Option<Call>,
+    /// When the store was made, which bounds its start function.
+    pub created_at: Instant,
+    /// The store's memory and table limits, and its memory use.
+    pub limiter: Limiter,
+}
+
+impl Host {
+    pub fn new(wasi: WasiCtx, stdio: Stdio, limiter: Limiter) -> Self {
+        Host {
+            wasi,
+            table: Table::new(),
+            adapter: WasiPreview1Adapter::new(),
+            stdio,
+            call: None,
+            created_at: Instant::now(),
+            limiter,
+        }
+    }
+}
+
+impl WasiView for Host {
+    fn table(&self) -> &Table {
+        &self.table
+    }
+
+    fn table_mut(&mut self) -> &mut Table {
+        &mut self.table
+    }
+
+    fn ctx(&self) -> &WasiCtx {
+        &self.wasi
+    }
+
+    fn ctx_mut(&mut self) -> &mut WasiCtx {
+        &mut self.wasi
+    }
+}
+
+impl WasiPreview1View for Host {
+    fn adapter(&self) -> &WasiPreview1Adapter {
+        &self.adapter
+    }
+
+    fn adapter_mut(&mut self) -> &mut WasiPreview1Adapter {
+        &mut self.adapter
+    }
+}
+
+/// What host functions know about the execution that called them.
+pub struct Call {
+    pub module_path: String,
+    pub tenant_id: Option<String>,
+    pub execution_id: String,
+    /// When the execution times out; host calls don't wait past it.
+    pub deadline: Instant,
+    /// Whether the execution is cancelled, and its fuel; see `executions`.
+    pub progress: Arc<Progress>,
+    pub http: Arc<http::HttpFetch>,
+    pub kv: Arc<kv::KvStore>,
+    pub settings: Arc<PluginSettings>,
+    pub logs: log::PluginLogs,
+    pub crm: Arc<crm::CrmApi>,
+    /// Records `crm_query` returned so far.
+    pub crm_rows_read: usize,
+    /// The execution's scratch directory, removed with the store.
+    pub scratch: Option<Scratch>,
+    /// Samples the plugin's stack, when the execution is profiled.
+    pub profiler: Option<Profiler>,
+    /// Where the plugin's coredump goes if it traps; see `traps`.
+    pub coredump: Coredump,
+    pub plugins: Arc<plugins::PluginCalls>,
+    /// The modules of the executions the call tree has up to this one;
+    /// see `plugins`.
+    pub stack: Vec<String>,
+}
+
+/// The capability a plugin needs to import `function`, if any.
+pub fn capability(function: &str) -> Option<Capability> {
+    match function {
+        "http_fetch" => Some(Capability::Http),
+        "kv_get" | "kv_set" | "kv_delete" => Some(Capability::Kv),
+        "crm_query" => Some(Capability::CrmRead),
+        "call_plugin" => Some(Capability::Plugins),
+        _ => None,
+    }
+}
+
+pub fn add_to_linker(linker: &mut Linker<Host>, profile: Profile, capabilities: Capabilities) -> Result<()> {
+    if profile.network() && capabilities.has(Capability::Http) {
+        linker.func_wrap(HOST_MODULE, "http_fetch", http::http_fetch)?;
+    }
+    if capabilities.has(Capability::Kv) {
+        linker.func_wrap(HOST_MODULE, "kv_get", kv::kv_get)?;
+        linker.func_wrap(HOST_MODULE, "kv_set", kv::kv_set)?;
+        linker.func_wrap(HOST_MODULE, "kv_delete", kv::kv_delete)?;
+    }
+    linker.func_wrap(HOST_MODULE, "config_get", config::config_get)?;
+    linker.func_wrap(HOST_MODULE, "secret_get", config::secret_get)?;
+    linker.func_wrap(HOST_MODULE, "log_debug", log::log_debug)?;
+    linker.func_wrap(HOST_MODULE, "log_info", log::log_info)?;
+    linker.func_wrap(HOST_MODULE, "log_warn", log::log_warn)?;
+    linker.func_wrap(HOST_MODULE, "log_error", log::log_error)?;
+    if capabilities.has(Capability::CrmRead) {
+        linker.func_wrap(HOST_MODULE, "crm_query", crm::crm_query)?;
+    }
+    if capabilities.has(Capability::Plugins) {
+        linker.func_wrap(HOST_MODULE, "call_plugin", plugins::call_plugin)?;
+    }
+    Ok(())
+}
+
+/// The calling execution, for host functions that only serve executions.
+fn call<'a>(caller: &'a Caller<'_, Host>, function: &str) -> Result<&'a Call> {
+    caller
+        .data()
+        .call
+        .as_ref()
+        .with_context(|| format!("{} called outside an execution", function))
+}
+
+/// The plugin's exported memory, which host functions read and write.
+fn memory(caller: &mut Caller<'_, Host>) -> Result<Memory> {
+    caller
+        .get_export("memory")
+        .and_then(|export| export.into_memory())
+        .context("Host functions need the plugin to export its memory")
+}
+
+/// Waits for `future` on the runtime. Host functions are synchronous; the
+/// blocking thread the plugin runs on waits for the host like it waits for
+/// the plugin.
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Handle::current().block_on(future)
+}
@@ -0,0 +1,171 @@
+//! `crm.call_plugin`: runs another plugin, so logic several plugins share
+//! can live in a library plugin they call.
+//!
+//! ```text
+//! call_plugin(name_ptr: i32, name_len: i32, input_ptr: i32, input_len: i32, output_ptr: i32, output_cap: i32) -> i32
+//! ```
+//!
+//! The name is a module reference like an execute request's
+//! `module_path`, version references included (see `versions`), and the
+//! function to call after a `#`, `run` when there is none:
+//! `address-normalize@^1#normalize`. The callee takes the input and
+//! returns its result as JSON documents, like plugins called with the
+//! `json` calling convention. The result is written to `output_ptr` and
+//! its length returned, or one of the negative codes below.
+//!
+//! The callee runs for the caller's tenant, as part of the caller's
+//! execution: it takes no quota or plan pool slot of its own, isn't billed
+//! apart from it, and has until the caller's deadline. The call tree
+//! shares the fuel of the execution at its root: a callee may burn what
+//! its caller has left, and what it burns comes out of its caller's,
+//! everything it had when it ran out. Calls nest at most
+//! `plugin_call_max_depth` deep, and a plugin can't call one of the
+//! modules the call is made from, itself included.
+
+use super::{block_on, call, memory, Host};
+use crate::{execute, resolve_module, ExecuteRequest, ExecuteResponse, RuntimeConfig, ServiceState};
+use anyhow::Result;
+use metrics::counter;
+use serde_json::Value;
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::Instant;
+use tracing::debug;
+use wasmtime::{Caller, Trap};
+
+/// The callee failed; see the runtime's logs for why.
+pub const FAILED: i32 = -1;
+/// The name or input isn't valid.
+pub const INVALID_REQUEST: i32 = -2;
+/// The call would nest deeper than `plugin_call_max_depth`.
+pub const DEPTH_EXCEEDED: i32 = -3;
+/// The callee is one of the modules the call is made from.
+pub const CYCLE: i32 = -4;
+/// The result doesn't fit in `output_cap` bytes.
+pub const BUFFER_TOO_SMALL: i32 = -5;
+
+const MAX_NAME_BYTES: usize = 512;
+const MAX_INPUT_BYTES: usize = 1024 * 1024;
+
+/// Where an execution a plugin asked for sits in its call tree.
+#[derive(Debug, Clone)]
+pub struct Nesting {
+    /// The modules of the executions above it, the root first.
+    pub stack: Vec<String>,
+    /// Its caller's deadline.
+    pub deadline: Instant,
+}
+
+pub struct PluginCalls {
+    /// Set once the service state is, which holds this.
+    state: OnceLock<Weak<ServiceState>>,
+    max_depth: usize,
+}
+
+impl PluginCalls {
+    pub fn new(config: &RuntimeConfig) -> Arc<Self> {
+        Arc::new(PluginCalls { state: OnceLock::new(), max_depth: config.plugin_call_max_depth })
+    }
+
+    /// Lets plugins call others through `state`.
+    pub fn attach(&self, state: &Arc<ServiceState>) {
+        let _ = self.state.set(Arc::downgrade(state));
+    }
+
+    /// Runs `function` of `module_path` with `input`, below `nesting`.
+    async fn call(
+        &self,
+        module_path: &str,
+        function: &str,
+        input: Value,
+        tenant_id: Option<String>,
+        nesting: Nesting,
+        fuel: u64,
+    ) -> Result<ExecuteResponse, i32> {
+        if nesting.stack.len() > self.max_depth {
+            return Err(DEPTH_EXCEEDED);
+        }
+        let Some(state) = self.state.get().and_then(Weak::upgrade) else {
+            return Err(FAILED);
+        };
+        let request = serde_json::json!({
+            "module_path": module_path,
+            "function_name": function,
+            "params": input,
+            "tenant_id": tenant_id,
+            "calling_convention": "json",
+        });
+        let mut req: ExecuteRequest = serde_json::from_value(request).map_err(|_| INVALID_REQUEST)?;
+        // Resolved ahead of the execution, which then runs that module,
+        // for the stack to be checked against
+        if let Err(e) = resolve_module(&state, &mut req).await {
+            debug!("call_plugin of {} not resolved: {:#}", module_path, e);
+            return Err(FAILED);
+        }
+        if nesting.stack.contains(&req.module_path) {
+            debug!("call_plugin of {} from {} refused", req.module_path, nesting.stack.join(" -> "));
+            return Err(CYCLE);
+        }
+        req.fuel_limit = Some(fuel);
+        req.nesting = Some(nesting);
+        Ok(execute(&state, req).await)
+    }
+}
+
+pub fn call_plugin(
+    mut caller: Caller<'_, Host>,
+    name_ptr: i32,
+    name_len: i32,
+    input_ptr: i32,
+    input_len: i32,
+    output_ptr: i32,
+    output_cap: i32,
+) -> Result<i32> {
+    let call = call(&caller, "call_plugin")?;
+    let (plugins, tenant_id) = (call.plugins.clone(), call.tenant_id.clone());
+    let nesting = Nesting { stack: call.stack.clone(), deadline: call.deadline };
+    let memory = memory(&mut caller)?;
+
+    let code = |result: &'static str, code: i32| {
+        counter!("plugin_calls_total", "result" => result).increment(1);
+        Ok(code)
+    };
+    if name_len < 0 || name_len as usize > MAX_NAME_BYTES || input_len < 0 || input_len as usize > MAX_INPUT_BYTES {
+        return code("invalid", INVALID_REQUEST);
+    }
+    let mut name = vec![0; name_len as usize];
+    memory.read(&caller, name_ptr as u32 as usize, &mut name)?;
+    let mut input = vec![0; input_len as usize];
+    memory.read(&caller, input_ptr as u32 as usize, &mut input)?;
+    let (Ok(name), Ok(input)) = (String::from_utf8(name), serde_json::from_slice::<Value>(&input)) else {
+        return code("invalid", INVALID_REQUEST);
+    };
+    let (module_path, function) = name.split_once('#').unwrap_or((name.as_str(), "run"));
+
+    let fuel = caller.get_fuel()?;
+    let response = match block_on(plugins.call(module_path, function, input, tenant_id, nesting, fuel)) {
+        Ok(response) => response,
+        Err(DEPTH_EXCEEDED) => return code("depth_exceeded", DEPTH_EXCEEDED),
+        Err(CYCLE) => return code("cycle", CYCLE),
+        Err(INVALID_REQUEST) => return code("invalid", INVALID_REQUEST),
+        Err(other) => return code("failed", other),
+    };
+
+    // The callee's fuel is the caller's
+    let out_of_fuel = response.trap.as_ref().is_some_and(|trap| trap.reason == "out_of_fuel");
+    let burned = if out_of_fuel { fuel } else { response.fuel_consumed.min(fuel) };
+    caller.set_fuel(fuel - burned)?;
+    if burned == fuel {
+        return Err(Trap::OutOfFuel.into());
+    }
+    if !response.success {
+        debug!("call_plugin of {} failed: {}", name, response.error.map(|error| error.message).unwrap_or_default());
+        return code("failed", FAILED);
+    }
+
+    let output = serde_json::to_vec(&response.result.unwrap_or(Value::Null))?;
+    if output.len() > output_cap.max(0) as usize {
+        return code("buffer_too_small", BUFFER_TOO_SMALL);
+    }
+    memory.write(&mut caller, output_ptr as u32 as usize, &output)?;
+    code("ok", output.len() as i32)
+}
@@ -0,0 +1,161 @@
+//! Idempotent execute requests, so a gateway retrying a call it didn't get
+//! the answer to doesn't run the plugin's side effects twice.
+//!
+//! A `POST /execute` with an `Idempotency-Key` header runs once per key and
+//! tenant. Its response is kept for `idempotency_window_secs` and replayed,
+//! with `Idempotent-Replayed: true`, for retries with the same key and the
+//! same request: the same body fields and query. The key is refused with
+//! 422 for a different request, and with 409 while the first one still
+//! runs. Failures that are `retryable` (see `errors`), such as throttled
+//! requests, aren't kept, so their retries run.
+//!
+//! Keys are kept in Redis, under `runtime_idempotency:{tenant}:{key}`, so a
+//! retry landing on another replica is answered alike. While Redis is
+//! unavailable requests run as if they had no key.
+
+use crate::redis_store::RedisStore;
+use crate::{ExecuteQuery, ExecuteRequest, RuntimeConfig, MAX_EXECUTION_TIMEOUT};
+use anyhow::{Context, Result};
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+/// How long an idempotency store call may wait on Redis.
+const IDEMPOTENCY_TIMEOUT: Duration = Duration::from_secs(2);
+
+const IDEMPOTENCY_PREFIX: &str = "runtime_idempotency";
+
+/// Longest key accepted.
+pub const MAX_KEY_LEN: usize = 255;
+
+/// Header the key comes in.
+pub const IDEMPOTENCY_HEADER: &str = "idempotency-key";
+
+/// Header marking a replayed response.
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// A key's request and, once it finished, its response.
+#[derive(Debug, Serialize, Deserialize)]
+struct Record {
+    fingerprint: String,
+    #[serde(default)]
+    status: Option<u16>,
+    #[serde(default)]
+    body: Option<Value>,
+}
+
+/// What to do with a request carrying a key.
+#[derive(Debug)]
+pub enum Claim {
+    /// The first with the key: run it, then `complete` or `release` it.
+    Run,
+    /// A retry of one that finished.
+    Replay { status: u16, body: Value },
+    /// A retry of one still running.
+    InProgress,
+    /// The key was used for another request.
+    Mismatch,
+}
+
+pub struct Idempotency {
+    redis: Arc<RedisStore>,
+    window: Duration,
+}
+
+impl Idempotency {
+    pub fn new(redis_url: &str, config: &RuntimeConfig) -> Arc<Self> {
+        Arc::new(Idempotency {
+            redis: RedisStore::new(redis_url),
+            window: Duration::from_secs(config.idempotency_window_secs),
+        })
+    }
+
+    /// Claims `key` of `tenant_id` for the request with `fingerprint`.
+    pub async fn claim(&self, tenant_id: Option<&str>, key: &str, fingerprint: &str) -> Result<Claim> {
+        let stored_key = stored_key(tenant_id, key);
+        let pending = serde_json::to_string(&Record { fingerprint: fingerprint.to_string(), status: None, body: None })?;
+        // Running requests hold the key only for as long as one may run,
+        // so a replica dying mid-request doesn't hold it for the window
+        let hold = (MAX_EXECUTION_TIMEOUT + Duration::from_secs(60)).min(self.window);
+        let claimed: Option<String> =
+            self.run(redis::cmd("SET").arg(&stored_key).arg(pending).arg("NX").arg("EX").arg(hold.as_secs())).await?;
+        let claim = if claimed.is_some() {
+            Claim::Run
+        } else {
+            let stored: Option<String> = self.run(redis::cmd("GET").arg(&stored_key)).await?;
+            match stored.and_then(|stored| serde_json::from_str::<Record>(&stored).ok()) {
+                // Released or expired in between; a retry claims it
+                None => Claim::InProgress,
+                Some(record) if record.fingerprint != fingerprint => Claim::Mismatch,
+                Some(Record { status: Some(status), body: Some(body), .. }) => Claim::Replay { status, body },
+                Some(_) => Claim::InProgress,
+            }
+        };
+        let result = match claim {
+            Claim::Run => "new",
+            Claim::Replay { .. } => "replayed",
+            Claim::InProgress => "in_progress",
+            Claim::Mismatch => "mismatch",
+        };
+        counter!("plugin_idempotent_requests_total", "result" => result).increment(1);
+        Ok(claim)
+    }
+
+    /// Keeps the response to the request that claimed `key`.
+    pub async fn complete(&self, tenant_id: Option<&str>, key: &str, fingerprint: &str, status: u16, body: Value) {
+        let record = Record { fingerprint: fingerprint.to_string(), status: Some(status), body: Some(body) };
+        let stored = match serde_json::to_string(&record) {
+            Ok(stored) => stored,
+            Err(e) => {
+                warn!("Response for idempotency key {} not kept: {}", key, e);
+                return;
+            }
+        };
+        let kept = self.run::<()>(redis::cmd("SET").arg(stored_key(tenant_id, key)).arg(stored).arg("EX").arg(self.window.as_secs())).await;
+        if let Err(e) = kept {
+            warn!("Response for idempotency key {} not kept: {:#}", key, e);
+        }
+    }
+
+    /// Frees `key` for a retry to run.
+    pub async fn release(&self, tenant_id: Option<&str>, key: &str) {
+        if let Err(e) = self.run::<()>(redis::cmd("DEL").arg(stored_key(tenant_id, key))).await {
+            warn!("Idempotency key {} not released: {:#}", key, e);
+        }
+    }
+
+    /// Runs `command` on the store's Redis connection.
+    async fn run<T: redis::FromRedisValue>(&self, command: &redis::Cmd) -> Result<T> {
+        self.redis.run(command, IDEMPOTENCY_TIMEOUT).await.context("Idempotency store unavailable")
+    }
+}
+
+fn stored_key(tenant_id: Option<&str>, key: &str) -> String {
+    format!("{}:{}:{}", IDEMPOTENCY_PREFIX, tenant_id.unwrap_or("-"), key)
+}
+
+/// What makes two requests the same: every field a caller sends.
+pub fn fingerprint(req: &ExecuteRequest, query: &ExecuteQuery) -> String {
+    let env: BTreeMap<_, _> = req.env.iter().collect();
+    let request = json!([
+        req.module_path,
+        req.function_name,
+        req.params,
+        req.timeout_seconds,
+        req.tenant_id,
+        req.secrets,
+        env,
+        req.include_logs,
+        format!("{:?}", req.result_encoding),
+        format!("{:?}", req.calling_convention),
+        req.seed,
+        query.run_async,
+        query.profile,
+    ]);
+    hex::encode(Sha256::digest(request.to_string()))
+}
@@ -0,0 +1,101 @@
+//! Inline modules: one-off plugins sent with the call instead of stored,
+//! for the developer sandbox in the CRM UI to run what a user just wrote.
+//!
+//! `POST /execute/inline` takes the fields of an execute request but
+//! `module_path`, plus the module, base64-encoded:
+//!
+//! ```json
+//! {"module": "AGFzbQEAAAA…", "function_name": "run", "params": [2, 3]}
+//! ```
+//!
+//! Inline modules are off unless `inline_modules` is set, and refused
+//! while module signatures are required, since they can't be signed. They
+//! are at most `inline_max_module_bytes` long, burn at most
+//! `inline_fuel_limit` fuel, run for at most `inline_max_timeout_secs`,
+//! and get no secrets. They are compiled for the call and kept nowhere,
+//! their results included, and run as `inline:{sha256}`, which is what
+//! logs, metrics and billing know them by.
+
+use crate::errors::{ErrorCode, ExecuteError};
+use crate::{authorize, execute, ExecuteRequest, ServiceState, TENANT_HEADER};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use metrics::counter;
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::Filter;
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+fn reply(status: StatusCode, body: Value) -> Reply {
+    warp::reply::with_status(warp::reply::json(&body), status)
+}
+
+pub fn routes(
+    state: Arc<ServiceState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("execute" / "inline")
+        .and(warp::post())
+        // Base64 takes 4 bytes for every 3
+        .and(warp::body::content_length_limit(state.config().inline_max_module_bytes as u64 * 4 / 3 + 1024 * 1024))
+        .and(warp::body::json())
+        .and(warp::header::optional::<String>(TENANT_HEADER))
+        .and(warp::header::optional::<String>("authorization"))
+        .then(move |body: Map<String, Value>, tenant_id: Option<String>, authorization: Option<String>| {
+            let state = state.clone();
+            async move { run(&state, body, tenant_id, authorization.as_deref()).await }
+        })
+}
+
+async fn run(state: &ServiceState, mut body: Map<String, Value>, tenant_header: Option<String>, authorization: Option<&str>) -> Reply {
+    let config = state.config();
+    if !config.inline_modules {
+        return reply(StatusCode::NOT_FOUND, json!({ "error": "Inline modules are not enabled" }));
+    }
+    if state.signatures.is_some() {
+        return reply(StatusCode::FORBIDDEN, json!({ "error": "Inline modules can't run while module signatures are required" }));
+    }
+    if body.contains_key("module_path") {
+        return reply(StatusCode::BAD_REQUEST, json!({ "error": "Inline executions run their module, not a module_path" }));
+    }
+    let bytes = match body.remove("module") {
+        Some(Value::String(module)) => match BASE64.decode(module) {
+            Ok(bytes) => bytes,
+            Err(e) => return reply(StatusCode::BAD_REQUEST, json!({ "error": format!("Module is not valid base64: {}", e) })),
+        },
+        _ => return reply(StatusCode::BAD_REQUEST, json!({ "error": "An inline execution needs its module, base64-encoded" })),
+    };
+    if bytes.len() > config.inline_max_module_bytes {
+        let error = format!("Module is {} bytes; inline modules are at most {}", bytes.len(), config.inline_max_module_bytes);
+        return reply(StatusCode::PAYLOAD_TOO_LARGE, json!({ "error": error }));
+    }
+
+    body.insert("module_path".to_string(), json!(""));
+    let mut req: ExecuteRequest = match serde_json::from_value(Value::Object(body)) {
+        Ok(req) => req,
+        Err(e) => return reply(StatusCode::BAD_REQUEST, json!({ "error": format!("Invalid request: {}", e) })),
+    };
+    if !req.secrets.is_empty() {
+        return reply(StatusCode::BAD_REQUEST, json!({ "error": "Inline modules get no secrets" }));
+    }
+    if let Err(error) = authorize(state, &mut req, tenant_header, authorization).await {
+        return reply(error.status(), json!({ "success": false, "error": error }));
+    }
+    let compiled = match state.compiled.compile(&bytes) {
+        Ok(compiled) => compiled,
+        Err(e) => {
+            counter!("plugin_inline_executions_total", "status" => "invalid").increment(1);
+            let error = ExecuteError::new(ErrorCode::InvalidModule, format!("{:#}", e));
+            return reply(error.status(), json!({ "success": false, "error": error }));
+        }
+    };
+    req.module_path = format!("inline:{}", compiled.sha256);
+    req.compiled = Some(compiled);
+    req.fuel_limit = Some(config.inline_fuel_limit);
+    req.timeout_seconds = Some(req.timeout_seconds.unwrap_or(config.inline_max_timeout_secs).min(config.inline_max_timeout_secs));
+
+    let response = execute(state, req).await;
+    counter!("plugin_inline_executions_total", "status" => if response.success { "success" } else { "failure" }).increment(1);
+    reply(response.status(), json!(response))
+}
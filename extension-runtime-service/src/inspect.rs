@@ -0,0 +1,98 @@
+//! What a module imports and exports, read from its compiled form without
+//! instantiating it. Components can't be inspected: wasmtime doesn't expose
+//! their types yet.
+
+use crate::compiled::{Code, Compiled};
+use serde::Serialize;
+use wasmtime::{ExternType, Mutability};
+
+#[derive(Debug, Serialize)]
+pub struct ModuleInfo {
+    pub sha256: String,
+    pub exports: Vec<Export>,
+    pub imports: Vec<Import>,
+}
+
+impl ModuleInfo {
+    /// What the module's memories take when it is instantiated, going by
+    /// the minimum sizes it declares.
+    pub fn initial_memory_bytes(&self) -> u64 {
+        let kinds = self.exports.iter().map(|export| &export.kind).chain(self.imports.iter().map(|import| &import.kind));
+        kinds
+            .map(|kind| match kind {
+                Extern::Memory { minimum_pages, .. } => minimum_pages * 65536,
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Export {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: Extern,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: Extern,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Extern {
+    Function { params: Vec<String>, results: Vec<String> },
+    /// Sizes in 64 KiB pages.
+    Memory { minimum_pages: u64, maximum_pages: Option<u64> },
+    Table { element: String, minimum: u32, maximum: Option<u32> },
+    Global { content: String, mutable: bool },
+}
+
+impl From<ExternType> for Extern {
+    fn from(ty: ExternType) -> Self {
+        match ty {
+            ExternType::Func(func) => Extern::Function {
+                params: func.params().map(|ty| ty.to_string()).collect(),
+                results: func.results().map(|ty| ty.to_string()).collect(),
+            },
+            ExternType::Memory(memory) => Extern::Memory {
+                minimum_pages: memory.minimum(),
+                maximum_pages: memory.maximum(),
+            },
+            ExternType::Table(table) => Extern::Table {
+                element: table.element().to_string(),
+                minimum: table.minimum(),
+                maximum: table.maximum(),
+            },
+            ExternType::Global(global) => Extern::Global {
+                content: global.content().to_string(),
+                mutable: global.mutability() == Mutability::Var,
+            },
+        }
+    }
+}
+
+pub fn inspect(compiled: &Compiled) -> anyhow::Result<ModuleInfo> {
+    let Code::Module(module) = &compiled.code else {
+        anyhow::bail!("Components can't be inspected");
+    };
+    Ok(ModuleInfo {
+        sha256: compiled.sha256.clone(),
+        exports: module
+            .exports()
+            .map(|export| Export { name: export.name().to_string(), kind: export.ty().into() })
+            .collect(),
+        imports: module
+            .imports()
+            .map(|import| Import {
+                module: import.module().to_string(),
+                name: import.name().to_string(),
+                kind: import.ty().into(),
+            })
+            .collect(),
+    })
+}
@@ -0,0 +1,362 @@
+//! Asynchronous executions, so plugins running for minutes don't hold a
+//! connection open:
+//!
+//! - `POST /execute?async=true`: takes the usual execute request and
+//!   answers 202 with the job, `{"job_id": "…", "status": "queued", …}`.
+//! - `GET /jobs/{id}`: the job; once `succeeded` or `failed` it carries the
+//!   execute response under `response`.
+//! - `DELETE /jobs/{id}`: cancels a `queued` or `running` job; 409 once it
+//!   finished.
+//! - `GET /jobs/{id}/coredump`: the coredump of a job whose plugin trapped,
+//!   as `application/wasm`, when the job has `coredump`; see `traps`.
+//!
+//! Jobs are kept in Redis under `runtime_job:{id}`, and their coredumps
+//! under `runtime_job_coredump:{id}`, for `job_ttl_secs`, so
+//! any replica answers for them. A job is `running` once it has an
+//! execution slot. Cancelling stops the job, interrupting its plugin, when
+//! this replica runs it; a job running on another replica finishes there,
+//! but stays `cancelled` and its result is dropped. While authentication
+//! is on, callers only see their own tenant's jobs.
+
+use crate::redis_store::RedisStore;
+use crate::{execute, ExecuteRequest, ServiceState, TENANT_HEADER};
+use anyhow::{Context, Result};
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::task::AbortHandle;
+use tracing::{info, warn, Instrument};
+use warp::http::StatusCode;
+use warp::{Filter, Reply as _};
+
+/// How long a job store call may wait on Redis.
+const JOBS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Sets `KEYS[1]` to `ARGV[2]` only while it still holds `ARGV[1]`, so a
+/// job's runner and a cancellation can't overwrite each other.
+const COMPARE_AND_SET: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    redis.call('SET', KEYS[1], ARGV[2], 'EX', ARGV[3])
+    return 1
+end
+return 0
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    fn name(self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    fn is_finished(self) -> bool {
+        matches!(self, JobStatus::Succeeded | JobStatus::Failed | JobStatus::Cancelled)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub job_id: String,
+    pub status: JobStatus,
+    pub module_path: String,
+    pub function_name: String,
+    pub tenant_id: Option<String>,
+    /// Unix seconds.
+    pub created_at: i64,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    /// The execute response, once finished.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<Value>,
+    /// Whether the plugin's coredump was kept.
+    #[serde(default)]
+    pub coredump: bool,
+}
+
+pub struct Jobs {
+    redis: Arc<RedisStore>,
+    ttl_secs: u64,
+    /// Jobs this replica runs, to stop when cancelled.
+    running: std::sync::Mutex<HashMap<String, AbortHandle>>,
+}
+
+impl Jobs {
+    pub fn new(redis_url: &str, ttl_secs: u64) -> Arc<Self> {
+        Arc::new(Jobs {
+            redis: RedisStore::new(redis_url),
+            ttl_secs,
+            running: std::sync::Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Stores a job for `req` and starts running it.
+    pub async fn submit(self: &Arc<Self>, state: Arc<ServiceState>, mut req: ExecuteRequest) -> Result<Job> {
+        let job = Job {
+            job_id: uuid::Uuid::new_v4().to_string(),
+            status: JobStatus::Queued,
+            module_path: req.module_path.clone(),
+            function_name: req.function_name.clone(),
+            tenant_id: req.tenant_id.clone(),
+            created_at: now(),
+            started_at: None,
+            finished_at: None,
+            response: None,
+            coredump: false,
+        };
+        let stored = serde_json::to_string(&job)?;
+        self.run::<()>(redis::cmd("SET").arg(key(&job.job_id)).arg(&stored).arg("EX").arg(self.ttl_secs)).await?;
+
+        let (started, on_start) = oneshot::channel();
+        req.started = Some(started);
+        let jobs = self.clone();
+        {
+            // Registered before the runner can finish and deregister it
+            let mut running = self.running.lock().unwrap();
+            // In the submitting request's trace, which it outlives
+            let runner = tokio::spawn(jobs.execute(state, req, job.clone(), stored, on_start).in_current_span());
+            running.insert(job.job_id.clone(), runner.abort_handle());
+        }
+        counter!("plugin_jobs_total", "status" => "queued").increment(1);
+        info!("Queued job {} for {}", job.job_id, job.module_path);
+        Ok(job)
+    }
+
+    async fn execute(
+        self: Arc<Self>,
+        state: Arc<ServiceState>,
+        req: ExecuteRequest,
+        mut job: Job,
+        mut stored: String,
+        on_start: oneshot::Receiver<()>,
+    ) {
+        let mut execution = std::pin::pin!(execute(&state, req));
+        let mut response = tokio::select! {
+            response = &mut execution => response,
+            Ok(()) = on_start => {
+                job.status = JobStatus::Running;
+                job.started_at = Some(now());
+                if !self.update(&mut stored, &job).await {
+                    self.running.lock().unwrap().remove(&job.job_id);
+                    return;
+                }
+                execution.await
+            }
+        };
+
+        if let Some(coredump) = response.coredump.take() {
+            let mut command = redis::cmd("SET");
+            command.arg(coredump_key(&job.job_id)).arg(coredump).arg("EX").arg(self.ttl_secs);
+            match self.run::<()>(&command).await {
+                Ok(()) => job.coredump = true,
+                Err(e) => warn!("Failed to store the coredump of job {}: {:#}", job.job_id, e),
+            }
+        }
+        job.status = if response.success { JobStatus::Succeeded } else { JobStatus::Failed };
+        job.started_at = job.started_at.or(Some(now()));
+        job.finished_at = Some(now());
+        job.response = serde_json::to_value(&response).ok();
+        self.running.lock().unwrap().remove(&job.job_id);
+        if self.update(&mut stored, &job).await {
+            counter!("plugin_jobs_total", "status" => job.status.name()).increment(1);
+        }
+    }
+
+    /// Replaces the job last stored as `stored`; false when it was
+    /// cancelled meanwhile or Redis failed.
+    async fn update(&self, stored: &mut String, job: &Job) -> bool {
+        let updated = match serde_json::to_string(job) {
+            Ok(updated) => updated,
+            Err(e) => {
+                warn!("Failed to encode job {}: {}", job.job_id, e);
+                return false;
+            }
+        };
+        match self.compare_and_set(&job.job_id, stored, &updated).await {
+            Ok(true) => {
+                *stored = updated;
+                true
+            }
+            Ok(false) => {
+                info!("Job {} was cancelled; dropping its result", job.job_id);
+                false
+            }
+            Err(e) => {
+                warn!("Failed to update job {}: {:#}", job.job_id, e);
+                false
+            }
+        }
+    }
+
+    pub async fn coredump(&self, job_id: &str) -> Result<Option<Vec<u8>>> {
+        self.run(redis::cmd("GET").arg(coredump_key(job_id))).await
+    }
+
+    pub async fn get(&self, job_id: &str) -> Result<Option<Job>> {
+        let stored: Option<String> = self.run(redis::cmd("GET").arg(key(job_id))).await?;
+        stored.map(|stored| serde_json::from_str(&stored).context("Invalid stored job")).transpose()
+    }
+
+    /// Cancels the job unless it already finished, and returns it.
+    pub async fn cancel(&self, job_id: &str) -> Result<Option<Job>> {
+        // Retried when the runner updates the job in between
+        for _ in 0..3 {
+            let stored: Option<String> = self.run(redis::cmd("GET").arg(key(job_id))).await?;
+            let Some(stored) = stored else {
+                return Ok(None);
+            };
+            let mut job: Job = serde_json::from_str(&stored).context("Invalid stored job")?;
+            if job.status.is_finished() {
+                return Ok(Some(job));
+            }
+            job.status = JobStatus::Cancelled;
+            job.finished_at = Some(now());
+            if self.compare_and_set(job_id, &stored, &serde_json::to_string(&job)?).await? {
+                if let Some(runner) = self.running.lock().unwrap().remove(job_id) {
+                    runner.abort();
+                }
+                counter!("plugin_jobs_total", "status" => "cancelled").increment(1);
+                info!("Cancelled job {}", job_id);
+                return Ok(Some(job));
+            }
+        }
+        anyhow::bail!("Job {} kept changing while being cancelled", job_id)
+    }
+
+    async fn compare_and_set(&self, job_id: &str, current: &str, new: &str) -> Result<bool> {
+        let mut command = redis::cmd("EVAL");
+        command.arg(COMPARE_AND_SET).arg(1).arg(key(job_id)).arg(current).arg(new).arg(self.ttl_secs);
+        let set: i64 = self.run(&command).await?;
+        Ok(set == 1)
+    }
+
+    /// Runs `command` on the store's Redis connection.
+    async fn run<T: redis::FromRedisValue>(&self, command: &redis::Cmd) -> Result<T> {
+        self.redis.run(command, JOBS_TIMEOUT).await.context("Job store unavailable")
+    }
+}
+
+fn key(job_id: &str) -> String {
+    format!("runtime_job:{}", job_id)
+}
+
+fn coredump_key(job_id: &str) -> String {
+    format!("runtime_job_coredump:{}", job_id)
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+fn reply(status: StatusCode, body: Value) -> Reply {
+    warp::reply::with_status(warp::reply::json(&body), status)
+}
+
+pub fn routes(
+    state: Arc<ServiceState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let caller = warp::header::optional::<String>(TENANT_HEADER)
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::any().map(move || state.clone()));
+    let base = warp::path("jobs").and(warp::path::param::<String>()).and(warp::path::end()).and(caller.clone());
+
+    let get = base.clone().and(warp::get()).then(
+        |job_id: String, tenant_id: Option<String>, authorization: Option<String>, state: Arc<ServiceState>| async move {
+            if let Err(refused) = authorize(&state, tenant_id.as_deref(), authorization.as_deref(), &job_id).await {
+                return refused;
+            }
+            match state.jobs.get(&job_id).await {
+                Ok(Some(job)) => reply(StatusCode::OK, json!(job)),
+                Ok(None) => not_found(&job_id),
+                Err(e) => unavailable(e),
+            }
+        },
+    );
+    let coredump = warp::path!("jobs" / String / "coredump").and(warp::get()).and(caller).then(
+        |job_id: String, tenant_id: Option<String>, authorization: Option<String>, state: Arc<ServiceState>| async move {
+            if let Err(refused) = authorize(&state, tenant_id.as_deref(), authorization.as_deref(), &job_id).await {
+                return refused.into_response();
+            }
+            match state.jobs.coredump(&job_id).await {
+                Ok(Some(coredump)) => warp::reply::with_header(coredump, "content-type", "application/wasm").into_response(),
+                Ok(None) => {
+                    reply(StatusCode::NOT_FOUND, json!({ "error": format!("No coredump for job {}", job_id) })).into_response()
+                }
+                Err(e) => unavailable(e).into_response(),
+            }
+        },
+    );
+    let cancel = base.and(warp::delete()).then(
+        |job_id: String, tenant_id: Option<String>, authorization: Option<String>, state: Arc<ServiceState>| async move {
+            if let Err(refused) = authorize(&state, tenant_id.as_deref(), authorization.as_deref(), &job_id).await {
+                return refused;
+            }
+            match state.jobs.cancel(&job_id).await {
+                Ok(Some(job)) if job.status == JobStatus::Cancelled => reply(StatusCode::OK, json!(job)),
+                Ok(Some(job)) => {
+                    reply(StatusCode::CONFLICT, json!({ "error": format!("Job {} already {}", job_id, job.status.name()) }))
+                }
+                Ok(None) => not_found(&job_id),
+                Err(e) => unavailable(e),
+            }
+        },
+    );
+    get.or(cancel).unify().or(coredump)
+}
+
+/// Refuses callers who may not see the job: with authentication on, those
+/// without a token for the job's tenant, otherwise those naming another
+/// tenant in `X-Tenant-ID`. Other tenants' jobs are reported missing.
+async fn authorize(
+    state: &ServiceState,
+    tenant_id: Option<&str>,
+    authorization: Option<&str>,
+    job_id: &str,
+) -> Result<(), Reply> {
+    let tenant_id = match &state.auth {
+        Some(auth) => match auth.authenticate(authorization, tenant_id).await {
+            Ok(caller) => Some(caller.tenant_id),
+            Err(e) => {
+                let status = if e.is_forbidden() { StatusCode::FORBIDDEN } else { StatusCode::UNAUTHORIZED };
+                return Err(reply(status, json!({ "error": e.to_string() })));
+            }
+        },
+        None => tenant_id.map(str::to_string),
+    };
+    let Some(tenant_id) = tenant_id else {
+        return Ok(());
+    };
+    match state.jobs.get(job_id).await {
+        Ok(Some(job)) if job.tenant_id.as_deref() != Some(tenant_id.as_str()) => Err(not_found(job_id)),
+        Ok(_) => Ok(()),
+        Err(e) => Err(unavailable(e)),
+    }
+}
+
+fn not_found(job_id: &str) -> Reply {
+    reply(StatusCode::NOT_FOUND, json!({ "error": format!("Job not found: {}", job_id) }))
+}
+
+fn unavailable(e: anyhow::Error) -> Reply {
+    warn!("Job store call failed: {:#}", e);
+    reply(StatusCode::SERVICE_UNAVAILABLE, json!({ "error": format!("{:#}", e) }))
+}
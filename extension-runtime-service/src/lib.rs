@@ -0,0 +1,13 @@
+//! Pieces of the runtime shared with the benches under `benches/` and the
+//! tests under `tests/`; the service itself is in `main.rs`.
+
+pub mod errors;
+pub mod params;
+pub mod pools;
+pub mod registry;
+
+/// Whether `segment`, from a caller, may be part of a secret's or
+/// directory's path, which keeps it from escaping its prefix.
+pub fn valid_path_segment(segment: &str) -> bool {
+    !segment.is_empty() && segment.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
@@ -0,0 +1,108 @@
+//! Plugin lifecycle hooks, so plugins can set themselves up as they're
+//! registered and clean up as they're removed.
+//!
+//! A core module may export any of these functions, taking no parameters:
+//!
+//! - `on_install`: when uploaded through `POST /modules`, before it's
+//!   stored; if it fails the upload is refused.
+//! - `on_activate`: once it's stored and can be executed.
+//! - `on_deactivate`, then `on_uninstall`: when deleted through
+//!   `DELETE /modules/{id}`, before it's removed. The module is removed
+//!   even when they fail.
+//!
+//! Hooks run sandboxed like any execution of the module, for the tenant
+//! the module belongs to or, for system modules, without a tenant, for up
+//! to `HOOK_TIMEOUT_SECS`. Their outcomes come back with the upload
+//! or deletion and are recorded on the admin audit trail. Components have
+//! no hooks.
+
+use crate::compiled::{Code, Compiled};
+use crate::errors::ExecuteError;
+use crate::{execute, ExecuteRequest, ServiceState};
+use extension_runtime_service::params::{CallingConvention, ResultEncoding};
+use metrics::counter;
+use serde::Serialize;
+use serde_json::json;
+use tracing::{info, warn};
+
+/// Longest a hook may run.
+const HOOK_TIMEOUT_SECS: u64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hook {
+    Install,
+    Activate,
+    Deactivate,
+    Uninstall,
+}
+
+impl Hook {
+    /// The export that implements the hook.
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::Install => "on_install",
+            Hook::Activate => "on_activate",
+            Hook::Deactivate => "on_deactivate",
+            Hook::Uninstall => "on_uninstall",
+        }
+    }
+}
+
+/// How a hook went.
+#[derive(Debug, Clone, Serialize)]
+pub struct Outcome {
+    pub hook: &'static str,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ExecuteError>,
+    pub execution_id: String,
+    pub execution_time_ms: u64,
+}
+
+/// Runs `hook` when the module exports it, as module `id` of `tenant_id`;
+/// `None` when it doesn't.
+pub async fn run(state: &ServiceState, tenant_id: Option<&str>, id: &str, compiled: &Compiled, hook: Hook) -> Option<Outcome> {
+    let Code::Module(module) = &compiled.code else {
+        return None;
+    };
+    module.get_export(hook.name())?.func()?;
+
+    let req = ExecuteRequest {
+        module_path: id.to_string(),
+        function_name: hook.name().to_string(),
+        params: json!([]),
+        timeout_seconds: Some(HOOK_TIMEOUT_SECS),
+        tenant_id: tenant_id.map(str::to_string),
+        secrets: Vec::new(),
+        env: Default::default(),
+        include_logs: false,
+        result_encoding: ResultEncoding::default(),
+        calling_convention: CallingConvention::default(),
+        caller: None,
+        logs: None,
+        started: None,
+        compiled: Some(compiled.clone()),
+        profile: false,
+        seed: None,
+        fuel_limit: None,
+        nesting: None,
+    };
+    let response = execute(state, req).await;
+    counter!(
+        "module_lifecycle_hooks_total",
+        "hook" => hook.name(),
+        "result" => if response.success { "success" } else { "failure" }
+    )
+    .increment(1);
+    match &response.error {
+        None => info!("Module {} ran {}", id, hook.name()),
+        Some(error) => warn!("Module {} failed {}: {}", id, hook.name(), error),
+    }
+    Some(Outcome {
+        hook: hook.name(),
+        success: response.success,
+        error: response.error,
+        execution_id: response.execution_id,
+        execution_time_ms: response.execution_time_ms,
+    })
+}
@@ -0,0 +1,189 @@
+//! Plugin output. What a plugin writes to stdout and stderr during an
+//! execution is kept, up to `max_output_bytes` per stream, and returned in
+//! its response so plugin authors can debug without access to the
+//! runtime's console. Callers that follow an execution as it runs, such as
+//! the gRPC `ExecuteStream` call, also get the output line by line, in full.
+
+use bytes::Bytes;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::UnboundedSender;
+use wasmtime_wasi::preview2::{HostOutputStream, StdoutStream, StreamResult, Subscribe};
+
+/// Longer lines are split, so a plugin that never writes a newline can't
+/// buffer without bound.
+const MAX_LINE_BYTES: usize = 8 * 1024;
+
+/// Bytes a plugin may write per call; output past the limit is dropped
+/// anyway, so this only sizes the plugin's chunks.
+const WRITE_PERMIT: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug)]
+pub struct LogLine {
+    pub stream: Stream,
+    pub line: String,
+}
+
+pub type LogSender = UnboundedSender<LogLine>;
+
+/// What one execution's plugin wrote so far.
+#[derive(Debug, Default)]
+pub struct Captured {
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether either stream went over the limit.
+    pub truncated: bool,
+}
+
+#[derive(Default)]
+struct Buffers {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    truncated: bool,
+}
+
+/// One execution's stdout and stderr.
+#[derive(Clone)]
+pub struct Output {
+    limit: usize,
+    buffers: Arc<Mutex<Buffers>>,
+}
+
+impl Output {
+    pub fn new(limit: usize) -> Self {
+        Output { limit, buffers: Arc::default() }
+    }
+
+    /// Points a store's stdout and stderr here, and at `logs` when set.
+    pub fn attach(&self, stdio: &Stdio, logs: Option<&LogSender>) {
+        stdio.stdout.set(OutputWriter::new(Stream::Stdout, self.clone(), logs.cloned()));
+        stdio.stderr.set(OutputWriter::new(Stream::Stderr, self.clone(), logs.cloned()));
+    }
+
+    pub fn captured(&self) -> Captured {
+        let buffers = self.buffers.lock().unwrap_or_else(|e| e.into_inner());
+        Captured {
+            stdout: String::from_utf8_lossy(&buffers.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&buffers.stderr).into_owned(),
+            truncated: buffers.truncated,
+        }
+    }
+
+    fn append(&self, stream: Stream, bytes: &[u8]) {
+        let mut buffers = self.buffers.lock().unwrap_or_else(|e| e.into_inner());
+        let buffer = match stream {
+            Stream::Stdout => &mut buffers.stdout,
+            Stream::Stderr => &mut buffers.stderr,
+        };
+        let room = self.limit.saturating_sub(buffer.len());
+        buffer.extend_from_slice(&bytes[..bytes.len().min(room)]);
+        if bytes.len() > room {
+            buffers.truncated = true;
+        }
+    }
+}
+
+struct OutputWriter {
+    stream: Stream,
+    output: Output,
+    logs: Option<LogSender>,
+    partial: Vec<u8>,
+}
+
+impl OutputWriter {
+    fn new(stream: Stream, output: Output, logs: Option<LogSender>) -> Self {
+        OutputWriter { stream, output, logs, partial: Vec::new() }
+    }
+
+    fn send(&mut self) {
+        let line = std::mem::take(&mut self.partial);
+        let line = String::from_utf8_lossy(&line).trim_end_matches('\r').to_string();
+        if let Some(logs) = &self.logs {
+            // The caller may have gone away; the plugin keeps running regardless
+            let _ = logs.send(LogLine { stream: self.stream, line });
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) {
+        self.output.append(self.stream, buf);
+        if self.logs.is_none() {
+            return;
+        }
+        for &byte in buf {
+            if byte == b'\n' {
+                self.send();
+                continue;
+            }
+            self.partial.push(byte);
+            if self.partial.len() >= MAX_LINE_BYTES {
+                self.send();
+            }
+        }
+    }
+}
+
+impl Drop for OutputWriter {
+    /// Sends an unterminated last line once the plugin's store goes away.
+    fn drop(&mut self) {
+        if !self.partial.is_empty() {
+            self.send();
+        }
+    }
+}
+
+/// A store's stdout and stderr. Stores are created before the execution
+/// that uses them, so their WASI context writes to these, which go nowhere
+/// until an execution attaches its [`Output`].
+#[derive(Clone, Default)]
+pub struct Stdio {
+    pub stdout: Pipe,
+    pub stderr: Pipe,
+}
+
+#[derive(Clone, Default)]
+pub struct Pipe {
+    writer: Arc<Mutex<Option<OutputWriter>>>,
+}
+
+impl Pipe {
+    fn set(&self, writer: OutputWriter) {
+        *self.writer.lock().unwrap_or_else(|e| e.into_inner()) = Some(writer);
+    }
+}
+
+impl StdoutStream for Pipe {
+    fn stream(&self) -> Box<dyn HostOutputStream> {
+        Box::new(self.clone())
+    }
+
+    fn isatty(&self) -> bool {
+        false
+    }
+}
+
+impl HostOutputStream for Pipe {
+    fn write(&mut self, bytes: Bytes) -> StreamResult<()> {
+        if let Some(writer) = self.writer.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            writer.write(&bytes);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> StreamResult<()> {
+        Ok(())
+    }
+
+    fn check_write(&mut self) -> StreamResult<usize> {
+        Ok(WRITE_PERMIT)
+    }
+}
+
+#[async_trait::async_trait]
+impl Subscribe for Pipe {
+    async fn ready(&mut self) {}
+}
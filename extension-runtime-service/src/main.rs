@@ -1,22 +1,342 @@
 use anyhow::{Context, Result};
-use metrics::{counter, gauge, histogram};
-use prometheus::{Encoder, TextEncoder};
-use std::sync::Arc;
+use crm_audit::{AuditConfig, AuditTrail};
+use crm_config::Configurable;
+use crm_flags::FlagClient;
+use crm_secrets::SecretStore;
+use crm_startup::{retry, Dependency, Health, StartupConfig};
+use extension_runtime_service::errors;
+use extension_runtime_service::params::{
+    call_json, component_results_to_json, json_to_component_params, json_to_wasm_args, lift_results, lower_args,
+    CallingConvention, ResultEncoding,
+};
+use extension_runtime_service::pools::{self, ExecutionPools, PlanResolver, PoolConfig};
+use extension_runtime_service::registry::{self, ModuleRegistry, RegistryConfig};
+use extension_runtime_service::valid_path_segment;
+use metrics::counter;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 use tokio::time::timeout;
 use tracing::{error, info, instrument, warn};
-use warp::Filter;
+use warp::{Filter, Reply};
 use wasmtime::*;
-use wasmtime_wasi::{WasiCtx, WasiCtxBuilder};
-use std::path::Path;
+use wasmtime_wasi::preview2::WasiCtxBuilder;
+
+mod audit;
+mod auth;
+mod batch;
+mod breakers;
+mod capabilities;
+mod compiled;
+mod determinism;
+mod environment;
+mod epoch;
+mod executions;
+mod grants;
+mod grpc;
+mod host;
+mod idempotency;
+mod inline;
+mod inspect;
+mod jobs;
+mod lifecycle;
+mod logs;
+mod modules;
+mod manifest;
+mod manifests;
+mod oci;
+mod openapi;
+mod pipelines;
+mod profiles;
+mod profiling;
+mod quotas;
+mod recorder;
+mod redis_store;
+mod reload;
+mod results;
+mod rollouts;
+mod schedules;
+mod scratch;
+mod settings;
+mod signing;
+mod storage;
+mod traps;
+mod validate;
+mod versions;
+mod warm;
+
+use audit::{AuditLog, AuditRecord};
+use auth::{AuthContext, Authenticator};
+use breakers::Breakers;
+use capabilities::{Capabilities, Capability};
+use compiled::{Compiled, CompiledCache};
+use environment::EnvSchema;
+use errors::{ErrorCode, ExecuteError};
+use grants::WasiGrant;
+use host::crm::CrmApi;
+use host::http::HttpFetch;
+use host::kv::KvStore;
+use host::log::{PluginLog, PluginLogs};
+use host::plugins::{Nesting, PluginCalls};
+use host::{Call, Host, HOST_MODULE};
+use idempotency::{Claim, Idempotency, IDEMPOTENCY_HEADER, REPLAYED_HEADER};
+use executions::Executions;
+use jobs::Jobs;
+use schedules::Schedules;
+use settings::PluginSettings;
+use logs::{LogSender, Output, Stdio};
+use profiles::{Engines, Profile};
+use profiling::{ProfileReport, Profiler};
+use quotas::{Limiter, Limits, Quotas, TenantQuota};
+use recorder::PluginMetrics;
+use results::ResultCache;
+use rollouts::{Rollouts, Routed};
+use signing::Signatures;
+use scratch::Scratch;
+use storage::ModuleSource;
+use traps::{Coredump, TrapInfo};
+use warm::{Warm, WarmPools};
+
+// Per-tenant switch for linking WASI into plugin instances
+const WASI_FLAG: &str = "runtime.wasi";
+
+// Identifies the calling tenant; must agree with a tenant_id in the body
+const TENANT_HEADER: &str = "x-tenant-id";
+
+// Plugin secrets live under {prefix}/{tenant_id}/{name} in the secrets backend
+const PLUGIN_SECRETS_PREFIX: &str = "plugins";
+
+// Largest module accepted for upload or execution
+const MAX_MODULE_BYTES: usize = 10 * 1024 * 1024;
+
+// Longest `max_timeout_secs` may be
+const MAX_EXECUTION_TIMEOUT: Duration = Duration::from_secs(300);
+
+// How long past its deadline an execution is abandoned; plugins are
+// interrupted at the deadline itself, which this leaves time to report
+const DEADLINE_GRACE: Duration = Duration::from_millis(500);
 
 // Enhanced configuration for safety
-#[derive(Clone)]
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 struct RuntimeConfig {
     max_memory_pages: u32,
     max_table_elements: u32,
+    /// Highest security profile plugins may run with, unless the tenant's
+    /// quota says otherwise; see `profiles`.
+    max_security_profile: Profile,
+    /// Memory trusted plugins may grow to, when more than their tenant's
+    /// `max_memory_pages`.
+    trusted_max_memory_pages: u32,
+    /// What plugins may be granted, unless the tenant's quota says
+    /// otherwise, and what plugins whose manifest lists none ask for; see
+    /// `capabilities`.
+    granted_capabilities: Capabilities,
+    default_capabilities: Capabilities,
     max_instances: u32,
+    /// Preallocates instance slots with wasmtime's pooling allocator rather
+    /// than mapping each instance's memory on demand: cheaper
+    /// instantiation and less fragmentation, for the address space the
+    /// pool reserves up front.
+    pooling_allocator: bool,
+    /// Slots in each security profile's pool. Every live instance holds
+    /// one, warm ones included, and instantiation fails while none is free.
+    pooling_total_instances: u32,
+    pooling_total_memories: u32,
+    pooling_total_tables: u32,
+    /// Share of `max_instances` reserved for enterprise tenants; only they
+    /// can use it.
+    pool_enterprise_percent: u32,
+    /// Share reserved for pro tenants, which enterprise tenants may borrow
+    /// when idle. The rest is the free pool, open to every tier.
+    pool_pro_percent: u32,
+    /// Executions a tenant may have waiting for a slot while the pools are
+    /// full; 0 turns them away at once. See `pools`.
+    queue_depth_per_tenant: usize,
+    /// Longest an execution waits for a slot.
+    queue_timeout_ms: u64,
+    /// Plan for tenants without a `tenant_plan:{tenant_id}` key in Redis.
+    default_tenant_plan: String,
+    plan_cache_ttl_secs: u64,
     fuel_limit: u64,
+    /// Longest an execution may ask to run for, at most 300 seconds.
+    max_timeout_secs: u64,
+    /// How often running plugins are checked against their deadline; see
+    /// `epoch`.
+    epoch_interval_ms: u64,
+    /// Keeps a coredump of plugins that trap; see `traps`.
+    coredump_on_trap: bool,
+    /// Bytes of a plugin's stdout, and of its stderr, returned with each
+    /// execution; the rest is dropped.
+    max_output_bytes: usize,
+    /// Hosts each module may reach through `http_fetch`, by module path;
+    /// `*.example.com` matches subdomains. Unlisted modules can't fetch.
+    http_fetch_allowlists: HashMap<String, Vec<String>>,
+    http_fetch_max_request_bytes: usize,
+    http_fetch_max_response_bytes: usize,
+    /// Per call, and never past the execution's own timeout.
+    http_fetch_timeout_ms: u64,
+    /// Fuel charged per response byte, so fetching counts against the
+    /// plugin's budget like computing does.
+    http_fetch_fuel_per_byte: u64,
+    /// Largest value a plugin may store with `kv_set`.
+    kv_max_value_bytes: usize,
+    /// Longest a stored value is kept; plugins may ask for less.
+    kv_max_ttl_secs: u64,
+    /// Largest settings a tenant may store for a plugin, serialized.
+    plugin_settings_max_bytes: usize,
+    /// Modules that may read the secrets their settings reference through
+    /// `secret_get`, by module path; see `settings`.
+    secret_settings_modules: Vec<String>,
+    /// Plugin log lines returned to callers that ask for them, per
+    /// execution; all of them are logged.
+    max_plugin_logs: usize,
+    /// Core CRM API `crm_query` reads records from, e.g.
+    /// `http://backend:8000/api/v1`; plugins can't query while unset.
+    crm_api_url: Option<String>,
+    /// Service token for the CRM API; may be a `secret://` reference.
+    crm_api_token: Option<String>,
+    /// Records one execution may read through `crm_query`.
+    crm_query_max_rows: usize,
+    /// Fuel charged per record returned.
+    crm_query_fuel_per_record: u64,
+    crm_query_timeout_ms: u64,
+    /// Directories and network addresses each module may reach through
+    /// WASI, by module path; see `grants`.
+    wasi_grants: HashMap<String, WasiGrant>,
+    /// Variables of the runtime's environment passed to every plugin, and
+    /// those requests may set; see `environment`.
+    plugin_env: Vec<String>,
+    request_env: HashMap<String, EnvSchema>,
+    /// Where executions' scratch directories are made; see `scratch`.
+    scratch_dir: Option<String>,
+    /// How long asynchronous executions' jobs are kept; see `jobs`.
+    job_ttl_secs: u64,
+    /// How often each replica checks for scheduled executions that are
+    /// due; see `schedules`.
+    schedule_poll_interval_secs: u64,
+    /// How long each replica routes by the rollouts it last read; see
+    /// `rollouts`.
+    rollout_cache_ttl_secs: u64,
+    /// Most items a `POST /execute/batch` may have, and run at once; see
+    /// `batch`.
+    batch_max_items: usize,
+    batch_max_parallel: usize,
+    /// Most steps a pipeline may have; see `pipelines`.
+    pipeline_max_steps: usize,
+    /// How deep plugins' calls to other plugins may nest; see
+    /// `host::plugins`.
+    plugin_call_max_depth: usize,
+    /// Longest a plugin's results are cached for, whatever it declares,
+    /// and longest result cached; see `results`.
+    result_cache_max_ttl_secs: u64,
+    result_cache_max_bytes: usize,
+    /// How long responses to requests with an `Idempotency-Key` are
+    /// replayed for; see `idempotency`.
+    idempotency_window_secs: u64,
+    /// Share of a plugin's executions, of at least `breaker_min_executions`
+    /// over the last `breaker_window_secs`, that may fail before it's
+    /// disabled for `breaker_open_secs`; 0 turns that off. See `breakers`.
+    breaker_failure_percent: u32,
+    breaker_min_executions: u32,
+    breaker_window_secs: u64,
+    breaker_open_secs: u64,
+    /// Whether `POST /execute/inline` runs modules sent with the call, and
+    /// how big, long and costly they may be; see `inline`.
+    inline_modules: bool,
+    inline_max_module_bytes: usize,
+    inline_fuel_limit: u64,
+    inline_max_timeout_secs: u64,
+    /// How many modules and tenants metrics are labeled with by name; see
+    /// `recorder`.
+    metrics_max_modules: usize,
+    metrics_max_tenants: usize,
+    /// Redis stream every tenant invocation is appended to.
+    audit_stream: String,
+    audit_stream_max_len: usize,
+    /// How often `RUNTIME_CONFIG_FILE` is checked for changes, which later
+    /// executions then run with; see `reload`.
+    config_reload_interval_secs: u64,
+    /// Address the HTTP API listens on.
+    bind_address: String,
+    /// Certificate and key the HTTP API is served with over TLS, for
+    /// deployments without a proxy in front. Without them the API is
+    /// plain HTTP, or mesh mTLS when `TLS_*` is configured.
+    tls_cert_file: Option<String>,
+    tls_key_file: Option<String>,
+    /// CA that clients of the HTTP API must present certificates from.
+    tls_client_ca_file: Option<String>,
+    /// How often the TLS files are checked for rotation.
+    tls_reload_interval_secs: u64,
+    /// Serves `crm.v1.ExtensionRuntime` over gRPC on this port when set.
+    grpc_port: Option<u16>,
+    /// Object store holding uploaded modules, e.g.
+    /// `s3://crm-modules-use1/modules`. Modules are read from
+    /// `WASM_MODULE_DIR` while unset.
+    registry_url: Option<String>,
+    /// Passive-region copy of the registry that reads fail over to.
+    registry_secondary_url: Option<String>,
+    /// AWS region of the secondary bucket, when it differs from `AWS_REGION`.
+    registry_secondary_region: Option<String>,
+    /// How often new, changed and deleted modules are replicated to the
+    /// secondary.
+    registry_reconcile_interval_secs: u64,
+    /// How long a fetched module is reused before the registry is asked again.
+    registry_cache_ttl_secs: u64,
+    /// Directory plain module paths are read from when there is neither a
+    /// registry nor a module store; `WASM_MODULE_DIR` by default.
+    module_dir: Option<String>,
+    /// More directories, by name, that `{name}:{path}` module paths are
+    /// read from; a `{tenant_id}` in one makes its paths
+    /// `{name}:{tenant_id}:{path}`, for that tenant only. See `storage`.
+    module_roots: HashMap<String, String>,
+    /// S3-compatible bucket modules are read from by object key, e.g.
+    /// `s3://crm-plugins/modules`, when there is no registry.
+    module_store_url: Option<String>,
+    /// Endpoint of an S3-compatible store such as MinIO.
+    module_store_endpoint: Option<String>,
+    /// Where modules downloaded from `module_store_url` are cached; a
+    /// directory under the system temp dir by default.
+    module_cache_dir: Option<String>,
+    module_cache_ttl_secs: u64,
+    /// `user:password` per OCI registry host, for `oci://` module paths;
+    /// `secret://` references are resolved.
+    oci_credentials: HashMap<String, String>,
+    /// OCI registries reached over plain HTTP instead of HTTPS.
+    oci_insecure_registries: Vec<String>,
+    /// How long an `oci://` tag keeps resolving to the same layer before
+    /// its manifest is fetched again. Digest-pinned paths never expire.
+    oci_tag_ttl_secs: u64,
+    /// Ed25519 public keys modules must be signed with, one base64 key per
+    /// line; see `signing`. Signatures aren't checked while unset.
+    trusted_keys_file: Option<String>,
+    /// Runs unsigned and badly signed modules anyway, for development.
+    allow_unsigned_modules: bool,
+    /// Compiled modules kept in memory; more are reloaded from the on-disk
+    /// artifact cache.
+    compiled_cache_entries: usize,
+    /// Instances kept ready per module; 0 instantiates on every request.
+    warm_pool_size: usize,
+    /// Modules warm instances are kept for, least recently used dropped.
+    warm_pool_modules: usize,
+    /// Per-tenant overrides of the limits above, by tenant id.
+    tenant_quotas: HashMap<String, TenantQuota>,
+    /// Version requirements plugins are resolved with instead of what
+    /// references ask for, by tenant id and plugin name; see `versions`.
+    version_pins: HashMap<String, HashMap<String, String>>,
+    /// JWKS that execute calls' Bearer tokens are verified against; calls
+    /// are not authenticated while unset.
+    jwks_url: Option<String>,
+    jwks_refresh_secs: u64,
+    /// Expected `iss` and `aud` claims, checked when set.
+    jwt_issuer: Option<String>,
+    jwt_audience: Option<String>,
+    /// Scope a token must grant to execute plugins.
+    jwt_required_scope: String,
+    /// Scope that makes a token an administrator's, who may manage system
+    /// and every tenant's modules; see `auth`.
+    jwt_admin_scope: String,
 }
 
 impl Default for RuntimeConfig {
@@ -24,58 +344,485 @@ impl Default for RuntimeConfig {
         Self {
             max_memory_pages: 100, // ~6.4MB limit
             max_table_elements: 1000,
+            max_security_profile: Profile::Standard,
+            trusted_max_memory_pages: 1024, // 64MB
+            granted_capabilities: Capabilities::all(),
+            default_capabilities: Capabilities::all(),
             max_instances: 10,
+            pooling_allocator: false,
+            pooling_total_instances: 1_000,
+            pooling_total_memories: 1_000,
+            pooling_total_tables: 1_000,
+            pool_enterprise_percent: 50,
+            pool_pro_percent: 30,
+            queue_depth_per_tenant: 16,
+            queue_timeout_ms: 5_000,
+            default_tenant_plan: "pro".to_string(),
+            plan_cache_ttl_secs: 300,
             fuel_limit: 1_000_000, // Computational limit
+            max_timeout_secs: MAX_EXECUTION_TIMEOUT.as_secs(),
+            epoch_interval_ms: 10,
+            coredump_on_trap: false,
+            max_output_bytes: 64 * 1024,
+            http_fetch_allowlists: HashMap::new(),
+            http_fetch_max_request_bytes: 64 * 1024,
+            http_fetch_max_response_bytes: 1024 * 1024,
+            http_fetch_timeout_ms: 5_000,
+            http_fetch_fuel_per_byte: 10,
+            kv_max_value_bytes: 64 * 1024,
+            kv_max_ttl_secs: 30 * 86_400,
+            plugin_settings_max_bytes: 64 * 1024,
+            secret_settings_modules: Vec::new(),
+            max_plugin_logs: 100,
+            crm_api_url: None,
+            crm_api_token: None,
+            crm_query_max_rows: 1_000,
+            crm_query_fuel_per_record: 1_000,
+            crm_query_timeout_ms: 5_000,
+            wasi_grants: HashMap::new(),
+            plugin_env: Vec::new(),
+            request_env: HashMap::new(),
+            scratch_dir: None,
+            job_ttl_secs: 86_400,
+            schedule_poll_interval_secs: 1,
+            rollout_cache_ttl_secs: 5,
+            batch_max_items: 1000,
+            batch_max_parallel: 8,
+            pipeline_max_steps: 16,
+            plugin_call_max_depth: 4,
+            result_cache_max_ttl_secs: 3600,
+            result_cache_max_bytes: 64 * 1024,
+            idempotency_window_secs: 86_400,
+            breaker_failure_percent: 50,
+            breaker_min_executions: 20,
+            breaker_window_secs: 60,
+            breaker_open_secs: 30,
+            inline_modules: false,
+            inline_max_module_bytes: 1024 * 1024,
+            inline_fuel_limit: 100_000,
+            inline_max_timeout_secs: 5,
+            metrics_max_modules: 500,
+            metrics_max_tenants: 1000,
+            audit_stream: "runtime_audit".to_string(),
+            audit_stream_max_len: 1_000_000,
+            bind_address: "127.0.0.1:8080".to_string(),
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_client_ca_file: None,
+            tls_reload_interval_secs: 30,
+            config_reload_interval_secs: 10,
+            grpc_port: None,
+            registry_url: None,
+            registry_secondary_url: None,
+            registry_secondary_region: None,
+            registry_reconcile_interval_secs: 60,
+            registry_cache_ttl_secs: 30,
+            module_dir: None,
+            module_roots: HashMap::new(),
+            module_store_url: None,
+            module_store_endpoint: None,
+            module_cache_dir: None,
+            module_cache_ttl_secs: 600,
+            oci_credentials: HashMap::new(),
+            oci_insecure_registries: Vec::new(),
+            oci_tag_ttl_secs: 300,
+            trusted_keys_file: None,
+            allow_unsigned_modules: false,
+            compiled_cache_entries: 64,
+            warm_pool_size: 4,
+            warm_pool_modules: 32,
+            tenant_quotas: HashMap::new(),
+            version_pins: HashMap::new(),
+            jwks_url: None,
+            jwks_refresh_secs: 300,
+            jwt_issuer: None,
+            jwt_audience: None,
+            jwt_required_scope: "plugins:execute".to_string(),
+            jwt_admin_scope: "plugins:admin".to_string(),
+        }
+    }
+}
+
+impl RuntimeConfig {
+    /// `None` when no registry is configured and modules come from the
+    /// module directory.
+    fn registry_config(&self) -> Option<RegistryConfig> {
+        Some(RegistryConfig {
+            primary_url: self.registry_url.clone()?,
+            secondary_url: self.registry_secondary_url.clone(),
+            secondary_region: self.registry_secondary_region.clone(),
+            cache_ttl: Duration::from_secs(self.registry_cache_ttl_secs),
+        })
+    }
+
+    fn pool_config(&self) -> PoolConfig {
+        PoolConfig {
+            max_instances: self.max_instances,
+            enterprise_percent: self.pool_enterprise_percent,
+            pro_percent: self.pool_pro_percent,
+            queue_depth_per_tenant: self.queue_depth_per_tenant,
+            queue_timeout: Duration::from_millis(self.queue_timeout_ms),
         }
     }
 }
 
+impl Configurable for RuntimeConfig {
+    const SECRET_FIELDS: &'static [&'static str] = &["oci_credentials", "crm_api_token"];
+
+    fn validate(&self) -> std::result::Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if self.max_memory_pages == 0 {
+            problems.push("max_memory_pages must be positive".to_string());
+        }
+        if self.trusted_max_memory_pages == 0 {
+            problems.push("trusted_max_memory_pages must be positive".to_string());
+        }
+        if self.max_instances == 0 {
+            problems.push("max_instances must be positive".to_string());
+        }
+        let live_instances = self.max_instances as usize + self.warm_pool_size * self.warm_pool_modules;
+        if self.pooling_allocator && (self.pooling_total_instances as usize) < live_instances {
+            problems.push(format!(
+                "pooling_total_instances must cover max_instances and warm_pool_size × warm_pool_modules ({})",
+                live_instances
+            ));
+        }
+        if self.pool_enterprise_percent + self.pool_pro_percent > 100 {
+            problems.push("pool_enterprise_percent and pool_pro_percent must not add up to more than 100".to_string());
+        }
+        if self.queue_timeout_ms == 0 {
+            problems.push("queue_timeout_ms must be positive".to_string());
+        }
+        if self.fuel_limit == 0 {
+            problems.push("fuel_limit must be positive".to_string());
+        }
+        if self.max_timeout_secs == 0 || self.max_timeout_secs > MAX_EXECUTION_TIMEOUT.as_secs() {
+            problems.push(format!("max_timeout_secs must be between 1 and {}", MAX_EXECUTION_TIMEOUT.as_secs()));
+        }
+        if self.epoch_interval_ms == 0 {
+            problems.push("epoch_interval_ms must be positive".to_string());
+        }
+        if self.bind_address.parse::<SocketAddr>().is_err() {
+            problems.push(format!("bind_address {} is not an ip:port address", self.bind_address));
+        }
+        if self.tls_cert_file.is_some() != self.tls_key_file.is_some() {
+            problems.push("tls_cert_file and tls_key_file must be set together".to_string());
+        }
+        if self.tls_client_ca_file.is_some() && self.tls_cert_file.is_none() {
+            problems.push("tls_client_ca_file needs tls_cert_file".to_string());
+        }
+        if self.tls_reload_interval_secs == 0 {
+            problems.push("tls_reload_interval_secs must be positive".to_string());
+        }
+        if self.config_reload_interval_secs == 0 {
+            problems.push("config_reload_interval_secs must be positive".to_string());
+        }
+        if self.http_fetch_timeout_ms == 0 {
+            problems.push("http_fetch_timeout_ms must be positive".to_string());
+        }
+        if self.crm_query_timeout_ms == 0 {
+            problems.push("crm_query_timeout_ms must be positive".to_string());
+        }
+        if self.job_ttl_secs == 0 {
+            problems.push("job_ttl_secs must be positive".to_string());
+        }
+        if self.schedule_poll_interval_secs == 0 {
+            problems.push("schedule_poll_interval_secs must be positive".to_string());
+        }
+        if self.rollout_cache_ttl_secs == 0 {
+            problems.push("rollout_cache_ttl_secs must be positive".to_string());
+        }
+        if self.batch_max_items == 0 || self.batch_max_parallel == 0 {
+            problems.push("batch_max_items and batch_max_parallel must be positive".to_string());
+        }
+        if self.pipeline_max_steps == 0 {
+            problems.push("pipeline_max_steps must be positive".to_string());
+        }
+        if self.plugin_call_max_depth == 0 {
+            problems.push("plugin_call_max_depth must be positive".to_string());
+        }
+        if self.result_cache_max_ttl_secs == 0 || self.result_cache_max_bytes == 0 {
+            problems.push("result_cache_max_ttl_secs and result_cache_max_bytes must be positive".to_string());
+        }
+        if self.idempotency_window_secs == 0 {
+            problems.push("idempotency_window_secs must be positive".to_string());
+        }
+        if self.breaker_failure_percent > 100 {
+            problems.push("breaker_failure_percent must be at most 100".to_string());
+        }
+        if self.breaker_min_executions == 0 || self.breaker_window_secs == 0 || self.breaker_open_secs == 0 {
+            problems.push("breaker_min_executions, breaker_window_secs and breaker_open_secs must be positive".to_string());
+        }
+        if self.inline_max_module_bytes == 0 || self.inline_fuel_limit == 0 || self.inline_max_timeout_secs == 0 {
+            problems.push("inline_max_module_bytes, inline_fuel_limit and inline_max_timeout_secs must be positive".to_string());
+        }
+        if self.metrics_max_modules == 0 || self.metrics_max_tenants == 0 {
+            problems.push("metrics_max_modules and metrics_max_tenants must be positive".to_string());
+        }
+        if self.inline_max_module_bytes > MAX_MODULE_BYTES {
+            problems.push(format!("inline_max_module_bytes must be at most {}", MAX_MODULE_BYTES));
+        }
+        if self.kv_max_ttl_secs == 0 {
+            problems.push("kv_max_ttl_secs must be positive".to_string());
+        }
+        if self.plugin_settings_max_bytes == 0 {
+            problems.push("plugin_settings_max_bytes must be positive".to_string());
+        }
+        if self.registry_secondary_url.is_some() && self.registry_url.is_none() {
+            problems.push("registry_secondary_url needs registry_url".to_string());
+        }
+        if self.registry_url.is_some() && self.module_store_url.is_some() {
+            problems.push("registry_url and module_store_url are exclusive".to_string());
+        }
+        for (name, dir) in &self.module_roots {
+            if !valid_path_segment(name) {
+                problems.push(format!("module_roots name {} must be letters, digits, _ and -", name));
+            }
+            if !std::path::Path::new(dir).is_absolute() {
+                problems.push(format!("module_roots.{} must be an absolute directory", name));
+            }
+            if dir.matches(storage::TENANT_PLACEHOLDER).count() > 1 {
+                problems.push(format!("module_roots.{} may have {} once", name, storage::TENANT_PLACEHOLDER));
+            }
+        }
+        if self.module_cache_ttl_secs == 0 {
+            problems.push("module_cache_ttl_secs must be positive".to_string());
+        }
+        if self.oci_credentials.values().any(|credentials| !credentials.contains(':')) {
+            problems.push("oci_credentials must be user:password".to_string());
+        }
+        if self.compiled_cache_entries == 0 {
+            problems.push("compiled_cache_entries must be positive".to_string());
+        }
+        if self.warm_pool_modules == 0 {
+            problems.push("warm_pool_modules must be positive".to_string());
+        }
+        for (tenant_id, quota) in &self.tenant_quotas {
+            quota.validate(tenant_id, &mut problems);
+        }
+        versions::validate_pins(&self.version_pins, &mut problems);
+        for (module_path, grant) in &self.wasi_grants {
+            grant.validate(module_path, &mut problems);
+        }
+        for name in self.plugin_env.iter().chain(self.request_env.keys()) {
+            if !environment::valid_name(name) {
+                problems.push(format!("invalid environment variable name '{}'", name));
+            }
+        }
+        if self.jwt_admin_scope == self.jwt_required_scope {
+            problems.push("jwt_admin_scope must differ from jwt_required_scope".to_string());
+        }
+        if self.jwks_refresh_secs == 0 {
+            problems.push("jwks_refresh_secs must be positive".to_string());
+        }
+        if self.registry_reconcile_interval_secs == 0 {
+            problems.push("registry_reconcile_interval_secs must be positive".to_string());
+        }
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
-    // Register metrics
-    prometheus::register_counter!(
-        "plugin_executions_total",
-        "Total number of plugin executions"
-    ).unwrap();
-    prometheus::register_counter!(
-        "plugin_execution_failures_total",
-        "Total number of failed plugin executions"
-    ).unwrap();
-    prometheus::register_histogram!(
-        "plugin_execution_duration_seconds",
-        "Duration of plugin executions in seconds",
-        vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]
-    ).unwrap();
-    prometheus::register_gauge!(
-        "active_plugin_instances",
-        "Number of active plugin instances"
-    ).unwrap();
+    let _observability = crm_observability::init("extension-runtime-service")?;
+    recorder::install()?;
     info!("Starting Enhanced Extension Runtime Service");
-    let config = RuntimeConfig::default();
-    let engine = create_secure_engine(&config)?;
+    let config = reload::load()?;
+    info!("Runtime configuration: {}", crm_config::redacted(&config));
+    let engines = Engines::new(&config)?;
+    epoch::spawn_ticker(engines.clone(), Duration::from_millis(config.epoch_interval_ms))?;
+    let startup = StartupConfig::from_env()?;
+    let secrets = Arc::new(SecretStore::from_env()?);
+    let redis_url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".to_string());
+    let redis_url = retry("secrets backend", &startup.retry_policy(), || secrets.resolve_str(&redis_url)).await?;
+    // OCI registry credentials and the CRM API token may be secret:// references
+    let config = retry("secrets backend", &startup.retry_policy(), || secrets.resolve(&config)).await?;
+    // Uploaded modules, replicated to the passive region when configured
+    let registry = config.registry_config().map(|registry| ModuleRegistry::open(&registry)).transpose()?.map(Arc::new);
+    if let Some(registry) = &registry {
+        registry.clone().spawn_reconcile(Duration::from_secs(config.registry_reconcile_interval_secs));
+    }
+    // Neither blocks startup: flags fall back to defaults, audit records
+    // are dropped and plugin KV calls fail while Redis is down, and only
+    // calls into missing modules fail, so the runtime serves degraded instead
+    let mut dependencies = vec![Dependency::redis("redis", &redis_url).optional()];
+    if registry.is_none() && config.module_store_url.is_none() {
+        for (name, dir) in storage::local_dirs(&config) {
+            dependencies.push(Dependency::path(&name, dir).optional());
+        }
+    }
+    let modules = storage::from_config(&config, registry.clone())?;
+    let signatures = Signatures::from_config(&config)?;
+    let compiled = CompiledCache::new(
+        engines.clone(),
+        storage::cache_dir(&config).join("compiled"),
+        config.compiled_cache_entries,
+        config.default_capabilities,
+    )?;
+    let warm = WarmPools::new(engines, &config);
+    let health = Health::new("extension-runtime-service", &startup, dependencies);
+    let probes = health.clone();
+    tokio::spawn(async move {
+        if let Err(e) = probes.start().await {
+            error!("Health probes not started: {}", e);
+        }
+    });
+    // Module uploads and deletions go on the admin audit trail
+    let admin_audit = AuditTrail::new("extension-runtime-service", &secrets.resolve(&AuditConfig::from_env()?).await?)?;
     let state = Arc::new(ServiceState {
-        engine,
-        config,
-        active_instances: Arc::new(std::sync::atomic::AtomicU32::new(0)),
+        admin_audit,
+        secrets: secrets.clone(),
+        flags: FlagClient::new(&redis_url, Duration::from_secs(30)),
+        audit: AuditLog::new(&redis_url, &config.audit_stream, config.audit_stream_max_len),
+        registry,
+        modules,
+        signatures,
+        compiled,
+        warm,
+        pools: ExecutionPools::new(&config.pool_config()),
+        plans: PlanResolver::new(
+            &redis_url,
+            &config.default_tenant_plan,
+            Duration::from_secs(config.plan_cache_ttl_secs),
+        ),
+        quotas: Quotas::new(&redis_url, &config),
+        auth: Authenticator::from_config(&config)?,
+        http_fetch: HttpFetch::from_config(&config)?,
+        kv: KvStore::new(&redis_url, &config),
+        settings: PluginSettings::new(&redis_url, &config, secrets.clone()),
+        executions: Executions::new(),
+        breakers: Breakers::new(),
+        jobs: Jobs::new(&redis_url, config.job_ttl_secs),
+        schedules: Schedules::new(&redis_url),
+        rollouts: Rollouts::new(&redis_url, &config),
+        results: ResultCache::new(&redis_url, &config),
+        idempotency: Idempotency::new(&redis_url, &config),
+        crm: CrmApi::from_config(&config)?,
+        plugin_calls: PluginCalls::new(&config),
+        plugin_metrics: PluginMetrics::new(&config),
+        config: RwLock::new(Arc::new(config)),
     });
-    let metrics_route = warp::path("metrics").and_then(handle_metrics);
+    state.plugin_calls.attach(&state);
+    reload::spawn_watch(state.clone());
+    let tls = crm_tls::MtlsContext::from_env()?;
+    let config = state.config();
+    let public_tls = match (&config.tls_cert_file, &config.tls_key_file) {
+        (Some(cert_file), Some(key_file)) => {
+            let public_tls = crm_tls::PublicTls::load(cert_file, key_file, config.tls_client_ca_file.as_deref())?;
+            public_tls.spawn_reload(Duration::from_secs(config.tls_reload_interval_secs));
+            Some(public_tls)
+        }
+        _ => None,
+    };
+    let bind_address: SocketAddr = config.bind_address.parse()?;
+    if let Some(port) = config.grpc_port {
+        tokio::spawn(grpc::serve(port, state.clone(), tls.clone()));
+    }
+    schedules::spawn_scheduler(state.clone(), Duration::from_secs(config.schedule_poll_interval_secs));
+    let metrics_route = crm_observability::metrics_route();
+    let admin_guard = auth::admin_guard(state.clone());
+    let modules_route = modules::routes(state.clone());
+    let precompile_route = compiled::routes(state.clone());
+    let validate_route = validate::routes(state.clone());
+    let manifests_route = manifests::routes(state.clone());
+    let executions_route = executions::routes(state.clone());
+    let jobs_route = jobs::routes(state.clone());
+    let schedules_route = schedules::routes(state.clone());
+    let rollouts_route = rollouts::routes(state.clone());
+    let results_route = results::routes(state.clone());
+    let breakers_route = breakers::routes(state.clone());
+    let settings_route = settings::routes(state.clone());
+    let batch_route = batch::routes(state.clone());
+    let pipelines_route = pipelines::routes(state.clone());
+    let inline_route = inline::routes(state.clone());
     let execute_route = warp::post()
         .and(warp::path("execute"))
         .and(warp::body::content_length_limit(1024 * 1024)) // 1MB limit
         .and(warp::body::json())
+        .and(warp::query::<ExecuteQuery>())
+        .and(warp::header::optional::<String>(TENANT_HEADER))
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>(IDEMPOTENCY_HEADER))
         .and(warp::any().map(move || state.clone()))
         .and_then(handle_execute);
-    let routes = metrics_route.or(execute_route);
-    info!("Enhanced secure server running on http://localhost:8080");
-    warp::serve(routes).run(([127, 0, 0, 1], 8080)).await;
+    let routes = metrics_route
+        .or(health.route())
+        .or(admin_guard)
+        .or(crm_chaos::routes())
+        .or(modules_route)
+        .or(precompile_route)
+        .or(validate_route)
+        .or(manifests_route)
+        .or(executions_route)
+        .or(jobs_route)
+        .or(schedules_route)
+        .or(rollouts_route)
+        .or(results_route)
+        .or(breakers_route)
+        .or(settings_route)
+        .or(batch_route)
+        .or(pipelines_route)
+        .or(inline_route)
+        .or(openapi::routes())
+        .or(execute_route)
+        .with(crm_observability::http_metrics())
+        .with(crm_observability::traced());
+    // The runtime's own certificate takes precedence over mesh mTLS, where
+    // only callers with an allowed SPIFFE ID get in
+    match (public_tls, tls) {
+        (Some(public_tls), _) => {
+            info!(
+                "Enhanced secure server running on https://{}{}",
+                bind_address,
+                if public_tls.requires_client_certs() { " (client certificates required)" } else { "" }
+            );
+            crm_tls::serve(bind_address, public_tls.server_config(), warp::service(routes)).await?
+        }
+        (None, Some(tls)) => crm_tls::serve(bind_address, tls.server_config(), warp::service(routes)).await?,
+        (None, None) => {
+            info!("Enhanced secure server running on http://{}", bind_address);
+            warp::serve(routes).run(bind_address).await;
+        }
+    }
     Ok(())
 }
 
 struct ServiceState {
-    engine: Engine,
-    config: RuntimeConfig,
-    active_instances: Arc<std::sync::atomic::AtomicU32>,
+    admin_audit: AuditTrail,
+    secrets: Arc<SecretStore>,
+    flags: FlagClient,
+    audit: AuditLog,
+    registry: Option<Arc<ModuleRegistry>>,
+    modules: Arc<dyn ModuleSource>,
+    signatures: Option<Signatures>,
+    compiled: CompiledCache,
+    warm: Arc<WarmPools>,
+    pools: Arc<ExecutionPools>,
+    plans: PlanResolver,
+    quotas: Arc<Quotas>,
+    auth: Option<Authenticator>,
+    http_fetch: Arc<HttpFetch>,
+    kv: Arc<KvStore>,
+    settings: Arc<PluginSettings>,
+    executions: Arc<Executions>,
+    breakers: Arc<Breakers>,
+    jobs: Arc<Jobs>,
+    schedules: Arc<Schedules>,
+    rollouts: Arc<Rollouts>,
+    results: Arc<ResultCache>,
+    idempotency: Arc<Idempotency>,
+    crm: Arc<CrmApi>,
+    plugin_calls: Arc<PluginCalls>,
+    plugin_metrics: PluginMetrics,
+    /// Replaced when the config file changes; see `reload`.
+    config: RwLock<Arc<RuntimeConfig>>,
+}
+
+impl ServiceState {
+    /// The current configuration, which an execution keeps to the end.
+    fn config(&self) -> Arc<RuntimeConfig> {
+        self.config.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -84,295 +831,955 @@ struct ExecuteRequest {
     function_name: String,
     params: serde_json::Value, // More flexible parameter handling
     timeout_seconds: Option<u64>,
+    /// Tenant the plugin runs for; used for per-tenant feature flags.
+    #[serde(default)]
+    tenant_id: Option<String>,
+    /// Names of the tenant's plugin secrets to expose as environment
+    /// variables; needs a tenant_id and WASI.
+    #[serde(default)]
+    secrets: Vec<String>,
+    /// Environment variables for the plugin, as `request_env` allows;
+    /// needs WASI.
+    #[serde(default)]
+    env: HashMap<String, String>,
+    /// Return the lines the plugin logged through the `crm.log_*` host
+    /// functions with the response.
+    #[serde(default)]
+    include_logs: bool,
+    /// Whether a core module function returns values or a string or bytes
+    /// in its memory; see `params`.
+    #[serde(default)]
+    result_encoding: ResultEncoding,
+    /// `json` for plugins that take `params` as one JSON document and
+    /// return one; see `params`.
+    #[serde(default)]
+    calling_convention: CallingConvention,
+    /// The authenticated caller, when authentication is on.
+    #[serde(skip)]
+    caller: Option<AuthContext>,
+    /// Where the plugin's output goes while it runs, for streaming calls.
+    #[serde(skip)]
+    logs: Option<LogSender>,
+    /// Told once the execution has a slot, for asynchronous calls.
+    #[serde(skip)]
+    started: Option<tokio::sync::oneshot::Sender<()>>,
+    /// The module to run instead of loading `module_path`, for lifecycle
+    /// hooks of modules not stored yet and items of a batch; otherwise
+    /// loaded before the execution is admitted, for its result cache key.
+    #[serde(skip)]
+    compiled: Option<Compiled>,
+    /// Sample the plugin's stack while it runs; see `profiling`.
+    #[serde(skip)]
+    profile: bool,
+    /// Seeds the random sources of deterministic plugins; see
+    /// `determinism`.
+    #[serde(default)]
+    seed: Option<u64>,
+    /// Fuel the call may burn, when less than the tenant's `fuel_limit`;
+    /// for steps of a pipeline and plugins called by others.
+    #[serde(skip)]
+    fuel_limit: Option<u64>,
+    /// The calls a plugin called by another is made from; see
+    /// `host::plugins`.
+    #[serde(skip)]
+    nesting: Option<Nesting>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct ExecuteQuery {
+    /// Answer with a job right away instead of waiting; see `jobs`.
+    #[serde(default, rename = "async")]
+    run_async: bool,
+    /// Return a profile of the plugin with the response; see `profiling`.
+    #[serde(default)]
+    profile: bool,
 }
 
 #[derive(serde::Serialize)]
 struct ExecuteResponse {
+    /// Tags the plugin's log lines and the runtime's own for this call.
+    execution_id: String,
     success: bool,
     result: Option<serde_json::Value>,
-    error: Option<String>,
+    /// Why it failed; see `errors`.
+    error: Option<ExecuteError>,
     execution_time_ms: u64,
+    /// Peak linear memory of the plugin's instances, initial memory
+    /// included.
     memory_used_bytes: u64,
     fuel_consumed: u64,
+    /// What the plugin wrote, up to `max_output_bytes` each.
+    stdout: String,
+    stderr: String,
+    output_truncated: bool,
+    /// Lines logged through the host functions, when asked for.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    logs: Vec<PluginLog>,
+    /// The plugin's stack samples, when asked for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<serde_json::Value>,
+    /// Why and where the plugin trapped, when it did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trap: Option<TrapInfo>,
+    /// The plugin's state when it trapped, with `coredump_on_trap`; kept
+    /// for asynchronous executions.
+    #[serde(skip)]
+    coredump: Option<Vec<u8>>,
+    /// The version a version reference ran; see `versions`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolved_version: Option<String>,
+    /// Turned away by a capacity or quota limit rather than failed.
+    #[serde(skip)]
+    throttled: bool,
+    /// Answered from the result cache; see `results`.
+    cached: bool,
 }
 
-fn create_secure_engine(_config: &RuntimeConfig) -> Result<Engine> {
+impl ExecuteResponse {
+    /// Turned away before the plugin ran.
+    fn failed(error: ExecuteError) -> Self {
+        ExecuteResponse {
+            execution_id: String::new(),
+            success: false,
+            result: None,
+            error: Some(error),
+            execution_time_ms: 0,
+            memory_used_bytes: 0,
+            fuel_consumed: 0,
+            stdout: String::new(),
+            stderr: String::new(),
+            output_truncated: false,
+            logs: Vec::new(),
+            profile: None,
+            trap: None,
+            coredump: None,
+            resolved_version: None,
+            throttled: false,
+            cached: false,
+        }
+    }
+
+    fn throttled(error: ExecuteError) -> Self {
+        ExecuteResponse { throttled: true, ..ExecuteResponse::failed(error) }
+    }
+
+    fn cached(result: serde_json::Value) -> Self {
+        ExecuteResponse {
+            success: true,
+            result: Some(result),
+            error: None,
+            cached: true,
+            ..ExecuteResponse::failed(ExecuteError::new(ErrorCode::ExecutionFailed, String::new()))
+        }
+    }
+
+    /// The HTTP status it's answered with.
+    fn status(&self) -> warp::http::StatusCode {
+        self.error.as_ref().map_or(warp::http::StatusCode::OK, ExecuteError::status)
+    }
+}
+
+/// The engine plugins with `profile` are compiled and run with, or
+/// deterministic plugins with it; see `determinism`.
+fn create_secure_engine(config: &RuntimeConfig, profile: Profile, deterministic: bool) -> Result<Engine> {
     let mut engine_config = Config::new();
     // Security configurations
     engine_config.wasm_backtrace_details(WasmBacktraceDetails::Enable);
     engine_config.consume_fuel(true); // Enable fuel-based limiting
     engine_config.epoch_interruption(true);
+    engine_config.coredump_on_trap(config.coredump_on_trap);
     // Resource limits
     engine_config.max_wasm_stack(512 * 1024); // 512KB stack limit
     engine_config.wasm_multi_memory(false); // Disable multiple memories
     engine_config.wasm_memory64(false); // Disable 64-bit memory
     // Disable potentially dangerous features
     engine_config.wasm_threads(false);
-    engine_config.wasm_reference_types(false);
-    engine_config.wasm_simd(false);
     engine_config.wasm_relaxed_simd(false);
-    engine_config.wasm_bulk_memory(false);
-    Ok(Engine::new(&engine_config)?)
-}
-
-async fn handle_metrics() -> Result<impl warp::Reply, warp::Rejection> {
-    let encoder = TextEncoder::new();
-    let mut buffer = Vec::new();
-    let metric_families = prometheus::gather();
-    encoder.encode(&metric_families, &mut buffer).unwrap();
-    Ok(String::from_utf8(buffer).unwrap())
+    // Opened up by the profile
+    engine_config.wasm_simd(profile >= Profile::Standard && !deterministic);
+    engine_config.wasm_bulk_memory(profile >= Profile::Standard);
+    engine_config.wasm_reference_types(profile >= Profile::Trusted);
+    // The same NaN bits on every host
+    engine_config.cranelift_nan_canonicalization(deterministic);
+    // WASI Preview 2 plugins are components
+    engine_config.wasm_component_model(true);
+    if config.pooling_allocator {
+        // Slots fit the largest memory any tenant is allowed with the profile
+        let largest = Limits {
+            max_memory_pages: config
+                .tenant_quotas
+                .values()
+                .filter_map(|quota| quota.max_memory_pages)
+                .fold(config.max_memory_pages, u32::max),
+            ..Limits::from_config(config)
+        };
+        let memory_pages = profile.limits(largest, config).max_memory_pages;
+        let mut pooling = PoolingAllocationConfig::default();
+        pooling
+            .total_core_instances(config.pooling_total_instances)
+            .total_component_instances(config.pooling_total_instances)
+            .total_memories(config.pooling_total_memories)
+            .total_tables(config.pooling_total_tables)
+            .memory_pages(memory_pages as u64)
+            .table_elements(config.max_table_elements);
+        engine_config.allocation_strategy(InstanceAllocationStrategy::Pooling(pooling));
+        info!(
+            "Pooling allocator for the {}{} profile: {} instances, {} memories of {} pages, {} tables",
+            if deterministic { "deterministic " } else { "" },
+            profile.name(),
+            config.pooling_total_instances,
+            config.pooling_total_memories,
+            memory_pages,
+            config.pooling_total_tables
+        );
+    }
+    Engine::new(&engine_config)
 }
 
-#[instrument(skip(state), fields(module_path = %req.module_path, function = %req.function_name))]
 async fn handle_execute(
-    req: ExecuteRequest,
+    mut req: ExecuteRequest,
+    query: ExecuteQuery,
+    tenant_header: Option<String>,
+    authorization: Option<String>,
+    idempotency_key: Option<String>,
     state: Arc<ServiceState>,
-) -> Result<impl warp::Reply, warp::Rejection> {
-    // Check instance limit
-    let current_instances = state.active_instances.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-    if current_instances >= state.config.max_instances {
-        state.active_instances.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
-        counter!("plugin_execution_failures_total", "reason" => "instance_limit");
-        return Ok(warp::reply::json(&ExecuteResponse {
-            success: false,
-            result: None,
-            error: Some("Too many active instances".to_string()),
-            execution_time_ms: 0,
-            memory_used_bytes: 0,
-            fuel_consumed: 0,
-        }));
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let failed = |status, error: ExecuteError| {
+        let error = serde_json::json!({ "success": false, "error": error });
+        Ok(warp::reply::with_status(warp::reply::json(&error), status).into_response())
+    };
+    if let Err(error) = authorize(&state, &mut req, tenant_header, authorization.as_deref()).await {
+        return failed(error.status(), error);
     }
-    let execution_timeout = Duration::from_secs(
-        req.timeout_seconds.unwrap_or(30).min(300) // Max 5 minutes
-    );
-    let result = timeout(execution_timeout, execute_plugin_safe(&state.engine, &req, &state.config)).await;
-    // Decrement active instances
-    state.active_instances.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
-    gauge!("active_plugin_instances");
-    match result {
-        Ok(Ok(response)) => {
-            counter!("plugin_executions_total", "status" => "success");
-            let duration_secs = response.execution_time_ms as f64 / 1000.0;
-            histogram!("plugin_execution_duration_seconds").record(duration_secs);  // Fixed line
-            Ok(warp::reply::json(&response))
+    // A retry gets the response of the first request with its key; see
+    // `idempotency`
+    let idempotent = match idempotency_key {
+        Some(key) if key.is_empty() || key.len() > idempotency::MAX_KEY_LEN => {
+            let error = format!("Idempotency-Key must be 1 to {} characters", idempotency::MAX_KEY_LEN);
+            return failed(warp::http::StatusCode::BAD_REQUEST, ExecuteError::new(ErrorCode::InvalidRequest, error));
+        }
+        Some(key) => {
+            let fingerprint = idempotency::fingerprint(&req, &query);
+            match state.idempotency.claim(req.tenant_id.as_deref(), &key, &fingerprint).await {
+                Ok(Claim::Run) => Some((key, fingerprint)),
+                Ok(Claim::Replay { status, body }) => {
+                    let status = warp::http::StatusCode::from_u16(status).unwrap_or(warp::http::StatusCode::OK);
+                    let reply = warp::reply::with_status(warp::reply::json(&body), status);
+                    return Ok(warp::reply::with_header(reply, REPLAYED_HEADER, "true").into_response());
+                }
+                Ok(Claim::InProgress) => {
+                    let error = "A request with this Idempotency-Key is still running";
+                    return failed(warp::http::StatusCode::CONFLICT, ExecuteError::new(ErrorCode::Conflict, error));
+                }
+                Ok(Claim::Mismatch) => {
+                    let error = "Idempotency-Key was already used for a different request";
+                    return failed(warp::http::StatusCode::UNPROCESSABLE_ENTITY, ExecuteError::new(ErrorCode::InvalidRequest, error));
+                }
+                Err(e) => {
+                    warn!("Idempotency-Key ignored: {:#}", e);
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+    let tenant_id = req.tenant_id.clone();
+    req.profile = query.profile;
+    let (status, body, retryable) = if query.run_async {
+        match state.jobs.submit(state.clone(), req).await {
+            Ok(job) => (warp::http::StatusCode::ACCEPTED, serde_json::to_value(&job).unwrap_or_default(), false),
+            Err(e) => {
+                error!("Failed to queue job: {:#}", e);
+                if let Some((key, _)) = &idempotent {
+                    state.idempotency.release(tenant_id.as_deref(), key).await;
+                }
+                let error = ExecuteError::new(ErrorCode::Unavailable, format!("Failed to queue job: {:#}", e));
+                return failed(warp::http::StatusCode::SERVICE_UNAVAILABLE, error);
+            }
+        }
+    } else {
+        let response = execute(&state, req).await;
+        let retryable = response.error.as_ref().is_some_and(|error| error.retryable);
+        (response.status(), serde_json::to_value(&response).unwrap_or_default(), retryable)
+    };
+    // Throttled requests, and others that may succeed later, didn't run,
+    // so their retries may
+    if let Some((key, fingerprint)) = &idempotent {
+        if retryable {
+            state.idempotency.release(tenant_id.as_deref(), key).await;
+        } else {
+            state.idempotency.complete(tenant_id.as_deref(), key, fingerprint, status.as_u16(), body.clone()).await;
+        }
+    }
+    Ok(warp::reply::with_status(warp::reply::json(&body), status).into_response())
+}
+
+/// Settles who `req` runs for: the tenant in `X-Tenant-ID` or the body,
+/// or the verified token's with authentication on.
+async fn authorize(
+    state: &ServiceState,
+    req: &mut ExecuteRequest,
+    tenant_header: Option<String>,
+    authorization: Option<&str>,
+) -> std::result::Result<(), ExecuteError> {
+    if let Some(tenant_id) = tenant_header {
+        if req.tenant_id.as_ref().is_some_and(|body| *body != tenant_id) {
+            let error = "tenant_id does not match the x-tenant-id header";
+            return Err(ExecuteError::new(ErrorCode::InvalidRequest, error));
+        }
+        req.tenant_id = Some(tenant_id);
+    }
+    // A verified token decides the tenant
+    if let Some(auth) = &state.auth {
+        match auth.authenticate(authorization, req.tenant_id.as_deref()).await {
+            Ok(caller) => {
+                req.tenant_id = Some(caller.tenant_id.clone());
+                req.caller = Some(caller);
+            }
+            Err(e) => {
+                warn!("Execute call refused: {:?}", e);
+                let code = if e.is_forbidden() { ErrorCode::NotPermitted } else { ErrorCode::Unauthenticated };
+                return Err(ExecuteError::new(code, e.to_string()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rewrites `req.module_path` to the id of the module it runs, when a
+/// rollout or version reference picks one, and returns the rollout and
+/// version picked.
+async fn resolve_module(state: &ServiceState, req: &mut ExecuteRequest) -> Result<(Option<Routed>, Option<String>)> {
+    let routed = state.rollouts.route(req).await;
+    if let Some(routed) = &routed {
+        req.module_path = registry::module_id(&routed.plugin, &routed.version);
+    }
+    let resolved_version = match versions::resolve(state, &req.module_path, req.tenant_id.as_deref()).await? {
+        Some((module_id, version)) => {
+            req.module_path = module_id;
+            Some(version.to_string())
+        }
+        None => routed.as_ref().map(|routed| routed.version.clone()),
+    };
+    Ok((routed, resolved_version))
+}
+
+/// Runs a request end to end: limits, flags, secrets, execution and audit.
+/// Shared by the HTTP and gRPC endpoints.
+#[instrument(skip(state), fields(
+    module_path = %req.module_path,
+    function = %req.function_name,
+    tenant_id = req.tenant_id.as_deref(),
+    caller = req.caller.as_ref().map(|caller| caller.subject.as_str()),
+    execution_id = tracing::field::Empty,
+    resolved_version = tracing::field::Empty,
+    fuel_consumed = tracing::field::Empty,
+    error_code = tracing::field::Empty,
+    otel.status_code = tracing::field::Empty,
+))]
+async fn execute(state: &ServiceState, mut req: ExecuteRequest) -> ExecuteResponse {
+    let execution_id = uuid::Uuid::new_v4().to_string();
+    tracing::Span::current().record("execution_id", execution_id.as_str());
+    let (routed, resolved_version) = match resolve_module(state, &mut req).await {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            counter!("plugin_execution_failures_total", "reason" => "unresolved_version").increment(1);
+            warn!("Plugin version not resolved: {:#}", e);
+            let error = ExecuteError::new(errors::classify(&e), format!("{:#}", e));
+            let response = ExecuteResponse { execution_id, ..ExecuteResponse::failed(error) };
+            record_outcome(state, &req, &response);
+            return response;
+        }
+    };
+    // Plugins that declare a cache TTL answer repeated calls from the
+    // cache, without a quota or plan pool slot; see `results`. A module
+    // that doesn't load fails below, where the load is retried
+    if req.compiled.is_none() {
+        req.compiled = compiled::load(state, req.tenant_id.as_deref(), &req.module_path).await.ok();
+    }
+    let cache_key = req.compiled.as_ref().and_then(|compiled| state.results.key(compiled, &req));
+    if let Some(key) = &cache_key
+        && let Some(result) = state.results.get(key).await
+    {
+        let response = ExecuteResponse { execution_id, resolved_version, ..ExecuteResponse::cached(result) };
+        record_outcome(state, &req, &response);
+        if let Some(routed) = &routed {
+            routed.record(&response);
+        }
+        return response;
+    }
+    // A plugin that keeps failing fails fast, without a quota or plan pool
+    // slot; see `breakers`
+    let admitted = match &req.compiled {
+        Some(compiled) => match state.breakers.admit(&state.config(), &compiled.sha256, req.tenant_id.as_deref()) {
+            Ok(admitted) => Some(admitted),
+            Err(error) => {
+                counter!("plugin_execution_failures_total", "reason" => "breaker_open").increment(1);
+                let response = ExecuteResponse { execution_id, resolved_version, ..ExecuteResponse::failed(error) };
+                record_outcome(state, &req, &response);
+                return response;
+            }
+        },
+        None => None,
+    };
+    // The tenant's own quota first, then a slot from its plan pool, queued
+    // for while the pools are full; both are held until the call finishes.
+    // Plugins called by others run on their root execution's
+    let quota = match req.nesting {
+        Some(_) => None,
+        None => match state.quotas.admit(req.tenant_id.as_deref()).await {
+            Ok(quota) => Some(quota),
+            Err(exceeded) => {
+                counter!("plugin_execution_failures_total", "reason" => "tenant_quota").increment(1);
+                let error = ExecuteError::new(ErrorCode::QuotaExceeded, exceeded.to_string());
+                let response = ExecuteResponse { execution_id, ..ExecuteResponse::throttled(error) };
+                record_outcome(state, &req, &response);
+                return response;
+            }
+        },
+    };
+    let tier = state.plans.tier(req.tenant_id.as_deref()).await;
+    let permit = match req.nesting {
+        Some(_) => None,
+        None => match state.pools.acquire(tier, req.tenant_id.as_deref()).await {
+            Ok(permit) => Some(permit),
+            Err(unavailable) => {
+                counter!("plugin_execution_failures_total", "reason" => "instance_limit", "tier" => tier.name()).increment(1);
+                let error = ExecuteError::new(ErrorCode::CapacityExceeded, unavailable.to_string());
+                let response = ExecuteResponse { execution_id, ..ExecuteResponse::throttled(error) };
+                record_outcome(state, &req, &response);
+                return response;
+            }
+        },
+    };
+    if let Some(started) = req.started.take() {
+        let _ = started.send(());
+    }
+    let config = state.config();
+    let mut limits = state.quotas.limits(req.tenant_id.as_deref());
+    if let Some(fuel_limit) = req.fuel_limit {
+        limits.fuel_limit = limits.fuel_limit.min(fuel_limit);
+    }
+    // Cancellable from here on, and interrupted if this future is dropped
+    let running = state.executions.start(&execution_id, &req, tier, limits.fuel_limit);
+    info!("Plugin execution started");
+    let mut execution_timeout = Duration::from_secs(
+        req.timeout_seconds.unwrap_or(30)
+    ).min(Duration::from_secs(config.max_timeout_secs));
+    if let Some(nesting) = &req.nesting {
+        execution_timeout = execution_timeout.min(nesting.deadline.saturating_duration_since(Instant::now()));
+    }
+    let wasi_enabled = match &req.tenant_id {
+        Some(tenant_id) => state.flags.is_enabled(tenant_id, WASI_FLAG, true).await,
+        None => true,
+    };
+    // Outside the timeout so output written before it is still returned
+    let output = Output::new(config.max_output_bytes);
+    let plugin_logs = PluginLogs::new(if req.include_logs { config.max_plugin_logs } else { 0 });
+    let profile = ProfileReport::default();
+    let coredump = Coredump::default();
+    let deadline = Instant::now() + execution_timeout;
+    // Shared with the blocking thread the plugin runs on
+    let req = Arc::new(req);
+    let result = timeout(execution_timeout + DEADLINE_GRACE, async {
+        let mut env = environment::request_env(&config, &req, wasi_enabled)?;
+        env.extend(plugin_secrets(&state.secrets, &req, wasi_enabled).await?);
+        let compiled = match &req.compiled {
+            Some(compiled) => compiled.clone(),
+            None => compiled::load(state, req.tenant_id.as_deref(), &req.module_path).await?,
+        };
+        if compiled.profile > limits.max_security_profile {
+            let error = anyhow::anyhow!(
+                "Module needs the {} security profile; at most {} is allowed",
+                compiled.profile.name(),
+                limits.max_security_profile.name()
+            );
+            return Err(errors::coded(ErrorCode::NotPermitted, error));
+        }
+        let missing = compiled.capabilities.missing_from(limits.capabilities);
+        if !missing.is_empty() {
+            let error = anyhow::anyhow!("Module needs capabilities the tenant isn't granted: {}", missing);
+            return Err(errors::coded(ErrorCode::NotPermitted, error));
+        }
+        let limits = compiled.profile.limits(limits, &config);
+        let grant = config.wasi_grants.get(&req.module_path).filter(|_| wasi_enabled);
+        let space = grant.and_then(|grant| grant.scratch.as_ref()).filter(|_| compiled.capabilities.has(Capability::FsTmp));
+        let scratch = match space {
+            Some(space) => Some(Scratch::create(
+                &scratch::scratch_dir(&config),
+                space,
+                req.tenant_id.as_deref(),
+                &execution_id,
+            )?),
+            None => None,
+        };
+        let grant = grant.map(|grant| scratch.as_ref().map_or_else(|| grant.clone(), |scratch| scratch.grant(grant)));
+        let profiler = if req.profile {
+            let interval = Duration::from_millis(config.epoch_interval_ms);
+            Some(Profiler::start(&compiled, &req.module_path, interval, &profile)?)
+        } else {
+            None
+        };
+        let seed = req.seed.filter(|_| compiled.deterministic);
+        let mut warm = tracing::info_span!("plugin.instantiate", sha256 = %compiled.sha256)
+            .in_scope(|| state.warm.checkout(&compiled, wasi_enabled, &env, grant.as_ref(), seed, &limits))?;
+        output.attach(&warm.store.data().stdio, req.logs.as_ref());
+        warm.store.data_mut().call = Some(Call {
+            module_path: req.module_path.clone(),
+            tenant_id: req.tenant_id.clone(),
+            execution_id: execution_id.clone(),
+            deadline,
+            progress: running.progress(),
+            http: state.http_fetch.clone(),
+            kv: state.kv.clone(),
+            settings: state.settings.clone(),
+            logs: plugin_logs.clone(),
+            crm: state.crm.clone(),
+            crm_rows_read: 0,
+            scratch,
+            profiler,
+            coredump: coredump.clone(),
+            plugins: state.plugin_calls.clone(),
+            stack: req.nesting.iter().flat_map(|nesting| nesting.stack.iter()).chain([&req.module_path]).cloned().collect(),
+        });
+        execute_plugin_safe(req.clone(), warm, limits.fuel_limit).await
+    }).await;
+    drop(running);
+    drop(permit);
+    drop(quota);
+    let mut response = match result {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) if epoch::is_cancelled(&e) => {
+            counter!("plugin_execution_failures_total", "reason" => "cancelled").increment(1);
+            warn!("Plugin execution cancelled");
+            ExecuteResponse {
+                execution_id: String::new(),
+                success: false,
+                result: None,
+                error: Some(ExecuteError::new(ErrorCode::Cancelled, "Execution cancelled")),
+                execution_time_ms: execution_timeout.saturating_sub(deadline.saturating_duration_since(Instant::now())).as_millis() as u64,
+                memory_used_bytes: 0,
+                fuel_consumed: 0,
+                stdout: String::new(),
+                stderr: String::new(),
+                output_truncated: false,
+                logs: Vec::new(),
+                profile: None,
+                trap: None,
+                coredump: None,
+                resolved_version: None,
+                throttled: false,
+                cached: false,
+            }
+        }
+        Ok(Err(e)) if scratch::is_quota_exceeded(&e) => {
+            counter!("plugin_execution_failures_total", "reason" => "scratch_quota").increment(1);
+            warn!("Plugin stopped: {}", e.root_cause());
+            ExecuteResponse {
+                execution_id: String::new(),
+                success: false,
+                result: None,
+                error: Some(ExecuteError::new(ErrorCode::LimitExceeded, format!("Execution stopped: {}", e.root_cause()))),
+                execution_time_ms: execution_timeout.saturating_sub(deadline.saturating_duration_since(Instant::now())).as_millis() as u64,
+                memory_used_bytes: 0,
+                fuel_consumed: 0,
+                stdout: String::new(),
+                stderr: String::new(),
+                output_truncated: false,
+                logs: Vec::new(),
+                profile: None,
+                trap: None,
+                coredump: None,
+                resolved_version: None,
+                throttled: false,
+                cached: false,
+            }
+        }
+        Ok(Err(e)) if epoch::is_interrupt(&e) => {
+            counter!("plugin_execution_failures_total", "reason" => "deadline").increment(1);
+            warn!("Plugin execution interrupted at its deadline");
+            ExecuteResponse {
+                execution_id: String::new(),
+                success: false,
+                result: None,
+                error: Some(ExecuteError::new(ErrorCode::Timeout, "Execution interrupted: deadline exceeded")),
+                execution_time_ms: execution_timeout.as_millis() as u64,
+                memory_used_bytes: 0,
+                fuel_consumed: 0,
+                stdout: String::new(),
+                stderr: String::new(),
+                output_truncated: false,
+                logs: Vec::new(),
+                profile: None,
+                trap: traps::describe(&e),
+                coredump: None,
+                resolved_version: None,
+                throttled: false,
+                cached: false,
+            }
         }
         Ok(Err(e)) => {
-            counter!("plugin_execution_failures_total", "reason" => "execution_error");
+            counter!("plugin_execution_failures_total", "reason" => "execution_error").increment(1);
             error!("Plugin execution failed: {}", e);
-            Ok(warp::reply::json(&ExecuteResponse {
+            ExecuteResponse {
+                execution_id: String::new(),
                 success: false,
                 result: None,
-                error: Some(format!("Execution error: {}", e)),
+                error: Some(ExecuteError::new(errors::classify(&e), format!("Execution error: {}", e))),
                 execution_time_ms: 0,
                 memory_used_bytes: 0,
                 fuel_consumed: 0,
-            }))
+                stdout: String::new(),
+                stderr: String::new(),
+                output_truncated: false,
+                logs: Vec::new(),
+                profile: None,
+                trap: traps::describe(&e),
+                coredump: None,
+                resolved_version: None,
+                throttled: false,
+                cached: false,
+            }
         }
         Err(_) => {
-            counter!("plugin_execution_failures_total", "reason" => "timeout");
+            counter!("plugin_execution_failures_total", "reason" => "timeout").increment(1);
             warn!("Plugin execution timed out");
-            Ok(warp::reply::json(&ExecuteResponse {
+            ExecuteResponse {
+                execution_id: String::new(),
                 success: false,
                 result: None,
-                error: Some("Execution timed out".to_string()),
+                error: Some(ExecuteError::new(ErrorCode::Timeout, "Execution timed out")),
                 execution_time_ms: execution_timeout.as_millis() as u64,
                 memory_used_bytes: 0,
                 fuel_consumed: 0,
-            }))
+                stdout: String::new(),
+                stderr: String::new(),
+                output_truncated: false,
+                logs: Vec::new(),
+                profile: None,
+                trap: None,
+                coredump: None,
+                resolved_version: None,
+                throttled: false,
+                cached: false,
+            }
         }
+    };
+    let captured = output.captured();
+    response.stdout = captured.stdout;
+    response.stderr = captured.stderr;
+    response.output_truncated = captured.truncated;
+    response.logs = plugin_logs.take();
+    response.profile = profile.take();
+    response.coredump = coredump.take();
+    response.resolved_version = resolved_version;
+    response.execution_id = execution_id;
+    record_outcome(state, &req, &response);
+    state.plugin_metrics.ran(&req.module_path, req.tenant_id.as_deref(), &response);
+    if let (Some(admitted), Some(compiled)) = (admitted, &req.compiled) {
+        state.breakers.record(&config, &req.module_path, &compiled.sha256, req.tenant_id.as_deref(), admitted, &response);
     }
+    if let Some(routed) = &routed {
+        routed.record(&response);
+    }
+    if let (Some(key), Some(result), true) = (cache_key, &response.result, response.success) {
+        let (results, result) = (state.results.clone(), result.clone());
+        tokio::spawn(async move { results.put(&key, &result).await });
+    }
+    // Only tenant invocations are billable, plugins called by others as
+    // part of their root execution
+    if let (Some(tenant_id), None) = (&req.tenant_id, &req.nesting) {
+        let bytes_out = response.result.as_ref().map_or(0, |result| result.to_string().len())
+            + response.stdout.len()
+            + response.stderr.len();
+        state.audit.record(AuditRecord {
+            execution_id: response.execution_id.clone(),
+            tenant_id: tenant_id.clone(),
+            module_path: req.module_path.clone(),
+            function_name: req.function_name.clone(),
+            success: response.success,
+            fuel_consumed: response.fuel_consumed,
+            execution_time_ms: response.execution_time_ms,
+            memory_peak_bytes: response.memory_used_bytes,
+            bytes_in: req.params.to_string().len() as u64,
+            bytes_out: bytes_out as u64,
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or_default(),
+        });
+    }
+    response
 }
 
-async fn execute_plugin_safe(
-    engine: &Engine,
+/// Counts an execution and tags its span with how it ended, for traces to
+/// show failed executions and why.
+fn record_outcome(state: &ServiceState, req: &ExecuteRequest, response: &ExecuteResponse) {
+    state.plugin_metrics.executed(&req.module_path, req.tenant_id.as_deref(), response);
+    let span = tracing::Span::current();
+    span.record("fuel_consumed", response.fuel_consumed);
+    if let Some(resolved_version) = &response.resolved_version {
+        span.record("resolved_version", resolved_version.as_str());
+    }
+    if let Some(error) = &response.error {
+        span.record("error_code", error.code.name());
+        span.record("otel.status_code", "ERROR");
+    }
+}
+
+/// Fetches the secrets a request asks for. Reads go through the store's
+/// cache, so rotated values reach plugins once the cached copy expires.
+async fn plugin_secrets(
+    secrets: &SecretStore,
     req: &ExecuteRequest,
-    config: &RuntimeConfig
+    wasi_enabled: bool,
+) -> Result<Vec<(String, String)>> {
+    if req.secrets.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(tenant_id) = &req.tenant_id else {
+        anyhow::bail!("Plugin secrets require a tenant_id");
+    };
+    if !wasi_enabled {
+        anyhow::bail!("Plugin secrets require WASI");
+    }
+    if !valid_path_segment(tenant_id) {
+        anyhow::bail!("Invalid tenant_id");
+    }
+    let mut env = Vec::with_capacity(req.secrets.len());
+    for name in &req.secrets {
+        if !valid_path_segment(name) {
+            anyhow::bail!("Invalid secret name: {}", name);
+        }
+        let value = secrets
+            .get(&format!("{}/{}/{}", PLUGIN_SECRETS_PREFIX, tenant_id, name))
+            .await
+            .with_context(|| format!("Failed to load plugin secret {}", name))?;
+        env.push((name.clone(), value));
+    }
+    Ok(env)
+}
+
+/// The linker core modules with `profile` and `capabilities` are
+/// instantiated with; WASI is linked only when the tenant has it enabled.
+/// `wasi_snapshot_preview1` is served by the Preview 2 context through the
+/// adapter.
+fn new_linker(engine: &Engine, wasi_enabled: bool, profile: Profile, capabilities: Capabilities) -> Result<Linker<Host>> {
+    let mut linker: Linker<Host> = Linker::new(engine);
+    if wasi_enabled {
+        wasmtime_wasi::preview2::preview1::add_to_linker_sync(&mut linker)?;
+    }
+    host::add_to_linker(&mut linker, profile, capabilities)?;
+    Ok(linker)
+}
+
+/// The linker components are instantiated with: the `wasi:cli/command`
+/// world when the tenant has WASI enabled, nothing otherwise. `wasi:http`
+/// isn't linked, so components importing it don't instantiate.
+fn new_component_linker(engine: &Engine, wasi_enabled: bool) -> Result<component::Linker<Host>> {
+    let mut linker = component::Linker::new(engine);
+    if wasi_enabled {
+        wasmtime_wasi::preview2::command::sync::add_to_linker(&mut linker)?;
+    }
+    Ok(linker)
+}
+
+/// A store held to `limits`, whose WASI context only has the features
+/// `capabilities` include, virtualized with `seed` for deterministic
+/// plugins.
+fn new_store(
+    engine: &Engine,
+    limits: &Limits,
+    env: &[(String, String)],
+    grant: Option<&WasiGrant>,
+    capabilities: Capabilities,
+    seed: Option<u64>,
+) -> Result<Store<Host>> {
+    // Create restricted WASI context: no file system or network access
+    // unless granted, and stdio only once an execution captures it
+    let stdio = Stdio::default();
+    let mut builder = WasiCtxBuilder::new();
+    builder.envs(env).stdout(stdio.stdout.clone()).stderr(stdio.stderr.clone());
+    if let Some(grant) = grant {
+        grant.apply(&mut builder)?;
+    }
+    capabilities::restrict(capabilities, &mut builder);
+    if let Some(seed) = seed {
+        determinism::apply(seed, capabilities, &mut builder);
+    }
+    let mut store = Store::new(engine, Host::new(builder.build(), stdio, Limiter::new(limits)));
+    store.limiter(|host| &mut host.limiter);
+    // Checked every tick for the execution's deadline and cancellation
+    store.epoch_deadline_callback(epoch::on_tick);
+    store.set_epoch_deadline(1);
+    apply_limits(&mut store, limits)?;
+    Ok(store)
+}
+
+/// Sets the store's fuel and memory limits, replacing any earlier ones.
+fn apply_limits(store: &mut Store<Host>, limits: &Limits) -> Result<()> {
+    // Set resource limits - fuel is enabled in engine config
+    store.set_fuel(limits.fuel_limit)?;
+    store.data_mut().limiter.set_limits(limits);
+    Ok(())
+}
+
+async fn execute_plugin_safe(
+    req: Arc<ExecuteRequest>,
+    warm: Warm,
+    fuel_limit: u64,
 ) -> Result<ExecuteResponse> {
     let start = Instant::now();
-    // Use a configurable base directory (default to server working dir)
-    let base_dir = std::env::var("WASM_MODULE_DIR")
-        .unwrap_or_else(|_| "/Users/karassayraushanbek/Documents/work/multi-saas-crm/extension-runtime-service".to_string());
-    // Resolve module path
-    let module_path = Path::new(&base_dir).join(&req.module_path).canonicalize()
-        .with_context(|| format!("Invalid module path: {}", req.module_path))?;
-    // Prevent directory traversal (redundant with canonicalize, but extra safety)
-    if module_path.to_str().unwrap().contains("..") {
-        anyhow::bail!("Directory traversal detected");
-    }
-    // Load and validate module
-    let module_bytes = std::fs::read(&module_path)
-        .with_context(|| format!("Failed to read WASM module at {}", module_path.display()))?;
-    if module_bytes.len() > 10 * 1024 * 1024 { // 10MB limit
-        anyhow::bail!("Module too large");
-    }
-    let module = Module::from_binary(engine, &module_bytes)
-        .context("Failed to parse WASM module")?;
-    // Validate module exports/imports
-    validate_module_safety(&module)?;
-    // Set up secure linker
-    let mut linker: Linker<WasiCtx> = Linker::new(engine);
-    wasmtime_wasi::add_to_linker(&mut linker, |s| s)?;
-    // Create restricted WASI context
-    let wasi_ctx = WasiCtxBuilder::new()
-        .inherit_stdio() // Only allow stdio, no file system access
-        .build();
-    let mut store = Store::new(engine, wasi_ctx);
-    // Set resource limits - fuel is enabled in engine config
-    store.set_fuel(config.fuel_limit)?;
-    store.set_epoch_deadline(1);
-    // Configure memory limits
-    let memory_limit = config.max_memory_pages as usize * 65536;
-    let table_limit = config.max_table_elements as usize;
-    store.limiter(move |_| {
-        Box::leak(Box::new(ResourceLimiter {
-            memory_limit,
-            table_limit,
-        }))
-    });
-    let instance = linker
-        .instantiate(&mut store, &module)
-        .context("Failed to instantiate module")?;
-    // Get and validate function
-    let func = instance
-        .get_func(&mut store, &req.function_name)
-        .context("Function not found")?;
-    let func_type = func.ty(&store);
-    let param_types: Vec<ValType> = func_type.params().collect();
-    let result_types: Vec<ValType> = func_type.results().collect();
-    // Measure initial memory
-    let memory = instance.get_memory(&mut store, "memory");
-    let initial_memory = if let Some(mem) = memory {
-        mem.size(&store) as u64 * 65536
-    } else {
-        0
-    };
-    // Execute function with parameter validation
-    let result = execute_function_with_params(&mut store, func, &param_types, &result_types, &req.params)
-        .context("Function execution failed")?;
+    // Injected traps fail the call the same way a trapping plugin does
+    crm_chaos::inject(crm_chaos::WASM).await.context("Function execution failed")?;
+    // Plugins run on the blocking pool rather than a runtime worker, so a
+    // CPU-heavy one can't hold up the server; the store comes back with
+    // the result. Calls past the execution timeout still end at their
+    // epoch deadline.
+    let call = req.clone();
+    let span = tracing::info_span!("plugin.call");
+    let (result, store) = tokio::task::spawn_blocking(move || {
+        let _span = span.enter();
+        let Warm { mut store, instance } = warm;
+        profiling::sample(store.as_context_mut());
+        let result = match instance {
+            warm::Instance::Module(instance) => call_module(&call, &mut store, instance),
+            warm::Instance::Component(instance) => call_component(&call, &mut store, instance),
+        };
+        profiling::sample(store.as_context_mut());
+        profiling::finish(&mut store);
+        if let Err(e) = &result {
+            traps::capture(&mut store, e);
+        }
+        (result, store)
+    })
+    .await
+    .context("Function execution failed")?;
+    let result = result?;
     let execution_time = start.elapsed().as_millis() as u64;
-    let fuel_consumed = config.fuel_limit - store.get_fuel().unwrap_or(0);
-    // Measure final memory
-    let final_memory = if let Some(mem) = memory {
-        mem.size(&store) as u64 * 65536
-    } else {
-        0
-    };
+    let fuel_consumed = fuel_limit - store.get_fuel().unwrap_or(0);
+    // Instantiation's memory included, which for a warm instance happened
+    // before the call
+    let limiter = &store.data().limiter;
+    let memory_used = limiter.peak_memory_bytes();
     info!(
-        "Plugin executed successfully: function={}, time={}ms, fuel={}, memory_delta={}",
-        req.function_name, execution_time, fuel_consumed, final_memory - initial_memory
+        "Plugin executed successfully: function={}, time={}ms, fuel={}, peak_memory={}, peak_table_elements={}",
+        req.function_name, execution_time, fuel_consumed, memory_used, limiter.peak_table_elements()
     );
     Ok(ExecuteResponse {
+        execution_id: String::new(),
         success: true,
         result: Some(result),
         error: None,
         execution_time_ms: execution_time,
-        memory_used_bytes: final_memory - initial_memory,
+        memory_used_bytes: memory_used,
         fuel_consumed,
+        stdout: String::new(),
+        stderr: String::new(),
+        output_truncated: false,
+        logs: Vec::new(),
+        profile: None,
+        trap: None,
+        coredump: None,
+        resolved_version: None,
+        throttled: false,
+        cached: false,
     })
 }
 
-fn validate_module_safety(module: &Module) -> Result<()> {
+/// Calls a core module's export.
+fn call_module(req: &ExecuteRequest, store: &mut Store<Host>, instance: Instance) -> Result<serde_json::Value> {
+    // Get and validate function
+    let func = instance
+        .get_func(&mut *store, &req.function_name)
+        .ok_or_else(|| errors::coded(ErrorCode::FunctionNotFound, anyhow::anyhow!("Function not found")))?;
+    let func_type = func.ty(&*store);
+    let param_types: Vec<ValType> = func_type.params().collect();
+    let result_types: Vec<ValType> = func_type.results().collect();
+    // Execute function with parameter validation
+    execute_function_with_params(store, &instance, func, &param_types, &result_types, req)
+        .context("Function execution failed")
+}
+
+/// Calls a component's export: a top-level function by name, or one in an
+/// exported interface as `interface#function`, e.g.
+/// `wasi:cli/run@0.2.0-rc-2023-11-10#run`.
+fn call_component(
+    req: &ExecuteRequest,
+    store: &mut Store<Host>,
+    instance: component::Instance,
+) -> Result<serde_json::Value> {
+    let func = match req.function_name.rsplit_once('#') {
+        Some((interface, name)) => instance.exports(&mut *store).instance(interface).and_then(|mut exports| exports.func(name)),
+        None => instance.get_func(&mut *store, &req.function_name),
+    }
+    .ok_or_else(|| errors::coded(ErrorCode::FunctionNotFound, anyhow::anyhow!("Function not found")))?;
+    let params = json_to_component_params(&req.params, &func.params(&*store))
+        .map_err(|e| errors::coded(ErrorCode::ParamMismatch, e))?;
+    let mut results = vec![component::Val::Bool(false); func.results(&*store).len()];
+    func.call(&mut *store, &params, &mut results)
+        .and_then(|()| func.post_return(&mut *store))
+        .context("Function execution failed")?;
+    component_results_to_json(&results)
+}
+
+fn validate_module_safety(module: &Module, capabilities: Capabilities) -> Result<()> {
+    match import_violations(module, capabilities).into_iter().next() {
+        Some(violation) => Err(anyhow::anyhow!(violation)),
+        None => Ok(()),
+    }
+}
+
+/// Every import plugins with `capabilities` may not have.
+fn import_violations(module: &Module, capabilities: Capabilities) -> Vec<String> {
     // Check for suspicious imports
+    let mut violations = Vec::new();
     for import in module.imports() {
         match import.module() {
             "wasi_snapshot_preview1" => continue, // Allow WASI
+            HOST_MODULE => {
+                if !host::FUNCTIONS.contains(&import.name()) {
+                    violations.push(format!("Unknown host function: {}.{}", HOST_MODULE, import.name()));
+                } else if let Some(needed) = host::capability(import.name()).filter(|needed| !capabilities.has(*needed)) {
+                    violations.push(format!(
+                        "Host function {}.{} needs the {} capability, which the manifest doesn't ask for",
+                        HOST_MODULE,
+                        import.name(),
+                        needed.name()
+                    ));
+                }
+            }
             "env" => {
                 // Allow only safe env imports
                 match import.name() {
                     "memory" | "table" => continue,
-                    _ => anyhow::bail!("Unsafe import: env.{}", import.name()),
+                    _ => violations.push(format!("Unsafe import: env.{}", import.name())),
                 }
             }
-            _ => anyhow::bail!("Unauthorized import module: {}", import.module()),
+            _ => violations.push(format!("Unauthorized import module: {}", import.module())),
         }
     }
-    Ok(())
+    violations
 }
 
 fn execute_function_with_params(
-    store: &mut Store<WasiCtx>,
+    store: &mut Store<Host>,
+    instance: &Instance,
     func: Func,
     param_types: &[ValType],
     result_types: &[ValType],
-    params: &serde_json::Value,
+    req: &ExecuteRequest,
 ) -> Result<serde_json::Value> {
-    // Convert JSON params to WASM values
-    let param_values = json_to_wasm_params(params, param_types)?;
+    if req.calling_convention == CallingConvention::Json {
+        return call_json(store, instance, func, &req.params);
+    }
+    // Convert JSON params to WASM values, strings and bytes copied into the
+    // plugin's memory
+    let args = json_to_wasm_args(&req.params, param_types).map_err(|e| errors::coded(ErrorCode::ParamMismatch, e))?;
+    let param_values = lower_args(&mut *store, instance, args)?;
     // Execute function
     let mut results = vec![Val::I32(0); result_types.len()];
-    func.call(store, &param_values, &mut results)?;
+    func.call(&mut *store, &param_values, &mut results)?;
     // Convert results back to JSON
-    wasm_results_to_json(&results)
-}
-
-fn json_to_wasm_params(json: &serde_json::Value, param_types: &[ValType]) -> Result<Vec<Val>> {
-    let params_array = match json {
-        serde_json::Value::Array(arr) => arr,
-        _ => anyhow::bail!("Parameters must be an array"),
-    };
-    if params_array.len() != param_types.len() {
-        anyhow::bail!("Parameter count mismatch");
-    }
-    let mut wasm_params = Vec::new();
-    for (json_param, wasm_type) in params_array.iter().zip(param_types.iter()) {
-        let wasm_val = match (json_param, wasm_type) {
-            (serde_json::Value::Number(n), ValType::I32) => {
-                Val::I32(n.as_i64().context("Invalid i32")? as i32)
-            }
-            (serde_json::Value::Number(n), ValType::I64) => {
-                Val::I64(n.as_i64().context("Invalid i64")?)
-            }
-            (serde_json::Value::Number(n), ValType::F32) => {
-                Val::F32((n.as_f64().context("Invalid f32")? as f32).to_bits())
-            }
-            (serde_json::Value::Number(n), ValType::F64) => {
-                Val::F64(n.as_f64().context("Invalid f64")?.to_bits())
-            }
-            _ => anyhow::bail!("Unsupported parameter type combination"),
-        };
-        wasm_params.push(wasm_val);
-    }
-    Ok(wasm_params)
+    lift_results(store, instance, &results, req.result_encoding)
 }
-
-fn wasm_results_to_json(results: &[Val]) -> Result<serde_json::Value> {
-    if results.len() == 1 {
-        // Single result
-        Ok(wasm_val_to_json(&results[0])?)
-    } else {
-        // Multiple results as array
-        let json_results: Result<Vec<_>> = results.iter().map(wasm_val_to_json).collect();
-        Ok(serde_json::Value::Array(json_results?))
-    }
-}
-
-fn wasm_val_to_json(val: &Val) -> Result<serde_json::Value> {
-    match val {
-        Val::I32(i) => Ok(serde_json::Value::Number((*i).into())),
-        Val::I64(i) => Ok(serde_json::Value::Number((*i).into())),
-        Val::F32(f) => Ok(serde_json::Value::Number(
-            serde_json::Number::from_f64(f32::from_bits(*f) as f64)
-                .context("Invalid f32")?
-        )),
-        Val::F64(f) => Ok(serde_json::Value::Number(
-            serde_json::Number::from_f64(f64::from_bits(*f))
-                .context("Invalid f64")?
-        )),
-        _ => anyhow::bail!("Unsupported result type"),
-    }
-}
-
-struct ResourceLimiter {
-    memory_limit: usize,
-    table_limit: usize,
-}
-
-impl wasmtime::ResourceLimiter for ResourceLimiter {
-    fn memory_growing(&mut self, _current: usize, desired: usize, _maximum: Option<usize>) -> anyhow::Result<bool> {
-        Ok(desired <= self.memory_limit)
-    }
-
-    fn table_growing(&mut self, _current: u32, desired: u32, _maximum: Option<u32>) -> anyhow::Result<bool> {
-        Ok(desired <= self.table_limit as u32)
-    }
-}
\ No newline at end of file
@@ -0,0 +1,71 @@
+//! Plugin manifests: what a plugin declares about itself, as a JSON object
+//! in a `manifest` custom section of the module, e.g. in the text format:
+//!
+//! ```wat
+//! (@custom "manifest" "{\"profile\": \"standard\"}")
+//! ```
+//!
+//! Embedded, a manifest goes wherever its module is stored and is covered by
+//! the module's signature. Modules without one get the defaults.
+//!
+//! - `profile`: the security profile to run with; see `profiles`.
+//! - `capabilities`: what the plugin needs besides compute, e.g.
+//!   `["kv", "clock"]`; see `capabilities`.
+//! - `deterministic`: whether runs must be reproducible; see
+//!   `determinism`.
+//! - `cache_ttl_secs`: how long the plugin's results may be reused for
+//!   calls with the same params; see `results`.
+//!
+//! Plugins published to the marketplace also declare a `name`, `version`
+//! and `entrypoints`, which the runtime ignores; see `manifests`.
+
+use crate::capabilities::Capabilities;
+use crate::profiles::Profile;
+use crate::signing::leb128;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Name of the custom section manifests are in.
+pub const MANIFEST_SECTION: &str = "manifest";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginManifest {
+    pub profile: Profile,
+    /// Unset gets the runtime's `default_capabilities`.
+    pub capabilities: Option<Capabilities>,
+    pub deterministic: bool,
+    /// Unset doesn't cache results.
+    pub cache_ttl_secs: Option<u64>,
+}
+
+impl PluginManifest {
+    /// The manifest of `bytes`, a core module or component.
+    pub fn from_module(bytes: &[u8]) -> Result<Self> {
+        match custom_section(bytes, MANIFEST_SECTION) {
+            Some(contents) => serde_json::from_slice(contents).context("Invalid plugin manifest"),
+            None => Ok(PluginManifest::default()),
+        }
+    }
+}
+
+/// The contents of the first custom section named `name`.
+fn custom_section<'a>(bytes: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    // Sections follow the 8-byte preamble: an id, a LEB128 size and the
+    // contents, which for custom sections (id 0) start with their name
+    let mut offset = 8;
+    while offset < bytes.len() {
+        let id = bytes[offset];
+        let (size, read) = leb128(&bytes[offset + 1..])?;
+        let contents = bytes.get(offset + 1 + read..(offset + 1 + read).checked_add(size)?)?;
+        offset += 1 + read + size;
+        if id != 0 {
+            continue;
+        }
+        let (name_len, read) = leb128(contents)?;
+        if contents.get(read..read + name_len)? == name.as_bytes() {
+            return Some(&contents[read + name_len..]);
+        }
+    }
+    None
+}
@@ -0,0 +1,217 @@
+//! Manifest validation for the marketplace publishing flow, so a plugin
+//! author learns everything wrong with a listing in one round trip.
+//!
+//! `POST /manifests/validate` takes the manifest to publish and,
+//! optionally, the module it describes, base64-encoded:
+//!
+//! ```json
+//! {"manifest": {"name": "invoice-export", "version": "1.2.0",
+//!               "capabilities": ["kv"],
+//!               "entrypoints": [{"name": "run", "input_schema": {"type": "object"}}]},
+//!  "module": "AGFzbQEAAAA…"}
+//! ```
+//!
+//! Marketplace manifests are runtime manifests (see `manifest`) with a
+//! `name`, `version`, `description` and `entrypoints` on top. The checks:
+//!
+//! - `schema`: the manifest satisfies `plugin-manifest.schema.json`.
+//! - `name`: 3 to 64 lowercase letters, digits and inner dashes.
+//! - `version`: a semantic version.
+//! - `entrypoints`: at least one, each named once and, given a core
+//!   module, exported by it as a function. Components' exports can't be
+//!   listed, so theirs aren't cross-checked.
+//! - `schemas`: entrypoints' `input_schema` and `output_schema` are in the
+//!   JSON Schema subset contracts are written in; see `crm_contracts`.
+//! - `module`: the module decodes and parses.
+//! - `imports`: the module only imports host functions the manifest's
+//!   capabilities cover.
+//! - `embedded`: a manifest embedded in the module asks for the same
+//!   profile, capabilities, determinism and cache TTL, since that's the
+//!   one the runtime goes by.
+//!
+//! Every problem names the check and the path of the offending value, `$`
+//! being the manifest itself; 200 when there are none, 422 otherwise.
+
+use crate::compiled::is_component;
+use crate::manifest::PluginManifest;
+use crate::{import_violations, ServiceState, MAX_MODULE_BYTES};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::sync::{Arc, OnceLock};
+use warp::http::StatusCode;
+use warp::Filter;
+use wasmtime::{ExternType, Module};
+
+const MANIFEST_SCHEMA: &str = include_str!("../plugin-manifest.schema.json");
+
+fn manifest_schema() -> &'static Value {
+    static SCHEMA: OnceLock<Value> = OnceLock::new();
+    SCHEMA.get_or_init(|| serde_json::from_str(MANIFEST_SCHEMA).expect("plugin-manifest.schema.json is valid JSON"))
+}
+
+#[derive(Debug, Deserialize)]
+struct ValidateRequest {
+    manifest: Value,
+    /// The module, base64-encoded.
+    #[serde(default)]
+    module: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Problem {
+    check: &'static str,
+    path: String,
+    message: String,
+}
+
+impl Problem {
+    fn new(check: &'static str, path: impl Into<String>, message: impl Into<String>) -> Self {
+        Problem { check, path: path.into(), message: message.into() }
+    }
+
+    /// From a `crm_contracts` problem, `<path>: <message>`.
+    fn at_path(check: &'static str, prefix: &str, problem: &str) -> Self {
+        match problem.split_once(": ") {
+            Some((path, message)) => {
+                Problem::new(check, format!("{}{}", prefix, path.strip_prefix('$').unwrap_or(path)), message)
+            }
+            None => Problem::new(check, prefix, problem),
+        }
+    }
+}
+
+pub fn routes(
+    state: Arc<ServiceState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("manifests" / "validate")
+        .and(warp::post())
+        // Base64 takes 4 bytes for every 3
+        .and(warp::body::content_length_limit(MAX_MODULE_BYTES as u64 * 4 / 3 + 64 * 1024))
+        .and(warp::body::json())
+        .map(move |request: ValidateRequest| {
+            let problems = check(&state, &request);
+            let valid = problems.is_empty();
+            counter!("manifest_validations_total", "result" => if valid { "valid" } else { "invalid" }).increment(1);
+            let status = if valid { StatusCode::OK } else { StatusCode::UNPROCESSABLE_ENTITY };
+            warp::reply::with_status(warp::reply::json(&json!({"valid": valid, "problems": problems})), status)
+        })
+}
+
+fn check(state: &ServiceState, request: &ValidateRequest) -> Vec<Problem> {
+    let manifest = &request.manifest;
+    let mut problems: Vec<Problem> =
+        crm_contracts::validate(manifest_schema(), manifest).iter().map(|problem| Problem::at_path("schema", "$", problem)).collect();
+
+    if let Some(name) = manifest.get("name").and_then(Value::as_str)
+        && !valid_name(name)
+    {
+        let message = format!("{:?} must be 3 to 64 lowercase letters, digits and dashes, e.g. invoice-export", name);
+        problems.push(Problem::new("name", "$.name", message));
+    }
+    if let Some(version) = manifest.get("version").and_then(Value::as_str)
+        && let Err(e) = semver::Version::parse(version)
+    {
+        problems.push(Problem::new("version", "$.version", format!("{:?} is not a semantic version, e.g. 1.2.0: {}", version, e)));
+    }
+
+    let entrypoints = manifest.get("entrypoints").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+    if entrypoints.is_empty() && manifest.get("entrypoints").is_some() {
+        problems.push(Problem::new("entrypoints", "$.entrypoints", "List at least one entrypoint"));
+    }
+    let mut names = HashSet::new();
+    for (i, entrypoint) in entrypoints.iter().enumerate() {
+        if let Some(name) = entrypoint.get("name").and_then(Value::as_str)
+            && !names.insert(name)
+        {
+            problems.push(Problem::new("entrypoints", format!("$.entrypoints[{}].name", i), format!("Entrypoint {} is listed twice", name)));
+        }
+        for field in ["input_schema", "output_schema"] {
+            let Some(schema) = entrypoint.get(field).filter(|schema| schema.is_object()) else {
+                continue;
+            };
+            let prefix = format!("$.entrypoints[{}].{}", i, field);
+            problems.extend(crm_contracts::check_supported(schema).iter().map(|problem| Problem::at_path("schemas", &prefix, problem)));
+        }
+    }
+
+    // The schema already reported what's wrong with these fields, so the
+    // module is checked against the defaults instead
+    let declared: PluginManifest = serde_json::from_value(runtime_fields(manifest)).unwrap_or_default();
+    if let Some(module) = &request.module {
+        problems.extend(check_module(state, module, &declared, entrypoints));
+    }
+    problems
+}
+
+/// The fields of a marketplace manifest the runtime goes by.
+fn runtime_fields(manifest: &Value) -> Value {
+    let fields = ["profile", "capabilities", "deterministic", "cache_ttl_secs"]
+        .into_iter()
+        .filter_map(|field| Some((field.to_string(), manifest.get(field)?.clone())))
+        .collect();
+    Value::Object(fields)
+}
+
+fn valid_name(name: &str) -> bool {
+    (3..=64).contains(&name.len())
+        && name.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+}
+
+fn check_module(state: &ServiceState, module: &str, declared: &PluginManifest, entrypoints: &[Value]) -> Vec<Problem> {
+    let bytes = match BASE64.decode(module) {
+        Ok(bytes) => bytes,
+        Err(e) => return vec![Problem::new("module", "module", format!("Not valid base64: {}", e))],
+    };
+    if bytes.len() > MAX_MODULE_BYTES {
+        let message = format!("Module is {} bytes; at most {} are allowed", bytes.len(), MAX_MODULE_BYTES);
+        return vec![Problem::new("module", "module", message)];
+    }
+    let mut problems = Vec::new();
+    match PluginManifest::from_module(&bytes) {
+        Ok(embedded) if embedded != PluginManifest::default() && embedded != *declared => {
+            let message = format!(
+                "The module embeds a manifest asking for {}, which the runtime goes by; publish the same values",
+                serde_json::to_string(&embedded).unwrap_or_default()
+            );
+            problems.push(Problem::new("embedded", "$", message));
+        }
+        Ok(_) => {}
+        Err(e) => problems.push(Problem::new("embedded", "module", format!("{:#}", e))),
+    }
+    if is_component(&bytes) {
+        return problems;
+    }
+
+    let engine = state.compiled.engine(declared.profile, declared.deterministic);
+    let module = match Module::from_binary(engine, &bytes) {
+        Ok(module) => module,
+        Err(e) => {
+            problems.push(Problem::new("module", "module", format!("{:#}", e)));
+            return problems;
+        }
+    };
+    let capabilities = declared.capabilities.unwrap_or(state.config().default_capabilities);
+    problems.extend(import_violations(&module, capabilities).into_iter().map(|message| Problem::new("imports", "$.capabilities", message)));
+
+    let exported: Vec<&str> =
+        module.exports().filter(|export| matches!(export.ty(), ExternType::Func(_))).map(|export| export.name()).collect();
+    for (i, entrypoint) in entrypoints.iter().enumerate() {
+        let Some(name) = entrypoint.get("name").and_then(Value::as_str) else {
+            continue;
+        };
+        if !exported.contains(&name) {
+            let message = match exported.as_slice() {
+                [] => format!("The module doesn't export a function {}, or any other", name),
+                _ => format!("The module doesn't export a function {}; it exports {}", name, exported.join(", ")),
+            };
+            problems.push(Problem::new("entrypoints", format!("$.entrypoints[{}].name", i), message));
+        }
+    }
+    problems
+}
@@ -0,0 +1,333 @@
+//! The `/modules` API over the module registry, so operators can manage
+//! plugin binaries without object-store access:
+//!
+//! - `POST /modules`: multipart upload with a `module` file part and `name`
+//!   (defaults to the file name) and `version` fields. The module must
+//!   compile, pass the same import checks as at execution time and install;
+//!   see `lifecycle`.
+//! - `GET /modules`: metadata of every module.
+//! - `DELETE /modules/{id}`: removes `name@version`, after its uninstall
+//!   hooks.
+//! - `GET /modules/{id}/inspect`: the module's exports with their
+//!   signatures, imports and memories, read from the compiled module
+//!   without running it, e.g. for the plugin marketplace to show call
+//!   signatures.
+//!
+//! Calls act on one tenant's modules: uploads are its own, invisible to
+//! other tenants, listing shows its modules and the system ones, and
+//! deletions only remove its own. Or they act on system modules, which
+//! every tenant runs, and listing shows every module, with the tenant it
+//! belongs to. See `registry`. With authentication on, the caller's token
+//! decides which: its tenant's modules, or, for administrators, those of
+//! the tenant in `X-Tenant-ID` and without it the system ones; see `auth`.
+//! Otherwise `X-Tenant-ID` does, and without it calls act on the system
+//! modules.
+//!
+//! Not found while no registry is configured. Uploads and deletions are
+//! recorded on the admin audit trail.
+
+use crate::inspect::inspect;
+use crate::lifecycle::{self, Hook};
+use crate::registry::{self, module_id, ModuleRegistry};
+use crate::{compiled, ServiceState, MAX_MODULE_BYTES, TENANT_HEADER};
+use crm_audit::AuditEvent;
+use futures::TryStreamExt;
+use serde_json::json;
+use std::sync::Arc;
+use tracing::{error, warn};
+use warp::http::StatusCode;
+use warp::hyper::body::Buf;
+use warp::multipart::{FormData, Part};
+use warp::Filter;
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+pub fn routes(
+    state: Arc<ServiceState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let base = warp::path("modules")
+        .and(warp::any().map(move || state.clone()))
+        .and_then(require_registry)
+        .and(warp::header::optional::<String>(TENANT_HEADER))
+        .and(warp::header::optional::<String>("authorization"));
+
+    let upload = base
+        .clone()
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::multipart::form().max_length(MAX_MODULE_BYTES as u64 + 64 * 1024))
+        .and(crm_audit::actor())
+        .then(upload);
+    let list = base.clone().and(warp::path::end()).and(warp::get()).then(list);
+    let delete = base
+        .clone()
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(crm_audit::actor())
+        .then(delete);
+    let inspect = base
+        .and(warp::path::param::<String>())
+        .and(warp::path("inspect"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .then(inspect_module);
+
+    upload.or(list).unify().or(delete).unify().or(inspect).unify()
+}
+
+async fn require_registry(
+    state: Arc<ServiceState>,
+) -> Result<(Arc<ServiceState>, Arc<ModuleRegistry>), warp::Rejection> {
+    let registry = state.registry.clone().ok_or_else(warp::reject::not_found)?;
+    Ok((state, registry))
+}
+
+async fn upload(
+    (state, registry): (Arc<ServiceState>, Arc<ModuleRegistry>),
+    tenant_id: Option<String>,
+    authorization: Option<String>,
+    mut form: FormData,
+    actor: String,
+) -> Reply {
+    let tenant_id = match namespace(&state, tenant_id, authorization).await {
+        Ok(tenant_id) => tenant_id,
+        Err(refused) => return refused,
+    };
+    let tenant_id = tenant_id.as_deref();
+    let (mut name, mut version, mut file_name, mut bytes) = (None, String::new(), None, None);
+    loop {
+        let part = match form.try_next().await {
+            Ok(Some(part)) => part,
+            Ok(None) => break,
+            Err(e) => return reply(StatusCode::BAD_REQUEST, json!({ "error": format!("Invalid upload: {}", e) })),
+        };
+        let field = part.name().to_string();
+        if field == "module" {
+            file_name = part.filename().map(str::to_string);
+        }
+        let content = match read_part(part).await {
+            Ok(content) => content,
+            Err(e) => return reply(StatusCode::BAD_REQUEST, json!({ "error": format!("Invalid upload: {}", e) })),
+        };
+        match field.as_str() {
+            "module" => bytes = Some(content),
+            "name" => name = Some(String::from_utf8_lossy(&content).trim().to_string()),
+            "version" => version = String::from_utf8_lossy(&content).trim().to_string(),
+            _ => {}
+        }
+    }
+
+    let Some(bytes) = bytes else {
+        return reply(StatusCode::BAD_REQUEST, json!({ "error": "Missing module file part" }));
+    };
+    let Some(name) = name.or(file_name) else {
+        return reply(StatusCode::BAD_REQUEST, json!({ "error": "Missing module name" }));
+    };
+    if let Err(e) = registry::check_name(&name, &version) {
+        return reply(StatusCode::BAD_REQUEST, json!({ "error": e.to_string() }));
+    }
+    if bytes.len() > MAX_MODULE_BYTES {
+        return reply(StatusCode::PAYLOAD_TOO_LARGE, json!({ "error": "Module too large" }));
+    }
+    // Only signed modules get in, so they must carry their signature
+    if let Some(signatures) = &state.signatures
+        && let Err(e) = signatures.verify(state.modules.as_ref(), tenant_id, &module_id(&name, &version), &bytes).await
+    {
+        return reply(StatusCode::BAD_REQUEST, json!({ "error": format!("Invalid module: {:#}", e) }));
+    }
+    // Compiling also warms the compiled module cache for the first execution
+    let compiled = match state.compiled.get(&bytes).await {
+        Ok(compiled) => compiled,
+        Err(e) => return reply(StatusCode::BAD_REQUEST, json!({ "error": format!("Invalid module: {:#}", e) })),
+    };
+    // Not stored unless it installs
+    let id = module_id(&name, &version);
+    let mut hooks = Vec::new();
+    if let Some(installed) = lifecycle::run(&state, tenant_id, &id, &compiled, Hook::Install).await {
+        let error = installed.error.clone();
+        hooks.push(installed);
+        if let Some(error) = error {
+            state
+                .admin_audit
+                .record(
+                    AuditEvent::new(actor, "module.install_failed", &id)
+                        .details(json!({ "tenant_id": tenant_id, "hooks": hooks })),
+                )
+                .await;
+            let error = format!("{} failed: {}", Hook::Install.name(), error);
+            return reply(StatusCode::UNPROCESSABLE_ENTITY, json!({ "error": error, "hooks": hooks }));
+        }
+    }
+
+    match registry.upload(tenant_id, &name, &version, bytes).await {
+        Ok(metadata) => {
+            hooks.extend(lifecycle::run(&state, tenant_id, &id, &compiled, Hook::Activate).await);
+            state
+                .admin_audit
+                .record(AuditEvent::new(actor, "module.uploaded", metadata.id()).details(json!({
+                    "tenant_id": tenant_id,
+                    "sha256": metadata.sha256,
+                    "size": metadata.size,
+                    "hooks": hooks,
+                })))
+                .await;
+            reply(StatusCode::CREATED, json!({ "id": metadata.id(), "module": metadata, "hooks": hooks }))
+        }
+        Err(e) => unavailable("upload module", &id, e),
+    }
+}
+
+async fn list(
+    (state, registry): (Arc<ServiceState>, Arc<ModuleRegistry>),
+    tenant_id: Option<String>,
+    authorization: Option<String>,
+) -> Reply {
+    let tenant_id = match namespace(&state, tenant_id, authorization).await {
+        Ok(tenant_id) => tenant_id,
+        Err(refused) => return refused,
+    };
+    match registry.list().await {
+        Ok(modules) => {
+            let modules: Vec<_> = modules
+                .into_iter()
+                .filter(|metadata| tenant_id.is_none() || metadata.visible_to(tenant_id.as_deref()))
+                .map(|metadata| json!({ "id": metadata.id(), "module": metadata }))
+                .collect();
+            reply(StatusCode::OK, json!({ "modules": modules }))
+        }
+        Err(e) => unavailable("list modules", "registry", e),
+    }
+}
+
+async fn delete(
+    (state, registry): (Arc<ServiceState>, Arc<ModuleRegistry>),
+    tenant_id: Option<String>,
+    authorization: Option<String>,
+    id: String,
+    actor: String,
+) -> Reply {
+    let tenant_id = match namespace(&state, tenant_id, authorization).await {
+        Ok(tenant_id) => tenant_id,
+        Err(refused) => return refused,
+    };
+    if let Err(e) = registry::check_id(&id) {
+        return reply(StatusCode::BAD_REQUEST, json!({ "error": e.to_string() }));
+    }
+    // Only the namespace's own module, not the system one the tenant runs
+    // under the same id
+    let exists = match registry.list().await {
+        Ok(modules) => modules.iter().any(|metadata| metadata.id() == id && metadata.tenant_id == tenant_id),
+        Err(e) => return unavailable("list modules", "registry", e),
+    };
+    if !exists {
+        return reply(StatusCode::NOT_FOUND, json!({ "error": format!("Module not found: {}", id) }));
+    }
+    let tenant_id = tenant_id.as_deref();
+    // Run while the module still exists; failing hooks don't keep it
+    let mut hooks = Vec::new();
+    match compiled::load(&state, tenant_id, &id).await {
+        Ok(compiled) => {
+            for hook in [Hook::Deactivate, Hook::Uninstall] {
+                hooks.extend(lifecycle::run(&state, tenant_id, &id, &compiled, hook).await);
+            }
+        }
+        Err(e) => warn!("Not running uninstall hooks of module {}: {:#}", id, e),
+    }
+    match registry.delete(tenant_id, &id).await {
+        Ok(true) => {
+            state
+                .admin_audit
+                .record(
+                    AuditEvent::new(actor, "module.deleted", &id).details(json!({ "tenant_id": tenant_id, "hooks": hooks })),
+                )
+                .await;
+            reply(StatusCode::OK, json!({ "deleted": id, "hooks": hooks }))
+        }
+        Ok(false) => reply(StatusCode::NOT_FOUND, json!({ "error": format!("Module not found: {}", id) })),
+        Err(e) => unavailable("delete module", &id, e),
+    }
+}
+
+async fn inspect_module(
+    (state, registry): (Arc<ServiceState>, Arc<ModuleRegistry>),
+    tenant_id: Option<String>,
+    authorization: Option<String>,
+    id: String,
+) -> Reply {
+    let tenant_id = match namespace(&state, tenant_id, authorization).await {
+        Ok(tenant_id) => tenant_id,
+        Err(refused) => return refused,
+    };
+    if let Err(e) = registry::check_id(&id) {
+        return reply(StatusCode::BAD_REQUEST, json!({ "error": e.to_string() }));
+    }
+    let tenant_id = tenant_id.as_deref();
+    // The module the tenant runs: its own before a system one
+    let metadata = match registry.list().await {
+        Ok(modules) => modules
+            .into_iter()
+            .filter(|metadata| metadata.id() == id && metadata.visible_to(tenant_id))
+            .max_by_key(|metadata| metadata.tenant_id.is_some()),
+        Err(e) => return unavailable("list modules", "registry", e),
+    };
+    let Some(metadata) = metadata else {
+        return reply(StatusCode::NOT_FOUND, json!({ "error": format!("Module not found: {}", id) }));
+    };
+    // Compiled like for an execution, so this also warms the cache
+    let info = match compiled::load(&state, tenant_id, &id).await.and_then(|compiled| inspect(&compiled)) {
+        Ok(info) => info,
+        Err(e) => return reply(StatusCode::UNPROCESSABLE_ENTITY, json!({ "error": format!("{:#}", e) })),
+    };
+    reply(
+        StatusCode::OK,
+        json!({
+            "id": id,
+            "sha256": info.sha256,
+            "size_bytes": metadata.size,
+            "initial_memory_bytes": info.initial_memory_bytes(),
+            "exports": info.exports,
+            "imports": info.imports,
+        }),
+    )
+}
+
+/// The tenant whose modules a call with `X-Tenant-ID` `tenant_id` and
+/// `authorization` acts on, `None` for the system modules; see the module
+/// docs. Refuses callers who may not, and values that can't name a
+/// tenant's modules.
+pub(crate) async fn namespace(
+    state: &ServiceState,
+    tenant_id: Option<String>,
+    authorization: Option<String>,
+) -> Result<Option<String>, Reply> {
+    let tenant_id = match &state.auth {
+        Some(auth) => auth.namespace(authorization.as_deref(), tenant_id.as_deref()).await.map_err(|e| {
+            let status = if e.is_forbidden() { StatusCode::FORBIDDEN } else { StatusCode::UNAUTHORIZED };
+            reply(status, json!({ "error": e.to_string() }))
+        })?,
+        None => tenant_id,
+    };
+    match tenant_id.as_deref().map(registry::check_tenant) {
+        Some(Err(e)) => Err(reply(StatusCode::BAD_REQUEST, json!({ "error": e.to_string() }))),
+        _ => Ok(tenant_id),
+    }
+}
+
+async fn read_part(part: Part) -> Result<Vec<u8>, warp::Error> {
+    part.stream()
+        .try_fold(Vec::new(), |mut content, chunk| async move {
+            content.extend_from_slice(chunk.chunk());
+            Ok(content)
+        })
+        .await
+}
+
+fn unavailable(what: &str, id: &str, e: anyhow::Error) -> Reply {
+    error!("Failed to {} {}: {:#}", what, id, e);
+    reply(StatusCode::SERVICE_UNAVAILABLE, json!({ "error": format!("Could not {}", what) }))
+}
+
+fn reply(status: StatusCode, body: serde_json::Value) -> Reply {
+    warp::reply::with_status(warp::reply::json(&body), status)
+}
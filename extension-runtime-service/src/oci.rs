@@ -0,0 +1,282 @@
+//! Plugin modules published as OCI artifacts, referenced as
+//! `oci://{registry}/{repository}:{tag}` or pinned by digest as
+//! `oci://{registry}/{repository}@sha256:{hex}`.
+//!
+//! The manifest is fetched over the distribution API and its wasm layer
+//! (`application/vnd.wasm.content.layer.v1+wasm` or `application/wasm`,
+//! else the only layer) is downloaded and checked against its digest.
+//! Layers are cached on disk by digest, so only the manifest is fetched
+//! again once a tag's resolution expires, and digest-pinned references
+//! skip the registry entirely while their layer is cached. Registries that
+//! want a token get one through the standard Bearer challenge, using the
+//! credentials configured for their host.
+
+use crate::errors::{self, ErrorCode};
+use crate::storage::DiskCache;
+use anyhow::{Context, Result};
+use metrics::counter;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+pub const SCHEME: &str = "oci://";
+
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.manifest.v1+json, application/vnd.docker.distribution.manifest.v2+json";
+const WASM_LAYER_TYPES: [&str; 2] = ["application/vnd.wasm.content.layer.v1+wasm", "application/wasm"];
+
+/// A parsed `oci://` module path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub registry: String,
+    pub repository: String,
+    pub tag: Option<String>,
+    /// `sha256:{hex}` when pinned.
+    pub digest: Option<String>,
+}
+
+impl Reference {
+    pub fn parse(module_path: &str) -> Result<Self> {
+        let rest = module_path.strip_prefix(SCHEME).context("Not an oci:// reference")?;
+        let (registry, path) = rest.split_once('/').context("OCI reference needs a repository")?;
+        let (path, digest) = match path.split_once('@') {
+            Some((path, digest)) => (path, Some(digest.to_string())),
+            None => (path, None),
+        };
+        // A colon after the last slash separates the tag
+        let (repository, tag) = match path.rfind(':').filter(|colon| !path[*colon..].contains('/')) {
+            Some(colon) => (&path[..colon], Some(path[colon + 1..].to_string())),
+            None => (path, None),
+        };
+
+        if registry.is_empty() || repository.is_empty() {
+            anyhow::bail!("Invalid OCI reference: {}", module_path);
+        }
+        let valid_repository = repository.split('/').all(|part| {
+            !part.is_empty() && part.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '-'))
+        });
+        if !valid_repository {
+            anyhow::bail!("Invalid OCI repository: {}", repository);
+        }
+        if let Some(digest) = &digest {
+            let valid = digest
+                .strip_prefix("sha256:")
+                .is_some_and(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()));
+            if !valid {
+                anyhow::bail!("Only sha256 digests are supported: {}", digest);
+            }
+        }
+        if digest.is_none() && tag.is_none() {
+            anyhow::bail!("OCI reference needs a tag or digest: {}", module_path);
+        }
+
+        Ok(Reference {
+            registry: registry.to_string(),
+            repository: repository.to_string(),
+            tag,
+            digest,
+        })
+    }
+
+    /// What the manifest is requested by; the digest wins when both are given.
+    fn manifest_reference(&self) -> &str {
+        self.digest.as_deref().or(self.tag.as_deref()).unwrap_or("latest")
+    }
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    layers: Vec<Descriptor>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Descriptor {
+    media_type: String,
+    digest: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    #[serde(alias = "access_token")]
+    token: String,
+}
+
+pub struct OciSource {
+    http: reqwest::Client,
+    /// `user:password` per registry host.
+    credentials: HashMap<String, String>,
+    /// Registries reached over plain HTTP, e.g. a local test registry.
+    plain_http: Vec<String>,
+    layers: DiskCache,
+    /// Layer digest each reference resolved to, and when.
+    resolved: Mutex<HashMap<String, (String, Instant)>>,
+    tag_ttl: Duration,
+    tokens: Mutex<HashMap<String, String>>,
+}
+
+impl OciSource {
+    pub fn new(
+        credentials: HashMap<String, String>,
+        plain_http: Vec<String>,
+        layers: DiskCache,
+        tag_ttl: Duration,
+    ) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(60))
+            .build()
+            .context("Failed to build OCI client")?;
+        Ok(OciSource {
+            http,
+            credentials,
+            plain_http,
+            layers,
+            resolved: Mutex::new(HashMap::new()),
+            tag_ttl,
+            tokens: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub async fn fetch(&self, module_path: &str) -> Result<Arc<Vec<u8>>> {
+        let reference = Reference::parse(module_path)?;
+
+        // Digest-pinned manifests never change, so their layer never does
+        let resolved = self.resolved.lock().await.get(module_path).cloned();
+        if let Some((layer, resolved_at)) = resolved
+            && (reference.digest.is_some() || resolved_at.elapsed() < self.tag_ttl)
+            && let Some(bytes) = self.layers.get(&layer).await
+        {
+            counter!("oci_layer_cache_total", "result" => "hit").increment(1);
+            return Ok(Arc::new(bytes));
+        }
+
+        let layer = self.resolve(&reference).await?;
+        self.resolved
+            .lock()
+            .await
+            .insert(module_path.to_string(), (layer.clone(), Instant::now()));
+        if let Some(bytes) = self.layers.get(&layer).await {
+            counter!("oci_layer_cache_total", "result" => "hit").increment(1);
+            return Ok(Arc::new(bytes));
+        }
+        counter!("oci_layer_cache_total", "result" => "miss").increment(1);
+
+        let url = format!("{}/v2/{}/blobs/{}", self.base_url(&reference.registry), reference.repository, layer);
+        let bytes = self.get(&reference, &url, None).await?.bytes().await?.to_vec();
+        check_digest(&layer, &bytes).with_context(|| format!("Layer of {}", module_path))?;
+        info!("Pulled {} ({} bytes, {})", module_path, bytes.len(), layer);
+        self.layers.put(&layer, &bytes).await;
+        Ok(Arc::new(bytes))
+    }
+
+    /// The digest of the reference's wasm layer, from its manifest.
+    async fn resolve(&self, reference: &Reference) -> Result<String> {
+        let url = format!(
+            "{}/v2/{}/manifests/{}",
+            self.base_url(&reference.registry),
+            reference.repository,
+            reference.manifest_reference()
+        );
+        let body = self.get(reference, &url, Some(MANIFEST_TYPES)).await?.bytes().await?;
+        if let Some(digest) = &reference.digest {
+            check_digest(digest, &body).context("Manifest does not match the pinned digest")?;
+        }
+        let manifest: Manifest = serde_json::from_slice(&body).context("Invalid OCI manifest")?;
+
+        let layer = match manifest.layers.iter().find(|layer| WASM_LAYER_TYPES.contains(&layer.media_type.as_str())) {
+            Some(layer) => layer,
+            None if manifest.layers.len() == 1 => &manifest.layers[0],
+            None => anyhow::bail!("OCI artifact has no wasm layer"),
+        };
+        debug!("Resolved {:?} to layer {}", reference, layer.digest);
+        Ok(layer.digest.clone())
+    }
+
+    /// GETs `url`, answering a Bearer challenge once if the registry
+    /// sends one.
+    async fn get(&self, reference: &Reference, url: &str, accept: Option<&str>) -> Result<reqwest::Response> {
+        let request = |token: Option<&str>| {
+            let mut request = self.http.get(url);
+            if let Some(accept) = accept {
+                request = request.header(reqwest::header::ACCEPT, accept);
+            }
+            match (token, self.credentials.get(&reference.registry)) {
+                (Some(token), _) => request.bearer_auth(token),
+                (None, Some(credentials)) => {
+                    let (user, password) = credentials.split_once(':').unwrap_or((credentials, ""));
+                    request.basic_auth(user, Some(password))
+                }
+                (None, None) => request,
+            }
+        };
+
+        let cached_token = self.tokens.lock().await.get(&reference.registry).cloned();
+        let mut response = request(cached_token.as_deref()).send().await?;
+        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            let challenge = response
+                .headers()
+                .get(reqwest::header::WWW_AUTHENTICATE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            if let Some(challenge) = challenge {
+                let token = self.token(reference, &challenge).await?;
+                response = request(Some(&token)).send().await?;
+                self.tokens.lock().await.insert(reference.registry.clone(), token);
+            }
+        }
+        match response.status() {
+            status if status.is_success() => Ok(response),
+            reqwest::StatusCode::NOT_FOUND => Err(errors::coded(ErrorCode::ModuleNotFound, anyhow::anyhow!("Module not found: {}", url))),
+            status => anyhow::bail!("OCI registry answered {} for {}", status, url),
+        }
+    }
+
+    /// A token from the realm named in a `Bearer realm=...,service=...`
+    /// challenge, scoped to pulling the repository.
+    async fn token(&self, reference: &Reference, challenge: &str) -> Result<String> {
+        let params = challenge
+            .strip_prefix("Bearer ")
+            .context("Unsupported OCI registry authentication")?;
+        let params: HashMap<&str, &str> = params
+            .split(',')
+            .filter_map(|pair| pair.trim().split_once('='))
+            .map(|(key, value)| (key, value.trim_matches('"')))
+            .collect();
+        let realm = params.get("realm").context("OCI auth challenge without a realm")?;
+
+        let scope = format!("repository:{}:pull", reference.repository);
+        let mut query = vec![("scope", scope.as_str())];
+        if let Some(service) = params.get("service") {
+            query.push(("service", service));
+        }
+        let mut request = self.http.get(*realm).query(&query);
+        if let Some(credentials) = self.credentials.get(&reference.registry) {
+            let (user, password) = credentials.split_once(':').unwrap_or((credentials, ""));
+            request = request.basic_auth(user, Some(password));
+        }
+        let response: TokenResponse = request
+            .send()
+            .await?
+            .error_for_status()
+            .context("OCI token request failed")?
+            .json()
+            .await?;
+        Ok(response.token)
+    }
+
+    fn base_url(&self, registry: &str) -> String {
+        let scheme = if self.plain_http.iter().any(|host| host == registry) { "http" } else { "https" };
+        format!("{}://{}", scheme, registry)
+    }
+}
+
+fn check_digest(digest: &str, bytes: &[u8]) -> Result<()> {
+    let actual = format!("sha256:{}", hex::encode(Sha256::digest(bytes)));
+    if actual != digest {
+        anyhow::bail!("Digest mismatch: expected {}, got {}", digest, actual);
+    }
+    Ok(())
+}
@@ -0,0 +1,24 @@
+//! The runtime's HTTP API as an OpenAPI 3 document, for other services to
+//! generate typed clients from.
+//!
+//! `GET /openapi.json` serves `openapi.json`, which covers every route
+//! the runtime serves, `/metrics`, `/healthz` and `/admin/chaos` included,
+//! with their request and response schemas. It is kept by hand next to the
+//! routes: a route added or changed goes there too, which
+//! `tests/openapi.rs` checks. Failed executions are described by their
+//! `ExecuteError` and the statuses its codes map to (see `errors`).
+
+use serde_json::Value;
+use std::sync::OnceLock;
+use warp::Filter;
+
+const OPENAPI: &str = include_str!("../openapi.json");
+
+fn document() -> &'static Value {
+    static DOCUMENT: OnceLock<Value> = OnceLock::new();
+    DOCUMENT.get_or_init(|| serde_json::from_str(OPENAPI).expect("openapi.json is valid JSON"))
+}
+
+pub fn routes() -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("openapi.json").and(warp::get()).map(|| warp::reply::json(document()))
+}
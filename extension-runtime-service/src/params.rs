@@ -0,0 +1,370 @@
+//! Conversion between the JSON arguments and results of the execute API and
+//! WebAssembly values, of core modules and of components.
+//!
+//! Core modules take strings and bytes as a pointer and length, a pair of
+//! i32 parameters: the runtime asks the plugin's `alloc(len: i32) -> i32`
+//! export for a buffer and copies the UTF-8, or the bytes of a
+//! `{"base64": "…"}` argument, into it. Results that are strings or bytes
+//! come back the same way, as a `(ptr, len)` pair of i32 results, when the
+//! request's `result_encoding` says so. Nothing is freed; an instance only
+//! serves one execution.
+//!
+//! With the `json` calling convention the plugin exports
+//! `handle(ptr: i32, len: i32) -> (i32, i32)` instead: the request's
+//! `params`, whatever their shape, are passed as one JSON document and the
+//! JSON document returned is the result.
+
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use serde::Deserialize;
+use wasmtime::component;
+use wasmtime::{AsContextMut, Func, Instance, Val, ValType};
+
+/// How a core module function takes its arguments.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallingConvention {
+    /// One parameter per argument, or a `(ptr, len)` pair for strings and
+    /// bytes.
+    #[default]
+    Values,
+    /// `params` as a JSON document in, a JSON document out.
+    Json,
+}
+
+impl std::str::FromStr for CallingConvention {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "" | "values" => Ok(CallingConvention::Values),
+            "json" => Ok(CallingConvention::Json),
+            other => anyhow::bail!("Unknown calling convention: {}", other),
+        }
+    }
+}
+
+/// What a core module function's results are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResultEncoding {
+    /// Numbers, as returned.
+    #[default]
+    Values,
+    /// A UTF-8 string at `(ptr, len)`.
+    String,
+    /// Bytes at `(ptr, len)`, returned as `{"base64": "…"}`.
+    Bytes,
+}
+
+impl std::str::FromStr for ResultEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "" | "values" => Ok(ResultEncoding::Values),
+            "string" => Ok(ResultEncoding::String),
+            "bytes" => Ok(ResultEncoding::Bytes),
+            other => anyhow::bail!("Unknown result encoding: {}", other),
+        }
+    }
+}
+
+/// An argument in WebAssembly terms: a value, or bytes to copy into the
+/// plugin's memory and pass as `(ptr, len)`.
+#[derive(Debug, Clone)]
+pub enum WasmArg {
+    Val(Val),
+    Bytes(Vec<u8>),
+}
+
+/// Converts a JSON array of arguments to the function's parameter types.
+pub fn json_to_wasm_params(json: &serde_json::Value, param_types: &[ValType]) -> Result<Vec<Val>> {
+    let params_array = match json {
+        serde_json::Value::Array(arr) => arr,
+        _ => anyhow::bail!("Parameters must be an array"),
+    };
+    if params_array.len() != param_types.len() {
+        anyhow::bail!("Parameter count mismatch");
+    }
+    let mut wasm_params = Vec::new();
+    for (json_param, wasm_type) in params_array.iter().zip(param_types.iter()) {
+        wasm_params.push(json_to_wasm_val(json_param, wasm_type)?);
+    }
+    Ok(wasm_params)
+}
+
+fn json_to_wasm_val(json: &serde_json::Value, wasm_type: &ValType) -> Result<Val> {
+    Ok(match (json, wasm_type) {
+        (serde_json::Value::Number(n), ValType::I32) => {
+            Val::I32(n.as_i64().context("Invalid i32")? as i32)
+        }
+        (serde_json::Value::Number(n), ValType::I64) => {
+            Val::I64(n.as_i64().context("Invalid i64")?)
+        }
+        (serde_json::Value::Number(n), ValType::F32) => {
+            Val::F32((n.as_f64().context("Invalid f32")? as f32).to_bits())
+        }
+        (serde_json::Value::Number(n), ValType::F64) => {
+            Val::F64(n.as_f64().context("Invalid f64")?.to_bits())
+        }
+        _ => anyhow::bail!("Unsupported parameter type combination"),
+    })
+}
+
+/// Converts a JSON array of arguments to the function's parameter types,
+/// strings and `{"base64": "…"}` bytes each taking a `(ptr, len)` pair.
+pub fn json_to_wasm_args(json: &serde_json::Value, param_types: &[ValType]) -> Result<Vec<WasmArg>> {
+    let params_array = match json {
+        serde_json::Value::Array(arr) => arr,
+        _ => anyhow::bail!("Parameters must be an array"),
+    };
+    let mut types = param_types.iter();
+    let mut args = Vec::with_capacity(params_array.len());
+    for json_param in params_array {
+        let Some(bytes) = payload(json_param)? else {
+            let wasm_type = types.next().context("Parameter count mismatch")?;
+            args.push(WasmArg::Val(json_to_wasm_val(json_param, wasm_type)?));
+            continue;
+        };
+        match (types.next(), types.next()) {
+            (Some(ValType::I32), Some(ValType::I32)) => args.push(WasmArg::Bytes(bytes)),
+            (None, _) => anyhow::bail!("Parameter count mismatch"),
+            _ => anyhow::bail!("Strings and bytes are passed as a (ptr, len) pair of i32 parameters"),
+        }
+    }
+    if types.next().is_some() {
+        anyhow::bail!("Parameter count mismatch");
+    }
+    Ok(args)
+}
+
+/// The bytes of a string or `{"base64": "…"}` argument.
+fn payload(json: &serde_json::Value) -> Result<Option<Vec<u8>>> {
+    match json {
+        serde_json::Value::String(s) => Ok(Some(s.clone().into_bytes())),
+        serde_json::Value::Object(object) if object.len() == 1 => match object.get("base64") {
+            Some(serde_json::Value::String(encoded)) => Ok(Some(BASE64.decode(encoded).context("Invalid base64")?)),
+            _ => Ok(None),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Copies byte arguments into buffers from the plugin's `alloc` export,
+/// passing each as its pointer and length.
+pub fn lower_args(mut store: impl AsContextMut, instance: &Instance, args: Vec<WasmArg>) -> Result<Vec<Val>> {
+    let mut params = Vec::with_capacity(args.len());
+    for arg in args {
+        let bytes = match arg {
+            WasmArg::Val(val) => {
+                params.push(val);
+                continue;
+            }
+            WasmArg::Bytes(bytes) => bytes,
+        };
+        let ptr = write_bytes(&mut store, instance, &bytes)?;
+        params.push(Val::I32(ptr));
+        params.push(Val::I32(bytes.len() as i32));
+    }
+    Ok(params)
+}
+
+/// Copies `bytes` into a buffer from the plugin's `alloc(len: i32) -> i32`
+/// export, returning its pointer.
+pub fn write_bytes(mut store: impl AsContextMut, instance: &Instance, bytes: &[u8]) -> Result<i32> {
+    let memory = instance
+        .get_memory(&mut store, "memory")
+        .context("Strings and bytes need the plugin to export its memory")?;
+    let alloc = instance
+        .get_typed_func::<i32, i32>(&mut store, "alloc")
+        .context("Strings and bytes need the plugin to export alloc(len: i32) -> i32")?;
+    let len = i32::try_from(bytes.len()).context("Parameter too large")?;
+    let ptr = alloc.call(&mut store, len)?;
+    memory
+        .write(&mut store, ptr as u32 as usize, bytes)
+        .context("alloc returned a buffer outside the plugin's memory")?;
+    Ok(ptr)
+}
+
+/// The `len` bytes at `ptr` in the plugin's memory.
+pub fn read_bytes(mut store: impl AsContextMut, instance: &Instance, ptr: i32, len: i32) -> Result<Vec<u8>> {
+    let memory = instance
+        .get_memory(&mut store, "memory")
+        .context("Strings and bytes need the plugin to export its memory")?;
+    let mut bytes = vec![0; usize::try_from(len).context("Negative result length")?];
+    memory
+        .read(&store, ptr as u32 as usize, &mut bytes)
+        .context("Result is outside the plugin's memory")?;
+    Ok(bytes)
+}
+
+/// The function's results as JSON, read from the plugin's memory unless
+/// `encoding` says they are plain values.
+pub fn lift_results(
+    store: impl AsContextMut,
+    instance: &Instance,
+    results: &[Val],
+    encoding: ResultEncoding,
+) -> Result<serde_json::Value> {
+    let (ptr, len) = match (encoding, results) {
+        (ResultEncoding::Values, _) => return wasm_results_to_json(results),
+        (_, [Val::I32(ptr), Val::I32(len)]) => (*ptr, *len),
+        _ => anyhow::bail!("Strings and bytes are returned as a (ptr, len) pair of i32 results"),
+    };
+    let bytes = read_bytes(store, instance, ptr, len)?;
+    Ok(match encoding {
+        ResultEncoding::String => serde_json::Value::String(String::from_utf8(bytes).context("Result is not UTF-8")?),
+        _ => serde_json::json!({ "base64": BASE64.encode(bytes) }),
+    })
+}
+
+/// A single result as a JSON value, several as a JSON array.
+pub fn wasm_results_to_json(results: &[Val]) -> Result<serde_json::Value> {
+    if results.len() == 1 {
+        // Single result
+        Ok(wasm_val_to_json(&results[0])?)
+    } else {
+        // Multiple results as array
+        let json_results: Result<Vec<_>> = results.iter().map(wasm_val_to_json).collect();
+        Ok(serde_json::Value::Array(json_results?))
+    }
+}
+
+fn wasm_val_to_json(val: &Val) -> Result<serde_json::Value> {
+    match val {
+        Val::I32(i) => Ok(serde_json::Value::Number((*i).into())),
+        Val::I64(i) => Ok(serde_json::Value::Number((*i).into())),
+        Val::F32(f) => Ok(serde_json::Value::Number(
+            serde_json::Number::from_f64(f32::from_bits(*f) as f64)
+                .context("Invalid f32")?
+        )),
+        Val::F64(f) => Ok(serde_json::Value::Number(
+            serde_json::Number::from_f64(f64::from_bits(*f))
+                .context("Invalid f64")?
+        )),
+        _ => anyhow::bail!("Unsupported result type"),
+    }
+}
+
+/// Converts a JSON array of arguments to a component function's parameter
+/// types. Only scalars and strings can be passed in.
+pub fn json_to_component_params(
+    json: &serde_json::Value,
+    param_types: &[component::Type],
+) -> Result<Vec<component::Val>> {
+    let params_array = match json {
+        serde_json::Value::Array(arr) => arr,
+        _ => anyhow::bail!("Parameters must be an array"),
+    };
+    if params_array.len() != param_types.len() {
+        anyhow::bail!("Parameter count mismatch");
+    }
+    params_array
+        .iter()
+        .zip(param_types)
+        .map(|(json_param, ty)| json_to_component_val(json_param, ty))
+        .collect()
+}
+
+fn json_to_component_val(json: &serde_json::Value, ty: &component::Type) -> Result<component::Val> {
+    use component::{Type, Val};
+    let int = |n: &serde_json::Value| n.as_i64().context("Invalid integer");
+    let uint = |n: &serde_json::Value| n.as_u64().context("Invalid unsigned integer");
+    Ok(match (json, ty) {
+        (serde_json::Value::Bool(b), Type::Bool) => Val::Bool(*b),
+        (n @ serde_json::Value::Number(_), Type::S8) => Val::S8(int(n)?.try_into()?),
+        (n @ serde_json::Value::Number(_), Type::U8) => Val::U8(uint(n)?.try_into()?),
+        (n @ serde_json::Value::Number(_), Type::S16) => Val::S16(int(n)?.try_into()?),
+        (n @ serde_json::Value::Number(_), Type::U16) => Val::U16(uint(n)?.try_into()?),
+        (n @ serde_json::Value::Number(_), Type::S32) => Val::S32(int(n)?.try_into()?),
+        (n @ serde_json::Value::Number(_), Type::U32) => Val::U32(uint(n)?.try_into()?),
+        (n @ serde_json::Value::Number(_), Type::S64) => Val::S64(int(n)?),
+        (n @ serde_json::Value::Number(_), Type::U64) => Val::U64(uint(n)?),
+        (serde_json::Value::Number(n), Type::Float32) => Val::Float32(n.as_f64().context("Invalid float32")? as f32),
+        (serde_json::Value::Number(n), Type::Float64) => Val::Float64(n.as_f64().context("Invalid float64")?),
+        (serde_json::Value::String(s), Type::Char) => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Val::Char(c),
+                _ => anyhow::bail!("Invalid char"),
+            }
+        }
+        (serde_json::Value::String(s), Type::String) => Val::String(s.as_str().into()),
+        _ => anyhow::bail!("Unsupported parameter type combination"),
+    })
+}
+
+/// A single result as a JSON value, several as a JSON array. Records are
+/// objects, `option`s null or their value, and `result`s `{"ok": …}` or
+/// `{"err": …}`.
+pub fn component_results_to_json(results: &[component::Val]) -> Result<serde_json::Value> {
+    if results.len() == 1 {
+        component_val_to_json(&results[0])
+    } else {
+        let json_results: Result<Vec<_>> = results.iter().map(component_val_to_json).collect();
+        Ok(serde_json::Value::Array(json_results?))
+    }
+}
+
+fn component_val_to_json(val: &component::Val) -> Result<serde_json::Value> {
+    use component::Val;
+    use serde_json::{json, Value};
+    let float = |f: f64| serde_json::Number::from_f64(f).map(Value::Number).context("Invalid float");
+    let all = |vals: &[Val]| vals.iter().map(component_val_to_json).collect::<Result<Vec<_>>>();
+    let payload = |val: Option<&Val>| val.map_or(Ok(Value::Null), component_val_to_json);
+    Ok(match val {
+        Val::Bool(b) => json!(b),
+        Val::S8(i) => json!(i),
+        Val::U8(i) => json!(i),
+        Val::S16(i) => json!(i),
+        Val::U16(i) => json!(i),
+        Val::S32(i) => json!(i),
+        Val::U32(i) => json!(i),
+        Val::S64(i) => json!(i),
+        Val::U64(i) => json!(i),
+        Val::Float32(f) => float(*f as f64)?,
+        Val::Float64(f) => float(*f)?,
+        Val::Char(c) => json!(c.to_string()),
+        Val::String(s) => json!(s.as_ref()),
+        Val::List(list) => Value::Array(all(list)?),
+        Val::Tuple(tuple) => Value::Array(all(tuple.values())?),
+        Val::Record(record) => Value::Object(
+            record
+                .fields()
+                .map(|(name, val)| Ok((name.to_string(), component_val_to_json(val)?)))
+                .collect::<Result<_>>()?,
+        ),
+        Val::Variant(variant) => json!({ variant.discriminant(): payload(variant.payload())? }),
+        Val::Enum(case) => json!(case.discriminant()),
+        Val::Option(option) => payload(option.value())?,
+        Val::Result(result) => match result.value() {
+            Ok(ok) => json!({ "ok": payload(ok)? }),
+            Err(err) => json!({ "err": payload(err)? }),
+        },
+        Val::Flags(flags) => json!(flags.flags().collect::<Vec<_>>()),
+        Val::Resource(_) => anyhow::bail!("Unsupported result type"),
+    })
+}
+
+/// Calls `func` with the `json` calling convention: `params` serialized into
+/// the plugin's memory, and the JSON it returns parsed, null when empty.
+pub fn call_json(
+    mut store: impl AsContextMut,
+    instance: &Instance,
+    func: Func,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value> {
+    let handle = func
+        .typed::<(i32, i32), (i32, i32)>(&store)
+        .context("The json calling convention needs handle(ptr: i32, len: i32) -> (i32, i32)")?;
+    let request = serde_json::to_vec(params)?;
+    let ptr = write_bytes(&mut store, instance, &request)?;
+    let (ptr, len) = handle.call(&mut store, (ptr, request.len() as i32))?;
+    if len == 0 {
+        return Ok(serde_json::Value::Null);
+    }
+    let response = read_bytes(&mut store, instance, ptr, len)?;
+    serde_json::from_slice(&response).context("Plugin returned invalid JSON")
+}
@@ -0,0 +1,150 @@
+//! Pipelines: plugin executions chained server-side, so a composite
+//! workflow such as enrich, score, then route a lead takes one round trip
+//! instead of one per plugin.
+//!
+//! `POST /execute/pipeline` runs its steps in order:
+//!
+//! ```json
+//! {"params": [{"lead_id": 42}],
+//!  "steps": [{"module_path": "enrich@^1", "function_name": "run", "calling_convention": "json"},
+//!            {"module_path": "lead-scoring@^2", "function_name": "score", "calling_convention": "json",
+//!             "timeout_seconds": 2, "fuel_limit": 5000000}]}
+//! ```
+//!
+//! The first step gets the pipeline's `params`, and each one after gets the
+//! result of the step before: as is for `json` steps, otherwise as the
+//! argument list, a result that isn't a list being its only argument and
+//! no result none.
+//! Steps take the fields of an execute request but `params`, plus a
+//! `fuel_limit`, which can only lower the tenant's. Each runs like a single
+//! execution would, with its own timeout, quota and plan pool slot.
+//!
+//! The first step to fail stops the pipeline; the response has every step's
+//! execute response up to it, its index as `failed_step`, and the status
+//! of its error, and otherwise the last step's result as `result`. Pipelines are at most
+//! `pipeline_max_steps` long.
+
+use crate::{authorize, execute, CallingConvention, ExecuteRequest, ServiceState, TENANT_HEADER};
+use metrics::counter;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use std::time::Instant;
+use warp::http::StatusCode;
+use warp::Filter;
+
+#[derive(Debug, Deserialize)]
+struct PipelineRequest {
+    #[serde(default)]
+    params: Value,
+    steps: Vec<Step>,
+    #[serde(default)]
+    tenant_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Step {
+    #[serde(default)]
+    fuel_limit: Option<u64>,
+    #[serde(flatten)]
+    execute: Map<String, Value>,
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+fn reply(status: StatusCode, body: Value) -> Reply {
+    warp::reply::with_status(warp::reply::json(&body), status)
+}
+
+pub fn routes(
+    state: Arc<ServiceState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("execute" / "pipeline")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(1024 * 1024))
+        .and(warp::body::json())
+        .and(warp::header::optional::<String>(TENANT_HEADER))
+        .and(warp::header::optional::<String>("authorization"))
+        .then(move |pipeline: PipelineRequest, tenant_id: Option<String>, authorization: Option<String>| {
+            let state = state.clone();
+            async move { run(&state, pipeline, tenant_id, authorization.as_deref()).await }
+        })
+}
+
+async fn run(state: &ServiceState, pipeline: PipelineRequest, tenant_header: Option<String>, authorization: Option<&str>) -> Reply {
+    if pipeline.steps.is_empty() {
+        return reply(StatusCode::BAD_REQUEST, json!({ "error": "A pipeline needs at least one step" }));
+    }
+    let max_steps = state.config().pipeline_max_steps;
+    if pipeline.steps.len() > max_steps {
+        let error = format!("A pipeline has at most {} steps", max_steps);
+        return reply(StatusCode::BAD_REQUEST, json!({ "error": error }));
+    }
+
+    // Every step is checked before the first runs
+    let mut steps = Vec::with_capacity(pipeline.steps.len());
+    for (i, step) in pipeline.steps.into_iter().enumerate() {
+        let mut execute = step.execute;
+        if execute.contains_key("params") {
+            let error = format!("Step {} gets its params from the pipeline or the step before", i);
+            return reply(StatusCode::BAD_REQUEST, json!({ "error": error }));
+        }
+        execute.insert("params".to_string(), Value::Null);
+        if let Some(tenant_id) = &pipeline.tenant_id {
+            execute.entry("tenant_id").or_insert_with(|| json!(tenant_id));
+        }
+        let mut req: ExecuteRequest = match serde_json::from_value(Value::Object(execute)) {
+            Ok(req) => req,
+            Err(e) => return reply(StatusCode::BAD_REQUEST, json!({ "error": format!("Invalid step {}: {}", i, e) })),
+        };
+        req.fuel_limit = step.fuel_limit;
+        if let Err(error) = authorize(state, &mut req, tenant_header.clone(), authorization).await {
+            return reply(error.status(), json!({ "success": false, "error": error }));
+        }
+        steps.push(req);
+    }
+
+    let started = Instant::now();
+    let total = steps.len();
+    let mut params = pipeline.params;
+    let mut responses = Vec::with_capacity(total);
+    let mut failed_step = None;
+    let mut status = StatusCode::OK;
+    for (i, mut req) in steps.into_iter().enumerate() {
+        req.params = wire(params, req.calling_convention);
+        let response = execute(state, req).await;
+        params = response.result.clone().unwrap_or(Value::Null);
+        let success = response.success;
+        status = response.status();
+        responses.push(response);
+        if !success {
+            failed_step = Some(i);
+            break;
+        }
+    }
+
+    let success = failed_step.is_none();
+    counter!("plugin_pipelines_total", "status" => if success { "success" } else { "failure" }).increment(1);
+    reply(
+        status,
+        json!({
+            "success": success,
+            "result": if success { params } else { Value::Null },
+            "failed_step": failed_step,
+            "steps_run": responses.len(),
+            "steps_total": total,
+            "execution_time_ms": started.elapsed().as_millis() as u64,
+            "steps": responses,
+        }),
+    )
+}
+
+/// `result` as the params of a step with `calling_convention`.
+fn wire(result: Value, calling_convention: CallingConvention) -> Value {
+    match (calling_convention, result) {
+        (CallingConvention::Json, result) => result,
+        (CallingConvention::Values, Value::Array(args)) => Value::Array(args),
+        (CallingConvention::Values, Value::Null) => Value::Array(Vec::new()),
+        (CallingConvention::Values, result) => Value::Array(vec![result]),
+    }
+}
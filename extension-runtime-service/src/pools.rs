@@ -0,0 +1,419 @@
+//! Execution capacity reserved per plan tier.
+//!
+//! `max_instances` is split into an enterprise, a pro and a free pool. An
+//! execution takes a slot from its tier's pool first and may then borrow
+//! idle slots from lower tiers, lowest first, but never from a higher tier.
+//! A burst of free-tier executions can therefore only exhaust the free
+//! pool, and enterprise tenants always find their reserved slots.
+//!
+//! Executions that find no slot wait in their tenant's queue, at most
+//! `queue_depth_per_tenant` deep and for at most `queue_timeout_ms`. Freed
+//! slots go to the waiting executions by weighted fair queueing across
+//! tenants, enterprise tenants weighing 4, pro 2 and free 1, so a tenant
+//! queueing a burst delays its own executions rather than everyone's.
+//!
+//! A changed config file resizes the pools in place; see `reload`. A pool
+//! that shrank takes no more executions until enough of its running ones
+//! finish, and one that grew takes waiting executions at once.
+
+use metrics::{counter, gauge, histogram};
+use redis::aio::Connection;
+use redis::AsyncCommands;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, Mutex};
+use tracing::{debug, warn};
+
+/// How long a plan lookup may hold up an execution before the default plan
+/// is used.
+const PLAN_LOOKUP_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Virtual time one execution takes at weight 1; see `Queues`.
+const SERVICE: u64 = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tier {
+    Enterprise,
+    Pro,
+    Free,
+}
+
+impl Tier {
+    /// Highest first; lower tiers come later.
+    const ALL: [Tier; 3] = [Tier::Enterprise, Tier::Pro, Tier::Free];
+
+    /// Plans other than `enterprise` and `free` get the pro pool.
+    pub fn from_plan(plan: &str) -> Self {
+        match plan {
+            "enterprise" => Tier::Enterprise,
+            "free" => Tier::Free,
+            _ => Tier::Pro,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tier::Enterprise => "enterprise",
+            Tier::Pro => "pro",
+            Tier::Free => "free",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Share of freed slots the tier's queued executions get.
+    fn weight(self) -> u64 {
+        match self {
+            Tier::Enterprise => 4,
+            Tier::Pro => 2,
+            Tier::Free => 1,
+        }
+    }
+}
+
+/// The pool sizes and queue limits of the runtime config.
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    pub max_instances: u32,
+    pub enterprise_percent: u32,
+    pub pro_percent: u32,
+    pub queue_depth_per_tenant: usize,
+    pub queue_timeout: Duration,
+}
+
+/// Why an execution didn't get a slot.
+#[derive(Debug, PartialEq)]
+pub enum Unavailable {
+    /// The tenant already has `queue_depth_per_tenant` executions waiting.
+    QueueFull(Tier),
+    /// No slot came free within `queue_timeout_ms`.
+    TimedOut(Tier),
+}
+
+impl std::fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Unavailable::QueueFull(tier) => {
+                write!(f, "Too many active instances for the {} tier and too many queued executions", tier.name())
+            }
+            Unavailable::TimedOut(tier) => {
+                write!(f, "Too many active instances for the {} tier; timed out waiting for one", tier.name())
+            }
+        }
+    }
+}
+
+/// Slots per pool, how many are taken and who is waiting for one.
+pub struct ExecutionPools {
+    state: std::sync::Mutex<State>,
+}
+
+struct State {
+    capacity: [u32; 3],
+    queue_depth: usize,
+    queue_timeout: Duration,
+    in_use: [u32; 3],
+    queues: Queues,
+}
+
+/// Waiting executions by tenant. Each gets a finish tag one execution's
+/// service past the later of the virtual clock and its tenant's previous
+/// tag, scaled down by its weight, and the lowest tag that fits a free
+/// slot goes first; the clock advances to the tags dispatched.
+#[derive(Default)]
+struct Queues {
+    tenants: HashMap<String, VecDeque<Waiter>>,
+    /// Latest finish tag per tenant with executions waiting.
+    last_tags: HashMap<String, u64>,
+    clock: u64,
+    next_id: u64,
+}
+
+struct Waiter {
+    id: u64,
+    tier: Tier,
+    tag: u64,
+    queued_at: Instant,
+    sender: oneshot::Sender<PoolPermit>,
+}
+
+impl ExecutionPools {
+    pub fn new(config: &PoolConfig) -> Arc<Self> {
+        let state = State {
+            capacity: capacity(config),
+            queue_depth: config.queue_depth_per_tenant,
+            queue_timeout: config.queue_timeout,
+            in_use: [0; 3],
+            queues: Queues::default(),
+        };
+        Arc::new(ExecutionPools { state: std::sync::Mutex::new(state) })
+    }
+
+    /// Takes the pool sizes and queue limits of `config` from now on.
+    pub fn resize(self: &Arc<Self>, config: &PoolConfig) {
+        let dispatched = {
+            let mut state = self.lock();
+            state.capacity = capacity(config);
+            state.queue_depth = config.queue_depth_per_tenant;
+            state.queue_timeout = config.queue_timeout;
+            self.dispatch(&mut state)
+        };
+        hand_over(dispatched);
+    }
+
+    /// A slot for an execution of `tenant_id` at `tier`, waiting in the
+    /// tenant's queue while its pool and every pool it may borrow from are
+    /// full.
+    pub async fn acquire(self: &Arc<Self>, tier: Tier, tenant_id: Option<&str>) -> Result<PoolPermit, Unavailable> {
+        let tenant = tenant_id.unwrap_or_default();
+        let (id, mut receiver, queue_timeout) = {
+            let mut state = self.lock();
+            if let Some(permit) = self.take(&mut state, tier) {
+                return Ok(permit);
+            }
+            if state.queues.tenants.get(tenant).map_or(0, VecDeque::len) >= state.queue_depth {
+                counter!("execution_queue_rejections_total", "tier" => tier.name(), "reason" => "full").increment(1);
+                return Err(Unavailable::QueueFull(tier));
+            }
+            let (sender, receiver) = oneshot::channel();
+            let id = state.queues.push(tenant, tier, sender);
+            record_queued(&state.queues);
+            (id, receiver, state.queue_timeout)
+        };
+
+        match tokio::time::timeout(queue_timeout, &mut receiver).await {
+            Ok(Ok(permit)) => Ok(permit),
+            // Only dropped with the pools
+            Ok(Err(_)) => Err(Unavailable::TimedOut(tier)),
+            Err(_) => {
+                let queued = {
+                    let mut state = self.lock();
+                    let queued = state.queues.remove(tenant, id);
+                    record_queued(&state.queues);
+                    queued
+                };
+                if queued {
+                    counter!("execution_queue_rejections_total", "tier" => tier.name(), "reason" => "timeout")
+                        .increment(1);
+                    return Err(Unavailable::TimedOut(tier));
+                }
+                // Dispatched as the wait ran out
+                receiver.await.map_err(|_| Unavailable::TimedOut(tier))
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn take(self: &Arc<Self>, state: &mut State, tier: Tier) -> Option<PoolPermit> {
+        let pool = state.free_pool(tier)?;
+        state.in_use[pool.index()] += 1;
+        if pool != tier {
+            counter!("execution_pool_borrowed_total", "tier" => tier.name(), "pool" => pool.name()).increment(1);
+        }
+        record(&state.in_use, pool);
+        Some(PoolPermit {
+            pools: Arc::clone(self),
+            pool,
+        })
+    }
+
+    fn release(self: &Arc<Self>, pool: Tier) {
+        let dispatched = {
+            let mut state = self.lock();
+            state.in_use[pool.index()] = state.in_use[pool.index()].saturating_sub(1);
+            record(&state.in_use, pool);
+            self.dispatch(&mut state)
+        };
+        hand_over(dispatched);
+    }
+
+    /// Gives free slots to waiting executions, to be handed over once the
+    /// lock is released.
+    fn dispatch(self: &Arc<Self>, state: &mut State) -> Vec<(oneshot::Sender<PoolPermit>, PoolPermit)> {
+        let mut dispatched = Vec::new();
+        while let Some((tenant, id)) = state.queues.next(|tier| state.free_pool(tier).is_some()) {
+            let waiter = state.queues.take(&tenant, id);
+            let permit = self.take(state, waiter.tier).expect("a pool has room for the waiter");
+            histogram!("execution_queue_wait_seconds", "tier" => waiter.tier.name())
+                .record(waiter.queued_at.elapsed().as_secs_f64());
+            dispatched.push((waiter.sender, permit));
+        }
+        record_queued(&state.queues);
+        dispatched
+    }
+}
+
+impl State {
+    /// The pool an execution at `tier` would take a slot from, if any.
+    fn free_pool(&self, tier: Tier) -> Option<Tier> {
+        // Own pool, then lower tiers from the bottom up so a borrowing
+        // enterprise execution leaves pro capacity for pro tenants
+        let lower = Tier::ALL[tier.index() + 1..].iter().rev().copied();
+        std::iter::once(tier).chain(lower).find(|pool| self.in_use[pool.index()] < self.capacity[pool.index()])
+    }
+}
+
+/// `max_instances` split into the enterprise, pro and free pools.
+fn capacity(config: &PoolConfig) -> [u32; 3] {
+    let enterprise = config.max_instances * config.enterprise_percent / 100;
+    let pro = config.max_instances * config.pro_percent / 100;
+    [enterprise, pro, config.max_instances - enterprise - pro]
+}
+
+/// Outside the lock: a permit whose execution stopped waiting is dropped,
+/// which releases it again.
+fn hand_over(dispatched: Vec<(oneshot::Sender<PoolPermit>, PoolPermit)>) {
+    for (sender, permit) in dispatched {
+        let _ = sender.send(permit);
+    }
+}
+
+impl Queues {
+    fn push(&mut self, tenant: &str, tier: Tier, sender: oneshot::Sender<PoolPermit>) -> u64 {
+        let last = self.last_tags.get(tenant).copied().unwrap_or_default();
+        let tag = last.max(self.clock) + SERVICE / tier.weight();
+        self.last_tags.insert(tenant.to_string(), tag);
+        let id = self.next_id;
+        self.next_id += 1;
+        let waiter = Waiter { id, tier, tag, queued_at: Instant::now(), sender };
+        self.tenants.entry(tenant.to_string()).or_default().push_back(waiter);
+        id
+    }
+
+    /// The waiting execution with the lowest tag among tenants' first ones
+    /// that `fits` a free slot.
+    fn next(&self, fits: impl Fn(Tier) -> bool) -> Option<(String, u64)> {
+        self.tenants
+            .iter()
+            .filter_map(|(tenant, waiters)| waiters.front().map(|waiter| (tenant, waiter)))
+            .filter(|(_, waiter)| fits(waiter.tier))
+            .min_by_key(|(_, waiter)| waiter.tag)
+            .map(|(tenant, waiter)| (tenant.clone(), waiter.id))
+    }
+
+    fn take(&mut self, tenant: &str, id: u64) -> Waiter {
+        let waiter = self.remove_waiter(tenant, id).expect("the waiter is queued");
+        self.clock = self.clock.max(waiter.tag);
+        waiter
+    }
+
+    /// Whether the waiter was still queued.
+    fn remove(&mut self, tenant: &str, id: u64) -> bool {
+        self.remove_waiter(tenant, id).is_some()
+    }
+
+    fn remove_waiter(&mut self, tenant: &str, id: u64) -> Option<Waiter> {
+        let waiters = self.tenants.get_mut(tenant)?;
+        let index = waiters.iter().position(|waiter| waiter.id == id)?;
+        let waiter = waiters.remove(index);
+        if waiters.is_empty() {
+            self.tenants.remove(tenant);
+            self.last_tags.remove(tenant);
+        }
+        waiter
+    }
+}
+
+fn record(in_use: &[u32; 3], pool: Tier) {
+    gauge!("execution_pool_in_use", "pool" => pool.name()).set(in_use[pool.index()] as f64);
+}
+
+fn record_queued(queues: &Queues) {
+    gauge!("execution_queue_waiting").set(queues.tenants.values().map(VecDeque::len).sum::<usize>() as f64);
+}
+
+/// A taken slot, given back when dropped.
+pub struct PoolPermit {
+    pools: Arc<ExecutionPools>,
+    pool: Tier,
+}
+
+impl Drop for PoolPermit {
+    fn drop(&mut self) {
+        self.pools.release(self.pool);
+    }
+}
+
+/// Resolves tenant plans from Redis (`tenant_plan:{tenant_id}`, the key the
+/// ingestion service reads) with a local cache. Falls back to the last
+/// known or default plan while Redis is unavailable, like the flag client
+/// falls back to flag defaults.
+pub struct PlanResolver {
+    client: Option<redis::Client>,
+    connection: Mutex<Option<Connection>>,
+    default_tier: Tier,
+    cache_ttl: Duration,
+    cache: Mutex<HashMap<String, (Tier, Instant)>>,
+}
+
+impl PlanResolver {
+    /// Connects lazily, like the audit log. Lookups are cached for
+    /// `cache_ttl`.
+    pub fn new(redis_url: &str, default_plan: &str, cache_ttl: Duration) -> Self {
+        let client = redis::Client::open(redis_url)
+            .map_err(|e| warn!("Invalid plan Redis URL, every tenant gets the default plan: {}", e))
+            .ok();
+
+        PlanResolver {
+            client,
+            connection: Mutex::new(None),
+            default_tier: Tier::from_plan(default_plan),
+            cache_ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The tenant's tier; the default plan's for calls without a tenant.
+    pub async fn tier(&self, tenant_id: Option<&str>) -> Tier {
+        let Some(tenant_id) = tenant_id else {
+            return self.default_tier;
+        };
+        let cached = self.cache.lock().await.get(tenant_id).copied();
+        if let Some((tier, _)) = cached.filter(|(_, fetched_at)| fetched_at.elapsed() < self.cache_ttl) {
+            return tier;
+        }
+
+        // A tenant keeps its last known tier while Redis is unavailable
+        let fallback = cached.map_or(self.default_tier, |(tier, _)| tier);
+        let tier = match tokio::time::timeout(PLAN_LOOKUP_TIMEOUT, self.lookup(tenant_id)).await {
+            Ok(Ok(plan)) => plan.as_deref().map_or(self.default_tier, Tier::from_plan),
+            Ok(Err(e)) => {
+                warn!("Plan lookup for tenant {} failed: {}", tenant_id, e);
+                *self.connection.lock().await = None;
+                return fallback;
+            }
+            Err(_) => {
+                warn!("Plan lookup for tenant {} timed out", tenant_id);
+                return fallback;
+            }
+        };
+        debug!("Resolved tier {} for tenant {}", tier.name(), tenant_id);
+
+        self.cache
+            .lock()
+            .await
+            .insert(tenant_id.to_string(), (tier, Instant::now()));
+        tier
+    }
+
+    async fn lookup(&self, tenant_id: &str) -> Result<Option<String>, redis::RedisError> {
+        let Some(client) = &self.client else {
+            return Ok(None);
+        };
+        let mut connection = self.connection.lock().await;
+        if connection.is_none() {
+            *connection = Some(client.get_async_connection().await?);
+        }
+        connection
+            .as_mut()
+            .expect("connection was just set")
+            .get(format!("tenant_plan:{}", tenant_id))
+            .await
+    }
+}
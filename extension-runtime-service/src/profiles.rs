@@ -0,0 +1,92 @@
+//! Security profiles: how much of WebAssembly and the host a plugin gets.
+//!
+//! - `strict`, the default: no SIMD, bulk memory or reference types, and no
+//!   network host functions.
+//! - `standard`: SIMD and bulk memory.
+//! - `trusted`: reference types too, memory up to
+//!   `trusted_max_memory_pages`, and `http_fetch`.
+//!
+//! A plugin asks for a profile in its manifest (see `manifest`) and runs
+//! only if its tenant is allowed that profile or a higher one: at most
+//! `max_security_profile`, or the tenant's own in `tenant_quotas`, e.g.
+//!
+//! ```toml
+//! max_security_profile = "standard"
+//!
+//! [tenant_quotas.acme]
+//! max_security_profile = "trusted"
+//! ```
+//!
+//! WebAssembly features are set per engine, so each profile has an engine
+//! of its own, and with the pooling allocator a pool of its own; so does
+//! each profile's deterministic variant, see `determinism`.
+
+use crate::quotas::Limits;
+use crate::{create_secure_engine, RuntimeConfig};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use wasmtime::Engine;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    #[default]
+    Strict,
+    Standard,
+    Trusted,
+}
+
+impl Profile {
+    pub fn name(self) -> &'static str {
+        match self {
+            Profile::Strict => "strict",
+            Profile::Standard => "standard",
+            Profile::Trusted => "trusted",
+        }
+    }
+
+    /// Whether plugins may reach the network through host functions.
+    pub fn network(self) -> bool {
+        self == Profile::Trusted
+    }
+
+    /// `limits`, with the larger memory trusted plugins get.
+    pub fn limits(self, limits: Limits, config: &RuntimeConfig) -> Limits {
+        match self {
+            Profile::Trusted => Limits {
+                max_memory_pages: limits.max_memory_pages.max(config.trusted_max_memory_pages),
+                ..limits
+            },
+            Profile::Strict | Profile::Standard => limits,
+        }
+    }
+}
+
+/// An engine per profile, and per profile for deterministic plugins.
+#[derive(Clone)]
+pub struct Engines {
+    engines: [Engine; 3],
+    deterministic: [Engine; 3],
+}
+
+impl Engines {
+    pub fn new(config: &RuntimeConfig) -> Result<Self> {
+        let profiles = |deterministic| -> Result<[Engine; 3]> {
+            Ok([
+                create_secure_engine(config, Profile::Strict, deterministic)?,
+                create_secure_engine(config, Profile::Standard, deterministic)?,
+                create_secure_engine(config, Profile::Trusted, deterministic)?,
+            ])
+        };
+        Ok(Engines { engines: profiles(false)?, deterministic: profiles(true)? })
+    }
+
+    pub fn get(&self, profile: Profile, deterministic: bool) -> &Engine {
+        let engines = if deterministic { &self.deterministic } else { &self.engines };
+        &engines[profile as usize]
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = &Engine> {
+        self.engines.iter().chain(&self.deterministic)
+    }
+}
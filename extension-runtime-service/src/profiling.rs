@@ -0,0 +1,76 @@
+//! Guest profiling, for plugin authors asking why their plugin is slow.
+//!
+//! `POST /execute?profile=true` samples the plugin's stack on every epoch
+//! tick, i.e. every `epoch_interval_ms`, while it runs, and returns the
+//! samples as the response's `profile`: a profile in the Firefox
+//! profiler's processed format, which https://profiler.firefox.com and
+//! speedscope show as a flame graph. Failed executions, those interrupted
+//! at their deadline included, return what was sampled until they stopped.
+//! Only core modules can be profiled.
+
+use crate::compiled::{Code, Compiled};
+use crate::host::Host;
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::warn;
+use wasmtime::{GuestProfiler, StoreContextMut};
+
+/// Where an execution's profile ends up, shared with the store sampling it.
+#[derive(Clone, Default)]
+pub struct ProfileReport(Arc<Mutex<Option<serde_json::Value>>>);
+
+impl ProfileReport {
+    pub fn take(&self) -> Option<serde_json::Value> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+}
+
+/// Samples one execution of a plugin.
+pub struct Profiler {
+    guest: GuestProfiler,
+    report: ProfileReport,
+}
+
+impl Profiler {
+    pub fn start(compiled: &Compiled, module_path: &str, interval: Duration, report: &ProfileReport) -> Result<Self> {
+        let Code::Module(module) = &compiled.code else {
+            anyhow::bail!("Components can't be profiled");
+        };
+        let modules = vec![(module_path.to_string(), module.clone())];
+        Ok(Profiler { guest: GuestProfiler::new(module_path, interval, modules), report: report.clone() })
+    }
+
+    /// Writes the profile to its report. A profile that can't be written is
+    /// only logged; the execution it describes still succeeded.
+    pub fn finish(self) {
+        let mut profile = Vec::new();
+        let written = self
+            .guest
+            .finish(&mut profile)
+            .and_then(|()| serde_json::from_slice(&profile).map_err(anyhow::Error::from));
+        match written {
+            Ok(profile) => *self.report.0.lock().unwrap_or_else(|e| e.into_inner()) = Some(profile),
+            Err(e) => warn!("Failed to write guest profile: {:#}", e),
+        }
+    }
+}
+
+/// Samples the stack of the plugin running in `store`, if its execution is
+/// being profiled.
+pub fn sample(mut store: StoreContextMut<'_, Host>) {
+    let Some(mut profiler) = store.data_mut().call.as_mut().and_then(|call| call.profiler.take()) else {
+        return;
+    };
+    profiler.guest.sample(&store);
+    if let Some(call) = store.data_mut().call.as_mut() {
+        call.profiler = Some(profiler);
+    }
+}
+
+/// Stops profiling the execution in `store`, writing its profile.
+pub fn finish(store: &mut wasmtime::Store<Host>) {
+    if let Some(profiler) = store.data_mut().call.as_mut().and_then(|call| call.profiler.take()) {
+        profiler.finish();
+    }
+}
@@ -0,0 +1,297 @@
+//! Per-tenant resource limits.
+//!
+//! Every tenant runs with the runtime's `fuel_limit` and `max_memory_pages`
+//! unless `tenant_quotas` overrides them, e.g. in the config file:
+//!
+//! ```toml
+//! [tenant_quotas.acme]
+//! fuel_limit = 5000000
+//! max_memory_pages = 200
+//! max_concurrent = 4
+//! daily_executions = 100000
+//! max_security_profile = "trusted"
+//! capabilities = ["kv", "crm_read", "clock"]
+//! ```
+//!
+//! `max_concurrent` caps the tenant's in-flight executions on this replica,
+//! on top of its plan pool. `daily_executions` is counted in Redis
+//! (`runtime_executions:{tenant_id}:{yyyymmdd}`, UTC) so it holds across
+//! replicas; while Redis is unavailable executions are let through rather
+//! than failing every tenant. `max_security_profile` is the highest
+//! security profile the tenant's plugins may run with; see `profiles`.
+//! `capabilities` replaces the runtime's `granted_capabilities`; see
+//! `capabilities`.
+//! Executions without a tenant only get the runtime-wide limits.
+//!
+//! A changed config file brings new limits and quotas for executions
+//! admitted from then on; see `reload`.
+
+use crate::capabilities::Capabilities;
+use crate::profiles::Profile;
+use crate::redis_store::{RedisStore, StoreError};
+use crate::RuntimeConfig;
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+/// How long the daily counter may hold up an execution before it is let
+/// through uncounted.
+const COUNTER_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// A tenant's overrides; unset fields use the runtime-wide value.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TenantQuota {
+    pub fuel_limit: Option<u64>,
+    pub max_memory_pages: Option<u32>,
+    pub max_concurrent: Option<u32>,
+    pub daily_executions: Option<u64>,
+    pub max_security_profile: Option<Profile>,
+    pub capabilities: Option<Capabilities>,
+}
+
+impl TenantQuota {
+    pub fn validate(&self, tenant_id: &str, problems: &mut Vec<String>) {
+        let zero = [
+            ("fuel_limit", self.fuel_limit == Some(0)),
+            ("max_memory_pages", self.max_memory_pages == Some(0)),
+            ("max_concurrent", self.max_concurrent == Some(0)),
+            ("daily_executions", self.daily_executions == Some(0)),
+        ];
+        for (field, _) in zero.iter().filter(|(_, zero)| *zero) {
+            problems.push(format!("tenant_quotas.{}.{} must be positive", tenant_id, field));
+        }
+    }
+}
+
+/// What one execution may use.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub fuel_limit: u64,
+    pub max_memory_pages: u32,
+    pub max_table_elements: u32,
+    pub max_security_profile: Profile,
+    /// What the tenant's plugins may be granted.
+    pub capabilities: Capabilities,
+}
+
+impl Limits {
+    /// The runtime-wide limits.
+    pub fn from_config(config: &RuntimeConfig) -> Self {
+        Limits {
+            fuel_limit: config.fuel_limit,
+            max_memory_pages: config.max_memory_pages,
+            max_table_elements: config.max_table_elements,
+            max_security_profile: config.max_security_profile,
+            capabilities: config.granted_capabilities,
+        }
+    }
+}
+
+/// Holds a store to its [`Limits`], noting the most linear memory and
+/// table elements its instances had at once. Kept in the store's data, so
+/// each store, and each execution, has its own.
+#[derive(Debug)]
+pub struct Limiter {
+    memory_limit: usize,
+    table_limit: u32,
+    memory_bytes: usize,
+    peak_memory_bytes: usize,
+    table_elements: u64,
+    peak_table_elements: u64,
+}
+
+impl Limiter {
+    pub fn new(limits: &Limits) -> Self {
+        let mut limiter = Limiter {
+            memory_limit: 0,
+            table_limit: 0,
+            memory_bytes: 0,
+            peak_memory_bytes: 0,
+            table_elements: 0,
+            peak_table_elements: 0,
+        };
+        limiter.set_limits(limits);
+        limiter
+    }
+
+    /// Replaces the limits; memory already allocated stays counted.
+    pub fn set_limits(&mut self, limits: &Limits) {
+        self.memory_limit = limits.max_memory_pages as usize * 65536;
+        self.table_limit = limits.max_table_elements;
+    }
+
+    /// The most bytes of linear memory the store's instances had at once,
+    /// their initial memory included.
+    pub fn peak_memory_bytes(&self) -> u64 {
+        self.peak_memory_bytes as u64
+    }
+
+    /// The most table elements the store's instances had at once.
+    pub fn peak_table_elements(&self) -> u64 {
+        self.peak_table_elements
+    }
+}
+
+impl wasmtime::ResourceLimiter for Limiter {
+    fn memory_growing(&mut self, current: usize, desired: usize, _maximum: Option<usize>) -> anyhow::Result<bool> {
+        if desired > self.memory_limit {
+            return Ok(false);
+        }
+        self.memory_bytes += desired.saturating_sub(current);
+        self.peak_memory_bytes = self.peak_memory_bytes.max(self.memory_bytes);
+        Ok(true)
+    }
+
+    fn table_growing(&mut self, current: u32, desired: u32, _maximum: Option<u32>) -> anyhow::Result<bool> {
+        if desired > self.table_limit {
+            return Ok(false);
+        }
+        self.table_elements += u64::from(desired.saturating_sub(current));
+        self.peak_table_elements = self.peak_table_elements.max(self.table_elements);
+        Ok(true)
+    }
+}
+
+/// Why an execution was turned away.
+#[derive(Debug, PartialEq)]
+pub enum Exceeded {
+    Concurrency(u32),
+    Daily(u64),
+}
+
+impl std::fmt::Display for Exceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Exceeded::Concurrency(max) => write!(f, "Tenant is limited to {} concurrent executions", max),
+            Exceeded::Daily(max) => write!(f, "Tenant has used its {} executions for today", max),
+        }
+    }
+}
+
+/// The runtime-wide limits and the tenants' overrides.
+struct Settings {
+    defaults: Limits,
+    tenants: HashMap<String, TenantQuota>,
+}
+
+impl Settings {
+    fn from_config(config: &RuntimeConfig) -> Self {
+        Settings { defaults: Limits::from_config(config), tenants: config.tenant_quotas.clone() }
+    }
+}
+
+pub struct Quotas {
+    settings: std::sync::RwLock<Arc<Settings>>,
+    running: std::sync::Mutex<HashMap<String, u32>>,
+    redis: Arc<RedisStore>,
+}
+
+impl Quotas {
+    pub fn new(redis_url: &str, config: &RuntimeConfig) -> Arc<Self> {
+        Arc::new(Quotas {
+            settings: std::sync::RwLock::new(Arc::new(Settings::from_config(config))),
+            running: std::sync::Mutex::new(HashMap::new()),
+            redis: RedisStore::new(redis_url),
+        })
+    }
+
+    /// Takes the limits and quotas of `config` from now on; running
+    /// executions keep theirs.
+    pub fn reconfigure(&self, config: &RuntimeConfig) {
+        *self.settings.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(Settings::from_config(config));
+    }
+
+    fn settings(&self) -> Arc<Settings> {
+        self.settings.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn limits(&self, tenant_id: Option<&str>) -> Limits {
+        let settings = self.settings();
+        let defaults = settings.defaults;
+        let Some(quota) = tenant_id.and_then(|tenant_id| settings.tenants.get(tenant_id)) else {
+            return defaults;
+        };
+        Limits {
+            fuel_limit: quota.fuel_limit.unwrap_or(defaults.fuel_limit),
+            max_memory_pages: quota.max_memory_pages.unwrap_or(defaults.max_memory_pages),
+            max_table_elements: defaults.max_table_elements,
+            max_security_profile: quota.max_security_profile.unwrap_or(defaults.max_security_profile),
+            capabilities: quota.capabilities.unwrap_or(defaults.capabilities),
+        }
+    }
+
+    /// Admits one execution for `tenant_id`, counting it against the daily
+    /// cap, or says which limit it would exceed.
+    pub async fn admit(self: &Arc<Self>, tenant_id: Option<&str>) -> Result<QuotaPermit, Exceeded> {
+        let settings = self.settings();
+        let Some((tenant_id, quota)) = tenant_id.and_then(|tenant_id| settings.tenants.get_key_value(tenant_id)) else {
+            return Ok(QuotaPermit { quotas: None, tenant_id: String::new() });
+        };
+
+        if let Some(max) = quota.max_concurrent {
+            let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+            let count = running.entry(tenant_id.clone()).or_default();
+            if *count >= max {
+                counter!("tenant_quota_rejections_total", "limit" => "concurrency").increment(1);
+                return Err(Exceeded::Concurrency(max));
+            }
+            *count += 1;
+        }
+        // Holds the concurrency slot from here, so a rejection below gives it back
+        let permit = QuotaPermit {
+            quotas: quota.max_concurrent.map(|_| Arc::clone(self)),
+            tenant_id: tenant_id.clone(),
+        };
+
+        if let Some(max) = quota.daily_executions {
+            // Not enforced while Redis is unavailable
+            match self.count_today(tenant_id).await {
+                Ok(count) if count > max => {
+                    counter!("tenant_quota_rejections_total", "limit" => "daily").increment(1);
+                    return Err(Exceeded::Daily(max));
+                }
+                Ok(_) | Err(StoreError::Unconfigured) => {}
+                Err(e) => warn!("Daily execution count for tenant {} failed: {}", tenant_id, e),
+            }
+        }
+        Ok(permit)
+    }
+
+    /// Increments and returns today's execution count.
+    async fn count_today(&self, tenant_id: &str) -> Result<u64, StoreError> {
+        let key = format!("runtime_executions:{}:{}", tenant_id, chrono::Utc::now().format("%Y%m%d"));
+        // Kept a day past its own so late replicas still see the count
+        let mut pipeline = redis::pipe();
+        pipeline.atomic().incr(&key, 1).expire(&key, 2 * 86_400).ignore();
+        let (count,): (u64,) = self.redis.run_pipeline(&pipeline, COUNTER_TIMEOUT).await?;
+        Ok(count)
+    }
+
+    fn release(&self, tenant_id: &str) {
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = running.get_mut(tenant_id) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                running.remove(tenant_id);
+            }
+        }
+    }
+}
+
+/// A tenant's concurrency slot, given back when dropped.
+pub struct QuotaPermit {
+    quotas: Option<Arc<Quotas>>,
+    tenant_id: String,
+}
+
+impl Drop for QuotaPermit {
+    fn drop(&mut self) {
+        if let Some(quotas) = &self.quotas {
+            quotas.release(&self.tenant_id);
+        }
+    }
+}
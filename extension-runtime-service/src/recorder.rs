@@ -0,0 +1,347 @@
+//! Where the runtime's `metrics` go: into the Prometheus registry `GET
+//! /metrics` serves, next to the shared HTTP and audit metrics.
+//!
+//! Every execution counts towards `plugin_executions_total`, by module,
+//! tenant and status (`success`, `failure` or `cached`), and failed ones
+//! towards `plugin_execution_errors_total` by their error code (see
+//! `errors`). Executions whose plugin ran record its wall time in
+//! `plugin_execution_duration_seconds` and its fuel in
+//! `plugin_fuel_consumed`, by module and tenant. `active_plugin_instances`
+//! is how many plugins run right now, plugins called by others included.
+//!
+//! Modules are labeled by name, without their version, and inline modules
+//! all as `inline`. Each replica labels at most `metrics_max_modules`
+//! modules and `metrics_max_tenants` tenants by name, the first it sees,
+//! and the rest as `other`, so a tenant uploading module after module
+//! can't blow up the series count. No metric has more than
+//! `MAX_SERIES_PER_METRIC` series; later ones aren't recorded.
+
+use crate::{ExecuteResponse, RuntimeConfig};
+use metrics::atomics::AtomicU64;
+use metrics::{
+    counter, describe_counter, describe_gauge, describe_histogram, histogram, Counter, Gauge, Histogram, HistogramFn, Key,
+    KeyName, Metadata, Recorder, SharedString, Unit,
+};
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::{self, MetricFamily, MetricType};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use tracing::debug;
+
+/// Series a metric may have, whatever its labels.
+const MAX_SERIES_PER_METRIC: usize = 10_000;
+
+/// Label of the modules and tenants past the guards.
+const OTHER: &str = "other";
+
+const DURATION_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+const FUEL_BUCKETS: &[f64] = &[1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10];
+
+/// Histogram buckets of the metric `name`.
+fn buckets(name: &str) -> &'static [f64] {
+    match name {
+        "plugin_fuel_consumed" => FUEL_BUCKETS,
+        _ if name.ends_with("_seconds") => DURATION_BUCKETS,
+        _ => prometheus::DEFAULT_BUCKETS,
+    }
+}
+
+/// Makes the `metrics` macros record into the Prometheus registry. Call
+/// once, before anything is recorded.
+pub fn install() -> anyhow::Result<()> {
+    let series = Arc::new(Series::default());
+    let desc = Desc::new(
+        "runtime_metrics".to_string(),
+        "Metrics recorded through the metrics crate".to_string(),
+        Vec::new(),
+        HashMap::new(),
+    )?;
+    prometheus::register(Box::new(Collected { series: series.clone(), desc }))?;
+    metrics::set_global_recorder(Bridge(series))
+        .map_err(|_| anyhow::anyhow!("A metrics recorder is already installed"))?;
+
+    describe_counter!("plugin_executions_total", "Plugin executions, by module, tenant and status");
+    describe_counter!("plugin_execution_errors_total", "Failed plugin executions, by module, tenant and error code");
+    describe_counter!("plugin_execution_failures_total", "Failed plugin executions, by reason");
+    describe_histogram!("plugin_execution_duration_seconds", Unit::Seconds, "Wall time of plugin executions");
+    describe_histogram!("plugin_fuel_consumed", "Fuel burned by plugin executions");
+    describe_gauge!("active_plugin_instances", "Plugins running right now");
+    Ok(())
+}
+
+#[derive(Default)]
+struct Series {
+    metrics: Mutex<HashMap<String, Metric>>,
+}
+
+#[derive(Default)]
+struct Metric {
+    help: Option<String>,
+    counters: HashMap<Key, Arc<AtomicU64>>,
+    gauges: HashMap<Key, Arc<AtomicU64>>,
+    histograms: HashMap<Key, Arc<Buckets>>,
+}
+
+impl Metric {
+    fn len(&self) -> usize {
+        self.counters.len() + self.gauges.len() + self.histograms.len()
+    }
+}
+
+/// Observations of a histogram series.
+struct Buckets {
+    bounds: &'static [f64],
+    observed: Mutex<Observed>,
+}
+
+#[derive(Default)]
+struct Observed {
+    /// Per bucket, not cumulative; the last is `+Inf`.
+    counts: Vec<u64>,
+    count: u64,
+    sum: f64,
+}
+
+impl HistogramFn for Buckets {
+    fn record(&self, value: f64) {
+        let bucket = self.bounds.iter().position(|bound| value <= *bound).unwrap_or(self.bounds.len());
+        let mut observed = self.observed.lock().unwrap();
+        if observed.counts.is_empty() {
+            observed.counts = vec![0; self.bounds.len() + 1];
+        }
+        observed.counts[bucket] += 1;
+        observed.count += 1;
+        observed.sum += value;
+    }
+}
+
+impl Series {
+    fn describe(&self, name: KeyName, description: SharedString) {
+        self.metrics.lock().unwrap().entry(name.as_str().to_string()).or_default().help = Some(description.to_string());
+    }
+
+    /// The series of `key` in one of `metric`'s maps, made on first use
+    /// unless the metric is full.
+    // `Key` only caches its own hash in its atomics, so it hashes the same
+    #[allow(clippy::mutable_key_type)]
+    fn series<T>(
+        &self,
+        key: &Key,
+        map: impl FnOnce(&mut Metric) -> &mut HashMap<Key, Arc<T>>,
+        make: impl FnOnce() -> T,
+    ) -> Option<Arc<T>> {
+        let mut metrics = self.metrics.lock().unwrap();
+        let metric = metrics.entry(key.name().to_string()).or_default();
+        let full = metric.len() >= MAX_SERIES_PER_METRIC;
+        let series = map(metric);
+        if let Some(series) = series.get(key) {
+            return Some(series.clone());
+        }
+        if full {
+            debug!("Metric {} has {} series; {:?} is not recorded", key.name(), MAX_SERIES_PER_METRIC, key);
+            return None;
+        }
+        let made = Arc::new(make());
+        series.insert(key.clone(), made.clone());
+        Some(made)
+    }
+}
+
+struct Bridge(Arc<Series>);
+
+impl Recorder for Bridge {
+    fn describe_counter(&self, key: KeyName, _unit: Option<Unit>, description: SharedString) {
+        self.0.describe(key, description);
+    }
+
+    fn describe_gauge(&self, key: KeyName, _unit: Option<Unit>, description: SharedString) {
+        self.0.describe(key, description);
+    }
+
+    fn describe_histogram(&self, key: KeyName, _unit: Option<Unit>, description: SharedString) {
+        self.0.describe(key, description);
+    }
+
+    fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        match self.0.series(key, |metric| &mut metric.counters, || AtomicU64::new(0)) {
+            Some(series) => Counter::from_arc(series),
+            None => Counter::noop(),
+        }
+    }
+
+    fn register_gauge(&self, key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        // Gauges keep the bits of an f64
+        match self.0.series(key, |metric| &mut metric.gauges, || AtomicU64::new(0f64.to_bits())) {
+            Some(series) => Gauge::from_arc(series),
+            None => Gauge::noop(),
+        }
+    }
+
+    fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        let bounds = buckets(key.name());
+        let make = || Buckets { bounds, observed: Mutex::new(Observed::default()) };
+        match self.0.series(key, |metric| &mut metric.histograms, make) {
+            Some(series) => Histogram::from_arc(series),
+            None => Histogram::noop(),
+        }
+    }
+}
+
+/// The series, as the Prometheus registry gathers them.
+struct Collected {
+    series: Arc<Series>,
+    desc: Desc,
+}
+
+impl Collector for Collected {
+    fn desc(&self) -> Vec<&Desc> {
+        vec![&self.desc]
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let metrics = self.series.metrics.lock().unwrap();
+        let mut families = Vec::new();
+        for (name, metric) in metrics.iter() {
+            let family = |kind: MetricType| {
+                let mut family = MetricFamily::default();
+                family.set_name(name.clone());
+                family.set_help(metric.help.clone().unwrap_or_else(|| name.clone()));
+                family.set_field_type(kind);
+                family
+            };
+            // A name is used for one kind of metric; the others are empty
+            if !metric.counters.is_empty() {
+                let mut counters = family(MetricType::COUNTER);
+                for (key, series) in &metric.counters {
+                    let mut counter = proto::Counter::default();
+                    counter.set_value(series.load(Ordering::Relaxed) as f64);
+                    let mut sample = sample(key);
+                    sample.set_counter(counter);
+                    counters.mut_metric().push(sample);
+                }
+                families.push(counters);
+            }
+            if !metric.gauges.is_empty() {
+                let mut gauges = family(MetricType::GAUGE);
+                for (key, series) in &metric.gauges {
+                    let mut gauge = proto::Gauge::default();
+                    gauge.set_value(f64::from_bits(series.load(Ordering::Relaxed)));
+                    let mut sample = sample(key);
+                    sample.set_gauge(gauge);
+                    gauges.mut_metric().push(sample);
+                }
+                families.push(gauges);
+            }
+            if !metric.histograms.is_empty() {
+                let mut histograms = family(MetricType::HISTOGRAM);
+                for (key, series) in &metric.histograms {
+                    let observed = series.observed.lock().unwrap();
+                    let mut histogram = proto::Histogram::default();
+                    histogram.set_sample_count(observed.count);
+                    histogram.set_sample_sum(observed.sum);
+                    let mut cumulative = 0;
+                    for (index, bound) in series.bounds.iter().enumerate() {
+                        cumulative += observed.counts.get(index).copied().unwrap_or(0);
+                        let mut bucket = proto::Bucket::default();
+                        bucket.set_upper_bound(*bound);
+                        bucket.set_cumulative_count(cumulative);
+                        histogram.mut_bucket().push(bucket);
+                    }
+                    let mut sample = sample(key);
+                    sample.set_histogram(histogram);
+                    histograms.mut_metric().push(sample);
+                }
+                families.push(histograms);
+            }
+        }
+        families
+    }
+}
+
+/// A sample of the series `key`, labeled but without a value.
+fn sample(key: &Key) -> proto::Metric {
+    let mut labels: Vec<_> = key
+        .labels()
+        .map(|label| {
+            let mut pair = proto::LabelPair::default();
+            pair.set_name(label.key().to_string());
+            pair.set_value(label.value().to_string());
+            pair
+        })
+        .collect();
+    labels.sort_by(|a, b| a.get_name().cmp(b.get_name()));
+    let mut sample = proto::Metric::default();
+    for label in labels {
+        sample.mut_label().push(label);
+    }
+    sample
+}
+
+/// Labels executions by module and tenant, within the guards.
+pub struct PluginMetrics {
+    max_modules: usize,
+    max_tenants: usize,
+    modules: Mutex<HashSet<String>>,
+    tenants: Mutex<HashSet<String>>,
+}
+
+impl PluginMetrics {
+    pub fn new(config: &RuntimeConfig) -> Self {
+        PluginMetrics {
+            max_modules: config.metrics_max_modules,
+            max_tenants: config.metrics_max_tenants,
+            modules: Mutex::new(HashSet::new()),
+            tenants: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn labels(&self, module_path: &str, tenant_id: Option<&str>) -> (String, String) {
+        let module = if module_path.starts_with("inline:") {
+            "inline".to_string()
+        } else {
+            let name = module_path.split_once('@').map_or(module_path, |(name, _)| name);
+            admit(&self.modules, self.max_modules, name)
+        };
+        let tenant = tenant_id.map_or_else(|| "-".to_string(), |tenant_id| admit(&self.tenants, self.max_tenants, tenant_id));
+        (module, tenant)
+    }
+
+    /// Counts an execution of `module_path` for `tenant_id`, whether or
+    /// not its plugin ran.
+    pub fn executed(&self, module_path: &str, tenant_id: Option<&str>, response: &ExecuteResponse) {
+        let (module, tenant) = self.labels(module_path, tenant_id);
+        let status = match (response.cached, response.success) {
+            (true, _) => "cached",
+            (false, true) => "success",
+            (false, false) => "failure",
+        };
+        counter!("plugin_executions_total", "module" => module.clone(), "tenant" => tenant.clone(), "status" => status)
+            .increment(1);
+        if let Some(error) = &response.error {
+            counter!("plugin_execution_errors_total", "module" => module, "tenant" => tenant, "code" => error.code.name())
+                .increment(1);
+        }
+    }
+
+    /// Records what an execution whose plugin ran took.
+    pub fn ran(&self, module_path: &str, tenant_id: Option<&str>, response: &ExecuteResponse) {
+        let (module, tenant) = self.labels(module_path, tenant_id);
+        histogram!("plugin_execution_duration_seconds", "module" => module.clone(), "tenant" => tenant.clone())
+            .record(response.execution_time_ms as f64 / 1000.0);
+        histogram!("plugin_fuel_consumed", "module" => module, "tenant" => tenant).record(response.fuel_consumed as f64);
+    }
+}
+
+/// `value`, if it's one of the first `max` seen, or `OTHER`.
+fn admit(seen: &Mutex<HashSet<String>>, max: usize, value: &str) -> String {
+    let mut seen = seen.lock().unwrap();
+    if seen.contains(value) {
+        return value.to_string();
+    }
+    if seen.len() >= max {
+        return OTHER.to_string();
+    }
+    seen.insert(value.to_string());
+    value.to_string()
+}
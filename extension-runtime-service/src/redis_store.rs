@@ -0,0 +1,158 @@
+//! A Redis connection for one of the runtime's stores.
+//!
+//! Each store kept in Redis (quota counts, plugin KV, plugin settings, jobs,
+//! schedules, rollouts, cached results and idempotency keys) has its own
+//! multiplexed connection, made on first use, so one store's trouble doesn't
+//! reach the others. Calls on a connection don't wait on one another and
+//! each gets its own timeout. A call failing doesn't drop the connection:
+//! it is only dropped, for the next call to reconnect, once it stops
+//! answering a PING.
+
+use redis::aio::MultiplexedConnection;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::warn;
+
+/// How long a connection has to answer the PING after a failed call.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+pub enum StoreError {
+    /// `REDIS_URL` is not a Redis URL.
+    Unconfigured,
+    Failed(redis::RedisError),
+    TimedOut,
+}
+
+impl std::fmt::Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreError::Unconfigured => write!(f, "Redis is not configured"),
+            StoreError::Failed(e) => write!(f, "{}", e),
+            StoreError::TimedOut => write!(f, "Redis timed out"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StoreError::Failed(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+pub struct RedisStore {
+    client: Option<redis::Client>,
+    connection: Mutex<Option<Connected>>,
+    connects: AtomicU64,
+}
+
+#[derive(Clone)]
+struct Connected {
+    /// Tells a reconnected connection from the one a health check is about.
+    id: u64,
+    connection: MultiplexedConnection,
+}
+
+impl RedisStore {
+    /// Connects lazily, on the first call.
+    pub fn new(redis_url: &str) -> Arc<Self> {
+        let client = redis::Client::open(redis_url)
+            .map_err(|e| warn!("Invalid Redis URL, a store kept in Redis is unavailable: {}", e))
+            .ok();
+
+        Arc::new(RedisStore {
+            client,
+            connection: Mutex::new(None),
+            connects: AtomicU64::new(0),
+        })
+    }
+
+    /// Runs `command`, waiting at most `timeout` for its reply.
+    pub async fn run<T: redis::FromRedisValue>(
+        self: &Arc<Self>,
+        command: &redis::Cmd,
+        timeout: Duration,
+    ) -> Result<T, StoreError> {
+        self.query(timeout, |mut connection| async move { command.query_async(&mut connection).await }).await
+    }
+
+    /// Runs `pipeline`, waiting at most `timeout` for its replies.
+    pub async fn run_pipeline<T: redis::FromRedisValue>(
+        self: &Arc<Self>,
+        pipeline: &redis::Pipeline,
+        timeout: Duration,
+    ) -> Result<T, StoreError> {
+        self.query(timeout, |mut connection| async move { pipeline.query_async(&mut connection).await }).await
+    }
+
+    async fn query<T, F, Fut>(self: &Arc<Self>, timeout: Duration, query: F) -> Result<T, StoreError>
+    where
+        F: FnOnce(MultiplexedConnection) -> Fut,
+        Fut: Future<Output = redis::RedisResult<T>>,
+    {
+        let Some(client) = &self.client else {
+            return Err(StoreError::Unconfigured);
+        };
+        let deadline = Instant::now() + timeout;
+        let connected = match tokio::time::timeout_at(deadline, self.connect(client)).await {
+            Ok(Ok(connected)) => connected,
+            Ok(Err(e)) => return Err(StoreError::Failed(e)),
+            Err(_) => return Err(StoreError::TimedOut),
+        };
+        let result = tokio::time::timeout_at(deadline, query(connected.connection.clone())).await;
+        match result {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(e)) => {
+                // Replies Redis refused, e.g. of the wrong type, say nothing
+                // about the connection
+                if e.is_io_error() || e.is_connection_dropped() || e.is_timeout() {
+                    self.check(connected);
+                }
+                Err(StoreError::Failed(e))
+            }
+            Err(_) => {
+                // A slow reply, or an unreachable Redis
+                self.check(connected);
+                Err(StoreError::TimedOut)
+            }
+        }
+    }
+
+    /// The current connection, or a new one. The lock isn't held while
+    /// connecting, so callers racing to connect each make one and the
+    /// first kept is shared.
+    async fn connect(&self, client: &redis::Client) -> redis::RedisResult<Connected> {
+        if let Some(connected) = self.connection.lock().await.clone() {
+            return Ok(connected);
+        }
+        let connection = client.get_multiplexed_tokio_connection().await?;
+        let id = self.connects.fetch_add(1, Ordering::Relaxed);
+        Ok(self.connection.lock().await.get_or_insert(Connected { id, connection }).clone())
+    }
+
+    /// PINGs `connected` in the background, dropping it if it doesn't answer.
+    fn check(self: &Arc<Self>, connected: Connected) {
+        let store = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut connection = connected.connection;
+            let ping = redis::cmd("PING");
+            let pong = ping.query_async::<_, String>(&mut connection);
+            if let Ok(Ok(_)) = tokio::time::timeout(HEALTH_CHECK_TIMEOUT, pong).await {
+                return;
+            }
+            let mut current = store.connection.lock().await;
+            // Unless it was already replaced
+            if current.as_ref().is_some_and(|current| current.id == connected.id) {
+                warn!("Redis connection failed its health check, reconnecting on the next call");
+                *current = None;
+            }
+        });
+    }
+}
@@ -0,0 +1,517 @@
+//! Modules uploaded to an object store, replicated active-passive to a
+//! second region.
+//!
+//! Module bytes are content-addressed, stored once as
+//! `{root}/blobs/{sha256}` however many modules share them. Each module is
+//! `{root}/{id}.meta.json`, where the id is `{name}@{version}` (or just the
+//! name for unversioned uploads), pointing at its blob by checksum. Modules
+//! from before content addressing sit at `{root}/{id}` and are still read.
+//!
+//! Modules uploaded for a tenant are that tenant's alone, under
+//! `{root}/tenants/{tenant_id}/{id}.meta.json`: other tenants can neither
+//! list nor run them. Modules at the root are system modules, published to
+//! every tenant: `POST /modules` without `X-Tenant-ID` and `crmctl module
+//! upload --registry-url` upload those. A tenant's module id is looked up among its own modules
+//! first, so a tenant module shadows a system module with the same id for
+//! that tenant only. Executions without a tenant see system modules only.
+//!
+//! `POST /modules` and `crmctl module upload --registry-url` write to the
+//! primary; a reconciliation loop copies new and changed modules to the
+//! secondary and removes deleted ones. Reads go to the primary and fail over
+//! to the secondary when it errors, and fall back to the last cached copy
+//! when both do, so executions survive a regional object-store outage.
+//!
+//! Execute requests may also name a module by a version requirement, e.g.
+//! `invoice-export@^1.2`, resolved to the highest matching version
+//! registered; see `versions`.
+
+use crate::errors::{self, ErrorCode};
+use anyhow::{Context, Result};
+use futures::TryStreamExt;
+use metrics::counter;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::{ObjectStore, PutPayload};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+use url::Url;
+
+const METADATA_SUFFIX: &str = ".meta.json";
+const BLOBS: &str = "blobs";
+const TENANTS: &str = "tenants";
+
+/// Describes each module; a module is only readable once its metadata
+/// exists and its blob matches it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModuleMetadata {
+    pub name: String,
+    /// Empty for modules uploaded without one.
+    #[serde(default)]
+    pub version: String,
+    /// Hex SHA-256 of the module bytes; also the blob's key.
+    pub sha256: String,
+    pub size: u64,
+    /// Unix seconds.
+    pub uploaded_at: i64,
+    /// The tenant the module belongs to; `None` for system modules. Taken
+    /// from where the metadata is stored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
+}
+
+impl ModuleMetadata {
+    /// What `module_path` names the module by.
+    pub fn id(&self) -> String {
+        module_id(&self.name, &self.version)
+    }
+
+    /// Whether `tenant_id` may see and run the module.
+    pub fn visible_to(&self, tenant_id: Option<&str>) -> bool {
+        self.tenant_id.is_none() || self.tenant_id.as_deref() == tenant_id
+    }
+}
+
+pub fn module_id(name: &str, version: &str) -> String {
+    match version {
+        "" => name.to_string(),
+        version => format!("{}@{}", name, version),
+    }
+}
+
+/// Names and versions are single path segments of letters, digits, `.`,
+/// `_`, `-` (and `+` in versions), so ids can't escape the registry root.
+fn valid_part(part: &str, extra: &[char]) -> bool {
+    !part.is_empty()
+        && !part.starts_with('.')
+        && part.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') || extra.contains(&c))
+}
+
+pub fn check_id(id: &str) -> Result<()> {
+    let (name, version) = id.split_once('@').unwrap_or((id, ""));
+    if !valid_part(name, &[]) || (id.contains('@') && !valid_part(version, &['+'])) || id.ends_with(METADATA_SUFFIX) {
+        anyhow::bail!("Invalid module name: {}", id);
+    }
+    Ok(())
+}
+
+/// Tenants' modules are kept under their id, which must be a single path
+/// segment like everywhere else it names a directory or key.
+pub fn check_tenant(tenant_id: &str) -> Result<()> {
+    if !crate::valid_path_segment(tenant_id) {
+        anyhow::bail!("Invalid tenant id: {}", tenant_id);
+    }
+    Ok(())
+}
+
+/// Whether `name` and `version` make a valid module id.
+pub fn check_name(name: &str, version: &str) -> Result<()> {
+    if name.contains('@') {
+        anyhow::bail!("Invalid module name: {}", name);
+    }
+    check_id(&module_id(name, version))
+}
+
+/// A module by the tenant it belongs to, `None` for system modules, and id.
+type ModuleKey = (Option<String>, String);
+
+struct Replica {
+    region: &'static str,
+    store: Arc<dyn ObjectStore>,
+    root: Path,
+}
+
+impl Replica {
+    fn open(region: &'static str, url: &str, aws_region: Option<&str>) -> Result<Self> {
+        let parsed = Url::parse(url).with_context(|| format!("Invalid {} registry URL", region))?;
+        let (store, root): (Arc<dyn ObjectStore>, Path) = match parsed.scheme() {
+            "s3" | "s3a" => {
+                let mut builder = AmazonS3Builder::from_env().with_url(url);
+                if let Some(aws_region) = aws_region {
+                    builder = builder.with_region(aws_region);
+                }
+                (Arc::new(builder.build()?), Path::from_url_path(parsed.path())?)
+            }
+            _ => {
+                let (store, root) = object_store::parse_url(&parsed)?;
+                (Arc::from(store), root)
+            }
+        };
+        Ok(Replica { region, store, root })
+    }
+
+    /// Where modules from before content addressing are kept; they're all
+    /// system modules.
+    fn legacy_path(&self, id: &str) -> Path {
+        self.root.child(id)
+    }
+
+    fn blob_path(&self, sha256: &str) -> Path {
+        self.root.child(BLOBS).child(sha256)
+    }
+
+    /// Where the metadata of `tenant_id`'s modules, or the system ones, is.
+    fn namespace(&self, tenant_id: Option<&str>) -> Path {
+        match tenant_id {
+            Some(tenant_id) => self.root.child(TENANTS).child(tenant_id),
+            None => self.root.clone(),
+        }
+    }
+
+    fn metadata_path(&self, tenant_id: Option<&str>, id: &str) -> Path {
+        self.namespace(tenant_id).child(format!("{}{}", id, METADATA_SUFFIX))
+    }
+
+    async fn has_blob(&self, sha256: &str) -> object_store::Result<bool> {
+        match self.store.head(&self.blob_path(sha256)).await {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the blob unless it is already there, then the metadata that
+    /// makes the module visible.
+    async fn write(&self, metadata: &ModuleMetadata, bytes: Vec<u8>) -> object_store::Result<()> {
+        if !self.has_blob(&metadata.sha256).await? {
+            self.store.put(&self.blob_path(&metadata.sha256), PutPayload::from(bytes)).await?;
+        }
+        let encoded = serde_json::to_vec(metadata).map_err(|e| object_store::Error::Generic {
+            store: "module registry",
+            source: Box::new(e),
+        })?;
+        let path = self.metadata_path(metadata.tenant_id.as_deref(), &metadata.id());
+        self.store.put(&path, PutPayload::from(encoded)).await?;
+        Ok(())
+    }
+
+    /// Removes the module's metadata, then its blob and any legacy copy
+    /// once no remaining module uses them.
+    async fn remove(&self, metadata: &ModuleMetadata, remaining: &HashMap<ModuleKey, ModuleMetadata>) -> object_store::Result<()> {
+        let id = metadata.id();
+        self.store.delete(&self.metadata_path(metadata.tenant_id.as_deref(), &id)).await?;
+        if !remaining.values().any(|other| other.sha256 == metadata.sha256) {
+            ignore_missing(self.store.delete(&self.blob_path(&metadata.sha256)).await)?;
+        }
+        if metadata.tenant_id.is_some() {
+            return Ok(());
+        }
+        ignore_missing(self.store.delete(&self.legacy_path(&id)).await)
+    }
+
+    async fn metadata(&self, tenant_id: Option<&str>, id: &str) -> object_store::Result<ModuleMetadata> {
+        let bytes = self.store.get(&self.metadata_path(tenant_id, id)).await?.bytes().await?;
+        let mut metadata: ModuleMetadata = serde_json::from_slice(&bytes).map_err(|e| object_store::Error::Generic {
+            store: "module registry",
+            source: Box::new(e),
+        })?;
+        metadata.tenant_id = tenant_id.map(str::to_string);
+        Ok(metadata)
+    }
+
+    /// The module, checked against its metadata so a half-replicated
+    /// upload is never executed.
+    async fn read(&self, tenant_id: Option<&str>, id: &str) -> object_store::Result<Vec<u8>> {
+        let metadata = self.metadata(tenant_id, id).await?;
+        let bytes = match self.store.get(&self.blob_path(&metadata.sha256)).await {
+            Ok(blob) => blob.bytes().await?,
+            Err(object_store::Error::NotFound { .. }) if tenant_id.is_none() => {
+                self.store.get(&self.legacy_path(id)).await?.bytes().await?
+            }
+            Err(e) => return Err(e),
+        };
+        if hex::encode(Sha256::digest(&bytes)) != metadata.sha256 {
+            return Err(object_store::Error::Generic {
+                store: "module registry",
+                source: format!("checksum mismatch for {} in {} region", id, self.region).into(),
+            });
+        }
+        Ok(bytes.to_vec())
+    }
+
+    /// `tenant_id`'s module `id`, or the system module when it has none.
+    async fn read_visible(&self, tenant_id: Option<&str>, id: &str) -> object_store::Result<Vec<u8>> {
+        if tenant_id.is_some() {
+            match self.read(tenant_id, id).await {
+                Err(object_store::Error::NotFound { .. }) => {}
+                read => return read,
+            }
+        }
+        self.read(None, id).await
+    }
+
+    /// Metadata of every module, by tenant and id.
+    async fn list(&self) -> object_store::Result<HashMap<ModuleKey, ModuleMetadata>> {
+        let objects: Vec<_> = self.store.list(Some(&self.root)).try_collect().await?;
+        let mut modules = HashMap::new();
+        for object in objects {
+            let Some(parts) = object.location.prefix_match(&self.root) else {
+                continue;
+            };
+            let parts: Vec<_> = parts.collect();
+            // System modules at the root, tenants' under `tenants/{tenant_id}`
+            let (tenant_id, file) = match parts.as_slice() {
+                [file] => (None, file),
+                [tenants, tenant_id, file] if tenants.as_ref() == TENANTS => (Some(tenant_id.as_ref()), file),
+                _ => continue,
+            };
+            let Some(id) = file.as_ref().strip_suffix(METADATA_SUFFIX) else {
+                continue;
+            };
+            let metadata = self.metadata(tenant_id, id).await?;
+            modules.insert((metadata.tenant_id.clone(), id.to_string()), metadata);
+        }
+        Ok(modules)
+    }
+}
+
+fn ignore_missing(result: object_store::Result<()>) -> object_store::Result<()> {
+    match result {
+        Err(object_store::Error::NotFound { .. }) => Ok(()),
+        result => result,
+    }
+}
+
+/// What one reconciliation pass changed on the secondary.
+#[derive(Debug, Default)]
+pub struct Reconciled {
+    pub copied: usize,
+    pub deleted: usize,
+}
+
+/// Where the registry and its replica are, from the runtime config.
+#[derive(Debug, Clone)]
+pub struct RegistryConfig {
+    pub primary_url: String,
+    pub secondary_url: Option<String>,
+    /// AWS region of an S3 secondary, when it isn't the default one.
+    pub secondary_region: Option<String>,
+    /// How long fetched modules and listings are reused.
+    pub cache_ttl: Duration,
+}
+
+/// Module bytes and when they were fetched.
+type Cached = (Arc<Vec<u8>>, Instant);
+
+pub struct ModuleRegistry {
+    primary: Replica,
+    secondary: Option<Replica>,
+    /// Modules fetched for a tenant, or without one, by tenant and id.
+    cache: Mutex<HashMap<ModuleKey, Cached>>,
+    /// Every module on the primary and when it was listed, for resolving
+    /// version requirements.
+    listing: Mutex<Option<(Vec<ModuleMetadata>, Instant)>>,
+    cache_ttl: Duration,
+}
+
+impl ModuleRegistry {
+    pub fn open(config: &RegistryConfig) -> Result<Self> {
+        let secondary = match &config.secondary_url {
+            Some(url) => Some(Replica::open("secondary", url, config.secondary_region.as_deref())?),
+            None => None,
+        };
+        info!(
+            "Module registry at {}{}",
+            config.primary_url,
+            config.secondary_url.as_deref().map(|url| format!(", replicated to {}", url)).unwrap_or_default()
+        );
+
+        Ok(ModuleRegistry {
+            primary: Replica::open("primary", &config.primary_url, None)?,
+            secondary,
+            cache: Mutex::new(HashMap::new()),
+            listing: Mutex::new(None),
+            cache_ttl: config.cache_ttl,
+        })
+    }
+
+    /// The bytes of the module `tenant_id` runs as `name`, its own or a
+    /// system module, from the cache, the primary, the secondary, or
+    /// finally a stale cached copy.
+    pub async fn fetch(&self, tenant_id: Option<&str>, name: &str) -> Result<Arc<Vec<u8>>> {
+        check_id(name)?;
+        if let Some(tenant_id) = tenant_id {
+            check_tenant(tenant_id)?;
+        }
+        let key = (tenant_id.map(str::to_string), name.to_string());
+        let cached = self.cache.lock().await.get(&key).cloned();
+        if let Some((bytes, _)) = cached.as_ref().filter(|(_, fetched)| fetched.elapsed() < self.cache_ttl) {
+            return Ok(bytes.clone());
+        }
+
+        let primary_error = match self.primary.read_visible(tenant_id, name).await {
+            Ok(bytes) => return Ok(self.cache(key, bytes).await),
+            // The primary is the source of truth for what exists
+            Err(object_store::Error::NotFound { .. }) => {
+                return Err(errors::coded(ErrorCode::ModuleNotFound, anyhow::anyhow!("Module not found: {}", name)));
+            }
+            Err(e) => e,
+        };
+        warn!("Primary module registry failed for {}: {}", name, primary_error);
+
+        if let Some(secondary) = &self.secondary {
+            counter!("module_registry_failovers_total").increment(1);
+            match secondary.read_visible(tenant_id, name).await {
+                Ok(bytes) => return Ok(self.cache(key, bytes).await),
+                Err(e) => warn!("Secondary module registry failed for {}: {}", name, e),
+            }
+        }
+        match cached {
+            Some((bytes, _)) => {
+                warn!("Serving cached copy of {} while the registry is unavailable", name);
+                Ok(bytes)
+            }
+            None => Err(primary_error).with_context(|| format!("Module registry unavailable for {}", name)),
+        }
+    }
+
+    /// Stores `bytes` as `name@version` of `tenant_id`, or as a system
+    /// module, on the primary, replacing any module with that id there.
+    pub async fn upload(&self, tenant_id: Option<&str>, name: &str, version: &str, bytes: Vec<u8>) -> Result<ModuleMetadata> {
+        check_name(name, version)?;
+        if let Some(tenant_id) = tenant_id {
+            check_tenant(tenant_id)?;
+        }
+        let metadata = ModuleMetadata {
+            name: name.to_string(),
+            version: version.to_string(),
+            sha256: hex::encode(Sha256::digest(&bytes)),
+            size: bytes.len() as u64,
+            uploaded_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or_default(),
+            tenant_id: tenant_id.map(str::to_string),
+        };
+
+        self.primary.write(&metadata, bytes).await.context("Writing to primary registry")?;
+        self.forget(&metadata).await;
+        info!("Uploaded module {} ({} bytes, sha256 {})", describe(&metadata), metadata.size, metadata.sha256);
+        Ok(metadata)
+    }
+
+    /// Every module on the primary, system modules first, by id.
+    pub async fn list(&self) -> Result<Vec<ModuleMetadata>> {
+        let mut modules: Vec<_> = self.primary.list().await.context("Listing primary registry")?.into_values().collect();
+        modules.sort_by_key(|metadata| (metadata.tenant_id.clone(), metadata.id()));
+        Ok(modules)
+    }
+
+    /// Removes module `id` of `tenant_id`, or the system module, from the
+    /// primary; `false` when there was none.
+    pub async fn delete(&self, tenant_id: Option<&str>, id: &str) -> Result<bool> {
+        check_id(id)?;
+        let mut modules = self.primary.list().await.context("Listing primary registry")?;
+        let Some(metadata) = modules.remove(&(tenant_id.map(str::to_string), id.to_string())) else {
+            return Ok(false);
+        };
+        self.primary.remove(&metadata, &modules).await.context("Deleting from primary registry")?;
+        self.forget(&metadata).await;
+        info!("Deleted module {}", describe(&metadata));
+        Ok(true)
+    }
+
+    /// Drops what was cached of the module: for a system module, every
+    /// tenant's copy, as it may have fetched that one.
+    async fn forget(&self, metadata: &ModuleMetadata) {
+        let id = metadata.id();
+        self.cache.lock().await.retain(|(tenant_id, cached), _| {
+            *cached != id || (metadata.tenant_id.is_some() && *tenant_id != metadata.tenant_id)
+        });
+        *self.listing.lock().await = None;
+    }
+
+    /// The versions of `name` registered for `tenant_id`, its own and the
+    /// system ones, from a listing of the primary reused for
+    /// `registry_cache_ttl_secs`, or a stale one while the primary can't be
+    /// listed.
+    pub async fn versions(&self, tenant_id: Option<&str>, name: &str) -> Result<Vec<ModuleMetadata>> {
+        let mut listing = self.listing.lock().await;
+        let fresh = listing.as_ref().is_some_and(|(_, listed)| listed.elapsed() < self.cache_ttl);
+        if !fresh {
+            match self.list().await {
+                Ok(modules) => *listing = Some((modules, Instant::now())),
+                Err(e) if listing.is_some() => warn!("Resolving versions of {} from a stale listing: {:#}", name, e),
+                Err(e) => return Err(e),
+            }
+        }
+        let modules = listing.as_ref().map(|(modules, _)| modules.as_slice()).unwrap_or_default();
+        Ok(modules
+            .iter()
+            .filter(|metadata| metadata.name == name && !metadata.version.is_empty() && metadata.visible_to(tenant_id))
+            .cloned()
+            .collect())
+    }
+
+    async fn cache(&self, key: ModuleKey, bytes: Vec<u8>) -> Arc<Vec<u8>> {
+        let bytes = Arc::new(bytes);
+        self.cache.lock().await.insert(key, (bytes.clone(), Instant::now()));
+        bytes
+    }
+
+    /// Makes the secondary match the primary. Blobs are copied before the
+    /// metadata pointing at them and deleted after it, so readers of the
+    /// secondary never see metadata without its module. Does nothing when
+    /// either side can't be listed, rather than deleting on a partial view.
+    pub async fn reconcile(&self) -> Result<Reconciled> {
+        let Some(secondary) = &self.secondary else {
+            return Ok(Reconciled::default());
+        };
+        let wanted = self.primary.list().await.context("Listing primary registry")?;
+        let present = secondary.list().await.context("Listing secondary registry")?;
+        let mut reconciled = Reconciled::default();
+
+        for (key, metadata) in &wanted {
+            if present.get(key) == Some(metadata) {
+                continue;
+            }
+            let bytes = match secondary.has_blob(&metadata.sha256).await? {
+                true => Vec::new(),
+                false => self.primary.read(metadata.tenant_id.as_deref(), &metadata.id()).await?,
+            };
+            secondary.write(metadata, bytes).await?;
+            reconciled.copied += 1;
+        }
+        // Once reconciled the secondary holds exactly what the primary does
+        for (_, metadata) in present.iter().filter(|(key, _)| !wanted.contains_key(*key)) {
+            secondary.remove(metadata, &wanted).await?;
+            reconciled.deleted += 1;
+        }
+        Ok(reconciled)
+    }
+
+    pub fn spawn_reconcile(self: Arc<Self>, every: Duration) {
+        if self.secondary.is_none() {
+            return;
+        }
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            loop {
+                interval.tick().await;
+                match self.reconcile().await {
+                    Ok(Reconciled { copied: 0, deleted: 0 }) => {}
+                    Ok(reconciled) => info!(
+                        "Replicated modules to secondary registry: {} copied, {} deleted",
+                        reconciled.copied, reconciled.deleted
+                    ),
+                    Err(e) => {
+                        counter!("module_registry_reconcile_failures_total").increment(1);
+                        error!("Module registry reconciliation failed: {:#}", e);
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// The module's id, with its tenant's for logs.
+fn describe(metadata: &ModuleMetadata) -> String {
+    match &metadata.tenant_id {
+        Some(tenant_id) => format!("{} of tenant {}", metadata.id(), tenant_id),
+        None => metadata.id(),
+    }
+}
@@ -0,0 +1,169 @@
+//! Configuration changes applied without a restart.
+//!
+//! The runtime's settings are its defaults, then `RUNTIME_CONFIG_FILE`
+//! (TOML or JSON), then `RUNTIME_*` variables. When the file is set it is
+//! checked every `config_reload_interval_secs` and, once it changed,
+//! loaded again the same way, e.g. after editing:
+//!
+//! ```toml
+//! fuel_limit = 5000000
+//! max_memory_pages = 200
+//! max_instances = 200
+//! max_timeout_secs = 60
+//!
+//! [tenant_quotas.acme]
+//! fuel_limit = 10000000
+//! ```
+//!
+//! Executions started from then on run with the new limits; running ones
+//! keep the configuration they started with. What was set up at startup,
+//! such as the listen addresses, engines, module storage and the Redis
+//! backed stores, keeps its settings: changing those logs a warning that
+//! they need a restart, and until then the runtime goes on reading their
+//! old values, so e.g. `/validate` checks what executions do. With
+//! `pooling_allocator`, memory past what the pools were sized for at
+//! startup needs one too. A file that doesn't load or validate is logged
+//! and the current configuration kept. Applied changes are recorded on the
+//! admin audit trail as `config.reloaded`.
+
+use crate::{RuntimeConfig, ServiceState};
+use anyhow::Result;
+use crm_audit::AuditEvent;
+use crm_config::{ConfigError, ConfigLoader, Configurable};
+use metrics::counter;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// Variable naming the config file.
+const CONFIG_FILE_VAR: &str = "RUNTIME_CONFIG_FILE";
+
+/// Settings later executions pick up; changing any other needs a restart.
+const RELOADED: &[&str] = &[
+    "max_memory_pages",
+    "max_table_elements",
+    "max_security_profile",
+    "trusted_max_memory_pages",
+    "granted_capabilities",
+    "fuel_limit",
+    "tenant_quotas",
+    "max_instances",
+    "pool_enterprise_percent",
+    "pool_pro_percent",
+    "queue_depth_per_tenant",
+    "queue_timeout_ms",
+    "max_timeout_secs",
+    "max_output_bytes",
+    "max_plugin_logs",
+    "wasi_grants",
+    "request_env",
+    "scratch_dir",
+    "version_pins",
+    "plugin_settings_max_bytes",
+    "batch_max_items",
+    "batch_max_parallel",
+    "pipeline_max_steps",
+    "inline_modules",
+    "inline_fuel_limit",
+    "inline_max_timeout_secs",
+    "breaker_failure_percent",
+    "breaker_min_executions",
+    "breaker_window_secs",
+    "breaker_open_secs",
+    "config_reload_interval_secs",
+];
+
+/// The defaults, then the config file, then `RUNTIME_*` variables.
+pub fn load() -> Result<RuntimeConfig, ConfigError> {
+    ConfigLoader::new().file_from_env(CONFIG_FILE_VAR).env_prefix("RUNTIME_").load()
+}
+
+/// Reloads the config file whenever it changes, if there is one.
+pub fn spawn_watch(state: Arc<ServiceState>) {
+    let Some(path) = std::env::var_os(CONFIG_FILE_VAR).filter(|path| !path.is_empty()).map(PathBuf::from) else {
+        return;
+    };
+    tokio::spawn(async move {
+        let mut modified = modified_time(&path);
+        loop {
+            tokio::time::sleep(Duration::from_secs(state.config().config_reload_interval_secs)).await;
+            let current = modified_time(&path);
+            if current == modified {
+                continue;
+            }
+            // Read before loading, so a write racing the load is seen next time
+            modified = current;
+            match reload(&state, &path).await {
+                Ok(changed) if changed.is_empty() => {}
+                Ok(changed) => {
+                    counter!("runtime_config_reloads_total", "result" => "applied").increment(1);
+                    info!("Reloaded runtime configuration from {}: {}", path.display(), changed.join(", "));
+                }
+                Err(e) => {
+                    counter!("runtime_config_reloads_total", "result" => "rejected").increment(1);
+                    warn!("Keeping current runtime configuration: {:#}", e);
+                }
+            }
+        }
+    });
+}
+
+/// Loads the configuration again and applies what later executions pick
+/// up, returning the settings that changed.
+async fn reload(state: &ServiceState, path: &Path) -> Result<Vec<String>> {
+    let config = load()?;
+    let config = state.secrets.resolve(&config).await?;
+    let current = state.config();
+    let changed = changed_settings(&current, &config)?;
+    if changed.is_empty() {
+        return Ok(changed);
+    }
+    let (applied, restart): (Vec<&str>, Vec<&str>) = changed.iter().map(String::as_str).partition(|key| RELOADED.contains(key));
+    if !restart.is_empty() {
+        warn!("Changed settings take effect after a restart: {}", restart.join(", "));
+    }
+    if applied.is_empty() {
+        return Ok(Vec::new());
+    }
+    let config = with_settings(&current, &config, &applied)?;
+
+    state.quotas.reconfigure(&config);
+    state.pools.resize(&config.pool_config());
+    *state.config.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(config);
+    let details = json!({ "changed": applied, "needs_restart": restart });
+    state
+        .admin_audit
+        .record(AuditEvent::new("config-file", "config.reloaded", path.display().to_string()).details(details))
+        .await;
+    Ok(applied.into_iter().map(str::to_string).collect())
+}
+
+/// `current` with the settings named by `keys` taken from `new`.
+fn with_settings(current: &RuntimeConfig, new: &RuntimeConfig, keys: &[&str]) -> Result<RuntimeConfig> {
+    let (Value::Object(mut settings), Value::Object(new)) = (serde_json::to_value(current)?, serde_json::to_value(new)?) else {
+        anyhow::bail!("The runtime configuration isn't a table of settings");
+    };
+    for key in keys {
+        if let Some(value) = new.get(*key) {
+            settings.insert(key.to_string(), value.clone());
+        }
+    }
+    let config: RuntimeConfig = serde_json::from_value(Value::Object(settings))?;
+    // Checked again, as some settings are only valid together
+    config.validate().map_err(|problems| anyhow::anyhow!("Invalid configuration: {}", problems.join("; ")))?;
+    Ok(config)
+}
+
+/// Names of the settings that differ between `current` and `new`.
+fn changed_settings(current: &RuntimeConfig, new: &RuntimeConfig) -> Result<Vec<String>> {
+    let (Value::Object(current), Value::Object(new)) = (serde_json::to_value(current)?, serde_json::to_value(new)?) else {
+        anyhow::bail!("The runtime configuration isn't a table of settings");
+    };
+    Ok(new.into_iter().filter(|(key, value)| current.get(key) != Some(value)).map(|(key, _)| key).collect())
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
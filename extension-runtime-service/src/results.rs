@@ -0,0 +1,182 @@
+//! Result caching, for pure plugins called again and again with the same
+//! params, e.g. scoring the same lead payload. A plugin opts in by
+//! declaring how long its results stay valid in its manifest (see
+//! `manifest`):
+//!
+//! ```json
+//! {"deterministic": true, "cache_ttl_secs": 300}
+//! ```
+//!
+//! Its successful results are then kept, for at most
+//! `result_cache_max_ttl_secs`, and returned for calls of the same module,
+//! down to its content hash, by the same tenant, of the same function with
+//! the same params, encodings, environment and seed, without running it or
+//! taking a quota or plan pool slot. Cached responses say `"cached":
+//! true`; they have no output, and burned no fuel. Executions asking for
+//! secrets, logs or a profile always run, and results longer than
+//! `result_cache_max_bytes` aren't kept.
+//!
+//! Results are kept in Redis, under `runtime_results:{sha256}:{tenant}:…`,
+//! so every replica shares them; a new version of a module has another
+//! hash, so it never gets the old one's results. `DELETE
+//! /admin/results/{module_path}` drops a module's results, for a tenant
+//! with `?tenant_id=`, which also looks the path up among that tenant's own
+//! modules, and is recorded on the admin audit trail. Lookups
+//! count towards `plugin_result_cache_total`, by result.
+
+use crate::compiled::Compiled;
+use crate::redis_store::RedisStore;
+use crate::{compiled, ExecuteRequest, RuntimeConfig, ServiceState};
+use anyhow::{Context, Result};
+use crm_audit::AuditEvent;
+use metrics::counter;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use warp::http::StatusCode;
+use warp::Filter;
+
+/// How long a result cache call may wait on Redis; a slow cache is a miss.
+const RESULTS_TIMEOUT: Duration = Duration::from_millis(500);
+
+const RESULTS_PREFIX: &str = "runtime_results";
+
+/// Where an execution's result is kept, and for how long.
+#[derive(Debug, Clone)]
+pub struct CacheKey {
+    key: String,
+    ttl: Duration,
+}
+
+pub struct ResultCache {
+    redis: Arc<RedisStore>,
+    max_ttl: Duration,
+    max_bytes: usize,
+}
+
+impl ResultCache {
+    pub fn new(redis_url: &str, config: &RuntimeConfig) -> Arc<Self> {
+        Arc::new(ResultCache {
+            redis: RedisStore::new(redis_url),
+            max_ttl: Duration::from_secs(config.result_cache_max_ttl_secs),
+            max_bytes: config.result_cache_max_bytes,
+        })
+    }
+
+    /// Where the result of `req` to `compiled` is kept, when it may be.
+    pub fn key(&self, compiled: &Compiled, req: &ExecuteRequest) -> Option<CacheKey> {
+        let ttl = compiled.cache_ttl?.min(self.max_ttl);
+        if ttl.is_zero() || !req.secrets.is_empty() || req.include_logs || req.profile || req.logs.is_some() {
+            return None;
+        }
+        let env: BTreeMap<_, _> = req.env.iter().collect();
+        let call = json!([
+            req.function_name,
+            req.params,
+            format!("{:?}", req.calling_convention),
+            format!("{:?}", req.result_encoding),
+            env,
+            req.seed,
+        ]);
+        let hash = hex::encode(Sha256::digest(call.to_string()));
+        let tenant_id = req.tenant_id.as_deref().unwrap_or("-");
+        Some(CacheKey { key: format!("{}:{}:{}:{}", RESULTS_PREFIX, compiled.sha256, tenant_id, hash), ttl })
+    }
+
+    /// The result kept under `key`, if any.
+    pub async fn get(&self, key: &CacheKey) -> Option<Value> {
+        let stored: Option<String> = match self.run(redis::cmd("GET").arg(&key.key)).await {
+            Ok(stored) => stored,
+            Err(e) => {
+                debug!("Result cache lookup failed: {:#}", e);
+                counter!("plugin_result_cache_total", "result" => "error").increment(1);
+                return None;
+            }
+        };
+        let result = stored.and_then(|stored| serde_json::from_str(&stored).ok());
+        counter!("plugin_result_cache_total", "result" => if result.is_some() { "hit" } else { "miss" }).increment(1);
+        result
+    }
+
+    /// Keeps `result` under `key`, unless it's too long.
+    pub async fn put(&self, key: &CacheKey, result: &Value) {
+        let stored = result.to_string();
+        if stored.len() > self.max_bytes {
+            return;
+        }
+        if let Err(e) = self.run::<()>(redis::cmd("SET").arg(&key.key).arg(stored).arg("EX").arg(key.ttl.as_secs().max(1))).await {
+            debug!("Result not cached: {:#}", e);
+        }
+    }
+
+    /// Drops the results of the module with content hash `sha256`, of
+    /// `tenant_id` or every tenant; how many there were.
+    pub async fn invalidate(&self, sha256: &str, tenant_id: Option<&str>) -> Result<usize> {
+        let pattern = format!("{}:{}:{}:*", RESULTS_PREFIX, sha256, tenant_id.unwrap_or("*"));
+        let mut cursor = 0u64;
+        let mut deleted = 0;
+        loop {
+            let (next, keys): (u64, Vec<String>) =
+                self.run(redis::cmd("SCAN").arg(cursor).arg("MATCH").arg(&pattern).arg("COUNT").arg(1000)).await?;
+            if !keys.is_empty() {
+                deleted += self.run::<usize>(redis::cmd("DEL").arg(&keys)).await?;
+            }
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        info!("Dropped {} cached results of module {}", deleted, sha256);
+        Ok(deleted)
+    }
+
+    /// Runs `command` on the store's Redis connection.
+    async fn run<T: redis::FromRedisValue>(&self, command: &redis::Cmd) -> Result<T> {
+        self.redis.run(command, RESULTS_TIMEOUT).await.context("Result cache unavailable")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct InvalidateQuery {
+    #[serde(default)]
+    tenant_id: Option<String>,
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+fn reply(status: StatusCode, body: Value) -> Reply {
+    warp::reply::with_status(warp::reply::json(&body), status)
+}
+
+pub fn routes(
+    state: Arc<ServiceState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("admin" / "results" / String)
+        .and(warp::delete())
+        .and(warp::query::<InvalidateQuery>())
+        .and(crm_audit::actor())
+        .and(warp::any().map(move || state.clone()))
+        .then(invalidate)
+}
+
+async fn invalidate(module_path: String, query: InvalidateQuery, actor: String, state: Arc<ServiceState>) -> Reply {
+    let compiled = match compiled::load(&state, query.tenant_id.as_deref(), &module_path).await {
+        Ok(compiled) => compiled,
+        Err(e) => return reply(StatusCode::NOT_FOUND, json!({ "error": format!("{:#}", e) })),
+    };
+    match state.results.invalidate(&compiled.sha256, query.tenant_id.as_deref()).await {
+        Ok(deleted) => {
+            let details = json!({ "sha256": compiled.sha256, "tenant_id": query.tenant_id, "deleted": deleted });
+            state.admin_audit.record(AuditEvent::new(actor, "results.invalidated", &module_path).details(details)).await;
+            reply(StatusCode::OK, json!({ "module_path": module_path, "sha256": compiled.sha256, "deleted": deleted }))
+        }
+        Err(e) => {
+            warn!("Result cache invalidation failed: {:#}", e);
+            reply(StatusCode::SERVICE_UNAVAILABLE, json!({ "error": format!("{:#}", e) }))
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! Gradual rollouts of new plugin versions: executions naming a plugin by
+//! its bare name, e.g. `invoice-export`, are split between a `stable` and
+//! a `canary` version of it in the module registry.
+//!
+//! - `PUT /admin/rollouts/{plugin}`: `{"stable": "1.3.1", "canary":
+//!   "2.0.0", "canary_percent": 10, "key": "tenant"}` starts or adjusts a
+//!   rollout; both versions must be registered as system modules.
+//! - `GET /admin/rollouts`: every rollout, by plugin.
+//! - `DELETE /admin/rollouts/{plugin}`: ends one; bare names then run the
+//!   unversioned module again. Cutting over is `canary_percent` 100 first.
+//!
+//! `key` keeps the split consistent: with `tenant` (the default) each
+//! tenant stays on one version, with `user` each authenticated caller does,
+//! falling back to the tenant without authentication. Executions without
+//! either are split at random. Raising `canary_percent` only moves callers
+//! from stable to canary, never back.
+//!
+//! Rollouts are kept in Redis, in the hash `runtime_rollouts`, so every
+//! replica routes alike, each rereading them every
+//! `rollout_cache_ttl_secs`. Executions report the version they ran as
+//! `resolved_version`, and each version gets its own
+//! `plugin_rollout_executions_total` and
+//! `plugin_rollout_execution_duration_seconds` series, labeled by plugin,
+//! version and, for executions, status, to compare them before cutting
+//! over. Changes are recorded on the admin audit trail.
+
+use crate::redis_store::RedisStore;
+use crate::registry::{self, module_id};
+use crate::{ExecuteRequest, ExecuteResponse, RuntimeConfig, ServiceState};
+use anyhow::{Context, Result};
+use crm_audit::AuditEvent;
+use metrics::{counter, histogram};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use warp::http::StatusCode;
+use warp::Filter;
+
+/// How long a rollout store call may wait on Redis.
+const ROLLOUTS_TIMEOUT: Duration = Duration::from_secs(2);
+
+const ROLLOUTS_KEY: &str = "runtime_rollouts";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RolloutKey {
+    #[default]
+    Tenant,
+    User,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rollout {
+    pub stable: String,
+    pub canary: String,
+    /// Share of executions routed to `canary`, 0 to 100.
+    pub canary_percent: u8,
+    #[serde(default)]
+    pub key: RolloutKey,
+}
+
+impl Rollout {
+    /// Why the rollout of `plugin` can't be routed, if it can't.
+    fn problem(&self, plugin: &str) -> Option<String> {
+        if let Err(e) = registry::check_name(plugin, "") {
+            return Some(e.to_string());
+        }
+        if let Some(e) = [&self.stable, &self.canary].into_iter().find_map(|version| registry::check_name(plugin, version).err()) {
+            return Some(e.to_string());
+        }
+        if self.stable.is_empty() || self.canary.is_empty() {
+            return Some("A rollout needs a stable and a canary version".to_string());
+        }
+        if self.stable == self.canary {
+            return Some("The stable and canary versions must differ".to_string());
+        }
+        if self.canary_percent > 100 {
+            return Some("canary_percent must be at most 100".to_string());
+        }
+        None
+    }
+
+    /// The version an execution keyed by `key` runs; random without one.
+    fn version(&self, plugin: &str, key: Option<&str>) -> &str {
+        let bucket = match key {
+            Some(key) => {
+                let digest = Sha256::digest(format!("{}:{}", plugin, key));
+                u64::from_be_bytes(digest[..8].try_into().expect("digest has 8 bytes")) % 100
+            }
+            None => (uuid::Uuid::new_v4().as_u128() % 100) as u64,
+        };
+        if bucket < u64::from(self.canary_percent) { &self.canary } else { &self.stable }
+    }
+}
+
+/// Where a rollout sent an execution.
+#[derive(Debug, Clone)]
+pub struct Routed {
+    pub plugin: String,
+    pub version: String,
+}
+
+impl Routed {
+    /// Counts the execution towards its version's metrics.
+    pub fn record(&self, response: &ExecuteResponse) {
+        let status = if response.success { "success" } else { "failure" };
+        counter!(
+            "plugin_rollout_executions_total",
+            "plugin" => self.plugin.clone(),
+            "version" => self.version.clone(),
+            "status" => status
+        )
+        .increment(1);
+        histogram!(
+            "plugin_rollout_execution_duration_seconds",
+            "plugin" => self.plugin.clone(),
+            "version" => self.version.clone()
+        )
+        .record(response.execution_time_ms as f64 / 1000.0);
+    }
+}
+
+/// Rollouts and when they were read.
+type Cached = (Arc<HashMap<String, Rollout>>, Instant);
+
+pub struct Rollouts {
+    redis: Arc<RedisStore>,
+    cache_ttl: Duration,
+    cache: Mutex<Option<Cached>>,
+}
+
+impl Rollouts {
+    pub fn new(redis_url: &str, config: &RuntimeConfig) -> Arc<Self> {
+        Arc::new(Rollouts {
+            redis: RedisStore::new(redis_url),
+            cache_ttl: Duration::from_secs(config.rollout_cache_ttl_secs),
+            cache: Mutex::new(None),
+        })
+    }
+
+    /// Where `req` goes, when it names a plugin being rolled out.
+    pub async fn route(&self, req: &ExecuteRequest) -> Option<Routed> {
+        if req.module_path.contains('@') {
+            return None;
+        }
+        let rollouts = self.current().await;
+        let rollout = rollouts.get(&req.module_path)?;
+        let tenant_id = req.tenant_id.as_deref();
+        let key = match rollout.key {
+            RolloutKey::Tenant => tenant_id,
+            RolloutKey::User => req.caller.as_ref().map(|caller| caller.subject.as_str()).or(tenant_id),
+        };
+        let version = rollout.version(&req.module_path, key);
+        Some(Routed { plugin: req.module_path.clone(), version: version.to_string() })
+    }
+
+    /// The rollouts, reread once `cache_ttl` old; the last ones read while
+    /// Redis is unavailable, none before it ever was.
+    async fn current(&self) -> Arc<HashMap<String, Rollout>> {
+        let mut cache = self.cache.lock().await;
+        if let Some((rollouts, _)) = cache.as_ref().filter(|(_, read_at)| read_at.elapsed() < self.cache_ttl) {
+            return rollouts.clone();
+        }
+        match self.list().await {
+            Ok(rollouts) => {
+                let rollouts = Arc::new(rollouts);
+                *cache = Some((rollouts.clone(), Instant::now()));
+                rollouts
+            }
+            Err(e) => {
+                warn!("Failed to read rollouts: {:#}", e);
+                // Not retried by every execution while Redis is down
+                let rollouts = cache.as_ref().map(|(rollouts, _)| rollouts.clone()).unwrap_or_default();
+                *cache = Some((rollouts.clone(), Instant::now()));
+                rollouts
+            }
+        }
+    }
+
+    pub async fn list(&self) -> Result<HashMap<String, Rollout>> {
+        let stored: HashMap<String, String> = self.run(redis::cmd("HGETALL").arg(ROLLOUTS_KEY)).await?;
+        Ok(stored
+            .into_iter()
+            .filter_map(|(plugin, stored)| match serde_json::from_str(&stored) {
+                Ok(rollout) => Some((plugin, rollout)),
+                Err(e) => {
+                    warn!("Ignoring malformed rollout of {}: {}", plugin, e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    pub async fn put(&self, plugin: &str, rollout: &Rollout) -> Result<()> {
+        let stored = serde_json::to_string(rollout)?;
+        self.run::<()>(redis::cmd("HSET").arg(ROLLOUTS_KEY).arg(plugin).arg(stored)).await?;
+        *self.cache.lock().await = None;
+        info!("Rolling out {} {} to {}% over {}", plugin, rollout.canary, rollout.canary_percent, rollout.stable);
+        Ok(())
+    }
+
+    /// Whether there was such a rollout.
+    pub async fn delete(&self, plugin: &str) -> Result<bool> {
+        let deleted: i64 = self.run(redis::cmd("HDEL").arg(ROLLOUTS_KEY).arg(plugin)).await?;
+        *self.cache.lock().await = None;
+        if deleted == 1 {
+            info!("Ended rollout of {}", plugin);
+        }
+        Ok(deleted == 1)
+    }
+
+    /// Runs `command` on the store's Redis connection.
+    async fn run<T: redis::FromRedisValue>(&self, command: &redis::Cmd) -> Result<T> {
+        self.redis.run(command, ROLLOUTS_TIMEOUT).await.context("Rollout store unavailable")
+    }
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+fn reply(status: StatusCode, body: Value) -> Reply {
+    warp::reply::with_status(warp::reply::json(&body), status)
+}
+
+pub fn routes(
+    state: Arc<ServiceState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let state = warp::any().map(move || state.clone());
+    let list = warp::path!("admin" / "rollouts").and(warp::get()).and(state.clone()).then(|state: Arc<ServiceState>| async move {
+        match state.rollouts.list().await {
+            Ok(rollouts) => reply(StatusCode::OK, json!({ "rollouts": rollouts })),
+            Err(e) => unavailable(e),
+        }
+    });
+    let put = warp::path!("admin" / "rollouts" / String)
+        .and(warp::put())
+        .and(warp::body::content_length_limit(64 * 1024))
+        .and(warp::body::json())
+        .and(crm_audit::actor())
+        .and(state.clone())
+        .then(put);
+    let delete = warp::path!("admin" / "rollouts" / String)
+        .and(warp::delete())
+        .and(crm_audit::actor())
+        .and(state)
+        .then(delete);
+    list.or(put).unify().or(delete).unify()
+}
+
+async fn put(plugin: String, rollout: Rollout, actor: String, state: Arc<ServiceState>) -> Reply {
+    if let Some(problem) = rollout.problem(&plugin) {
+        return reply(StatusCode::BAD_REQUEST, json!({ "error": problem }));
+    }
+    let Some(registry) = &state.registry else {
+        return reply(StatusCode::NOT_FOUND, json!({ "error": "Rollouts need the module registry" }));
+    };
+    let registered = match registry.versions(None, &plugin).await {
+        Ok(registered) => registered,
+        Err(e) => return unavailable(e.context("Listing registry")),
+    };
+    let missing = [&rollout.stable, &rollout.canary]
+        .into_iter()
+        .find(|version| !registered.iter().any(|metadata| metadata.version == **version));
+    if let Some(version) = missing {
+        let error = format!("Module not found: {}", module_id(&plugin, version));
+        return reply(StatusCode::UNPROCESSABLE_ENTITY, json!({ "error": error }));
+    }
+    if let Err(e) = state.rollouts.put(&plugin, &rollout).await {
+        return unavailable(e);
+    }
+    state
+        .admin_audit
+        .record(AuditEvent::new(actor, "rollout.updated", &plugin).details(json!(rollout)))
+        .await;
+    reply(StatusCode::OK, json!({ "plugin": plugin, "rollout": rollout }))
+}
+
+async fn delete(plugin: String, actor: String, state: Arc<ServiceState>) -> Reply {
+    match state.rollouts.delete(&plugin).await {
+        Ok(true) => {
+            state.admin_audit.record(AuditEvent::new(actor, "rollout.deleted", &plugin)).await;
+            reply(StatusCode::OK, json!({ "plugin": plugin, "deleted": true }))
+        }
+        Ok(false) => reply(StatusCode::NOT_FOUND, json!({ "error": format!("No rollout of {}", plugin) })),
+        Err(e) => unavailable(e),
+    }
+}
+
+fn unavailable(e: anyhow::Error) -> Reply {
+    warn!("Rollout store call failed: {:#}", e);
+    reply(StatusCode::SERVICE_UNAVAILABLE, json!({ "error": format!("{:#}", e) }))
+}
+
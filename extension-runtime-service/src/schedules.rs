@@ -0,0 +1,549 @@
+//! Plugin executions on a cron schedule, for periodic extensions such as
+//! nightly lead scoring or hourly syncs.
+//!
+//! - `POST /schedules`: `{"cron": "0 0 2 * * *", "execute": {…}}` with the
+//!   usual execute request under `execute`; answers 201 with the schedule.
+//! - `GET /schedules`, `GET /schedules/{id}`: the tenant's schedules, with
+//!   their next and last run.
+//! - `PUT /schedules/{id}`: replaces one; `DELETE /schedules/{id}`.
+//!
+//! Cron expressions have seconds and are in UTC. Schedules belong to the
+//! tenant in the token, or in `X-Tenant-ID` while authentication is off,
+//! and their executions run for that tenant. They're kept in Redis, so any
+//! replica serves them; every replica checks them every
+//! `schedule_poll_interval_secs`, and each run is claimed by one.
+//!
+//! A run fires up to `jitter_secs` after its time, spreading schedules that
+//! share a time. Runs missed while no replica was up follow `catch_up`:
+//! `once` (the default) collapses them into one run, `all` runs each of the
+//! latest `MAX_CATCH_UP_RUNS`, one per check, and `skip` drops every run
+//! more than `MISSED_AFTER_SECS` late.
+
+use crate::redis_store::RedisStore;
+use crate::{execute, ExecuteRequest, ServiceState, TENANT_HEADER};
+use anyhow::{Context, Result};
+use chrono::{TimeZone, Utc};
+use metrics::{counter, histogram};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+use warp::http::StatusCode;
+use warp::Filter;
+
+/// How long a schedule store call may wait on Redis.
+const SCHEDULES_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Tenants with at least one schedule.
+const SCHEDULE_TENANTS_KEY: &str = "runtime_schedule_tenants";
+
+/// How late a run may fire before `skip` drops it.
+const MISSED_AFTER_SECS: i64 = 60;
+
+/// Missed runs `all` catches up on; older ones are skipped.
+const MAX_CATCH_UP_RUNS: usize = 24;
+
+/// Jitter is capped at a day.
+const MAX_JITTER_SECS: u64 = 86400;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CatchUp {
+    Skip,
+    #[default]
+    Once,
+    All,
+}
+
+/// A schedule as callers send it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleSpec {
+    /// Cron expression with seconds, e.g. `0 0 * * * *` for every hour.
+    pub cron: String,
+    /// The execute request each run makes; its `tenant_id` is ignored.
+    pub execute: Value,
+    #[serde(default)]
+    pub jitter_secs: u64,
+    #[serde(default)]
+    pub catch_up: CatchUp,
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+impl ScheduleSpec {
+    /// Why the schedule can't run, if it can't.
+    fn problem(&self) -> Option<String> {
+        if let Err(e) = cron::Schedule::from_str(&self.cron) {
+            return Some(format!("Invalid cron expression {:?}: {}", self.cron, e));
+        }
+        if let Err(e) = serde_json::from_value::<ExecuteRequest>(self.execute.clone()) {
+            return Some(format!("Invalid execute request: {}", e));
+        }
+        if self.jitter_secs > MAX_JITTER_SECS {
+            return Some(format!("jitter_secs must be at most {}", MAX_JITTER_SECS));
+        }
+        None
+    }
+}
+
+/// A schedule, stored as JSON in the Redis hash `runtime_schedules:{tenant_id}`
+/// keyed by schedule id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub schedule_id: String,
+    pub tenant_id: String,
+    /// Unix seconds.
+    pub created_at: i64,
+    #[serde(flatten)]
+    pub spec: ScheduleSpec,
+}
+
+impl Schedule {
+    /// Scheduled times after `after` (unix seconds).
+    fn runs_after(&self, after: i64) -> Result<impl Iterator<Item = i64>> {
+        let schedule = cron::Schedule::from_str(&self.spec.cron)
+            .with_context(|| format!("Invalid cron expression {:?}", self.spec.cron))?;
+        let after = Utc.timestamp_opt(after, 0).single().context("Invalid time")?;
+        Ok(schedule.after_owned(after).map(|t| t.timestamp()))
+    }
+
+    /// When the run scheduled at `scheduled_at` fires: up to `jitter_secs`
+    /// later, the same on every replica.
+    fn fires_at(&self, scheduled_at: i64) -> i64 {
+        if self.spec.jitter_secs == 0 {
+            return scheduled_at;
+        }
+        let digest = Sha256::digest(format!("{}:{}", self.schedule_id, scheduled_at));
+        let offset = u64::from_be_bytes(digest[..8].try_into().expect("digest has 8 bytes"));
+        scheduled_at + (offset % (self.spec.jitter_secs + 1)) as i64
+    }
+
+    fn module_path(&self) -> &str {
+        self.spec.execute["module_path"].as_str().unwrap_or_default()
+    }
+}
+
+/// The outcome of a schedule's latest run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastRun {
+    pub scheduled_at: i64,
+    pub started_at: i64,
+    pub execution_id: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+pub struct Schedules {
+    redis: Arc<RedisStore>,
+}
+
+impl Schedules {
+    pub fn new(redis_url: &str) -> Arc<Self> {
+        Arc::new(Schedules {
+            redis: RedisStore::new(redis_url),
+        })
+    }
+
+    pub async fn create(&self, tenant_id: &str, spec: ScheduleSpec) -> Result<Schedule> {
+        let schedule = Schedule {
+            schedule_id: uuid::Uuid::new_v4().to_string(),
+            tenant_id: tenant_id.to_string(),
+            created_at: now(),
+            spec,
+        };
+        self.put(&schedule).await?;
+        self.run::<()>(redis::cmd("SADD").arg(SCHEDULE_TENANTS_KEY).arg(tenant_id)).await?;
+        info!("Created schedule {} for tenant {}: {}", schedule.schedule_id, tenant_id, schedule.spec.cron);
+        Ok(schedule)
+    }
+
+    /// Replaces the schedule's spec; `None` when there's no such schedule.
+    pub async fn update(&self, tenant_id: &str, schedule_id: &str, spec: ScheduleSpec) -> Result<Option<Schedule>> {
+        let Some(mut schedule) = self.get(tenant_id, schedule_id).await? else {
+            return Ok(None);
+        };
+        schedule.spec = spec;
+        self.put(&schedule).await?;
+        info!("Updated schedule {} for tenant {}: {}", schedule_id, tenant_id, schedule.spec.cron);
+        Ok(Some(schedule))
+    }
+
+    async fn put(&self, schedule: &Schedule) -> Result<()> {
+        let stored = serde_json::to_string(schedule)?;
+        self.run(redis::cmd("HSET").arg(schedules_key(&schedule.tenant_id)).arg(&schedule.schedule_id).arg(stored))
+            .await
+    }
+
+    pub async fn get(&self, tenant_id: &str, schedule_id: &str) -> Result<Option<Schedule>> {
+        let stored: Option<String> = self.run(redis::cmd("HGET").arg(schedules_key(tenant_id)).arg(schedule_id)).await?;
+        stored.map(|stored| serde_json::from_str(&stored).context("Invalid stored schedule")).transpose()
+    }
+
+    pub async fn list(&self, tenant_id: &str) -> Result<Vec<Schedule>> {
+        let stored: HashMap<String, String> = self.run(redis::cmd("HGETALL").arg(schedules_key(tenant_id))).await?;
+        let mut schedules: Vec<Schedule> = stored
+            .into_iter()
+            .filter_map(|(schedule_id, stored)| match serde_json::from_str(&stored) {
+                Ok(schedule) => Some(schedule),
+                Err(e) => {
+                    warn!("Ignoring malformed schedule {} for tenant {}: {}", schedule_id, tenant_id, e);
+                    None
+                }
+            })
+            .collect();
+        schedules.sort_by_key(|schedule| schedule.created_at);
+        Ok(schedules)
+    }
+
+    /// Whether there was such a schedule.
+    pub async fn delete(&self, tenant_id: &str, schedule_id: &str) -> Result<bool> {
+        let deleted: i64 = self.run(redis::cmd("HDEL").arg(schedules_key(tenant_id)).arg(schedule_id)).await?;
+        self.run::<()>(redis::cmd("HDEL").arg(runs_key(tenant_id)).arg(schedule_id)).await?;
+        self.run::<()>(redis::cmd("DEL").arg(last_run_key(tenant_id, schedule_id))).await?;
+        if deleted == 1 {
+            info!("Deleted schedule {} for tenant {}", schedule_id, tenant_id);
+        }
+        Ok(deleted == 1)
+    }
+
+    async fn tenants(&self) -> Result<Vec<String>> {
+        self.run(redis::cmd("SMEMBERS").arg(SCHEDULE_TENANTS_KEY)).await
+    }
+
+    /// The latest scheduled time already handled.
+    async fn handled(&self, tenant_id: &str, schedule_id: &str) -> Result<Option<i64>> {
+        self.run(redis::cmd("GET").arg(last_run_key(tenant_id, schedule_id))).await
+    }
+
+    async fn set_handled(&self, tenant_id: &str, schedule_id: &str, scheduled_at: i64) -> Result<()> {
+        self.run(redis::cmd("SET").arg(last_run_key(tenant_id, schedule_id)).arg(scheduled_at)).await
+    }
+
+    /// Claims one scheduled time so only one replica handles it.
+    async fn claim(&self, tenant_id: &str, schedule_id: &str, scheduled_at: i64) -> Result<bool> {
+        let claimed: Option<String> = self
+            .run(
+                redis::cmd("SET")
+                    .arg(format!("runtime_schedule_lock:{}:{}:{}", tenant_id, schedule_id, scheduled_at))
+                    .arg(1)
+                    .arg("NX")
+                    .arg("EX")
+                    .arg(86400),
+            )
+            .await?;
+        Ok(claimed.is_some())
+    }
+
+    pub async fn last_run(&self, tenant_id: &str, schedule_id: &str) -> Result<Option<LastRun>> {
+        let stored: Option<String> = self.run(redis::cmd("HGET").arg(runs_key(tenant_id)).arg(schedule_id)).await?;
+        stored.map(|stored| serde_json::from_str(&stored).context("Invalid stored run")).transpose()
+    }
+
+    async fn set_last_run(&self, tenant_id: &str, schedule_id: &str, run: &LastRun) -> Result<()> {
+        let stored = serde_json::to_string(run)?;
+        self.run(redis::cmd("HSET").arg(runs_key(tenant_id)).arg(schedule_id).arg(stored)).await
+    }
+
+    /// Runs `command` on the store's Redis connection.
+    async fn run<T: redis::FromRedisValue>(&self, command: &redis::Cmd) -> Result<T> {
+        self.redis.run(command, SCHEDULES_TIMEOUT).await.context("Schedule store unavailable")
+    }
+}
+
+fn schedules_key(tenant_id: &str) -> String {
+    format!("runtime_schedules:{}", tenant_id)
+}
+
+fn runs_key(tenant_id: &str) -> String {
+    format!("runtime_schedule_runs:{}", tenant_id)
+}
+
+fn last_run_key(tenant_id: &str, schedule_id: &str) -> String {
+    format!("runtime_schedule_last_run:{}:{}", tenant_id, schedule_id)
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// Checks the schedules every `interval` for as long as the process runs.
+pub fn spawn_scheduler(state: Arc<ServiceState>, interval: Duration) {
+    info!("Checking plugin schedules every {:?}", interval);
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            tick(&state).await;
+        }
+    });
+}
+
+/// Starts every run that's due.
+async fn tick(state: &Arc<ServiceState>) {
+    let tenants = match state.schedules.tenants().await {
+        Ok(tenants) => tenants,
+        Err(e) => {
+            debug!("Failed to list schedule tenants: {:#}", e);
+            return;
+        }
+    };
+    for tenant_id in tenants {
+        let schedules = match state.schedules.list(&tenant_id).await {
+            Ok(schedules) => schedules,
+            Err(e) => {
+                error!("Failed to load schedules for tenant {}: {:#}", tenant_id, e);
+                continue;
+            }
+        };
+        for schedule in schedules.into_iter().filter(|schedule| schedule.spec.active) {
+            if let Err(e) = run_if_due(state, schedule.clone()).await {
+                error!("Schedule {} for tenant {} failed: {:#}", schedule.schedule_id, tenant_id, e);
+            }
+        }
+    }
+}
+
+async fn run_if_due(state: &Arc<ServiceState>, schedule: Schedule) -> Result<()> {
+    let schedules = &state.schedules;
+    let (tenant_id, schedule_id) = (&schedule.tenant_id, &schedule.schedule_id);
+    let now = now();
+    let Some(handled) = schedules.handled(tenant_id, schedule_id).await? else {
+        // New schedules start from their next run
+        schedules.set_handled(tenant_id, schedule_id, now).await?;
+        return Ok(());
+    };
+
+    // The latest runs that are due, oldest first
+    let mut due = VecDeque::new();
+    let mut dropped = 0;
+    for scheduled_at in schedule.runs_after(handled)?.take_while(|&scheduled_at| scheduled_at <= now) {
+        if schedule.fires_at(scheduled_at) > now {
+            break;
+        }
+        due.push_back(scheduled_at);
+        if due.len() > MAX_CATCH_UP_RUNS {
+            due.pop_front();
+            dropped += 1;
+        }
+    }
+    let (Some(&first), Some(&latest)) = (due.front(), due.back()) else {
+        return Ok(());
+    };
+    let (scheduled_at, skipped) = match schedule.spec.catch_up {
+        CatchUp::All => (first, dropped),
+        CatchUp::Once => (latest, dropped + due.len() - 1),
+        CatchUp::Skip if now - schedule.fires_at(latest) > MISSED_AFTER_SECS => (latest, dropped + due.len()),
+        CatchUp::Skip => (latest, dropped + due.len() - 1),
+    };
+    if !schedules.claim(tenant_id, schedule_id, scheduled_at).await? {
+        debug!("Schedule {} run at {} already claimed", schedule_id, scheduled_at);
+        return Ok(());
+    }
+    schedules.set_handled(tenant_id, schedule_id, scheduled_at).await?;
+    if skipped > 0 {
+        counter!("scheduled_executions_skipped_total", "schedule_id" => schedule_id.clone()).increment(skipped as u64);
+        warn!("Schedule {} for tenant {} skipped {} missed runs", schedule_id, tenant_id, skipped);
+    }
+    if schedule.spec.catch_up == CatchUp::Skip && skipped == dropped + due.len() {
+        return Ok(());
+    }
+
+    let state = state.clone();
+    tokio::spawn(async move { fire(&state, schedule, scheduled_at).await });
+    Ok(())
+}
+
+/// Runs the schedule's execution for the run scheduled at `scheduled_at`.
+async fn fire(state: &ServiceState, schedule: Schedule, scheduled_at: i64) {
+    let (tenant_id, schedule_id) = (&schedule.tenant_id, &schedule.schedule_id);
+    let mut req: ExecuteRequest = match serde_json::from_value(schedule.spec.execute.clone()) {
+        Ok(req) => req,
+        Err(e) => {
+            error!("Schedule {} for tenant {} has an invalid execute request: {}", schedule_id, tenant_id, e);
+            return;
+        }
+    };
+    req.tenant_id = Some(tenant_id.clone());
+    let started_at = now();
+    histogram!("scheduled_execution_lag_seconds", "schedule_id" => schedule_id.clone())
+        .record((started_at - scheduled_at) as f64);
+    info!("Running schedule {} for tenant {}: {}", schedule_id, tenant_id, schedule.module_path());
+
+    let response = execute(state, req).await;
+    let status = if response.success { "success" } else { "failure" };
+    counter!("scheduled_executions_total", "schedule_id" => schedule_id.clone(), "status" => status).increment(1);
+    let run = LastRun {
+        scheduled_at,
+        started_at,
+        execution_id: response.execution_id,
+        success: response.success,
+        error: response.error.map(|error| error.message),
+    };
+    if let Err(e) = state.schedules.set_last_run(tenant_id, schedule_id, &run).await {
+        warn!("Failed to record schedule {} run: {:#}", schedule_id, e);
+    }
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+fn reply(status: StatusCode, body: Value) -> Reply {
+    warp::reply::with_status(warp::reply::json(&body), status)
+}
+
+pub fn routes(
+    state: Arc<ServiceState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let base = warp::path("schedules")
+        .and(warp::header::optional::<String>(TENANT_HEADER))
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::any().map(move || state.clone()))
+        .then(|tenant_id: Option<String>, authorization: Option<String>, state: Arc<ServiceState>| async move {
+            let tenant_id = tenant(&state, tenant_id, authorization.as_deref()).await;
+            (state, tenant_id)
+        })
+        .untuple_one();
+
+    let create = base
+        .clone()
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::body::content_length_limit(1024 * 1024))
+        .and(warp::body::json())
+        .then(|state: Arc<ServiceState>, tenant_id: Result<String, Reply>, spec: ScheduleSpec| async move {
+            let tenant_id = match tenant_id {
+                Ok(tenant_id) => tenant_id,
+                Err(refused) => return refused,
+            };
+            if let Some(problem) = spec.problem() {
+                return reply(StatusCode::BAD_REQUEST, json!({ "error": problem }));
+            }
+            match state.schedules.create(&tenant_id, spec).await {
+                Ok(schedule) => reply(StatusCode::CREATED, describe(&state, schedule).await),
+                Err(e) => unavailable(e),
+            }
+        });
+    let list = base.clone().and(warp::path::end()).and(warp::get()).then(
+        |state: Arc<ServiceState>, tenant_id: Result<String, Reply>| async move {
+            let tenant_id = match tenant_id {
+                Ok(tenant_id) => tenant_id,
+                Err(refused) => return refused,
+            };
+            match state.schedules.list(&tenant_id).await {
+                Ok(schedules) => {
+                    let mut described = Vec::with_capacity(schedules.len());
+                    for schedule in schedules {
+                        described.push(describe(&state, schedule).await);
+                    }
+                    reply(StatusCode::OK, json!({ "schedules": described }))
+                }
+                Err(e) => unavailable(e),
+            }
+        },
+    );
+    let get = base.clone().and(warp::path::param::<String>()).and(warp::path::end()).and(warp::get()).then(
+        |state: Arc<ServiceState>, tenant_id: Result<String, Reply>, schedule_id: String| async move {
+            let tenant_id = match tenant_id {
+                Ok(tenant_id) => tenant_id,
+                Err(refused) => return refused,
+            };
+            match state.schedules.get(&tenant_id, &schedule_id).await {
+                Ok(Some(schedule)) => reply(StatusCode::OK, describe(&state, schedule).await),
+                Ok(None) => not_found(&schedule_id),
+                Err(e) => unavailable(e),
+            }
+        },
+    );
+    let update = base
+        .clone()
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::put())
+        .and(warp::body::content_length_limit(1024 * 1024))
+        .and(warp::body::json())
+        .then(
+            |state: Arc<ServiceState>, tenant_id: Result<String, Reply>, schedule_id: String, spec: ScheduleSpec| async move {
+                let tenant_id = match tenant_id {
+                    Ok(tenant_id) => tenant_id,
+                    Err(refused) => return refused,
+                };
+                if let Some(problem) = spec.problem() {
+                    return reply(StatusCode::BAD_REQUEST, json!({ "error": problem }));
+                }
+                match state.schedules.update(&tenant_id, &schedule_id, spec).await {
+                    Ok(Some(schedule)) => reply(StatusCode::OK, describe(&state, schedule).await),
+                    Ok(None) => not_found(&schedule_id),
+                    Err(e) => unavailable(e),
+                }
+            },
+        );
+    let delete = base.and(warp::path::param::<String>()).and(warp::path::end()).and(warp::delete()).then(
+        |state: Arc<ServiceState>, tenant_id: Result<String, Reply>, schedule_id: String| async move {
+            let tenant_id = match tenant_id {
+                Ok(tenant_id) => tenant_id,
+                Err(refused) => return refused,
+            };
+            match state.schedules.delete(&tenant_id, &schedule_id).await {
+                Ok(true) => reply(StatusCode::OK, json!({ "schedule_id": schedule_id, "deleted": true })),
+                Ok(false) => not_found(&schedule_id),
+                Err(e) => unavailable(e),
+            }
+        },
+    );
+    create.or(list).unify().or(get).unify().or(update).unify().or(delete).unify()
+}
+
+/// The caller's tenant: the token's with authentication on, otherwise the
+/// one in `X-Tenant-ID`, which is then required.
+async fn tenant(state: &ServiceState, tenant_id: Option<String>, authorization: Option<&str>) -> Result<String, Reply> {
+    match &state.auth {
+        Some(auth) => match auth.authenticate(authorization, tenant_id.as_deref()).await {
+            Ok(caller) => Ok(caller.tenant_id),
+            Err(e) => {
+                let status = if e.is_forbidden() { StatusCode::FORBIDDEN } else { StatusCode::UNAUTHORIZED };
+                Err(reply(status, json!({ "error": e.to_string() })))
+            }
+        },
+        None => tenant_id.ok_or_else(|| {
+            reply(StatusCode::BAD_REQUEST, json!({ "error": "Schedules need an X-Tenant-ID header" }))
+        }),
+    }
+}
+
+/// The schedule with its next run and the outcome of its last one.
+async fn describe(state: &ServiceState, schedule: Schedule) -> Value {
+    let next_run_at = schedule
+        .runs_after(now())
+        .ok()
+        .and_then(|mut runs| runs.next())
+        .filter(|_| schedule.spec.active);
+    let last_run = match state.schedules.last_run(&schedule.tenant_id, &schedule.schedule_id).await {
+        Ok(last_run) => last_run,
+        Err(e) => {
+            warn!("Failed to load schedule {} run: {:#}", schedule.schedule_id, e);
+            None
+        }
+    };
+    let mut described = json!(schedule);
+    described["next_run_at"] = json!(next_run_at);
+    described["last_run"] = json!(last_run);
+    described
+}
+
+fn not_found(schedule_id: &str) -> Reply {
+    reply(StatusCode::NOT_FOUND, json!({ "error": format!("Schedule not found: {}", schedule_id) }))
+}
+
+fn unavailable(e: anyhow::Error) -> Reply {
+    warn!("Schedule store call failed: {:#}", e);
+    reply(StatusCode::SERVICE_UNAVAILABLE, json!({ "error": format!("{:#}", e) }))
+}
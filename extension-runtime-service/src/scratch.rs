@@ -0,0 +1,137 @@
+//! Scratch space for plugins that need to write files.
+//!
+//! A module whose `wasi_grants` entry has `scratch`, and whose capabilities
+//! include `fs_tmp`, gets an empty, writable directory of its own at
+//! `guest` (by default `/tmp`) for each execution:
+//!
+//! ```toml
+//! [wasi_grants."invoice-export.wasm"]
+//! scratch = { guest = "/tmp", max_bytes = 10485760 }
+//! ```
+//!
+//! Directories are made under `scratch_dir`, one per tenant and execution,
+//! and removed with everything in them once the plugin has stopped. The
+//! files in one may add up to `max_bytes`; usage is checked every
+//! `CHECK_INTERVAL` while the plugin runs, and a plugin over its quota is
+//! stopped like one past its deadline.
+
+use crate::grants::{Preopen, WasiGrant};
+use crate::RuntimeConfig;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// How often a running plugin's scratch usage is measured.
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A module's scratch space, per execution.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScratchGrant {
+    #[serde(default = "default_guest")]
+    pub guest: String,
+    pub max_bytes: u64,
+}
+
+fn default_guest() -> String {
+    "/tmp".to_string()
+}
+
+/// Why a plugin over its scratch quota stopped.
+#[derive(Debug)]
+pub struct QuotaExceeded {
+    pub max_bytes: u64,
+}
+
+impl std::fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "scratch space over its quota of {} bytes", self.max_bytes)
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+/// Whether `error` is a plugin stopped for filling its scratch space.
+pub fn is_quota_exceeded(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<QuotaExceeded>().is_some()
+}
+
+/// Root of the per-execution scratch directories.
+pub fn scratch_dir(config: &RuntimeConfig) -> PathBuf {
+    config
+        .scratch_dir
+        .as_ref()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join("crm-plugin-scratch"))
+}
+
+/// One execution's scratch directory; removed on drop.
+pub struct Scratch {
+    path: PathBuf,
+    guest: String,
+    max_bytes: u64,
+    checked_at: Instant,
+}
+
+impl Scratch {
+    pub fn create(root: &Path, grant: &ScratchGrant, tenant_id: Option<&str>, execution_id: &str) -> Result<Self> {
+        let parent = match tenant_id {
+            // Keep tenants from naming a directory outside their own
+            Some(tenant_id) if crate::valid_path_segment(tenant_id) => root.join("tenants").join(tenant_id),
+            Some(_) => anyhow::bail!("Invalid tenant_id"),
+            None => root.join("untenanted"),
+        };
+        let path = parent.join(execution_id);
+        std::fs::create_dir_all(&path)
+            .with_context(|| format!("Failed to create scratch directory {}", path.display()))?;
+        Ok(Scratch { path, guest: grant.guest.clone(), max_bytes: grant.max_bytes, checked_at: Instant::now() })
+    }
+
+    /// `grant` with the scratch directory preopened, writable.
+    pub fn grant(&self, grant: &WasiGrant) -> WasiGrant {
+        let mut grant = grant.clone();
+        grant.preopens.push(Preopen {
+            host: self.path.display().to_string(),
+            guest: self.guest.clone(),
+            writable: true,
+        });
+        grant
+    }
+
+    /// Fails once the files in the directory outgrow the quota; measures
+    /// at most every `CHECK_INTERVAL`.
+    pub fn check(&mut self) -> Result<(), QuotaExceeded> {
+        if self.checked_at.elapsed() < CHECK_INTERVAL {
+            return Ok(());
+        }
+        self.checked_at = Instant::now();
+        if usage(&self.path) > self.max_bytes {
+            return Err(QuotaExceeded { max_bytes: self.max_bytes });
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.path) {
+            warn!("Failed to remove scratch directory {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Bytes in the files under `dir`, without following symlinks.
+fn usage(dir: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => usage(&entry.path()),
+            Ok(_) => entry.metadata().map(|metadata| metadata.len()).unwrap_or_default(),
+            Err(_) => 0,
+        })
+        .sum()
+}
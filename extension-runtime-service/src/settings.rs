@@ -0,0 +1,235 @@
+//! Per-plugin settings, such as API keys for a plugin's allowed hosts or
+//! thresholds, that each tenant configures for the plugins it runs.
+//!
+//! - `PUT /settings/{module_path}`: replaces the plugin's settings with the
+//!   JSON object in the body.
+//! - `GET /settings/{module_path}`: the plugin's settings.
+//! - `DELETE /settings/{module_path}`.
+//!
+//! Settings belong to the tenant in the token, or in `X-Tenant-ID` while
+//! authentication is off, and are kept in Redis under
+//! `plugin_settings:{tenant_id}:{module_path}`. Plugins read their tenant's
+//! settings with `crm.config_get`; see `host::config`. They're capped at
+//! `plugin_settings_max_bytes`, serialized.
+//!
+//! Credentials aren't kept in settings: a top-level string setting of the
+//! form `secret://{name}` references the tenant's plugin secret `name`,
+//! under `plugins/{tenant_id}/{name}` in the secrets backend (Vault, AWS
+//! SSM, …; see `crm_secrets`). Plugins see the reference through
+//! `config_get`, and only modules listed in `secret_settings_modules` get
+//! the value, through `crm.secret_get`, at execution time. Values are never
+//! logged or returned by the API.
+
+use crate::redis_store::RedisStore;
+use crate::{valid_path_segment, RuntimeConfig, ServiceState, PLUGIN_SECRETS_PREFIX, TENANT_HEADER};
+use anyhow::{Context, Result};
+use crm_secrets::SecretStore;
+use metrics::counter;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use warp::http::StatusCode;
+use warp::Filter;
+
+/// How long a settings API call may wait on Redis; plugins wait no longer
+/// than their execution's deadline.
+const SETTINGS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How settings reference the tenant's plugin secrets.
+const SECRET_SCHEME: &str = "secret://";
+
+pub struct PluginSettings {
+    redis: Arc<RedisStore>,
+    secrets: Arc<SecretStore>,
+    secret_modules: HashSet<String>,
+}
+
+impl PluginSettings {
+    pub fn new(redis_url: &str, config: &RuntimeConfig, secrets: Arc<SecretStore>) -> Arc<Self> {
+        Arc::new(PluginSettings {
+            redis: RedisStore::new(redis_url),
+            secrets,
+            secret_modules: config.secret_settings_modules.iter().cloned().collect(),
+        })
+    }
+
+    /// Whether the module may read the secrets its settings reference.
+    pub fn reads_secrets(&self, module_path: &str) -> bool {
+        self.secret_modules.contains(module_path)
+    }
+
+    /// The value of the secret the setting `key` references; `None` when
+    /// there's no such setting or it isn't a reference.
+    pub async fn secret(&self, tenant_id: &str, module_path: &str, key: &str, timeout: Duration) -> Result<Option<String>> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let Some(stored) = self.get(tenant_id, module_path, timeout).await? else {
+            return Ok(None);
+        };
+        let settings: Value = serde_json::from_str(&stored).context("Invalid stored settings")?;
+        let Some(name) = settings.get(key).and_then(Value::as_str).and_then(|value| value.strip_prefix(SECRET_SCHEME)) else {
+            return Ok(None);
+        };
+        if !valid_path_segment(tenant_id) || !valid_path_segment(name) {
+            anyhow::bail!("Invalid secret reference in setting {}", key);
+        }
+        let path = format!("{}/{}/{}", PLUGIN_SECRETS_PREFIX, tenant_id, name);
+        let value = tokio::time::timeout_at(deadline, self.secrets.get(&path))
+            .await
+            .context("Secrets backend timed out")?
+            .with_context(|| format!("Failed to load plugin secret {}", name))?;
+        Ok(Some(value))
+    }
+
+    /// The plugin's settings for the tenant, serialized; `None` when it has
+    /// none.
+    pub async fn get(&self, tenant_id: &str, module_path: &str, timeout: Duration) -> Result<Option<String>> {
+        self.run(redis::cmd("GET").arg(settings_key(tenant_id, module_path)), timeout).await
+    }
+
+    pub async fn put(&self, tenant_id: &str, module_path: &str, settings: &str) -> Result<()> {
+        self.run::<()>(redis::cmd("SET").arg(settings_key(tenant_id, module_path)).arg(settings), SETTINGS_TIMEOUT).await?;
+        info!("Updated settings of {} for tenant {}", module_path, tenant_id);
+        Ok(())
+    }
+
+    /// Whether there were settings.
+    pub async fn delete(&self, tenant_id: &str, module_path: &str) -> Result<bool> {
+        let deleted: i64 = self.run(redis::cmd("DEL").arg(settings_key(tenant_id, module_path)), SETTINGS_TIMEOUT).await?;
+        if deleted == 1 {
+            info!("Deleted settings of {} for tenant {}", module_path, tenant_id);
+        }
+        Ok(deleted == 1)
+    }
+
+    /// Runs `command` on the store's Redis connection.
+    async fn run<T: redis::FromRedisValue>(&self, command: &redis::Cmd, timeout: Duration) -> Result<T> {
+        self.redis.run(command, timeout).await.context("Plugin settings store unavailable")
+    }
+}
+
+fn settings_key(tenant_id: &str, module_path: &str) -> String {
+    format!("plugin_settings:{}:{}", tenant_id, module_path)
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+fn reply(status: StatusCode, body: Value) -> Reply {
+    warp::reply::with_status(warp::reply::json(&body), status)
+}
+
+pub fn routes(
+    state: Arc<ServiceState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let base = warp::path("settings")
+        .and(warp::path::tail())
+        .and(warp::header::optional::<String>(TENANT_HEADER))
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::any().map(move || state.clone()))
+        .then(
+            |module_path: warp::path::Tail, tenant_id: Option<String>, authorization: Option<String>, state: Arc<ServiceState>| async move {
+                let tenant_id = tenant(&state, tenant_id, authorization.as_deref()).await;
+                (state, tenant_id, module_path.as_str().to_string())
+            },
+        )
+        .untuple_one();
+
+    let put = base
+        .clone()
+        .and(warp::put())
+        .and(warp::body::content_length_limit(1024 * 1024))
+        .and(warp::body::json())
+        .then(|state: Arc<ServiceState>, tenant_id: Result<String, Reply>, module_path: String, settings: Value| async move {
+            let tenant_id = match tenant_id {
+                Ok(tenant_id) => tenant_id,
+                Err(refused) => return refused,
+            };
+            if module_path.is_empty() {
+                return reply(StatusCode::BAD_REQUEST, json!({ "error": "Settings need a module path" }));
+            }
+            let Some(object) = settings.as_object() else {
+                return reply(StatusCode::BAD_REQUEST, json!({ "error": "Settings must be a JSON object" }));
+            };
+            let invalid = object.iter().find(|(_, value)| {
+                value.as_str().and_then(|value| value.strip_prefix(SECRET_SCHEME)).is_some_and(|name| !valid_path_segment(name))
+            });
+            if let Some((key, _)) = invalid {
+                return reply(StatusCode::BAD_REQUEST, json!({ "error": format!("Invalid secret reference in setting {}", key) }));
+            }
+            let stored = settings.to_string();
+            let max_bytes = state.config().plugin_settings_max_bytes;
+            if stored.len() > max_bytes {
+                return reply(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    json!({ "error": format!("Settings exceed {} bytes", max_bytes) }),
+                );
+            }
+            match state.settings.put(&tenant_id, &module_path, &stored).await {
+                Ok(()) => {
+                    counter!("plugin_settings_updates_total").increment(1);
+                    reply(StatusCode::OK, json!({ "module_path": module_path, "settings": settings }))
+                }
+                Err(e) => unavailable(e),
+            }
+        });
+    let get = base.clone().and(warp::get()).then(
+        |state: Arc<ServiceState>, tenant_id: Result<String, Reply>, module_path: String| async move {
+            let tenant_id = match tenant_id {
+                Ok(tenant_id) => tenant_id,
+                Err(refused) => return refused,
+            };
+            match state.settings.get(&tenant_id, &module_path, SETTINGS_TIMEOUT).await {
+                Ok(Some(stored)) => {
+                    let settings: Value = serde_json::from_str(&stored).unwrap_or_else(|e| {
+                        warn!("Malformed settings of {} for tenant {}: {}", module_path, tenant_id, e);
+                        Value::Null
+                    });
+                    reply(StatusCode::OK, json!({ "module_path": module_path, "settings": settings }))
+                }
+                Ok(None) => not_found(&module_path),
+                Err(e) => unavailable(e),
+            }
+        },
+    );
+    let delete = base.and(warp::delete()).then(
+        |state: Arc<ServiceState>, tenant_id: Result<String, Reply>, module_path: String| async move {
+            let tenant_id = match tenant_id {
+                Ok(tenant_id) => tenant_id,
+                Err(refused) => return refused,
+            };
+            match state.settings.delete(&tenant_id, &module_path).await {
+                Ok(true) => reply(StatusCode::OK, json!({ "module_path": module_path, "deleted": true })),
+                Ok(false) => not_found(&module_path),
+                Err(e) => unavailable(e),
+            }
+        },
+    );
+    put.or(get).unify().or(delete).unify()
+}
+
+/// The caller's tenant: the token's with authentication on, otherwise the
+/// one in `X-Tenant-ID`, which is then required.
+async fn tenant(state: &ServiceState, tenant_id: Option<String>, authorization: Option<&str>) -> Result<String, Reply> {
+    match &state.auth {
+        Some(auth) => match auth.authenticate(authorization, tenant_id.as_deref()).await {
+            Ok(caller) => Ok(caller.tenant_id),
+            Err(e) => {
+                let status = if e.is_forbidden() { StatusCode::FORBIDDEN } else { StatusCode::UNAUTHORIZED };
+                Err(reply(status, json!({ "error": e.to_string() })))
+            }
+        },
+        None => tenant_id.ok_or_else(|| {
+            reply(StatusCode::BAD_REQUEST, json!({ "error": "Plugin settings need an X-Tenant-ID header" }))
+        }),
+    }
+}
+
+fn not_found(module_path: &str) -> Reply {
+    reply(StatusCode::NOT_FOUND, json!({ "error": format!("No settings for module: {}", module_path) }))
+}
+
+fn unavailable(e: anyhow::Error) -> Reply {
+    warn!("Plugin settings store call failed: {:#}", e);
+    reply(StatusCode::SERVICE_UNAVAILABLE, json!({ "error": format!("{:#}", e) }))
+}
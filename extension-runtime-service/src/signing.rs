@@ -0,0 +1,152 @@
+//! Module signatures, so only modules signed by a trusted key run.
+//!
+//! `trusted_keys_file` lists the Ed25519 public keys modules may be signed
+//! with, one base64 key per line; blank lines and `#` comments are skipped.
+//! A module is signed either
+//!
+//! - in a trailing `signature` custom section holding the 64-byte signature
+//!   of every byte before it, or
+//! - by a detached `{module_path}.sig` next to it in the module source,
+//!   holding the signature of the whole module, raw or base64.
+//!
+//! Modules without a valid signature from a trusted key are rejected before
+//! they are compiled, unless `allow_unsigned_modules` is set for
+//! development. Signatures aren't checked while `trusted_keys_file` is
+//! unset.
+
+use crate::storage::ModuleSource;
+use crate::RuntimeConfig;
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use metrics::counter;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::sync::Mutex;
+use tracing::warn;
+
+/// Name of the custom section embedded signatures are in.
+pub const SIGNATURE_SECTION: &str = "signature";
+
+const SIGNATURE_BYTES: usize = 64;
+
+pub struct Signatures {
+    keys: Vec<Vec<u8>>,
+    allow_unsigned: bool,
+    /// Hashes of modules already verified.
+    verified: Mutex<HashSet<String>>,
+}
+
+impl Signatures {
+    /// `None` while `trusted_keys_file` is unset.
+    pub fn from_config(config: &RuntimeConfig) -> Result<Option<Self>> {
+        let Some(path) = &config.trusted_keys_file else {
+            warn!("trusted_keys_file is not set; module signatures are not verified");
+            return Ok(None);
+        };
+        let contents =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read trusted keys from {}", path))?;
+        let mut keys = Vec::new();
+        for line in contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let key = STANDARD.decode(line).with_context(|| format!("Trusted key {} is not base64", line))?;
+            if key.len() != 32 {
+                anyhow::bail!("Trusted key {} is not an Ed25519 public key", line);
+            }
+            keys.push(key);
+        }
+        if keys.is_empty() {
+            anyhow::bail!("{} has no trusted keys", path);
+        }
+        if config.allow_unsigned_modules {
+            warn!("allow_unsigned_modules is set; unsigned modules will run");
+        }
+        Ok(Some(Signatures { keys, allow_unsigned: config.allow_unsigned_modules, verified: Mutex::new(HashSet::new()) }))
+    }
+
+    /// Fails unless `bytes`, fetched as `module_path` for `tenant_id` from
+    /// `source`, are signed by a trusted key or unsigned modules are
+    /// allowed.
+    pub async fn verify(&self, source: &dyn ModuleSource, tenant_id: Option<&str>, module_path: &str, bytes: &[u8]) -> Result<()> {
+        let hash = hex::encode(Sha256::digest(bytes));
+        if self.verified.lock().unwrap().contains(&hash) {
+            return Ok(());
+        }
+
+        let (signed, signature) = match embedded_signature(bytes) {
+            Some((signed, signature)) => (signed, signature.to_vec()),
+            None => match source.fetch(tenant_id, &format!("{}.sig", module_path)).await {
+                Ok(signature) => (bytes, detached_signature(&signature)),
+                Err(_) => (bytes, Vec::new()),
+            },
+        };
+        let problem = if signature.is_empty() {
+            "is not signed"
+        } else if self.keys.iter().any(|key| UnparsedPublicKey::new(&ED25519, key).verify(signed, &signature).is_ok()) {
+            counter!("module_signature_checks_total", "result" => "valid").increment(1);
+            self.verified.lock().unwrap().insert(hash);
+            return Ok(());
+        } else {
+            "is not signed by a trusted key"
+        };
+
+        if self.allow_unsigned {
+            counter!("module_signature_checks_total", "result" => "allowed").increment(1);
+            warn!("Module {} {}; running it anyway", module_path, problem);
+            return Ok(());
+        }
+        counter!("module_signature_checks_total", "result" => "rejected").increment(1);
+        anyhow::bail!("Module {} {}", module_path, problem)
+    }
+}
+
+/// The bytes before a trailing `signature` section, and the signature in it.
+fn embedded_signature(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    // Sections follow the 8-byte preamble: an id, a LEB128 size and the
+    // contents, which for custom sections (id 0) start with their name
+    let mut offset = 8;
+    let mut last = None;
+    while offset < bytes.len() {
+        let start = offset;
+        let id = bytes[offset];
+        let (size, read) = leb128(&bytes[offset + 1..])?;
+        let contents = offset + 1 + read;
+        offset = contents.checked_add(size)?;
+        if offset > bytes.len() {
+            return None;
+        }
+        last = Some((start, id, contents));
+    }
+
+    let (start, id, contents) = last?;
+    if id != 0 {
+        return None;
+    }
+    let (name_len, read) = leb128(&bytes[contents..])?;
+    let name = bytes.get(contents + read..contents + read + name_len)?;
+    let signature = &bytes[contents + read + name_len..];
+    (name == SIGNATURE_SECTION.as_bytes() && signature.len() == SIGNATURE_BYTES).then(|| (&bytes[..start], signature))
+}
+
+/// A detached signature, raw or base64.
+fn detached_signature(contents: &[u8]) -> Vec<u8> {
+    if contents.len() == SIGNATURE_BYTES {
+        return contents.to_vec();
+    }
+    std::str::from_utf8(contents)
+        .ok()
+        .and_then(|text| STANDARD.decode(text.trim()).ok())
+        .unwrap_or_default()
+}
+
+/// An unsigned LEB128 value of at most 32 bits, and how many bytes it took.
+pub fn leb128(bytes: &[u8]) -> Option<(usize, usize)> {
+    let mut value = 0usize;
+    for (i, &byte) in bytes.iter().take(5).enumerate() {
+        value |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
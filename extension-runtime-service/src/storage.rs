@@ -0,0 +1,398 @@
+//! Where module bytes come from. `module_path` in an execute request is
+//! resolved for the request's tenant by one [`ModuleSource`], picked at
+//! startup. A tenant can run its own modules and the system modules shared
+//! with every tenant, never another tenant's:
+//!
+//! - the module registry, when `registry_url` is set; see `registry`;
+//! - an S3-compatible bucket (AWS, MinIO), when `module_store_url` is set,
+//!   with `module_path` naming the object key under
+//!   `tenants/{tenant_id}/` for the tenant's own modules, or else under the
+//!   bucket root for system ones. Paths can't start with `tenants/`
+//!   themselves. Downloads are kept in a local disk cache for
+//!   `module_cache_ttl_secs` and evicted after;
+//! - otherwise directories on local disk: `module_dir` for plain paths, and
+//!   the named `module_roots` for `{root}:{path}` ones, e.g. with
+//!
+//!   ```toml
+//!   module_dir = "/srv/plugins"
+//!   [module_roots]
+//!   system = "/opt/crm/plugins"
+//!   tenant = "/srv/tenant-plugins/{tenant_id}"
+//!   ```
+//!
+//!   `scoring.wasm` is `/srv/plugins/scoring.wasm`, `system:crm/dedupe.wasm`
+//!   is `/opt/crm/plugins/crm/dedupe.wasm` and `tenant:acme:hooks.wasm` is
+//!   `/srv/tenant-plugins/acme/hooks.wasm`, for tenant `acme` only: other
+//!   tenants, and executions without one, don't find it. The path within a
+//!   root may only name files and directories under it, and the file it
+//!   resolves to, links followed, must still be under the root.
+//!
+//! Whichever is picked, `oci://` paths are pulled from OCI registries
+//! instead (see [`crate::oci`]).
+
+use crate::errors::{self, ErrorCode};
+use crate::oci::{self, OciSource};
+use crate::registry::ModuleRegistry;
+use crate::RuntimeConfig;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use metrics::counter;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path as ObjectPath;
+use object_store::ObjectStore;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, info, warn};
+use url::Url;
+
+#[async_trait]
+pub trait ModuleSource: Send + Sync {
+    /// Named in logs and health checks.
+    fn describe(&self) -> String;
+
+    /// The module `tenant_id` runs as `module_path`: its own, or a system
+    /// module.
+    async fn fetch(&self, tenant_id: Option<&str>, module_path: &str) -> Result<Arc<Vec<u8>>>;
+}
+
+/// The source `config` selects.
+pub fn from_config(config: &RuntimeConfig, registry: Option<Arc<ModuleRegistry>>) -> Result<Arc<dyn ModuleSource>> {
+    let source: Arc<dyn ModuleSource> = match (registry, &config.module_store_url) {
+        (Some(registry), _) => registry,
+        (None, Some(url)) => Arc::new(BucketSource::open(url, config)?),
+        (None, None) => Arc::new(LocalRoots::from_config(config)),
+    };
+    info!("Loading modules from {}", source.describe());
+
+    let layers = DiskCache::new(cache_dir(config).join("oci"), Duration::from_secs(config.module_cache_ttl_secs))?;
+    layers.spawn_eviction();
+    let oci = OciSource::new(
+        config.oci_credentials.clone(),
+        config.oci_insecure_registries.clone(),
+        layers,
+        Duration::from_secs(config.oci_tag_ttl_secs),
+    )?;
+    Ok(Arc::new(WithOci { oci, source }))
+}
+
+/// Root of the runtime's on-disk caches.
+pub fn cache_dir(config: &RuntimeConfig) -> PathBuf {
+    config
+        .module_cache_dir
+        .as_ref()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join("crm-module-cache"))
+}
+
+/// Where a bucket keeps each tenant's own modules.
+const TENANTS: &str = "tenants";
+
+/// Stands for the tenant's id in a module root's directory.
+pub const TENANT_PLACEHOLDER: &str = "{tenant_id}";
+
+/// Where plain module paths are read from without a registry or bucket.
+pub fn module_dir(config: &RuntimeConfig) -> Option<String> {
+    config
+        .module_dir
+        .clone()
+        .or_else(|| std::env::var("WASM_MODULE_DIR").ok())
+        .filter(|dir| !dir.is_empty())
+}
+
+/// The local directories modules are read from, named for health checks;
+/// tenants' roots, which exist per tenant, aren't listed.
+pub fn local_dirs(config: &RuntimeConfig) -> Vec<(String, String)> {
+    let roots = config
+        .module_roots
+        .iter()
+        .filter(|(_, dir)| !dir.contains(TENANT_PLACEHOLDER))
+        .map(|(name, dir)| (format!("module root {}", name), dir.clone()));
+    module_dir(config).map(|dir| ("module directory".to_string(), dir)).into_iter().chain(roots).collect()
+}
+
+#[async_trait]
+impl ModuleSource for ModuleRegistry {
+    fn describe(&self) -> String {
+        "the module registry".to_string()
+    }
+
+    async fn fetch(&self, tenant_id: Option<&str>, module_path: &str) -> Result<Arc<Vec<u8>>> {
+        ModuleRegistry::fetch(self, tenant_id, module_path).await
+    }
+}
+
+/// Sends `oci://` paths to the OCI source and everything else on.
+struct WithOci {
+    oci: OciSource,
+    source: Arc<dyn ModuleSource>,
+}
+
+#[async_trait]
+impl ModuleSource for WithOci {
+    fn describe(&self) -> String {
+        format!("{} and OCI registries", self.source.describe())
+    }
+
+    /// OCI references name images anyone with access to their registry
+    /// can pull, so they aren't any tenant's.
+    async fn fetch(&self, tenant_id: Option<&str>, module_path: &str) -> Result<Arc<Vec<u8>>> {
+        if module_path.starts_with(oci::SCHEME) {
+            self.oci.fetch(module_path).await
+        } else {
+            self.source.fetch(tenant_id, module_path).await
+        }
+    }
+}
+
+/// The module directory and the named module roots.
+pub struct LocalRoots {
+    default: Option<String>,
+    roots: HashMap<String, String>,
+}
+
+impl LocalRoots {
+    pub fn from_config(config: &RuntimeConfig) -> Self {
+        let default = module_dir(config);
+        if default.is_none() && config.module_roots.is_empty() {
+            warn!("Neither module_dir, WASM_MODULE_DIR nor module_roots is set; no module will be found");
+        }
+        LocalRoots { default, roots: config.module_roots.clone() }
+    }
+
+    /// The directory `module_path` is read from for `caller`, and its path
+    /// within it.
+    fn resolve<'a>(&self, caller: Option<&str>, module_path: &'a str) -> Result<(String, &'a str)> {
+        let Some((name, rest)) = module_path.split_once(':') else {
+            let Some(dir) = &self.default else {
+                let error = anyhow::anyhow!("No module directory to read {} from", module_path);
+                return Err(errors::coded(ErrorCode::ModuleNotFound, error));
+            };
+            return Ok((dir.clone(), module_path));
+        };
+        let Some(dir) = self.roots.get(name) else {
+            return Err(errors::coded(ErrorCode::ModuleNotFound, anyhow::anyhow!("No module root named {}", name)));
+        };
+        if !dir.contains(TENANT_PLACEHOLDER) {
+            return Ok((dir.clone(), rest));
+        }
+        match rest.split_once(':') {
+            // Another tenant's modules are as good as missing
+            Some((tenant_id, _)) if crate::valid_path_segment(tenant_id) && caller != Some(tenant_id) => {
+                Err(errors::coded(ErrorCode::ModuleNotFound, anyhow::anyhow!("Module not found: {}", module_path)))
+            }
+            Some((tenant_id, rest)) if crate::valid_path_segment(tenant_id) => {
+                Ok((dir.replace(TENANT_PLACEHOLDER, tenant_id), rest))
+            }
+            _ => {
+                let error = anyhow::anyhow!("Paths in module root {} are {}:{{tenant_id}}:{{path}}", name, name);
+                Err(errors::coded(ErrorCode::InvalidRequest, error))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ModuleSource for LocalRoots {
+    fn describe(&self) -> String {
+        let roots = self.roots.iter().map(|(name, dir)| format!("{} ({})", dir, name));
+        self.default.iter().cloned().chain(roots).collect::<Vec<_>>().join(", ")
+    }
+
+    async fn fetch(&self, tenant_id: Option<&str>, module_path: &str) -> Result<Arc<Vec<u8>>> {
+        let (root, relative) = self.resolve(tenant_id, module_path)?;
+        let path = within(Path::new(&root), relative, module_path).await?;
+        let module_bytes =
+            tokio::fs::read(&path).await.with_context(|| format!("Failed to read WASM module at {}", path.display()))?;
+        Ok(Arc::new(module_bytes))
+    }
+}
+
+/// `relative` under `root`, links followed, as long as it stays there.
+async fn within(root: &Path, relative: &str, module_path: &str) -> Result<PathBuf> {
+    // Names only: no `..`, `.`, or absolute paths, which `join` would take
+    // in place of the root
+    let relative = Path::new(relative);
+    if relative.as_os_str().is_empty() || !relative.components().all(|part| matches!(part, Component::Normal(_))) {
+        return Err(errors::coded(ErrorCode::InvalidRequest, anyhow::anyhow!("Invalid module path: {}", module_path)));
+    }
+    let root = canonical(root, module_path).await?;
+    let path = canonical(&root.join(relative), module_path).await?;
+    // A link may still lead out of the root
+    if !path.starts_with(&root) {
+        let error = anyhow::anyhow!("Module path {} leads out of its module root", module_path);
+        return Err(errors::coded(ErrorCode::NotPermitted, error));
+    }
+    Ok(path)
+}
+
+async fn canonical(path: &Path, module_path: &str) -> Result<PathBuf> {
+    match tokio::fs::canonicalize(path).await {
+        Ok(path) => Ok(path),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err(errors::coded(ErrorCode::ModuleNotFound, anyhow::anyhow!("Module not found: {}", module_path)))
+        }
+        Err(e) => Err(e).with_context(|| format!("Invalid module path: {}", module_path)),
+    }
+}
+
+/// Plain objects in an S3-compatible bucket, fetched by key.
+pub struct BucketSource {
+    url: String,
+    store: Arc<dyn ObjectStore>,
+    root: ObjectPath,
+    cache: DiskCache,
+}
+
+impl BucketSource {
+    pub fn open(url: &str, config: &RuntimeConfig) -> Result<Self> {
+        let parsed = Url::parse(url).context("Invalid module_store_url")?;
+        let (store, root): (Arc<dyn ObjectStore>, ObjectPath) = match parsed.scheme() {
+            "s3" | "s3a" => {
+                let mut builder = AmazonS3Builder::from_env().with_url(url);
+                // MinIO and other S3-compatible stores
+                if let Some(endpoint) = &config.module_store_endpoint {
+                    builder = builder.with_endpoint(endpoint).with_allow_http(endpoint.starts_with("http://"));
+                }
+                (Arc::new(builder.build()?), ObjectPath::from_url_path(parsed.path())?)
+            }
+            _ => {
+                let (store, root) = object_store::parse_url(&parsed)?;
+                (Arc::from(store), root)
+            }
+        };
+        let cache = DiskCache::new(cache_dir(config), Duration::from_secs(config.module_cache_ttl_secs))?;
+        cache.spawn_eviction();
+
+        Ok(BucketSource {
+            url: url.to_string(),
+            store,
+            root,
+            cache,
+        })
+    }
+}
+
+#[async_trait]
+impl ModuleSource for BucketSource {
+    fn describe(&self) -> String {
+        self.url.clone()
+    }
+
+    async fn fetch(&self, tenant_id: Option<&str>, module_path: &str) -> Result<Arc<Vec<u8>>> {
+        let segments: Vec<_> = module_path.split('/').collect();
+        if segments.iter().any(|segment| segment.is_empty() || *segment == "." || *segment == "..") {
+            anyhow::bail!("Invalid module path: {}", module_path);
+        }
+        // Tenants' modules are only reached through their tenant
+        if segments[0] == TENANTS {
+            return Err(errors::coded(ErrorCode::InvalidRequest, anyhow::anyhow!("Invalid module path: {}", module_path)));
+        }
+        if let Some(tenant_id) = tenant_id
+            && !crate::valid_path_segment(tenant_id)
+        {
+            anyhow::bail!("Invalid tenant id: {}", tenant_id);
+        }
+
+        let cache_key = match tenant_id {
+            // Not a path a system module can have
+            Some(tenant_id) => format!("{}/{}/{}", TENANTS, tenant_id, module_path),
+            None => module_path.to_string(),
+        };
+        if let Some(bytes) = self.cache.get(&cache_key).await {
+            counter!("module_cache_requests_total", "result" => "hit").increment(1);
+            return Ok(Arc::new(bytes));
+        }
+        counter!("module_cache_requests_total", "result" => "miss").increment(1);
+
+        let own = tenant_id.map(|tenant_id| self.root.child(TENANTS).child(tenant_id));
+        for prefix in own.into_iter().chain([self.root.clone()]) {
+            let key = segments.iter().fold(prefix, |key, segment| key.child(*segment));
+            match self.store.get(&key).await {
+                Ok(object) => {
+                    let bytes = object.bytes().await?.to_vec();
+                    self.cache.put(&cache_key, &bytes).await;
+                    return Ok(Arc::new(bytes));
+                }
+                Err(object_store::Error::NotFound { .. }) => continue,
+                Err(e) => return Err(e).with_context(|| format!("Failed to fetch {} from {}", module_path, self.url)),
+            }
+        }
+        Err(errors::coded(ErrorCode::ModuleNotFound, anyhow::anyhow!("Module not found: {}", module_path)))
+    }
+}
+
+/// Downloaded modules on local disk, named by a hash of their path and
+/// valid for `ttl` after they were written.
+pub struct DiskCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl DiskCache {
+    pub fn new(dir: PathBuf, ttl: Duration) -> Result<Self> {
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create module cache {}", dir.display()))?;
+        Ok(DiskCache { dir, ttl })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.wasm", hex::encode(Sha256::digest(key.as_bytes()))))
+    }
+
+    pub async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let path = self.path(key);
+        let written = tokio::fs::metadata(&path).await.ok()?.modified().ok()?;
+        if expired(written, self.ttl) {
+            return None;
+        }
+        tokio::fs::read(&path).await.ok()
+    }
+
+    /// Best effort: a failed write only means the next fetch downloads again.
+    pub async fn put(&self, key: &str, bytes: &[u8]) {
+        // Written aside and renamed so readers never see a partial module
+        let path = self.path(key);
+        let partial = path.with_extension(format!("{}.partial", std::process::id()));
+        let written = async {
+            tokio::fs::write(&partial, bytes).await?;
+            tokio::fs::rename(&partial, &path).await
+        };
+        if let Err(e) = written.await {
+            warn!("Failed to cache module {}: {}", key, e);
+        }
+    }
+
+    /// Removes expired entries every TTL.
+    pub fn spawn_eviction(&self) {
+        let (dir, ttl) = (self.dir.clone(), self.ttl);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(ttl.max(Duration::from_secs(1)));
+            loop {
+                interval.tick().await;
+                match evict(&dir, ttl).await {
+                    Ok(0) => {}
+                    Ok(evicted) => debug!("Evicted {} cached modules", evicted),
+                    Err(e) => warn!("Module cache eviction failed: {}", e),
+                }
+            }
+        });
+    }
+}
+
+async fn evict(dir: &Path, ttl: Duration) -> std::io::Result<usize> {
+    let mut evicted = 0;
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let written = entry.metadata().await?.modified()?;
+        if expired(written, ttl) && tokio::fs::remove_file(entry.path()).await.is_ok() {
+            evicted += 1;
+        }
+    }
+    Ok(evicted)
+}
+
+fn expired(written: SystemTime, ttl: Duration) -> bool {
+    written.elapsed().is_ok_and(|age| age >= ttl)
+}
@@ -0,0 +1,120 @@
+//! What went wrong when a plugin traps.
+//!
+//! Failed executions whose plugin trapped carry a `trap` in their response:
+//! the reason, e.g. `unreachable`, `memory_out_of_bounds`,
+//! `stack_overflow` or `out_of_fuel`, and the wasm backtrace, innermost
+//! frame first, with function names from the module's name section and
+//! source locations from its DWARF debug info when it has any.
+//!
+//! With `coredump_on_trap` set, the runtime also keeps a wasm coredump of
+//! the plugin's memories and globals at the trap, which debuggers such as
+//! `wasmgdb` read. Asynchronous executions store it with their job, served
+//! by `GET /jobs/{id}/coredump`; see `jobs`. Capturing one copies the
+//! plugin's memory, so it is off by default.
+
+use crate::host::Host;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use wasmtime::{FrameInfo, Store, Trap, WasmBacktrace, WasmCoreDump};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrapInfo {
+    pub reason: &'static str,
+    /// Wasmtime's description of the trap.
+    pub message: String,
+    pub backtrace: Vec<Frame>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Frame {
+    pub module: Option<String>,
+    pub function: Option<String>,
+    pub function_index: u32,
+    /// Of the trapping instruction in the module's bytes.
+    pub module_offset: Option<usize>,
+    /// Source locations, several when functions were inlined.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub symbols: Vec<Symbol>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Symbol {
+    pub name: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+impl From<&FrameInfo> for Frame {
+    fn from(frame: &FrameInfo) -> Self {
+        Frame {
+            module: frame.module().name().map(str::to_string),
+            function: frame.func_name().map(str::to_string),
+            function_index: frame.func_index(),
+            module_offset: frame.module_offset(),
+            symbols: frame
+                .symbols()
+                .iter()
+                .map(|symbol| Symbol {
+                    name: symbol.name().map(str::to_string),
+                    file: symbol.file().map(str::to_string),
+                    line: symbol.line(),
+                    column: symbol.column(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The trap `error` is, if the plugin trapped.
+pub fn describe(error: &anyhow::Error) -> Option<TrapInfo> {
+    let trap = error.downcast_ref::<Trap>()?;
+    let backtrace = error
+        .downcast_ref::<WasmBacktrace>()
+        .map(|backtrace| backtrace.frames().iter().map(Frame::from).collect())
+        .unwrap_or_default();
+    Some(TrapInfo { reason: reason(trap), message: trap.to_string(), backtrace })
+}
+
+fn reason(trap: &Trap) -> &'static str {
+    match trap {
+        Trap::StackOverflow => "stack_overflow",
+        Trap::MemoryOutOfBounds => "memory_out_of_bounds",
+        Trap::HeapMisaligned => "heap_misaligned",
+        Trap::TableOutOfBounds => "table_out_of_bounds",
+        Trap::IndirectCallToNull => "indirect_call_to_null",
+        Trap::BadSignature => "bad_signature",
+        Trap::IntegerOverflow => "integer_overflow",
+        Trap::IntegerDivisionByZero => "integer_division_by_zero",
+        Trap::BadConversionToInteger => "bad_conversion_to_integer",
+        Trap::UnreachableCodeReached => "unreachable",
+        Trap::Interrupt => "interrupt",
+        Trap::OutOfFuel => "out_of_fuel",
+        _ => "other",
+    }
+}
+
+/// Where an execution's coredump ends up, shared with the store it is
+/// taken from.
+#[derive(Clone, Default)]
+pub struct Coredump(Arc<Mutex<Option<Vec<u8>>>>);
+
+impl Coredump {
+    pub fn take(&self) -> Option<Vec<u8>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+}
+
+/// Serializes the coredump wasmtime attached to `error`, if any, while the
+/// store it describes is still around.
+pub fn capture(store: &mut Store<Host>, error: &anyhow::Error) {
+    let Some(dump) = error.downcast_ref::<WasmCoreDump>() else {
+        return;
+    };
+    let Some(call) = store.data().call.as_ref() else {
+        return;
+    };
+    let (slot, name) = (call.coredump.clone(), call.module_path.clone());
+    let bytes = dump.serialize(&mut *store, &name);
+    *slot.0.lock().unwrap_or_else(|e| e.into_inner()) = Some(bytes);
+}
@@ -0,0 +1,184 @@
+//! Dry-run validation, so plugin developers find out in CI whether the
+//! runtime would accept a module instead of at its first execution.
+//!
+//! `POST /validate` with the module as the request body runs the checks an
+//! execution does, without caching or running anything:
+//!
+//! - `size`: at most `MAX_MODULE_BYTES`.
+//! - `parse`: a valid core module or component.
+//! - `imports`: only WASI, the `crm` host functions the manifest's
+//!   capabilities cover and `env.memory` or `env.table`.
+//! - `manifest`: no manifest, or a valid one; see `manifest`.
+//! - `link`: every import resolves against the runtime's linker, for the
+//!   security profile and capabilities the manifest asks for.
+//! - `wasi`: WASI imports only when WASI is enabled for the tenant, the
+//!   token's or the one in `X-Tenant-ID` as for `/modules`, or for everyone
+//!   without one.
+//! - `profile`: the profile is one the tenant, or everyone without one,
+//!   may run.
+//! - `capabilities`: the tenant, or everyone without one, is granted every
+//!   capability the plugin asks for; see `capabilities`.
+//!
+//! The report lists every violation found and the profile and capabilities
+//! checked; 200
+//! when there are none, 422 otherwise. Size and parse failures stop the
+//! checks after them.
+
+use crate::capabilities::Capabilities;
+use crate::compiled::is_component;
+use crate::manifest::PluginManifest;
+use crate::modules;
+use crate::profiles::Profile;
+use crate::{import_violations, new_component_linker, new_linker, ServiceState, MAX_MODULE_BYTES, TENANT_HEADER, WASI_FLAG};
+use anyhow::Result;
+use metrics::counter;
+use serde::Serialize;
+use serde_json::json;
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::hyper::body::Bytes;
+use warp::Filter;
+use wasmtime::component::Component;
+use wasmtime::{Engine, Module};
+
+#[derive(Debug, Serialize)]
+struct Violation {
+    check: &'static str,
+    message: String,
+}
+
+impl Violation {
+    fn new(check: &'static str, message: impl Into<String>) -> Self {
+        Violation { check, message: message.into() }
+    }
+}
+
+enum Parsed {
+    Module(Module),
+    Component(Component),
+}
+
+impl Parsed {
+    fn kind(&self) -> &'static str {
+        match self {
+            Parsed::Module(_) => "module",
+            Parsed::Component(_) => "component",
+        }
+    }
+
+    /// Whether every import resolves, with or without WASI linked.
+    fn link(&self, engine: &Engine, wasi: bool, profile: Profile, capabilities: Capabilities) -> Result<()> {
+        match self {
+            Parsed::Module(module) => {
+                new_linker(engine, wasi, profile, capabilities)?.instantiate_pre(module).map(drop)
+            }
+            Parsed::Component(component) => new_component_linker(engine, wasi)?.instantiate_pre(component).map(drop),
+        }
+    }
+}
+
+pub fn routes(
+    state: Arc<ServiceState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("validate")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::body::content_length_limit(MAX_MODULE_BYTES as u64 + 1))
+        .and(warp::body::bytes())
+        .and(warp::header::optional::<String>(TENANT_HEADER))
+        .and(warp::header::optional::<String>("authorization"))
+        .then(move |bytes: Bytes, tenant_id: Option<String>, authorization: Option<String>| {
+            let state = state.clone();
+            async move {
+                match modules::namespace(&state, tenant_id, authorization).await {
+                    Ok(tenant_id) => validate(&state, &bytes, tenant_id.as_deref()).await,
+                    Err(refused) => refused,
+                }
+            }
+        })
+}
+
+async fn validate(
+    state: &ServiceState,
+    bytes: &[u8],
+    tenant_id: Option<&str>,
+) -> warp::reply::WithStatus<warp::reply::Json> {
+    let (kind, (profile, capabilities), violations) = check(state, bytes, tenant_id).await;
+    let valid = violations.is_empty();
+    counter!("module_validations_total", "result" => if valid { "valid" } else { "invalid" }).increment(1);
+    let status = if valid { StatusCode::OK } else { StatusCode::UNPROCESSABLE_ENTITY };
+    let report = json!({
+        "valid": valid,
+        "kind": kind,
+        "profile": profile.name(),
+        "capabilities": capabilities,
+        "size_bytes": bytes.len(),
+        "violations": violations,
+    });
+    warp::reply::with_status(warp::reply::json(&report), status)
+}
+
+/// What `bytes` parse as, if anything, the profile and capabilities they're
+/// checked for, and what's wrong with them.
+async fn check(
+    state: &ServiceState,
+    bytes: &[u8],
+    tenant_id: Option<&str>,
+) -> (Option<&'static str>, (Profile, Capabilities), Vec<Violation>) {
+    let default_capabilities = state.config().default_capabilities;
+    if bytes.len() > MAX_MODULE_BYTES {
+        let message = format!("Module is {} bytes; at most {} are allowed", bytes.len(), MAX_MODULE_BYTES);
+        return (None, (Profile::default(), default_capabilities), vec![Violation::new("size", message)]);
+    }
+    let mut violations = Vec::new();
+    let manifest = PluginManifest::from_module(bytes).unwrap_or_else(|e| {
+        violations.push(Violation::new("manifest", format!("{:#}", e)));
+        PluginManifest::default()
+    });
+    let profile = manifest.profile;
+    let capabilities = manifest.capabilities.unwrap_or(default_capabilities);
+    let engine = state.compiled.engine(profile, manifest.deterministic);
+    let parsed = if is_component(bytes) {
+        Component::from_binary(engine, bytes).map(Parsed::Component)
+    } else {
+        Module::from_binary(engine, bytes).map(Parsed::Module)
+    };
+    let parsed = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            violations.push(Violation::new("parse", format!("{:#}", e)));
+            return (None, (profile, capabilities), violations);
+        }
+    };
+
+    if let Parsed::Module(module) = &parsed {
+        violations.extend(import_violations(module, capabilities).into_iter().map(|message| Violation::new("imports", message)));
+    }
+    // Linked as for a tenant with WASI, so a missing import is told apart
+    // from WASI being off
+    match parsed.link(engine, true, profile, capabilities) {
+        Err(e) => violations.push(Violation::new("link", format!("{:#}", e))),
+        Ok(()) => {
+            let wasi_enabled = match tenant_id {
+                Some(tenant_id) => state.flags.is_enabled(tenant_id, WASI_FLAG, true).await,
+                None => true,
+            };
+            if !wasi_enabled && parsed.link(engine, false, profile, capabilities).is_err() {
+                let message = format!("Module imports WASI, which is disabled for tenant {}", tenant_id.unwrap_or_default());
+                violations.push(Violation::new("wasi", message));
+            }
+        }
+    }
+    let limits = state.quotas.limits(tenant_id);
+    let allowed = limits.max_security_profile;
+    if profile > allowed {
+        let message = format!("Module needs the {} security profile; at most {} is allowed", profile.name(), allowed.name());
+        violations.push(Violation::new("profile", message));
+    }
+    let missing = capabilities.missing_from(limits.capabilities);
+    if !missing.is_empty() {
+        let message = format!("Module needs capabilities the tenant isn't granted: {}", missing);
+        violations.push(Violation::new("capabilities", message));
+    }
+    (Some(parsed.kind()), (profile, capabilities), violations)
+}
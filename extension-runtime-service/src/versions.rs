@@ -0,0 +1,78 @@
+//! Version references: an execute request's `module_path` may name a
+//! registered module by a semver requirement instead of an exact version,
+//! e.g. `invoice-export@^1.2` or `invoice-export@>=1.2, <1.5`. It runs the
+//! highest version registered under that name that matches, pre-releases
+//! only when the requirement names one, and the response's
+//! `resolved_version` says which.
+//!
+//! Exact ids such as `invoice-export@1.2.0` keep naming that one module,
+//! so a requirement needs an operator: `^1.2`, not `1.2`.
+//!
+//! A tenant can pin a plugin to other versions than its callers ask for,
+//! e.g. while a release is rolled back for it. A pin replaces the
+//! requirement of every reference to the plugin made for the tenant:
+//!
+//! ```toml
+//! [version_pins.acme]
+//! invoice-export = "=1.4.2"
+//! ```
+//!
+//! Versions are resolved by the module registry, among the tenant's own
+//! modules and the system ones, so references need `registry_url`.
+
+use crate::errors::{self, ErrorCode};
+use crate::registry;
+use crate::ServiceState;
+use anyhow::{Context, Result};
+use semver::{Version, VersionReq};
+use std::collections::HashMap;
+use tracing::debug;
+
+/// The plugin name and requirement of `module_path`, when it's a version
+/// reference rather than a module id.
+fn parse(module_path: &str) -> Option<(&str, VersionReq)> {
+    if registry::check_id(module_path).is_ok() {
+        return None;
+    }
+    let (name, requirement) = module_path.split_once('@')?;
+    registry::check_name(name, "").ok()?;
+    Some((name, VersionReq::parse(requirement).ok()?))
+}
+
+/// The module id and version `module_path` resolves to for `tenant_id`,
+/// when it's a version reference.
+pub async fn resolve(state: &ServiceState, module_path: &str, tenant_id: Option<&str>) -> Result<Option<(String, Version)>> {
+    let Some((name, requested)) = parse(module_path) else {
+        return Ok(None);
+    };
+    let config = state.config();
+    let pin = tenant_id.and_then(|tenant_id| config.version_pins.get(tenant_id)?.get(name));
+    let requirement = match pin {
+        Some(pin) => VersionReq::parse(pin).with_context(|| format!("Invalid version pin for {}: {}", name, pin))?,
+        None => requested,
+    };
+    let Some(registry) = &state.registry else {
+        anyhow::bail!("Version references need the module registry: {}", module_path);
+    };
+    let (version, id) = registry
+        .versions(tenant_id, name)
+        .await?
+        .iter()
+        .filter_map(|metadata| Some((Version::parse(&metadata.version).ok()?, metadata.id())))
+        .filter(|(version, _)| requirement.matches(version))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .ok_or_else(|| errors::coded(ErrorCode::ModuleNotFound, anyhow::anyhow!("No version of {} matches {}", name, requirement)))?;
+    debug!("Resolved {} to {}{}", module_path, id, if pin.is_some() { " (pinned)" } else { "" });
+    Ok(Some((id, version)))
+}
+
+/// Problems with `version_pins`, for config validation.
+pub fn validate_pins(pins: &HashMap<String, HashMap<String, String>>, problems: &mut Vec<String>) {
+    for (tenant_id, pins) in pins {
+        for (name, pin) in pins {
+            if VersionReq::parse(pin).is_err() {
+                problems.push(format!("version_pins.{}.{} is not a version requirement: {}", tenant_id, name, pin));
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! Warm instances, so hot plugins skip instantiation on the request path.
+//!
+//! Each module gets an `InstancePre` (its imports resolved against the
+//! linker once) and up to `warm_pool_size` instances created from it ahead
+//! of time. An execution checks one out and throws it away afterwards, as
+//! a used instance's memory and globals can't be trusted to be reset, and
+//! a background refill replaces it. Requests that pass secrets, their own
+//! environment variables or a seed to the plugin, and modules with
+//! `wasi_grants`, get their own instance, since a warm one was created
+//! without them. Pools are kept for the `warm_pool_modules` most recently used
+//! modules.
+
+use crate::capabilities::Capabilities;
+use crate::compiled::{Code, Compiled};
+use crate::environment;
+use crate::grants::WasiGrant;
+use crate::host::Host;
+use crate::profiles::{Engines, Profile};
+use crate::quotas::Limits;
+use crate::{apply_limits, new_component_linker, new_linker, new_store, RuntimeConfig};
+use anyhow::{Context, Result};
+use metrics::{counter, gauge};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, warn};
+use wasmtime::{component, Engine, Store};
+
+/// An instance and the store it lives in.
+pub struct Warm {
+    pub store: Store<Host>,
+    pub instance: Instance,
+}
+
+pub enum Instance {
+    Module(wasmtime::Instance),
+    Component(component::Instance),
+}
+
+/// A module's imports, resolved.
+#[derive(Clone)]
+enum InstancePre {
+    Module(wasmtime::InstancePre<Host>),
+    Component(component::InstancePre<Host>),
+}
+
+impl InstancePre {
+    fn new(engine: &Engine, code: &Code, wasi: bool, profile: Profile, capabilities: Capabilities) -> Result<Self> {
+        Ok(match code {
+            Code::Module(module) => {
+                InstancePre::Module(new_linker(engine, wasi, profile, capabilities)?.instantiate_pre(module)?)
+            }
+            Code::Component(component) => {
+                InstancePre::Component(new_component_linker(engine, wasi)?.instantiate_pre(component)?)
+            }
+        })
+    }
+
+    fn instantiate(&self, store: &mut Store<Host>) -> Result<Instance> {
+        Ok(match self {
+            InstancePre::Module(pre) => Instance::Module(pre.instantiate(store)?),
+            InstancePre::Component(pre) => Instance::Component(pre.instantiate(store)?),
+        })
+    }
+}
+
+struct Pool {
+    pre: InstancePre,
+    /// What instances are made for.
+    compiled: Compiled,
+    ready: Vec<Warm>,
+    refilling: bool,
+    used: u64,
+}
+
+pub struct WarmPools {
+    engines: Engines,
+    config: RuntimeConfig,
+    /// What warm instances are created with, until checked out.
+    defaults: Limits,
+    /// The `plugin_env` every instance gets.
+    passthrough: Vec<(String, String)>,
+    /// By module hash and whether WASI is linked.
+    pools: Mutex<HashMap<(String, bool), Pool>>,
+    clock: AtomicU64,
+}
+
+impl WarmPools {
+    pub fn new(engines: Engines, config: &RuntimeConfig) -> Arc<Self> {
+        Arc::new(WarmPools {
+            engines,
+            config: config.clone(),
+            defaults: Limits::from_config(config),
+            passthrough: environment::passthrough(config),
+            pools: Mutex::new(HashMap::new()),
+            clock: AtomicU64::new(0),
+        })
+    }
+
+    /// An instance of `compiled` ready to call, held to `limits`. `seed`
+    /// only matters to deterministic plugins; see `determinism`.
+    pub fn checkout(
+        self: &Arc<Self>,
+        compiled: &Compiled,
+        wasi: bool,
+        env: &[(String, String)],
+        grant: Option<&WasiGrant>,
+        seed: Option<u64>,
+        limits: &Limits,
+    ) -> Result<Warm> {
+        let fresh = !env.is_empty() || grant.is_some() || seed.is_some();
+        let key = (compiled.sha256.clone(), wasi);
+        let (profile, capabilities) = (compiled.profile, compiled.capabilities);
+        let (pre, warm) = {
+            let mut pools = self.pools.lock().unwrap();
+            let pool = match pools.get_mut(&key) {
+                Some(pool) => pool,
+                None => {
+                    let engine = self.engines.get(profile, compiled.deterministic);
+                    let pre = InstancePre::new(engine, &compiled.code, wasi, profile, capabilities)
+                        .context("Failed to instantiate module")?;
+                    self.evict(&mut pools);
+                    let pool = Pool { pre, compiled: compiled.clone(), ready: Vec::new(), refilling: false, used: 0 };
+                    pools.entry(key.clone()).or_insert(pool)
+                }
+            };
+            pool.used = self.clock.fetch_add(1, Ordering::Relaxed);
+            let warm = if fresh { None } else { pool.ready.pop() };
+            if !fresh && !pool.refilling && pool.ready.len() < self.config.warm_pool_size {
+                pool.refilling = true;
+                let pools = self.clone();
+                let key = key.clone();
+                tokio::spawn(async move { pools.refill(key) });
+            }
+            (pool.pre.clone(), warm)
+        };
+        self.report();
+
+        // A warm instance was created under the default memory limit, so one
+        // whose memory already outgrew a tighter tenant limit isn't used
+        let fits = |warm: &mut Warm| {
+            let Instance::Module(instance) = &warm.instance else {
+                return true;
+            };
+            let memory = instance.get_memory(&mut warm.store, "memory");
+            memory.is_none_or(|memory| memory.size(&warm.store) <= limits.max_memory_pages as u64)
+        };
+        let mut warm = match warm.and_then(|mut warm| fits(&mut warm).then_some(warm)) {
+            Some(warm) => {
+                counter!("warm_instance_checkouts_total", "result" => "hit").increment(1);
+                warm
+            }
+            None => {
+                counter!("warm_instance_checkouts_total", "result" => "miss").increment(1);
+                self.instantiate(&pre, compiled, env, grant, seed, limits)?
+            }
+        };
+        apply_limits(&mut warm.store, limits)?;
+        Ok(warm)
+    }
+
+    fn instantiate(
+        &self,
+        pre: &InstancePre,
+        compiled: &Compiled,
+        env: &[(String, String)],
+        grant: Option<&WasiGrant>,
+        seed: Option<u64>,
+        limits: &Limits,
+    ) -> Result<Warm> {
+        // The execution's own variables win over the runtime's
+        let env: Vec<(String, String)> = self
+            .passthrough
+            .iter()
+            .filter(|(name, _)| !env.iter().any(|(own, _)| own == name))
+            .chain(env)
+            .cloned()
+            .collect();
+        let engine = self.engines.get(compiled.profile, compiled.deterministic);
+        let seed = compiled.deterministic.then(|| seed.unwrap_or_default());
+        let mut store = new_store(engine, limits, &env, grant, compiled.capabilities, seed)?;
+        let instance = pre.instantiate(&mut store).context("Failed to instantiate module")?;
+        Ok(Warm { store, instance })
+    }
+
+    /// Tops the pool under `key` back up to `warm_pool_size`.
+    fn refill(&self, key: (String, bool)) {
+        loop {
+            let (pre, compiled) = {
+                let mut pools = self.pools.lock().unwrap();
+                let Some(pool) = pools.get_mut(&key) else { return };
+                if pool.ready.len() >= self.config.warm_pool_size {
+                    pool.refilling = false;
+                    break;
+                }
+                (pool.pre.clone(), pool.compiled.clone())
+            };
+            let defaults = compiled.profile.limits(self.defaults, &self.config);
+            let warm = match self.instantiate(&pre, &compiled, &[], None, None, &defaults) {
+                Ok(warm) => warm,
+                Err(e) => {
+                    warn!("Failed to warm an instance of {}: {:#}", key.0, e);
+                    if let Some(pool) = self.pools.lock().unwrap().get_mut(&key) {
+                        pool.refilling = false;
+                    }
+                    return;
+                }
+            };
+            match self.pools.lock().unwrap().get_mut(&key) {
+                Some(pool) => pool.ready.push(warm),
+                None => return,
+            }
+        }
+        self.report();
+    }
+
+    /// Drops least recently used pools until there is room for one more.
+    fn evict(&self, pools: &mut HashMap<(String, bool), Pool>) {
+        while pools.len() >= self.config.warm_pool_modules {
+            let Some(oldest) = pools.iter().min_by_key(|(_, pool)| pool.used).map(|(key, _)| key.clone()) else {
+                return;
+            };
+            debug!("Dropping warm instances of {}", oldest.0);
+            pools.remove(&oldest);
+        }
+    }
+
+    fn report(&self) {
+        let ready: usize = self.pools.lock().unwrap().values().map(|pool| pool.ready.len()).sum();
+        gauge!("warm_instances_ready").set(ready as f64);
+    }
+}
@@ -0,0 +1,174 @@
+//! `openapi.json` against the routes the runtime serves: every operation it
+//! documents is answered by a route, and every path a route matches on is
+//! documented.
+
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+const METHODS: &[&str] = &["get", "put", "post", "delete", "patch"];
+
+/// Where the runtime's routes and the shared routes it mounts are defined.
+const ROUTE_SOURCES: &[&str] = &[
+    "src",
+    "../crm-observability/src/http.rs",
+    "../crm-startup/src/health.rs",
+    "../crm-chaos/src/lib.rs",
+];
+
+fn document() -> Value {
+    serde_json::from_str(include_str!("../openapi.json")).unwrap()
+}
+
+/// Documented operations as (method, path) pairs, leaving out those of
+/// features this build doesn't have.
+fn operations(document: &Value) -> Vec<(String, String)> {
+    let mut operations = Vec::new();
+    for (path, item) in document["paths"].as_object().unwrap() {
+        if item["x-cargo-feature"] == "chaos" && !cfg!(feature = "chaos") {
+            continue;
+        }
+        for method in METHODS {
+            if item.get(*method).is_some() {
+                operations.push((method.to_string(), path.clone()));
+            }
+        }
+    }
+    operations
+}
+
+struct Runtime {
+    process: Child,
+    url: String,
+    dir: PathBuf,
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+async fn start() -> Runtime {
+    let dir = std::env::temp_dir().join(format!("runtime-openapi-{}", uuid::Uuid::new_v4()));
+    for sub in ["modules", "cache", "registry"] {
+        std::fs::create_dir_all(dir.join(sub)).unwrap();
+    }
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let process = Command::new(env!("CARGO_BIN_EXE_extension-runtime-service"))
+        .env("RUNTIME_BIND_ADDRESS", format!("127.0.0.1:{}", port))
+        // With a registry, so the /modules routes are served
+        .env("RUNTIME_REGISTRY_URL", format!("file://{}", dir.join("registry").display()))
+        .env("WASM_MODULE_DIR", dir.join("modules"))
+        .env("RUNTIME_MODULE_CACHE_DIR", dir.join("cache"))
+        // Nothing listens here: the stores kept in Redis are unavailable
+        .env("REDIS_URL", "redis://127.0.0.1:1")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let runtime = Runtime { process, url: format!("http://127.0.0.1:{}", port), dir };
+
+    let http = reqwest::Client::new();
+    for _ in 0..300 {
+        if http.get(format!("{}/openapi.json", runtime.url)).send().await.is_ok() {
+            return runtime;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("the runtime didn't start");
+}
+
+#[tokio::test]
+async fn documented_operations_are_served() {
+    let runtime = start().await;
+    let http = reqwest::Client::new();
+
+    let mut unserved = Vec::new();
+    for (method, path) in operations(&document()) {
+        // Any value will do for a path parameter: the route has to match,
+        // not to find anything
+        let concrete: Vec<&str> = path
+            .split('/')
+            .map(|segment| if segment.starts_with('{') { "example.wasm" } else { segment })
+            .collect();
+        let url = format!("{}{}", runtime.url, concrete.join("/"));
+        let response = http
+            .request(method.to_uppercase().parse().unwrap(), url)
+            .header("Content-Type", "application/json")
+            .body("{}")
+            .send()
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        let body = response.bytes().await.unwrap();
+
+        // What's left when no route matched: an empty 404, or a 405 from
+        // a route whose path matched under another method. Anything else,
+        // a refused body included, comes from the route.
+        if status == 405 || (status == 404 && body.is_empty()) {
+            unserved.push(format!("{} {} ({})", method.to_uppercase(), path, status));
+        }
+    }
+    assert!(unserved.is_empty(), "documented but not served: {:?}", unserved);
+}
+
+#[test]
+fn routed_paths_are_documented() {
+    let document = document();
+    let documented: Vec<&str> = document["paths"]
+        .as_object()
+        .unwrap()
+        .keys()
+        .flat_map(|path| path.split('/'))
+        .filter(|segment| !segment.is_empty() && !segment.starts_with('{'))
+        .collect();
+
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut undocumented = Vec::new();
+    for source in ROUTE_SOURCES {
+        for file in rust_files(&manifest_dir.join(source)) {
+            let code = std::fs::read_to_string(&file).unwrap();
+            for segment in path_segments(&code) {
+                if !documented.contains(&segment.as_str()) {
+                    undocumented.push(format!("{} in {}", segment, file.display()));
+                }
+            }
+        }
+    }
+    assert!(undocumented.is_empty(), "routed but not documented: {:?}", undocumented);
+}
+
+fn rust_files(path: &Path) -> Vec<PathBuf> {
+    if path.is_file() {
+        return vec![path.to_path_buf()];
+    }
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend(rust_files(&path));
+        } else if path.extension().is_some_and(|extension| extension == "rs") {
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// The literal segments of the `warp::path!(..)` and `warp::path(..)`
+/// filters in `code`.
+fn path_segments(code: &str) -> Vec<String> {
+    let mut segments = Vec::new();
+    for (prefix, end) in [("warp::path!(", ')'), ("warp::path(", ')')] {
+        let mut rest = code;
+        while let Some(start) = rest.find(prefix) {
+            rest = &rest[start + prefix.len()..];
+            let arguments = &rest[..rest.find(end).unwrap_or(rest.len())];
+            segments.extend(arguments.split('"').skip(1).step_by(2).map(|literal| literal.to_string()));
+        }
+    }
+    segments
+}
@@ -0,0 +1,219 @@
+use extension_runtime_service::pools::{ExecutionPools, PoolConfig, PoolPermit, Tier, Unavailable};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Executions that got a slot, by label.
+type Granted = (&'static str, PoolPermit);
+
+/// Ten slots: five enterprise, three pro and two free.
+fn config(queue_depth_per_tenant: usize, queue_timeout: Duration) -> PoolConfig {
+    PoolConfig {
+        max_instances: 10,
+        enterprise_percent: 50,
+        pro_percent: 30,
+        queue_depth_per_tenant,
+        queue_timeout,
+    }
+}
+
+/// A single free slot, with room to queue.
+fn one_slot() -> Arc<ExecutionPools> {
+    ExecutionPools::new(&PoolConfig {
+        max_instances: 1,
+        enterprise_percent: 0,
+        pro_percent: 0,
+        queue_depth_per_tenant: 8,
+        queue_timeout: Duration::from_secs(5),
+    })
+}
+
+async fn take(pools: &Arc<ExecutionPools>, tier: Tier, count: usize) -> Vec<PoolPermit> {
+    let mut permits = Vec::new();
+    for _ in 0..count {
+        permits.push(pools.acquire(tier, Some("t1")).await.unwrap());
+    }
+    permits
+}
+
+async fn refused(pools: &Arc<ExecutionPools>, tier: Tier) -> Unavailable {
+    match pools.acquire(tier, Some("t1")).await {
+        Ok(_) => panic!("a {} execution got a slot", tier.name()),
+        Err(e) => e,
+    }
+}
+
+/// Queues an execution labelled `label`, which reports with its permit
+/// once it gets one.
+fn queue(
+    pools: &Arc<ExecutionPools>,
+    tier: Tier,
+    tenant: &'static str,
+    label: &'static str,
+    granted: &mpsc::UnboundedSender<Granted>,
+) -> tokio::task::JoinHandle<()> {
+    let pools = Arc::clone(pools);
+    let granted = granted.clone();
+    tokio::spawn(async move {
+        let permit = pools.acquire(tier, Some(tenant)).await.unwrap();
+        let _ = granted.send((label, permit));
+    })
+}
+
+/// Lets the queued executions reach their queue.
+async fn settle() {
+    tokio::time::sleep(Duration::from_millis(20)).await;
+}
+
+/// Releases `permit` and the ones the waiters get after it, in the order
+/// they got them.
+async fn drain(mut permit: PoolPermit, granted: &mut mpsc::UnboundedReceiver<Granted>, count: usize) -> Vec<&'static str> {
+    let mut order = Vec::new();
+    for _ in 0..count {
+        drop(permit);
+        let (label, next) = tokio::time::timeout(Duration::from_secs(1), granted.recv()).await.unwrap().unwrap();
+        order.push(label);
+        permit = next;
+    }
+    order
+}
+
+#[tokio::test]
+async fn tiers_only_take_their_own_or_lower_pools() {
+    let pools = ExecutionPools::new(&config(0, Duration::from_secs(1)));
+
+    // Free executions can't go past the free pool
+    let free = take(&pools, Tier::Free, 2).await;
+    assert_eq!(refused(&pools, Tier::Free).await, Unavailable::QueueFull(Tier::Free));
+
+    // Pro ones have the pro pool to themselves, and no more while the
+    // free pool is full
+    let mut pro = take(&pools, Tier::Pro, 3).await;
+    assert_eq!(refused(&pools, Tier::Pro).await, Unavailable::QueueFull(Tier::Pro));
+
+    // The enterprise pool is left for enterprise tenants
+    let enterprise = take(&pools, Tier::Enterprise, 5).await;
+    assert_eq!(refused(&pools, Tier::Enterprise).await, Unavailable::QueueFull(Tier::Enterprise));
+
+    // A freed pro slot can be borrowed by enterprise, not by free
+    pro.pop();
+    assert_eq!(refused(&pools, Tier::Free).await, Unavailable::QueueFull(Tier::Free));
+    let borrowed = pools.acquire(Tier::Enterprise, Some("t1")).await.unwrap();
+    drop((free, pro, enterprise, borrowed));
+}
+
+#[tokio::test]
+async fn enterprise_borrows_the_free_pool_before_the_pro_pool() {
+    let pools = ExecutionPools::new(&config(0, Duration::from_secs(1)));
+    let enterprise = take(&pools, Tier::Enterprise, 5).await;
+    let borrowed = take(&pools, Tier::Enterprise, 2).await;
+
+    // Both free slots went, so pro tenants still find theirs
+    let pro = take(&pools, Tier::Pro, 3).await;
+    assert_eq!(refused(&pools, Tier::Free).await, Unavailable::QueueFull(Tier::Free));
+    drop((enterprise, borrowed, pro));
+}
+
+#[tokio::test]
+async fn a_released_slot_goes_to_the_waiting_tenant() {
+    let pools = one_slot();
+    let (granted, mut grants) = mpsc::unbounded_channel();
+    let held = pools.acquire(Tier::Free, Some("t1")).await.unwrap();
+
+    let waiting = queue(&pools, Tier::Free, "t2", "t2", &granted);
+    settle().await;
+    assert!(grants.try_recv().is_err());
+
+    drop(held);
+    let (label, _permit) = tokio::time::timeout(Duration::from_secs(1), grants.recv()).await.unwrap().unwrap();
+    assert_eq!(label, "t2");
+    waiting.await.unwrap();
+}
+
+#[tokio::test]
+async fn a_tenant_waiting_executions_go_in_order() {
+    let pools = one_slot();
+    let (granted, mut grants) = mpsc::unbounded_channel();
+    let held = pools.acquire(Tier::Free, Some("t1")).await.unwrap();
+
+    for label in ["first", "second", "third"] {
+        queue(&pools, Tier::Free, "t2", label, &granted);
+        settle().await;
+    }
+    assert_eq!(drain(held, &mut grants, 3).await, ["first", "second", "third"]);
+}
+
+#[tokio::test]
+async fn a_burst_only_delays_its_own_tenant() {
+    let pools = one_slot();
+    let (granted, mut grants) = mpsc::unbounded_channel();
+    let held = pools.acquire(Tier::Free, Some("t1")).await.unwrap();
+
+    for label in ["burst 1", "burst 2", "burst 3"] {
+        queue(&pools, Tier::Free, "bursting", label, &granted);
+    }
+    settle().await;
+    // Queued last, but at four times the weight
+    queue(&pools, Tier::Enterprise, "enterprise", "enterprise", &granted);
+    settle().await;
+
+    assert_eq!(drain(held, &mut grants, 4).await, ["enterprise", "burst 1", "burst 2", "burst 3"]);
+}
+
+#[tokio::test]
+async fn waits_are_limited_in_depth_and_time() {
+    let pools = ExecutionPools::new(&config(1, Duration::from_millis(50)));
+    let free = take(&pools, Tier::Free, 2).await;
+
+    let waiting = tokio::spawn({
+        let pools = Arc::clone(&pools);
+        async move { pools.acquire(Tier::Free, Some("t1")).await.err() }
+    });
+    settle().await;
+    // The tenant's one place in the queue is taken
+    assert_eq!(refused(&pools, Tier::Free).await, Unavailable::QueueFull(Tier::Free));
+    assert_eq!(waiting.await.unwrap(), Some(Unavailable::TimedOut(Tier::Free)));
+
+    // Timing out gave the place back
+    assert_eq!(
+        pools.acquire(Tier::Free, Some("t1")).await.err(),
+        Some(Unavailable::TimedOut(Tier::Free))
+    );
+    drop(free);
+}
+
+#[tokio::test]
+async fn a_cancelled_wait_doesnt_keep_the_slot() {
+    let pools = one_slot();
+    let (granted, mut grants) = mpsc::unbounded_channel();
+    let held = pools.acquire(Tier::Free, Some("t1")).await.unwrap();
+
+    let cancelled = queue(&pools, Tier::Free, "t2", "cancelled", &granted);
+    settle().await;
+    queue(&pools, Tier::Free, "t2", "next", &granted);
+    settle().await;
+    cancelled.abort();
+    let _ = cancelled.await;
+
+    // The slot handed to the cancelled execution comes back for the next one
+    assert_eq!(drain(held, &mut grants, 1).await, ["next"]);
+}
+
+#[tokio::test]
+async fn a_grown_pool_takes_waiting_executions_at_once() {
+    let pools = one_slot();
+    let (granted, mut grants) = mpsc::unbounded_channel();
+    let _held = pools.acquire(Tier::Free, Some("t1")).await.unwrap();
+    queue(&pools, Tier::Free, "t2", "t2", &granted);
+    settle().await;
+
+    pools.resize(&PoolConfig {
+        max_instances: 2,
+        enterprise_percent: 0,
+        pro_percent: 0,
+        queue_depth_per_tenant: 8,
+        queue_timeout: Duration::from_secs(5),
+    });
+    let (label, _permit) = tokio::time::timeout(Duration::from_secs(1), grants.recv()).await.unwrap().unwrap();
+    assert_eq!(label, "t2");
+}
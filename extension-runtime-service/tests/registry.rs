@@ -0,0 +1,169 @@
+use extension_runtime_service::errors::{classify, ErrorCode};
+use extension_runtime_service::registry::{ModuleRegistry, RegistryConfig};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// A primary and a secondary registry in local directories.
+struct Regions {
+    dir: PathBuf,
+}
+
+impl Regions {
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("runtime-registry-{}", uuid::Uuid::new_v4()));
+        for region in ["primary", "secondary"] {
+            std::fs::create_dir_all(dir.join(region)).unwrap();
+        }
+        Regions { dir }
+    }
+
+    fn path(&self, region: &str) -> PathBuf {
+        self.dir.join(region)
+    }
+
+    fn registry(&self, cache_ttl: Duration) -> ModuleRegistry {
+        ModuleRegistry::open(&RegistryConfig {
+            primary_url: format!("file://{}", self.path("primary").display()),
+            secondary_url: Some(format!("file://{}", self.path("secondary").display())),
+            secondary_region: None,
+            cache_ttl,
+        })
+        .unwrap()
+    }
+
+    /// Takes the primary down: its directory becomes a file, so every read
+    /// fails rather than finding nothing.
+    fn fail_primary(&self) {
+        let primary = self.path("primary");
+        std::fs::rename(&primary, self.path("primary-down")).unwrap();
+        std::fs::write(&primary, b"").unwrap();
+    }
+}
+
+impl Drop for Regions {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Every file under `dir`, relative to it.
+fn files(dir: &Path) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(next) = pending.pop() {
+        for entry in std::fs::read_dir(next).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                files.push(path.strip_prefix(dir).unwrap().display().to_string());
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+#[tokio::test]
+async fn reconciling_copies_uploads_and_deletions_to_the_secondary() {
+    let regions = Regions::new();
+    let registry = regions.registry(Duration::from_secs(30));
+    let system = registry.upload(None, "invoice-export", "1.0.0", b"\0asm v1".to_vec()).await.unwrap();
+    registry.upload(Some("t1"), "invoice-export", "1.0.0", b"\0asm v1".to_vec()).await.unwrap();
+    registry.upload(Some("t1"), "lead-score", "", b"\0asm lead".to_vec()).await.unwrap();
+
+    let reconciled = registry.reconcile().await.unwrap();
+    assert_eq!((reconciled.copied, reconciled.deleted), (3, 0));
+    assert_eq!(files(&regions.path("secondary")), files(&regions.path("primary")));
+    // Modules sharing their bytes share the blob
+    assert_eq!(files(&regions.path("secondary")).iter().filter(|file| file.starts_with("blobs/")).count(), 2);
+
+    // Nothing changed, nothing to do
+    let reconciled = registry.reconcile().await.unwrap();
+    assert_eq!((reconciled.copied, reconciled.deleted), (0, 0));
+
+    // A blob still used by the tenant's copy is kept
+    assert!(registry.delete(None, &system.id()).await.unwrap());
+    let reconciled = registry.reconcile().await.unwrap();
+    assert_eq!((reconciled.copied, reconciled.deleted), (0, 1));
+    assert_eq!(files(&regions.path("secondary")), files(&regions.path("primary")));
+    assert!(files(&regions.path("secondary")).contains(&format!("blobs/{}", system.sha256)));
+}
+
+#[tokio::test]
+async fn reads_fail_over_to_the_secondary() {
+    let regions = Regions::new();
+    let uploader = regions.registry(Duration::from_secs(30));
+    uploader.upload(Some("t1"), "lead-score", "1.0.0", b"\0asm lead".to_vec()).await.unwrap();
+    uploader.upload(None, "invoice-export", "", b"\0asm invoice".to_vec()).await.unwrap();
+    uploader.reconcile().await.unwrap();
+
+    regions.fail_primary();
+    let registry = regions.registry(Duration::from_secs(30));
+    assert_eq!(*registry.fetch(Some("t1"), "lead-score@1.0.0").await.unwrap(), b"\0asm lead");
+    assert_eq!(*registry.fetch(Some("t1"), "invoice-export").await.unwrap(), b"\0asm invoice");
+
+    // Still only visible to its tenant
+    assert!(registry.fetch(Some("t2"), "lead-score@1.0.0").await.is_err());
+}
+
+#[tokio::test]
+async fn the_primary_decides_what_exists() {
+    let regions = Regions::new();
+    let registry = regions.registry(Duration::ZERO);
+    let uploaded = registry.upload(None, "invoice-export", "", b"\0asm invoice".to_vec()).await.unwrap();
+    registry.reconcile().await.unwrap();
+
+    // Deleted on the primary, not yet on the secondary
+    registry.delete(None, &uploaded.id()).await.unwrap();
+    let error = registry.fetch(None, "invoice-export").await.unwrap_err();
+    assert_eq!(classify(&error), ErrorCode::ModuleNotFound);
+}
+
+#[tokio::test]
+async fn serves_the_cached_copy_while_both_regions_fail() {
+    let regions = Regions::new();
+    // Cached copies are stale at once, so every fetch goes to the registry
+    let registry = regions.registry(Duration::ZERO);
+    registry.upload(None, "invoice-export", "", b"\0asm invoice".to_vec()).await.unwrap();
+    registry.fetch(None, "invoice-export").await.unwrap();
+
+    // Never replicated, so the secondary doesn't have it either
+    regions.fail_primary();
+    assert_eq!(*registry.fetch(None, "invoice-export").await.unwrap(), b"\0asm invoice");
+
+    // With nothing cached the fetch fails, and not as a missing module
+    let error = regions.registry(Duration::ZERO).fetch(None, "invoice-export").await.unwrap_err();
+    assert_ne!(classify(&error), ErrorCode::ModuleNotFound);
+}
+
+#[tokio::test]
+async fn reconciling_leaves_the_secondary_alone_when_the_primary_cant_be_listed() {
+    let regions = Regions::new();
+    let registry = regions.registry(Duration::from_secs(30));
+    registry.upload(None, "invoice-export", "", b"\0asm invoice".to_vec()).await.unwrap();
+    registry.reconcile().await.unwrap();
+    let replicated = files(&regions.path("secondary"));
+
+    // Metadata that doesn't parse fails the listing: an unreadable primary
+    // isn't an empty one
+    std::fs::write(regions.path("primary").join("lead-score.meta.json"), b"{").unwrap();
+    assert!(registry.reconcile().await.is_err());
+    assert_eq!(files(&regions.path("secondary")), replicated);
+}
+
+#[tokio::test]
+async fn a_tenant_module_shadows_the_system_one_for_its_tenant() {
+    let regions = Regions::new();
+    let registry = regions.registry(Duration::from_secs(30));
+    registry.upload(None, "invoice-export", "", b"\0asm system".to_vec()).await.unwrap();
+    registry.upload(Some("t1"), "invoice-export", "", b"\0asm t1".to_vec()).await.unwrap();
+
+    assert_eq!(*registry.fetch(Some("t1"), "invoice-export").await.unwrap(), b"\0asm t1");
+    assert_eq!(*registry.fetch(Some("t2"), "invoice-export").await.unwrap(), b"\0asm system");
+    assert_eq!(*registry.fetch(None, "invoice-export").await.unwrap(), b"\0asm system");
+
+    // Ids and tenants can't leave their directory
+    assert!(registry.upload(Some("../t2"), "invoice-export", "", Vec::new()).await.is_err());
+    assert!(registry.fetch(None, "../primary-down/invoice-export").await.is_err());
+}
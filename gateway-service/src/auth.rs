@@ -0,0 +1,68 @@
+use axum::http::HeaderMap;
+use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+
+/// Claims issued by the identity service; same shape the Node gateway reads.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Claims {
+    pub user_id: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    pub tenant_id: String,
+    #[serde(default)]
+    pub role: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    MissingToken,
+    InvalidToken(jsonwebtoken::errors::Error),
+    /// The request named a different tenant than the token was issued for.
+    TenantMismatch,
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::MissingToken => write!(f, "No token provided"),
+            AuthError::InvalidToken(_) => write!(f, "Invalid token"),
+            AuthError::TenantMismatch => write!(f, "Token does not grant access to this tenant"),
+        }
+    }
+}
+
+pub struct Authenticator {
+    key: DecodingKey,
+    validation: Validation,
+}
+
+impl Authenticator {
+    pub fn new(secret: &str) -> Self {
+        Authenticator {
+            key: DecodingKey::from_secret(secret.as_bytes()),
+            validation: Validation::new(Algorithm::HS256),
+        }
+    }
+
+    /// Verifies the bearer token and resolves the tenant. An explicit
+    /// `X-Tenant-ID` header is accepted only if it matches the token.
+    pub fn authenticate(&self, headers: &HeaderMap) -> Result<Claims, AuthError> {
+        let token = headers
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or(AuthError::MissingToken)?;
+
+        let claims = decode::<Claims>(token, &self.key, &self.validation)
+            .map_err(AuthError::InvalidToken)?
+            .claims;
+
+        if let Some(requested) = headers.get("x-tenant-id").and_then(|v| v.to_str().ok()) {
+            if requested != claims.tenant_id {
+                return Err(AuthError::TenantMismatch);
+            }
+        }
+
+        Ok(claims)
+    }
+}
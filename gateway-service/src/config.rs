@@ -0,0 +1,55 @@
+use std::env;
+
+/// A path prefix proxied to an upstream service, e.g. `/runtime` →
+/// `http://extension-runtime:8080`. The prefix is stripped before forwarding.
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub prefix: String,
+    pub upstream: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub port: u16,
+    /// HS256 secret shared with the identity service that issues tokens.
+    pub jwt_secret: String,
+    pub redis_url: String,
+    pub routes: Vec<Route>,
+    pub rate_limit_per_minute: u64,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Config {
+            port: env::var("GATEWAY_PORT")
+                .unwrap_or_else(|_| "8000".to_string())
+                .parse()
+                .unwrap_or(8000),
+            jwt_secret: env::var("JWT_SECRET")?,
+            redis_url: env::var("REDIS_URL")
+                .unwrap_or_else(|_| "redis://localhost:6379".to_string()),
+            // Format: "/runtime=http://host:port,/ingest=http://host:port";
+            // https:// upstreams are reached over mTLS
+            routes: parse_routes(
+                &env::var("GATEWAY_ROUTES").unwrap_or_else(|_| {
+                    "/runtime=http://extension-runtime:8080,/ingest=http://event-ingestion:8080".to_string()
+                }),
+            ),
+            rate_limit_per_minute: env::var("RATE_LIMIT_PER_MINUTE")
+                .unwrap_or_else(|_| "600".to_string())
+                .parse()
+                .unwrap_or(600),
+        })
+    }
+}
+
+fn parse_routes(spec: &str) -> Vec<Route> {
+    spec.split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(prefix, upstream)| Route {
+            prefix: prefix.trim().trim_end_matches('/').to_string(),
+            upstream: upstream.trim().trim_end_matches('/').to_string(),
+        })
+        .filter(|route| !route.prefix.is_empty() && !route.upstream.is_empty())
+        .collect()
+}
@@ -0,0 +1,101 @@
+use axum::extract::State;
+use axum::http::{Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use hyper::Body;
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tracing::{info, error, warn, debug};
+
+mod auth;
+mod config;
+mod proxy;
+mod rate_limiter;
+
+use auth::{AuthError, Authenticator};
+use config::Config;
+use proxy::{Proxy, ProxyError};
+use rate_limiter::RateLimiter;
+
+struct GatewayState {
+    authenticator: Authenticator,
+    rate_limiter: RateLimiter,
+    proxy: Proxy,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Logs, traces and panic capture
+    let _observability = crm_observability::init("gateway-service")?;
+
+    info!("Starting API Gateway");
+
+    // Load configuration
+    let config = Config::from_env()?;
+    for route in &config.routes {
+        info!("Routing {} -> {}", route.prefix, route.upstream);
+    }
+
+    // Client certificate for https:// upstreams
+    let tls = crm_tls::MtlsContext::from_env()?;
+    if tls.is_none() && config.routes.iter().any(|route| route.upstream.starts_with("https://")) {
+        return Err("https:// upstreams need TLS_CERT_FILE, TLS_KEY_FILE and TLS_CA_FILE".into());
+    }
+
+    let redis_client = redis::Client::open(config.redis_url.as_str())?;
+    let state = Arc::new(GatewayState {
+        authenticator: Authenticator::new(&config.jwt_secret),
+        rate_limiter: RateLimiter::new(redis_client, config.rate_limit_per_minute),
+        proxy: Proxy::new(config.routes.clone(), tls.as_ref()),
+    });
+
+    let app = Router::new()
+        .route("/health", get(|| async { Json(json!({ "status": "ok" })) }))
+        .fallback(handle_gateway)
+        .with_state(state);
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
+    info!("API Gateway listening on {}", addr);
+    axum::Server::bind(&addr).serve(app.into_make_service()).await?;
+
+    Ok(())
+}
+
+async fn handle_gateway(State(state): State<Arc<GatewayState>>, request: Request<Body>) -> Response {
+    let claims = match state.authenticator.authenticate(request.headers()) {
+        Ok(claims) => claims,
+        Err(e) => {
+            match &e {
+                AuthError::InvalidToken(cause) => debug!("Rejected request to {}: {}", request.uri().path(), cause),
+                _ => debug!("Rejected request to {}: {}", request.uri().path(), e),
+            }
+            let status = match e {
+                AuthError::TenantMismatch => StatusCode::FORBIDDEN,
+                AuthError::MissingToken | AuthError::InvalidToken(_) => StatusCode::UNAUTHORIZED,
+            };
+            return error_response(status, &e.to_string());
+        }
+    };
+
+    match state.rate_limiter.try_acquire(&claims.tenant_id).await {
+        Ok(true) => {}
+        Ok(false) => return error_response(StatusCode::TOO_MANY_REQUESTS, "Rate limit exceeded"),
+        // Fail open: a Redis outage should not take every tenant offline
+        Err(e) => warn!("Rate limiter unavailable for tenant {}: {}", claims.tenant_id, e),
+    }
+
+    match state.proxy.forward(request, &claims).await {
+        Ok(response) => response.into_response(),
+        Err(ProxyError::NoRoute) => error_response(StatusCode::NOT_FOUND, "Not found"),
+        Err(e) => {
+            error!("Proxy error for tenant {}: {}", claims.tenant_id, e);
+            error_response(StatusCode::BAD_GATEWAY, "Upstream unavailable")
+        }
+    }
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
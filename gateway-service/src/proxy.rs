@@ -0,0 +1,86 @@
+use crate::auth::Claims;
+use crate::config::Route;
+use axum::http::{HeaderName, HeaderValue, Request, Response, Uri};
+use crm_tls::{MtlsConnector, MtlsContext};
+use hyper::{Body, Client};
+use std::sync::Arc;
+
+/// Identity headers set by the gateway; inbound copies are dropped so
+/// clients cannot impersonate another user or tenant.
+const IDENTITY_HEADERS: [&str; 4] = ["x-user-id", "x-user-email", "x-tenant-id", "x-user-role"];
+
+#[derive(Debug)]
+pub enum ProxyError {
+    NoRoute,
+    InvalidUri(String),
+    Upstream(hyper::Error),
+}
+
+impl std::fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProxyError::NoRoute => write!(f, "No upstream for this path"),
+            ProxyError::InvalidUri(uri) => write!(f, "Invalid upstream URI: {}", uri),
+            ProxyError::Upstream(e) => write!(f, "Upstream request failed: {}", e),
+        }
+    }
+}
+
+pub struct Proxy {
+    client: Client<MtlsConnector>,
+    routes: Vec<Route>,
+}
+
+impl Proxy {
+    /// `https://` upstreams are reached over mTLS and need `tls`.
+    pub fn new(mut routes: Vec<Route>, tls: Option<&Arc<MtlsContext>>) -> Self {
+        // Longest prefix wins
+        routes.sort_by_key(|route| std::cmp::Reverse(route.prefix.len()));
+
+        Proxy {
+            client: Client::builder().build(MtlsConnector::new(tls)),
+            routes,
+        }
+    }
+
+    pub async fn forward(&self, mut request: Request<Body>, claims: &Claims) -> Result<Response<Body>, ProxyError> {
+        let path = request.uri().path();
+        let (route, rest) = self
+            .routes
+            .iter()
+            .find_map(|route| {
+                let rest = path.strip_prefix(route.prefix.as_str())?;
+                (rest.is_empty() || rest.starts_with('/')).then_some((route, rest))
+            })
+            .ok_or(ProxyError::NoRoute)?;
+
+        let target = match request.uri().query() {
+            Some(query) => format!("{}{}?{}", route.upstream, rest, query),
+            None => format!("{}{}", route.upstream, rest),
+        };
+        *request.uri_mut() = target
+            .parse::<Uri>()
+            .map_err(|_| ProxyError::InvalidUri(target.clone()))?;
+
+        let headers = request.headers_mut();
+        headers.remove("authorization");
+        headers.remove("host");
+        for name in IDENTITY_HEADERS {
+            headers.remove(name);
+        }
+
+        let identity = [
+            ("x-user-id", Some(claims.user_id.as_str())),
+            ("x-user-email", claims.email.as_deref()),
+            ("x-tenant-id", Some(claims.tenant_id.as_str())),
+            ("x-user-role", claims.role.as_deref()),
+        ];
+        for (name, value) in identity {
+            if let Some(value) = value.and_then(|v| HeaderValue::from_str(v).ok()) {
+                headers.insert(HeaderName::from_static(name), value);
+            }
+        }
+
+        self.client.request(request).await.map_err(ProxyError::Upstream)
+    }
+}
@@ -0,0 +1,61 @@
+use redis::aio::MultiplexedConnection;
+use redis::AsyncCommands;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+/// Fixed one-minute request windows per tenant, counted in Redis so the
+/// limit holds across gateway replicas. A per-tenant override can be set in
+/// `gateway_rate_limit:{tenant_id}`.
+///
+/// Requests share one multiplexed connection rather than queueing for it,
+/// and a connection that failed is dropped and opened again by the next
+/// request, so a Redis blip doesn't turn rate limiting off until a restart.
+pub struct RateLimiter {
+    redis_client: redis::Client,
+    connection: Mutex<Option<MultiplexedConnection>>,
+    default_per_minute: u64,
+}
+
+impl RateLimiter {
+    pub fn new(redis_client: redis::Client, default_per_minute: u64) -> Self {
+        RateLimiter {
+            redis_client,
+            connection: Mutex::new(None),
+            default_per_minute,
+        }
+    }
+
+    pub async fn try_acquire(&self, tenant_id: &str) -> Result<bool, redis::RedisError> {
+        let minute = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() / 60)
+            .unwrap_or_default();
+        let key = format!("gateway_rate:{}:{}", tenant_id, minute);
+
+        let mut conn = self.connection().await?;
+        let result = async {
+            let limit: Option<u64> = conn.get(format!("gateway_rate_limit:{}", tenant_id)).await?;
+            let count: u64 = conn.incr(&key, 1).await?;
+            if count == 1 {
+                let _: () = conn.expire(&key, 120).await?;
+            }
+            Ok(count <= limit.unwrap_or(self.default_per_minute))
+        }
+        .await;
+        if result.is_err() {
+            *self.connection.lock().await = None;
+        }
+        result
+    }
+
+    /// The shared connection, opened if there is none.
+    async fn connection(&self) -> Result<MultiplexedConnection, redis::RedisError> {
+        let mut connection = self.connection.lock().await;
+        if let Some(connection) = connection.as_ref() {
+            return Ok(connection.clone());
+        }
+        let opened = self.redis_client.get_multiplexed_tokio_connection().await?;
+        *connection = Some(opened.clone());
+        Ok(opened)
+    }
+}
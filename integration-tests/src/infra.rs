@@ -0,0 +1,127 @@
+use crate::Error;
+use crm_events::CrmEvent;
+use rdkafka::config::ClientConfig;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use std::time::Duration;
+use testcontainers::runners::AsyncRunner;
+use testcontainers::{ContainerAsync, ImageExt};
+use testcontainers_modules::clickhouse::{ClickHouse, CLICKHOUSE_PORT};
+use testcontainers_modules::kafka::{Kafka, KAFKA_PORT};
+use testcontainers_modules::redis::{Redis, REDIS_PORT};
+
+/// Kafka, ClickHouse and Redis containers; stopped when dropped. Kafka
+/// supports transactions.
+pub struct Infra {
+    _kafka: ContainerAsync<Kafka>,
+    _clickhouse: ContainerAsync<ClickHouse>,
+    _redis: ContainerAsync<Redis>,
+    pub kafka_brokers: String,
+    pub clickhouse_url: String,
+    pub redis_url: String,
+    http: reqwest::Client,
+}
+
+impl Infra {
+    pub async fn start() -> Result<Self, Error> {
+        // A single broker can only host the transaction log with one replica
+        let kafka = Kafka::default()
+            .with_env_var("KAFKA_TRANSACTION_STATE_LOG_REPLICATION_FACTOR", "1")
+            .with_env_var("KAFKA_TRANSACTION_STATE_LOG_MIN_ISR", "1");
+        let (kafka, clickhouse, redis) = tokio::try_join!(
+            kafka.start(),
+            ClickHouse::default().start(),
+            Redis::default().start(),
+        )?;
+
+        let kafka_brokers = format!("127.0.0.1:{}", kafka.get_host_port_ipv4(KAFKA_PORT).await?);
+        let clickhouse_url = format!(
+            "http://{}:{}",
+            clickhouse.get_host().await?,
+            clickhouse.get_host_port_ipv4(CLICKHOUSE_PORT).await?
+        );
+        let redis_url = format!(
+            "redis://{}:{}",
+            redis.get_host().await?,
+            redis.get_host_port_ipv4(REDIS_PORT).await?
+        );
+
+        Ok(Infra {
+            _kafka: kafka,
+            _clickhouse: clickhouse,
+            _redis: redis,
+            kafka_brokers,
+            clickhouse_url,
+            redis_url,
+            http: reqwest::Client::new(),
+        })
+    }
+
+    /// Applies a `;`-separated SQL file such as `event-ingestion-service/schema.sql`.
+    pub async fn apply_clickhouse_schema(&self, sql: &str) -> Result<(), Error> {
+        let without_comments: String = sql
+            .lines()
+            .filter(|line| !line.trim_start().starts_with("--"))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mut database = "default".to_string();
+        for statement in without_comments.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+            // Each HTTP request is its own session, so track USE ourselves
+            if let Some(name) = statement.strip_prefix("USE ") {
+                database = name.trim().to_string();
+                continue;
+            }
+            self.clickhouse_query_in(&database, statement).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn clickhouse_query(&self, database: &str, sql: &str) -> Result<String, Error> {
+        self.clickhouse_query_in(database, sql).await
+    }
+
+    async fn clickhouse_query_in(&self, database: &str, sql: &str) -> Result<String, Error> {
+        let response = self
+            .http
+            .post(&self.clickhouse_url)
+            .query(&[("database", database)])
+            .body(sql.to_string())
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(format!("ClickHouse {}: {}", status, body.trim()).into());
+        }
+        Ok(body)
+    }
+
+    /// Runs a `SELECT count() ...` and parses the single number it returns.
+    pub async fn clickhouse_count(&self, database: &str, sql: &str) -> Result<u64, Error> {
+        Ok(self.clickhouse_query(database, sql).await?.trim().parse()?)
+    }
+
+    pub fn producer(&self) -> Result<FutureProducer, Error> {
+        Ok(ClientConfig::new()
+            .set("bootstrap.servers", &self.kafka_brokers)
+            .set("message.timeout.ms", "5000")
+            .create()?)
+    }
+
+    pub async fn produce(&self, producer: &FutureProducer, topic: &str, event: &CrmEvent) -> Result<(), Error> {
+        let payload = serde_json::to_string(event)?;
+        producer
+            .send(
+                FutureRecord::to(topic).key(&event.tenant_id).payload(&payload),
+                Duration::from_secs(5),
+            )
+            .await
+            .map_err(|(e, _)| e)?;
+        Ok(())
+    }
+
+    pub async fn redis(&self) -> Result<redis::aio::Connection, Error> {
+        Ok(redis::Client::open(self.redis_url.as_str())?.get_async_connection().await?)
+    }
+}
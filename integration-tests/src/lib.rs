@@ -0,0 +1,110 @@
+//! End-to-end test harness for the CRM Rust services.
+//!
+//! Tests start real dependencies in Docker through testcontainers, run the
+//! service binaries from this workspace against them and assert on what the
+//! services leave behind (ClickHouse rows, Redis keys, HTTP responses).
+//!
+//! The tests need a Docker daemon, so they are `#[ignore]`d by default. CI
+//! runs them with:
+//!
+//! ```text
+//! cargo test -p crm-integration-tests -- --ignored --test-threads=1
+//! ```
+
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+mod infra;
+
+pub use infra::Infra;
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Builds a workspace binary and returns its path.
+pub fn service_binary(package: &str) -> Result<PathBuf, Error> {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let status = Command::new(cargo)
+        .args(["build", "--quiet", "-p", package])
+        .current_dir(workspace_root())
+        .status()?;
+    if !status.success() {
+        return Err(format!("cargo build -p {} failed", package).into());
+    }
+
+    let target_dir = std::env::var("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| workspace_root().join("target"));
+    Ok(target_dir.join("debug").join(package))
+}
+
+pub fn workspace_root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("integration-tests lives inside the workspace")
+        .to_path_buf()
+}
+
+pub fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+/// A free localhost port for a service under test.
+pub fn free_port() -> Result<u16, Error> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
+
+/// A running service binary, killed when dropped.
+pub struct ServiceProcess {
+    name: String,
+    child: Child,
+}
+
+impl ServiceProcess {
+    pub fn spawn(package: &str, envs: &[(&str, String)]) -> Result<Self, Error> {
+        let child = Command::new(service_binary(package)?)
+            .envs(envs.iter().map(|(key, value)| (*key, value.as_str())))
+            .env("RUST_LOG", std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string()))
+            .stdin(Stdio::null())
+            .spawn()?;
+
+        Ok(ServiceProcess {
+            name: package.to_string(),
+            child,
+        })
+    }
+
+    /// Fails if the process has already exited, e.g. on a config error.
+    pub fn assert_running(&mut self) -> Result<(), Error> {
+        match self.child.try_wait()? {
+            Some(status) => Err(format!("{} exited early: {}", self.name, status).into()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ServiceProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Polls `check` until it returns `Some`, or fails after `timeout`.
+pub async fn eventually<T, F, Fut>(what: impl std::fmt::Display, timeout: Duration, mut check: F) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<Option<T>, Error>>,
+{
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        if let Some(value) = check().await? {
+            return Ok(value);
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(format!("timed out waiting for {}", what).into());
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+}
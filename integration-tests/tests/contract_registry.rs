@@ -0,0 +1,52 @@
+use crm_contracts::{ContractRegistry, ContractStatus};
+use crm_integration_tests::Error;
+use serde_json::json;
+use testcontainers::runners::AsyncRunner;
+use testcontainers_modules::postgres::Postgres;
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn only_compatible_versions_are_accepted() -> Result<(), Error> {
+    let postgres = Postgres::default().start().await?;
+    let database_url = format!(
+        "postgres://postgres:postgres@{}:{}/postgres",
+        postgres.get_host().await?,
+        postgres.get_host_port_ipv4(5432).await?
+    );
+    let mut registry = ContractRegistry::connect(&database_url).await?;
+
+    let v1 = json!({
+        "type": "object",
+        "properties": { "deal_id": { "type": "string" }, "amount": { "type": "number" } },
+        "required": ["deal_id"]
+    });
+    let mut v2 = v1.clone();
+    v2["required"] = json!(["deal_id", "amount"]);
+    let mut v3 = v1.clone();
+    v3["properties"]["amount"] = json!({ "type": ["number", "string"] });
+
+    for schema in [&v1, &v2, &v3] {
+        registry.propose("it_deal_updated", schema).await?;
+    }
+    assert_eq!(registry.pending().await?, vec!["it_deal_updated"]);
+
+    // Each proposal is checked against the latest accepted version before it
+    let reviewed = registry.review("it_deal_updated").await?;
+    let statuses: Vec<_> = reviewed.iter().map(|contract| (contract.version, contract.status)).collect();
+    assert_eq!(
+        statuses,
+        vec![(1, ContractStatus::Accepted), (2, ContractStatus::Rejected), (3, ContractStatus::Accepted)]
+    );
+    assert_eq!(reviewed[1].problems, vec!["$: amount is now required"]);
+    assert!(registry.pending().await?.is_empty());
+
+    let accepted = registry.accepted().await?;
+    assert_eq!(accepted["it_deal_updated"].version, 3);
+    assert!(accepted["it_deal_updated"].validate(&json!({ "deal_id": "d1", "amount": "12" })).is_empty());
+    assert_eq!(registry.versions("it_deal_updated").await?.len(), 3);
+
+    // Reconnecting reapplies the schema without touching the contracts
+    let registry = ContractRegistry::connect(&database_url).await?;
+    assert_eq!(registry.accepted().await?["it_deal_updated"].version, 3);
+    Ok(())
+}
@@ -0,0 +1,23 @@
+;; A WASI Preview 2 component: adds two numbers, at the top level and in
+;; the `example:math/ops` interface, and draws a random number through
+;; `wasi:random`, so it only instantiates where WASI is linked.
+(component
+  (import "wasi:random/random@0.2.0-rc-2023-11-10" (instance $random
+    (export "get-random-u64" (func (result u64)))))
+  (core func $get_random_u64 (canon lower (func $random "get-random-u64")))
+  (core module $m
+    (import "wasi" "get-random-u64" (func $get_random_u64 (result i64)))
+    (func (export "add") (param i32 i32) (result i32)
+      (i32.add (local.get 0) (local.get 1)))
+    (func (export "random") (result i64)
+      (call $get_random_u64)))
+  (core instance $i (instantiate $m
+    (with "wasi" (instance (export "get-random-u64" (func $get_random_u64))))))
+  (func $add (param "a" s32) (param "b" s32) (result s32)
+    (canon lift (core func $i "add")))
+  (func $random (result u64)
+    (canon lift (core func $i "random")))
+  (instance $ops (export "add" (func $add)))
+  (export "add" (func $add))
+  (export "random" (func $random))
+  (export "example:math/ops" (instance $ops)))
@@ -0,0 +1,22 @@
+;; Reads the plugin's settings, and the secret its "token" setting
+;; references, with the crm config_get and secret_get host functions.
+(module
+  (import "crm" "config_get" (func $config_get (param i32 i32) (result i32)))
+  (import "crm" "secret_get" (func $secret_get (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "token")
+  (data (i32.const 8) "threshold")
+  ;; The result of config_get into a buffer of $cap bytes
+  (func (export "read") (param $cap i32) (result i32)
+    (call $config_get (i32.const 64) (local.get $cap)))
+  ;; The settings as a (ptr, len) string
+  (func (export "settings") (result i32 i32)
+    (i32.const 64)
+    (call $config_get (i32.const 64) (i32.const 4096)))
+  ;; The secret "token" references as a (ptr, len) string
+  (func (export "token") (result i32 i32)
+    (i32.const 1024)
+    (call $secret_get (i32.const 0) (i32.const 5) (i32.const 1024) (i32.const 1024)))
+  ;; The result of secret_get for "threshold", which isn't a reference
+  (func (export "threshold_secret") (result i32)
+    (call $secret_get (i32.const 8) (i32.const 9) (i32.const 1024) (i32.const 1024))))
@@ -0,0 +1,10 @@
+;; Writes "hello" and an unterminated "world" to stdout through WASI.
+(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 16) "hello\nworld")
+  (func (export "run")
+    ;; One iovec at 0: the 11 bytes at 16
+    (i32.store (i32.const 0) (i32.const 16))
+    (i32.store (i32.const 4) (i32.const 11))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
@@ -0,0 +1,17 @@
+;; Plugins with the json calling convention: `handle` returns the request
+;; document it was given, `broken` something that isn't JSON.
+(module
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  (data (i32.const 16) "{\"unterminated\":")
+  (func (export "alloc") (param $len i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $next))
+    (global.set $next (i32.add (global.get $next) (local.get $len)))
+    (local.get $ptr))
+  (func (export "handle") (param $ptr i32) (param $len i32) (result i32 i32)
+    (local.get $ptr)
+    (local.get $len))
+  (func (export "broken") (param $ptr i32) (param $len i32) (result i32 i32)
+    (i32.const 16)
+    (i32.const 16)))
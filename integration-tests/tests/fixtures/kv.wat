@@ -0,0 +1,23 @@
+;; Keeps a visit count under "visits" with the crm KV host functions.
+(module
+  (import "crm" "kv_get" (func $kv_get (param i32 i32 i32 i32) (result i32)))
+  (import "crm" "kv_set" (func $kv_set (param i32 i32 i32 i32 i64) (result i32)))
+  (import "crm" "kv_delete" (func $kv_delete (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "visits")
+  (data (i32.const 16) "42")
+  ;; Stores "42" for an hour
+  (func (export "remember") (result i32)
+    (call $kv_set (i32.const 0) (i32.const 6) (i32.const 16) (i32.const 2) (i64.const 3600)))
+  ;; The stored count as a number, or the negative result of kv_get
+  (func (export "recall") (result i32)
+    (local $n i32)
+    (local.set $n (call $kv_get (i32.const 0) (i32.const 6) (i32.const 32) (i32.const 16)))
+    (if (result i32) (i32.eq (local.get $n) (i32.const 2))
+      (then
+        (i32.add
+          (i32.mul (i32.sub (i32.load8_u (i32.const 32)) (i32.const 48)) (i32.const 10))
+          (i32.sub (i32.load8_u (i32.const 33)) (i32.const 48))))
+      (else (local.get $n))))
+  (func (export "forget") (result i32)
+    (call $kv_delete (i32.const 0) (i32.const 6))))
@@ -0,0 +1,12 @@
+;; Logs an info line with fields and a warning without through the crm log
+;; host functions.
+(module
+  (import "crm" "log_info" (func $log_info (param i32 i32 i32 i32)))
+  (import "crm" "log_warn" (func $log_warn (param i32 i32 i32 i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "syncing deal")
+  (data (i32.const 16) "{\"deal_id\":\"d1\"}")
+  (data (i32.const 48) "slow response")
+  (func (export "run")
+    (call $log_info (i32.const 0) (i32.const 12) (i32.const 16) (i32.const 16))
+    (call $log_warn (i32.const 48) (i32.const 13) (i32.const 0) (i32.const 0))))
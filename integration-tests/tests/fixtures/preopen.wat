@@ -0,0 +1,24 @@
+;; Prints greeting.txt from the first preopened directory, fd 3, to stdout
+;; through WASI. Returns path_open's errno.
+(module
+  (import "wasi_snapshot_preview1" "path_open"
+    (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 64) "greeting.txt")
+  (func (export "run") (result i32)
+    (local $errno i32)
+    ;; The opened fd goes to 0, with fd_read rights
+    (local.set $errno
+      (call $path_open (i32.const 3) (i32.const 0) (i32.const 64) (i32.const 12)
+        (i32.const 0) (i64.const 2) (i64.const 0) (i32.const 0) (i32.const 0)))
+    (if (local.get $errno) (then (return (local.get $errno))))
+    ;; One iovec at 16: up to 1024 bytes at 1024
+    (i32.store (i32.const 16) (i32.const 1024))
+    (i32.store (i32.const 20) (i32.const 1024))
+    (drop (call $fd_read (i32.load (i32.const 0)) (i32.const 16) (i32.const 1) (i32.const 24)))
+    ;; Writes back as many bytes as were read
+    (i32.store (i32.const 20) (i32.load (i32.const 24)))
+    (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 28)))
+    (i32.const 0)))
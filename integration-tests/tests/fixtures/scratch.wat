@@ -0,0 +1,32 @@
+;; Writes 4 KiB chunks to out.bin in the first preopened directory, fd 3,
+;; through WASI.
+(module
+  (import "wasi_snapshot_preview1" "path_open"
+    (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 64) "out.bin")
+  ;; Creates out.bin with $chunks chunks and returns path_open's errno; with
+  ;; $spin set it then loops until stopped
+  (func (export "write") (param $chunks i32) (param $spin i32) (result i32)
+    (local $errno i32)
+    ;; The opened fd goes to 0, with fd_write rights
+    (local.set $errno
+      (call $path_open (i32.const 3) (i32.const 0) (i32.const 64) (i32.const 7)
+        (i32.const 1) (i64.const 64) (i64.const 0) (i32.const 0) (i32.const 0)))
+    (if (local.get $errno) (then (return (local.get $errno))))
+    ;; One iovec at 16: 4096 bytes at 4096
+    (i32.store (i32.const 16) (i32.const 4096))
+    (i32.store (i32.const 20) (i32.const 4096))
+    (block $written
+      (loop $next
+        (br_if $written (i32.eqz (local.get $chunks)))
+        (drop (call $fd_write (i32.load (i32.const 0)) (i32.const 16) (i32.const 1) (i32.const 24)))
+        (local.set $chunks (i32.sub (local.get $chunks) (i32.const 1)))
+        (br $next)))
+    (if (local.get $spin) (then (loop $forever (br $forever))))
+    (i32.const 0))
+  ;; path_open's errno for an existing out.bin
+  (func (export "open") (result i32)
+    (call $path_open (i32.const 3) (i32.const 0) (i32.const 64) (i32.const 7)
+      (i32.const 0) (i64.const 64) (i64.const 0) (i32.const 0) (i32.const 0))))
@@ -0,0 +1,27 @@
+;; Takes and returns strings and bytes as (ptr, len) pairs, with a bump
+;; allocator for the runtime to copy arguments in.
+(module
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  (func (export "alloc") (param $len i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $next))
+    (global.set $next (i32.add (global.get $next) (local.get $len)))
+    (local.get $ptr))
+  ;; Returns its argument
+  (func (export "echo") (param $ptr i32) (param $len i32) (result i32 i32)
+    (local.get $ptr)
+    (local.get $len))
+  ;; How many times $byte occurs in the argument
+  (func (export "count") (param $ptr i32) (param $len i32) (param $byte i32) (result i32)
+    (local $end i32)
+    (local $count i32)
+    (local.set $end (i32.add (local.get $ptr) (local.get $len)))
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $ptr) (local.get $end)))
+        (if (i32.eq (i32.load8_u (local.get $ptr)) (local.get $byte))
+          (then (local.set $count (i32.add (local.get $count) (i32.const 1)))))
+        (local.set $ptr (i32.add (local.get $ptr) (i32.const 1)))
+        (br $next)))
+    (local.get $count)))
@@ -0,0 +1,112 @@
+use crm_events::CrmEvent;
+use crm_integration_tests::{eventually, free_port, workspace_root, Error, Infra, ServiceProcess};
+use redis::AsyncCommands;
+use serde_json::json;
+use std::time::Duration;
+
+const TOPIC: &str = "crm-events";
+const DATABASE: &str = "crm_analytics";
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn events_reach_clickhouse_and_redis() -> Result<(), Error> {
+    let infra = Infra::start().await?;
+    let schema = std::fs::read_to_string(workspace_root().join("event-ingestion-service/schema.sql"))?;
+    infra.apply_clickhouse_schema(&schema).await?;
+
+    let mut service = ServiceProcess::spawn(
+        "event-ingestion-service",
+        &[
+            ("KAFKA_BROKERS", infra.kafka_brokers.clone()),
+            ("KAFKA_TOPICS", TOPIC.to_string()),
+            ("KAFKA_GROUP_ID", format!("it-{}", uuid::Uuid::new_v4())),
+            ("CLICKHOUSE_URL", infra.clickhouse_url.clone()),
+            ("CLICKHOUSE_DATABASE", DATABASE.to_string()),
+            ("REDIS_URL", infra.redis_url.clone()),
+            ("FLUSH_INTERVAL_MS", "500".to_string()),
+            ("METRICS_PORT", free_port()?.to_string()),
+        ],
+    )?;
+
+    let producer = infra.producer()?;
+
+    // The consumer starts at the latest offset, so wait until it has joined
+    // before producing the events the assertions count
+    let warmup = CrmEvent::builder("it_warmup", "warmup").build();
+    eventually("the consumer to join", Duration::from_secs(90), || {
+        let (infra, producer, warmup) = (&infra, &producer, &warmup);
+        async move {
+            infra.produce(producer, TOPIC, warmup).await?;
+            let seen: Option<u64> = infra.redis().await?.get("metrics:it_warmup:warmup").await?;
+            Ok(seen.map(|_| ()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let tenant = "it_tenant";
+    for amount in [1000.0, 2500.0, 4000.0] {
+        let event = CrmEvent::builder(tenant, "deal_updated")
+            .user_id("user_1")
+            .payload(json!({ "stage": "negotiation", "amount": amount }))
+            .build();
+        infra.produce(&producer, TOPIC, &event).await?;
+    }
+    for page in ["/pricing", "/docs"] {
+        let event = CrmEvent::builder(tenant, "page_view")
+            .user_id("user_2")
+            .payload(json!({ "page_url": page, "session_duration": 12.5 }))
+            .build();
+        infra.produce(&producer, TOPIC, &event).await?;
+    }
+    let opened = CrmEvent::builder(tenant, "email_opened")
+        .payload(json!({ "campaign_id": "spring_launch", "message_id": "msg_1" }))
+        .build();
+    infra.produce(&producer, TOPIC, &opened).await?;
+
+    // Generic table, routed table and materialized columns. Batches may land
+    // in several flushes, so wait for the full count rather than the first row
+    let rows = |table: &str, event_type: &str, expected: u64| {
+        let (infra, sql) = (
+            &infra,
+            format!(
+                "SELECT count() FROM {} WHERE tenant_id = '{}' AND event_type = '{}'",
+                table, tenant, event_type
+            ),
+        );
+        eventually(format!("{} {} rows", expected, event_type), Duration::from_secs(30), move || {
+            let sql = sql.clone();
+            async move {
+                let count = infra.clickhouse_count(DATABASE, &sql).await?;
+                Ok((count >= expected).then_some(count))
+            }
+        })
+    };
+    assert_eq!(rows("events", "deal_updated", 3).await?, 3);
+    assert_eq!(rows("page_views", "page_view", 2).await?, 2);
+    assert_eq!(rows("events", "email_opened", 1).await?, 1);
+
+    let pages = infra
+        .clickhouse_query(
+            DATABASE,
+            &format!("SELECT page_url FROM page_views WHERE tenant_id = '{}' ORDER BY page_url", tenant),
+        )
+        .await?;
+    assert_eq!(pages.lines().collect::<Vec<_>>(), vec!["/docs", "/pricing"]);
+
+    // Real-time counters
+    let mut redis = infra.redis().await?;
+    let deal_counter: u64 = redis.get(format!("metrics:{}:deal_updated", tenant)).await?;
+    let page_counter: u64 = redis.get(format!("metrics:{}:page_view", tenant)).await?;
+    let last_seen: Option<i64> = redis.get(format!("activity:{}:user_1", tenant)).await?;
+    let opens: Option<u64> = redis
+        .hget(format!("campaign:{}:spring_launch", tenant), "email_opened")
+        .await?;
+    assert_eq!(deal_counter, 3);
+    assert_eq!(page_counter, 2);
+    assert!(last_seen.is_some());
+    assert_eq!(opens, Some(1));
+
+    service.assert_running()?;
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use crm_events::CrmEvent;
+use crm_integration_tests::{eventually, free_port, Error, Infra, ServiceProcess};
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::message::{Headers, Message};
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::Duration;
+use testcontainers::runners::AsyncRunner;
+use testcontainers_modules::postgres::Postgres;
+use tokio_postgres::NoTls;
+
+const TOPIC: &str = "crm-outbox-events";
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn outbox_rows_are_published_once() -> Result<(), Error> {
+    let infra = Infra::start().await?;
+    let postgres = Postgres::default().start().await?;
+    let database_url = format!(
+        "postgres://postgres:postgres@{}:{}/postgres",
+        postgres.get_host().await?,
+        postgres.get_host_port_ipv4(5432).await?
+    );
+    let relay_env = |port: u16| {
+        vec![
+            ("OUTBOX_DATABASE_URL", database_url.clone()),
+            ("OUTBOX_KAFKA_BROKERS", infra.kafka_brokers.clone()),
+            ("OUTBOX_PORT", port.to_string()),
+        ]
+    };
+
+    let relay = ServiceProcess::spawn("outbox-relay", &relay_env(free_port()?))?;
+
+    let (mut client, connection) = tokio_postgres::connect(&database_url, NoTls).await?;
+    tokio::spawn(connection);
+    eventually("the relay to create the outbox", Duration::from_secs(60), || {
+        let client = &client;
+        async move {
+            let row = client.query_one("SELECT to_regclass('outbox') IS NOT NULL", &[]).await?;
+            Ok(row.get::<_, bool>(0).then_some(()))
+        }
+    })
+    .await?;
+
+    let event = |n: u32| CrmEvent::builder("it_outbox", "deal_updated").payload(json!({ "n": n })).build();
+    let transaction = client.transaction().await?;
+    for n in 0..3 {
+        crm_outbox::enqueue(&transaction, TOPIC, &event(n)).await?;
+    }
+    transaction.commit().await?;
+    // Rolled back with its change, so never published
+    let transaction = client.transaction().await?;
+    crm_outbox::enqueue(&transaction, TOPIC, &event(99)).await?;
+    transaction.rollback().await?;
+
+    let consumer: StreamConsumer = ClientConfig::new()
+        .set("bootstrap.servers", &infra.kafka_brokers)
+        .set("group.id", format!("it-{}", uuid::Uuid::new_v4()))
+        .set("auto.offset.reset", "earliest")
+        .set("isolation.level", "read_committed")
+        .create()?;
+    consumer.subscribe(&[TOPIC])?;
+    let mut seen: HashMap<String, u32> = HashMap::new();
+    while seen.len() < 3 {
+        let message = tokio::time::timeout(Duration::from_secs(60), consumer.recv()).await??;
+        *seen.entry(outbox_id(&message)?).or_default() += 1;
+    }
+
+    // A restarted relay carries on without republishing
+    drop(relay);
+    let mut relay = ServiceProcess::spawn("outbox-relay", &relay_env(free_port()?))?;
+    crm_outbox::enqueue(&client, TOPIC, &event(3)).await?;
+    while seen.len() < 4 {
+        let message = tokio::time::timeout(Duration::from_secs(60), consumer.recv()).await??;
+        *seen.entry(outbox_id(&message)?).or_default() += 1;
+    }
+    // Anything republished would arrive by now
+    while let Ok(message) = tokio::time::timeout(Duration::from_secs(5), consumer.recv()).await {
+        *seen.entry(outbox_id(&message?)?).or_default() += 1;
+    }
+    assert_eq!(seen.len(), 4);
+    assert!(seen.values().all(|count| *count == 1), "duplicates: {:?}", seen);
+
+    let unpublished: i64 = client
+        .query_one("SELECT count(*) FROM outbox WHERE published_at IS NULL", &[])
+        .await?
+        .get(0);
+    assert_eq!(unpublished, 0);
+    relay.assert_running()?;
+    Ok(())
+}
+
+fn outbox_id(message: &impl Message) -> Result<String, Error> {
+    let value = message
+        .headers()
+        .and_then(|headers| headers.iter().find(|header| header.key == crm_outbox::OUTBOX_ID_HEADER))
+        .and_then(|header| header.value)
+        .ok_or("message without an outbox id")?;
+    Ok(String::from_utf8_lossy(value).to_string())
+}
@@ -0,0 +1,3514 @@
+use base64::Engine;
+use crm_integration_tests::{eventually, fixture, free_port, Error, Infra, ServiceProcess};
+use crm_proto::v1::extension_runtime_client::ExtensionRuntimeClient;
+use crm_proto::v1::{self, log_line};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+// The runtime always listens here
+const RUNTIME_URL: &str = "http://127.0.0.1:8080";
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn uploaded_module_executes() -> Result<(), Error> {
+    // Uploading a module means placing it in the runtime's module directory
+    let module_dir = std::env::temp_dir().join(format!("crm-it-modules-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::copy(fixture("add.wasm"), module_dir.join("add.wasm"))?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_MODULE_CACHE_DIR", module_dir.join("cache").display().to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    // Compiled ahead of time, then served from the compiled module cache
+    for cache in ["compiled", "memory"] {
+        let precompiled: Value = http
+            .post(format!("{}/precompile", RUNTIME_URL))
+            .json(&json!({ "module_paths": ["add.wasm"] }))
+            .send()
+            .await?
+            .json()
+            .await?;
+        assert_eq!(precompiled["modules"][0]["cache"], cache, "{}", precompiled);
+    }
+
+    let execute = |function: &str, params: Value| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .json(&json!({ "module_path": "add.wasm", "function_name": function, "params": params }))
+            .send()
+    };
+
+    let response: Value = execute("example", json!([2, 3])).await?.json().await?;
+    assert_eq!(response["success"], true, "{}", response);
+    assert_eq!(response["result"], json!(5));
+    assert!(response["fuel_consumed"].as_u64().unwrap_or_default() > 0);
+
+    let response: Value = execute("missing", json!([])).await?.json().await?;
+    assert_eq!(response["success"], false);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn modules_are_managed_through_the_registry_api() -> Result<(), Error> {
+    let registry_dir = std::env::temp_dir().join(format!("crm-it-registry-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&registry_dir)?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[("RUNTIME_REGISTRY_URL", format!("file://{}", registry_dir.display()))],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let upload = |version: &str| {
+        let form = reqwest::multipart::Form::new()
+            .text("name", "add")
+            .text("version", version.to_string())
+            .part(
+                "module",
+                reqwest::multipart::Part::bytes(std::fs::read(fixture("add.wasm")).unwrap()).file_name("add.wasm"),
+            );
+        http.post(format!("{}/modules", RUNTIME_URL)).multipart(form).send()
+    };
+    for version in ["1.0.0", "1.1.0"] {
+        let response = upload(version).await?;
+        assert_eq!(response.status(), 201);
+    }
+
+    // Both versions share one content-addressed blob
+    let listed: Value = http.get(format!("{}/modules", RUNTIME_URL)).send().await?.json().await?;
+    let ids: Vec<_> = listed["modules"].as_array().unwrap().iter().map(|m| m["id"].clone()).collect();
+    assert_eq!(ids, vec![json!("add@1.0.0"), json!("add@1.1.0")]);
+    assert_eq!(std::fs::read_dir(registry_dir.join("blobs"))?.count(), 1);
+
+    let execute = || {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .json(&json!({ "module_path": "add@1.0.0", "function_name": "example", "params": [2, 3] }))
+            .send()
+    };
+    let response: Value = execute().await?.json().await?;
+    assert_eq!(response["result"], json!(5), "{}", response);
+
+    let inspected: Value = http.get(format!("{}/modules/add@1.1.0/inspect", RUNTIME_URL)).send().await?.json().await?;
+    let example = inspected["exports"].as_array().unwrap().iter().find(|export| export["name"] == "example");
+    assert_eq!(
+        example,
+        Some(&json!({ "name": "example", "kind": "function", "params": ["i32", "i32"], "results": ["i32"] })),
+        "{}",
+        inspected
+    );
+    assert!(inspected["size_bytes"].as_u64().unwrap() > 0, "{}", inspected);
+
+    let deleted = http.delete(format!("{}/modules/add@1.0.0", RUNTIME_URL)).send().await?;
+    assert_eq!(deleted.status(), 200);
+    let missing = http.delete(format!("{}/modules/add@1.0.0", RUNTIME_URL)).send().await?;
+    assert_eq!(missing.status(), 404);
+    let uninspectable = http.get(format!("{}/modules/add@1.0.0/inspect", RUNTIME_URL)).send().await?;
+    assert_eq!(uninspectable.status(), 404);
+    // Still used by 1.1.0
+    assert_eq!(std::fs::read_dir(registry_dir.join("blobs"))?.count(), 1);
+
+    std::fs::remove_dir_all(&registry_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn version_references_run_the_highest_matching_version() -> Result<(), Error> {
+    let registry_dir = std::env::temp_dir().join(format!("crm-it-versions-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&registry_dir)?;
+    let config_file = registry_dir.join("runtime.toml");
+    std::fs::write(&config_file, "[version_pins.acme]\nanswer = \"=1.2.0\"\n")?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("RUNTIME_REGISTRY_URL", format!("file://{}", registry_dir.display())),
+            ("RUNTIME_CONFIG_FILE", config_file.display().to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    // Each version answers with its minor version
+    for (version, answer) in [("1.2.0", 2), ("1.3.1", 3), ("2.0.0", 20), ("1.4.0-beta.1", 4)] {
+        let plugin = format!(r#"(module (func (export "run") (result i32) (i32.const {})))"#, answer);
+        let form = reqwest::multipart::Form::new()
+            .text("name", "answer")
+            .text("version", version)
+            .part("module", reqwest::multipart::Part::bytes(wat::parse_str(plugin)?).file_name("answer.wasm"));
+        let response = http.post(format!("{}/modules", RUNTIME_URL)).multipart(form).send().await?;
+        assert_eq!(response.status(), 201);
+    }
+
+    let execute = |module_path: &str, tenant_id: &str| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .header("x-tenant-id", tenant_id)
+            .json(&json!({ "module_path": module_path, "function_name": "run", "params": [] }))
+            .send()
+    };
+    let response: Value = execute("answer@^1.2", "globex").await?.json().await?;
+    assert_eq!(response["result"], json!(3), "{}", response);
+    assert_eq!(response["resolved_version"], "1.3.1", "{}", response);
+
+    // Exact ids don't resolve anything
+    let response: Value = execute("answer@2.0.0", "globex").await?.json().await?;
+    assert_eq!(response["result"], json!(20), "{}", response);
+    assert!(response.get("resolved_version").is_none(), "{}", response);
+
+    // The tenant's pin wins over the requirement
+    let response: Value = execute("answer@^1.2", "acme").await?.json().await?;
+    assert_eq!(response["resolved_version"], "1.2.0", "{}", response);
+
+    let response: Value = execute("answer@^3", "globex").await?.json().await?;
+    assert_eq!(response["success"], false, "{}", response);
+    assert_eq!(response["error"]["code"], "MODULE_NOT_FOUND", "{}", response);
+    assert!(response["error"]["message"].as_str().unwrap().contains("No version of answer matches"), "{}", response);
+
+    std::fs::remove_dir_all(&registry_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn lifecycle_hooks_run_as_modules_come_and_go() -> Result<(), Error> {
+    let registry_dir = std::env::temp_dir().join(format!("crm-it-lifecycle-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&registry_dir)?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[("RUNTIME_REGISTRY_URL", format!("file://{}", registry_dir.display()))],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let upload = |name: &str, wat: &str| {
+        let form = reqwest::multipart::Form::new()
+            .text("name", name.to_string())
+            .part("module", reqwest::multipart::Part::bytes(wat::parse_str(wat).unwrap()).file_name("hooked.wasm"));
+        http.post(format!("{}/modules", RUNTIME_URL)).multipart(form).send()
+    };
+    let hooks = |body: &Value| -> Vec<(String, bool)> {
+        let hooks = body["hooks"].as_array().cloned().unwrap_or_default();
+        hooks.iter().map(|hook| (hook["hook"].as_str().unwrap_or_default().to_string(), hook["success"] == true)).collect()
+    };
+
+    // A module that doesn't install isn't stored
+    let refused = upload("broken", r#"(module (func (export "on_install") unreachable))"#).await?;
+    assert_eq!(refused.status(), 422);
+    let refused: Value = refused.json().await?;
+    assert_eq!(hooks(&refused), vec![("on_install".to_string(), false)], "{}", refused);
+    let listed: Value = http.get(format!("{}/modules", RUNTIME_URL)).send().await?.json().await?;
+    assert_eq!(listed["modules"], json!([]), "{}", listed);
+
+    let hooked = r#"(module
+      (func (export "on_install"))
+      (func (export "on_activate"))
+      (func (export "on_deactivate"))
+      (func (export "on_uninstall") unreachable)
+      (func (export "answer") (result i32) (i32.const 42)))"#;
+    let installed = upload("hooked", hooked).await?;
+    assert_eq!(installed.status(), 201);
+    let installed: Value = installed.json().await?;
+    assert_eq!(
+        hooks(&installed),
+        vec![("on_install".to_string(), true), ("on_activate".to_string(), true)],
+        "{}",
+        installed
+    );
+    let response: Value = http
+        .post(format!("{}/execute", RUNTIME_URL))
+        .json(&json!({ "module_path": "hooked", "function_name": "answer", "params": [] }))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(response["result"], json!(42), "{}", response);
+
+    // Removed even though on_uninstall traps
+    let deleted = http.delete(format!("{}/modules/hooked", RUNTIME_URL)).send().await?;
+    assert_eq!(deleted.status(), 200);
+    let deleted: Value = deleted.json().await?;
+    assert_eq!(
+        hooks(&deleted),
+        vec![("on_deactivate".to_string(), true), ("on_uninstall".to_string(), false)],
+        "{}",
+        deleted
+    );
+    let missing = http.delete(format!("{}/modules/hooked", RUNTIME_URL)).send().await?;
+    assert_eq!(missing.status(), 404);
+
+    std::fs::remove_dir_all(&registry_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn modules_are_pulled_from_oci_registries() -> Result<(), Error> {
+    let registry = FakeOciRegistry::start(std::fs::read(fixture("add.wasm"))?).await?;
+    let cache_dir = std::env::temp_dir().join(format!("crm-it-oci-cache-{}", uuid::Uuid::new_v4()));
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("RUNTIME_OCI_CREDENTIALS", format!("{}=ci:hunter2", registry.host)),
+            ("RUNTIME_OCI_INSECURE_REGISTRIES", registry.host.clone()),
+            ("RUNTIME_MODULE_CACHE_DIR", cache_dir.display().to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |module_path: String| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .json(&json!({ "module_path": module_path, "function_name": "example", "params": [2, 3] }))
+            .send()
+    };
+
+    let tagged = format!("oci://{}/plugins/add:1.0.0", registry.host);
+    for _ in 0..2 {
+        let response: Value = execute(tagged.clone()).await?.json().await?;
+        assert_eq!(response["result"], json!(5), "{}", response);
+    }
+    // The layer is downloaded once and served from the cache after
+    assert_eq!(registry.blob_pulls.load(Ordering::SeqCst), 1);
+
+    let pinned = format!("oci://{}/plugins/add@{}", registry.host, registry.manifest_digest);
+    let response: Value = execute(pinned).await?.json().await?;
+    assert_eq!(response["result"], json!(5), "{}", response);
+
+    let wrong = format!("oci://{}/plugins/add@sha256:{}", registry.host, "0".repeat(64));
+    let response: Value = execute(wrong).await?.json().await?;
+    assert_eq!(response["success"], false, "{}", response);
+
+    std::fs::remove_dir_all(&cache_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn execute_requires_a_token_from_the_jwks() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-auth-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::copy(fixture("add.wasm"), module_dir.join("add.wasm"))?;
+
+    let jwks = std::fs::read(fixture("jwks.json"))?;
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let jwks_url = format!("http://{}/.well-known/jwks.json", listener.local_addr()?);
+    serve_http(listener, move |_, _| ("200 OK", String::new(), jwks.clone()));
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_JWKS_URL", jwks_url),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let key = jsonwebtoken::EncodingKey::from_rsa_pem(&std::fs::read(fixture("jwt_rsa.pem"))?)?;
+    let token = |scope: &str| {
+        let mut header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256);
+        header.kid = Some("it-key".to_string());
+        let claims = json!({ "sub": "svc-scoring", "tenant_id": "acme", "scope": scope, "exp": 4_102_444_800u64 });
+        jsonwebtoken::encode(&header, &claims, &key).unwrap()
+    };
+    let execute = |token: Option<String>, body: Value| {
+        let mut request = http.post(format!("{}/execute", RUNTIME_URL)).json(&body);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        request.send()
+    };
+    let add = json!({ "module_path": "add.wasm", "function_name": "example", "params": [2, 3] });
+
+    assert_eq!(execute(None, add.clone()).await?.status(), 401);
+    assert_eq!(execute(Some("not-a-jwt".to_string()), add.clone()).await?.status(), 401);
+    assert_eq!(execute(Some(token("contacts:read")), add.clone()).await?.status(), 403);
+
+    let response = execute(Some(token("contacts:read plugins:execute")), add.clone()).await?;
+    assert_eq!(response.status(), 200);
+    let response: Value = response.json().await?;
+    assert_eq!(response["result"], json!(5), "{}", response);
+
+    // The token decides the tenant
+    let mut other_tenant = add;
+    other_tenant["tenant_id"] = json!("globex");
+    assert_eq!(execute(Some(token("plugins:execute")), other_tenant).await?.status(), 403);
+
+    // The admin routes take the admin scope
+    let breakers = |token: Option<String>| {
+        let mut request = http.get(format!("{}/admin/breakers", RUNTIME_URL));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        request.send()
+    };
+    assert_eq!(breakers(None).await?.status(), 401);
+    assert_eq!(breakers(Some(token("plugins:execute"))).await?.status(), 403);
+    assert_eq!(breakers(Some(token("plugins:admin"))).await?.status(), 200);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service"]
+async fn runtime_serves_https_on_its_bind_address() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-tls-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::copy(fixture("add.wasm"), module_dir.join("add.wasm"))?;
+
+    let address = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+    let tls_fixture = |name: &str| format!("{}/../crm-tls/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_BIND_ADDRESS", address.to_string()),
+            ("RUNTIME_TLS_CERT_FILE", tls_fixture("runtime.pem")),
+            ("RUNTIME_TLS_KEY_FILE", tls_fixture("runtime-key.pem")),
+        ],
+    )?;
+
+    // The fixture certificate names a SPIFFE ID rather than a host
+    let https = reqwest::Client::builder().danger_accept_invalid_certs(true).build()?;
+    eventually("the runtime to serve /metrics over TLS", Duration::from_secs(60), || {
+        let https = &https;
+        async move {
+            let ready = https.get(format!("https://{}/metrics", address)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let response: Value = https
+        .post(format!("https://{}/execute", address))
+        .json(&json!({ "module_path": "add.wasm", "function_name": "example", "params": [2, 3] }))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(response["result"], json!(5), "{}", response);
+
+    assert!(reqwest::get(format!("http://{}/metrics", address)).await.is_err());
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn grpc_api_precompiles_inspects_and_streams_output() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-grpc-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::copy(fixture("add.wasm"), module_dir.join("add.wasm"))?;
+    std::fs::write(module_dir.join("hello.wasm"), wat::parse_file(fixture("hello.wat"))?)?;
+
+    let grpc_port = free_port()?;
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_MODULE_CACHE_DIR", module_dir.join("cache").display().to_string()),
+            ("RUNTIME_GRPC_PORT", grpc_port.to_string()),
+            ("RUNTIME_MAX_OUTPUT_BYTES", "8".to_string()),
+        ],
+    )?;
+
+    let grpc_url = format!("http://127.0.0.1:{}", grpc_port);
+    let mut client = eventually("the runtime to serve gRPC", Duration::from_secs(60), || {
+        let grpc_url = grpc_url.clone();
+        async move { Ok(ExtensionRuntimeClient::connect(grpc_url).await.ok()) }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let precompiled = client
+        .precompile(v1::PrecompileRequest { module_paths: vec!["add.wasm".to_string(), "missing.wasm".to_string()] })
+        .await?
+        .into_inner();
+    assert_eq!(precompiled.modules[0].cache.as_deref(), Some("compiled"), "{:?}", precompiled);
+    assert!(precompiled.modules[1].error.is_some());
+
+    let inspected = client
+        .inspect_module(v1::InspectModuleRequest { module_path: "hello.wasm".to_string() })
+        .await?
+        .into_inner();
+    let run = inspected.exports.iter().find(|export| export.name == "run").expect("run is exported");
+    assert_eq!(run.kind.as_ref().and_then(|kind| kind.function.clone()), Some(v1::FunctionType::default()));
+    assert_eq!(inspected.imports[0].name, "fd_write");
+
+    let mut events = client
+        .execute_stream(v1::ExecuteRequest {
+            module_path: "hello.wasm".to_string(),
+            function_name: "run".to_string(),
+            ..Default::default()
+        })
+        .await?
+        .into_inner();
+    let mut lines = Vec::new();
+    let response = loop {
+        let event = events.message().await?.expect("the stream ends with a response");
+        match (event.log, event.response) {
+            (Some(log), _) => lines.push((log.stream(), log.line)),
+            (None, Some(response)) => break response,
+            (None, None) => panic!("empty event"),
+        }
+    };
+    assert!(response.success, "{:?}", response);
+    // Streamed in full, but cut to max_output_bytes in the response
+    assert_eq!(
+        lines,
+        [(log_line::Stream::Stdout, "hello".to_string()), (log_line::Stream::Stdout, "world".to_string())]
+    );
+    assert!(events.message().await?.is_none());
+    assert_eq!((response.stdout.as_str(), response.output_truncated), ("hello\nwo", true));
+
+    let response: Value = reqwest::Client::new()
+        .post(format!("{}/execute", RUNTIME_URL))
+        .json(&json!({ "module_path": "hello.wasm", "function_name": "run", "params": [] }))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(response["stdout"], "hello\nwo", "{}", response);
+    assert_eq!(response["stderr"], "");
+    assert_eq!(response["output_truncated"], true);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+/// Serves one wasm artifact as `plugins/add:1.0.0`, behind the token
+/// handshake registries use: anonymous requests are challenged, and
+/// `/token` only hands out a token for the `ci:hunter2` credentials.
+struct FakeOciRegistry {
+    host: String,
+    manifest_digest: String,
+    blob_pulls: Arc<AtomicUsize>,
+}
+
+impl FakeOciRegistry {
+    async fn start(wasm: Vec<u8>) -> Result<Self, Error> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let host = listener.local_addr()?.to_string();
+        let layer_digest = format!("sha256:{}", hex::encode(Sha256::digest(&wasm)));
+        let manifest = json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": { "mediaType": "application/vnd.oci.empty.v1+json", "digest": "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a", "size": 2 },
+            "layers": [{ "mediaType": "application/vnd.wasm.content.layer.v1+wasm", "digest": layer_digest, "size": wasm.len() }],
+        })
+        .to_string()
+        .into_bytes();
+        let manifest_digest = format!("sha256:{}", hex::encode(Sha256::digest(&manifest)));
+        let blob_pulls = Arc::new(AtomicUsize::new(0));
+
+        let routes = [
+            ("/v2/plugins/add/manifests/1.0.0".to_string(), manifest.clone()),
+            (format!("/v2/plugins/add/manifests/{}", manifest_digest), manifest),
+            (format!("/v2/plugins/add/blobs/{}", layer_digest), wasm),
+        ];
+        let (pulls, challenge) = (blob_pulls.clone(), format!("Bearer realm=\"http://{}/token\",service=\"fake\"", host));
+        serve_http(listener, move |path, head| {
+            let authorization = header(head, "authorization");
+            if path.starts_with("/token") {
+                // Basic ci:hunter2
+                if authorization == "Basic Y2k6aHVudGVyMg==" && path.contains("scope=repository%3Aplugins%2Fadd%3Apull") {
+                    ("200 OK", String::new(), br#"{"token":"t0k3n"}"#.to_vec())
+                } else {
+                    ("401 Unauthorized", String::new(), Vec::new())
+                }
+            } else if authorization != "Bearer t0k3n" {
+                ("401 Unauthorized", format!("WWW-Authenticate: {}\r\n", challenge), Vec::new())
+            } else if let Some((_, body)) = routes.iter().find(|(route, _)| route == path) {
+                if path.contains("/blobs/") {
+                    pulls.fetch_add(1, Ordering::SeqCst);
+                }
+                ("200 OK", String::new(), body.clone())
+            } else {
+                ("404 Not Found", String::new(), Vec::new())
+            }
+        });
+
+        Ok(FakeOciRegistry {
+            host,
+            manifest_digest,
+            blob_pulls,
+        })
+    }
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn tenant_quotas_override_runtime_limits() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-quotas-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::copy(fixture("add.wasm"), module_dir.join("add.wasm"))?;
+    // Too little fuel for even one call
+    let config_file = module_dir.join("runtime.toml");
+    std::fs::write(&config_file, "[tenant_quotas.starved]\nfuel_limit = 5\n")?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_CONFIG_FILE", config_file.display().to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |tenant_id: &str, body: Value| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .header("x-tenant-id", tenant_id)
+            .json(&body)
+            .send()
+    };
+    let add = json!({ "module_path": "add.wasm", "function_name": "example", "params": [2, 3] });
+
+    let response: Value = execute("acme", add.clone()).await?.json().await?;
+    assert_eq!(response["result"], json!(5), "{}", response);
+    let response: Value = execute("starved", add.clone()).await?.json().await?;
+    assert_eq!(response["success"], false, "{}", response);
+
+    let mut mismatched = add;
+    mismatched["tenant_id"] = json!("starved");
+    let response = execute("acme", mismatched).await?;
+    assert_eq!(response.status(), 400);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn plugins_fetch_only_from_allowlisted_hosts() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+    serve_http(listener, |path, _| match path {
+        "/enrich" => ("200 OK", "x-source: it\r\n".to_string(), b"pong".to_vec()),
+        _ => ("404 Not Found", String::new(), Vec::new()),
+    });
+
+    let module_dir = std::env::temp_dir().join(format!("crm-it-fetch-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    let allowed = json!({ "method": "POST", "url": format!("http://127.0.0.1:{}/enrich", port), "body": "acme" });
+    // Same server, but not by a name on the allowlist
+    let denied = json!({ "url": format!("http://localhost:{}/enrich", port) });
+    let plugin = json_call_plugin("http_fetch", &[("fetch_allowed", vec![allowed]), ("fetch_denied", vec![denied])]);
+    // Only trusted plugins get the network
+    let plugin = plugin.replacen("(module", r#"(module (@custom "manifest" "{\"profile\": \"trusted\"}")"#, 1);
+    std::fs::write(module_dir.join("fetch.wasm"), wat::parse_str(plugin)?)?;
+    let config_file = module_dir.join("runtime.toml");
+    std::fs::write(
+        &config_file,
+        "max_security_profile = \"trusted\"\n[http_fetch_allowlists]\n\"fetch.wasm\" = [\"127.0.0.1\"]\n",
+    )?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_CONFIG_FILE", config_file.display().to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |function: &str| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .json(&json!({ "module_path": "fetch.wasm", "function_name": function, "params": [] }))
+            .send()
+    };
+
+    // The plugin prints the response it got
+    let response: Value = execute("fetch_allowed").await?.json().await?;
+    assert!(response["result"].as_i64().unwrap_or_default() > 0, "{}", response);
+    let fetched: Value = serde_json::from_str(response["stdout"].as_str().unwrap_or_default())?;
+    assert_eq!(fetched["status"], 200, "{}", fetched);
+    assert_eq!(fetched["body"], "pong");
+    assert_eq!(fetched["headers"]["x-source"], "it");
+
+    let response: Value = execute("fetch_denied").await?.json().await?;
+    assert_eq!(response["result"], json!(-1), "{}", response);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "requires Docker and runs the runtime service on port 8080"]
+async fn plugins_keep_state_per_tenant() -> Result<(), Error> {
+    let infra = Infra::start().await?;
+    let module_dir = std::env::temp_dir().join(format!("crm-it-kv-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::write(module_dir.join("kv.wasm"), wat::parse_file(fixture("kv.wat"))?)?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("REDIS_URL", infra.redis_url.clone()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |function: &str, tenant_id: Option<&str>| {
+        let http = &http;
+        let body = json!({ "module_path": "kv.wasm", "function_name": function, "params": [], "tenant_id": tenant_id });
+        async move {
+            let response: Value = http.post(format!("{}/execute", RUNTIME_URL)).json(&body).send().await?.json().await?;
+            Ok::<_, Error>(response["result"].clone())
+        }
+    };
+
+    assert_eq!(execute("remember", Some("acme")).await?, json!(0));
+    assert_eq!(execute("recall", Some("acme")).await?, json!(42));
+    // Another tenant's plugin sees its own store, and tenantless calls none
+    assert_eq!(execute("recall", Some("globex")).await?, json!(-1));
+    assert_eq!(execute("recall", None).await?, json!(-6));
+
+    let mut redis = infra.redis().await?;
+    let ttl: i64 = redis::cmd("TTL").arg("plugin_kv:acme:kv.wasm:visits").query_async(&mut redis).await?;
+    assert!((1..=3600).contains(&ttl), "ttl {}", ttl);
+
+    assert_eq!(execute("forget", Some("acme")).await?, json!(0));
+    assert_eq!(execute("recall", Some("acme")).await?, json!(-1));
+    assert_eq!(execute("forget", Some("acme")).await?, json!(-1));
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "requires Docker and runs the runtime service on port 8080"]
+async fn rollouts_split_executions_between_versions() -> Result<(), Error> {
+    let infra = Infra::start().await?;
+    let registry_dir = std::env::temp_dir().join(format!("crm-it-rollouts-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&registry_dir)?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("RUNTIME_REGISTRY_URL", format!("file://{}", registry_dir.display())),
+            ("REDIS_URL", infra.redis_url.clone()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    // Each version answers with its major version
+    for (version, answer) in [("1.0.0", 1), ("2.0.0", 2)] {
+        let plugin = format!(r#"(module (func (export "run") (result i32) (i32.const {})))"#, answer);
+        let form = reqwest::multipart::Form::new()
+            .text("name", "answer")
+            .text("version", version)
+            .part("module", reqwest::multipart::Part::bytes(wat::parse_str(plugin)?).file_name("answer.wasm"));
+        let response = http.post(format!("{}/modules", RUNTIME_URL)).multipart(form).send().await?;
+        assert_eq!(response.status(), 201);
+    }
+
+    let roll_out = |canary: &str, canary_percent: u8| {
+        http.put(format!("{}/admin/rollouts/answer", RUNTIME_URL))
+            .json(&json!({ "stable": "1.0.0", "canary": canary, "canary_percent": canary_percent }))
+            .send()
+    };
+    let execute = |tenant_id: String| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .header("x-tenant-id", tenant_id)
+            .json(&json!({ "module_path": "answer", "function_name": "run", "params": [] }))
+            .send()
+    };
+
+    let response = roll_out("3.0.0", 10).await?;
+    assert_eq!(response.status(), 422);
+    let response = roll_out("2.0.0", 50).await?;
+    assert_eq!(response.status(), 200);
+
+    // Split between the versions, each tenant always on the same one
+    let mut answers = std::collections::HashMap::new();
+    for i in 0..40 {
+        let tenant_id = format!("tenant-{}", i);
+        let first: Value = execute(tenant_id.clone()).await?.json().await?;
+        let second: Value = execute(tenant_id).await?.json().await?;
+        assert_eq!(first["resolved_version"], second["resolved_version"], "{} {}", first, second);
+        *answers.entry(first["result"].to_string()).or_insert(0) += 1;
+    }
+    assert_eq!(answers.len(), 2, "{:?}", answers);
+
+    // Cut over
+    roll_out("2.0.0", 100).await?;
+    let response: Value = execute("tenant-0".to_string()).await?.json().await?;
+    assert_eq!(response["result"], json!(2), "{}", response);
+    assert_eq!(response["resolved_version"], "2.0.0", "{}", response);
+
+    let listed: Value = http.get(format!("{}/admin/rollouts", RUNTIME_URL)).send().await?.json().await?;
+    assert_eq!(listed["rollouts"]["answer"]["canary_percent"], 100, "{}", listed);
+    let metrics = http.get(format!("{}/metrics", RUNTIME_URL)).send().await?.text().await?;
+    for version in ["1.0.0", "2.0.0"] {
+        let series = format!(r#"plugin_rollout_executions_total{{plugin="answer",status="success",version="{}"}}"#, version);
+        assert!(metrics.contains(&series), "{} in {}", series, metrics);
+    }
+
+    let deleted = http.delete(format!("{}/admin/rollouts/answer", RUNTIME_URL)).send().await?;
+    assert_eq!(deleted.status(), 200);
+
+    std::fs::remove_dir_all(&registry_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "requires Docker and runs the runtime service on port 8080"]
+async fn plugins_read_their_tenants_settings() -> Result<(), Error> {
+    let infra = Infra::start().await?;
+    let module_dir = std::env::temp_dir().join(format!("crm-it-config-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::write(module_dir.join("config.wasm"), wat::parse_file(fixture("config.wat"))?)?;
+    std::fs::copy(module_dir.join("config.wasm"), module_dir.join("untrusted.wasm"))?;
+    let secrets_file = module_dir.join("secrets.env");
+    std::fs::write(&secrets_file, "plugins/acme/crm_token=s3cr3t\n")?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("REDIS_URL", infra.redis_url.clone()),
+            ("RUNTIME_PLUGIN_SETTINGS_MAX_BYTES", "64".to_string()),
+            ("RUNTIME_SECRET_SETTINGS_MODULES", "config.wasm".to_string()),
+            ("SECRETS_PROVIDER", "env-file".to_string()),
+            ("SECRETS_ENV_FILE", secrets_file.display().to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let settings_url = format!("{}/settings/config.wasm", RUNTIME_URL);
+    let settings = json!({ "threshold": 7, "token": "secret://crm_token" });
+    let response = http.put(&settings_url).header("X-Tenant-ID", "acme").json(&settings).send().await?;
+    assert_eq!(response.status(), 200);
+    let response: Value = http.get(&settings_url).header("X-Tenant-ID", "acme").send().await?.json().await?;
+    assert_eq!(response["settings"], settings, "{}", response);
+    let response = http.get(&settings_url).header("X-Tenant-ID", "globex").send().await?;
+    assert_eq!(response.status(), 404);
+    let response = http.put(&settings_url).json(&settings).send().await?;
+    assert_eq!(response.status(), 400);
+    let response = http.put(&settings_url).header("X-Tenant-ID", "acme").json(&json!([1, 2])).send().await?;
+    assert_eq!(response.status(), 400);
+    let escaping = json!({ "token": "secret://../other/crm_token" });
+    let response = http.put(&settings_url).header("X-Tenant-ID", "acme").json(&escaping).send().await?;
+    assert_eq!(response.status(), 400);
+    let too_large = json!({ "key": "x".repeat(64) });
+    let response = http.put(&settings_url).header("X-Tenant-ID", "acme").json(&too_large).send().await?;
+    assert_eq!(response.status(), 413);
+
+    let execute = |body: Value| {
+        let http = &http;
+        async move {
+            let response: Value = http.post(format!("{}/execute", RUNTIME_URL)).json(&body).send().await?.json().await?;
+            Ok::<_, Error>(response)
+        }
+    };
+    let read = |cap: i32, tenant_id: Option<&str>| {
+        execute(json!({ "module_path": "config.wasm", "function_name": "read", "params": [cap], "tenant_id": tenant_id }))
+    };
+
+    let response = execute(json!({
+        "module_path": "config.wasm",
+        "function_name": "settings",
+        "params": [],
+        "tenant_id": "acme",
+        "result_encoding": "string",
+    }))
+    .await?;
+    assert_eq!(response["result"], json!(settings.to_string()), "{}", response);
+    assert_eq!(read(4, Some("acme")).await?["result"], json!(-4));
+    // Only allowed modules resolve secret references, and only references
+    let response = execute(json!({
+        "module_path": "config.wasm",
+        "function_name": "token",
+        "params": [],
+        "tenant_id": "acme",
+        "result_encoding": "string",
+    }))
+    .await?;
+    assert_eq!(response["result"], json!("s3cr3t"), "{}", response);
+    let threshold_secret = json!({ "module_path": "config.wasm", "function_name": "threshold_secret", "params": [], "tenant_id": "acme" });
+    assert_eq!(execute(threshold_secret).await?["result"], json!(-1));
+    let untrusted = json!({ "module_path": "untrusted.wasm", "function_name": "threshold_secret", "params": [], "tenant_id": "acme" });
+    assert_eq!(execute(untrusted).await?["result"], json!(-7));
+    // Other tenants' plugins see only their own settings, tenantless ones none
+    assert_eq!(read(256, Some("globex")).await?["result"], json!(-1));
+    assert_eq!(read(256, None).await?["result"], json!(-6));
+
+    let response = http.delete(&settings_url).header("X-Tenant-ID", "acme").send().await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(read(256, Some("acme")).await?["result"], json!(-1));
+    let response = http.delete(&settings_url).header("X-Tenant-ID", "acme").send().await?;
+    assert_eq!(response.status(), 404);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn plugin_logs_are_returned_on_request() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-log-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::write(module_dir.join("log.wasm"), wat::parse_file(fixture("log.wat"))?)?;
+
+    let mut service =
+        ServiceProcess::spawn("extension-runtime-service", &[("WASM_MODULE_DIR", module_dir.display().to_string())])?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |include_logs: bool| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .json(&json!({ "module_path": "log.wasm", "function_name": "run", "params": [], "include_logs": include_logs }))
+            .send()
+    };
+
+    let response: Value = execute(true).await?.json().await?;
+    assert_eq!(response["success"], true, "{}", response);
+    assert_eq!(
+        response["logs"],
+        json!([
+            { "level": "info", "message": "syncing deal", "fields": { "deal_id": "d1" } },
+            { "level": "warn", "message": "slow response", "fields": {} },
+        ])
+    );
+    let first_id = response["execution_id"].as_str().unwrap_or_default().to_string();
+    assert!(uuid::Uuid::parse_str(&first_id).is_ok(), "{}", response);
+
+    // Logged either way, but only returned when asked for
+    let response: Value = execute(false).await?.json().await?;
+    assert!(response.get("logs").is_none(), "{}", response);
+    assert_ne!(response["execution_id"], json!(first_id));
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn plugins_query_their_tenants_crm_records() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+    serve_http(listener, |path, head| {
+        if header(head, "authorization") != "Bearer crm-token" || header(head, "x-tenant-id") != "acme" {
+            return ("401 Unauthorized", String::new(), Vec::new());
+        }
+        match path {
+            // More than asked for, which the runtime must not pass on
+            "/api/v1/contacts/?skip=0&limit=2&search=jo" => {
+                let page = json!({ "contacts": [{ "id": "c1" }, { "id": "c2" }, { "id": "c3" }], "total": 7 });
+                ("200 OK", String::new(), page.to_string().into_bytes())
+            }
+            "/api/v1/opportunities/d1" => ("200 OK", String::new(), json!({ "id": "d1" }).to_string().into_bytes()),
+            _ => ("404 Not Found", String::new(), Vec::new()),
+        }
+    });
+
+    let module_dir = std::env::temp_dir().join(format!("crm-it-crm-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    let contacts = json!({ "entity": "contacts", "search": "jo", "limit": 2 });
+    let plugin = json_call_plugin(
+        "crm_query",
+        &[
+            ("contacts", vec![contacts.clone()]),
+            ("deal", vec![json!({ "entity": "deals", "id": "d1" })]),
+            ("missing_deal", vec![json!({ "entity": "deals", "id": "d2" })]),
+            ("invoices", vec![json!({ "entity": "invoices" })]),
+            // The first page uses up the execution's 2 rows
+            ("contacts_twice", vec![contacts.clone(), contacts]),
+        ],
+    );
+    std::fs::write(module_dir.join("crm.wasm"), wat::parse_str(plugin)?)?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_CRM_API_URL", format!("http://127.0.0.1:{}/api/v1", port)),
+            ("RUNTIME_CRM_API_TOKEN", "crm-token".to_string()),
+            ("RUNTIME_CRM_QUERY_MAX_ROWS", "2".to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |function: &str, tenant_id: Option<&str>| {
+        let http = &http;
+        let body = json!({ "module_path": "crm.wasm", "function_name": function, "params": [], "tenant_id": tenant_id });
+        async move {
+            let response: Value = http.post(format!("{}/execute", RUNTIME_URL)).json(&body).send().await?.json().await?;
+            Ok::<_, Error>(response)
+        }
+    };
+
+    let response = execute("contacts", Some("acme")).await?;
+    assert!(response["result"].as_i64().unwrap_or_default() > 0, "{}", response);
+    let page: Value = serde_json::from_str(response["stdout"].as_str().unwrap_or_default())?;
+    assert_eq!(page, json!({ "records": [{ "id": "c1" }, { "id": "c2" }], "total": 7 }));
+    // Records cost fuel on top of the plugin's own
+    let invalid = execute("invoices", Some("acme")).await?;
+    assert_eq!(invalid["result"], json!(-2), "{}", invalid);
+    let record_fuel = response["fuel_consumed"].as_u64().unwrap_or_default() - invalid["fuel_consumed"].as_u64().unwrap_or_default();
+    assert!(record_fuel >= 2 * 1_000, "{}", record_fuel);
+
+    let response = execute("deal", Some("acme")).await?;
+    let deal: Value = serde_json::from_str(response["stdout"].as_str().unwrap_or_default())?;
+    assert_eq!(deal, json!({ "records": [{ "id": "d1" }], "total": 1 }));
+    assert_eq!(execute("missing_deal", Some("acme")).await?["result"], json!(-1));
+
+    assert_eq!(execute("contacts_twice", Some("acme")).await?["result"], json!(-3));
+    assert_eq!(execute("contacts", None).await?["result"], json!(-6));
+    // The CRM API only answers for acme
+    assert_eq!(execute("contacts", Some("globex")).await?["result"], json!(-4));
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn strings_and_bytes_pass_through_plugin_memory() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-strings-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::write(module_dir.join("strings.wasm"), wat::parse_file(fixture("strings.wat"))?)?;
+    std::fs::copy(fixture("add.wasm"), module_dir.join("add.wasm"))?;
+
+    let mut service =
+        ServiceProcess::spawn("extension-runtime-service", &[("WASM_MODULE_DIR", module_dir.display().to_string())])?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |body: Value| http.post(format!("{}/execute", RUNTIME_URL)).json(&body).send();
+    let call = |function: &str, params: Value, result_encoding: Option<&str>| {
+        let mut body = json!({ "module_path": "strings.wasm", "function_name": function, "params": params });
+        if let Some(result_encoding) = result_encoding {
+            body["result_encoding"] = json!(result_encoding);
+        }
+        execute(body)
+    };
+
+    let response: Value = call("echo", json!(["héllo, wörld"]), Some("string")).await?.json().await?;
+    assert_eq!(response["result"], json!("héllo, wörld"), "{}", response);
+    let response: Value = call("echo", json!([{ "base64": "AP+A" }]), Some("bytes")).await?.json().await?;
+    assert_eq!(response["result"], json!({ "base64": "AP+A" }), "{}", response);
+    // Without an encoding the pointer and length come back as numbers
+    let response: Value = call("echo", json!(["abc"]), None).await?.json().await?;
+    assert_eq!(response["result"][1], json!(3), "{}", response);
+    let response: Value = call("count", json!(["banana", 97]), None).await?.json().await?;
+    assert_eq!(response["result"], json!(3), "{}", response);
+
+    // A string where count wants its byte
+    let response: Value = call("count", json!(["banana", "a"]), None).await?.json().await?;
+    assert_eq!(response["success"], false, "{}", response);
+    let response: Value = call("count", json!([97, 98, 99]), Some("string")).await?.json().await?;
+    assert_eq!(response["success"], false, "{}", response);
+    // add takes two i32s but has no alloc to copy a string in with
+    let add = json!({ "module_path": "add.wasm", "function_name": "example", "params": ["ab"] });
+    let response: Value = execute(add).await?.json().await?;
+    assert_eq!(response["success"], false, "{}", response);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn json_plugins_take_and_return_documents() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-json-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::write(module_dir.join("json.wasm"), wat::parse_file(fixture("json.wat"))?)?;
+    std::fs::copy(fixture("add.wasm"), module_dir.join("add.wasm"))?;
+
+    let mut service =
+        ServiceProcess::spawn("extension-runtime-service", &[("WASM_MODULE_DIR", module_dir.display().to_string())])?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |module_path: &str, function: &str, params: Value| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .json(&json!({
+                "module_path": module_path,
+                "function_name": function,
+                "params": params,
+                "calling_convention": "json",
+            }))
+            .send()
+    };
+
+    let deal = json!({ "deal": { "id": "d1", "amount": 1200.5, "tags": ["enterprise", "renewal"] } });
+    let response: Value = execute("json.wasm", "handle", deal.clone()).await?.json().await?;
+    assert_eq!(response["success"], true, "{}", response);
+    assert_eq!(response["result"], deal);
+
+    let response: Value = execute("json.wasm", "broken", json!({})).await?.json().await?;
+    assert_eq!(response["success"], false, "{}", response);
+    // Not a handle(ptr, len) -> (ptr, len) export
+    let response: Value = execute("add.wasm", "example", json!({})).await?.json().await?;
+    assert_eq!(response["success"], false, "{}", response);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn only_modules_signed_by_trusted_keys_run() -> Result<(), Error> {
+    let base64 = base64::engine::general_purpose::STANDARD;
+    let key = |seed: u8| Ed25519KeyPair::from_seed_unchecked(&[seed; 32]).unwrap();
+    let (trusted, untrusted) = (key(1), key(2));
+    // A trailing custom section: id 0, size, then the name and signature
+    let embed = |key: &Ed25519KeyPair, mut module: Vec<u8>| {
+        let signature = key.sign(&module);
+        module.extend([0, 1 + 9 + 64, 9]);
+        module.extend(b"signature");
+        module.extend(signature.as_ref());
+        module
+    };
+
+    let module_dir = std::env::temp_dir().join(format!("crm-it-signing-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    let add = std::fs::read(fixture("add.wasm"))?;
+    std::fs::write(module_dir.join("embedded.wasm"), embed(&trusted, add.clone()))?;
+    let strings = wat::parse_file(fixture("strings.wat"))?;
+    std::fs::write(module_dir.join("detached.wasm.sig"), base64.encode(trusted.sign(&strings)))?;
+    std::fs::write(module_dir.join("detached.wasm"), strings)?;
+    std::fs::write(module_dir.join("forged.wasm"), embed(&untrusted, wat::parse_file(fixture("json.wat"))?))?;
+    std::fs::write(module_dir.join("unsigned.wasm"), wat::parse_file(fixture("log.wat"))?)?;
+    let keys_file = module_dir.join("trusted_keys");
+    std::fs::write(&keys_file, format!("# release key\n{}\n", base64.encode(trusted.public_key())))?;
+
+    let http = reqwest::Client::new();
+    let execute = |module_path: &str, function: &str, params: Value| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .json(&json!({ "module_path": module_path, "function_name": function, "params": params }))
+            .send()
+    };
+
+    for allow_unsigned in [false, true] {
+        let mut service = ServiceProcess::spawn(
+            "extension-runtime-service",
+            &[
+                ("WASM_MODULE_DIR", module_dir.display().to_string()),
+                ("RUNTIME_TRUSTED_KEYS_FILE", keys_file.display().to_string()),
+                ("RUNTIME_ALLOW_UNSIGNED_MODULES", allow_unsigned.to_string()),
+            ],
+        )?;
+        eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+            let http = &http;
+            async move {
+                let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+                Ok(ready.then_some(()))
+            }
+        })
+        .await?;
+        service.assert_running()?;
+
+        let response: Value = execute("embedded.wasm", "example", json!([2, 3])).await?.json().await?;
+        assert_eq!(response["result"], json!(5), "{}", response);
+        let response: Value = execute("detached.wasm", "count", json!(["banana", 97])).await?.json().await?;
+        assert_eq!(response["result"], json!(3), "{}", response);
+
+        let response: Value = execute("forged.wasm", "handle", json!([1, 2])).await?.json().await?;
+        assert_eq!(response["success"], allow_unsigned, "{}", response);
+        let response: Value = execute("unsigned.wasm", "run", json!([])).await?.json().await?;
+        assert_eq!(response["success"], allow_unsigned, "{}", response);
+        if !allow_unsigned {
+            assert_eq!(response["error"]["code"], "NOT_PERMITTED", "{}", response);
+            assert!(response["error"]["message"].as_str().unwrap_or_default().contains("not signed"), "{}", response);
+        }
+    }
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn wasi_preview2_components_execute() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-component-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::write(module_dir.join("component.wasm"), wat::parse_file(fixture("component.wat"))?)?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_MODULE_CACHE_DIR", module_dir.join("cache").display().to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |function: &str, params: Value| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .json(&json!({ "module_path": "component.wasm", "function_name": function, "params": params }))
+            .send()
+    };
+
+    let response: Value = execute("add", json!([2, 3])).await?.json().await?;
+    assert_eq!(response["success"], true, "{}", response);
+    assert_eq!(response["result"], json!(5));
+    assert!(response["fuel_consumed"].as_u64().unwrap_or_default() > 0);
+    let response: Value = execute("example:math/ops#add", json!([-2, 3])).await?.json().await?;
+    assert_eq!(response["result"], json!(1), "{}", response);
+    // Served by the Preview 2 context
+    let response: Value = execute("random", json!([])).await?.json().await?;
+    assert!(response["result"].is_u64(), "{}", response);
+
+    let response: Value = execute("add", json!(["2", 3])).await?.json().await?;
+    assert_eq!(response["success"], false, "{}", response);
+    let response: Value = execute("example:math/ops#missing", json!([])).await?.json().await?;
+    assert_eq!(response["success"], false, "{}", response);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn wasi_grants_preopen_directories_per_module() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-grants-{}", uuid::Uuid::new_v4()));
+    let data_dir = module_dir.join("data");
+    std::fs::create_dir_all(&data_dir)?;
+    std::fs::write(data_dir.join("greeting.txt"), "hello from the host")?;
+    // Same module twice, granted the directory under one path only
+    let plugin = wat::parse_file(fixture("preopen.wat"))?;
+    std::fs::write(module_dir.join("granted.wasm"), &plugin)?;
+    std::fs::write(module_dir.join("ungranted.wasm"), &plugin)?;
+    let config_file = module_dir.join("runtime.toml");
+    std::fs::write(
+        &config_file,
+        format!(
+            "[wasi_grants.\"granted.wasm\"]\npreopens = [{{ host = \"{}\", guest = \"/data\" }}]\n",
+            data_dir.display()
+        ),
+    )?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_CONFIG_FILE", config_file.display().to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |module_path: &str| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .json(&json!({ "module_path": module_path, "function_name": "run", "params": [] }))
+            .send()
+    };
+
+    let response: Value = execute("granted.wasm").await?.json().await?;
+    assert_eq!(response["result"], json!(0), "{}", response);
+    assert_eq!(response["stdout"], "hello from the host");
+
+    // No preopened directory: EBADF
+    let response: Value = execute("ungranted.wasm").await?.json().await?;
+    assert_eq!(response["result"], json!(8), "{}", response);
+    assert_eq!(response["stdout"], "");
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn granted_plugins_get_scratch_space_per_execution() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-scratch-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::write(module_dir.join("scratch.wasm"), wat::parse_file(fixture("scratch.wat"))?)?;
+    let scratch_dir = module_dir.join("scratch");
+    let config_file = module_dir.join("runtime.toml");
+    std::fs::write(
+        &config_file,
+        format!(
+            "scratch_dir = \"{}\"\n[wasi_grants.\"scratch.wasm\"]\nscratch = {{ max_bytes = 65536 }}\n",
+            scratch_dir.display()
+        ),
+    )?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_CONFIG_FILE", config_file.display().to_string()),
+            // Enough for the spinning plugin to outlast its quota checks
+            ("RUNTIME_FUEL_LIMIT", u64::MAX.to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |function: &str, params: Value| {
+        let body = json!({
+            "module_path": "scratch.wasm",
+            "function_name": function,
+            "params": params,
+            "tenant_id": "acme",
+            "timeout_seconds": 10,
+        });
+        let http = &http;
+        async move {
+            let response: Value = http.post(format!("{}/execute", RUNTIME_URL)).json(&body).send().await?.json().await?;
+            Ok::<_, Error>(response)
+        }
+    };
+
+    let response = execute("write", json!([4, 0])).await?;
+    assert_eq!(response["result"], json!(0), "{}", response);
+    // The next execution starts empty: ENOENT
+    let response = execute("open", json!([])).await?;
+    assert_eq!(response["result"], json!(44), "{}", response);
+
+    let started = std::time::Instant::now();
+    let response = execute("write", json!([32, 1])).await?;
+    assert_eq!(response["success"], false, "{}", response);
+    assert_eq!(response["error"]["code"], "LIMIT_EXCEEDED", "{}", response);
+    assert!(response["error"]["message"].as_str().unwrap_or_default().contains("quota of 65536 bytes"), "{}", response);
+    assert!(started.elapsed() < Duration::from_secs(5), "stopped after {:?}", started.elapsed());
+
+    // Every execution's directory is removed once it's done
+    let left: Vec<_> = std::fs::read_dir(scratch_dir.join("tenants").join("acme"))?.collect();
+    assert!(left.is_empty(), "{:?}", left);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn plugins_see_only_allowlisted_environment_variables() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-env-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::write(module_dir.join("env.wasm"), wat::parse_file(fixture("env.wat"))?)?;
+    let config_file = module_dir.join("runtime.toml");
+    std::fs::write(
+        &config_file,
+        "plugin_env = [\"LANG\", \"PLUGIN_REGION\"]\n\
+         [request_env.PLUGIN_ENV]\nvalues = [\"production\", \"staging\"]\n\
+         [request_env.LANG]\nmax_bytes = 16\n",
+    )?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_CONFIG_FILE", config_file.display().to_string()),
+            ("LANG", "en_US.UTF-8".to_string()),
+            ("PLUGIN_SECRET_LOOKALIKE", "not for plugins".to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |env: Value| {
+        let body = json!({ "module_path": "env.wasm", "function_name": "run", "params": [], "env": env });
+        let http = &http;
+        async move {
+            let response: Value = http.post(format!("{}/execute", RUNTIME_URL)).json(&body).send().await?.json().await?;
+            Ok::<_, Error>(response)
+        }
+    };
+
+    // Only the passed-through variables that are set
+    let response = execute(json!({})).await?;
+    assert_eq!(response["stdout"], "LANG=en_US.UTF-8\n", "{}", response);
+
+    let response = execute(json!({ "PLUGIN_ENV": "staging", "LANG": "de_DE.UTF-8" })).await?;
+    let mut lines: Vec<&str> = response["stdout"].as_str().unwrap_or_default().lines().collect();
+    lines.sort();
+    assert_eq!(lines, ["LANG=de_DE.UTF-8", "PLUGIN_ENV=staging"], "{}", response);
+
+    for refused in [json!({ "PLUGIN_ENV": "dev" }), json!({ "LANG": "x".repeat(17) }), json!({ "HOME": "/" })] {
+        let response = execute(refused.clone()).await?;
+        assert_eq!(response["success"], false, "{} {}", refused, response);
+        assert_eq!(response["stdout"], "", "{}", response);
+    }
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn pooling_allocator_serves_modules_and_components() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-pooling-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::copy(fixture("add.wasm"), module_dir.join("add.wasm"))?;
+    std::fs::write(module_dir.join("component.wasm"), wat::parse_file(fixture("component.wat"))?)?;
+
+    // Room for the busy slots and two modules' warm instances, not more
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_MODULE_CACHE_DIR", module_dir.join("cache").display().to_string()),
+            ("RUNTIME_POOLING_ALLOCATOR", "true".to_string()),
+            ("RUNTIME_MAX_INSTANCES", "4".to_string()),
+            ("RUNTIME_WARM_POOL_SIZE", "2".to_string()),
+            ("RUNTIME_WARM_POOL_MODULES", "2".to_string()),
+            ("RUNTIME_POOLING_TOTAL_INSTANCES", "16".to_string()),
+            ("RUNTIME_POOLING_TOTAL_MEMORIES", "16".to_string()),
+            ("RUNTIME_POOLING_TOTAL_TABLES", "16".to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |module_path: &str, function: &str| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .json(&json!({ "module_path": module_path, "function_name": function, "params": [2, 3] }))
+            .send()
+    };
+
+    // More executions than slots, so slots are reused
+    for _ in 0..10 {
+        let response: Value = execute("add.wasm", "example").await?.json().await?;
+        assert_eq!(response["result"], json!(5), "{}", response);
+        let response: Value = execute("component.wasm", "add").await?.json().await?;
+        assert_eq!(response["result"], json!(5), "{}", response);
+    }
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn security_profiles_gate_features_and_host_functions() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-profiles-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    let simd = r#"(func (export "lanes") (result i32) (i32x4.extract_lane 0 (i32x4.splat (i32.const 7))))"#;
+    let network = r#"(import "crm" "http_fetch" (func (param i32 i32 i32 i32) (result i32))) (func (export "run") (result i32) (i32.const 1))"#;
+    let manifest = |profile: &str| format!(r#"(@custom "manifest" "{{\"profile\": \"{}\"}}")"#, profile);
+    let modules = [
+        ("simd.wasm", simd, String::new()),
+        ("simd-standard.wasm", simd, manifest("standard")),
+        ("network.wasm", network, String::new()),
+        ("network-trusted.wasm", network, manifest("trusted")),
+    ];
+    for (name, funcs, manifest) in &modules {
+        std::fs::write(module_dir.join(name), wat::parse_str(format!("(module {} {})", manifest, funcs))?)?;
+    }
+    let config_file = module_dir.join("runtime.toml");
+    std::fs::write(&config_file, "[tenant_quotas.acme]\nmax_security_profile = \"trusted\"\n")?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_CONFIG_FILE", config_file.display().to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |module_path: &str, function: &str, tenant_id: &str| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .header("x-tenant-id", tenant_id)
+            .json(&json!({ "module_path": module_path, "function_name": function, "params": [] }))
+            .send()
+    };
+
+    // SIMD is off in the strict default
+    let response: Value = execute("simd.wasm", "lanes", "globex").await?.json().await?;
+    assert_eq!(response["success"], false, "{}", response);
+    let response: Value = execute("simd-standard.wasm", "lanes", "globex").await?.json().await?;
+    assert_eq!(response["result"], json!(7), "{}", response);
+
+    // http_fetch is only linked for trusted plugins, which only acme may run
+    let response: Value = execute("network.wasm", "run", "acme").await?.json().await?;
+    assert_eq!(response["success"], false, "{}", response);
+    let response: Value = execute("network-trusted.wasm", "run", "globex").await?.json().await?;
+    assert_eq!(
+        response["error"]["message"], "Execution error: Module needs the trusted security profile; at most standard is allowed",
+        "{}",
+        response
+    );
+    let response: Value = execute("network-trusted.wasm", "run", "acme").await?.json().await?;
+    assert_eq!(response["result"], json!(1), "{}", response);
+
+    let validate = |tenant_id: &str| {
+        let bytes = std::fs::read(module_dir.join("network-trusted.wasm")).unwrap();
+        http.post(format!("{}/validate", RUNTIME_URL)).header("x-tenant-id", tenant_id).body(bytes).send()
+    };
+    let report: Value = validate("globex").await?.json().await?;
+    assert_eq!(report["profile"], "trusted", "{}", report);
+    assert_eq!(report["violations"][0]["check"], "profile", "{}", report);
+    let report: Value = validate("acme").await?.json().await?;
+    assert_eq!(report["valid"], true, "{}", report);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn capabilities_limit_what_plugins_are_linked_with() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-capabilities-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    let kv = r#"(import "crm" "kv_get" (func (param i32 i32 i32 i32) (result i32))) (func (export "run") (result i32) (i32.const 1))"#;
+    let query = r#"(import "crm" "crm_query" (func (param i32 i32 i32 i32) (result i32))) (func (export "run") (result i32) (i32.const 1))"#;
+    // Whether the wall clock has moved past zero
+    let clock = r#"(import "wasi_snapshot_preview1" "clock_time_get" (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "run") (result i32)
+    (drop (call $clock_time_get (i32.const 0) (i64.const 0) (i32.const 0)))
+    (i64.ne (i64.load (i32.const 0)) (i64.const 0)))"#;
+    let manifest = |capabilities: &str| format!(r#"(@custom "manifest" "{{\"capabilities\": {}}}")"#, capabilities.replace('"', "\\\""));
+    let modules = [
+        ("kv.wasm", kv, manifest(r#"["kv"]"#)),
+        ("kv-undeclared.wasm", kv, String::new()),
+        ("query.wasm", query, manifest(r#"["crm_read"]"#)),
+        ("clock.wasm", clock, manifest(r#"["clock"]"#)),
+        ("no-clock.wasm", clock, manifest("[]")),
+    ];
+    for (name, funcs, manifest) in &modules {
+        std::fs::write(module_dir.join(name), wat::parse_str(format!("(module {} {})", manifest, funcs))?)?;
+    }
+    let config_file = module_dir.join("runtime.toml");
+    std::fs::write(
+        &config_file,
+        "granted_capabilities = [\"kv\", \"clock\"]\ndefault_capabilities = [\"clock\"]\n\
+         [tenant_quotas.acme]\ncapabilities = [\"kv\", \"crm_read\", \"clock\"]\n",
+    )?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_CONFIG_FILE", config_file.display().to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |module_path: &str, tenant_id: &str| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .header("x-tenant-id", tenant_id)
+            .json(&json!({ "module_path": module_path, "function_name": "run", "params": [] }))
+            .send()
+    };
+
+    let response: Value = execute("kv.wasm", "globex").await?.json().await?;
+    assert_eq!(response["result"], json!(1), "{}", response);
+    // Without a manifest a plugin only gets default_capabilities
+    let response: Value = execute("kv-undeclared.wasm", "globex").await?.json().await?;
+    assert!(
+        response["error"]["message"].as_str().unwrap_or_default().contains("needs the kv capability"),
+        "{}",
+        response
+    );
+
+    // crm_read is only granted to acme
+    let response: Value = execute("query.wasm", "globex").await?.json().await?;
+    assert_eq!(
+        response["error"]["message"], "Execution error: Module needs capabilities the tenant isn't granted: crm_read",
+        "{}",
+        response
+    );
+    let response: Value = execute("query.wasm", "acme").await?.json().await?;
+    assert_eq!(response["result"], json!(1), "{}", response);
+
+    // Plugins without the clock see it stopped at zero
+    let response: Value = execute("clock.wasm", "globex").await?.json().await?;
+    assert_eq!(response["result"], json!(1), "{}", response);
+    let response: Value = execute("no-clock.wasm", "globex").await?.json().await?;
+    assert_eq!(response["result"], json!(0), "{}", response);
+
+    let bytes = std::fs::read(module_dir.join("query.wasm"))?;
+    let report: Value =
+        http.post(format!("{}/validate", RUNTIME_URL)).header("x-tenant-id", "globex").body(bytes).send().await?.json().await?;
+    assert_eq!(report["capabilities"], json!(["crm_read"]), "{}", report);
+    assert_eq!(report["violations"][0]["check"], "capabilities", "{}", report);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn deterministic_plugins_replay_exactly() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-determinism-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    // Random bytes mixed with the time
+    let random = r#"(import "wasi_snapshot_preview1" "random_get" (func $random_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "clock_time_get" (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "draw") (result i64)
+    (drop (call $random_get (i32.const 0) (i32.const 8)))
+    (drop (call $clock_time_get (i32.const 0) (i64.const 0) (i32.const 8)))
+    (i64.xor (i64.load (i32.const 0)) (i64.load (i32.const 8))))"#;
+    let simd = r#"(func (export "lanes") (result i32) (i32x4.extract_lane 0 (i32x4.splat (i32.const 7))))"#;
+    let modules = [
+        ("random.wasm", random, ""),
+        ("random-deterministic.wasm", random, r#"(@custom "manifest" "{\"deterministic\": true}")"#),
+        ("simd-deterministic.wasm", simd, r#"(@custom "manifest" "{\"profile\": \"standard\", \"deterministic\": true}")"#),
+    ];
+    for (name, funcs, manifest) in &modules {
+        std::fs::write(module_dir.join(name), wat::parse_str(format!("(module {} {})", manifest, funcs))?)?;
+    }
+
+    let mut service = ServiceProcess::spawn("extension-runtime-service", &[("WASM_MODULE_DIR", module_dir.display().to_string())])?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |module_path: &str, function: &str, seed: Option<u64>| {
+        let http = &http;
+        let body = json!({ "module_path": module_path, "function_name": function, "params": [], "seed": seed });
+        async move {
+            let response: Value = http.post(format!("{}/execute", RUNTIME_URL)).json(&body).send().await?.json().await?;
+            Ok::<_, Error>(response)
+        }
+    };
+
+    let first = execute("random.wasm", "draw", None).await?;
+    let second = execute("random.wasm", "draw", None).await?;
+    assert_ne!(first["result"], second["result"], "{} {}", first, second);
+
+    let first = execute("random-deterministic.wasm", "draw", None).await?;
+    let second = execute("random-deterministic.wasm", "draw", Some(0)).await?;
+    assert_eq!(first["result"], second["result"], "{} {}", first, second);
+    assert_eq!(first["fuel_consumed"], second["fuel_consumed"], "{} {}", first, second);
+    let seeded = execute("random-deterministic.wasm", "draw", Some(7)).await?;
+    assert_ne!(seeded["result"], first["result"], "{}", seeded);
+    let replayed = execute("random-deterministic.wasm", "draw", Some(7)).await?;
+    assert_eq!(replayed["result"], seeded["result"], "{}", replayed);
+
+    // SIMD is off for deterministic plugins, whatever their profile
+    let response = execute("simd-deterministic.wasm", "lanes", None).await?;
+    assert_eq!(response["success"], false, "{}", response);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn batches_run_every_item_and_report_each() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-batch-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::copy(fixture("add.wasm"), module_dir.join("add.wasm"))?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[("WASM_MODULE_DIR", module_dir.display().to_string())],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let mut items: Vec<Value> = (0..20).map(|i| json!({ "function_name": "example", "params": [i, 1] })).collect();
+    items.push(json!({ "function_name": "missing", "params": [] }));
+    items.push(json!({ "function_name": "example", "params": [1, 1], "module_path": "other.wasm" }));
+    let response = http
+        .post(format!("{}/execute/batch", RUNTIME_URL))
+        .json(&json!({ "module_path": "add.wasm", "max_parallel": 4, "items": items }))
+        .send()
+        .await?;
+    assert_eq!(response.status(), 200);
+    let batch: Value = response.json().await?;
+    assert_eq!(batch["succeeded"], 20, "{}", batch);
+    assert_eq!(batch["failed"], 2, "{}", batch);
+    let results = batch["results"].as_array().unwrap();
+    // In the order sent
+    for (i, result) in results.iter().take(20).enumerate() {
+        assert_eq!(result["result"], json!(i + 1), "{}", result);
+    }
+    assert_eq!(results[20]["success"], false, "{}", results[20]);
+    assert_eq!(results[21]["error"]["code"], "INVALID_REQUEST", "{}", results[21]);
+    assert!(results[21]["error"]["message"].as_str().unwrap().contains("module_path"), "{}", results[21]);
+
+    let response = http
+        .post(format!("{}/execute/batch", RUNTIME_URL))
+        .json(&json!({ "module_path": "add.wasm", "items": [] }))
+        .send()
+        .await?;
+    assert_eq!(response.status(), 400);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn pipelines_feed_each_result_to_the_next_step() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-pipeline-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    let math = r#"(module
+        (func (export "double") (param i32) (result i32) (i32.mul (local.get 0) (i32.const 2)))
+        (func (export "inc") (param i32) (result i32) (i32.add (local.get 0) (i32.const 1))))"#;
+    std::fs::write(module_dir.join("math.wasm"), wat::parse_str(math)?)?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[("WASM_MODULE_DIR", module_dir.display().to_string())],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let run = |steps: Value| {
+        http.post(format!("{}/execute/pipeline", RUNTIME_URL))
+            .json(&json!({ "params": [3], "steps": steps }))
+            .send()
+    };
+    let step = |function: &str| json!({ "module_path": "math.wasm", "function_name": function });
+
+    let pipeline: Value = run(json!([step("double"), step("inc"), step("double")])).await?.json().await?;
+    assert_eq!(pipeline["success"], true, "{}", pipeline);
+    assert_eq!(pipeline["result"], json!(14), "{}", pipeline);
+    assert_eq!(pipeline["steps_run"], 3, "{}", pipeline);
+
+    // The first failure stops the pipeline
+    let pipeline: Value = run(json!([step("double"), step("missing"), step("inc")])).await?.json().await?;
+    assert_eq!(pipeline["success"], false, "{}", pipeline);
+    assert_eq!(pipeline["failed_step"], 1, "{}", pipeline);
+    assert_eq!(pipeline["steps_run"], 2, "{}", pipeline);
+
+    // Budgets are per step
+    let starved = json!({ "module_path": "math.wasm", "function_name": "inc", "fuel_limit": 1 });
+    let pipeline: Value = run(json!([step("double"), starved])).await?.json().await?;
+    assert_eq!(pipeline["failed_step"], 1, "{}", pipeline);
+
+    let response = run(json!([{ "module_path": "math.wasm", "function_name": "inc", "params": [1] }])).await?;
+    assert_eq!(response.status(), 400);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn plugins_call_other_plugins_within_one_budget() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-plugin-calls-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::write(module_dir.join("json.wasm"), wat::parse_file(fixture("json.wat"))?)?;
+    std::fs::write(module_dir.join("spin.wasm"), wat::parse_str(r#"(module
+        (memory (export "memory") 1)
+        (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+        (func (export "run") (param i32 i32) (result i32 i32) (loop $spin (br $spin)) (unreachable)))"#)?)?;
+    for (name, next) in [
+        ("a.wasm", "b.wasm#run"),
+        ("b.wasm", "json.wasm#handle"),
+        ("deep-1.wasm", "deep-2.wasm"),
+        ("deep-2.wasm", "deep-3.wasm"),
+        ("deep-3.wasm", "json.wasm#handle"),
+        ("loop-1.wasm", "loop-2.wasm"),
+        ("loop-2.wasm", "loop-1.wasm"),
+        ("greedy.wasm", "spin.wasm"),
+    ] {
+        std::fs::write(module_dir.join(name), wat::parse_str(relay_plugin(next))?)?;
+    }
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_PLUGIN_CALL_MAX_DEPTH", "2".to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |module_path: &str| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .json(&json!({
+                "module_path": module_path,
+                "function_name": "run",
+                "params": { "lead_id": 42 },
+                "calling_convention": "json",
+            }))
+            .send()
+    };
+
+    // a calls b, which calls the library
+    let response: Value = execute("a.wasm").await?.json().await?;
+    assert_eq!(response["success"], true, "{}", response);
+    assert_eq!(response["result"], json!({ "lead_id": 42 }), "{}", response);
+
+    // The relays answer with the code call_plugin returned them: the third
+    // call nests too deep, and loop-2 may not call loop-1 back
+    let response: Value = execute("deep-1.wasm").await?.json().await?;
+    assert_eq!(response["result"], json!(-3), "{}", response);
+    let response: Value = execute("loop-1.wasm").await?.json().await?;
+    assert_eq!(response["result"], json!(-4), "{}", response);
+
+    // The callee burns its caller's fuel, so both run out
+    let response: Value = execute("greedy.wasm").await?.json().await?;
+    assert_eq!(response["success"], false, "{}", response);
+    assert_eq!(response["trap"]["reason"], "out_of_fuel", "{}", response);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "requires Docker and runs the runtime service on port 8080"]
+async fn declared_cacheable_results_are_reused_until_invalidated() -> Result<(), Error> {
+    let infra = Infra::start().await?;
+    let module_dir = std::env::temp_dir().join(format!("crm-it-results-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    let score = r#"(func (export "score") (param i32) (result i32) (i32.mul (local.get 0) (i32.const 10)))"#;
+    std::fs::write(
+        module_dir.join("score.wasm"),
+        wat::parse_str(format!(r#"(module (@custom "manifest" "{{\"cache_ttl_secs\": 60}}") {})"#, score))?,
+    )?;
+    std::fs::write(module_dir.join("uncached.wasm"), wat::parse_str(format!("(module {})", score))?)?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[("WASM_MODULE_DIR", module_dir.display().to_string()), ("REDIS_URL", infra.redis_url.clone())],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |module_path: &'static str, lead: i32, tenant_id: &'static str| {
+        let http = &http;
+        async move {
+            let response: Value = http
+                .post(format!("{}/execute", RUNTIME_URL))
+                .json(&json!({ "module_path": module_path, "function_name": "score", "params": [lead], "tenant_id": tenant_id }))
+                .send()
+                .await?
+                .json()
+                .await?;
+            assert_eq!(response["result"], json!(lead * 10), "{}", response);
+            Ok::<_, Error>(response["cached"].as_bool().unwrap())
+        }
+    };
+
+    assert!(!execute("score.wasm", 3, "acme").await?);
+    eventually("the result to be cached", Duration::from_secs(10), || async {
+        Ok(execute("score.wasm", 3, "acme").await?.then_some(()))
+    })
+    .await?;
+    // Other params and other tenants have results of their own
+    assert!(!execute("score.wasm", 4, "acme").await?);
+    assert!(!execute("score.wasm", 3, "globex").await?);
+    // Plugins that don't declare a TTL always run
+    assert!(!execute("uncached.wasm", 3, "acme").await?);
+    assert!(!execute("uncached.wasm", 3, "acme").await?);
+
+    let response: Value =
+        http.delete(format!("{}/admin/results/score.wasm?tenant_id=acme", RUNTIME_URL)).send().await?.json().await?;
+    assert!(response["deleted"].as_u64().unwrap() >= 1, "{}", response);
+    assert!(!execute("score.wasm", 3, "acme").await?);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "requires Docker and runs the runtime service on port 8080"]
+async fn retries_with_an_idempotency_key_replay_the_first_response() -> Result<(), Error> {
+    let infra = Infra::start().await?;
+    let module_dir = std::env::temp_dir().join(format!("crm-it-idempotency-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::copy(fixture("add.wasm"), module_dir.join("add.wasm"))?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[("WASM_MODULE_DIR", module_dir.display().to_string()), ("REDIS_URL", infra.redis_url.clone())],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |key: Option<&str>, params: Value| {
+        let mut request = http
+            .post(format!("{}/execute", RUNTIME_URL))
+            .header("x-tenant-id", "acme")
+            .json(&json!({ "module_path": "add.wasm", "function_name": "example", "params": params }));
+        if let Some(key) = key {
+            request = request.header("idempotency-key", key);
+        }
+        request.send()
+    };
+
+    let first = execute(Some("charge-42"), json!([2, 3])).await?;
+    assert_eq!(first.status(), 200);
+    assert!(first.headers().get("idempotent-replayed").is_none());
+    let first: Value = first.json().await?;
+
+    // The retry isn't run again
+    let retry = execute(Some("charge-42"), json!([2, 3])).await?;
+    assert_eq!(retry.status(), 200);
+    assert_eq!(retry.headers()["idempotent-replayed"], "true");
+    let retry: Value = retry.json().await?;
+    assert_eq!(retry["execution_id"], first["execution_id"], "{} {}", first, retry);
+
+    let response = execute(Some("charge-42"), json!([2, 4])).await?;
+    assert_eq!(response.status(), 422);
+
+    let other: Value = execute(Some("charge-43"), json!([2, 3])).await?.json().await?;
+    assert_ne!(other["execution_id"], first["execution_id"]);
+    let unkeyed: Value = execute(None, json!([2, 3])).await?.json().await?;
+    assert_ne!(unkeyed["execution_id"], first["execution_id"]);
+
+    let response = execute(Some(&"k".repeat(256)), json!([2, 3])).await?;
+    assert_eq!(response.status(), 400);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn inline_modules_run_under_their_own_limits() -> Result<(), Error> {
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[("RUNTIME_INLINE_MODULES", "true".to_string()), ("RUNTIME_INLINE_MAX_MODULE_BYTES", "4096".to_string())],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let base64 = base64::engine::general_purpose::STANDARD;
+    let execute = |plugin: &str, function: &str, params: Value| {
+        let module = base64.encode(wat::parse_str(plugin).unwrap());
+        http.post(format!("{}/execute/inline", RUNTIME_URL))
+            .json(&json!({ "module": module, "function_name": function, "params": params }))
+            .send()
+    };
+
+    let add = r#"(module (func (export "add") (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1))))"#;
+    let response: Value = execute(add, "add", json!([2, 3])).await?.json().await?;
+    assert_eq!(response["success"], true, "{}", response);
+    assert_eq!(response["result"], json!(5), "{}", response);
+
+    // Inline modules get far less fuel than stored ones
+    let spin = r#"(module (func (export "spin") (loop $spin (br $spin))))"#;
+    let response: Value = execute(spin, "spin", json!([])).await?.json().await?;
+    assert_eq!(response["trap"]["reason"], "out_of_fuel", "{}", response);
+
+    let large = format!(r#"(module (memory 1) (data (i32.const 0) "{}") (func (export "run")))"#, "x".repeat(8192));
+    let response = execute(&large, "run", json!([])).await?;
+    assert_eq!(response.status(), 413);
+
+    let inline = |body: Value| http.post(format!("{}/execute/inline", RUNTIME_URL)).json(&body).send();
+    let response = inline(json!({ "module": base64.encode(b"not wasm"), "function_name": "run", "params": [] })).await?;
+    assert_eq!(response.status(), 422);
+    let response = inline(json!({ "module_path": "add.wasm", "function_name": "add", "params": [2, 3] })).await?;
+    assert_eq!(response.status(), 400);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn failed_executions_carry_an_error_code_and_status() -> Result<(), Error> {
+    let mut service = ServiceProcess::spawn("extension-runtime-service", &[("RUNTIME_INLINE_MODULES", "true".to_string())])?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let base64 = base64::engine::general_purpose::STANDARD;
+    let plugin = r#"(module
+        (memory 1)
+        (func (export "add") (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1)))
+        (func (export "oob") (result i32) (i32.load (i32.const 70000)))
+        (func (export "abort") unreachable)
+        (func (export "spin") (loop $spin (br $spin))))"#;
+    let module = base64.encode(wat::parse_str(plugin)?);
+    let execute = |function: &str, params: Value| {
+        http.post(format!("{}/execute/inline", RUNTIME_URL))
+            .json(&json!({ "module": module, "function_name": function, "params": params }))
+            .send()
+    };
+
+    for (function, params, status, code) in [
+        ("missing", json!([]), 404, "FUNCTION_NOT_FOUND"),
+        ("add", json!([1]), 400, "PARAM_MISMATCH"),
+        ("oob", json!([]), 422, "TRAP_OOB"),
+        ("abort", json!([]), 422, "TRAP"),
+        ("spin", json!([]), 422, "FUEL_EXHAUSTED"),
+    ] {
+        let response = execute(function, params).await?;
+        assert_eq!(response.status(), status, "{}", function);
+        let response: Value = response.json().await?;
+        assert_eq!(response["success"], false, "{}", response);
+        assert_eq!(response["error"]["code"], code, "{}", response);
+        assert_eq!(response["error"]["retryable"], false, "{}", response);
+        assert!(!response["error"]["message"].as_str().unwrap_or_default().is_empty(), "{}", response);
+    }
+    let response = execute("add", json!([2, 3])).await?;
+    assert_eq!(response.status(), 200);
+    let response: Value = response.json().await?;
+    assert!(response["error"].is_null(), "{}", response);
+
+    let response = http
+        .post(format!("{}/execute", RUNTIME_URL))
+        .json(&json!({ "module_path": "missing.wasm", "function_name": "run", "params": [] }))
+        .send()
+        .await?;
+    assert_eq!(response.status(), 404);
+    let response: Value = response.json().await?;
+    assert_eq!(response["error"]["code"], "MODULE_NOT_FOUND", "{}", response);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn executions_are_measured_by_module_and_tenant() -> Result<(), Error> {
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[("RUNTIME_INLINE_MODULES", "true".to_string()), ("RUNTIME_METRICS_MAX_TENANTS", "1".to_string())],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let module = base64::engine::general_purpose::STANDARD.encode(wat::parse_str(
+        r#"(module (func (export "add") (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1))))"#,
+    )?);
+    let execute = |tenant_id: &str, function: &str| {
+        http.post(format!("{}/execute/inline", RUNTIME_URL))
+            .header("X-Tenant-ID", tenant_id)
+            .json(&json!({ "module": module, "function_name": function, "params": [2, 3] }))
+            .send()
+    };
+    assert_eq!(execute("tenant-a", "add").await?.status(), 200);
+    assert_eq!(execute("tenant-a", "missing").await?.status(), 404);
+    // Past metrics_max_tenants
+    assert_eq!(execute("tenant-b", "add").await?.status(), 200);
+
+    let metrics = http.get(format!("{}/metrics", RUNTIME_URL)).send().await?.text().await?;
+    for series in [
+        r#"plugin_executions_total{module="inline",status="success",tenant="tenant-a"} 1"#,
+        r#"plugin_executions_total{module="inline",status="failure",tenant="tenant-a"} 1"#,
+        r#"plugin_execution_errors_total{code="FUNCTION_NOT_FOUND",module="inline",tenant="tenant-a"} 1"#,
+        r#"plugin_executions_total{module="inline",status="success",tenant="other"} 1"#,
+        r#"plugin_fuel_consumed_count{module="inline",tenant="tenant-a"} 2"#,
+        "active_plugin_instances 0",
+    ] {
+        assert!(metrics.contains(series), "{} in {}", series, metrics);
+    }
+    assert!(!metrics.contains("tenant-b"), "{}", metrics);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "requires Docker and runs the runtime service on port 8080"]
+async fn tenant_executions_are_metered_on_the_audit_stream() -> Result<(), Error> {
+    let infra = Infra::start().await?;
+    let module_dir = std::env::temp_dir().join(format!("crm-it-metering-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::copy(fixture("add.wasm"), module_dir.join("add.wasm"))?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("REDIS_URL", infra.redis_url.clone()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let response: Value = http
+        .post(format!("{}/execute", RUNTIME_URL))
+        .json(&json!({ "module_path": "add.wasm", "function_name": "example", "params": [2, 3], "tenant_id": "acme" }))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(response["success"], true, "{}", response);
+
+    let entry = eventually("the execution on the audit stream", Duration::from_secs(10), || {
+        let infra = &infra;
+        async move {
+            let entries: redis::streams::StreamRangeReply =
+                redis::cmd("XRANGE").arg("runtime_audit").arg("-").arg("+").query_async(&mut infra.redis().await?).await?;
+            Ok(entries.ids.into_iter().next())
+        }
+    })
+    .await?;
+    let field = |name: &str| entry.get::<String>(name).unwrap_or_default();
+    assert_eq!(field("execution_id"), response["execution_id"].as_str().unwrap_or_default());
+    assert_eq!(field("tenant_id"), "acme");
+    assert_eq!(field("module_path"), "add.wasm");
+    assert_eq!(field("success"), "1");
+    assert_eq!(field("fuel_consumed"), response["fuel_consumed"].to_string());
+    assert_eq!(field("memory_peak_bytes"), response["memory_used_bytes"].to_string());
+    assert_eq!(field("bytes_in"), "5");
+    // The result, with no output
+    assert_eq!(field("bytes_out"), "1");
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn module_paths_name_their_module_root() -> Result<(), Error> {
+    let dir = std::env::temp_dir().join(format!("crm-it-roots-{}", uuid::Uuid::new_v4()));
+    for root in ["default", "system/crm", "tenants/acme", "tenants/globex"] {
+        std::fs::create_dir_all(dir.join(root))?;
+    }
+    std::fs::copy(fixture("add.wasm"), dir.join("default/add.wasm"))?;
+    std::fs::copy(fixture("add.wasm"), dir.join("system/crm/add.wasm"))?;
+    std::fs::copy(fixture("add.wasm"), dir.join("tenants/acme/add.wasm"))?;
+    std::fs::copy(fixture("add.wasm"), dir.join("tenants/globex/add.wasm"))?;
+    std::os::unix::fs::symlink(dir.join("system/crm/add.wasm"), dir.join("default/linked.wasm"))?;
+    let config_file = dir.join("runtime.toml");
+    std::fs::write(
+        &config_file,
+        format!(
+            "[module_roots]\nsystem = \"{}\"\ntenant = \"{}/{{tenant_id}}\"\n",
+            dir.join("system").display(),
+            dir.join("tenants").display()
+        ),
+    )?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", dir.join("default").display().to_string()),
+            ("RUNTIME_CONFIG_FILE", config_file.display().to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    for (module_path, code) in [
+        ("add.wasm", None),
+        ("system:crm/add.wasm", None),
+        ("tenant:acme:add.wasm", None),
+        // Another tenant's
+        ("tenant:globex:add.wasm", Some("MODULE_NOT_FOUND")),
+        ("tenant:add.wasm", Some("INVALID_REQUEST")),
+        ("system:../default/add.wasm", Some("INVALID_REQUEST")),
+        ("system:/etc/passwd", Some("INVALID_REQUEST")),
+        // Into another root, through a link
+        ("linked.wasm", Some("NOT_PERMITTED")),
+        ("vendor:add.wasm", Some("MODULE_NOT_FOUND")),
+    ] {
+        let response: Value = http
+            .post(format!("{}/execute", RUNTIME_URL))
+            .header("x-tenant-id", "acme")
+            .json(&json!({ "module_path": module_path, "function_name": "example", "params": [2, 3] }))
+            .send()
+            .await?
+            .json()
+            .await?;
+        match code {
+            None => assert_eq!(response["result"], json!(5), "{}: {}", module_path, response),
+            Some(code) => assert_eq!(response["error"]["code"], code, "{}: {}", module_path, response),
+        }
+    }
+    // Tenants' roots aren't open to executions without a tenant
+    let response: Value = http
+        .post(format!("{}/execute", RUNTIME_URL))
+        .json(&json!({ "module_path": "tenant:acme:add.wasm", "function_name": "example", "params": [2, 3] }))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(response["error"]["code"], "MODULE_NOT_FOUND", "{}", response);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn config_file_changes_apply_to_later_executions() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-reload-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::copy(fixture("add.wasm"), module_dir.join("add.wasm"))?;
+    let config_file = module_dir.join("runtime.toml");
+    std::fs::write(&config_file, "config_reload_interval_secs = 1\nfuel_limit = 1\n")?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_CONFIG_FILE", config_file.display().to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = || {
+        let http = &http;
+        async move {
+            let response: Value = http
+                .post(format!("{}/execute", RUNTIME_URL))
+                .json(&json!({ "module_path": "add.wasm", "function_name": "example", "params": [2, 3] }))
+                .send()
+                .await?
+                .json()
+                .await?;
+            Ok::<_, Error>(response)
+        }
+    };
+    let response = execute().await?;
+    assert_eq!(response["error"]["code"], "FUEL_EXHAUSTED", "{}", response);
+
+    std::fs::write(&config_file, "config_reload_interval_secs = 1\nfuel_limit = 1000000\n")?;
+    eventually("the raised fuel limit to apply", Duration::from_secs(10), || {
+        let execute = &execute;
+        async move {
+            let response = execute().await?;
+            Ok((response["success"] == true).then_some(()))
+        }
+    })
+    .await?;
+
+    // An invalid file leaves the configuration as it was
+    std::fs::write(&config_file, "config_reload_interval_secs = 1\nfuel_limit = 0\n")?;
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    let response = execute().await?;
+    assert_eq!(response["success"], true, "{}", response);
+    service.assert_running()?;
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn tenants_run_their_own_modules_and_system_ones() -> Result<(), Error> {
+    let registry_dir = std::env::temp_dir().join(format!("crm-it-namespaces-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&registry_dir)?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[("RUNTIME_REGISTRY_URL", format!("file://{}", registry_dir.display()))],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let with_tenant = |request: reqwest::RequestBuilder, tenant_id: Option<&str>| match tenant_id {
+        Some(tenant_id) => request.header("x-tenant-id", tenant_id),
+        None => request,
+    };
+    let upload = |name: &str, tenant_id: Option<&str>| {
+        let form = reqwest::multipart::Form::new().text("name", name.to_string()).part(
+            "module",
+            reqwest::multipart::Part::bytes(std::fs::read(fixture("add.wasm")).unwrap()).file_name("add.wasm"),
+        );
+        with_tenant(http.post(format!("{}/modules", RUNTIME_URL)), tenant_id).multipart(form).send()
+    };
+    assert_eq!(upload("shared", None).await?.status(), 201);
+    assert_eq!(upload("private", Some("acme")).await?.status(), 201);
+    assert!(registry_dir.join("tenants/acme/private.meta.json").exists());
+
+    let execute = |module_path: &str, tenant_id: Option<&str>| {
+        let body = json!({ "module_path": module_path, "function_name": "example", "params": [2, 3] });
+        with_tenant(http.post(format!("{}/execute", RUNTIME_URL)), tenant_id).json(&body).send()
+    };
+    for (module_path, tenant_id, code) in [
+        ("private", Some("acme"), None),
+        ("private", Some("globex"), Some("MODULE_NOT_FOUND")),
+        ("private", None, Some("MODULE_NOT_FOUND")),
+        ("shared", Some("acme"), None),
+        ("shared", Some("globex"), None),
+        ("shared", None, None),
+    ] {
+        let response: Value = execute(module_path, tenant_id).await?.json().await?;
+        match code {
+            None => assert_eq!(response["result"], json!(5), "{} for {:?}: {}", module_path, tenant_id, response),
+            Some(code) => assert_eq!(response["error"]["code"], code, "{} for {:?}: {}", module_path, tenant_id, response),
+        }
+    }
+
+    let listed = |tenant_id: Option<&'static str>| {
+        let request = with_tenant(http.get(format!("{}/modules", RUNTIME_URL)), tenant_id);
+        async move {
+            let listed: Value = request.send().await?.json().await?;
+            let ids: Vec<_> = listed["modules"].as_array().unwrap().iter().map(|m| m["id"].clone()).collect();
+            Ok::<_, Error>(ids)
+        }
+    };
+    assert_eq!(listed(Some("globex")).await?, vec![json!("shared")]);
+    assert_eq!(listed(Some("acme")).await?, vec![json!("shared"), json!("private")]);
+
+    // Neither found nor removable outside its own namespace
+    let inspect = with_tenant(http.get(format!("{}/modules/private/inspect", RUNTIME_URL)), Some("globex"));
+    assert_eq!(inspect.send().await?.status(), 404);
+    let delete = with_tenant(http.delete(format!("{}/modules/private", RUNTIME_URL)), Some("globex"));
+    assert_eq!(delete.send().await?.status(), 404);
+    let delete = with_tenant(http.delete(format!("{}/modules/shared", RUNTIME_URL)), Some("acme"));
+    assert_eq!(delete.send().await?.status(), 404);
+    let delete = with_tenant(http.delete(format!("{}/modules/private", RUNTIME_URL)), Some("acme"));
+    assert_eq!(delete.send().await?.status(), 200);
+
+    std::fs::remove_dir_all(&registry_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn module_namespaces_follow_the_callers_token() -> Result<(), Error> {
+    let registry_dir = std::env::temp_dir().join(format!("crm-it-module-auth-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&registry_dir)?;
+
+    let jwks = std::fs::read(fixture("jwks.json"))?;
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let jwks_url = format!("http://{}/.well-known/jwks.json", listener.local_addr()?);
+    serve_http(listener, move |_, _| ("200 OK", String::new(), jwks.clone()));
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("RUNTIME_REGISTRY_URL", format!("file://{}", registry_dir.display())),
+            ("RUNTIME_JWKS_URL", jwks_url),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let key = jsonwebtoken::EncodingKey::from_rsa_pem(&std::fs::read(fixture("jwt_rsa.pem"))?)?;
+    let token = |scope: &str| {
+        let mut header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256);
+        header.kid = Some("it-key".to_string());
+        let claims = json!({ "sub": "ops", "tenant_id": "acme", "scope": scope, "exp": 4_102_444_800u64 });
+        jsonwebtoken::encode(&header, &claims, &key).unwrap()
+    };
+    let upload = |name: &str, scope: Option<&str>, tenant_id: Option<&str>| {
+        let form = reqwest::multipart::Form::new().text("name", name.to_string()).part(
+            "module",
+            reqwest::multipart::Part::bytes(std::fs::read(fixture("add.wasm")).unwrap()).file_name("add.wasm"),
+        );
+        let mut request = http.post(format!("{}/modules", RUNTIME_URL)).multipart(form);
+        if let Some(scope) = scope {
+            request = request.bearer_auth(token(scope));
+        }
+        if let Some(tenant_id) = tenant_id {
+            request = request.header("x-tenant-id", tenant_id);
+        }
+        request.send()
+    };
+
+    assert_eq!(upload("anonymous", None, None).await?.status(), 401);
+    assert_eq!(upload("spoofed", Some("plugins:execute"), Some("globex")).await?.status(), 403);
+    // Without the admin scope, leaving out the tenant doesn't publish for everyone
+    assert_eq!(upload("own", Some("plugins:execute"), None).await?.status(), 201);
+    assert!(registry_dir.join("tenants/acme/own.meta.json").exists());
+    assert!(!registry_dir.join("own.meta.json").exists());
+
+    assert_eq!(upload("shared", Some("plugins:admin"), None).await?.status(), 201);
+    assert!(registry_dir.join("shared.meta.json").exists());
+    assert_eq!(upload("globex-only", Some("plugins:admin"), Some("globex")).await?.status(), 201);
+    assert!(registry_dir.join("tenants/globex/globex-only.meta.json").exists());
+
+    let delete = |id: &str, scope: &str| http.delete(format!("{}/modules/{}", RUNTIME_URL, id)).bearer_auth(token(scope)).send();
+    assert_eq!(delete("shared", "plugins:execute").await?.status(), 404);
+    assert_eq!(delete("shared", "plugins:admin").await?.status(), 200);
+    service.assert_running()?;
+
+    std::fs::remove_dir_all(&registry_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn failing_plugins_are_disabled_until_their_breaker_closes() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-breakers-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    let plugin = r#"(module
+  (memory (export "memory") 1)
+  (func (export "fail") (unreachable))
+  (func (export "answer") (result i32) (i32.const 42)))"#;
+    std::fs::write(module_dir.join("flaky.wasm"), wat::parse_str(plugin)?)?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_BREAKER_MIN_EXECUTIONS", "3".to_string()),
+            ("RUNTIME_BREAKER_OPEN_SECS", "2".to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |function: &str, tenant_id: &str| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .header("x-tenant-id", tenant_id)
+            .json(&json!({ "module_path": "flaky.wasm", "function_name": function, "params": [] }))
+            .send()
+    };
+    let trip = || async {
+        for _ in 0..3 {
+            let response: Value = execute("fail", "acme").await?.json().await?;
+            assert_eq!(response["error"]["code"], "TRAP", "{}", response);
+        }
+        Ok::<_, Error>(())
+    };
+
+    trip().await?;
+    let disabled = execute("answer", "acme").await?;
+    assert_eq!(disabled.status(), 503);
+    let disabled: Value = disabled.json().await?;
+    assert_eq!(disabled["error"]["code"], "PLUGIN_DISABLED", "{}", disabled);
+    assert_eq!(disabled["error"]["retryable"], false, "{}", disabled);
+    // Other tenants' executions aren't affected
+    let response: Value = execute("answer", "globex").await?.json().await?;
+    assert_eq!(response["result"], json!(42), "{}", response);
+
+    let listed: Value = http.get(format!("{}/admin/breakers", RUNTIME_URL)).send().await?.json().await?;
+    assert_eq!(listed["count"], 1, "{}", listed);
+    assert_eq!(listed["breakers"][0]["module_path"], "flaky.wasm", "{}", listed);
+    assert_eq!(listed["breakers"][0]["tenant_id"], "acme", "{}", listed);
+    assert_eq!(listed["breakers"][0]["state"], "open", "{}", listed);
+
+    let reset: Value = http
+        .delete(format!("{}/admin/breakers/flaky.wasm?tenant_id=acme", RUNTIME_URL))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(reset["reset"], 1, "{}", reset);
+    let response: Value = execute("answer", "acme").await?.json().await?;
+    assert_eq!(response["result"], json!(42), "{}", response);
+
+    // Once open long enough, a successful probe closes it again
+    trip().await?;
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    let response: Value = execute("answer", "acme").await?.json().await?;
+    assert_eq!(response["result"], json!(42), "{}", response);
+    let listed: Value = http.get(format!("{}/admin/breakers", RUNTIME_URL)).send().await?.json().await?;
+    assert_eq!(listed["count"], 0, "{}", listed);
+    service.assert_running()?;
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn the_api_is_described_by_an_openapi_document() -> Result<(), Error> {
+    let mut service = ServiceProcess::spawn("extension-runtime-service", &[])?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let response = http.get(format!("{}/openapi.json", RUNTIME_URL)).send().await?;
+    assert_eq!(response.status(), 200);
+    let document: Value = response.json().await?;
+    assert!(document["openapi"].as_str().is_some_and(|version| version.starts_with("3.")), "{}", document["openapi"]);
+    for path in ["/execute", "/execute/batch", "/jobs/{job_id}", "/modules", "/admin/rollouts/{plugin}"] {
+        assert!(document["paths"][path].is_object(), "{} is not described", path);
+    }
+    let codes = &document["components"]["schemas"]["ErrorCode"]["enum"];
+    assert!(codes.as_array().unwrap().contains(&json!("FUEL_EXHAUSTED")), "{}", codes);
+
+    // Every reference resolves, for client generators not to choke on it
+    fn references<'a>(value: &'a Value, found: &mut Vec<&'a str>) {
+        match value {
+            Value::Object(object) => {
+                for (key, value) in object {
+                    match (key.as_str(), value) {
+                        ("$ref", Value::String(reference)) => found.push(reference),
+                        _ => references(value, found),
+                    }
+                }
+            }
+            Value::Array(values) => values.iter().for_each(|value| references(value, found)),
+            _ => {}
+        }
+    }
+    let mut found = Vec::new();
+    references(&document, &mut found);
+    assert!(!found.is_empty());
+    for reference in found {
+        let pointer = reference.strip_prefix('#').unwrap_or(reference);
+        assert!(document.pointer(pointer).is_some(), "{} doesn't resolve", reference);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn modules_are_validated_without_running() -> Result<(), Error> {
+    let mut service = ServiceProcess::spawn("extension-runtime-service", &[])?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let validate = |bytes: Vec<u8>| http.post(format!("{}/validate", RUNTIME_URL)).body(bytes).send();
+
+    let response = validate(std::fs::read(fixture("add.wasm"))?).await?;
+    assert_eq!(response.status(), 200);
+    let report: Value = response.json().await?;
+    assert_eq!(report["valid"], true, "{}", report);
+    assert_eq!(report["kind"], "module", "{}", report);
+
+    // Every violation is reported, not just the first
+    let plugin = r#"(module
+        (import "env" "abort" (func))
+        (import "crm" "launch_missiles" (func))
+        (import "wasi_snapshot_preview1" "not_a_function" (func))
+        (func (export "run")))"#;
+    let response = validate(wat::parse_str(plugin)?).await?;
+    assert_eq!(response.status(), 422);
+    let report: Value = response.json().await?;
+    let checks: Vec<_> = report["violations"].as_array().unwrap().iter().map(|v| v["check"].clone()).collect();
+    assert_eq!(checks, vec![json!("imports"), json!("imports"), json!("link")], "{}", report);
+
+    let report: Value = validate(b"not wasm".to_vec()).await?.json().await?;
+    assert_eq!(report["violations"][0]["check"], "parse", "{}", report);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn marketplace_manifests_are_checked_against_their_module() -> Result<(), Error> {
+    let mut service = ServiceProcess::spawn("extension-runtime-service", &[])?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let module = base64::engine::general_purpose::STANDARD.encode(wat::parse_str(
+        r#"(module
+            (import "crm" "kv_get" (func (param i32 i32 i32 i32) (result i32)))
+            (func (export "run")))"#,
+    )?);
+    let validate = |body: Value| http.post(format!("{}/manifests/validate", RUNTIME_URL)).json(&body).send();
+
+    let manifest = json!({
+        "name": "invoice-export",
+        "version": "1.2.0",
+        "capabilities": ["kv"],
+        "entrypoints": [{"name": "run", "input_schema": {"type": "object", "required": ["invoice_id"]}}],
+    });
+    let response = validate(json!({"manifest": manifest, "module": module})).await?;
+    assert_eq!(response.status(), 200);
+    let report: Value = response.json().await?;
+    assert_eq!(report["valid"], true, "{}", report);
+
+    // Every problem is reported with where it is
+    let manifest = json!({
+        "name": "Invoice Export",
+        "version": "1.2",
+        "capabilities": [],
+        "entrypoints": [
+            {"name": "export", "output_schema": {"oneOf": []}},
+            {"name": "export"},
+        ],
+        "homepage": "https://example.com",
+    });
+    let response = validate(json!({"manifest": manifest, "module": module})).await?;
+    assert_eq!(response.status(), 422);
+    let report: Value = response.json().await?;
+    let problems: Vec<_> =
+        report["problems"].as_array().unwrap().iter().map(|p| (p["check"].clone(), p["path"].clone())).collect();
+    for expected in [
+        ("schema", "$"),
+        ("name", "$.name"),
+        ("version", "$.version"),
+        ("entrypoints", "$.entrypoints[1].name"),
+        ("schemas", "$.entrypoints[0].output_schema"),
+        ("imports", "$.capabilities"),
+        ("entrypoints", "$.entrypoints[0].name"),
+    ] {
+        assert!(problems.contains(&(json!(expected.0), json!(expected.1))), "{:?} in {}", expected, report);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn runaway_plugins_are_interrupted_at_their_deadline() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-loop-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::write(module_dir.join("loop.wasm"), wat::parse_str(r#"(module (func (export "spin") (loop (br 0))))"#)?)?;
+
+    // Enough fuel that only the deadline can stop it
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_FUEL_LIMIT", u64::MAX.to_string()),
+            ("RUNTIME_MAX_INSTANCES", "20".to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let spin = |timeout_seconds: u64| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .json(&json!({ "module_path": "loop.wasm", "function_name": "spin", "params": [], "timeout_seconds": timeout_seconds }))
+            .send()
+    };
+    let started = std::time::Instant::now();
+    let response = spin(1).await?;
+    assert_eq!(response.status(), 504);
+    let response: Value = response.json().await?;
+    assert_eq!(response["success"], false, "{}", response);
+    assert_eq!(response["error"]["code"], "TIMEOUT", "{}", response);
+    assert_eq!(response["error"]["retryable"], false, "{}", response);
+    assert_eq!(response["error"]["message"], "Execution interrupted: deadline exceeded", "{}", response);
+    assert!(started.elapsed() < Duration::from_secs(5), "took {:?}", started.elapsed());
+
+    // Plugins spinning on every core leave the server responsive
+    let spinning: Vec<_> = (0..4).map(|_| tokio::spawn(spin(3))).collect();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let started = std::time::Instant::now();
+    let metrics = http.get(format!("{}/metrics", RUNTIME_URL)).send().await?;
+    assert_eq!(metrics.status(), 200);
+    assert!(started.elapsed() < Duration::from_secs(1), "took {:?}", started.elapsed());
+    for spinning in spinning {
+        let response: Value = spinning.await??.json().await?;
+        assert_eq!(response["success"], false, "{}", response);
+    }
+
+    // Finished executions can't be cancelled
+    let execution_id = response["execution_id"].as_str().unwrap_or_default();
+    let cancel = http.post(format!("{}/executions/{}/cancel", RUNTIME_URL, execution_id)).send().await?;
+    assert_eq!(cancel.status(), 404);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn profiled_executions_return_their_stack_samples() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-profile-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    let plugin = r#"(module
+  (func $hot (export "spin") (loop (br 0)))
+  (func (export "answer") (result i32) (i32.const 42)))"#;
+    std::fs::write(module_dir.join("hot.wasm"), wat::parse_str(plugin)?)?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_FUEL_LIMIT", u64::MAX.to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |function: &str, query: &str| {
+        http.post(format!("{}/execute{}", RUNTIME_URL, query))
+            .json(&json!({ "module_path": "hot.wasm", "function_name": function, "params": [], "timeout_seconds": 1 }))
+            .send()
+    };
+
+    let response: Value = execute("answer", "").await?.json().await?;
+    assert_eq!(response["result"], json!(42), "{}", response);
+    assert!(response.get("profile").is_none(), "{}", response);
+
+    let response: Value = execute("answer", "?profile=true").await?.json().await?;
+    assert_eq!(response["result"], json!(42), "{}", response);
+    assert!(response["profile"]["threads"].is_array(), "{}", response);
+
+    // A plugin stopped at its deadline still reports where it spent the time
+    let response: Value = execute("spin", "?profile=true").await?.json().await?;
+    assert_eq!(response["error"]["message"], "Execution interrupted: deadline exceeded", "{}", response);
+    let samples = &response["profile"]["threads"][0]["samples"]["length"];
+    assert!(samples.as_u64().unwrap_or_default() > 10, "{}", samples);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "requires Docker and runs the runtime service on port 8080"]
+async fn trapping_plugins_report_why_and_where() -> Result<(), Error> {
+    let infra = Infra::start().await?;
+    let module_dir = std::env::temp_dir().join(format!("crm-it-traps-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    let plugin = r#"(module
+  (memory (export "memory") 1)
+  (func $fail (unreachable))
+  (func (export "fail") (call $fail))
+  (func (export "divide") (param i32) (result i32) (i32.div_s (i32.const 1) (local.get 0)))
+  (func (export "overrun") (result i32) (i32.load (i32.const 70000))))"#;
+    std::fs::write(module_dir.join("traps.wasm"), wat::parse_str(plugin)?)?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("REDIS_URL", infra.redis_url.clone()),
+            ("RUNTIME_COREDUMP_ON_TRAP", "true".to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = |function: &str, params: Value| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .json(&json!({ "module_path": "traps.wasm", "function_name": function, "params": params }))
+            .send()
+    };
+
+    let response: Value = execute("fail", json!([])).await?.json().await?;
+    assert_eq!(response["trap"]["reason"], "unreachable", "{}", response);
+    let functions: Vec<_> = response["trap"]["backtrace"].as_array().into_iter().flatten().map(|frame| &frame["function"]).collect();
+    assert_eq!(functions, [&json!("fail"), &Value::Null], "{}", response);
+    let response: Value = execute("divide", json!([0])).await?.json().await?;
+    assert_eq!(response["trap"]["reason"], "integer_division_by_zero", "{}", response);
+    let response: Value = execute("overrun", json!([])).await?.json().await?;
+    assert_eq!(response["trap"]["reason"], "memory_out_of_bounds", "{}", response);
+    // Failures other than traps have none
+    let response: Value = execute("missing", json!([])).await?.json().await?;
+    assert_eq!(response["success"], false, "{}", response);
+    assert!(response.get("trap").is_none(), "{}", response);
+
+    // Asynchronous executions keep the coredump with their job
+    let body = json!({ "module_path": "traps.wasm", "function_name": "fail", "params": [] });
+    let job: Value = http.post(format!("{}/execute?async=true", RUNTIME_URL)).json(&body).send().await?.json().await?;
+    let url = format!("{}/jobs/{}", RUNTIME_URL, job["job_id"].as_str().unwrap_or_default());
+    let failed = eventually("the job to fail", Duration::from_secs(30), || {
+        let (http, url) = (&http, &url);
+        async move {
+            let job: Value = http.get(url).send().await?.json().await?;
+            Ok((job["status"] == "failed").then_some(job))
+        }
+    })
+    .await?;
+    assert_eq!(failed["coredump"], true, "{}", failed);
+    let coredump = http.get(format!("{}/coredump", url)).send().await?;
+    assert_eq!(coredump.status(), 200);
+    assert_eq!(coredump.headers()["content-type"], "application/wasm");
+    assert!(coredump.bytes().await?.starts_with(b"\0asm"));
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn executions_report_their_own_peak_memory() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-memory-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    let plugin = r#"(module (memory 1) (func (export "grow") (param i32) (result i32) (memory.grow (local.get 0))))"#;
+    std::fs::write(module_dir.join("grow.wasm"), wat::parse_str(plugin)?)?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[("WASM_MODULE_DIR", module_dir.display().to_string()), ("RUNTIME_MAX_MEMORY_PAGES", "8".to_string())],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let grow = |pages: i32| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .json(&json!({ "module_path": "grow.wasm", "function_name": "grow", "params": [pages] }))
+            .send()
+    };
+    // Each execution counts its own instance's memory, from its first page
+    for pages in [3, 1, 3] {
+        let response: Value = grow(pages).await?.json().await?;
+        assert_eq!(response["success"], true, "{}", response);
+        assert_eq!(response["result"], json!(1));
+        assert_eq!(response["memory_used_bytes"], json!((1 + pages as u64) * 65536), "{}", response);
+    }
+
+    // Growth past the limit is refused and not counted
+    let response: Value = grow(8).await?.json().await?;
+    assert_eq!(response["result"], json!(-1), "{}", response);
+    assert_eq!(response["memory_used_bytes"], json!(65536), "{}", response);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn running_executions_are_listed_and_cancelled() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-cancel-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    // Fuel is only sampled as of a function call
+    let wat = r#"(module (func $tick) (func (export "spin") (loop (call $tick) (br 0))))"#;
+    std::fs::write(module_dir.join("loop.wasm"), wat::parse_str(wat)?)?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_FUEL_LIMIT", u64::MAX.to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let spinning = tokio::spawn(
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .json(&json!({ "module_path": "loop.wasm", "function_name": "spin", "params": [], "timeout_seconds": 30, "tenant_id": "acme" }))
+            .send(),
+    );
+    // Listed once it has a slot, burning fuel
+    let execution = eventually("the execution to be listed", Duration::from_secs(10), || {
+        let http = &http;
+        async move {
+            let listed: Value = http.get(format!("{}/admin/executions", RUNTIME_URL)).send().await?.json().await?;
+            let execution = listed["executions"].get(0).cloned();
+            Ok(execution.filter(|execution| execution["fuel_consumed"].as_u64() > Some(0)))
+        }
+    })
+    .await?;
+    assert_eq!(execution["module_path"], "loop.wasm", "{}", execution);
+    assert_eq!(execution["tenant_id"], "acme", "{}", execution);
+    assert!(execution["tier"].is_string(), "{}", execution);
+    let execution_id = execution["execution_id"].as_str().unwrap_or_default().to_string();
+
+    // Only for its own tenant
+    let cancel = |tenant_id: &str| {
+        http.post(format!("{}/executions/{}/cancel", RUNTIME_URL, execution_id))
+            .header("X-Tenant-ID", tenant_id)
+            .send()
+    };
+    assert_eq!(cancel("globex").await?.status(), 404);
+    let started = std::time::Instant::now();
+    assert_eq!(cancel("acme").await?.status(), 202);
+    let response: Value = spinning.await??.json().await?;
+    assert!(started.elapsed() < Duration::from_secs(2), "took {:?}", started.elapsed());
+    assert_eq!(response["success"], false, "{}", response);
+    assert_eq!(response["error"]["code"], "CANCELLED", "{}", response);
+    assert_eq!(response["error"]["message"], "Execution cancelled", "{}", response);
+    assert_eq!(response["execution_id"], execution_id.as_str(), "{}", response);
+
+    let listed: Value = http.get(format!("{}/admin/executions", RUNTIME_URL)).send().await?.json().await?;
+    assert_eq!(listed["count"], 0, "{}", listed);
+    assert_eq!(cancel("acme").await?.status(), 404);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "runs the runtime service on port 8080"]
+async fn executions_queue_per_tenant_for_a_free_slot() -> Result<(), Error> {
+    let module_dir = std::env::temp_dir().join(format!("crm-it-queue-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::write(module_dir.join("loop.wasm"), wat::parse_str(r#"(module (func (export "spin") (loop (br 0))))"#)?)?;
+
+    // One slot, shared by every tier, and one queued execution per tenant
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("RUNTIME_FUEL_LIMIT", u64::MAX.to_string()),
+            ("RUNTIME_MAX_INSTANCES", "1".to_string()),
+            ("RUNTIME_POOL_ENTERPRISE_PERCENT", "0".to_string()),
+            ("RUNTIME_POOL_PRO_PERCENT", "0".to_string()),
+            ("RUNTIME_QUEUE_DEPTH_PER_TENANT", "1".to_string()),
+            ("RUNTIME_QUEUE_TIMEOUT_MS", "10000".to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let spin = |tenant_id: &str| {
+        http.post(format!("{}/execute", RUNTIME_URL))
+            .json(&json!({ "module_path": "loop.wasm", "function_name": "spin", "params": [], "timeout_seconds": 1, "tenant_id": tenant_id }))
+            .send()
+    };
+    let running = tokio::spawn(spin("acme"));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let queued = tokio::spawn(spin("acme"));
+    let other_tenant = tokio::spawn(spin("globex"));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    // Only acme's queue is full
+    let rejected = spin("acme").await?;
+    assert_eq!(rejected.status(), 429);
+    let rejected: Value = rejected.json().await?;
+    assert_eq!(rejected["error"]["code"], "CAPACITY_EXCEEDED", "{}", rejected);
+    assert_eq!(rejected["error"]["retryable"], true, "{}", rejected);
+    assert!(rejected["error"]["message"].as_str().unwrap_or_default().contains("queued"), "{}", rejected);
+
+    // The queued executions run once the slot is free, one after the other
+    for execution in [running, queued, other_tenant] {
+        let response = execution.await??;
+        assert_eq!(response.status(), 504);
+        let response: Value = response.json().await?;
+        assert_eq!(response["error"]["message"], "Execution interrupted: deadline exceeded", "{}", response);
+    }
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "requires Docker and runs the runtime service on port 8080"]
+async fn asynchronous_executions_run_as_jobs() -> Result<(), Error> {
+    let infra = Infra::start().await?;
+    let module_dir = std::env::temp_dir().join(format!("crm-it-jobs-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::copy(fixture("add.wasm"), module_dir.join("add.wasm"))?;
+    std::fs::write(module_dir.join("loop.wasm"), wat::parse_str(r#"(module (func (export "spin") (loop (br 0))))"#)?)?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("REDIS_URL", infra.redis_url.clone()),
+            ("RUNTIME_FUEL_LIMIT", u64::MAX.to_string()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let submit = |body: Value| {
+        let http = &http;
+        async move {
+            let response = http.post(format!("{}/execute?async=true", RUNTIME_URL)).json(&body).send().await?;
+            assert_eq!(response.status(), 202);
+            let job: Value = response.json().await?;
+            assert_eq!(job["status"], "queued", "{}", job);
+            Ok::<_, Error>(job["job_id"].as_str().unwrap_or_default().to_string())
+        }
+    };
+    let job = |job_id: &str| {
+        let http = &http;
+        let url = format!("{}/jobs/{}", RUNTIME_URL, job_id);
+        async move { Ok::<Value, Error>(http.get(url).send().await?.json().await?) }
+    };
+    let job_reaching = |job_id: String, status: &'static str| {
+        let job = &job;
+        eventually("the job to reach its status", Duration::from_secs(30), move || {
+            let job_id = job_id.clone();
+            async move {
+                let job = job(&job_id).await?;
+                Ok((job["status"] == status).then_some(job))
+            }
+        })
+    };
+
+    let added = submit(json!({ "module_path": "add.wasm", "function_name": "example", "params": [2, 3] })).await?;
+    let finished = job_reaching(added.clone(), "succeeded").await?;
+    assert_eq!(finished["response"]["result"], json!(5), "{}", finished);
+    let too_late = http.delete(format!("{}/jobs/{}", RUNTIME_URL, added)).send().await?;
+    assert_eq!(too_late.status(), 409);
+
+    let spinning =
+        submit(json!({ "module_path": "loop.wasm", "function_name": "spin", "params": [], "timeout_seconds": 60 }))
+            .await?;
+    job_reaching(spinning.clone(), "running").await?;
+    let cancelled = http.delete(format!("{}/jobs/{}", RUNTIME_URL, spinning)).send().await?;
+    assert_eq!(cancelled.status(), 200);
+    assert_eq!(job(&spinning).await?["status"], "cancelled");
+
+    let missing = http.get(format!("{}/jobs/{}", RUNTIME_URL, uuid::Uuid::new_v4())).send().await?;
+    assert_eq!(missing.status(), 404);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "requires Docker and runs the runtime service on port 8080"]
+async fn schedules_run_plugins_on_their_cron() -> Result<(), Error> {
+    let infra = Infra::start().await?;
+    let module_dir = std::env::temp_dir().join(format!("crm-it-schedules-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&module_dir)?;
+    std::fs::copy(fixture("add.wasm"), module_dir.join("add.wasm"))?;
+
+    let mut service = ServiceProcess::spawn(
+        "extension-runtime-service",
+        &[
+            ("WASM_MODULE_DIR", module_dir.display().to_string()),
+            ("REDIS_URL", infra.redis_url.clone()),
+        ],
+    )?;
+
+    let http = reqwest::Client::new();
+    eventually("the runtime to serve /metrics", Duration::from_secs(60), || {
+        let http = &http;
+        async move {
+            let ready = http.get(format!("{}/metrics", RUNTIME_URL)).send().await.is_ok();
+            Ok(ready.then_some(()))
+        }
+    })
+    .await?;
+    service.assert_running()?;
+
+    let execute = json!({ "module_path": "add.wasm", "function_name": "example", "params": [2, 3] });
+    let create = |tenant_id: &str, body: Value| {
+        http.post(format!("{}/schedules", RUNTIME_URL)).header("X-Tenant-ID", tenant_id).json(&body).send()
+    };
+    let invalid = create("acme", json!({ "cron": "every minute", "execute": execute })).await?;
+    assert_eq!(invalid.status(), 400);
+
+    let created = create("acme", json!({ "cron": "* * * * * *", "execute": execute })).await?;
+    assert_eq!(created.status(), 201);
+    let schedule: Value = created.json().await?;
+    let url = format!("{}/schedules/{}", RUNTIME_URL, schedule["schedule_id"].as_str().unwrap_or_default());
+
+    // Runs every second, for the tenant that owns it
+    let ran = eventually("the schedule to run", Duration::from_secs(30), || {
+        let (http, url) = (&http, &url);
+        async move {
+            let schedule: Value = http.get(url).header("X-Tenant-ID", "acme").send().await?.json().await?;
+            Ok(schedule["last_run"].is_object().then_some(schedule))
+        }
+    })
+    .await?;
+    assert_eq!(ran["last_run"]["success"], true, "{}", ran);
+    assert!(ran["next_run_at"].is_i64(), "{}", ran);
+    let other_tenant = http.get(&url).header("X-Tenant-ID", "globex").send().await?;
+    assert_eq!(other_tenant.status(), 404);
+
+    let paused = http
+        .put(&url)
+        .header("X-Tenant-ID", "acme")
+        .json(&json!({ "cron": "* * * * * *", "execute": execute, "active": false }))
+        .send()
+        .await?;
+    assert_eq!(paused.status(), 200);
+    let paused: Value = paused.json().await?;
+    assert_eq!(paused["next_run_at"], Value::Null, "{}", paused);
+
+    let deleted = http.delete(&url).header("X-Tenant-ID", "acme").send().await?;
+    assert_eq!(deleted.status(), 200);
+    let listed: Value = http.get(format!("{}/schedules", RUNTIME_URL)).header("X-Tenant-ID", "acme").send().await?.json().await?;
+    assert_eq!(listed["schedules"], json!([]), "{}", listed);
+
+    std::fs::remove_dir_all(&module_dir)?;
+    Ok(())
+}
+
+/// A plugin with the given exports, each making its JSON requests to the
+/// host `function`, e.g. `http_fetch`, in turn. Responses are printed and
+/// the last result returned.
+fn json_call_plugin(function: &str, exports: &[(&str, Vec<Value>)]) -> String {
+    let (mut data, mut funcs) = (String::new(), String::new());
+    let mut offset = 0;
+    for (name, requests) in exports {
+        let mut calls = String::new();
+        for request in requests {
+            let request = request.to_string();
+            data += &format!("  (data (i32.const {}) \"{}\")\n", offset, request.replace('"', "\\\""));
+            calls += &format!("    (local.set $n (call $call (i32.const {}) (i32.const {})))\n", offset, request.len());
+            offset += 512;
+        }
+        funcs += &format!("  (func (export \"{}\") (result i32)\n    (local $n i32)\n{}    (local.get $n))\n", name, calls);
+    }
+    assert!(offset <= 16384, "requests overlap the response buffer");
+    format!(
+        r#"(module
+  (import "crm" "{function}" (func $host (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+{data}  (func $call (param $ptr i32) (param $len i32) (result i32)
+    (local $n i32)
+    (local.set $n (call $host (local.get $ptr) (local.get $len) (i32.const 16384) (i32.const 16384)))
+    (if (i32.gt_s (local.get $n) (i32.const 0))
+      (then
+        (i32.store (i32.const 32768) (i32.const 16384))
+        (i32.store (i32.const 32772) (local.get $n))
+        (drop (call $fd_write (i32.const 1) (i32.const 32768) (i32.const 1) (i32.const 32776)))))
+    (local.get $n))
+{funcs})"#
+    )
+}
+
+/// A plugin with the json calling convention that passes its input on to
+/// the plugin `next` and returns its result, or the negative code
+/// `call_plugin` returned, as a JSON number.
+fn relay_plugin(next: &str) -> String {
+    format!(
+        r#"(module
+  (import "crm" "call_plugin" (func $call_plugin (param i32 i32 i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "{next}")
+  (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+  (func (export "run") (param $ptr i32) (param $len i32) (result i32 i32)
+    (local $n i32)
+    (local.set $n (call $call_plugin (i32.const 0) (i32.const {len}) (local.get $ptr) (local.get $len) (i32.const 8192) (i32.const 8192)))
+    (if (result i32 i32) (i32.ge_s (local.get $n) (i32.const 0))
+      (then (i32.const 8192) (local.get $n))
+      (else
+        (i32.store8 (i32.const 512) (i32.const 45))
+        (i32.store8 (i32.const 513) (i32.sub (i32.const 48) (local.get $n)))
+        (i32.const 512) (i32.const 2)))))"#,
+        len = next.len()
+    )
+}
+
+/// Answers HTTP requests on `listener` with `respond`, which gets the path
+/// and request head, see [`header`], and returns the status, extra header
+/// lines and body.
+fn serve_http<F>(listener: TcpListener, respond: F)
+where
+    F: Fn(&str, &str) -> (&'static str, String, Vec<u8>) + Send + Sync + 'static,
+{
+    let respond = Arc::new(respond);
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let respond = respond.clone();
+            tokio::spawn(async move {
+                let mut request = vec![0; 8192];
+                let Ok(read) = socket.read(&mut request).await else { return };
+                let request = String::from_utf8_lossy(&request[..read]).to_string();
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+                let request_head = request.split("\r\n\r\n").next().unwrap_or_default();
+
+                let (status, headers, body) = respond(path, request_head);
+                let head = format!(
+                    "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    headers,
+                    body.len()
+                );
+                let _ = socket.write_all(head.as_bytes()).await;
+                let _ = socket.write_all(&body).await;
+            });
+        }
+    });
+}
+
+/// The value of header `name` in a request head, or "".
+fn header<'a>(head: &'a str, name: &str) -> &'a str {
+    head.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+        .unwrap_or_default()
+}
@@ -0,0 +1,58 @@
+-- Billing tables for the metering service. Applied at startup; every
+-- statement is idempotent.
+
+CREATE TABLE IF NOT EXISTS billing_plans (
+    plan_id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    monthly_price_cents BIGINT NOT NULL,
+    -- Allowances for a full month; prorated for partial periods
+    included_events BIGINT NOT NULL,
+    included_compute_units BIGINT NOT NULL,
+    event_overage_cents_per_thousand BIGINT NOT NULL DEFAULT 0,
+    compute_unit_overage_cents BIGINT NOT NULL DEFAULT 0,
+    -- Hard caps for a full month; NULL bills overage instead
+    event_limit BIGINT,
+    compute_unit_limit BIGINT
+);
+
+-- A tenant is on one plan at a time; a plan change closes the open row
+CREATE TABLE IF NOT EXISTS tenant_subscriptions (
+    tenant_id TEXT NOT NULL,
+    plan_id TEXT NOT NULL REFERENCES billing_plans (plan_id),
+    starts_at TIMESTAMPTZ NOT NULL,
+    ends_at TIMESTAMPTZ,
+    PRIMARY KEY (tenant_id, starts_at)
+);
+
+CREATE TABLE IF NOT EXISTS usage_daily (
+    tenant_id TEXT NOT NULL,
+    day DATE NOT NULL,
+    events BIGINT NOT NULL DEFAULT 0,
+    plugin_invocations BIGINT NOT NULL DEFAULT 0,
+    fuel_consumed BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (tenant_id, day)
+);
+
+-- The plugin share of usage_daily, per module, from the runtime's audit stream
+CREATE TABLE IF NOT EXISTS plugin_usage_daily (
+    tenant_id TEXT NOT NULL,
+    day DATE NOT NULL,
+    module_path TEXT NOT NULL,
+    invocations BIGINT NOT NULL DEFAULT 0,
+    failures BIGINT NOT NULL DEFAULT 0,
+    fuel_consumed BIGINT NOT NULL DEFAULT 0,
+    execution_time_ms BIGINT NOT NULL DEFAULT 0,
+    bytes_in BIGINT NOT NULL DEFAULT 0,
+    bytes_out BIGINT NOT NULL DEFAULT 0,
+    -- Highest of the day's executions
+    memory_peak_bytes BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (tenant_id, day, module_path)
+);
+
+INSERT INTO billing_plans (plan_id, name, monthly_price_cents, included_events, included_compute_units,
+                           event_overage_cents_per_thousand, compute_unit_overage_cents, event_limit, compute_unit_limit)
+VALUES
+    ('free', 'Free', 0, 100000, 1000, 0, 0, 100000, 1000),
+    ('starter', 'Starter', 4900, 1000000, 20000, 5, 1, NULL, NULL),
+    ('pro', 'Pro', 29900, 10000000, 250000, 3, 1, NULL, NULL)
+ON CONFLICT (plan_id) DO NOTHING;
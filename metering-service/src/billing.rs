@@ -0,0 +1,240 @@
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Plan {
+    pub plan_id: String,
+    pub name: String,
+    pub monthly_price_cents: i64,
+    pub included_events: i64,
+    pub included_compute_units: i64,
+    pub event_overage_cents_per_thousand: i64,
+    pub compute_unit_overage_cents: i64,
+    pub event_limit: Option<i64>,
+    pub compute_unit_limit: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Subscription {
+    pub plan_id: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DailyUsage {
+    pub day: NaiveDate,
+    pub events: i64,
+    pub plugin_invocations: i64,
+    pub fuel_consumed: i64,
+}
+
+/// A tenant's usage of one plugin over a period.
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginUsage {
+    pub module_path: String,
+    pub invocations: i64,
+    pub failures: i64,
+    pub fuel_consumed: i64,
+    pub execution_time_ms: i64,
+    pub bytes_in: i64,
+    pub bytes_out: i64,
+    /// Highest of any one execution.
+    pub memory_peak_bytes: i64,
+}
+
+/// A calendar month in UTC, `[start, end)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Period {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl Period {
+    /// Parses `YYYY-MM`.
+    pub fn parse(s: &str) -> Option<Period> {
+        let (year, month) = s.split_once('-')?;
+        Period::month(year.parse().ok()?, month.parse().ok()?)
+    }
+
+    pub fn containing(day: NaiveDate) -> Period {
+        Period::month(day.year(), day.month()).expect("a real date has a valid month")
+    }
+
+    fn month(year: i32, month: u32) -> Option<Period> {
+        let start = NaiveDate::from_ymd_opt(year, month, 1)?;
+        let end = if month == 12 {
+            NaiveDate::from_ymd_opt(year + 1, 1, 1)?
+        } else {
+            NaiveDate::from_ymd_opt(year, month + 1, 1)?
+        };
+        Some(Period { start, end })
+    }
+
+    pub fn days(&self) -> i64 {
+        (self.end - self.start).num_days()
+    }
+
+    pub fn label(&self) -> String {
+        self.start.format("%Y-%m").to_string()
+    }
+}
+
+/// The part of a period spent on one plan.
+#[derive(Debug, Clone, Serialize)]
+pub struct Segment {
+    pub plan_id: String,
+    pub from: NaiveDate,
+    /// Exclusive.
+    pub to: NaiveDate,
+    /// Share of the period, for proration.
+    pub fraction: f64,
+    pub base_cents: i64,
+    pub events: i64,
+    pub included_events: i64,
+    pub event_overage_cents: i64,
+    pub plugin_invocations: i64,
+    pub compute_units: i64,
+    pub included_compute_units: i64,
+    pub compute_overage_cents: i64,
+    pub total_cents: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Limits {
+    pub plan_id: String,
+    pub events: i64,
+    pub event_limit: Option<i64>,
+    pub compute_units: i64,
+    pub compute_unit_limit: Option<i64>,
+    pub exceeded: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PeriodSummary {
+    pub tenant_id: String,
+    pub period: String,
+    pub segments: Vec<Segment>,
+    pub total_cents: i64,
+    /// Usage against the hard caps of the plan the period ends on.
+    pub limits: Option<Limits>,
+}
+
+/// Splits the period into plan segments and prices each one. Days no
+/// subscription covers are billed on `default_plan`.
+pub fn summarize(
+    tenant_id: &str,
+    period: Period,
+    subscriptions: &[Subscription],
+    usage: &[DailyUsage],
+    plans: &[Plan],
+    default_plan: &str,
+    fuel_per_compute_unit: u64,
+) -> PeriodSummary {
+    let segments: Vec<Segment> = plan_spans(period, subscriptions, default_plan)
+        .into_iter()
+        .filter_map(|(plan_id, from, to)| {
+            let plan = plans.iter().find(|p| p.plan_id == plan_id)?;
+            Some(price(plan, period, from, to, usage, fuel_per_compute_unit))
+        })
+        .collect();
+
+    let limits = segments.last().and_then(|last| {
+        let plan = plans.iter().find(|p| p.plan_id == last.plan_id)?;
+        let event_limit = plan.event_limit.map(|limit| prorate(limit, last.fraction));
+        let compute_unit_limit = plan.compute_unit_limit.map(|limit| prorate(limit, last.fraction));
+        Some(Limits {
+            plan_id: plan.plan_id.clone(),
+            events: last.events,
+            event_limit,
+            compute_units: last.compute_units,
+            compute_unit_limit,
+            exceeded: event_limit.is_some_and(|limit| last.events > limit)
+                || compute_unit_limit.is_some_and(|limit| last.compute_units > limit),
+        })
+    });
+
+    PeriodSummary {
+        tenant_id: tenant_id.to_string(),
+        period: period.label(),
+        total_cents: segments.iter().map(|s| s.total_cents).sum(),
+        segments,
+        limits,
+    }
+}
+
+/// `(plan_id, from, to)` covering the whole period in order. A plan that
+/// starts mid-day applies from that day.
+fn plan_spans(period: Period, subscriptions: &[Subscription], default_plan: &str) -> Vec<(String, NaiveDate, NaiveDate)> {
+    let mut subscriptions: Vec<&Subscription> = subscriptions.iter().collect();
+    subscriptions.sort_by_key(|s| s.starts_at);
+
+    let mut spans = Vec::new();
+    let mut cursor = period.start;
+    for (i, subscription) in subscriptions.iter().enumerate() {
+        let from = subscription.starts_at.date_naive().max(period.start);
+        // A later subscription takes over from its start day
+        let next_start = subscriptions.get(i + 1).map(|next| next.starts_at.date_naive());
+        let to = [subscription.ends_at.map(|e| e.date_naive()), next_start, Some(period.end)]
+            .into_iter()
+            .flatten()
+            .min()
+            .unwrap_or(period.end);
+
+        if from > cursor {
+            let gap_end = from.min(period.end);
+            spans.push((default_plan.to_string(), cursor, gap_end));
+            cursor = gap_end;
+        }
+        let from = from.max(cursor);
+        if from < to {
+            spans.push((subscription.plan_id.clone(), from, to));
+            cursor = to;
+        }
+    }
+    if cursor < period.end {
+        spans.push((default_plan.to_string(), cursor, period.end));
+    }
+    spans
+}
+
+fn price(plan: &Plan, period: Period, from: NaiveDate, to: NaiveDate, usage: &[DailyUsage], fuel_per_compute_unit: u64) -> Segment {
+    let fraction = (to - from).num_days() as f64 / period.days() as f64;
+    let days = usage.iter().filter(|u| u.day >= from && u.day < to);
+    let (events, plugin_invocations, fuel) = days.fold((0i64, 0i64, 0i64), |(e, i, f), u| {
+        (e + u.events, i + u.plugin_invocations, f + u.fuel_consumed)
+    });
+    let compute_units = compute_units(fuel, fuel_per_compute_unit);
+
+    let base_cents = prorate(plan.monthly_price_cents, fraction);
+    let included_events = prorate(plan.included_events, fraction);
+    let included_compute_units = prorate(plan.included_compute_units, fraction);
+    let overage_events = (events - included_events).max(0) as u64;
+    let event_overage_cents = (overage_events * plan.event_overage_cents_per_thousand.max(0) as u64).div_ceil(1000) as i64;
+    let compute_overage_cents = (compute_units - included_compute_units).max(0) * plan.compute_unit_overage_cents;
+
+    Segment {
+        plan_id: plan.plan_id.clone(),
+        from,
+        to,
+        fraction,
+        base_cents,
+        events,
+        included_events,
+        event_overage_cents,
+        plugin_invocations,
+        compute_units,
+        included_compute_units,
+        compute_overage_cents,
+        total_cents: base_cents + event_overage_cents + compute_overage_cents,
+    }
+}
+
+/// Billed compute units for `fuel`, rounded up.
+pub fn compute_units(fuel: i64, fuel_per_compute_unit: u64) -> i64 {
+    (fuel.max(0) as u64).div_ceil(fuel_per_compute_unit) as i64
+}
+
+fn prorate(amount: i64, fraction: f64) -> i64 {
+    (amount as f64 * fraction).round() as i64
+}
@@ -0,0 +1,186 @@
+use crate::config::Config;
+use crate::store::{PluginUsageBuffer, UsageBuffer, UsageStore};
+use chrono::{NaiveDate, Utc};
+use crm_events::CrmEvent;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::{ClientConfig, Message};
+use redis::streams::{StreamId, StreamReadOptions, StreamReadReply};
+use redis::AsyncCommands;
+use std::sync::Arc;
+use tokio::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+/// Counts events on the ingestion topics per tenant and day of receipt.
+///
+/// Offsets are committed only after the counts reach Postgres, so a crash
+/// between the two can count a flush window twice but never drops events.
+pub async fn run_event_collector(config: Config, store: Arc<UsageStore>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let consumer: StreamConsumer = ClientConfig::new()
+        .set("group.id", &config.kafka_group_id)
+        .set("bootstrap.servers", &config.kafka_brokers)
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "latest")
+        .create()?;
+    let topics: Vec<&str> = config.event_topics.iter().map(String::as_str).collect();
+    consumer.subscribe(&topics)?;
+    info!("Metering events from {:?}", topics);
+
+    let mut buffer = UsageBuffer::new();
+    let mut ticker = tokio::time::interval(Duration::from_secs(config.flush_interval_secs));
+    loop {
+        tokio::select! {
+            message = consumer.recv() => {
+                let message = match message {
+                    Ok(message) => message,
+                    Err(e) => {
+                        warn!("Kafka error: {}", e);
+                        continue;
+                    }
+                };
+                record_event(&mut buffer, message.payload(), Utc::now().date_naive());
+            }
+            _ = ticker.tick() => {
+                if flush(&store, &mut buffer, &mut PluginUsageBuffer::new(), "events").await {
+                    if let Err(e) = consumer.commit_consumer_state(CommitMode::Async) {
+                        warn!("Failed to commit metered offsets: {}", e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Sums plugin fuel and invocations from the runtime's audit stream, per
+/// tenant and per tenant and module, acking entries once their counts reach
+/// Postgres.
+pub async fn run_audit_collector(config: Config, store: Arc<UsageStore>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let client = redis::Client::open(config.redis_url.as_str())?;
+    let mut conn = client.get_async_connection().await?;
+
+    let created: redis::RedisResult<()> = conn
+        .xgroup_create_mkstream(&config.audit_stream, &config.audit_group, "$")
+        .await;
+    if let Err(e) = created {
+        // BUSYGROUP: the group exists from a previous run
+        if e.code() != Some("BUSYGROUP") {
+            return Err(e.into());
+        }
+    }
+    info!("Metering plugin usage from stream {}", config.audit_stream);
+
+    // Stable across restarts so entries read but not acked before a crash are
+    // picked up again from this consumer's pending list
+    let consumer_name = std::env::var("HOSTNAME").unwrap_or_else(|_| "metering-service".to_string());
+    let options = StreamReadOptions::default()
+        .group(&config.audit_group, &consumer_name)
+        .count(500)
+        .block(1000);
+    let flush_interval = Duration::from_secs(config.flush_interval_secs);
+
+    let mut buffer = UsageBuffer::new();
+    let mut plugins = PluginUsageBuffer::new();
+    let mut pending_ids: Vec<String> = Vec::new();
+    let mut last_flush = Instant::now();
+    // Replay our pending entries first, then switch to new ones
+    let mut cursor = "0".to_string();
+    loop {
+        let reply: StreamReadReply = match conn.xread_options(&[&config.audit_stream], &[&cursor], &options).await {
+            Ok(reply) => reply,
+            Err(e) => {
+                warn!("Failed to read audit stream: {}", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+
+        let entries: Vec<_> = reply.keys.iter().flat_map(|key| &key.ids).collect();
+        if cursor != ">" {
+            cursor = match entries.last() {
+                Some(last) => last.id.clone(),
+                None => ">".to_string(),
+            };
+        }
+
+        for entry in entries {
+            pending_ids.push(entry.id.clone());
+            record_audit_entry(&mut buffer, &mut plugins, entry, Utc::now().date_naive());
+        }
+
+        if last_flush.elapsed() >= flush_interval {
+            last_flush = Instant::now();
+            if flush(&store, &mut buffer, &mut plugins, "plugin usage").await && !pending_ids.is_empty() {
+                let acked: redis::RedisResult<i64> = conn.xack(&config.audit_stream, &config.audit_group, &pending_ids).await;
+                match acked {
+                    Ok(_) => pending_ids.clear(),
+                    Err(e) => warn!("Failed to ack audit entries: {}", e),
+                }
+            }
+        }
+    }
+}
+
+/// Counts an event on the ingestion topics towards its tenant's `day`.
+pub fn record_event(buffer: &mut UsageBuffer, payload: Option<&[u8]>, day: NaiveDate) {
+    let event: CrmEvent = match payload.map(serde_json::from_slice) {
+        Some(Ok(event)) => event,
+        // The ingestion pipeline drops these too, so they aren't billed
+        _ => return,
+    };
+    buffer.entry((event.tenant_id, day)).or_default().events += 1;
+}
+
+/// Adds an execution from the audit stream to its tenant's `day`, and to
+/// its module's when the entry names one.
+pub fn record_audit_entry(
+    buffer: &mut UsageBuffer,
+    plugins: &mut PluginUsageBuffer,
+    entry: &StreamId,
+    day: NaiveDate,
+) {
+    let tenant_id: String = match entry.get("tenant_id") {
+        Some(tenant_id) => tenant_id,
+        None => {
+            debug!("Audit entry {} without tenant_id", entry.id);
+            return;
+        }
+    };
+    let fuel: i64 = entry.get("fuel_consumed").unwrap_or(0);
+
+    // Entries from runtimes predating the module fields count towards the
+    // tenant only
+    if let Some(module_path) = entry.get::<String>("module_path") {
+        let plugin = plugins.entry((tenant_id.clone(), day, module_path)).or_default();
+        plugin.invocations += 1;
+        if entry.get::<String>("success").as_deref() != Some("1") {
+            plugin.failures += 1;
+        }
+        plugin.fuel_consumed += fuel;
+        plugin.execution_time_ms += entry.get::<i64>("execution_time_ms").unwrap_or(0);
+        plugin.bytes_in += entry.get::<i64>("bytes_in").unwrap_or(0);
+        plugin.bytes_out += entry.get::<i64>("bytes_out").unwrap_or(0);
+        plugin.memory_peak_bytes = plugin.memory_peak_bytes.max(entry.get("memory_peak_bytes").unwrap_or(0));
+    }
+
+    let delta = buffer.entry((tenant_id, day)).or_default();
+    delta.plugin_invocations += 1;
+    delta.fuel_consumed += fuel;
+}
+
+/// Writes and clears the buffers; on failure they are kept for the next
+/// flush.
+async fn flush(store: &UsageStore, buffer: &mut UsageBuffer, plugins: &mut PluginUsageBuffer, what: &str) -> bool {
+    match store.add_usage(buffer, plugins).await {
+        Ok(()) => {
+            if !buffer.is_empty() {
+                debug!("Flushed {} {} counters", buffer.len() + plugins.len(), what);
+            }
+            buffer.clear();
+            plugins.clear();
+            true
+        }
+        Err(e) => {
+            error!("Failed to write {} to Postgres: {}", what, e);
+            false
+        }
+    }
+}
@@ -0,0 +1,7 @@
+//! Usage metering and billing. `main.rs` runs the collectors and serves the
+//! API; it is a library so the tests under `tests/` can drive it directly.
+
+pub mod billing;
+pub mod collect;
+pub mod config;
+pub mod store;
@@ -0,0 +1,314 @@
+use chrono::{DateTime, Utc};
+use crm_audit::{AuditConfig, AuditEvent, AuditTrail};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{error, info};
+use warp::http::StatusCode;
+use warp::Filter;
+
+use metering_service::billing::{self, Period, PeriodSummary};
+use metering_service::collect;
+use metering_service::config::Config;
+use metering_service::store::{self, UsageStore};
+
+#[derive(Debug, Deserialize)]
+struct PlanChange {
+    plan_id: String,
+    /// Unix seconds; defaults to now. Can't precede the current plan's start.
+    effective_at: Option<i64>,
+}
+
+struct AppState {
+    store: Arc<UsageStore>,
+    config: Config,
+    audit: AuditTrail,
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Logs, traces and panic capture
+    let _observability = crm_observability::init("metering-service")?;
+
+    info!("Starting Metering Service");
+
+    // Load configuration
+    let config = Config::from_env()?;
+    info!("Configuration: {}", crm_config::redacted(&config));
+
+    let store = Arc::new(UsageStore::connect(&config.database_url).await?);
+    // Plan changes are recorded on the admin audit trail
+    let audit = AuditTrail::new("metering-service", &AuditConfig::from_env()?)?;
+
+    {
+        let (config, store) = (config.clone(), Arc::clone(&store));
+        tokio::spawn(async move {
+            if let Err(e) = collect::run_event_collector(config, store).await {
+                error!("Event collector stopped: {}", e);
+            }
+        });
+    }
+    {
+        let (config, store) = (config.clone(), Arc::clone(&store));
+        tokio::spawn(async move {
+            if let Err(e) = collect::run_audit_collector(config, store).await {
+                error!("Audit collector stopped: {}", e);
+            }
+        });
+    }
+
+    let port = config.port;
+    let state = Arc::new(AppState { store, config, audit });
+    let with_state = warp::any().map(move || Arc::clone(&state));
+    let auth = warp::header::optional::<String>("authorization");
+
+    let plans = warp::get()
+        .and(warp::path!("plans"))
+        .and(auth)
+        .and(with_state.clone())
+        .and_then(handle_plans);
+    let usage = warp::get()
+        .and(warp::path!("tenants" / String / "usage"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(auth)
+        .and(with_state.clone())
+        .and_then(handle_usage);
+    let plugin_usage = warp::get()
+        .and(warp::path!("tenants" / String / "plugins" / "usage"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(auth)
+        .and(with_state.clone())
+        .and_then(handle_plugin_usage);
+    let limits = warp::get()
+        .and(warp::path!("tenants" / String / "limits"))
+        .and(auth)
+        .and(with_state.clone())
+        .and_then(handle_limits);
+    let change_plan = warp::put()
+        .and(warp::path!("tenants" / String / "plan"))
+        .and(auth)
+        .and(warp::body::content_length_limit(4 * 1024))
+        .and(warp::body::json())
+        .and(crm_audit::actor())
+        .and(with_state.clone())
+        .and_then(handle_change_plan);
+    let period_usage = warp::get()
+        .and(warp::path!("periods" / String / "usage"))
+        .and(auth)
+        .and(with_state)
+        .and_then(handle_period_usage);
+    let health = warp::path("health").map(|| "OK");
+
+    let routes = health
+        .or(crm_observability::metrics_route())
+        .or(plans)
+        .or(usage)
+        .or(plugin_usage)
+        .or(limits)
+        .or(change_plan)
+        .or(period_usage)
+        .with(crm_observability::http_metrics());
+
+    info!("Billing API listening on port {}", port);
+    warp::serve(routes).run(([0, 0, 0, 0], port)).await;
+
+    Ok(())
+}
+
+async fn handle_plans(auth: Option<String>, state: Arc<AppState>) -> Result<Reply, warp::Rejection> {
+    if let Some(denied) = check_auth(auth.as_deref(), &state) {
+        return Ok(denied);
+    }
+    match state.store.plans().await {
+        Ok(plans) => Ok(reply(StatusCode::OK, json!({ "plans": plans }))),
+        Err(e) => Ok(unavailable("load plans", e)),
+    }
+}
+
+/// `GET /tenants/{id}/usage?period=YYYY-MM`, the current month by default.
+async fn handle_usage(
+    tenant_id: String,
+    query: HashMap<String, String>,
+    auth: Option<String>,
+    state: Arc<AppState>,
+) -> Result<Reply, warp::Rejection> {
+    if let Some(denied) = check_auth(auth.as_deref(), &state) {
+        return Ok(denied);
+    }
+    let period = match query.get("period") {
+        Some(period) => match Period::parse(period) {
+            Some(period) => period,
+            None => return Ok(reply(StatusCode::BAD_REQUEST, json!({ "error": "period must be YYYY-MM" }))),
+        },
+        None => Period::containing(Utc::now().date_naive()),
+    };
+
+    match summarize(&state, &tenant_id, period).await {
+        Ok(summary) => Ok(reply(StatusCode::OK, json!(summary))),
+        Err(e) => Ok(unavailable("summarize usage", e)),
+    }
+}
+
+/// `GET /tenants/{id}/plugins/usage?period=YYYY-MM`: usage per module, the
+/// current month by default.
+async fn handle_plugin_usage(
+    tenant_id: String,
+    query: HashMap<String, String>,
+    auth: Option<String>,
+    state: Arc<AppState>,
+) -> Result<Reply, warp::Rejection> {
+    if let Some(denied) = check_auth(auth.as_deref(), &state) {
+        return Ok(denied);
+    }
+    let period = match query.get("period") {
+        Some(period) => match Period::parse(period) {
+            Some(period) => period,
+            None => return Ok(reply(StatusCode::BAD_REQUEST, json!({ "error": "period must be YYYY-MM" }))),
+        },
+        None => Period::containing(Utc::now().date_naive()),
+    };
+
+    let plugins = match state.store.plugin_usage(&tenant_id, period).await {
+        Ok(plugins) => plugins,
+        Err(e) => return Ok(unavailable("load plugin usage", e)),
+    };
+    let plugins: Vec<_> = plugins
+        .into_iter()
+        .map(|usage| {
+            let compute_units = billing::compute_units(usage.fuel_consumed, state.config.fuel_per_compute_unit);
+            let mut usage = json!(usage);
+            usage["compute_units"] = json!(compute_units);
+            usage
+        })
+        .collect();
+    Ok(reply(StatusCode::OK, json!({ "tenant_id": tenant_id, "period": period.label(), "plugins": plugins })))
+}
+
+/// `GET /tenants/{id}/limits`: this month's usage against the plan's caps.
+async fn handle_limits(tenant_id: String, auth: Option<String>, state: Arc<AppState>) -> Result<Reply, warp::Rejection> {
+    if let Some(denied) = check_auth(auth.as_deref(), &state) {
+        return Ok(denied);
+    }
+    let period = Period::containing(Utc::now().date_naive());
+    match summarize(&state, &tenant_id, period).await {
+        Ok(summary) => Ok(reply(StatusCode::OK, json!({ "tenant_id": tenant_id, "period": summary.period, "limits": summary.limits }))),
+        Err(e) => Ok(unavailable("check limits", e)),
+    }
+}
+
+async fn handle_change_plan(
+    tenant_id: String,
+    auth: Option<String>,
+    change: PlanChange,
+    actor: String,
+    state: Arc<AppState>,
+) -> Result<Reply, warp::Rejection> {
+    if let Some(denied) = check_auth(auth.as_deref(), &state) {
+        return Ok(denied);
+    }
+    let effective_at = match change.effective_at {
+        Some(secs) => match DateTime::from_timestamp(secs, 0) {
+            Some(at) => at,
+            None => return Ok(reply(StatusCode::BAD_REQUEST, json!({ "error": "invalid effective_at" }))),
+        },
+        None => Utc::now(),
+    };
+
+    match state.store.plans().await {
+        Ok(plans) if plans.iter().any(|p| p.plan_id == change.plan_id) => {}
+        Ok(_) => return Ok(reply(StatusCode::BAD_REQUEST, json!({ "error": "unknown plan" }))),
+        Err(e) => return Ok(unavailable("load plans", e)),
+    }
+    match state.store.current_subscription(&tenant_id).await {
+        // Rewriting history would change already-invoiced periods
+        Ok(Some(current)) if current.starts_at >= effective_at => {
+            return Ok(reply(
+                StatusCode::CONFLICT,
+                json!({ "error": "effective_at must be after the current plan's start" }),
+            ));
+        }
+        Ok(_) => {}
+        Err(e) => return Ok(unavailable("load subscription", e)),
+    }
+
+    match state.store.change_plan(&tenant_id, &change.plan_id, effective_at).await {
+        Ok(()) => {
+            info!("Tenant {} moved to plan {} at {}", tenant_id, change.plan_id, effective_at);
+            state
+                .audit
+                .record(
+                    AuditEvent::new(actor, "tenant.plan_changed", &tenant_id)
+                        .tenant(&tenant_id)
+                        .details(json!({ "plan_id": change.plan_id, "effective_at": effective_at.timestamp() })),
+                )
+                .await;
+            Ok(reply(
+                StatusCode::OK,
+                json!({ "tenant_id": tenant_id, "plan_id": change.plan_id, "effective_at": effective_at.timestamp() }),
+            ))
+        }
+        Err(e) => Ok(unavailable("change plan", e)),
+    }
+}
+
+/// `GET /periods/{YYYY-MM}/usage`: every tenant's summary, for invoicing.
+async fn handle_period_usage(period: String, auth: Option<String>, state: Arc<AppState>) -> Result<Reply, warp::Rejection> {
+    if let Some(denied) = check_auth(auth.as_deref(), &state) {
+        return Ok(denied);
+    }
+    let period = match Period::parse(&period) {
+        Some(period) => period,
+        None => return Ok(reply(StatusCode::BAD_REQUEST, json!({ "error": "period must be YYYY-MM" }))),
+    };
+
+    let tenants = match state.store.tenants(period).await {
+        Ok(tenants) => tenants,
+        Err(e) => return Ok(unavailable("list tenants", e)),
+    };
+    let mut summaries = Vec::with_capacity(tenants.len());
+    for tenant_id in &tenants {
+        match summarize(&state, tenant_id, period).await {
+            Ok(summary) => summaries.push(summary),
+            Err(e) => return Ok(unavailable("summarize usage", e)),
+        }
+    }
+
+    Ok(reply(StatusCode::OK, json!({ "period": period.label(), "tenants": summaries })))
+}
+
+async fn summarize(state: &AppState, tenant_id: &str, period: Period) -> Result<PeriodSummary, store::StoreError> {
+    let plans = state.store.plans().await?;
+    let subscriptions = state.store.subscriptions(tenant_id, period).await?;
+    let usage = state.store.usage(tenant_id, period).await?;
+
+    Ok(billing::summarize(
+        tenant_id,
+        period,
+        &subscriptions,
+        &usage,
+        &plans,
+        &state.config.default_plan,
+        state.config.fuel_per_compute_unit,
+    ))
+}
+
+fn check_auth(header: Option<&str>, state: &AppState) -> Option<Reply> {
+    let token = header.and_then(|h| h.strip_prefix("Bearer "));
+    if token == Some(state.config.api_token.as_str()) {
+        None
+    } else {
+        Some(reply(StatusCode::UNAUTHORIZED, json!({ "error": "unauthorized" })))
+    }
+}
+
+fn unavailable(what: &str, e: store::StoreError) -> Reply {
+    error!("Failed to {}: {}", what, e);
+    reply(StatusCode::SERVICE_UNAVAILABLE, json!({ "error": format!("could not {}", what) }))
+}
+
+fn reply(status: StatusCode, body: serde_json::Value) -> Reply {
+    warp::reply::with_status(warp::reply::json(&body), status)
+}
@@ -0,0 +1,287 @@
+use crate::billing::{DailyUsage, Period, Plan, PluginUsage, Subscription};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::collections::HashMap;
+use tokio_postgres::{Client, NoTls, Row};
+use tracing::error;
+
+pub type StoreError = Box<dyn std::error::Error + Send + Sync>;
+
+const SCHEMA: &str = include_str!("../schema.sql");
+
+/// Counts gathered since the last flush, per tenant and day.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UsageDelta {
+    pub events: i64,
+    pub plugin_invocations: i64,
+    pub fuel_consumed: i64,
+}
+
+pub type UsageBuffer = HashMap<(String, NaiveDate), UsageDelta>;
+
+/// Plugin usage gathered since the last flush, per tenant, day and module.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PluginUsageDelta {
+    pub invocations: i64,
+    pub failures: i64,
+    pub fuel_consumed: i64,
+    pub execution_time_ms: i64,
+    pub bytes_in: i64,
+    pub bytes_out: i64,
+    pub memory_peak_bytes: i64,
+}
+
+pub type PluginUsageBuffer = HashMap<(String, NaiveDate, String), PluginUsageDelta>;
+
+pub struct UsageStore {
+    client: Client,
+}
+
+impl UsageStore {
+    /// Connects and applies `schema.sql`.
+    pub async fn connect(database_url: &str) -> Result<Self, tokio_postgres::Error> {
+        let (client, connection) = tokio_postgres::connect(database_url, NoTls).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                error!("Postgres connection closed: {}", e);
+            }
+        });
+
+        // Periods and plan changes are compared as UTC dates
+        client.batch_execute("SET TIME ZONE 'UTC'").await?;
+        client.batch_execute(SCHEMA).await?;
+        Ok(UsageStore { client })
+    }
+
+    /// Adds the buffered counts in one statement, so a flush applies fully or
+    /// not at all.
+    pub async fn add_usage(&self, buffer: &UsageBuffer, plugins: &PluginUsageBuffer) -> Result<(), StoreError> {
+        if buffer.is_empty() && plugins.is_empty() {
+            return Ok(());
+        }
+
+        let mut tenants = Vec::with_capacity(buffer.len());
+        let mut days = Vec::with_capacity(buffer.len());
+        let mut events = Vec::with_capacity(buffer.len());
+        let mut invocations = Vec::with_capacity(buffer.len());
+        let mut fuel = Vec::with_capacity(buffer.len());
+        for ((tenant_id, day), delta) in buffer {
+            tenants.push(tenant_id.as_str());
+            days.push(*day);
+            events.push(delta.events);
+            invocations.push(delta.plugin_invocations);
+            fuel.push(delta.fuel_consumed);
+        }
+        let mut plugin_tenants = Vec::with_capacity(plugins.len());
+        let mut plugin_days = Vec::with_capacity(plugins.len());
+        let mut modules = Vec::with_capacity(plugins.len());
+        let mut plugin_invocations = Vec::with_capacity(plugins.len());
+        let mut failures = Vec::with_capacity(plugins.len());
+        let mut plugin_fuel = Vec::with_capacity(plugins.len());
+        let mut execution_time = Vec::with_capacity(plugins.len());
+        let mut bytes_in = Vec::with_capacity(plugins.len());
+        let mut bytes_out = Vec::with_capacity(plugins.len());
+        let mut memory_peak = Vec::with_capacity(plugins.len());
+        for ((tenant_id, day, module_path), delta) in plugins {
+            plugin_tenants.push(tenant_id.as_str());
+            plugin_days.push(*day);
+            modules.push(module_path.as_str());
+            plugin_invocations.push(delta.invocations);
+            failures.push(delta.failures);
+            plugin_fuel.push(delta.fuel_consumed);
+            execution_time.push(delta.execution_time_ms);
+            bytes_in.push(delta.bytes_in);
+            bytes_out.push(delta.bytes_out);
+            memory_peak.push(delta.memory_peak_bytes);
+        }
+
+        // The per-module rows ride along in a data-modifying CTE, keeping
+        // the flush a single statement
+        self.client
+            .execute(
+                "WITH daily AS ( \
+                     INSERT INTO usage_daily (tenant_id, day, events, plugin_invocations, fuel_consumed) \
+                     SELECT * FROM UNNEST($1::text[], $2::date[], $3::bigint[], $4::bigint[], $5::bigint[]) \
+                     ON CONFLICT (tenant_id, day) DO UPDATE SET \
+                         events = usage_daily.events + EXCLUDED.events, \
+                         plugin_invocations = usage_daily.plugin_invocations + EXCLUDED.plugin_invocations, \
+                         fuel_consumed = usage_daily.fuel_consumed + EXCLUDED.fuel_consumed \
+                 ) \
+                 INSERT INTO plugin_usage_daily (tenant_id, day, module_path, invocations, failures, fuel_consumed, \
+                                                 execution_time_ms, bytes_in, bytes_out, memory_peak_bytes) \
+                 SELECT * FROM UNNEST($6::text[], $7::date[], $8::text[], $9::bigint[], $10::bigint[], $11::bigint[], \
+                                      $12::bigint[], $13::bigint[], $14::bigint[], $15::bigint[]) \
+                 ON CONFLICT (tenant_id, day, module_path) DO UPDATE SET \
+                     invocations = plugin_usage_daily.invocations + EXCLUDED.invocations, \
+                     failures = plugin_usage_daily.failures + EXCLUDED.failures, \
+                     fuel_consumed = plugin_usage_daily.fuel_consumed + EXCLUDED.fuel_consumed, \
+                     execution_time_ms = plugin_usage_daily.execution_time_ms + EXCLUDED.execution_time_ms, \
+                     bytes_in = plugin_usage_daily.bytes_in + EXCLUDED.bytes_in, \
+                     bytes_out = plugin_usage_daily.bytes_out + EXCLUDED.bytes_out, \
+                     memory_peak_bytes = GREATEST(plugin_usage_daily.memory_peak_bytes, EXCLUDED.memory_peak_bytes)",
+                &[
+                    &tenants,
+                    &days,
+                    &events,
+                    &invocations,
+                    &fuel,
+                    &plugin_tenants,
+                    &plugin_days,
+                    &modules,
+                    &plugin_invocations,
+                    &failures,
+                    &plugin_fuel,
+                    &execution_time,
+                    &bytes_in,
+                    &bytes_out,
+                    &memory_peak,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn plans(&self) -> Result<Vec<Plan>, StoreError> {
+        let rows = self
+            .client
+            .query(
+                "SELECT plan_id, name, monthly_price_cents, included_events, included_compute_units, \
+                        event_overage_cents_per_thousand, compute_unit_overage_cents, event_limit, compute_unit_limit \
+                 FROM billing_plans ORDER BY monthly_price_cents",
+                &[],
+            )
+            .await?;
+        Ok(rows.iter().map(plan_from_row).collect())
+    }
+
+    /// Subscriptions overlapping the period.
+    pub async fn subscriptions(&self, tenant_id: &str, period: Period) -> Result<Vec<Subscription>, StoreError> {
+        let rows = self
+            .client
+            .query(
+                "SELECT plan_id, starts_at, ends_at FROM tenant_subscriptions \
+                 WHERE tenant_id = $1 AND starts_at < $3::date AND (ends_at IS NULL OR ends_at > $2::date) \
+                 ORDER BY starts_at",
+                &[&tenant_id, &period.start, &period.end],
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| Subscription {
+                plan_id: row.get(0),
+                starts_at: row.get(1),
+                ends_at: row.get(2),
+            })
+            .collect())
+    }
+
+    pub async fn current_subscription(&self, tenant_id: &str) -> Result<Option<Subscription>, StoreError> {
+        let row = self
+            .client
+            .query_opt(
+                "SELECT plan_id, starts_at, ends_at FROM tenant_subscriptions \
+                 WHERE tenant_id = $1 AND ends_at IS NULL",
+                &[&tenant_id],
+            )
+            .await?;
+        Ok(row.map(|row| Subscription {
+            plan_id: row.get(0),
+            starts_at: row.get(1),
+            ends_at: row.get(2),
+        }))
+    }
+
+    /// Ends the open subscription at `effective_at` and starts the new plan.
+    pub async fn change_plan(&self, tenant_id: &str, plan_id: &str, effective_at: DateTime<Utc>) -> Result<(), StoreError> {
+        // A single statement, so the close and the insert commit together
+        self.client
+            .execute(
+                "WITH closed AS ( \
+                     UPDATE tenant_subscriptions SET ends_at = $3 \
+                     WHERE tenant_id = $1 AND ends_at IS NULL \
+                 ) \
+                 INSERT INTO tenant_subscriptions (tenant_id, plan_id, starts_at) VALUES ($1, $2, $3)",
+                &[&tenant_id, &plan_id, &effective_at],
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn usage(&self, tenant_id: &str, period: Period) -> Result<Vec<DailyUsage>, StoreError> {
+        let rows = self
+            .client
+            .query(
+                "SELECT day, events, plugin_invocations, fuel_consumed FROM usage_daily \
+                 WHERE tenant_id = $1 AND day >= $2 AND day < $3 ORDER BY day",
+                &[&tenant_id, &period.start, &period.end],
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| DailyUsage {
+                day: row.get(0),
+                events: row.get(1),
+                plugin_invocations: row.get(2),
+                fuel_consumed: row.get(3),
+            })
+            .collect())
+    }
+
+    /// The tenant's plugin usage in the period, per module, busiest first.
+    pub async fn plugin_usage(&self, tenant_id: &str, period: Period) -> Result<Vec<PluginUsage>, StoreError> {
+        let rows = self
+            .client
+            .query(
+                "SELECT module_path, SUM(invocations)::bigint, SUM(failures)::bigint, SUM(fuel_consumed)::bigint, \
+                        SUM(execution_time_ms)::bigint, SUM(bytes_in)::bigint, SUM(bytes_out)::bigint, \
+                        MAX(memory_peak_bytes) \
+                 FROM plugin_usage_daily \
+                 WHERE tenant_id = $1 AND day >= $2 AND day < $3 \
+                 GROUP BY module_path ORDER BY 4 DESC, module_path",
+                &[&tenant_id, &period.start, &period.end],
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| PluginUsage {
+                module_path: row.get(0),
+                invocations: row.get(1),
+                failures: row.get(2),
+                fuel_consumed: row.get(3),
+                execution_time_ms: row.get(4),
+                bytes_in: row.get(5),
+                bytes_out: row.get(6),
+                memory_peak_bytes: row.get(7),
+            })
+            .collect())
+    }
+
+    /// Tenants with usage or a subscription in the period.
+    pub async fn tenants(&self, period: Period) -> Result<Vec<String>, StoreError> {
+        let rows = self
+            .client
+            .query(
+                "SELECT tenant_id FROM usage_daily WHERE day >= $1 AND day < $2 \
+                 UNION \
+                 SELECT tenant_id FROM tenant_subscriptions \
+                 WHERE starts_at < $2::date AND (ends_at IS NULL OR ends_at > $1::date) \
+                 ORDER BY tenant_id",
+                &[&period.start, &period.end],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+}
+
+fn plan_from_row(row: &Row) -> Plan {
+    Plan {
+        plan_id: row.get(0),
+        name: row.get(1),
+        monthly_price_cents: row.get(2),
+        included_events: row.get(3),
+        included_compute_units: row.get(4),
+        event_overage_cents_per_thousand: row.get(5),
+        compute_unit_overage_cents: row.get(6),
+        event_limit: row.get(7),
+        compute_unit_limit: row.get(8),
+    }
+}
@@ -0,0 +1,149 @@
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use metering_service::billing::{compute_units, summarize, DailyUsage, Period, Plan, Subscription};
+
+/// Fuel per compute unit.
+const FUEL: u64 = 1000;
+
+fn plans() -> Vec<Plan> {
+    vec![
+        Plan {
+            plan_id: "free".to_string(),
+            name: "Free".to_string(),
+            monthly_price_cents: 0,
+            included_events: 200,
+            included_compute_units: 10,
+            event_overage_cents_per_thousand: 0,
+            compute_unit_overage_cents: 0,
+            event_limit: Some(200),
+            compute_unit_limit: Some(10),
+        },
+        Plan {
+            plan_id: "pro".to_string(),
+            name: "Pro".to_string(),
+            monthly_price_cents: 10_000,
+            included_events: 1000,
+            included_compute_units: 100,
+            event_overage_cents_per_thousand: 50,
+            compute_unit_overage_cents: 2,
+            event_limit: Some(5000),
+            compute_unit_limit: None,
+        },
+    ]
+}
+
+fn at(day: u32, hour: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 4, day, hour, 0, 0).unwrap()
+}
+
+fn april(day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2024, 4, day).unwrap()
+}
+
+fn subscription(plan_id: &str, starts_at: DateTime<Utc>, ends_at: Option<DateTime<Utc>>) -> Subscription {
+    Subscription { plan_id: plan_id.to_string(), starts_at, ends_at }
+}
+
+fn used(day: u32, events: i64, fuel_consumed: i64) -> DailyUsage {
+    DailyUsage { day: april(day), events, plugin_invocations: 1, fuel_consumed }
+}
+
+/// April 2024, 30 days.
+fn period() -> Period {
+    Period::parse("2024-04").unwrap()
+}
+
+#[test]
+fn a_month_on_one_plan_bills_its_price_and_overage() {
+    let march = Utc.with_ymd_and_hms(2024, 3, 5, 0, 0, 0).unwrap();
+    let subscriptions = [subscription("pro", march, None)];
+    let usage = [used(1, 1000, 100_500), used(30, 500, 50_000)];
+
+    let summary = summarize("t1", period(), &subscriptions, &usage, &plans(), "free", FUEL);
+
+    assert_eq!(summary.period, "2024-04");
+    assert_eq!(summary.segments.len(), 1);
+    let segment = &summary.segments[0];
+    let may = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+    assert_eq!((segment.from, segment.to, segment.fraction), (april(1), may, 1.0));
+    assert_eq!((segment.events, segment.plugin_invocations), (1500, 2));
+    // 150.5 units of fuel bill as 151
+    assert_eq!(segment.compute_units, 151);
+    assert_eq!(segment.base_cents, 10_000);
+    assert_eq!(segment.event_overage_cents, 25);
+    assert_eq!(segment.compute_overage_cents, 102);
+    assert_eq!(summary.total_cents, 10_127);
+
+    let limits = summary.limits.unwrap();
+    assert_eq!((limits.plan_id.as_str(), limits.event_limit, limits.compute_unit_limit), ("pro", Some(5000), None));
+    assert!(!limits.exceeded);
+}
+
+#[test]
+fn a_plan_change_prorates_both_plans_from_its_day() {
+    // Mid-day, but the new plan applies from the start of that day
+    let subscriptions = [subscription("free", at(1, 0), None), subscription("pro", at(16, 12), None)];
+    let usage = [used(10, 80, 0), used(15, 100, 0), used(16, 700, 0)];
+
+    let summary = summarize("t1", period(), &subscriptions, &usage, &plans(), "free", FUEL);
+
+    let spans: Vec<_> = summary.segments.iter().map(|s| (s.plan_id.as_str(), s.from, s.fraction, s.events)).collect();
+    assert_eq!(spans, [("free", april(1), 0.5, 180), ("pro", april(16), 0.5, 700)]);
+    let pro = &summary.segments[1];
+    assert_eq!((pro.base_cents, pro.included_events), (5000, 500));
+    assert_eq!(pro.event_overage_cents, 10);
+    assert_eq!(summary.total_cents, 5010);
+    // Caps are those of the plan the period ends on, prorated too
+    assert_eq!(summary.limits.unwrap().event_limit, Some(2500));
+}
+
+#[test]
+fn days_without_a_subscription_are_billed_on_the_default_plan() {
+    let subscriptions = [subscription("pro", at(11, 0), Some(at(21, 0)))];
+
+    let summary = summarize("t1", period(), &subscriptions, &[], &plans(), "free", FUEL);
+
+    let spans: Vec<_> = summary.segments.iter().map(|s| (s.plan_id.as_str(), s.from, s.to)).collect();
+    let may = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+    assert_eq!(spans, [("free", april(1), april(11)), ("pro", april(11), april(21)), ("free", april(21), may)]);
+    assert_eq!(summary.total_cents, 3333);
+    assert_eq!(summary.limits.unwrap().plan_id, "free");
+}
+
+#[test]
+fn usage_past_a_prorated_cap_exceeds_the_limits() {
+    // Free for the last ten days: caps of 67 events and 3 compute units
+    let subscriptions = [subscription("pro", at(1, 0), None), subscription("free", at(21, 0), None)];
+    let usage = [used(5, 4000, 0), used(22, 60, 3000)];
+    let summary = summarize("t1", period(), &subscriptions, &usage, &plans(), "free", FUEL);
+    let limits = summary.limits.unwrap();
+    assert_eq!((limits.events, limits.event_limit, limits.compute_unit_limit), (60, Some(67), Some(3)));
+    assert!(!limits.exceeded);
+
+    let usage = [used(5, 4000, 0), used(22, 60, 3001)];
+    let summary = summarize("t1", period(), &subscriptions, &usage, &plans(), "free", FUEL);
+    assert!(summary.limits.unwrap().exceeded);
+}
+
+#[test]
+fn overage_rounds_up_to_the_cent() {
+    let subscriptions = [subscription("pro", at(1, 0), None)];
+    let summary = summarize("t1", period(), &subscriptions, &[used(2, 1001, 0)], &plans(), "free", FUEL);
+    assert_eq!(summary.segments[0].event_overage_cents, 1);
+
+    assert_eq!(compute_units(1, FUEL), 1);
+    assert_eq!(compute_units(2000, FUEL), 2);
+    assert_eq!(compute_units(-5, FUEL), 0);
+}
+
+#[test]
+fn periods_are_calendar_months() {
+    let february = Period::parse("2024-02").unwrap();
+    assert_eq!((february.start, february.days()), (NaiveDate::from_ymd_opt(2024, 2, 1).unwrap(), 29));
+    let december = Period::parse("2024-12").unwrap();
+    assert_eq!(december.end, NaiveDate::from_ymd_opt(2025, 1, 1).unwrap());
+    assert_eq!(Period::containing(april(30)), period());
+
+    for invalid in ["2024-13", "2024-00", "2024", "april"] {
+        assert_eq!(Period::parse(invalid), None, "{}", invalid);
+    }
+}
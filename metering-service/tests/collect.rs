@@ -0,0 +1,78 @@
+use chrono::NaiveDate;
+use crm_events::CrmEvent;
+use metering_service::collect::{record_audit_entry, record_event};
+use metering_service::store::{PluginUsageBuffer, UsageBuffer};
+use redis::streams::StreamId;
+
+fn day() -> NaiveDate {
+    NaiveDate::from_ymd_opt(2024, 4, 1).unwrap()
+}
+
+fn entry(fields: &[(&str, &str)]) -> StreamId {
+    StreamId {
+        id: "1712000000000-0".to_string(),
+        map: fields
+            .iter()
+            .map(|(name, value)| (name.to_string(), redis::Value::Data(value.as_bytes().to_vec())))
+            .collect(),
+    }
+}
+
+#[test]
+fn counts_events_per_tenant_and_day() {
+    let mut buffer = UsageBuffer::new();
+    for tenant_id in ["t1", "t1", "t2"] {
+        let event = serde_json::to_vec(&CrmEvent::builder(tenant_id, "contact.created").build()).unwrap();
+        record_event(&mut buffer, Some(&event), day());
+    }
+    // Not billed, as ingestion drops them too
+    record_event(&mut buffer, Some(b"{\"tenant_id\": 1}"), day());
+    record_event(&mut buffer, None, day());
+
+    assert_eq!(buffer.len(), 2);
+    assert_eq!(buffer[&("t1".to_string(), day())].events, 2);
+    assert_eq!(buffer[&("t2".to_string(), day())].events, 1);
+}
+
+#[test]
+fn sums_plugin_executions_per_tenant_and_module() {
+    let mut buffer = UsageBuffer::new();
+    let mut plugins = PluginUsageBuffer::new();
+    let execution = |success: &'static str, fuel: &'static str, memory: &'static str| {
+        entry(&[
+            ("tenant_id", "t1"),
+            ("module_path", "invoice-export@1.0.0"),
+            ("success", success),
+            ("fuel_consumed", fuel),
+            ("execution_time_ms", "12"),
+            ("bytes_in", "100"),
+            ("bytes_out", "40"),
+            ("memory_peak_bytes", memory),
+        ])
+    };
+    record_audit_entry(&mut buffer, &mut plugins, &execution("1", "5000", "65536"), day());
+    record_audit_entry(&mut buffer, &mut plugins, &execution("0", "700", "131072"), day());
+
+    let plugin = plugins[&("t1".to_string(), day(), "invoice-export@1.0.0".to_string())];
+    assert_eq!((plugin.invocations, plugin.failures, plugin.fuel_consumed), (2, 1, 5700));
+    assert_eq!((plugin.execution_time_ms, plugin.bytes_in, plugin.bytes_out), (24, 200, 80));
+    // The peak of any one execution, not a sum
+    assert_eq!(plugin.memory_peak_bytes, 131072);
+
+    let tenant = buffer[&("t1".to_string(), day())];
+    assert_eq!((tenant.plugin_invocations, tenant.fuel_consumed, tenant.events), (2, 5700, 0));
+}
+
+#[test]
+fn entries_without_a_module_count_towards_the_tenant_only() {
+    let mut buffer = UsageBuffer::new();
+    let mut plugins = PluginUsageBuffer::new();
+    record_audit_entry(&mut buffer, &mut plugins, &entry(&[("tenant_id", "t1"), ("fuel_consumed", "300")]), day());
+    // Nothing to bill without a tenant
+    record_audit_entry(&mut buffer, &mut plugins, &entry(&[("fuel_consumed", "300")]), day());
+
+    assert!(plugins.is_empty());
+    assert_eq!(buffer.len(), 1);
+    let tenant = buffer[&("t1".to_string(), day())];
+    assert_eq!((tenant.plugin_invocations, tenant.fuel_consumed), (1, 300));
+}
@@ -0,0 +1,52 @@
+use async_trait::async_trait;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use super::{Channel, ChannelError};
+use crate::config::Config;
+use crate::templates::RenderedMessage;
+
+pub struct EmailChannel {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl EmailChannel {
+    /// Returns `None` when no SMTP host is configured.
+    pub fn from_config(config: &Config) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let host = match &config.smtp_host {
+            Some(host) => host,
+            None => return Ok(None),
+        };
+
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+            .port(config.smtp_port);
+        if let (Some(username), Some(password)) = (&config.smtp_username, &config.smtp_password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(Some(EmailChannel {
+            transport: builder.build(),
+            from: config.email_from.parse()?,
+        }))
+    }
+}
+
+#[async_trait]
+impl Channel for EmailChannel {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    async fn send(&self, recipient: &str, message: &RenderedMessage) -> Result<(), ChannelError> {
+        let email = Message::builder()
+            .from(self.from.clone())
+            .to(recipient.parse()?)
+            .subject(message.subject.as_str())
+            .body(message.body.clone())?;
+
+        self.transport.send(email).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,17 @@
+use async_trait::async_trait;
+
+use crate::templates::RenderedMessage;
+
+pub mod email;
+pub mod push;
+pub mod sms;
+
+pub type ChannelError = Box<dyn std::error::Error + Send + Sync>;
+
+#[async_trait]
+pub trait Channel: Send + Sync {
+    /// Name used in `NotificationRequested::channel` and rate-limit keys.
+    fn name(&self) -> &'static str;
+
+    async fn send(&self, recipient: &str, message: &RenderedMessage) -> Result<(), ChannelError>;
+}
@@ -0,0 +1,51 @@
+use async_trait::async_trait;
+use serde_json::json;
+
+use super::{Channel, ChannelError};
+use crate::config::Config;
+use crate::templates::RenderedMessage;
+
+const FCM_SEND_URL: &str = "https://fcm.googleapis.com/fcm/send";
+
+/// Sends push notifications to a single device token through FCM.
+pub struct PushChannel {
+    client: reqwest::Client,
+    server_key: String,
+}
+
+impl PushChannel {
+    /// Returns `None` when no FCM server key is configured.
+    pub fn from_config(config: &Config, client: reqwest::Client) -> Option<Self> {
+        Some(PushChannel {
+            client,
+            server_key: config.fcm_server_key.clone()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Channel for PushChannel {
+    fn name(&self) -> &'static str {
+        "push"
+    }
+
+    async fn send(&self, recipient: &str, message: &RenderedMessage) -> Result<(), ChannelError> {
+        let body = json!({
+            "to": recipient,
+            "notification": {
+                "title": message.subject,
+                "body": message.body,
+            },
+        });
+
+        self.client
+            .post(FCM_SEND_URL)
+            .header("Authorization", format!("key={}", self.server_key))
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
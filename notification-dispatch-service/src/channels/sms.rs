@@ -0,0 +1,56 @@
+use async_trait::async_trait;
+
+use super::{Channel, ChannelError};
+use crate::config::Config;
+use crate::templates::RenderedMessage;
+
+/// Sends SMS through the Twilio Messages REST API.
+pub struct SmsChannel {
+    client: reqwest::Client,
+    account_sid: String,
+    auth_token: String,
+    from_number: String,
+}
+
+impl SmsChannel {
+    /// Returns `None` when Twilio credentials are not configured.
+    pub fn from_config(config: &Config, client: reqwest::Client) -> Option<Self> {
+        let account_sid = config.twilio_account_sid.clone()?;
+        let auth_token = config.twilio_auth_token.clone()?;
+
+        Some(SmsChannel {
+            client,
+            account_sid,
+            auth_token,
+            from_number: config.twilio_from_number.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl Channel for SmsChannel {
+    fn name(&self) -> &'static str {
+        "sms"
+    }
+
+    async fn send(&self, recipient: &str, message: &RenderedMessage) -> Result<(), ChannelError> {
+        let url = format!(
+            "https://api.twilio.com/2010-04-01/Accounts/{}/Messages.json",
+            self.account_sid
+        );
+
+        self.client
+            .post(url)
+            .basic_auth(&self.account_sid, Some(&self.auth_token))
+            .form(&[
+                ("To", recipient),
+                ("From", self.from_number.as_str()),
+                ("Body", message.body.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use std::env;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub kafka_brokers: String,
+    pub kafka_group_id: String,
+    pub kafka_topics: Vec<String>,
+    pub tracking_topic: String,
+    pub redis_url: String,
+    pub smtp_host: Option<String>,
+    pub smtp_port: u16,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub email_from: String,
+    pub twilio_account_sid: Option<String>,
+    pub twilio_auth_token: Option<String>,
+    pub twilio_from_number: String,
+    pub fcm_server_key: Option<String>,
+    pub rate_limit_per_minute: u64,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Config {
+            kafka_brokers: env::var("KAFKA_BROKERS")
+                .unwrap_or_else(|_| "localhost:9092".to_string()),
+            kafka_group_id: env::var("KAFKA_GROUP_ID")
+                .unwrap_or_else(|_| "notification-dispatch-group".to_string()),
+            kafka_topics: env::var("KAFKA_TOPICS")
+                .unwrap_or_else(|_| "notifications,alerts".to_string())
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .collect(),
+            tracking_topic: env::var("TRACKING_TOPIC")
+                .unwrap_or_else(|_| "crm-events".to_string()),
+            redis_url: env::var("REDIS_URL")
+                .unwrap_or_else(|_| "redis://localhost:6379".to_string()),
+            // SES is used through its SMTP interface (email-smtp.<region>.amazonaws.com)
+            smtp_host: env::var("SMTP_HOST").ok(),
+            smtp_port: env::var("SMTP_PORT")
+                .unwrap_or_else(|_| "587".to_string())
+                .parse()
+                .unwrap_or(587),
+            smtp_username: env::var("SMTP_USERNAME").ok(),
+            smtp_password: env::var("SMTP_PASSWORD").ok(),
+            email_from: env::var("EMAIL_FROM")
+                .unwrap_or_else(|_| "CRM <no-reply@localhost>".to_string()),
+            twilio_account_sid: env::var("TWILIO_ACCOUNT_SID").ok(),
+            twilio_auth_token: env::var("TWILIO_AUTH_TOKEN").ok(),
+            twilio_from_number: env::var("TWILIO_FROM_NUMBER")
+                .unwrap_or_else(|_| "".to_string()),
+            fcm_server_key: env::var("FCM_SERVER_KEY").ok(),
+            rate_limit_per_minute: env::var("RATE_LIMIT_PER_MINUTE")
+                .unwrap_or_else(|_| "60".to_string())
+                .parse()
+                .unwrap_or(60),
+        })
+    }
+}
@@ -0,0 +1,187 @@
+use crate::channels::Channel;
+use crate::config::Config;
+use crate::rate_limiter::RateLimiter;
+use crate::templates::TemplateStore;
+use crm_events::{CrmEvent, EventPayload, NotificationRequested, PayloadError, ReportReady};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::ClientConfig;
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::Duration;
+use tracing::{debug, error, warn};
+
+const TRACKING_SOURCE: &str = "notification-dispatch-service";
+
+/// Template used to email recipients of a `report_ready` event.
+const REPORT_READY_TEMPLATE: &str = "report_ready";
+
+/// Outcome of one notification, reported back to the ingestion pipeline as a
+/// `notification_sent`, `notification_throttled` or `notification_failed` event.
+enum Outcome {
+    Sent,
+    Throttled,
+    Failed(String),
+}
+
+impl Outcome {
+    fn event_type(&self) -> &'static str {
+        match self {
+            Outcome::Sent => "notification_sent",
+            Outcome::Throttled => "notification_throttled",
+            Outcome::Failed(_) => "notification_failed",
+        }
+    }
+}
+
+pub struct Dispatcher {
+    channels: HashMap<&'static str, Box<dyn Channel>>,
+    templates: TemplateStore,
+    rate_limiter: RateLimiter,
+    producer: FutureProducer,
+    tracking_topic: String,
+}
+
+impl Dispatcher {
+    pub fn new(
+        config: &Config,
+        channels: Vec<Box<dyn Channel>>,
+        templates: TemplateStore,
+        rate_limiter: RateLimiter,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", &config.kafka_brokers)
+            .set("message.timeout.ms", "5000")
+            .create()?;
+
+        Ok(Dispatcher {
+            channels: channels.into_iter().map(|c| (c.name(), c)).collect(),
+            templates,
+            rate_limiter,
+            producer,
+            tracking_topic: config.tracking_topic.clone(),
+        })
+    }
+
+    /// Dispatches the notifications an event asks for. Events other than
+    /// notification requests and report announcements are ignored.
+    pub async fn dispatch(&self, event: &CrmEvent) {
+        let requests = match requests_for(event) {
+            Ok(requests) => requests,
+            Err(e) => {
+                warn!("Skipping notification for tenant {}: {}", event.tenant_id, e);
+                return;
+            }
+        };
+
+        for request in requests {
+            let outcome = self.send(&event.tenant_id, &request).await;
+            match &outcome {
+                Outcome::Sent => debug!(
+                    "Sent {} notification for tenant {} using template {}",
+                    request.channel, event.tenant_id, request.template_id
+                ),
+                Outcome::Throttled => warn!(
+                    "Rate limit reached for {} notifications of tenant {}",
+                    request.channel, event.tenant_id
+                ),
+                Outcome::Failed(e) => error!(
+                    "Failed to send {} notification for tenant {}: {}",
+                    request.channel, event.tenant_id, e
+                ),
+            }
+
+            self.track(event, &request, &outcome).await;
+        }
+    }
+
+    async fn send(&self, tenant_id: &str, request: &NotificationRequested) -> Outcome {
+        let channel = match self.channels.get(request.channel.as_str()) {
+            Some(channel) => channel,
+            None => return Outcome::Failed(format!("channel '{}' is not configured", request.channel)),
+        };
+
+        match self.rate_limiter.try_acquire(tenant_id, channel.name()).await {
+            Ok(true) => {}
+            Ok(false) => return Outcome::Throttled,
+            Err(e) => return Outcome::Failed(format!("rate limiter unavailable: {}", e)),
+        }
+
+        let template = match self.templates.get(tenant_id, &request.template_id).await {
+            Ok(Some(template)) => template,
+            Ok(None) => return Outcome::Failed(format!("unknown template '{}'", request.template_id)),
+            Err(e) => return Outcome::Failed(format!("failed to load template: {}", e)),
+        };
+
+        let message = template.render(&request.data);
+        match channel.send(&request.recipient, &message).await {
+            Ok(()) => Outcome::Sent,
+            Err(e) => Outcome::Failed(e.to_string()),
+        }
+    }
+
+    async fn track(&self, source: &CrmEvent, request: &NotificationRequested, outcome: &Outcome) {
+        let mut payload = json!({
+            "channel": request.channel,
+            "template_id": request.template_id,
+        });
+        if let Outcome::Failed(e) = outcome {
+            payload["error"] = json!(e);
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(source.timestamp);
+
+        let mut builder = CrmEvent::builder(&source.tenant_id, outcome.event_type())
+            .payload(payload)
+            .timestamp(timestamp)
+            .source(TRACKING_SOURCE);
+        if let Some(user_id) = &source.user_id {
+            builder = builder.user_id(user_id);
+        }
+        let tracking_event = builder.build();
+
+        let body = match serde_json::to_string(&tracking_event) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("Failed to serialize tracking event: {}", e);
+                return;
+            }
+        };
+
+        let record = FutureRecord::to(&self.tracking_topic)
+            .key(&source.tenant_id)
+            .payload(&body);
+        if let Err((e, _)) = self.producer.send(record, Duration::from_secs(5)).await {
+            error!("Failed to publish tracking event: {}", e);
+        }
+    }
+}
+
+fn requests_for(event: &CrmEvent) -> Result<Vec<NotificationRequested>, PayloadError> {
+    match event.event_type.as_str() {
+        NotificationRequested::EVENT_TYPE => Ok(vec![event.payload_as()?]),
+        ReportReady::EVENT_TYPE => {
+            let report: ReportReady = event.payload_as()?;
+            let mut data = serde_json::Map::new();
+            data.insert("report_name".to_string(), json!(report.name));
+            data.insert("format".to_string(), json!(report.format));
+            data.insert("location".to_string(), json!(report.location));
+            data.insert("row_count".to_string(), json!(report.row_count));
+
+            Ok(report
+                .recipients
+                .into_iter()
+                .map(|recipient| NotificationRequested {
+                    channel: "email".to_string(),
+                    recipient,
+                    template_id: REPORT_READY_TEMPLATE.to_string(),
+                    data: data.clone(),
+                })
+                .collect())
+        }
+        _ => Ok(Vec::new()),
+    }
+}
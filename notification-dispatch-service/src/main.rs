@@ -0,0 +1,101 @@
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::{ClientConfig, Message};
+use tokio::time::Duration;
+use tracing::{info, error, warn};
+
+mod channels;
+mod config;
+mod dispatcher;
+mod rate_limiter;
+mod templates;
+
+use channels::email::EmailChannel;
+use channels::push::PushChannel;
+use channels::sms::SmsChannel;
+use channels::Channel;
+use config::Config;
+use crm_events::CrmEvent;
+use dispatcher::Dispatcher;
+use rate_limiter::RateLimiter;
+use templates::TemplateStore;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Logs, traces and panic capture
+    let _observability = crm_observability::init("notification-dispatch-service")?;
+
+    info!("Starting Notification Dispatch Service");
+
+    // Load configuration
+    let config = Config::from_env()?;
+
+    let http = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?;
+
+    let mut enabled: Vec<Box<dyn Channel>> = Vec::new();
+    if let Some(email) = EmailChannel::from_config(&config)? {
+        enabled.push(Box::new(email));
+    }
+    if let Some(sms) = SmsChannel::from_config(&config, http.clone()) {
+        enabled.push(Box::new(sms));
+    }
+    if let Some(push) = PushChannel::from_config(&config, http) {
+        enabled.push(Box::new(push));
+    }
+    let names: Vec<&str> = enabled.iter().map(|c| c.name()).collect();
+    info!("Enabled notification channels: {:?}", names);
+
+    let redis_client = redis::Client::open(config.redis_url.as_str())?;
+    let templates = TemplateStore::new(redis_client.get_async_connection().await?);
+    let rate_limiter = RateLimiter::new(
+        redis_client.get_async_connection().await?,
+        config.rate_limit_per_minute,
+    );
+    let dispatcher = Dispatcher::new(&config, enabled, templates, rate_limiter)?;
+
+    // Create Kafka consumer
+    let consumer = create_consumer(&config)?;
+    let topics: Vec<&str> = config.kafka_topics.iter().map(|s| s.as_str()).collect();
+    consumer.subscribe(&topics)?;
+
+    info!("Connected to Kafka, starting notification dispatch...");
+
+    loop {
+        let message = match consumer.recv().await {
+            Ok(message) => message,
+            Err(e) => {
+                error!("Error receiving message: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        let event: CrmEvent = match message.payload().map(serde_json::from_slice) {
+            Some(Ok(event)) => event,
+            Some(Err(e)) => {
+                warn!("Skipping undecodable event: {}", e);
+                continue;
+            }
+            None => {
+                warn!("Received empty message");
+                continue;
+            }
+        };
+
+        dispatcher.dispatch(&event).await;
+    }
+}
+
+fn create_consumer(config: &Config) -> Result<StreamConsumer, Box<dyn std::error::Error>> {
+    let consumer: StreamConsumer = ClientConfig::new()
+        .set("group.id", &config.kafka_group_id)
+        .set("bootstrap.servers", &config.kafka_brokers)
+        .set("enable.partition.eof", "false")
+        .set("session.timeout.ms", "6000")
+        .set("enable.auto.commit", "true")
+        .set("auto.offset.reset", "latest")
+        .create()?;
+
+    Ok(consumer)
+}
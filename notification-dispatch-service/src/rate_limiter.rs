@@ -0,0 +1,42 @@
+use redis::aio::Connection;
+use redis::AsyncCommands;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+/// Fixed one-minute windows per tenant and channel, counted in Redis so the
+/// limit holds across service replicas. A per-tenant override can be set in
+/// `notification_rate_limit:{tenant_id}`.
+pub struct RateLimiter {
+    redis_connection: Mutex<Connection>,
+    default_per_minute: u64,
+}
+
+impl RateLimiter {
+    pub fn new(redis_connection: Connection, default_per_minute: u64) -> Self {
+        RateLimiter {
+            redis_connection: Mutex::new(redis_connection),
+            default_per_minute,
+        }
+    }
+
+    pub async fn try_acquire(
+        &self,
+        tenant_id: &str,
+        channel: &str,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let minute = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() / 60)
+            .unwrap_or_default();
+        let key = format!("notify_rate:{}:{}:{}", tenant_id, channel, minute);
+
+        let mut conn = self.redis_connection.lock().await;
+        let limit: Option<u64> = conn.get(format!("notification_rate_limit:{}", tenant_id)).await?;
+        let count: u64 = conn.incr(&key, 1).await?;
+        if count == 1 {
+            let _: () = conn.expire(&key, 120).await?;
+        }
+
+        Ok(count <= limit.unwrap_or(self.default_per_minute))
+    }
+}
@@ -0,0 +1,88 @@
+use redis::aio::Connection;
+use redis::AsyncCommands;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tokio::sync::Mutex;
+
+/// A per-tenant message template, stored as JSON in the Redis hash
+/// `notification_templates:{tenant_id}` keyed by template id.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Template {
+    #[serde(default)]
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderedMessage {
+    pub subject: String,
+    pub body: String,
+}
+
+impl Template {
+    pub fn render(&self, data: &Map<String, Value>) -> RenderedMessage {
+        RenderedMessage {
+            subject: substitute(&self.subject, data),
+            body: substitute(&self.body, data),
+        }
+    }
+}
+
+/// Replaces `{{name}}` placeholders; unknown names render as empty strings.
+fn substitute(template: &str, data: &Map<String, Value>) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                let name = after[..end].trim();
+                match data.get(name) {
+                    Some(Value::String(s)) => output.push_str(s),
+                    Some(Value::Null) | None => {}
+                    Some(other) => output.push_str(&other.to_string()),
+                }
+                rest = &after[end + 2..];
+            }
+            None => {
+                output.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    output.push_str(rest);
+
+    output
+}
+
+pub struct TemplateStore {
+    redis_connection: Mutex<Connection>,
+}
+
+impl TemplateStore {
+    pub fn new(redis_connection: Connection) -> Self {
+        TemplateStore {
+            redis_connection: Mutex::new(redis_connection),
+        }
+    }
+
+    pub async fn get(
+        &self,
+        tenant_id: &str,
+        template_id: &str,
+    ) -> Result<Option<Template>, Box<dyn std::error::Error + Send + Sync>> {
+        let raw: Option<String> = self
+            .redis_connection
+            .lock()
+            .await
+            .hget(format!("notification_templates:{}", tenant_id), template_id)
+            .await?;
+
+        match raw {
+            Some(raw) => Ok(Some(serde_json::from_str(&raw)?)),
+            None => Ok(None),
+        }
+    }
+}
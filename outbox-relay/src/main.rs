@@ -0,0 +1,32 @@
+use crm_outbox::{OutboxRelay, RelayConfig};
+use crm_secrets::SecretStore;
+use crm_startup::{retry, Dependency, Health, StartupConfig};
+use tracing::info;
+use warp::Filter;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Logs, traces and panic capture
+    let _observability = crm_observability::init("outbox-relay")?;
+
+    info!("Starting Outbox Relay");
+
+    // The database URL usually holds a secret:// password
+    let startup = StartupConfig::from_env()?;
+    let secrets = SecretStore::from_env()?;
+    let template = RelayConfig::from_env()?;
+    let config = retry("secrets backend", &startup.retry_policy(), || secrets.resolve(&template)).await?;
+    info!("Configuration: {}", crm_config::redacted(&config));
+
+    let health = Health::new("outbox-relay", &startup, vec![Dependency::kafka("kafka", &config.kafka_brokers)]);
+    let routes = crm_observability::metrics_route().or(health.route());
+    info!("Metrics server running on http://0.0.0.0:{}/metrics", config.port);
+    tokio::spawn(warp::serve(routes).run(([0, 0, 0, 0], config.port)));
+    health.start().await?;
+
+    let relay = retry("postgres", &startup.retry_policy(), || OutboxRelay::connect(&config)).await?;
+    // Only returns once the database session, and with it the relay lock, is
+    // gone; exit so the orchestrator restarts us as a standby
+    relay.run().await?;
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use std::env;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub kafka_brokers: String,
+    /// Topic `report_ready` events are published to; the notification
+    /// service consumes it.
+    pub notification_topic: String,
+    pub redis_url: String,
+    pub clickhouse_url: String,
+    /// Should be granted SELECT on `clickhouse_database` only: report
+    /// queries are filtered to their tenant within that database.
+    pub clickhouse_user: String,
+    pub clickhouse_password: String,
+    pub clickhouse_database: String,
+    /// Tables and views of `clickhouse_database` report SQL may read; any
+    /// other table, and any table function, is refused.
+    pub report_tables: Vec<String>,
+    /// Reports are refused if they would return more rows than this.
+    pub max_result_rows: u64,
+    pub query_timeout_secs: u64,
+    /// Object storage root for rendered reports, e.g. `s3://bucket/reports`.
+    pub storage_url: String,
+    pub scheduler_poll_interval_secs: u64,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Config {
+            kafka_brokers: env::var("KAFKA_BROKERS")
+                .unwrap_or_else(|_| "localhost:9092".to_string()),
+            notification_topic: env::var("NOTIFICATION_TOPIC")
+                .unwrap_or_else(|_| "notifications".to_string()),
+            redis_url: env::var("REDIS_URL")
+                .unwrap_or_else(|_| "redis://localhost:6379".to_string()),
+            clickhouse_url: env::var("CLICKHOUSE_URL")
+                .unwrap_or_else(|_| "http://localhost:8123".to_string()),
+            clickhouse_user: env::var("CLICKHOUSE_USER")
+                .unwrap_or_else(|_| "default".to_string()),
+            clickhouse_password: env::var("CLICKHOUSE_PASSWORD")
+                .unwrap_or_else(|_| "".to_string()),
+            clickhouse_database: env::var("CLICKHOUSE_DATABASE")
+                .unwrap_or_else(|_| "crm_analytics".to_string()),
+            report_tables: env::var("REPORT_TABLES")
+                .unwrap_or_else(|_| {
+                    "events,page_views,page_views_resolved,event_metrics,daily_stats,\
+                     campaign_performance,revenue_monthly,contact_activity_daily"
+                        .to_string()
+                })
+                .split(',')
+                .map(|table| table.trim().to_string())
+                .filter(|table| !table.is_empty())
+                .collect(),
+            max_result_rows: env::var("MAX_RESULT_ROWS")
+                .unwrap_or_else(|_| "100000".to_string())
+                .parse()
+                .unwrap_or(100000),
+            query_timeout_secs: env::var("QUERY_TIMEOUT_SECS")
+                .unwrap_or_else(|_| "120".to_string())
+                .parse()
+                .unwrap_or(120),
+            storage_url: env::var("REPORT_STORAGE_URL")
+                .unwrap_or_else(|_| "file:///tmp/reports".to_string()),
+            scheduler_poll_interval_secs: env::var("SCHEDULER_POLL_INTERVAL_SECS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
+        })
+    }
+}
@@ -0,0 +1,140 @@
+use chrono::{TimeZone, Utc};
+use cron::Schedule;
+use redis::aio::Connection;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// Tenants with at least one report definition.
+const REPORT_TENANTS_KEY: &str = "report_tenants";
+
+/// Parameter the runner always binds to the owning tenant, e.g. for
+/// `{tenant_id:String}` in a query; definitions can't set it themselves.
+pub const TENANT_PARAM: &str = "tenant_id";
+
+/// A tenant-scheduled report, stored as JSON in the Redis hash
+/// `report_definitions:{tenant_id}` keyed by report id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportDefinition {
+    pub id: String,
+    pub name: String,
+    /// ClickHouse SQL using query parameters, e.g. `{since:Int64}`.
+    pub query: String,
+    /// Values bound to the query's parameters.
+    #[serde(default)]
+    pub params: Map<String, Value>,
+    /// Cron expression with seconds, e.g. `0 0 8 * * Mon` for Mondays 08:00 UTC.
+    pub schedule: String,
+    #[serde(default)]
+    pub format: ReportFormat,
+    #[serde(default)]
+    pub recipients: Vec<String>,
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    #[default]
+    Csv,
+    Xlsx,
+}
+
+impl ReportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ReportFormat::Csv => "csv",
+            ReportFormat::Xlsx => "xlsx",
+        }
+    }
+}
+
+impl ReportDefinition {
+    /// First scheduled time strictly after `after` (unix seconds).
+    pub fn next_run_after(&self, after: i64) -> Result<Option<i64>, cron::error::Error> {
+        let schedule = Schedule::from_str(&self.schedule)?;
+        let after = match Utc.timestamp_opt(after, 0).single() {
+            Some(after) => after,
+            None => return Ok(None),
+        };
+        Ok(schedule.after(&after).next().map(|t| t.timestamp()))
+    }
+}
+
+pub struct DefinitionStore {
+    redis_connection: Mutex<Connection>,
+}
+
+impl DefinitionStore {
+    pub fn new(redis_connection: Connection) -> Self {
+        DefinitionStore {
+            redis_connection: Mutex::new(redis_connection),
+        }
+    }
+
+    pub async fn tenants(&self) -> Result<Vec<String>, redis::RedisError> {
+        self.redis_connection.lock().await.smembers(REPORT_TENANTS_KEY).await
+    }
+
+    pub async fn definitions(&self, tenant_id: &str) -> Result<Vec<ReportDefinition>, redis::RedisError> {
+        let raw: HashMap<String, String> = self
+            .redis_connection
+            .lock()
+            .await
+            .hgetall(format!("report_definitions:{}", tenant_id))
+            .await?;
+
+        Ok(raw
+            .into_iter()
+            .filter_map(|(id, json)| match serde_json::from_str::<ReportDefinition>(&json) {
+                Ok(definition) if definition.params.contains_key(TENANT_PARAM) => {
+                    warn!("Ignoring report {} for tenant {}: {} is bound by the runner", id, tenant_id, TENANT_PARAM);
+                    None
+                }
+                Ok(definition) => Some(definition),
+                Err(e) => {
+                    warn!("Ignoring malformed report {} for tenant {}: {}", id, tenant_id, e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    pub async fn last_run(&self, tenant_id: &str, report_id: &str) -> Result<Option<i64>, redis::RedisError> {
+        self.redis_connection
+            .lock()
+            .await
+            .get(format!("report_last_run:{}:{}", tenant_id, report_id))
+            .await
+    }
+
+    pub async fn set_last_run(&self, tenant_id: &str, report_id: &str, at: i64) -> Result<(), redis::RedisError> {
+        self.redis_connection
+            .lock()
+            .await
+            .set(format!("report_last_run:{}:{}", tenant_id, report_id), at)
+            .await
+    }
+
+    /// Claims one scheduled occurrence so only one replica runs it.
+    pub async fn claim(&self, tenant_id: &str, report_id: &str, scheduled_at: i64) -> Result<bool, redis::RedisError> {
+        let claimed: Option<String> = redis::cmd("SET")
+            .arg(format!("report_lock:{}:{}:{}", tenant_id, report_id, scheduled_at))
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(86400)
+            .query_async(&mut *self.redis_connection.lock().await)
+            .await?;
+        Ok(claimed.is_some())
+    }
+}
@@ -0,0 +1,477 @@
+//! Checks report SQL before it runs.
+//!
+//! Report queries are written by tenants, so they may only read the
+//! reporting tables, whose rows ClickHouse filters to the report's tenant.
+//! A query must be one `SELECT`, or `WITH ... SELECT`, and every table it
+//! names, after `FROM`, `JOIN`, a comma of the `FROM` list or `IN`, must be
+//! one of those tables, in the report database, or a common table
+//! expression defined before it. Table functions such as `remote()`,
+//! `url()` or `numbers()` are refused, and so are functions missing from
+//! `FUNCTIONS` and `AGGREGATES`, e.g. `dictGet` or `file`, and `INTO
+//! OUTFILE`, `SETTINGS` and `FORMAT` clauses. Whatever the check doesn't
+//! understand is refused too, e.g. a subquery in a function's arguments
+//! or a bare name in an `IN` list, which ClickHouse may read as a table.
+
+use std::collections::HashSet;
+
+/// Functions report queries may call, besides aggregates.
+const FUNCTIONS: &[&str] = &[
+    // Dates and times
+    "now", "today", "yesterday", "toDate", "toDate32", "toDateTime", "toDateTime64", "toTimeZone", "toStartOfYear",
+    "toStartOfQuarter", "toStartOfMonth", "toStartOfWeek", "toMonday", "toStartOfDay", "toStartOfHour",
+    "toStartOfMinute", "toStartOfFiveMinutes", "toStartOfFifteenMinutes", "toStartOfInterval", "toYear",
+    "toQuarter", "toMonth", "toWeek", "toISOWeek", "toISOYear", "toDayOfMonth", "toDayOfWeek", "toDayOfYear",
+    "toHour", "toMinute", "toSecond", "toUnixTimestamp", "toYYYYMM", "toYYYYMMDD", "toYYYYMMDDhhmmss",
+    "fromUnixTimestamp", "fromUnixTimestamp64Milli", "toUnixTimestamp64Milli", "dateDiff", "date_diff", "dateAdd",
+    "date_add", "dateSub", "date_sub", "dateTrunc", "date_trunc", "addSeconds", "addMinutes", "addHours", "addDays",
+    "addWeeks", "addMonths", "addQuarters", "addYears", "subtractSeconds", "subtractMinutes", "subtractHours",
+    "subtractDays", "subtractWeeks", "subtractMonths", "subtractQuarters", "subtractYears", "formatDateTime",
+    "parseDateTimeBestEffort", "parseDateTimeBestEffortOrNull", "parseDateTimeBestEffortOrZero", "toIntervalSecond",
+    "toIntervalMinute", "toIntervalHour", "toIntervalDay", "toIntervalWeek", "toIntervalMonth", "toIntervalYear",
+    // Conversions
+    "toString", "toInt8", "toInt16", "toInt32", "toInt64", "toUInt8", "toUInt16", "toUInt32", "toUInt64",
+    "toFloat32", "toFloat64", "toDecimal32", "toDecimal64", "toDecimal128", "toInt64OrNull", "toInt64OrZero",
+    "toUInt64OrNull", "toUInt64OrZero", "toFloat64OrNull", "toFloat64OrZero", "toDateOrNull", "toDateTimeOrNull",
+    "toFixedString", "toTypeName", "cast", "accurateCastOrNull",
+    // Strings
+    "length", "lengthUTF8", "empty", "notEmpty", "lower", "upper", "lowerUTF8", "upperUTF8", "concat", "substring",
+    "substr", "substringUTF8", "trim", "trimBoth", "trimLeft", "trimRight", "leftPad", "rightPad", "lpad", "rpad",
+    "reverse", "replaceAll", "replaceOne", "replaceRegexpAll", "replaceRegexpOne", "match", "extract", "extractAll",
+    "like", "notLike", "ilike", "notILike", "position", "positionCaseInsensitive", "startsWith", "endsWith",
+    "splitByChar", "splitByString", "arrayStringConcat", "format", "domain", "domainWithoutWWW", "protocol", "path",
+    "queryString", "extractURLParameter", "cutQueryString", "JSONHas", "JSONLength", "JSONExtract",
+    "JSONExtractString", "JSONExtractInt", "JSONExtractUInt", "JSONExtractFloat", "JSONExtractBool",
+    "JSONExtractRaw", "JSONExtractKeys", "simpleJSONExtractString", "simpleJSONExtractInt",
+    "simpleJSONExtractFloat", "simpleJSONHas",
+    // Conditions and comparisons
+    "if", "multiIf", "coalesce", "ifNull", "nullIf", "isNull", "isNotNull", "assumeNotNull", "greatest", "least",
+    "equals", "notEquals", "less", "greater", "lessOrEquals", "greaterOrEquals", "and", "or", "not", "xor",
+    // Arithmetic
+    "abs", "round", "roundBankers", "floor", "ceil", "ceiling", "intDiv", "modulo", "plus", "minus", "multiply",
+    "divide", "negate", "sqrt", "pow", "power", "exp", "log", "ln", "log2", "log10", "sign", "isFinite", "isNaN",
+    "ifNotFinite", "formatReadableQuantity", "formatReadableSize",
+    // Arrays, tuples and maps
+    "array", "arrayJoin", "arrayMap", "arrayFilter", "arrayExists", "arrayAll", "arraySum", "arrayCount",
+    "arrayDistinct", "arraySort", "arrayReverseSort", "arraySlice", "arrayConcat", "arrayElement", "arrayEnumerate",
+    "arrayZip", "arrayFirst", "arrayUniq", "has", "hasAll", "hasAny", "indexOf", "range", "tuple", "tupleElement",
+    "map", "mapKeys", "mapValues", "mapContains",
+    // Hashes
+    "cityHash64", "sipHash64", "xxHash64", "farmFingerprint64",
+    // Window functions
+    "row_number", "rank", "dense_rank", "percent_rank", "ntile", "first_value", "last_value", "nth_value",
+    "lagInFrame", "leadInFrame",
+];
+
+/// Aggregate functions report queries may call, also with combinators,
+/// e.g. `countIf` or `uniqExactMerge`.
+const AGGREGATES: &[&str] = &[
+    "count", "sum", "avg", "avgWeighted", "min", "max", "any", "anyLast", "anyHeavy", "argMin", "argMax", "uniq",
+    "uniqExact", "uniqCombined", "uniqCombined64", "uniqHLL12", "uniqTheta", "groupArray", "groupUniqArray",
+    "groupArraySample", "quantile", "quantiles", "quantileExact", "quantileExactWeighted", "quantileTiming",
+    "quantileTDigest", "quantileDeterministic", "quantilesExact", "quantilesTDigest", "median", "medianExact",
+    "stddevPop", "stddevSamp", "varPop", "varSamp", "covarPop", "covarSamp", "corr", "topK", "topKWeighted",
+    "sumWithOverflow", "sumMap", "minMap", "maxMap", "entropy", "skewPop", "kurtPop", "windowFunnel", "retention",
+    "sequenceMatch", "sequenceCount",
+];
+
+/// Aggregate function combinators.
+const COMBINATORS: &[&str] = &["If", "Array", "Distinct", "OrNull", "OrDefault", "State", "Merge", "ForEach", "Map"];
+
+/// Keywords a parenthesis may follow without making them a function call.
+const KEYWORDS: &[&str] = &[
+    "SELECT", "FROM", "JOIN", "IN", "EXISTS", "AS", "OVER", "USING", "ON", "AND", "OR", "NOT", "WHERE", "PREWHERE",
+    "HAVING", "BY", "THEN", "ELSE", "WHEN", "CASE", "DISTINCT", "ALL", "ANY", "UNION", "EXCEPT", "INTERSECT", "IS",
+    "LIKE", "ILIKE", "BETWEEN", "INTERVAL", "WITH", "LIMIT", "OFFSET", "WINDOW", "QUALIFY", "FILTER", "GLOBAL",
+];
+
+/// Keywords ending a `FROM` clause.
+const CLAUSES: &[&str] = &[
+    "WHERE", "PREWHERE", "GROUP", "ORDER", "LIMIT", "HAVING", "UNION", "EXCEPT", "INTERSECT", "WINDOW", "QUALIFY",
+];
+
+/// Why a report query was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejected(pub String);
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Report query refused: {}", self.0)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+fn reject<T>(reason: impl Into<String>) -> Result<T, Rejected> {
+    Err(Rejected(reason.into()))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// An unquoted identifier or keyword.
+    Word(String),
+    /// A quoted identifier, unescaped.
+    Quoted(String),
+    /// A string or number.
+    Literal,
+    /// A query parameter, e.g. `{since:Int64}`.
+    Param,
+    Punct(char),
+}
+
+impl Token {
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Word(word) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn name(&self) -> Option<&str> {
+        match self {
+            Token::Word(name) | Token::Quoted(name) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+/// Where a `FROM` clause is at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum From {
+    /// Not in one.
+    None,
+    /// A table comes next.
+    Table,
+    /// After a table: its alias, `FINAL`, `SAMPLE` and the like.
+    AfterTable,
+    /// After `ON` or `USING`.
+    Condition,
+    /// After `ARRAY JOIN`, whose list is of expressions.
+    ArrayJoin,
+}
+
+/// A level of parentheses or brackets.
+struct Level {
+    close: char,
+    /// A function call's arguments.
+    call: bool,
+    /// An `IN` list, whose bare names ClickHouse may read as tables.
+    in_list: bool,
+    from: From,
+    /// In a `WITH` clause, before its `SELECT`.
+    with: bool,
+    /// Common table expressions defined at this level so far.
+    ctes: HashSet<String>,
+    /// The common table expression this is the body of, defined once it
+    /// closes.
+    defines: Option<String>,
+}
+
+impl Level {
+    fn new(close: char) -> Self {
+        Level { close, call: false, in_list: false, from: From::None, with: false, ctes: HashSet::new(), defines: None }
+    }
+}
+
+/// Refuses `sql` unless it only reads `tables` of `database`.
+pub fn check(sql: &str, database: &str, tables: &[String]) -> Result<(), Rejected> {
+    let tokens = tokenize(sql)?;
+    if !tokens.first().is_some_and(|token| token.is_keyword("SELECT") || token.is_keyword("WITH")) {
+        return reject("only SELECT queries are allowed");
+    }
+    if let Some(i) = tokens.iter().position(|token| *token == Token::Punct(';')) {
+        if i + 1 != tokens.len() {
+            return reject("only one statement is allowed");
+        }
+    }
+    let mut checker = Checker { tokens: &tokens, database, tables, levels: vec![Level::new(' ')] };
+    checker.run()
+}
+
+struct Checker<'a> {
+    tokens: &'a [Token],
+    database: &'a str,
+    tables: &'a [String],
+    levels: Vec<Level>,
+}
+
+impl Checker<'_> {
+    fn run(&mut self) -> Result<(), Rejected> {
+        let tokens = self.tokens;
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            let previous = i.checked_sub(1).map(|p| &tokens[p]);
+            let next = tokens.get(i + 1);
+
+            // A table, wherever one is expected
+            let expects_table = self.level().from == From::Table;
+            let in_list_name = self.level().in_list
+                && matches!(previous, Some(Token::Punct('(' | ',')))
+                && token.name().is_some()
+                && !token.is_keyword("SELECT")
+                && !token.is_keyword("WITH");
+            if expects_table || in_list_name {
+                match table_end(tokens, i) {
+                    // An IN list item starting with a name but longer, e.g. `a + 1`
+                    Some(end) if in_list_name && !matches!(tokens.get(end), Some(Token::Punct(',' | ')' | '('))) => {}
+                    Some(end) => {
+                        self.check_table(i, end)?;
+                        if expects_table {
+                            self.level().from = From::AfterTable;
+                        }
+                        i = end;
+                        continue;
+                    }
+                    None if expects_table && *token != Token::Punct('(') => {
+                        return reject("expected a table after FROM or JOIN");
+                    }
+                    None => {}
+                }
+            }
+
+            match token {
+                Token::Word(word) | Token::Quoted(word) if next == Some(&Token::Punct('(')) => {
+                    if is_keyword(token) {
+                        self.keyword(i)?;
+                    } else if !allowed_function(word) {
+                        return reject(format!("function {} is not allowed", word));
+                    }
+                }
+                Token::Word(word) => {
+                    banned(word)?;
+                    self.keyword(i)?;
+                }
+                Token::Punct('(') => {
+                    let mut opened = Level::new(')');
+                    opened.call = previous.is_some_and(|p| p.name().is_some() && !is_keyword(p));
+                    opened.in_list = previous.is_some_and(|p| p.is_keyword("IN"));
+                    let parent = self.level();
+                    if parent.from == From::Table {
+                        parent.from = From::AfterTable;
+                        if !next.is_some_and(|n| n.is_keyword("SELECT") || n.is_keyword("WITH")) {
+                            return reject("expected a subquery after FROM or JOIN");
+                        }
+                    }
+                    // `name AS (...)` in a WITH clause
+                    if parent.with && previous.is_some_and(|p| p.is_keyword("AS")) {
+                        opened.defines = i.checked_sub(2).and_then(|p| tokens[p].name()).map(str::to_string);
+                    }
+                    self.levels.push(opened);
+                }
+                Token::Punct('[') => self.levels.push(Level::new(']')),
+                Token::Punct(c @ (')' | ']')) => {
+                    let closed = self.levels.pop().expect("the query level is never closed");
+                    if closed.close != *c || self.levels.is_empty() {
+                        return reject("unbalanced parentheses");
+                    }
+                    if let Some(name) = closed.defines {
+                        self.level().ctes.insert(name);
+                    }
+                }
+                Token::Punct(',') => {
+                    let level = self.level();
+                    if matches!(level.from, From::AfterTable | From::Condition) {
+                        level.from = From::Table;
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        if self.levels.len() != 1 {
+            return reject("unbalanced parentheses");
+        }
+        if self.level().from == From::Table {
+            return reject("expected a table after FROM or JOIN");
+        }
+        Ok(())
+    }
+
+    fn level(&mut self) -> &mut Level {
+        self.levels.last_mut().expect("the query level is never closed")
+    }
+
+    /// What the keyword at `tokens[i]`, if it is one, changes.
+    fn keyword(&mut self, i: usize) -> Result<(), Rejected> {
+        let tokens = self.tokens;
+        let Token::Word(word) = &tokens[i] else {
+            return Ok(());
+        };
+        let keyword = word.to_ascii_uppercase();
+        let level = self.level();
+        match keyword.as_str() {
+            "SELECT" | "WITH" if level.call => return reject("subqueries in function arguments are not allowed"),
+            "SELECT" => {
+                level.with = false;
+                level.from = From::None;
+            }
+            "WITH" => level.with = true,
+            // Not `EXTRACT(DAY FROM ts)` and the like
+            "FROM" if !level.call => level.from = From::Table,
+            "JOIN" if i > 0 && tokens[i - 1].is_keyword("ARRAY") => level.from = From::ArrayJoin,
+            "JOIN" => level.from = From::Table,
+            "ON" | "USING" if level.from != From::None => level.from = From::Condition,
+            // `x IN table`; lists, calls and subqueries are checked once there
+            "IN" => {
+                if let Some(end) = table_end(tokens, i + 1) {
+                    if tokens.get(i + 2) != Some(&Token::Punct('(')) {
+                        self.check_table(i + 1, end)?;
+                    }
+                }
+            }
+            _ if CLAUSES.contains(&keyword.as_str()) => level.from = From::None,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Refuses the table named by `tokens[start..end]` unless reports may
+    /// read it.
+    fn check_table(&self, start: usize, end: usize) -> Result<(), Rejected> {
+        if self.tokens.get(end) == Some(&Token::Punct('(')) {
+            return reject("table functions are not allowed");
+        }
+        let (schema, table) = match &self.tokens[start..end] {
+            [table] => (None, table.name().unwrap_or_default()),
+            [schema, _, table] => (schema.name(), table.name().unwrap_or_default()),
+            _ => return reject("expected a table"),
+        };
+        let listed = self.tables.iter().any(|t| t == table);
+        let readable = match schema {
+            Some(schema) => schema == self.database && listed,
+            None => listed || self.levels.iter().any(|level| level.ctes.contains(table)),
+        };
+        if !readable {
+            let name = schema.map(|schema| format!("{}.{}", schema, table)).unwrap_or_else(|| table.to_string());
+            return reject(format!("table {} is not readable by reports", name));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `token` is a keyword a parenthesis may follow.
+fn is_keyword(token: &Token) -> bool {
+    matches!(token, Token::Word(word) if KEYWORDS.iter().any(|keyword| word.eq_ignore_ascii_case(keyword)))
+}
+
+fn banned(word: &str) -> Result<(), Rejected> {
+    for keyword in ["INTO", "OUTFILE", "SETTINGS", "FORMAT"] {
+        if word.eq_ignore_ascii_case(keyword) {
+            return reject(format!("{} is not allowed", keyword));
+        }
+    }
+    Ok(())
+}
+
+/// Where the table name starting at `tokens[start]` ends: `name` or
+/// `database.name`.
+fn table_end(tokens: &[Token], start: usize) -> Option<usize> {
+    tokens.get(start)?.name()?;
+    if tokens.get(start + 1) == Some(&Token::Punct('.')) {
+        tokens.get(start + 2)?.name()?;
+        return Some(start + 3);
+    }
+    Some(start + 1)
+}
+
+fn allowed_function(name: &str) -> bool {
+    FUNCTIONS.iter().any(|f| f.eq_ignore_ascii_case(name)) || allowed_aggregate(name)
+}
+
+fn allowed_aggregate(name: &str) -> bool {
+    AGGREGATES.iter().any(|f| f.eq_ignore_ascii_case(name))
+        || COMBINATORS.iter().any(|suffix| {
+            name.len() > suffix.len()
+                && name[name.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+                && allowed_aggregate(&name[..name.len() - suffix.len()])
+        })
+}
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, Rejected> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '-' && next == Some('-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && next == Some('*') {
+            let body: String = chars[i + 2..].iter().collect();
+            let Some(end) = body.find("*/") else {
+                return reject("unterminated comment");
+            };
+            // ClickHouse nests them
+            if body[..end].contains("/*") {
+                return reject("nested comments are not allowed");
+            }
+            i += 2 + body[..end].chars().count() + 2;
+        } else if c == '\'' {
+            i = quoted(&chars, i)?.1;
+            tokens.push(Token::Literal);
+        } else if c == '`' || c == '"' {
+            let (name, end) = quoted(&chars, i)?;
+            tokens.push(Token::Quoted(name));
+            i = end;
+        } else if c == '{' {
+            let Some(len) = chars[i + 1..].iter().position(|&c| c == '}') else {
+                return reject("unterminated query parameter");
+            };
+            let param: String = chars[i + 1..i + 1 + len].iter().collect();
+            let valid = param.split_once(':').is_some_and(|(name, kind)| {
+                !name.is_empty()
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                    && !kind.is_empty()
+                    && kind.chars().all(|c| c.is_ascii_alphanumeric() || "_(), '".contains(c))
+            });
+            if !valid {
+                return reject(format!("malformed query parameter {{{}}}", param));
+            }
+            tokens.push(Token::Param);
+            i += len + 2;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Word(chars[start..i].iter().collect()));
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Token::Literal);
+        } else if "(),.;+-*/%=<>!|[]:?".contains(c) {
+            tokens.push(Token::Punct(c));
+            i += 1;
+        } else {
+            return reject(format!("unexpected character {:?}", c));
+        }
+    }
+    Ok(tokens)
+}
+
+/// The quoted text starting at `chars[start]`, unescaped, and where it ends.
+fn quoted(chars: &[char], start: usize) -> Result<(String, usize), Rejected> {
+    let quote = chars[start];
+    let mut value = String::new();
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => {
+                value.extend(chars.get(i + 1));
+                i += 2;
+            }
+            c if c == quote && chars.get(i + 1) == Some(&quote) => {
+                value.push(quote);
+                i += 2;
+            }
+            c if c == quote => return Ok((value, i + 1)),
+            c => {
+                value.push(c);
+                i += 1;
+            }
+        }
+    }
+    reject("unterminated quote")
+}
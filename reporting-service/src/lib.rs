@@ -0,0 +1,4 @@
+//! Pieces of the reporting service shared with the tests under `tests/`;
+//! the service itself is in `main.rs`.
+
+pub mod guard;
@@ -0,0 +1,44 @@
+use crm_secrets::{EncryptionConfig, EnvelopeCipher, SecretStore};
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::info;
+
+mod config;
+mod definitions;
+mod query;
+mod render;
+mod scheduler;
+mod storage;
+
+use config::Config;
+use definitions::DefinitionStore;
+use query::QueryRunner;
+use scheduler::ReportScheduler;
+use storage::ReportStorage;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Logs, traces and panic capture
+    let _observability = crm_observability::init("reporting-service")?;
+
+    info!("Starting Reporting Service");
+
+    // Load configuration
+    let config = Config::from_env()?;
+
+    let redis_client = redis::Client::open(config.redis_url.as_str())?;
+    let definitions = DefinitionStore::new(redis_client.get_async_connection().await?);
+    // Reports show sensitive properties decrypted for their own tenant
+    let encryption = SecretStore::from_env()?.resolve(&EncryptionConfig::from_env()?).await?;
+    let runner = QueryRunner::new(&config, EnvelopeCipher::from_config(&encryption)?.map(Arc::new))?;
+    let storage = ReportStorage::new(&config.storage_url)?;
+    let scheduler = ReportScheduler::new(&config, definitions, runner, storage)?;
+
+    info!("Checking report schedules every {}s", config.scheduler_poll_interval_secs);
+
+    let mut interval = tokio::time::interval(Duration::from_secs(config.scheduler_poll_interval_secs.max(1)));
+    loop {
+        interval.tick().await;
+        scheduler.tick().await;
+    }
+}
@@ -0,0 +1,156 @@
+use crate::config::Config;
+use crm_secrets::EnvelopeCipher;
+use reporting_service::guard;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::sync::Arc;
+use tokio::time::Duration;
+
+pub type QueryError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A query result in column order, ready to render.
+#[derive(Debug)]
+pub struct Table {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Column {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonCompact {
+    meta: Vec<Column>,
+    data: Vec<Vec<Value>>,
+}
+
+/// Runs report queries over the ClickHouse HTTP interface. Parameters are
+/// sent as `param_<name>` so values are never spliced into the SQL, and the
+/// session is read-only. Report SQL is written by tenants, so it is checked
+/// by [`guard::check`] first: it may only read the configured report tables
+/// that have a `tenant_id` column, and only call ordinary functions. Those
+/// tables are then filtered to the report's tenant with
+/// `additional_table_filters`, whatever the query itself filters on.
+/// Encrypted event properties in the result are decrypted with the
+/// tenant's keys.
+pub struct QueryRunner {
+    http: reqwest::Client,
+    url: String,
+    user: String,
+    password: String,
+    database: String,
+    report_tables: Vec<String>,
+    max_result_rows: u64,
+    cipher: Option<Arc<EnvelopeCipher>>,
+}
+
+impl QueryRunner {
+    pub fn new(config: &Config, cipher: Option<Arc<EnvelopeCipher>>) -> Result<Self, Box<dyn std::error::Error>> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.query_timeout_secs))
+            .build()?;
+
+        Ok(QueryRunner {
+            http,
+            url: config.clickhouse_url.trim_end_matches('/').to_string(),
+            user: config.clickhouse_user.clone(),
+            password: config.clickhouse_password.clone(),
+            database: config.clickhouse_database.clone(),
+            report_tables: config.report_tables.clone(),
+            max_result_rows: config.max_result_rows,
+            cipher,
+        })
+    }
+
+    pub async fn run(&self, tenant_id: &str, sql: &str, params: &Map<String, Value>) -> Result<Table, QueryError> {
+        let tables = self.tenant_tables().await?;
+        // A report table without a tenant_id column can't be filtered
+        let readable: Vec<String> = tables
+            .iter()
+            .filter(|table| self.report_tables.contains(table))
+            .cloned()
+            .collect();
+        guard::check(sql, &self.database, &readable)?;
+
+        let filter = quote(&format!("tenant_id = {}", quote(tenant_id)));
+        let filters: Vec<String> = tables
+            .iter()
+            .flat_map(|table| [table.clone(), format!("{}.{}", self.database, table)])
+            .map(|table| format!("{}:{}", quote(&table), filter))
+            .collect();
+
+        let max_result_rows = self.max_result_rows.to_string();
+        let mut query: Vec<(String, String)> = vec![
+            ("max_result_rows".to_string(), max_result_rows),
+            ("result_overflow_mode".to_string(), "throw".to_string()),
+            ("output_format_json_quote_64bit_integers".to_string(), "0".to_string()),
+            ("additional_table_filters".to_string(), format!("{{{}}}", filters.join(","))),
+        ];
+        for (name, value) in params {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            query.push((format!("param_{}", name), value));
+        }
+        // Bound last so a definition can't override it
+        query.push(("param_tenant_id".to_string(), tenant_id.to_string()));
+
+        let mut result = self.post(query, sql).await?;
+        if let Some(cipher) = &self.cipher {
+            for cell in result.data.iter_mut().flatten() {
+                cipher.decrypt_json(tenant_id, cell).await?;
+            }
+        }
+        Ok(Table {
+            columns: result.meta.into_iter().map(|c| c.name).collect(),
+            rows: result.data,
+        })
+    }
+
+    /// Tables and views of the database holding more than one tenant's rows.
+    async fn tenant_tables(&self) -> Result<Vec<String>, QueryError> {
+        let query = vec![("param_database".to_string(), self.database.clone())];
+        let sql = "SELECT DISTINCT table FROM system.columns WHERE database = {database:String} AND name = 'tenant_id'";
+        let result = self.post(query, sql).await?;
+        Ok(result
+            .data
+            .into_iter()
+            .filter_map(|row| match row.into_iter().next() {
+                Some(Value::String(table)) => Some(table),
+                _ => None,
+            })
+            .collect())
+    }
+
+    async fn post(&self, mut query: Vec<(String, String)>, sql: &str) -> Result<JsonCompact, QueryError> {
+        query.extend([
+            ("database".to_string(), self.database.clone()),
+            ("default_format".to_string(), "JSONCompact".to_string()),
+            ("readonly".to_string(), "1".to_string()),
+        ]);
+        let response = self
+            .http
+            .post(&self.url)
+            .header("X-ClickHouse-User", &self.user)
+            .header("X-ClickHouse-Key", &self.password)
+            .query(&query)
+            .body(sql.to_string())
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("ClickHouse returned {}: {}", status, body.trim()).into());
+        }
+        Ok(response.json().await?)
+    }
+}
+
+/// `value` as a ClickHouse string literal.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
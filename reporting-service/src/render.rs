@@ -0,0 +1,59 @@
+use crate::definitions::ReportFormat;
+use crate::query::Table;
+use rust_xlsxwriter::{Format, Workbook};
+use serde_json::Value;
+
+pub type RenderError = Box<dyn std::error::Error + Send + Sync>;
+
+pub fn render(table: &Table, format: ReportFormat) -> Result<Vec<u8>, RenderError> {
+    match format {
+        ReportFormat::Csv => render_csv(table),
+        ReportFormat::Xlsx => render_xlsx(table),
+    }
+}
+
+fn render_csv(table: &Table) -> Result<Vec<u8>, RenderError> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(&table.columns)?;
+    for row in &table.rows {
+        writer.write_record(row.iter().map(cell_text))?;
+    }
+    Ok(writer.into_inner().map_err(|e| e.to_string())?)
+}
+
+fn render_xlsx(table: &Table) -> Result<Vec<u8>, RenderError> {
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    let header = Format::new().set_bold();
+
+    for (col, name) in table.columns.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, name, &header)?;
+    }
+    for (i, row) in table.rows.iter().enumerate() {
+        let r = i as u32 + 1;
+        for (col, value) in row.iter().enumerate() {
+            match value {
+                Value::Number(n) => {
+                    sheet.write_number(r, col as u16, n.as_f64().unwrap_or_default())?;
+                }
+                Value::Bool(b) => {
+                    sheet.write_boolean(r, col as u16, *b)?;
+                }
+                Value::Null => {}
+                other => {
+                    sheet.write_string(r, col as u16, cell_text(other))?;
+                }
+            }
+        }
+    }
+
+    Ok(workbook.save_to_buffer()?)
+}
+
+fn cell_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
@@ -0,0 +1,144 @@
+use crate::config::Config;
+use crate::definitions::{DefinitionStore, ReportDefinition};
+use crate::query::QueryRunner;
+use crate::render;
+use crate::storage::ReportStorage;
+use chrono::{TimeZone, Utc};
+use crm_events::{CrmEvent, ReportReady};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::ClientConfig;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::Duration;
+use tracing::{debug, error, info};
+
+type ReportError = Box<dyn std::error::Error + Send + Sync>;
+
+const EVENT_SOURCE: &str = "reporting-service";
+
+pub struct ReportScheduler {
+    definitions: DefinitionStore,
+    runner: QueryRunner,
+    storage: ReportStorage,
+    producer: FutureProducer,
+    notification_topic: String,
+}
+
+impl ReportScheduler {
+    pub fn new(
+        config: &Config,
+        definitions: DefinitionStore,
+        runner: QueryRunner,
+        storage: ReportStorage,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", &config.kafka_brokers)
+            .set("message.timeout.ms", "5000")
+            .create()?;
+
+        Ok(ReportScheduler {
+            definitions,
+            runner,
+            storage,
+            producer,
+            notification_topic: config.notification_topic.clone(),
+        })
+    }
+
+    /// Runs every report whose next scheduled time has passed. Missed
+    /// occurrences (e.g. while the service was down) collapse into one run.
+    pub async fn tick(&self) {
+        let tenants = match self.definitions.tenants().await {
+            Ok(tenants) => tenants,
+            Err(e) => {
+                error!("Failed to list report tenants: {}", e);
+                return;
+            }
+        };
+
+        for tenant_id in tenants {
+            let definitions = match self.definitions.definitions(&tenant_id).await {
+                Ok(definitions) => definitions,
+                Err(e) => {
+                    error!("Failed to load reports for tenant {}: {}", tenant_id, e);
+                    continue;
+                }
+            };
+
+            for definition in definitions.iter().filter(|d| d.active) {
+                if let Err(e) = self.run_if_due(&tenant_id, definition).await {
+                    error!("Report {} for tenant {} failed: {}", definition.id, tenant_id, e);
+                }
+            }
+        }
+    }
+
+    async fn run_if_due(&self, tenant_id: &str, definition: &ReportDefinition) -> Result<(), ReportError> {
+        let now = now();
+        let last_run = match self.definitions.last_run(tenant_id, &definition.id).await? {
+            Some(last_run) => last_run,
+            None => {
+                // New definitions start from their next occurrence
+                self.definitions.set_last_run(tenant_id, &definition.id, now).await?;
+                return Ok(());
+            }
+        };
+
+        let scheduled_at = match definition.next_run_after(last_run)? {
+            Some(at) if at <= now => at,
+            _ => return Ok(()),
+        };
+        if !self.definitions.claim(tenant_id, &definition.id, scheduled_at).await? {
+            debug!("Report {} for tenant {} already claimed", definition.id, tenant_id);
+            return Ok(());
+        }
+        self.definitions.set_last_run(tenant_id, &definition.id, now).await?;
+
+        self.run(tenant_id, definition, scheduled_at).await
+    }
+
+    async fn run(&self, tenant_id: &str, definition: &ReportDefinition, scheduled_at: i64) -> Result<(), ReportError> {
+        let table = self.runner.run(tenant_id, &definition.query, &definition.params).await?;
+        let body = render::render(&table, definition.format)?;
+
+        let stamp = Utc
+            .timestamp_opt(scheduled_at, 0)
+            .single()
+            .map(|t| t.format("%Y%m%dT%H%M%SZ").to_string())
+            .unwrap_or_else(|| scheduled_at.to_string());
+        let file_name = format!("{}-{}.{}", definition.id, stamp, definition.format.extension());
+        let location = self.storage.upload(tenant_id, &definition.id, &file_name, body).await?;
+
+        info!(
+            "Report {} for tenant {} ready at {} ({} rows)",
+            definition.id, tenant_id, location, table.rows.len()
+        );
+
+        let ready = ReportReady {
+            report_id: definition.id.clone(),
+            name: definition.name.clone(),
+            format: definition.format.extension().to_string(),
+            location,
+            row_count: table.rows.len() as u64,
+            recipients: definition.recipients.clone(),
+        };
+        let event = CrmEvent::typed(tenant_id, &ready)?.source(EVENT_SOURCE).build();
+        let payload = serde_json::to_string(&event)?;
+
+        let record = FutureRecord::to(&self.notification_topic)
+            .key(tenant_id)
+            .payload(&payload);
+        self.producer
+            .send(record, Duration::from_secs(5))
+            .await
+            .map_err(|(e, _)| e)?;
+
+        Ok(())
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,49 @@
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::{ObjectStore, PutPayload};
+use std::sync::Arc;
+use tracing::info;
+use url::Url;
+
+pub type StorageError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Uploads rendered reports under `{root}/{tenant_id}/{report_id}/`.
+pub struct ReportStorage {
+    store: Arc<dyn ObjectStore>,
+    root: Path,
+    root_url: String,
+}
+
+impl ReportStorage {
+    pub fn new(storage_url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let url = Url::parse(storage_url)?;
+        let (store, root): (Arc<dyn ObjectStore>, Path) = match url.scheme() {
+            "s3" | "s3a" => {
+                let store = AmazonS3Builder::from_env().with_url(storage_url).build()?;
+                (Arc::new(store), Path::from_url_path(url.path())?)
+            }
+            _ => {
+                let (store, path) = object_store::parse_url(&url)?;
+                (Arc::from(store), path)
+            }
+        };
+
+        info!("Reports stored under {}", storage_url);
+
+        Ok(ReportStorage {
+            store,
+            root,
+            root_url: storage_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// Stores the file and returns its URL.
+    pub async fn upload(&self, tenant_id: &str, report_id: &str, file_name: &str, body: Vec<u8>) -> Result<String, StorageError> {
+        let relative = format!("{}/{}/{}", tenant_id, report_id, file_name);
+        let path = self.root.child(tenant_id).child(report_id).child(file_name);
+
+        self.store.put(&path, PutPayload::from(body)).await?;
+
+        Ok(format!("{}/{}", self.root_url, relative))
+    }
+}
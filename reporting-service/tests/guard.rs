@@ -0,0 +1,110 @@
+use reporting_service::guard::check;
+
+const DATABASE: &str = "crm_analytics";
+
+fn tables() -> Vec<String> {
+    ["events", "page_views", "daily_stats"].iter().map(|t| t.to_string()).collect()
+}
+
+fn refused(sql: &str) -> String {
+    match check(sql, DATABASE, &tables()) {
+        Ok(()) => panic!("allowed: {}", sql),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[test]
+fn allows_report_queries() {
+    let allowed = [
+        "SELECT event_type, count() FROM events WHERE timestamp >= {from:DateTime} GROUP BY event_type",
+        "SELECT countIf(event_type = 'click') AS clicks, uniqExact(user_id) FROM crm_analytics.events",
+        "SELECT toStartOfDay(timestamp) AS day, sum(views) FROM daily_stats GROUP BY day ORDER BY day LIMIT 30",
+        "SELECT e.session_id, p.url FROM events AS e INNER JOIN page_views AS p ON e.session_id = p.session_id",
+        "WITH recent AS (SELECT * FROM events WHERE timestamp > now() - INTERVAL 7 DAY) SELECT count() FROM recent",
+        "SELECT * FROM events WHERE user_id IN (SELECT user_id FROM page_views WHERE url LIKE '%pricing%')",
+        "SELECT tag FROM events ARRAY JOIN tags AS tag",
+        "SELECT EXTRACT(DAY FROM timestamp), quantile(0.9)(duration_ms) FROM page_views GROUP BY 1;",
+        "SELECT * FROM (SELECT session_id FROM events) AS s, page_views WHERE s.session_id = page_views.session_id",
+        "SELECT 1 -- a comment naming system.tables\n",
+    ];
+    for sql in allowed {
+        assert_eq!(check(sql, DATABASE, &tables()), Ok(()), "{}", sql);
+    }
+}
+
+#[test]
+fn refuses_table_functions() {
+    for sql in [
+        "SELECT * FROM remote('other-host', 'crm_analytics', 'events')",
+        "SELECT * FROM url('http://169.254.169.254/latest/meta-data', 'RawBLOB')",
+        "SELECT * FROM file('/etc/passwd', 'LineAsString')",
+        "SELECT * FROM cluster('default', crm_analytics.events)",
+        "SELECT * FROM numbers(1000000000)",
+        "SELECT * FROM events, `remote`('other-host', 'db', 'events')",
+        "SELECT * FROM events JOIN s3('https://bucket/key') ON 1",
+    ] {
+        assert!(refused(sql).contains("table functions"), "{}", sql);
+    }
+}
+
+#[test]
+fn refuses_functions_outside_the_allowlist() {
+    for sql in [
+        "SELECT dictGet('tenants', 'name', toUInt64(1))",
+        "SELECT \"dictGetString\"('tenants', 'name', 1) FROM events",
+        "SELECT currentUser()",
+        "SELECT remoteIf(1)",
+        "SELECT count(*) FROM events WHERE getSetting('readonly') = 1",
+    ] {
+        assert!(refused(sql).contains("is not allowed"), "{}", sql);
+    }
+}
+
+#[test]
+fn refuses_other_tables() {
+    for sql in [
+        "SELECT * FROM system.tables",
+        "SELECT * FROM \"system\".\"users\"",
+        "SELECT * FROM other_db.events",
+        "SELECT * FROM identity_map",
+        "SELECT * FROM events, system.clusters",
+        "SELECT * FROM events LEFT JOIN system.processes AS p ON 1",
+        "SELECT * FROM events WHERE user_id IN system.users",
+        "SELECT * FROM events WHERE user_id IN (admin_audit)",
+        "SELECT * FROM (SELECT * FROM system.settings)",
+        "SELECT * FROM events WHERE user_id IN (SELECT name FROM system.users)",
+        "SELECT length((SELECT name FROM system.users LIMIT 1)) FROM events",
+    ] {
+        assert!(refused(sql).contains("not readable"), "{}", sql);
+    }
+}
+
+#[test]
+fn ctes_only_shadow_tables_after_their_definition() {
+    // The body of `identity_map` can't refer to itself to read the real table
+    let sql = "WITH identity_map AS (SELECT * FROM identity_map) SELECT * FROM identity_map";
+    assert!(refused(sql).contains("not readable"));
+
+    // A CTE is only visible in the query that defines it
+    let sql = "SELECT * FROM (WITH identity_map AS (SELECT 1) SELECT * FROM identity_map), identity_map";
+    assert!(refused(sql).contains("not readable"));
+}
+
+#[test]
+fn refuses_statements_and_clauses_outside_a_select() {
+    for sql in [
+        "INSERT INTO events SELECT * FROM events",
+        "SHOW TABLES",
+        "SELECT 1; SELECT * FROM system.users",
+        "SELECT * FROM events INTO OUTFILE '/tmp/out.csv'",
+        "SELECT * FROM events SETTINGS readonly = 0",
+        "SELECT * FROM events FORMAT Native",
+        "SELECT count(SELECT 1)",
+        "SELECT * FROM events /* a /* nested */ comment */",
+        "SELECT * FROM events WHERE x = {bad name:String}",
+        "SELECT $$raw$$",
+        "SELECT * FROM events WHERE (1",
+    ] {
+        refused(sql);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::config::Config;
+use serde::Deserialize;
+use tokio::time::Duration;
+
+pub type QueryError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A contact's rolled-up activity over the lookback window.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ContactActivity {
+    pub contact_id: String,
+    pub recency_days: u32,
+    pub interactions: u64,
+    pub engagements: u64,
+    pub revenue: f64,
+}
+
+/// Reads `contact_activity_daily` over the ClickHouse HTTP interface.
+pub struct ActivityReader {
+    http: reqwest::Client,
+    url: String,
+    user: String,
+    password: String,
+    database: String,
+    lookback_days: u32,
+}
+
+impl ActivityReader {
+    pub fn new(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.query_timeout_secs))
+            .build()?;
+
+        Ok(ActivityReader {
+            http,
+            url: config.clickhouse_url.trim_end_matches('/').to_string(),
+            user: config.clickhouse_user.clone(),
+            password: config.clickhouse_password.clone(),
+            database: config.clickhouse_database.clone(),
+            lookback_days: config.lookback_days,
+        })
+    }
+
+    /// Tenants with any contact activity in the window.
+    pub async fn tenants(&self) -> Result<Vec<String>, QueryError> {
+        let body = self
+            .query(
+                "SELECT DISTINCT tenant_id FROM contact_activity_daily \
+                 WHERE date >= today() - {lookback:UInt32} \
+                 FORMAT TabSeparated",
+                &[],
+            )
+            .await?;
+        Ok(body.lines().filter(|l| !l.is_empty()).map(str::to_string).collect())
+    }
+
+    pub async fn contacts(&self, tenant_id: &str) -> Result<Vec<ContactActivity>, QueryError> {
+        let body = self
+            .query(
+                "SELECT contact_id, \
+                        toUInt32(dateDiff('day', max(date), today())) AS recency_days, \
+                        sum(interactions) AS interactions, \
+                        sum(engagements) AS engagements, \
+                        sum(revenue) AS revenue \
+                 FROM contact_activity_daily \
+                 WHERE tenant_id = {tenant_id:String} AND date >= today() - {lookback:UInt32} \
+                 GROUP BY contact_id \
+                 FORMAT JSONEachRow",
+                &[("tenant_id", tenant_id.to_string())],
+            )
+            .await?;
+
+        body.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(Into::into))
+            .collect()
+    }
+
+    async fn query(&self, sql: &str, params: &[(&str, String)]) -> Result<String, QueryError> {
+        let mut query: Vec<(String, String)> = vec![
+            ("database".to_string(), self.database.clone()),
+            ("readonly".to_string(), "1".to_string()),
+            ("output_format_json_quote_64bit_integers".to_string(), "0".to_string()),
+            ("param_lookback".to_string(), self.lookback_days.to_string()),
+        ];
+        for (name, value) in params {
+            query.push((format!("param_{}", name), value.clone()));
+        }
+
+        let response = self
+            .http
+            .post(&self.url)
+            .header("X-ClickHouse-User", &self.user)
+            .header("X-ClickHouse-Key", &self.password)
+            .query(&query)
+            .body(sql.to_string())
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("ClickHouse returned {}: {}", status, body.trim()).into());
+        }
+
+        Ok(response.text().await?)
+    }
+}
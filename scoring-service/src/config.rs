@@ -0,0 +1,72 @@
+use crm_config::{ConfigLoader, Configurable};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub kafka_brokers: String,
+    /// Topic `score_updated` events go to; the workflow engine consumes it.
+    pub events_topic: String,
+    pub redis_url: String,
+    pub clickhouse_url: String,
+    pub clickhouse_user: String,
+    pub clickhouse_password: String,
+    pub clickhouse_database: String,
+    pub query_timeout_secs: u64,
+    pub score_interval_secs: u64,
+    /// Activity older than this doesn't count towards scores.
+    pub lookback_days: u32,
+    /// Days for the engagement score to halve without new activity.
+    pub engagement_half_life_days: f64,
+    /// Engagements at which the engagement score reaches ~63% before decay.
+    pub engagement_saturation: f64,
+    /// Engagement changes smaller than this don't emit `score_updated`.
+    pub engagement_change_threshold: f64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            kafka_brokers: "localhost:9092".to_string(),
+            events_topic: "crm-events".to_string(),
+            redis_url: "redis://localhost:6379".to_string(),
+            clickhouse_url: "http://localhost:8123".to_string(),
+            clickhouse_user: "default".to_string(),
+            clickhouse_password: "".to_string(),
+            clickhouse_database: "crm_analytics".to_string(),
+            query_timeout_secs: 120,
+            score_interval_secs: 3600,
+            lookback_days: 365,
+            engagement_half_life_days: 30.0,
+            engagement_saturation: 10.0,
+            engagement_change_threshold: 10.0,
+        }
+    }
+}
+
+impl Configurable for Config {
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if self.score_interval_secs == 0 {
+            problems.push("score_interval_secs must be positive".to_string());
+        }
+        if self.lookback_days == 0 {
+            problems.push("lookback_days must be positive".to_string());
+        }
+        if self.engagement_half_life_days <= 0.0 {
+            problems.push("engagement_half_life_days must be positive".to_string());
+        }
+        if self.engagement_saturation <= 0.0 {
+            problems.push("engagement_saturation must be positive".to_string());
+        }
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+}
+
+impl Config {
+    /// Defaults, then the file named by `CONFIG_FILE`, then environment
+    /// variables named after the fields (e.g. `SCORE_INTERVAL_SECS`).
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(ConfigLoader::new().file_from_env("CONFIG_FILE").load()?)
+    }
+}
@@ -0,0 +1,38 @@
+use tokio::time::Duration;
+use tracing::info;
+
+mod activity;
+mod config;
+mod scheduler;
+mod scores;
+mod store;
+
+use activity::ActivityReader;
+use config::Config;
+use scheduler::Scorer;
+use store::ScoreStore;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Logs, traces and panic capture
+    let _observability = crm_observability::init("scoring-service")?;
+
+    info!("Starting Scoring Service");
+
+    // Load configuration
+    let config = Config::from_env()?;
+    info!("Configuration: {}", crm_config::redacted(&config));
+
+    let redis_client = redis::Client::open(config.redis_url.as_str())?;
+    let store = ScoreStore::new(redis_client.get_async_connection().await?);
+    let reader = ActivityReader::new(&config)?;
+    let scorer = Scorer::new(&config, reader, store)?;
+
+    info!("Scoring contacts every {}s", config.score_interval_secs);
+
+    let mut interval = tokio::time::interval(Duration::from_secs(config.score_interval_secs));
+    loop {
+        interval.tick().await;
+        scorer.tick().await;
+    }
+}
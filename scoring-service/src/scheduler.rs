@@ -0,0 +1,125 @@
+use crate::activity::ActivityReader;
+use crate::config::Config;
+use crate::scores::{self, ContactScore};
+use crate::store::{self, ScoreStore, StoredScore};
+use crm_events::{CrmEvent, ScoreUpdated};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::ClientConfig;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::Duration;
+use tracing::{error, info};
+
+type ScoreError = Box<dyn std::error::Error + Send + Sync>;
+
+const EVENT_SOURCE: &str = "scoring-service";
+
+pub struct Scorer {
+    reader: ActivityReader,
+    store: ScoreStore,
+    producer: FutureProducer,
+    config: Config,
+}
+
+impl Scorer {
+    pub fn new(config: &Config, reader: ActivityReader, store: ScoreStore) -> Result<Self, Box<dyn std::error::Error>> {
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", &config.kafka_brokers)
+            .set("message.timeout.ms", "5000")
+            .create()?;
+
+        Ok(Scorer {
+            reader,
+            store,
+            producer,
+            config: config.clone(),
+        })
+    }
+
+    /// Rescores every tenant with recent activity.
+    pub async fn tick(&self) {
+        let tenants = match self.reader.tenants().await {
+            Ok(tenants) => tenants,
+            Err(e) => {
+                error!("Failed to list tenants to score: {}", e);
+                return;
+            }
+        };
+
+        for tenant_id in tenants {
+            // Held until the next interval so replicas don't rescore the tenant
+            match self.store.claim(&tenant_id, self.config.score_interval_secs).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    error!("Failed to claim scoring run for tenant {}: {}", tenant_id, e);
+                    continue;
+                }
+            }
+
+            if let Err(e) = self.score_tenant(&tenant_id).await {
+                error!("Scoring failed for tenant {}: {}", tenant_id, e);
+            }
+        }
+    }
+
+    async fn score_tenant(&self, tenant_id: &str) -> Result<(), ScoreError> {
+        let contacts = self.reader.contacts(tenant_id).await?;
+        let scores = scores::score(&contacts, &self.config);
+        let now = now_secs();
+
+        let mut changed = 0;
+        for score in &scores {
+            let previous = self.store.previous(tenant_id, &score.contact_id).await?;
+            self.store.save(tenant_id, score, now).await?;
+
+            if self.has_changed(previous.as_ref(), score) {
+                self.publish(tenant_id, score, previous).await?;
+                changed += 1;
+            }
+        }
+
+        info!("Scored {} contacts for tenant {} ({} changed)", scores.len(), tenant_id, changed);
+        Ok(())
+    }
+
+    fn has_changed(&self, previous: Option<&StoredScore>, score: &ContactScore) -> bool {
+        match previous {
+            None => true,
+            Some(previous) => {
+                previous.rfm != store::rfm(score)
+                    || previous.segment != score.segment
+                    || (previous.engagement - score.engagement).abs() >= self.config.engagement_change_threshold
+            }
+        }
+    }
+
+    async fn publish(&self, tenant_id: &str, score: &ContactScore, previous: Option<StoredScore>) -> Result<(), ScoreError> {
+        let payload = ScoreUpdated {
+            contact_id: score.contact_id.clone(),
+            recency: score.recency,
+            frequency: score.frequency,
+            monetary: score.monetary,
+            engagement: score.engagement,
+            segment: score.segment.to_string(),
+            previous_segment: previous.map(|p| p.segment).filter(|s| s != score.segment),
+        };
+        let event = CrmEvent::typed(tenant_id, &payload)?.source(EVENT_SOURCE).build();
+        let body = serde_json::to_string(&event)?;
+
+        self.producer
+            .send(
+                FutureRecord::to(&self.config.events_topic).key(tenant_id).payload(&body),
+                Duration::from_secs(5),
+            )
+            .await
+            .map_err(|(e, _)| e)?;
+        Ok(())
+    }
+}
+
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
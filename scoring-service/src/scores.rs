@@ -0,0 +1,71 @@
+use crate::activity::ContactActivity;
+use crate::config::Config;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContactScore {
+    pub contact_id: String,
+    /// Quintiles within the tenant, 1 (lowest) to 5 (highest); recent
+    /// activity scores high.
+    pub recency: u8,
+    pub frequency: u8,
+    pub monetary: u8,
+    pub engagement: f64,
+    pub segment: &'static str,
+}
+
+/// Scores every contact of one tenant against the others.
+pub fn score(contacts: &[ContactActivity], config: &Config) -> Vec<ContactScore> {
+    // Fewer days since the last activity is better, so rank on the negation
+    let recency = quintiles(contacts, |c| -(c.recency_days as f64));
+    let frequency = quintiles(contacts, |c| c.interactions as f64);
+    let monetary = quintiles(contacts, |c| c.revenue);
+
+    contacts
+        .iter()
+        .enumerate()
+        .map(|(i, contact)| ContactScore {
+            contact_id: contact.contact_id.clone(),
+            recency: recency[i],
+            frequency: frequency[i],
+            monetary: monetary[i],
+            engagement: engagement(contact, config),
+            segment: segment(recency[i], frequency[i], monetary[i]),
+        })
+        .collect()
+}
+
+/// 1-5 by rank; ties share the quintile of their first position.
+fn quintiles(contacts: &[ContactActivity], value: impl Fn(&ContactActivity) -> f64) -> Vec<u8> {
+    let mut order: Vec<usize> = (0..contacts.len()).collect();
+    order.sort_by(|a, b| value(&contacts[*a]).total_cmp(&value(&contacts[*b])));
+
+    let mut result = vec![1; contacts.len()];
+    let mut tie_start = 0;
+    for (rank, &index) in order.iter().enumerate() {
+        if rank > 0 && value(&contacts[index]) != value(&contacts[order[rank - 1]]) {
+            tie_start = rank;
+        }
+        let quintile = (tie_start * 5).checked_div(contacts.len()).unwrap_or(0);
+        result[index] = quintile as u8 + 1;
+    }
+    result
+}
+
+/// 0-100: saturates with the number of engagements and halves every
+/// `engagement_half_life_days` without activity.
+fn engagement(contact: &ContactActivity, config: &Config) -> f64 {
+    let volume = 1.0 - (-(contact.engagements as f64) / config.engagement_saturation).exp();
+    let decay = 0.5f64.powf(contact.recency_days as f64 / config.engagement_half_life_days);
+    (volume * decay * 1000.0).round() / 10.0
+}
+
+fn segment(recency: u8, frequency: u8, monetary: u8) -> &'static str {
+    match (recency, frequency, monetary) {
+        (r, f, m) if r >= 4 && f >= 4 && m >= 4 => "champion",
+        (r, f, _) if r >= 3 && f >= 4 => "loyal",
+        (r, f, _) if r >= 4 && f <= 2 => "new",
+        (r, f, _) if r <= 2 && f >= 3 => "at_risk",
+        (r, _, _) if r <= 2 => "hibernating",
+        _ => "regular",
+    }
+}
@@ -0,0 +1,90 @@
+use crate::scores::ContactScore;
+use redis::aio::Connection;
+use redis::AsyncCommands;
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+
+pub type StoreError = Box<dyn std::error::Error + Send + Sync>;
+
+/// What was stored for a contact on the previous run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredScore {
+    pub rfm: String,
+    pub engagement: f64,
+    pub segment: String,
+}
+
+/// Scores in Redis:
+///
+/// - `contact_score:{tenant_id}:{contact_id}` hash with `recency`,
+///   `frequency`, `monetary`, `rfm` (e.g. `545`), `engagement`, `segment`
+///   and `updated_at`
+/// - `contact_engagement:{tenant_id}` sorted set of contacts by engagement,
+///   for "most engaged" lists
+pub struct ScoreStore {
+    conn: Mutex<Connection>,
+}
+
+impl ScoreStore {
+    pub fn new(conn: Connection) -> Self {
+        ScoreStore { conn: Mutex::new(conn) }
+    }
+
+    /// Claims the tenant's run so only one replica scores it per interval.
+    pub async fn claim(&self, tenant_id: &str, ttl_secs: u64) -> Result<bool, StoreError> {
+        let claimed: Option<String> = redis::cmd("SET")
+            .arg(format!("score_lock:{}", tenant_id))
+            .arg("1")
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl_secs)
+            .query_async(&mut *self.conn.lock().await)
+            .await?;
+        Ok(claimed.is_some())
+    }
+
+    pub async fn previous(&self, tenant_id: &str, contact_id: &str) -> Result<Option<StoredScore>, StoreError> {
+        let fields: HashMap<String, String> = self
+            .conn
+            .lock()
+            .await
+            .hgetall(score_key(tenant_id, contact_id))
+            .await?;
+        if fields.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(StoredScore {
+            rfm: fields.get("rfm").cloned().unwrap_or_default(),
+            engagement: fields.get("engagement").and_then(|e| e.parse().ok()).unwrap_or_default(),
+            segment: fields.get("segment").cloned().unwrap_or_default(),
+        }))
+    }
+
+    pub async fn save(&self, tenant_id: &str, score: &ContactScore, updated_at: i64) -> Result<(), StoreError> {
+        let fields = [
+            ("recency", score.recency.to_string()),
+            ("frequency", score.frequency.to_string()),
+            ("monetary", score.monetary.to_string()),
+            ("rfm", rfm(score)),
+            ("engagement", score.engagement.to_string()),
+            ("segment", score.segment.to_string()),
+            ("updated_at", updated_at.to_string()),
+        ];
+
+        let _: () = redis::pipe()
+            .hset_multiple(score_key(tenant_id, &score.contact_id), &fields)
+            .zadd(format!("contact_engagement:{}", tenant_id), &score.contact_id, score.engagement)
+            .query_async(&mut *self.conn.lock().await)
+            .await?;
+        Ok(())
+    }
+}
+
+pub fn rfm(score: &ContactScore) -> String {
+    format!("{}{}{}", score.recency, score.frequency, score.monetary)
+}
+
+fn score_key(tenant_id: &str, contact_id: &str) -> String {
+    format!("contact_score:{}:{}", tenant_id, contact_id)
+}
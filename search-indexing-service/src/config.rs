@@ -0,0 +1,49 @@
+use std::env;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub kafka_brokers: String,
+    pub kafka_group_id: String,
+    pub kafka_topics: Vec<String>,
+    pub meilisearch_url: String,
+    pub meilisearch_api_key: Option<String>,
+    /// Indices are named `{index_prefix}_{tenant_id}_{entity}`.
+    pub index_prefix: String,
+    pub clickhouse_url: String,
+    pub clickhouse_user: String,
+    pub clickhouse_password: String,
+    pub clickhouse_database: String,
+    /// Table holding entity events, used when reindexing.
+    pub clickhouse_events_table: String,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Config {
+            kafka_brokers: env::var("KAFKA_BROKERS")
+                .unwrap_or_else(|_| "localhost:9092".to_string()),
+            kafka_group_id: env::var("KAFKA_GROUP_ID")
+                .unwrap_or_else(|_| "search-indexing-group".to_string()),
+            kafka_topics: env::var("KAFKA_TOPICS")
+                .unwrap_or_else(|_| "crm-events".to_string())
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .collect(),
+            meilisearch_url: env::var("MEILISEARCH_URL")
+                .unwrap_or_else(|_| "http://localhost:7700".to_string()),
+            meilisearch_api_key: env::var("MEILISEARCH_API_KEY").ok(),
+            index_prefix: env::var("INDEX_PREFIX")
+                .unwrap_or_else(|_| "crm".to_string()),
+            clickhouse_url: env::var("CLICKHOUSE_URL")
+                .unwrap_or_else(|_| "http://localhost:8123".to_string()),
+            clickhouse_user: env::var("CLICKHOUSE_USER")
+                .unwrap_or_else(|_| "default".to_string()),
+            clickhouse_password: env::var("CLICKHOUSE_PASSWORD")
+                .unwrap_or_else(|_| "".to_string()),
+            clickhouse_database: env::var("CLICKHOUSE_DATABASE")
+                .unwrap_or_else(|_| "crm_analytics".to_string()),
+            clickhouse_events_table: env::var("CLICKHOUSE_EVENTS_TABLE")
+                .unwrap_or_else(|_| "events".to_string()),
+        })
+    }
+}
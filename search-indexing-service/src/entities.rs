@@ -0,0 +1,108 @@
+use serde_json::{Map, Value};
+
+/// CRM entities projected into search. Each gets its own index per tenant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Entity {
+    Contact,
+    Lead,
+    Deal,
+}
+
+pub const ALL_ENTITIES: [Entity; 3] = [Entity::Contact, Entity::Lead, Entity::Deal];
+
+impl Entity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Entity::Contact => "contact",
+            Entity::Lead => "lead",
+            Entity::Deal => "deal",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        ALL_ENTITIES.into_iter().find(|e| e.name() == name)
+    }
+
+    pub fn index_suffix(self) -> &'static str {
+        match self {
+            Entity::Contact => "contacts",
+            Entity::Lead => "leads",
+            Entity::Deal => "deals",
+        }
+    }
+
+    /// Fields searched for free-text queries, in ranking order.
+    pub fn searchable_attributes(self) -> &'static [&'static str] {
+        match self {
+            Entity::Contact => &["first_name", "last_name", "email", "company", "phone"],
+            Entity::Lead => &["name", "email", "company", "source"],
+            Entity::Deal => &["name", "account_name", "stage"],
+        }
+    }
+
+    pub fn filterable_attributes(self) -> &'static [&'static str] {
+        match self {
+            Entity::Contact => &["owner_id", "company"],
+            Entity::Lead => &["owner_id", "status", "source"],
+            Entity::Deal => &["owner_id", "stage", "amount"],
+        }
+    }
+
+    pub fn sortable_attributes(self) -> &'static [&'static str] {
+        match self {
+            Entity::Contact | Entity::Lead => &["updated_at"],
+            Entity::Deal => &["updated_at", "amount"],
+        }
+    }
+
+    /// Event types that change this entity, used to select rows when reindexing.
+    pub fn event_types(self) -> Vec<String> {
+        let mut types: Vec<String> = ["created", "updated", "deleted"]
+            .iter()
+            .map(|op| format!("{}_{}", self.name(), op))
+            .collect();
+        if self == Entity::Deal {
+            types.push("deal_won".to_string());
+        }
+        types
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum EntityChange {
+    Upsert { entity: Entity, document: Map<String, Value> },
+    Delete { entity: Entity, id: String },
+}
+
+/// Maps an entity event (`contact_created`, `deal_updated`, ...) to an index
+/// change. The document id comes from `id` or `<entity>_id` in the payload.
+pub fn parse_change(event_type: &str, payload: &Value, timestamp: i64) -> Option<EntityChange> {
+    let (entity, op) = match event_type {
+        "deal_won" => (Entity::Deal, "updated"),
+        _ => {
+            let (name, op) = event_type.rsplit_once('_')?;
+            (Entity::parse(name)?, op)
+        }
+    };
+
+    let fields = payload.as_object()?;
+    let id = fields
+        .get("id")
+        .or_else(|| fields.get(&format!("{}_id", entity.name())))
+        .and_then(|v| match v {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        })?;
+
+    match op {
+        "created" | "updated" => {
+            let mut document = fields.clone();
+            document.insert("id".to_string(), Value::String(id));
+            document.insert("updated_at".to_string(), Value::from(timestamp));
+            Some(EntityChange::Upsert { entity, document })
+        }
+        "deleted" => Some(EntityChange::Delete { entity, id }),
+        _ => None,
+    }
+}
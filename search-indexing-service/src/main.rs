@@ -0,0 +1,114 @@
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::{ClientConfig, Message};
+use tokio::time::Duration;
+use tracing::{info, error, warn, debug};
+
+mod config;
+mod entities;
+mod reindex;
+mod search_index;
+
+use config::Config;
+use crm_events::CrmEvent;
+use entities::{parse_change, Entity, EntityChange, ALL_ENTITIES};
+use reindex::Reindexer;
+use search_index::{SearchError, SearchIndex};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Logs, traces and panic capture
+    let _observability = crm_observability::init("search-indexing-service")?;
+
+    // Load configuration
+    let config = Config::from_env()?;
+    let search = SearchIndex::new(&config)?;
+
+    // `search-indexing-service reindex <tenant_id> [contact|lead|deal]`
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("reindex") {
+        let tenant_id = args.get(2).ok_or("usage: reindex <tenant_id> [entity]")?;
+        let entities = match args.get(3) {
+            Some(name) => vec![Entity::parse(name).ok_or_else(|| format!("unknown entity: {}", name))?],
+            None => ALL_ENTITIES.to_vec(),
+        };
+
+        let reindexer = Reindexer::new(&config, &search);
+        for entity in entities {
+            reindexer.reindex(tenant_id, entity).await.map_err(|e| e.to_string())?;
+        }
+        return Ok(());
+    }
+
+    info!("Starting Search Indexing Service");
+
+    // Create Kafka consumer
+    let consumer = create_consumer(&config)?;
+    let topics: Vec<&str> = config.kafka_topics.iter().map(|s| s.as_str()).collect();
+    consumer.subscribe(&topics)?;
+
+    info!("Connected to Kafka, projecting entity events into search...");
+
+    loop {
+        let message = match consumer.recv().await {
+            Ok(message) => message,
+            Err(e) => {
+                error!("Error receiving message: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        let event: CrmEvent = match message.payload().map(serde_json::from_slice) {
+            Some(Ok(event)) => event,
+            Some(Err(e)) => {
+                warn!("Skipping undecodable event: {}", e);
+                continue;
+            }
+            None => {
+                warn!("Received empty message");
+                continue;
+            }
+        };
+
+        let change = match parse_change(&event.event_type, &event.payload, event.timestamp) {
+            Some(change) => change,
+            None => continue,
+        };
+
+        if let Err(e) = apply_change(&search, &event.tenant_id, change).await {
+            error!("Failed to index {} for tenant {}: {}", event.event_type, event.tenant_id, e);
+        }
+    }
+}
+
+async fn apply_change(search: &SearchIndex, tenant_id: &str, change: EntityChange) -> Result<(), SearchError> {
+    match change {
+        EntityChange::Upsert { entity, document } => {
+            let uid = search.index_uid(tenant_id, entity);
+            search.ensure_index(&uid, entity).await?;
+            search.upsert(&uid, &[document]).await?;
+            debug!("Upserted {} into {}", entity.name(), uid);
+        }
+        EntityChange::Delete { entity, id } => {
+            let uid = search.index_uid(tenant_id, entity);
+            search.ensure_index(&uid, entity).await?;
+            search.delete(&uid, &id).await?;
+            debug!("Deleted {} {} from {}", entity.name(), id, uid);
+        }
+    }
+
+    Ok(())
+}
+
+fn create_consumer(config: &Config) -> Result<StreamConsumer, Box<dyn std::error::Error>> {
+    let consumer: StreamConsumer = ClientConfig::new()
+        .set("group.id", &config.kafka_group_id)
+        .set("bootstrap.servers", &config.kafka_brokers)
+        .set("enable.partition.eof", "false")
+        .set("session.timeout.ms", "6000")
+        .set("enable.auto.commit", "true")
+        .set("auto.offset.reset", "latest")
+        .create()?;
+
+    Ok(consumer)
+}
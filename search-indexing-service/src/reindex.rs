@@ -0,0 +1,114 @@
+use crate::config::Config;
+use crate::entities::{parse_change, Entity, EntityChange};
+use crate::search_index::{SearchError, SearchIndex};
+use clickhouse::Client;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+/// Documents sent per Meilisearch request while rebuilding.
+const UPSERT_CHUNK_SIZE: usize = 1000;
+
+#[derive(Debug, Deserialize, clickhouse::Row)]
+struct StoredEvent {
+    event_type: String,
+    timestamp: i64,
+    properties: String,
+    metrics: String,
+}
+
+/// Rebuilds a tenant's entity index from the event history in ClickHouse.
+/// Documents are built into a staging index which is then swapped with the
+/// live one, so search keeps serving the old data until the rebuild is done.
+pub struct Reindexer<'a> {
+    clickhouse: Client,
+    events_table: String,
+    search: &'a SearchIndex,
+}
+
+impl<'a> Reindexer<'a> {
+    pub fn new(config: &Config, search: &'a SearchIndex) -> Self {
+        let clickhouse = Client::default()
+            .with_url(&config.clickhouse_url)
+            .with_user(&config.clickhouse_user)
+            .with_password(&config.clickhouse_password)
+            .with_database(&config.clickhouse_database);
+
+        Reindexer {
+            clickhouse,
+            events_table: config.clickhouse_events_table.clone(),
+            search,
+        }
+    }
+
+    pub async fn reindex(&self, tenant_id: &str, entity: Entity) -> Result<usize, SearchError> {
+        let rows = self
+            .clickhouse
+            .query(&format!(
+                "SELECT event_type, timestamp, properties, metrics FROM {} \
+                 WHERE tenant_id = ? AND has(?, event_type) ORDER BY timestamp",
+                self.events_table
+            ))
+            .bind(tenant_id)
+            .bind(entity.event_types())
+            .fetch_all::<StoredEvent>()
+            .await?;
+
+        // Replay the history in order, keeping the latest state per id
+        let mut documents: HashMap<String, Map<String, Value>> = HashMap::new();
+        for row in &rows {
+            let payload = match rebuild_payload(row) {
+                Some(payload) => payload,
+                None => {
+                    warn!("Skipping unreadable {} row for tenant {}", row.event_type, tenant_id);
+                    continue;
+                }
+            };
+
+            match parse_change(&row.event_type, &payload, row.timestamp) {
+                Some(EntityChange::Upsert { document, .. }) => {
+                    let id = document["id"].as_str().unwrap_or_default().to_string();
+                    documents.entry(id).or_default().extend(document);
+                }
+                Some(EntityChange::Delete { id, .. }) => {
+                    documents.remove(&id);
+                }
+                None => {}
+            }
+        }
+
+        let live = self.search.index_uid(tenant_id, entity);
+        let staging = format!("{}_reindex", live);
+
+        self.search.drop_index(&staging).await?;
+        self.search.ensure_index(&live, entity).await?;
+        self.search.ensure_index(&staging, entity).await?;
+
+        let documents: Vec<Map<String, Value>> = documents.into_values().collect();
+        for chunk in documents.chunks(UPSERT_CHUNK_SIZE) {
+            self.search.upsert(&staging, chunk).await?;
+        }
+
+        self.search.swap(&live, &staging).await?;
+        self.search.drop_index(&staging).await?;
+
+        info!(
+            "Reindexed {} {} documents for tenant {} from {} events",
+            documents.len(),
+            entity.name(),
+            tenant_id,
+            rows.len()
+        );
+        Ok(documents.len())
+    }
+}
+
+/// The ingestion pipeline stores numeric payload fields under `metrics` and
+/// everything else under `properties`; merging them recovers the payload.
+fn rebuild_payload(row: &StoredEvent) -> Option<Value> {
+    let mut payload: Map<String, Value> = serde_json::from_str(&row.properties).ok()?;
+    let metrics: Map<String, Value> = serde_json::from_str(&row.metrics).ok()?;
+    payload.extend(metrics);
+    Some(Value::Object(payload))
+}
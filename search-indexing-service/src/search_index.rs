@@ -0,0 +1,125 @@
+use crate::config::Config;
+use crate::entities::Entity;
+use serde_json::{json, Map, Value};
+use std::collections::HashSet;
+use tokio::sync::Mutex;
+use tokio::time::Duration;
+use tracing::info;
+
+pub type SearchError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Thin client for the Meilisearch HTTP API. Meilisearch applies writes as
+/// asynchronous tasks processed in enqueue order, so callers don't wait on
+/// them individually.
+pub struct SearchIndex {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    index_prefix: String,
+    /// Indices whose settings were applied by this process.
+    prepared: Mutex<HashSet<String>>,
+}
+
+impl SearchIndex {
+    pub fn new(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?;
+
+        Ok(SearchIndex {
+            http,
+            base_url: config.meilisearch_url.trim_end_matches('/').to_string(),
+            api_key: config.meilisearch_api_key.clone(),
+            index_prefix: config.index_prefix.clone(),
+            prepared: Mutex::new(HashSet::new()),
+        })
+    }
+
+    pub fn index_uid(&self, tenant_id: &str, entity: Entity) -> String {
+        format!("{}_{}_{}", self.index_prefix, sanitize(tenant_id), entity.index_suffix())
+    }
+
+    /// Creates the index if needed and applies the entity's mapping. Runs
+    /// once per index per process; settings updates are idempotent.
+    pub async fn ensure_index(&self, uid: &str, entity: Entity) -> Result<(), SearchError> {
+        if self.prepared.lock().await.contains(uid) {
+            return Ok(());
+        }
+
+        // Fails as a task (not an HTTP error) when the index already exists
+        self.send(
+            self.http
+                .post(format!("{}/indexes", self.base_url))
+                .json(&json!({ "uid": uid, "primaryKey": "id" })),
+        )
+        .await?;
+
+        self.send(
+            self.http
+                .patch(format!("{}/indexes/{}/settings", self.base_url, uid))
+                .json(&json!({
+                    "searchableAttributes": entity.searchable_attributes(),
+                    "filterableAttributes": entity.filterable_attributes(),
+                    "sortableAttributes": entity.sortable_attributes(),
+                })),
+        )
+        .await?;
+
+        info!("Prepared search index {}", uid);
+        self.prepared.lock().await.insert(uid.to_string());
+        Ok(())
+    }
+
+    /// Adds documents or merges fields into existing ones with the same id.
+    pub async fn upsert(&self, uid: &str, documents: &[Map<String, Value>]) -> Result<(), SearchError> {
+        if documents.is_empty() {
+            return Ok(());
+        }
+
+        self.send(
+            self.http
+                .put(format!("{}/indexes/{}/documents", self.base_url, uid))
+                .json(documents),
+        )
+        .await
+    }
+
+    pub async fn delete(&self, uid: &str, id: &str) -> Result<(), SearchError> {
+        self.send(
+            self.http
+                .delete(format!("{}/indexes/{}/documents/{}", self.base_url, uid, id)),
+        )
+        .await
+    }
+
+    pub async fn swap(&self, a: &str, b: &str) -> Result<(), SearchError> {
+        self.send(
+            self.http
+                .post(format!("{}/swap-indexes", self.base_url))
+                .json(&json!([{ "indexes": [a, b] }])),
+        )
+        .await
+    }
+
+    pub async fn drop_index(&self, uid: &str) -> Result<(), SearchError> {
+        self.prepared.lock().await.remove(uid);
+        self.send(self.http.delete(format!("{}/indexes/{}", self.base_url, uid))).await
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<(), SearchError> {
+        let request = match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        };
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Meilisearch index uids allow only alphanumerics, `-` and `_`.
+fn sanitize(tenant_id: &str) -> String {
+    tenant_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect()
+}
@@ -0,0 +1,114 @@
+use crate::clickhouse::ExportRow;
+use crate::storage;
+use arrow_array::{Array, Int64Array, RecordBatch, StringArray};
+use futures::TryStreamExt;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use tracing::debug;
+
+pub type ArchiveError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Reads the Delta table the ingestion service's lake sink writes, for
+/// events older than ClickHouse's retention.
+pub struct ArchiveReader {
+    store: Arc<dyn ObjectStore>,
+    table_root: Path,
+}
+
+impl ArchiveReader {
+    pub fn new(table_url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let opened = storage::open(table_url)?;
+        Ok(ArchiveReader {
+            store: opened.store,
+            table_root: opened.root,
+        })
+    }
+
+    /// Data files live in the current table version: every `add` in the log
+    /// that no later `remove` took back.
+    pub async fn data_files(&self) -> Result<Vec<Path>, ArchiveError> {
+        let log_dir = self.table_root.child("_delta_log");
+        let mut commits: Vec<Path> = self
+            .store
+            .list(Some(&log_dir))
+            .try_filter_map(|meta| async move {
+                let is_commit = meta.location.filename().is_some_and(|name| name.ends_with(".json"));
+                Ok(is_commit.then_some(meta.location))
+            })
+            .try_collect()
+            .await?;
+        // Zero-padded versions sort lexically
+        commits.sort();
+
+        let mut files = BTreeSet::new();
+        for commit in commits {
+            let bytes = self.store.get(&commit).await?.bytes().await?;
+            for line in String::from_utf8_lossy(&bytes).lines() {
+                let action: Value = match serde_json::from_str(line) {
+                    Ok(action) => action,
+                    Err(_) => continue,
+                };
+                if let Some(path) = action.pointer("/add/path").and_then(Value::as_str) {
+                    files.insert(path.to_string());
+                }
+                if let Some(path) = action.pointer("/remove/path").and_then(Value::as_str) {
+                    files.remove(path);
+                }
+            }
+        }
+
+        debug!("Archive has {} live data files", files.len());
+        Ok(files.iter().map(|file| self.table_root.child(file.as_str())).collect())
+    }
+
+    /// The tenant's rows in one data file with `from <= timestamp < before`.
+    pub async fn read(&self, file: &Path, tenant_id: &str, from: i64, before: i64) -> Result<Vec<ExportRow>, ArchiveError> {
+        let bytes = self.store.get(file).await?.bytes().await?;
+        let reader = ParquetRecordBatchReaderBuilder::try_new(bytes)?.build()?;
+
+        let mut rows = Vec::new();
+        for batch in reader {
+            let batch = batch?;
+            let strings = |name: &str| string_column(&batch, name);
+            let (tenants, event_types, user_ids) = (strings("tenant_id")?, strings("event_type")?, strings("user_id")?);
+            let (properties, metrics) = (strings("properties")?, strings("metrics")?);
+            let timestamps = int_column(&batch, "timestamp")?;
+            let ingested = int_column(&batch, "ingested_at")?;
+
+            for i in 0..batch.num_rows() {
+                let timestamp = timestamps.value(i);
+                if tenants.value(i) != tenant_id || timestamp < from || timestamp >= before {
+                    continue;
+                }
+                rows.push(ExportRow {
+                    tenant_id: tenant_id.to_string(),
+                    event_type: event_types.value(i).to_string(),
+                    user_id: if user_ids.is_null(i) { String::new() } else { user_ids.value(i).to_string() },
+                    timestamp,
+                    ingested_at: ingested.value(i),
+                    properties: properties.value(i).to_string(),
+                    metrics: metrics.value(i).to_string(),
+                });
+            }
+        }
+        Ok(rows)
+    }
+}
+
+fn string_column<'a>(batch: &'a RecordBatch, name: &str) -> Result<&'a StringArray, ArchiveError> {
+    batch
+        .column_by_name(name)
+        .and_then(|column| column.as_any().downcast_ref::<StringArray>())
+        .ok_or_else(|| format!("archive file has no string column {}", name).into())
+}
+
+fn int_column<'a>(batch: &'a RecordBatch, name: &str) -> Result<&'a Int64Array, ArchiveError> {
+    batch
+        .column_by_name(name)
+        .and_then(|column| column.as_any().downcast_ref::<Int64Array>())
+        .ok_or_else(|| format!("archive file has no int64 column {}", name).into())
+}
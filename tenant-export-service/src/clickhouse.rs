@@ -0,0 +1,144 @@
+use crate::config::Config;
+use serde::{Deserialize, Serialize};
+use tokio::time::Duration;
+
+pub type QueryError = Box<dyn std::error::Error + Send + Sync>;
+
+/// An event as stored by the ingestion service, in both ClickHouse and the
+/// Delta archive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportRow {
+    pub tenant_id: String,
+    pub event_type: String,
+    pub user_id: String,
+    pub timestamp: i64,
+    pub ingested_at: i64,
+    /// JSON object, as stored.
+    pub properties: String,
+    /// JSON object, as stored.
+    pub metrics: String,
+}
+
+/// Pages a tenant's events out of ClickHouse. The tenant and range are bound
+/// as query parameters; table names only come from config.
+pub struct EventReader {
+    http: reqwest::Client,
+    url: String,
+    user: String,
+    password: String,
+    database: String,
+}
+
+impl EventReader {
+    pub fn new(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.query_timeout_secs))
+            .build()?;
+
+        Ok(EventReader {
+            http,
+            url: config.clickhouse_url.trim_end_matches('/').to_string(),
+            user: config.clickhouse_user.clone(),
+            password: config.clickhouse_password.clone(),
+            database: config.clickhouse_database.clone(),
+        })
+    }
+
+    /// One page of rows in timestamp order.
+    pub async fn page(
+        &self,
+        table: &str,
+        tenant_id: &str,
+        from: i64,
+        to: i64,
+        offset: u64,
+        limit: usize,
+    ) -> Result<Vec<ExportRow>, QueryError> {
+        let sql = format!(
+            "SELECT tenant_id, event_type, user_id, timestamp, ingested_at, properties, metrics \
+             FROM {} \
+             WHERE tenant_id = {{tenant_id:String}} AND timestamp >= {{from:Int64}} AND timestamp < {{to:Int64}} \
+             ORDER BY timestamp, event_type, user_id \
+             LIMIT {{limit:UInt64}} OFFSET {{offset:UInt64}} \
+             FORMAT JSONEachRow",
+            table
+        );
+        let body = self
+            .query(
+                &sql,
+                &[
+                    ("tenant_id", tenant_id.to_string()),
+                    ("from", from.to_string()),
+                    ("to", to.to_string()),
+                    ("limit", limit.to_string()),
+                    ("offset", offset.to_string()),
+                ],
+            )
+            .await?;
+
+        body.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(Into::into))
+            .collect()
+    }
+
+    /// Oldest event ClickHouse still holds for the tenant in the range.
+    pub async fn oldest_timestamp(&self, tables: &[String], tenant_id: &str, from: i64, to: i64) -> Result<Option<i64>, QueryError> {
+        let mut oldest: Option<i64> = None;
+        for table in tables {
+            let sql = format!(
+                "SELECT min(timestamp), count() FROM {} \
+                 WHERE tenant_id = {{tenant_id:String}} AND timestamp >= {{from:Int64}} AND timestamp < {{to:Int64}} \
+                 FORMAT TabSeparated",
+                table
+            );
+            let body = self
+                .query(
+                    &sql,
+                    &[
+                        ("tenant_id", tenant_id.to_string()),
+                        ("from", from.to_string()),
+                        ("to", to.to_string()),
+                    ],
+                )
+                .await?;
+
+            let mut fields = body.trim().split('\t');
+            let min: i64 = fields.next().unwrap_or("0").parse()?;
+            let count: u64 = fields.next().unwrap_or("0").parse()?;
+            if count > 0 {
+                oldest = Some(oldest.map_or(min, |oldest| oldest.min(min)));
+            }
+        }
+        Ok(oldest)
+    }
+
+    async fn query(&self, sql: &str, params: &[(&str, String)]) -> Result<String, QueryError> {
+        let mut query: Vec<(String, String)> = vec![
+            ("database".to_string(), self.database.clone()),
+            ("readonly".to_string(), "1".to_string()),
+            ("output_format_json_quote_64bit_integers".to_string(), "0".to_string()),
+        ];
+        for (name, value) in params {
+            query.push((format!("param_{}", name), value.clone()));
+        }
+
+        let response = self
+            .http
+            .post(&self.url)
+            .header("X-ClickHouse-User", &self.user)
+            .header("X-ClickHouse-Key", &self.password)
+            .query(&query)
+            .body(sql.to_string())
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("ClickHouse returned {}: {}", status, body.trim()).into());
+        }
+
+        Ok(response.text().await?)
+    }
+}
@@ -0,0 +1,69 @@
+use crm_config::{ConfigLoader, Configurable};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub port: u16,
+    pub redis_url: String,
+    pub clickhouse_url: String,
+    pub clickhouse_user: String,
+    pub clickhouse_password: String,
+    pub clickhouse_database: String,
+    /// Tables holding tenant events; each is exported in full for the range.
+    pub export_tables: Vec<String>,
+    /// Delta table written by the ingestion service's lake sink, used for
+    /// events ClickHouse no longer retains.
+    pub archive_table_url: Option<String>,
+    /// Object storage root for bundles, e.g. `s3://bucket/exports`.
+    pub export_storage_url: String,
+    /// Rows per bundle file.
+    pub part_rows: usize,
+    pub download_url_ttl_secs: u64,
+    pub workers: usize,
+    pub query_timeout_secs: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            port: 8080,
+            redis_url: "redis://localhost:6379".to_string(),
+            clickhouse_url: "http://localhost:8123".to_string(),
+            clickhouse_user: "default".to_string(),
+            clickhouse_password: "".to_string(),
+            clickhouse_database: "crm_analytics".to_string(),
+            export_tables: vec!["events".to_string(), "page_views".to_string()],
+            archive_table_url: None,
+            export_storage_url: "file:///tmp/exports".to_string(),
+            part_rows: 100_000,
+            download_url_ttl_secs: 86400,
+            workers: 2,
+            query_timeout_secs: 300,
+        }
+    }
+}
+
+impl Configurable for Config {
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if self.export_tables.is_empty() {
+            problems.push("export_tables must not be empty".to_string());
+        }
+        if self.part_rows == 0 {
+            problems.push("part_rows must be positive".to_string());
+        }
+        if self.workers == 0 {
+            problems.push("workers must be positive".to_string());
+        }
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+}
+
+impl Config {
+    /// Defaults, then the file named by `CONFIG_FILE`, then environment
+    /// variables named after the fields (e.g. `EXPORT_STORAGE_URL`).
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(ConfigLoader::new().file_from_env("CONFIG_FILE").load()?)
+    }
+}
@@ -0,0 +1,192 @@
+use crate::archive::ArchiveReader;
+use crate::clickhouse::{EventReader, ExportRow};
+use crate::config::Config;
+use crate::jobs::{now_secs, ExportFile, ExportJob, JobError};
+use crate::render;
+use crate::storage::ExportStorage;
+use crm_secrets::EnvelopeCipher;
+use serde_json::json;
+use std::sync::Arc;
+use tracing::info;
+
+/// Builds a tenant's export bundle: ClickHouse rows for the range, archived
+/// rows for the part of the range ClickHouse no longer holds, split into
+/// files of `part_rows`, plus a `manifest.json` describing them. Encrypted
+/// properties are decrypted, since the export is the tenant's own data.
+pub struct Exporter {
+    reader: EventReader,
+    archive: Option<ArchiveReader>,
+    storage: ExportStorage,
+    cipher: Option<Arc<EnvelopeCipher>>,
+    tables: Vec<String>,
+    part_rows: usize,
+}
+
+impl Exporter {
+    pub fn new(
+        config: &Config,
+        reader: EventReader,
+        archive: Option<ArchiveReader>,
+        storage: ExportStorage,
+        cipher: Option<Arc<EnvelopeCipher>>,
+    ) -> Self {
+        Exporter {
+            reader,
+            archive,
+            storage,
+            cipher,
+            tables: config.export_tables.clone(),
+            part_rows: config.part_rows,
+        }
+    }
+
+    pub fn storage(&self) -> &ExportStorage {
+        &self.storage
+    }
+
+    /// Uploads the bundle and returns its files, manifest last.
+    pub async fn run(&self, job: &ExportJob) -> Result<Vec<ExportFile>, JobError> {
+        let mut bundle = Bundle::new(self, job);
+
+        for table in &self.tables {
+            let mut offset = 0u64;
+            loop {
+                let page = self
+                    .reader
+                    .page(table, &job.tenant_id, job.from, job.to, offset, self.part_rows)
+                    .await?;
+                let done = page.len() < self.part_rows;
+                offset += page.len() as u64;
+                bundle.push("clickhouse", page).await?;
+                if done {
+                    break;
+                }
+            }
+        }
+        bundle.finish_part().await?;
+
+        if let (true, Some(archive)) = (job.include_archive, &self.archive) {
+            // Only take what ClickHouse no longer has, so rows aren't exported twice
+            let cutoff = self
+                .reader
+                .oldest_timestamp(&self.tables, &job.tenant_id, job.from, job.to)
+                .await?
+                .unwrap_or(job.to);
+            if cutoff > job.from {
+                for file in archive.data_files().await? {
+                    let rows = archive.read(&file, &job.tenant_id, job.from, cutoff).await?;
+                    bundle.push("archive", rows).await?;
+                }
+                bundle.finish_part().await?;
+            }
+        }
+
+        bundle.finish().await
+    }
+}
+
+struct Bundle<'a> {
+    exporter: &'a Exporter,
+    job: &'a ExportJob,
+    rows: Vec<ExportRow>,
+    source: &'static str,
+    files: Vec<ExportFile>,
+}
+
+impl<'a> Bundle<'a> {
+    fn new(exporter: &'a Exporter, job: &'a ExportJob) -> Self {
+        Bundle {
+            exporter,
+            job,
+            rows: Vec::new(),
+            source: "clickhouse",
+            files: Vec::new(),
+        }
+    }
+
+    async fn push(&mut self, source: &'static str, mut rows: Vec<ExportRow>) -> Result<(), JobError> {
+        if let Some(cipher) = &self.exporter.cipher {
+            for row in &mut rows {
+                row.properties = cipher.decrypt_text(&self.job.tenant_id, &row.properties).await?;
+            }
+        }
+        if source != self.source {
+            self.finish_part().await?;
+            self.source = source;
+        }
+        self.rows.extend(rows);
+        while self.rows.len() >= self.exporter.part_rows {
+            let rest = self.rows.split_off(self.exporter.part_rows);
+            self.finish_part().await?;
+            self.rows = rest;
+        }
+        Ok(())
+    }
+
+    async fn finish_part(&mut self) -> Result<(), JobError> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+
+        let rows = std::mem::take(&mut self.rows);
+        let name = format!(
+            "part-{:05}-{}.{}",
+            self.files.len(),
+            self.source,
+            self.job.format.extension()
+        );
+        let body = render::render(&rows, self.job.format)?;
+        let path = self.upload(&name, body).await?;
+
+        self.files.push(ExportFile {
+            name,
+            path,
+            rows: rows.len() as u64,
+            source: self.source.to_string(),
+        });
+        Ok(())
+    }
+
+    async fn finish(mut self) -> Result<Vec<ExportFile>, JobError> {
+        self.finish_part().await?;
+
+        let manifest = json!({
+            "export_id": self.job.id,
+            "tenant_id": self.job.tenant_id,
+            "from": self.job.from,
+            "to": self.job.to,
+            "format": self.job.format,
+            "created_at": now_secs(),
+            "files": self
+                .files
+                .iter()
+                .map(|file| json!({ "name": file.name, "rows": file.rows, "source": file.source }))
+                .collect::<Vec<_>>(),
+        });
+        let path = self.upload("manifest.json", serde_json::to_vec_pretty(&manifest)?).await?;
+
+        let rows: u64 = self.files.iter().map(|file| file.rows).sum();
+        info!(
+            "Exported {} rows in {} files for tenant {} ({})",
+            rows,
+            self.files.len(),
+            self.job.tenant_id,
+            self.job.id
+        );
+
+        self.files.push(ExportFile {
+            name: "manifest.json".to_string(),
+            path,
+            rows: 0,
+            source: "manifest".to_string(),
+        });
+        Ok(self.files)
+    }
+
+    async fn upload(&self, name: &str, body: Vec<u8>) -> Result<String, JobError> {
+        self.exporter
+            .storage
+            .upload(&self.job.tenant_id, &self.job.id, name, body)
+            .await
+    }
+}
@@ -0,0 +1,122 @@
+use redis::aio::Connection;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+pub type JobError = Box<dyn std::error::Error + Send + Sync>;
+
+const QUEUE_KEY: &str = "tenant_exports:pending";
+/// Finished jobs (and their bundle listings) are kept for a week.
+const JOB_TTL_SECS: usize = 7 * 86400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportStatus {
+    Pending,
+    Running,
+    Completed,
+    Failed,
+}
+
+/// One file of a finished bundle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportFile {
+    pub name: String,
+    /// Object path; download URLs are signed on request.
+    pub path: String,
+    pub rows: u64,
+    /// `clickhouse`, `archive` or `manifest`.
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportJob {
+    pub id: String,
+    pub tenant_id: String,
+    /// Unix seconds, inclusive.
+    pub from: i64,
+    /// Unix seconds, exclusive.
+    pub to: i64,
+    pub format: ExportFormat,
+    pub include_archive: bool,
+    pub status: ExportStatus,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub files: Vec<ExportFile>,
+    pub created_at: i64,
+    #[serde(default)]
+    pub completed_at: Option<i64>,
+}
+
+/// Export jobs in Redis: bodies at `tenant_export:{id}` and a work queue of
+/// pending ids, so jobs accepted before a restart still run.
+pub struct JobStore {
+    conn: Mutex<Connection>,
+}
+
+impl JobStore {
+    pub fn new(conn: Connection) -> Self {
+        JobStore { conn: Mutex::new(conn) }
+    }
+
+    pub async fn enqueue(&self, job: &ExportJob) -> Result<(), JobError> {
+        self.save(job).await?;
+        let _: () = self.conn.lock().await.lpush(QUEUE_KEY, &job.id).await?;
+        Ok(())
+    }
+
+    pub async fn save(&self, job: &ExportJob) -> Result<(), JobError> {
+        let body = serde_json::to_string(job)?;
+        let _: () = self
+            .conn
+            .lock()
+            .await
+            .set_ex(job_key(&job.id), body, JOB_TTL_SECS)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<ExportJob>, JobError> {
+        let body: Option<String> = self.conn.lock().await.get(job_key(id)).await?;
+        Ok(match body {
+            Some(body) => Some(serde_json::from_str(&body)?),
+            None => None,
+        })
+    }
+}
+
+/// Blocks on the pending queue. Workers each hold their own connection so
+/// `BRPOP` doesn't stall the API.
+pub async fn next_job_id(conn: &mut Connection, wait_secs: f64) -> Result<Option<String>, JobError> {
+    let popped: Option<(String, String)> = conn.brpop(QUEUE_KEY, wait_secs).await?;
+    Ok(popped.map(|(_, id)| id))
+}
+
+fn job_key(id: &str) -> String {
+    format!("tenant_export:{}", id)
+}
+
+pub fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
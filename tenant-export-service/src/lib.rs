@@ -0,0 +1,10 @@
+//! The export pipeline. `main.rs` serves the API and runs the workers; it
+//! is a library so the tests under `tests/` can drive it directly.
+
+pub mod archive;
+pub mod clickhouse;
+pub mod config;
+pub mod exporter;
+pub mod jobs;
+pub mod render;
+pub mod storage;
@@ -0,0 +1,230 @@
+use crm_secrets::{EncryptionConfig, EnvelopeCipher, SecretStore};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::{error, info, warn};
+use warp::http::StatusCode;
+use warp::Filter;
+
+use tenant_export_service::archive::ArchiveReader;
+use tenant_export_service::clickhouse::EventReader;
+use tenant_export_service::config::Config;
+use tenant_export_service::exporter::Exporter;
+use tenant_export_service::jobs::{self, now_secs, ExportFormat, ExportJob, ExportStatus, JobStore};
+use tenant_export_service::storage::ExportStorage;
+
+/// Set by the gateway from the caller's token.
+const TENANT_HEADER: &str = "x-tenant-id";
+
+#[derive(Debug, Deserialize)]
+struct ExportRequest {
+    from: i64,
+    to: i64,
+    #[serde(default = "default_format")]
+    format: ExportFormat,
+    #[serde(default = "default_include_archive")]
+    include_archive: bool,
+}
+
+fn default_format() -> ExportFormat {
+    ExportFormat::Csv
+}
+
+fn default_include_archive() -> bool {
+    true
+}
+
+struct AppState {
+    jobs: JobStore,
+    exporter: Exporter,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Logs, traces and panic capture
+    let _observability = crm_observability::init("tenant-export-service")?;
+
+    info!("Starting Tenant Export Service");
+
+    // Load configuration
+    let config = Config::from_env()?;
+    info!("Configuration: {}", crm_config::redacted(&config));
+
+    let redis_client = redis::Client::open(config.redis_url.as_str())?;
+    let archive = match &config.archive_table_url {
+        Some(table_url) => Some(ArchiveReader::new(table_url)?),
+        None => None,
+    };
+    let storage = ExportStorage::new(
+        &config.export_storage_url,
+        Duration::from_secs(config.download_url_ttl_secs),
+    )?;
+    let encryption = SecretStore::from_env()?.resolve(&EncryptionConfig::from_env()?).await?;
+    let cipher = EnvelopeCipher::from_config(&encryption)?.map(Arc::new);
+    let state = Arc::new(AppState {
+        jobs: JobStore::new(redis_client.get_async_connection().await?),
+        exporter: Exporter::new(&config, EventReader::new(&config)?, archive, storage, cipher),
+    });
+
+    for worker in 0..config.workers {
+        let conn = redis_client.get_async_connection().await?;
+        tokio::spawn(run_worker(worker, conn, Arc::clone(&state)));
+    }
+
+    let with_state = {
+        let state = Arc::clone(&state);
+        warp::any().map(move || Arc::clone(&state))
+    };
+    let create = warp::post()
+        .and(warp::path!("exports"))
+        .and(warp::header::<String>(TENANT_HEADER))
+        .and(warp::body::content_length_limit(16 * 1024))
+        .and(warp::body::json())
+        .and(with_state.clone())
+        .and_then(handle_create);
+    let status = warp::get()
+        .and(warp::path!("exports" / String))
+        .and(warp::header::<String>(TENANT_HEADER))
+        .and(with_state)
+        .and_then(handle_status);
+    let health = warp::path("health").map(|| "OK");
+
+    let routes = health
+        .or(crm_observability::metrics_route())
+        .or(create)
+        .or(status)
+        .with(crm_observability::http_metrics());
+
+    info!("Tenant export API listening on port {}", config.port);
+    warp::serve(routes).run(([0, 0, 0, 0], config.port)).await;
+
+    Ok(())
+}
+
+async fn handle_create(
+    tenant_id: String,
+    request: ExportRequest,
+    state: Arc<AppState>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if request.from >= request.to {
+        return Ok(reply(StatusCode::BAD_REQUEST, json!({ "error": "from must be before to" })));
+    }
+
+    let job = ExportJob {
+        id: uuid::Uuid::new_v4().to_string(),
+        tenant_id,
+        from: request.from,
+        to: request.to,
+        format: request.format,
+        include_archive: request.include_archive,
+        status: ExportStatus::Pending,
+        error: None,
+        files: Vec::new(),
+        created_at: now_secs(),
+        completed_at: None,
+    };
+
+    match state.jobs.enqueue(&job).await {
+        Ok(()) => {
+            info!("Queued export {} for tenant {}", job.id, job.tenant_id);
+            Ok(reply(StatusCode::ACCEPTED, json!({ "id": job.id, "status": job.status })))
+        }
+        Err(e) => {
+            error!("Failed to queue export: {}", e);
+            Ok(reply(StatusCode::SERVICE_UNAVAILABLE, json!({ "error": "could not queue export" })))
+        }
+    }
+}
+
+async fn handle_status(
+    export_id: String,
+    tenant_id: String,
+    state: Arc<AppState>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let job = match state.jobs.get(&export_id).await {
+        // Other tenants' exports look the same as missing ones
+        Ok(Some(job)) if job.tenant_id == tenant_id => job,
+        Ok(_) => return Ok(reply(StatusCode::NOT_FOUND, json!({ "error": "export not found" }))),
+        Err(e) => {
+            error!("Failed to load export {}: {}", export_id, e);
+            return Ok(reply(StatusCode::SERVICE_UNAVAILABLE, json!({ "error": "could not load export" })));
+        }
+    };
+
+    // URLs are signed per request so they outlive neither the link TTL nor the job
+    let mut files = Vec::with_capacity(job.files.len());
+    for file in &job.files {
+        match state.exporter.storage().download_url(&file.path).await {
+            Ok(url) => files.push(json!({ "name": file.name, "rows": file.rows, "source": file.source, "url": url })),
+            Err(e) => {
+                error!("Failed to sign {}: {}", file.path, e);
+                return Ok(reply(StatusCode::SERVICE_UNAVAILABLE, json!({ "error": "could not sign download URLs" })));
+            }
+        }
+    }
+
+    Ok(reply(
+        StatusCode::OK,
+        json!({
+            "id": job.id,
+            "status": job.status,
+            "from": job.from,
+            "to": job.to,
+            "format": job.format,
+            "error": job.error,
+            "created_at": job.created_at,
+            "completed_at": job.completed_at,
+            "files": files,
+        }),
+    ))
+}
+
+fn reply(status: StatusCode, body: serde_json::Value) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(warp::reply::json(&body), status)
+}
+
+async fn run_worker(worker: usize, mut conn: redis::aio::Connection, state: Arc<AppState>) {
+    loop {
+        let export_id = match jobs::next_job_id(&mut conn, 5.0).await {
+            Ok(Some(id)) => id,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!("Export worker {} failed to poll queue: {}", worker, e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+
+        let mut job = match state.jobs.get(&export_id).await {
+            Ok(Some(job)) => job,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("Failed to load export {}: {}", export_id, e);
+                continue;
+            }
+        };
+
+        job.status = ExportStatus::Running;
+        if let Err(e) = state.jobs.save(&job).await {
+            error!("Failed to mark export {} running: {}", job.id, e);
+        }
+
+        match state.exporter.run(&job).await {
+            Ok(files) => {
+                job.status = ExportStatus::Completed;
+                job.files = files;
+            }
+            Err(e) => {
+                error!("Export {} for tenant {} failed: {}", job.id, job.tenant_id, e);
+                job.status = ExportStatus::Failed;
+                job.error = Some(e.to_string());
+            }
+        }
+        job.completed_at = Some(now_secs());
+
+        if let Err(e) = state.jobs.save(&job).await {
+            error!("Failed to record export {} result: {}", job.id, e);
+        }
+    }
+}
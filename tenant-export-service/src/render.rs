@@ -0,0 +1,62 @@
+use crate::clickhouse::ExportRow;
+use crate::jobs::ExportFormat;
+use arrow_array::{ArrayRef, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::sync::Arc;
+
+pub type RenderError = Box<dyn std::error::Error + Send + Sync>;
+
+pub fn render(rows: &[ExportRow], format: ExportFormat) -> Result<Vec<u8>, RenderError> {
+    match format {
+        ExportFormat::Csv => render_csv(rows),
+        ExportFormat::Parquet => render_parquet(rows),
+    }
+}
+
+fn render_csv(rows: &[ExportRow]) -> Result<Vec<u8>, RenderError> {
+    // Headers come from the row's field names
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    Ok(writer.into_inner().map_err(|e| e.to_string())?)
+}
+
+/// Same columns as the archive, so exported and archived files read alike.
+fn render_parquet(rows: &[ExportRow]) -> Result<Vec<u8>, RenderError> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("tenant_id", DataType::Utf8, false),
+        Field::new("event_type", DataType::Utf8, false),
+        Field::new("user_id", DataType::Utf8, true),
+        Field::new("timestamp", DataType::Int64, false),
+        Field::new("ingested_at", DataType::Int64, false),
+        Field::new("properties", DataType::Utf8, false),
+        Field::new("metrics", DataType::Utf8, false),
+    ]));
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.tenant_id.as_str()))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.event_type.as_str()))),
+        Arc::new(StringArray::from_iter(
+            rows.iter().map(|r| (!r.user_id.is_empty()).then_some(r.user_id.as_str())),
+        )),
+        Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.timestamp))),
+        Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.ingested_at))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.properties.as_str()))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.metrics.as_str()))),
+    ];
+
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    let mut data = Vec::new();
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(&mut data, schema, Some(properties))?;
+    writer.write(&batch)?;
+    writer.close()?;
+
+    Ok(data)
+}
@@ -0,0 +1,85 @@
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::signer::Signer;
+use object_store::{ObjectStore, PutPayload};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+use url::Url;
+
+pub type StorageError = Box<dyn std::error::Error + Send + Sync>;
+
+pub struct OpenedStore {
+    pub store: Arc<dyn ObjectStore>,
+    pub root: Path,
+    /// Set when the backend can presign URLs (S3 and compatibles).
+    pub signer: Option<Arc<dyn Signer>>,
+}
+
+pub fn open(storage_url: &str) -> Result<OpenedStore, Box<dyn std::error::Error>> {
+    let url = Url::parse(storage_url)?;
+    match url.scheme() {
+        "s3" | "s3a" => {
+            let store = Arc::new(AmazonS3Builder::from_env().with_url(storage_url).build()?);
+            Ok(OpenedStore {
+                store: store.clone(),
+                root: Path::from_url_path(url.path())?,
+                signer: Some(store),
+            })
+        }
+        _ => {
+            let (store, root) = object_store::parse_url(&url)?;
+            Ok(OpenedStore {
+                store: Arc::from(store),
+                root,
+                signer: None,
+            })
+        }
+    }
+}
+
+/// Uploads bundle files under `{root}/{tenant_id}/{export_id}/` and hands out
+/// time-limited download URLs for them.
+pub struct ExportStorage {
+    store: Arc<dyn ObjectStore>,
+    root: Path,
+    root_url: String,
+    signer: Option<Arc<dyn Signer>>,
+    url_ttl: Duration,
+}
+
+impl ExportStorage {
+    pub fn new(storage_url: &str, url_ttl: Duration) -> Result<Self, Box<dyn std::error::Error>> {
+        let OpenedStore { store, root, signer } = open(storage_url)?;
+
+        info!("Exports stored under {}", storage_url);
+
+        Ok(ExportStorage {
+            store,
+            root,
+            root_url: storage_url.trim_end_matches('/').to_string(),
+            signer,
+            url_ttl,
+        })
+    }
+
+    /// Stores the file and returns its path relative to the export root.
+    pub async fn upload(&self, tenant_id: &str, export_id: &str, file_name: &str, body: Vec<u8>) -> Result<String, StorageError> {
+        let path = self.root.child(tenant_id).child(export_id).child(file_name);
+        self.store.put(&path, PutPayload::from(body)).await?;
+
+        Ok(format!("{}/{}/{}", tenant_id, export_id, file_name))
+    }
+
+    /// A presigned GET URL where the backend supports it, otherwise the
+    /// object's URL (for local and in-cluster stores).
+    pub async fn download_url(&self, relative: &str) -> Result<String, StorageError> {
+        match &self.signer {
+            Some(signer) => {
+                let path = relative.split('/').fold(self.root.clone(), |path, part| path.child(part));
+                Ok(signer.signed_url(http::Method::GET, &path, self.url_ttl).await?.to_string())
+            }
+            None => Ok(format!("{}/{}", self.root_url, relative)),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use arrow_array::{RecordBatch, StringArray};
+use object_store::path::Path;
+use parquet::arrow::ArrowWriter;
+use std::sync::Arc;
+use tenant_export_service::archive::ArchiveReader;
+use tenant_export_service::clickhouse::ExportRow;
+use tenant_export_service::jobs::ExportFormat;
+use tenant_export_service::render;
+
+fn row(tenant_id: &str, user_id: &str, timestamp: i64) -> ExportRow {
+    ExportRow {
+        tenant_id: tenant_id.to_string(),
+        event_type: "page_view".to_string(),
+        user_id: user_id.to_string(),
+        timestamp,
+        ingested_at: timestamp + 1,
+        properties: r#"{"url":"/pricing"}"#.to_string(),
+        metrics: r#"{"duration_ms":250}"#.to_string(),
+    }
+}
+
+/// A Delta table in a temporary directory.
+struct Table {
+    dir: std::path::PathBuf,
+}
+
+impl Table {
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("export-archive-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("_delta_log")).unwrap();
+        Table { dir }
+    }
+
+    fn url(&self) -> String {
+        format!("file://{}", self.dir.display())
+    }
+
+    fn write_file(&self, name: &str, rows: &[ExportRow]) {
+        std::fs::write(self.dir.join(name), render::render(rows, ExportFormat::Parquet).unwrap()).unwrap();
+    }
+
+    fn commit(&self, version: u64, actions: &[serde_json::Value]) {
+        let lines: Vec<String> = actions.iter().map(|action| action.to_string()).collect();
+        let path = self.dir.join("_delta_log").join(format!("{:020}.json", version));
+        std::fs::write(path, lines.join("\n")).unwrap();
+    }
+}
+
+impl Drop for Table {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn names(files: &[Path]) -> Vec<&str> {
+    files.iter().map(|file| file.filename().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn data_files_are_those_the_log_added_and_didnt_remove() {
+    let table = Table::new();
+    table.commit(
+        0,
+        &[
+            serde_json::json!({ "protocol": { "minReaderVersion": 1, "minWriterVersion": 2 } }),
+            serde_json::json!({ "add": { "path": "part-a.parquet" } }),
+            serde_json::json!({ "add": { "path": "part-b.parquet" } }),
+        ],
+    );
+    table.commit(
+        1,
+        &[
+            serde_json::json!({ "remove": { "path": "part-a.parquet" } }),
+            serde_json::json!({ "add": { "path": "part-c.parquet" } }),
+        ],
+    );
+    // Not a commit
+    std::fs::write(table.dir.join("_delta_log").join("00000000000000000001.checkpoint.parquet"), b"").unwrap();
+
+    let archive = ArchiveReader::new(&table.url()).unwrap();
+    assert_eq!(names(&archive.data_files().await.unwrap()), ["part-b.parquet", "part-c.parquet"]);
+}
+
+#[tokio::test]
+async fn an_empty_table_has_no_data_files() {
+    let table = Table::new();
+    let archive = ArchiveReader::new(&table.url()).unwrap();
+    assert!(archive.data_files().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn reads_the_tenant_rows_in_the_range() {
+    let table = Table::new();
+    table.write_file(
+        "part-a.parquet",
+        &[row("t1", "u1", 100), row("t2", "u2", 150), row("t1", "", 150), row("t1", "u3", 200)],
+    );
+    table.commit(0, &[serde_json::json!({ "add": { "path": "part-a.parquet" } })]);
+
+    let archive = ArchiveReader::new(&table.url()).unwrap();
+    let files = archive.data_files().await.unwrap();
+    let rows = archive.read(&files[0], "t1", 100, 200).await.unwrap();
+
+    // Exported files read back like archived ones, users left out included
+    let read: Vec<_> = rows.iter().map(|row| (row.user_id.as_str(), row.timestamp)).collect();
+    assert_eq!(read, [("u1", 100), ("", 150)]);
+    assert_eq!(rows[0].tenant_id, "t1");
+    assert_eq!(rows[0].ingested_at, 101);
+    assert_eq!(rows[0].properties, r#"{"url":"/pricing"}"#);
+    assert_eq!(rows[0].metrics, r#"{"duration_ms":250}"#);
+}
+
+#[tokio::test]
+async fn refuses_files_without_the_event_columns() {
+    let table = Table::new();
+    let tenants: arrow_array::ArrayRef = Arc::new(StringArray::from(vec!["t1"]));
+    let batch = RecordBatch::try_from_iter([("tenant_id", tenants)]).unwrap();
+    let mut data = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut data, batch.schema(), None).unwrap();
+    writer.write(&batch).unwrap();
+    writer.close().unwrap();
+    std::fs::write(table.dir.join("part-a.parquet"), data).unwrap();
+    table.commit(0, &[serde_json::json!({ "add": { "path": "part-a.parquet" } })]);
+
+    let archive = ArchiveReader::new(&table.url()).unwrap();
+    let files = archive.data_files().await.unwrap();
+    let error = archive.read(&files[0], "t1", 0, i64::MAX).await.unwrap_err();
+    assert!(error.to_string().contains("no string column event_type"), "{}", error);
+}
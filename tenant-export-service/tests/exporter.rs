@@ -0,0 +1,201 @@
+use arrow_array::{Int64Array, StringArray};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tenant_export_service::archive::ArchiveReader;
+use tenant_export_service::clickhouse::{EventReader, ExportRow};
+use tenant_export_service::config::Config;
+use tenant_export_service::exporter::Exporter;
+use tenant_export_service::jobs::{ExportFormat, ExportJob, ExportStatus};
+use tenant_export_service::render;
+use tenant_export_service::storage::ExportStorage;
+use warp::Filter;
+
+fn row(tenant_id: &str, timestamp: i64) -> ExportRow {
+    ExportRow {
+        tenant_id: tenant_id.to_string(),
+        event_type: "page_view".to_string(),
+        user_id: "u1".to_string(),
+        timestamp,
+        ingested_at: timestamp,
+        properties: "{}".to_string(),
+        metrics: "{}".to_string(),
+    }
+}
+
+/// Answers the exporter's two queries over `rows` like ClickHouse would, or
+/// fails every query with a 500 when `rows` is `None`.
+async fn clickhouse(rows: Option<Vec<ExportRow>>) -> SocketAddr {
+    let rows = Arc::new(rows);
+    let route = warp::post()
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::body::bytes())
+        .map(move |params: HashMap<String, String>, sql: bytes::Bytes| {
+            let Some(rows) = rows.as_ref() else {
+                let error = "Code: 241. MEMORY_LIMIT_EXCEEDED".to_string();
+                return warp::reply::with_status(error, warp::http::StatusCode::INTERNAL_SERVER_ERROR);
+            };
+            let param = |name: &str| params[&format!("param_{}", name)].clone();
+            let (from, to): (i64, i64) = (param("from").parse().unwrap(), param("to").parse().unwrap());
+            let mut matching: Vec<&ExportRow> = rows
+                .iter()
+                .filter(|row| row.tenant_id == param("tenant_id") && row.timestamp >= from && row.timestamp < to)
+                .collect();
+            matching.sort_by_key(|row| row.timestamp);
+
+            let body = if String::from_utf8_lossy(&sql).contains("min(timestamp)") {
+                let min = matching.first().map_or(0, |row| row.timestamp);
+                format!("{}\t{}\n", min, matching.len())
+            } else {
+                matching
+                    .iter()
+                    .skip(param("offset").parse().unwrap())
+                    .take(param("limit").parse().unwrap())
+                    .map(|row| format!("{}\n", serde_json::to_string(row).unwrap()))
+                    .collect()
+            };
+            warp::reply::with_status(body, warp::http::StatusCode::OK)
+        });
+    let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    address
+}
+
+/// Where bundles and the archive go, removed with it.
+struct Dir(PathBuf);
+
+impl Dir {
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("export-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("exports")).unwrap();
+        Dir(dir)
+    }
+
+    fn url(&self, name: &str) -> String {
+        format!("file://{}", self.0.join(name).display())
+    }
+
+    /// An archive table with `rows` in a single data file.
+    fn archive(&self, rows: &[ExportRow]) -> ArchiveReader {
+        let table = self.0.join("archive");
+        std::fs::create_dir_all(table.join("_delta_log")).unwrap();
+        std::fs::write(table.join("part-a.parquet"), render::render(rows, ExportFormat::Parquet).unwrap()).unwrap();
+        let add = serde_json::json!({ "add": { "path": "part-a.parquet" } });
+        std::fs::write(table.join("_delta_log/00000000000000000000.json"), add.to_string()).unwrap();
+        ArchiveReader::new(&self.url("archive")).unwrap()
+    }
+
+    fn bundle_file(&self, name: &str) -> Vec<u8> {
+        std::fs::read(self.0.join("exports/t1/e1").join(name)).unwrap()
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn exporter(clickhouse: SocketAddr, archive: Option<ArchiveReader>, dir: &Dir) -> Exporter {
+    let config = Config {
+        clickhouse_url: format!("http://{}", clickhouse),
+        export_tables: vec!["events".to_string()],
+        part_rows: 2,
+        ..Config::default()
+    };
+    let storage = ExportStorage::new(&dir.url("exports"), Duration::from_secs(3600)).unwrap();
+    Exporter::new(&config, EventReader::new(&config).unwrap(), archive, storage, None)
+}
+
+fn job(format: ExportFormat, include_archive: bool) -> ExportJob {
+    ExportJob {
+        id: "e1".to_string(),
+        tenant_id: "t1".to_string(),
+        from: 0,
+        to: 1000,
+        format,
+        include_archive,
+        status: ExportStatus::Running,
+        error: None,
+        files: Vec::new(),
+        created_at: 0,
+        completed_at: None,
+    }
+}
+
+fn timestamps(parquet: Vec<u8>) -> Vec<i64> {
+    let mut timestamps = Vec::new();
+    for batch in ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(parquet)).unwrap().build().unwrap() {
+        let batch = batch.unwrap();
+        let tenants = batch.column_by_name("tenant_id").unwrap().as_any().downcast_ref::<StringArray>().unwrap();
+        assert!(tenants.iter().all(|tenant| tenant == Some("t1")));
+        let column = batch.column_by_name("timestamp").unwrap().as_any().downcast_ref::<Int64Array>().unwrap();
+        timestamps.extend(column.values().iter().copied());
+    }
+    timestamps
+}
+
+#[tokio::test]
+async fn bundles_clickhouse_rows_then_what_only_the_archive_has() {
+    let dir = Dir::new();
+    let mut rows: Vec<ExportRow> = (200..205).map(|timestamp| row("t1", timestamp)).collect();
+    rows.push(row("t2", 201));
+    let clickhouse = clickhouse(Some(rows)).await;
+    // 210 is still in ClickHouse's range, so it isn't exported twice
+    let archive = dir.archive(&[row("t1", 100), row("t2", 120), row("t1", 150), row("t1", 210)]);
+
+    let files = exporter(clickhouse, Some(archive), &dir).run(&job(ExportFormat::Parquet, true)).await.unwrap();
+
+    let listed: Vec<_> = files.iter().map(|file| (file.name.as_str(), file.rows, file.source.as_str())).collect();
+    assert_eq!(
+        listed,
+        [
+            ("part-00000-clickhouse.parquet", 2, "clickhouse"),
+            ("part-00001-clickhouse.parquet", 2, "clickhouse"),
+            ("part-00002-clickhouse.parquet", 1, "clickhouse"),
+            ("part-00003-archive.parquet", 2, "archive"),
+            ("manifest.json", 0, "manifest"),
+        ]
+    );
+    assert_eq!(files[0].path, "t1/e1/part-00000-clickhouse.parquet");
+    assert_eq!(timestamps(dir.bundle_file("part-00000-clickhouse.parquet")), [200, 201]);
+    assert_eq!(timestamps(dir.bundle_file("part-00002-clickhouse.parquet")), [204]);
+    assert_eq!(timestamps(dir.bundle_file("part-00003-archive.parquet")), [100, 150]);
+
+    let manifest: Value = serde_json::from_slice(&dir.bundle_file("manifest.json")).unwrap();
+    assert_eq!(manifest["export_id"], "e1");
+    assert_eq!(manifest["format"], "parquet");
+    assert_eq!(manifest["files"].as_array().unwrap().len(), 4);
+    let archived = serde_json::json!({ "name": "part-00003-archive.parquet", "rows": 2, "source": "archive" });
+    assert_eq!(manifest["files"][3], archived);
+}
+
+#[tokio::test]
+async fn leaves_the_archive_out_when_not_asked_for() {
+    let dir = Dir::new();
+    let clickhouse = clickhouse(Some(vec![row("t1", 200)])).await;
+    let archive = dir.archive(&[row("t1", 100)]);
+
+    let files = exporter(clickhouse, Some(archive), &dir).run(&job(ExportFormat::Csv, false)).await.unwrap();
+
+    let names: Vec<_> = files.iter().map(|file| file.name.as_str()).collect();
+    assert_eq!(names, ["part-00000-clickhouse.csv", "manifest.json"]);
+    let csv = String::from_utf8(dir.bundle_file("part-00000-clickhouse.csv")).unwrap();
+    assert_eq!(csv.lines().next(), Some("tenant_id,event_type,user_id,timestamp,ingested_at,properties,metrics"));
+    assert_eq!(csv.lines().count(), 2);
+}
+
+#[tokio::test]
+async fn fails_the_export_when_clickhouse_does() {
+    let dir = Dir::new();
+    let clickhouse = clickhouse(None).await;
+
+    let error = exporter(clickhouse, None, &dir).run(&job(ExportFormat::Csv, true)).await.unwrap_err();
+    assert!(error.to_string().contains("ClickHouse returned 500"), "{}", error);
+    // Nothing was uploaded, the manifest included
+    assert!(!dir.0.join("exports/t1").exists());
+}
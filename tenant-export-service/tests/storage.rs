@@ -0,0 +1,39 @@
+use std::time::Duration;
+use tenant_export_service::storage::ExportStorage;
+
+#[tokio::test]
+async fn local_exports_are_linked_directly() {
+    let dir = std::env::temp_dir().join(format!("export-storage-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let root_url = format!("file://{}/", dir.display());
+    let storage = ExportStorage::new(&root_url, Duration::from_secs(3600)).unwrap();
+
+    let path = storage.upload("t1", "e1", "manifest.json", b"{}".to_vec()).await.unwrap();
+    assert_eq!(path, "t1/e1/manifest.json");
+    assert_eq!(std::fs::read(dir.join("t1/e1/manifest.json")).unwrap(), b"{}");
+    assert_eq!(
+        storage.download_url(&path).await.unwrap(),
+        format!("file://{}/t1/e1/manifest.json", dir.display())
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn s3_exports_get_presigned_urls() {
+    // Signing is local: nothing is sent to S3
+    std::env::set_var("AWS_ACCESS_KEY_ID", "AKIDEXAMPLE");
+    std::env::set_var("AWS_SECRET_ACCESS_KEY", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY");
+    std::env::set_var("AWS_DEFAULT_REGION", "eu-west-1");
+    let storage = ExportStorage::new("s3://crm-exports/bundles", Duration::from_secs(900)).unwrap();
+
+    let url = url::Url::parse(&storage.download_url("t1/e1/part-00000-clickhouse.csv").await.unwrap()).unwrap();
+    assert_eq!(url.scheme(), "https");
+    assert!(url.path().ends_with("/bundles/t1/e1/part-00000-clickhouse.csv"), "{}", url);
+    let query: std::collections::HashMap<_, _> = url.query_pairs().into_owned().collect();
+    assert_eq!(query["X-Amz-Expires"], "900");
+    assert_eq!(query["X-Amz-Algorithm"], "AWS4-HMAC-SHA256");
+    assert!(query["X-Amz-Credential"].starts_with("AKIDEXAMPLE/"));
+    assert!(query["X-Amz-Credential"].contains("/eu-west-1/s3/"));
+    assert!(query.contains_key("X-Amz-Signature"));
+}
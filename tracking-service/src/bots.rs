@@ -0,0 +1,51 @@
+/// Crawlers, link previewers and the mail security gateways that fetch every
+/// link and image in an inbound message before the recipient sees it.
+const KNOWN_BOTS: &[&str] = &[
+    "bot",
+    "crawler",
+    "spider",
+    "slurp",
+    "facebookexternalhit",
+    "headlesschrome",
+    "phantomjs",
+    "curl/",
+    "wget/",
+    "python-requests",
+    "go-http-client",
+    "java/",
+    "barracuda",
+    "mimecast",
+    "proofpoint",
+    "symantec",
+    "trendmicro",
+    "forcepoint",
+    "safelinks",
+];
+
+/// Matches User-Agents that shouldn't count as opens or clicks. The hits are
+/// still answered so scanners see a normal response.
+pub struct BotFilter {
+    patterns: Vec<String>,
+}
+
+impl BotFilter {
+    pub fn new(extra: &[String]) -> Self {
+        let patterns = KNOWN_BOTS
+            .iter()
+            .map(|p| p.to_string())
+            .chain(extra.iter().map(|p| p.to_ascii_lowercase()))
+            .collect();
+        BotFilter { patterns }
+    }
+
+    pub fn is_bot(&self, user_agent: Option<&str>) -> bool {
+        match user_agent.map(str::trim) {
+            // Real mail clients always send one
+            None | Some("") => true,
+            Some(user_agent) => {
+                let user_agent = user_agent.to_ascii_lowercase();
+                self.patterns.iter().any(|p| user_agent.contains(p.as_str()))
+            }
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use crm_config::{ConfigLoader, Configurable};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Public listener for pixels and link clicks.
+    pub port: u16,
+    /// Internal listener for `POST /links`, behind mTLS when configured.
+    /// Reached through the gateway, which sets `X-Tenant-ID` from the
+    /// caller's token; never expose it directly.
+    pub internal_port: u16,
+    pub kafka_brokers: String,
+    pub events_topic: String,
+    /// Base URL tracked links and pixels are served from, e.g.
+    /// `https://t.example.com`.
+    pub public_url: String,
+    /// HMAC key for link tokens. Rotating it breaks links in emails already
+    /// sent.
+    pub signing_secret: String,
+    /// Extra case-insensitive User-Agent substrings treated as bots, on top
+    /// of the built-in list.
+    pub bot_user_agents: Vec<String>,
+    /// Hosts each tenant may link to, e.g. `{"acme": ["acme.com",
+    /// "*.acme.com"]}`; hosts under `"*"` are allowed for every tenant.
+    /// Links to any other host are refused.
+    pub link_hosts: HashMap<String, Vec<String>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            port: 8080,
+            internal_port: 8081,
+            kafka_brokers: "localhost:9092".to_string(),
+            events_topic: "crm-events".to_string(),
+            public_url: "http://localhost:8080".to_string(),
+            signing_secret: "".to_string(),
+            bot_user_agents: Vec::new(),
+            link_hosts: HashMap::new(),
+        }
+    }
+}
+
+impl Configurable for Config {
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if self.signing_secret.len() < 32 {
+            problems.push("signing_secret must be at least 32 characters".to_string());
+        }
+        if !self.public_url.starts_with("http://") && !self.public_url.starts_with("https://") {
+            problems.push("public_url must be an http(s) URL".to_string());
+        }
+        if self.internal_port == self.port {
+            problems.push("internal_port must differ from port".to_string());
+        }
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+}
+
+impl Config {
+    /// Defaults, then the file named by `CONFIG_FILE`, then environment
+    /// variables named after the fields (e.g. `SIGNING_SECRET`).
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(ConfigLoader::new().file_from_env("CONFIG_FILE").load()?)
+    }
+}
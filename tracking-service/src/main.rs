@@ -0,0 +1,298 @@
+use crm_events::{CrmEvent, EmailClicked, EmailOpened, EventPayload};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::ClientConfig;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+use warp::http::{header, Response, StatusCode};
+use warp::hyper::Body;
+use warp::Filter;
+
+mod bots;
+mod config;
+mod metrics;
+mod token;
+
+use bots::BotFilter;
+use config::Config;
+use token::{TokenSigner, TrackingToken};
+
+/// Set by the gateway from the caller's token; only trusted on the
+/// internal listener.
+const TENANT_HEADER: &str = "x-tenant-id";
+const EVENT_SOURCE: &str = "tracking-service";
+
+/// 1x1 transparent GIF.
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff,
+    0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00,
+    0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+/// Every hit must reach us, so neither mail clients nor proxies may cache.
+const NO_CACHE: &str = "no-store, no-cache, must-revalidate, private, max-age=0";
+
+#[derive(Debug, Deserialize)]
+struct LinksRequest {
+    message_id: String,
+    campaign_id: Option<String>,
+    template_id: Option<String>,
+    recipient: Option<String>,
+    user_id: Option<String>,
+    #[serde(default)]
+    links: Vec<String>,
+}
+
+struct AppState {
+    signer: TokenSigner,
+    bots: BotFilter,
+    producer: FutureProducer,
+    events_topic: String,
+    public_url: String,
+    link_hosts: HashMap<String, Vec<String>>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Logs, traces and panic capture
+    let _observability = crm_observability::init("tracking-service")?;
+
+    info!("Starting Tracking Service");
+
+    // Load configuration
+    let config = Config::from_env()?;
+    info!("Configuration: {}", crm_config::redacted(&config));
+
+    metrics::register();
+
+    // Hits are fire-and-forget, so batch briefly rather than per message
+    let producer: FutureProducer = ClientConfig::new()
+        .set("bootstrap.servers", &config.kafka_brokers)
+        .set("message.timeout.ms", "5000")
+        .set("linger.ms", "20")
+        .create()?;
+
+    let state = Arc::new(AppState {
+        signer: TokenSigner::new(&config.signing_secret),
+        bots: BotFilter::new(&config.bot_user_agents),
+        producer,
+        events_topic: config.events_topic.clone(),
+        public_url: config.public_url.trim_end_matches('/').to_string(),
+        link_hosts: config.link_hosts.clone(),
+    });
+
+    let with_state = {
+        let state = Arc::clone(&state);
+        warp::any().map(move || Arc::clone(&state))
+    };
+    let open = warp::get()
+        .and(warp::path!("o" / String))
+        .and(warp::header::optional::<String>("user-agent"))
+        .and(with_state.clone())
+        .map(handle_open);
+    let click = warp::get()
+        .and(warp::path!("c" / String))
+        .and(warp::header::optional::<String>("user-agent"))
+        .and(with_state.clone())
+        .map(handle_click);
+    let links = warp::post()
+        .and(warp::path!("links"))
+        .and(warp::header::<String>(TENANT_HEADER))
+        .and(warp::body::content_length_limit(64 * 1024))
+        .and(warp::body::json())
+        .and(with_state)
+        .map(handle_links);
+    let health = warp::path("health").map(|| "OK");
+
+    // Signing links is for the CRM's own services, through the gateway, and
+    // never on the public listener; behind mTLS when configured
+    let internal = links.with(crm_observability::http_metrics());
+    match crm_tls::MtlsContext::from_env()? {
+        Some(tls) => {
+            tokio::spawn(crm_tls::serve(([0, 0, 0, 0], config.internal_port), tls.server_config(), warp::service(internal)));
+        }
+        None => {
+            info!("Link signing listening on port {}", config.internal_port);
+            tokio::spawn(warp::serve(internal).run(([0, 0, 0, 0], config.internal_port)));
+        }
+    }
+
+    let routes = health
+        .or(crm_observability::metrics_route())
+        .or(open)
+        .or(click)
+        .with(crm_observability::http_metrics());
+
+    info!("Tracking endpoints listening on port {}", config.port);
+    warp::serve(routes).run(([0, 0, 0, 0], config.port)).await;
+
+    Ok(())
+}
+
+/// `GET /o/{token}.gif`. Always answers with the pixel so a bad token never
+/// shows as a broken image.
+fn handle_open(file: String, user_agent: Option<String>, state: Arc<AppState>) -> Response<Body> {
+    let encoded = file.strip_suffix(".gif").unwrap_or(&file);
+    match state.signer.decode(encoded) {
+        Some(token) => {
+            let payload = EmailOpened {
+                message_id: token.message_id.clone(),
+                campaign_id: token.campaign_id.clone(),
+                template_id: token.template_id.clone(),
+                recipient: token.recipient.clone(),
+                user_agent: user_agent.clone(),
+            };
+            record(&state, "open", &token, &payload, user_agent.as_deref());
+        }
+        None => metrics::inc_hit("open", "invalid"),
+    }
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "image/gif")
+        .header(header::CACHE_CONTROL, NO_CACHE)
+        .header(header::PRAGMA, "no-cache")
+        .header(header::EXPIRES, "0")
+        .body(Body::from(PIXEL))
+        .unwrap_or_default()
+}
+
+/// `GET /c/{token}`. Only redirects to URLs we signed.
+fn handle_click(encoded: String, user_agent: Option<String>, state: Arc<AppState>) -> Response<Body> {
+    let decoded = state.signer.decode(&encoded).and_then(|token| {
+        let link_url = token.link_url.clone().filter(|url| is_http_url(url))?;
+        Some((token, link_url))
+    });
+    let (token, link_url) = match decoded {
+        Some(decoded) => decoded,
+        None => {
+            metrics::inc_hit("click", "invalid");
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .header(header::CACHE_CONTROL, NO_CACHE)
+                .body(Body::from("Link not found"))
+                .unwrap_or_default();
+        }
+    };
+
+    let payload = EmailClicked {
+        message_id: token.message_id.clone(),
+        link_url: link_url.clone(),
+        campaign_id: token.campaign_id.clone(),
+        template_id: token.template_id.clone(),
+        recipient: token.recipient.clone(),
+        user_agent: user_agent.clone(),
+    };
+    record(&state, "click", &token, &payload, user_agent.as_deref());
+
+    Response::builder()
+        .status(StatusCode::FOUND)
+        .header(header::LOCATION, link_url)
+        .header(header::CACHE_CONTROL, NO_CACHE)
+        // Keep the token out of the destination's Referer
+        .header(header::REFERRER_POLICY, "no-referrer")
+        .body(Body::empty())
+        .unwrap_or_default()
+}
+
+/// `POST /links`: signs the pixel and link URLs for one outgoing message.
+/// Only links to the tenant's own `link_hosts` are signed, so the click
+/// endpoint can't redirect to just any site.
+fn handle_links(tenant_id: String, request: LinksRequest, state: Arc<AppState>) -> warp::reply::WithStatus<warp::reply::Json> {
+    if let Some(bad) = request.links.iter().find(|link| !is_http_url(link)) {
+        return reply(StatusCode::BAD_REQUEST, json!({ "error": format!("not an http(s) URL: {}", bad) }));
+    }
+    if let Some(bad) = request.links.iter().find(|link| !link_allowed(&state.link_hosts, &tenant_id, link)) {
+        return reply(StatusCode::FORBIDDEN, json!({ "error": format!("links to this host aren't allowed: {}", bad) }));
+    }
+
+    let token = TrackingToken {
+        tenant_id,
+        message_id: request.message_id,
+        campaign_id: request.campaign_id,
+        template_id: request.template_id,
+        recipient: request.recipient,
+        user_id: request.user_id,
+        link_url: None,
+    };
+
+    let pixel_url = format!("{}/o/{}.gif", state.public_url, state.signer.encode(&token));
+    let links: HashMap<&String, String> = request
+        .links
+        .iter()
+        .map(|link| {
+            let token = TrackingToken {
+                link_url: Some(link.clone()),
+                ..token.clone()
+            };
+            (link, format!("{}/c/{}", state.public_url, state.signer.encode(&token)))
+        })
+        .collect();
+
+    reply(StatusCode::OK, json!({ "pixel_url": pixel_url, "links": links }))
+}
+
+fn record<P: EventPayload>(state: &AppState, kind: &str, token: &TrackingToken, payload: &P, user_agent: Option<&str>) {
+    if state.bots.is_bot(user_agent) {
+        debug!("Ignoring {} of {} from bot {:?}", kind, token.message_id, user_agent);
+        metrics::inc_hit(kind, "bot");
+        return;
+    }
+
+    let mut event = match CrmEvent::typed(&token.tenant_id, payload) {
+        Ok(builder) => builder.source(EVENT_SOURCE),
+        Err(e) => {
+            warn!("Failed to build {} event: {}", kind, e);
+            metrics::inc_hit(kind, "dropped");
+            return;
+        }
+    };
+    if let Some(user_id) = &token.user_id {
+        event = event.user_id(user_id);
+    }
+    let body = match serde_json::to_string(&event.build()) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Failed to serialize {} event: {}", kind, e);
+            metrics::inc_hit(kind, "dropped");
+            return;
+        }
+    };
+
+    // Enqueue without waiting for the broker; the response never blocks on Kafka
+    let record = FutureRecord::to(&state.events_topic).key(&token.tenant_id).payload(&body);
+    match state.producer.send_result(record) {
+        Ok(_) => metrics::inc_hit(kind, "recorded"),
+        Err((e, _)) => {
+            warn!("Dropped {} event for tenant {}: {}", kind, token.tenant_id, e);
+            metrics::inc_hit(kind, "dropped");
+        }
+    }
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+/// Whether `link` goes to a host `tenant_id`, or every tenant, may link to.
+/// `*.example.com` allows the subdomains of `example.com`.
+fn link_allowed(link_hosts: &HashMap<String, Vec<String>>, tenant_id: &str, link: &str) -> bool {
+    // Parsed the way browsers do, so e.g. `https://evil.com\@acme.com` is evil.com
+    let Some(host) = url::Url::parse(link).ok().and_then(|url| url.host_str().map(str::to_ascii_lowercase)) else {
+        return false;
+    };
+    [tenant_id, "*"]
+        .iter()
+        .filter_map(|key| link_hosts.get(*key))
+        .flatten()
+        .map(|allowed| allowed.to_ascii_lowercase())
+        .any(|allowed| match allowed.strip_prefix("*.") {
+            Some(domain) => host.strip_suffix(domain).is_some_and(|sub| sub.ends_with('.')),
+            None => host == allowed,
+        })
+}
+
+fn reply(status: StatusCode, body: serde_json::Value) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(warp::reply::json(&body), status)
+}
@@ -0,0 +1,20 @@
+use prometheus::IntCounterVec;
+use std::sync::OnceLock;
+
+static HITS: OnceLock<IntCounterVec> = OnceLock::new();
+
+pub fn register() {
+    HITS.get_or_init(|| {
+        prometheus::register_int_counter_vec!(
+            "tracking_hits_total",
+            "Pixel and link hits by kind (open, click) and result (recorded, bot, invalid, dropped)",
+            &["kind", "result"]
+        ).unwrap()
+    });
+}
+
+pub fn inc_hit(kind: &str, result: &str) {
+    if let Some(counter) = HITS.get() {
+        counter.with_label_values(&[kind, result]).inc();
+    }
+}
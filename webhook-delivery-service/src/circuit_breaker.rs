@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::time::Duration;
+
+#[derive(Debug, Clone, Copy)]
+enum BreakerState {
+    Closed { consecutive_failures: u32 },
+    Open { until: Instant },
+    /// A single probe delivery is in flight after the open period elapsed.
+    HalfOpen,
+}
+
+/// Per-endpoint circuit breakers so one dead receiver does not soak up
+/// delivery capacity with retries.
+pub struct CircuitBreakers {
+    failure_threshold: u32,
+    open_duration: Duration,
+    states: Mutex<HashMap<String, BreakerState>>,
+}
+
+impl CircuitBreakers {
+    pub fn new(failure_threshold: u32, open_duration: Duration) -> Self {
+        CircuitBreakers {
+            failure_threshold: failure_threshold.max(1),
+            open_duration,
+            states: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns whether a delivery to the endpoint may be attempted now.
+    pub fn allow(&self, endpoint_id: &str) -> bool {
+        let mut states = self.states.lock().unwrap();
+        let state = states
+            .entry(endpoint_id.to_string())
+            .or_insert(BreakerState::Closed { consecutive_failures: 0 });
+
+        match *state {
+            BreakerState::Closed { .. } => true,
+            BreakerState::Open { until } if Instant::now() >= until => {
+                *state = BreakerState::HalfOpen;
+                true
+            }
+            BreakerState::Open { .. } | BreakerState::HalfOpen => false,
+        }
+    }
+
+    pub fn record_success(&self, endpoint_id: &str) {
+        self.states
+            .lock()
+            .unwrap()
+            .insert(endpoint_id.to_string(), BreakerState::Closed { consecutive_failures: 0 });
+    }
+
+    /// Records a failed attempt, returning true if this opened the circuit.
+    pub fn record_failure(&self, endpoint_id: &str) -> bool {
+        let mut states = self.states.lock().unwrap();
+        let state = states
+            .entry(endpoint_id.to_string())
+            .or_insert(BreakerState::Closed { consecutive_failures: 0 });
+
+        let failures = match *state {
+            BreakerState::Closed { consecutive_failures } => consecutive_failures + 1,
+            // A failed probe re-opens immediately
+            BreakerState::HalfOpen | BreakerState::Open { .. } => self.failure_threshold,
+        };
+
+        if failures >= self.failure_threshold {
+            *state = BreakerState::Open { until: Instant::now() + self.open_duration };
+            true
+        } else {
+            *state = BreakerState::Closed { consecutive_failures: failures };
+            false
+        }
+    }
+
+    pub fn open_count(&self) -> usize {
+        self.states
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|state| !matches!(state, BreakerState::Closed { .. }))
+            .count()
+    }
+}
+
@@ -0,0 +1,75 @@
+use std::env;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub kafka_brokers: String,
+    pub kafka_group_id: String,
+    pub kafka_topics: Vec<String>,
+    pub dead_letter_topic: String,
+    pub redis_url: String,
+    pub endpoint_cache_ttl_secs: u64,
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub request_timeout_ms: u64,
+    pub max_concurrent_deliveries: usize,
+    pub breaker_failure_threshold: u32,
+    pub breaker_open_secs: u64,
+    pub metrics_port: u16,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Config {
+            kafka_brokers: env::var("KAFKA_BROKERS")
+                .unwrap_or_else(|_| "localhost:9092".to_string()),
+            kafka_group_id: env::var("KAFKA_GROUP_ID")
+                .unwrap_or_else(|_| "webhook-delivery-group".to_string()),
+            kafka_topics: env::var("KAFKA_TOPICS")
+                .unwrap_or_else(|_| "crm-events".to_string())
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .collect(),
+            dead_letter_topic: env::var("DEAD_LETTER_TOPIC")
+                .unwrap_or_else(|_| "webhooks-dlq".to_string()),
+            redis_url: env::var("REDIS_URL")
+                .unwrap_or_else(|_| "redis://localhost:6379".to_string()),
+            endpoint_cache_ttl_secs: env::var("ENDPOINT_CACHE_TTL_SECS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
+            max_attempts: env::var("MAX_ATTEMPTS")
+                .unwrap_or_else(|_| "6".to_string())
+                .parse()
+                .unwrap_or(6),
+            initial_backoff_ms: env::var("INITIAL_BACKOFF_MS")
+                .unwrap_or_else(|_| "500".to_string())
+                .parse()
+                .unwrap_or(500),
+            max_backoff_ms: env::var("MAX_BACKOFF_MS")
+                .unwrap_or_else(|_| "60000".to_string())
+                .parse()
+                .unwrap_or(60000),
+            request_timeout_ms: env::var("REQUEST_TIMEOUT_MS")
+                .unwrap_or_else(|_| "10000".to_string())
+                .parse()
+                .unwrap_or(10000),
+            max_concurrent_deliveries: env::var("MAX_CONCURRENT_DELIVERIES")
+                .unwrap_or_else(|_| "100".to_string())
+                .parse()
+                .unwrap_or(100),
+            breaker_failure_threshold: env::var("BREAKER_FAILURE_THRESHOLD")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .unwrap_or(5),
+            breaker_open_secs: env::var("BREAKER_OPEN_SECS")
+                .unwrap_or_else(|_| "60".to_string())
+                .parse()
+                .unwrap_or(60),
+            metrics_port: env::var("METRICS_PORT")
+                .unwrap_or_else(|_| "8081".to_string())
+                .parse()
+                .unwrap_or(8081),
+        })
+    }
+}
@@ -0,0 +1,202 @@
+use crate::circuit_breaker::CircuitBreakers;
+use crate::config::Config;
+use crate::metrics;
+use crate::registry::WebhookEndpoint;
+use crate::signer::{self, SIGNATURE_HEADER};
+use crm_events::CrmEvent;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::ClientConfig;
+use serde::Serialize;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokio::time::{sleep, Duration};
+use tracing::{info, error, warn, debug};
+
+/// Record published to the dead-letter topic when delivery is abandoned.
+#[derive(Debug, Serialize)]
+struct DeadLetter<'a> {
+    endpoint_id: &'a str,
+    url: &'a str,
+    tenant_id: &'a str,
+    event: &'a CrmEvent,
+    attempts: u32,
+    error: String,
+}
+
+enum AttemptError {
+    Retryable(String),
+    Permanent(String),
+}
+
+pub struct Deliverer {
+    http: reqwest::Client,
+    producer: FutureProducer,
+    breakers: CircuitBreakers,
+    dead_letter_topic: String,
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Deliverer {
+    pub fn new(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.request_timeout_ms))
+            .build()?;
+
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", &config.kafka_brokers)
+            .set("message.timeout.ms", "5000")
+            .create()?;
+
+        Ok(Deliverer {
+            http,
+            producer,
+            breakers: CircuitBreakers::new(
+                config.breaker_failure_threshold,
+                Duration::from_secs(config.breaker_open_secs),
+            ),
+            dead_letter_topic: config.dead_letter_topic.clone(),
+            max_attempts: config.max_attempts.max(1),
+            initial_backoff: Duration::from_millis(config.initial_backoff_ms),
+            max_backoff: Duration::from_millis(config.max_backoff_ms),
+        })
+    }
+
+    /// Delivers the event with exponential backoff, dead-lettering it once
+    /// retries are exhausted, the error is permanent, or the circuit is open.
+    pub async fn deliver(&self, endpoint: &WebhookEndpoint, event: &CrmEvent) {
+        let body = match serde_json::to_vec(event) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("Failed to serialize event for webhook {}: {}", endpoint.id, e);
+                return;
+            }
+        };
+
+        let mut attempt = 0;
+        let last_error = loop {
+            attempt += 1;
+
+            if !self.breakers.allow(&endpoint.id) {
+                metrics::inc_delivery("circuit_open");
+                break format!("circuit open for endpoint {}", endpoint.id);
+            }
+
+            match self.attempt(endpoint, event, &body, attempt).await {
+                Ok(()) => {
+                    self.breakers.record_success(&endpoint.id);
+                    metrics::set_open_circuits(self.breakers.open_count());
+                    metrics::inc_delivery("delivered");
+                    debug!("Delivered {} to webhook {} on attempt {}", event.event_type, endpoint.id, attempt);
+                    return;
+                }
+                Err(AttemptError::Permanent(e)) => {
+                    // Not retried, but still a failed delivery: an endpoint
+                    // rejecting everything is as broken as one that's down
+                    if self.breakers.record_failure(&endpoint.id) {
+                        warn!("Circuit opened for webhook endpoint {}", endpoint.id);
+                    }
+                    metrics::set_open_circuits(self.breakers.open_count());
+                    metrics::inc_delivery("dead_lettered");
+                    break e;
+                }
+                Err(AttemptError::Retryable(e)) => {
+                    if self.breakers.record_failure(&endpoint.id) {
+                        warn!("Circuit opened for webhook endpoint {}", endpoint.id);
+                    }
+                    metrics::set_open_circuits(self.breakers.open_count());
+
+                    if attempt >= self.max_attempts {
+                        metrics::inc_delivery("dead_lettered");
+                        break e;
+                    }
+
+                    let backoff = self
+                        .initial_backoff
+                        .saturating_mul(2u32.saturating_pow(attempt - 1))
+                        .min(self.max_backoff);
+                    warn!("Webhook {} attempt {} failed: {}; retrying in {:?}", endpoint.id, attempt, e, backoff);
+                    sleep(backoff).await;
+                }
+            }
+        };
+
+        self.dead_letter(endpoint, event, attempt, last_error).await;
+    }
+
+    async fn attempt(
+        &self,
+        endpoint: &WebhookEndpoint,
+        event: &CrmEvent,
+        body: &[u8],
+        attempt: u32,
+    ) -> Result<(), AttemptError> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+
+        let started = Instant::now();
+        let response = self
+            .http
+            .post(&endpoint.url)
+            .header("Content-Type", "application/json")
+            .header(SIGNATURE_HEADER, signer::sign(&endpoint.secret, timestamp, body))
+            .header("X-CRM-Event-Type", &event.event_type)
+            .header("X-CRM-Endpoint-Id", &endpoint.id)
+            .header("X-CRM-Delivery-Attempt", attempt.to_string())
+            .body(body.to_vec())
+            .send()
+            .await;
+
+        match response {
+            Ok(response) if response.status().is_success() => {
+                metrics::observe_attempt("success", started.elapsed());
+                Ok(())
+            }
+            Ok(response) => {
+                let status = response.status();
+                metrics::observe_attempt("http_error", started.elapsed());
+                // Client errors won't fix themselves, except timeouts and throttling
+                if status.is_client_error() && status.as_u16() != 408 && status.as_u16() != 429 {
+                    Err(AttemptError::Permanent(format!("endpoint returned {}", status)))
+                } else {
+                    Err(AttemptError::Retryable(format!("endpoint returned {}", status)))
+                }
+            }
+            Err(e) => {
+                metrics::observe_attempt("transport_error", started.elapsed());
+                Err(AttemptError::Retryable(e.to_string()))
+            }
+        }
+    }
+
+    async fn dead_letter(&self, endpoint: &WebhookEndpoint, event: &CrmEvent, attempts: u32, error: String) {
+        warn!("Dead-lettering {} for webhook {} after {} attempts: {}", event.event_type, endpoint.id, attempts, error);
+
+        let record = DeadLetter {
+            endpoint_id: &endpoint.id,
+            url: &endpoint.url,
+            tenant_id: &event.tenant_id,
+            event,
+            attempts,
+            error,
+        };
+        let payload = match serde_json::to_string(&record) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("Failed to serialize dead letter: {}", e);
+                return;
+            }
+        };
+
+        let message = FutureRecord::to(&self.dead_letter_topic)
+            .key(&event.tenant_id)
+            .payload(&payload);
+        if let Err((e, _)) = self.producer.send(message, Duration::from_secs(5)).await {
+            error!("Failed to publish dead letter for webhook {}: {}", endpoint.id, e);
+        } else {
+            info!("Dead letter published for webhook {}", endpoint.id);
+        }
+    }
+}
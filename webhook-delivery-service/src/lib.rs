@@ -0,0 +1,9 @@
+//! The webhook delivery pipeline. `main.rs` wires it to Kafka; it is a
+//! library so the tests under `tests/` can drive it directly.
+
+pub mod circuit_breaker;
+pub mod config;
+pub mod delivery;
+pub mod metrics;
+pub mod registry;
+pub mod signer;
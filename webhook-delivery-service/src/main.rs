@@ -0,0 +1,98 @@
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::{ClientConfig, Message};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::time::Duration;
+use tracing::{info, error, warn};
+
+use crm_events::CrmEvent;
+use webhook_delivery_service::config::Config;
+use webhook_delivery_service::delivery::Deliverer;
+use webhook_delivery_service::metrics;
+use webhook_delivery_service::registry::EndpointRegistry;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Logs, traces and panic capture
+    let _observability = crm_observability::init("webhook-delivery-service")?;
+
+    info!("Starting Webhook Delivery Service");
+
+    // Load configuration
+    let config = Config::from_env()?;
+
+    // Expose Prometheus metrics
+    metrics::register();
+    tokio::spawn(crm_observability::serve_metrics(config.metrics_port));
+
+    let registry = EndpointRegistry::new(
+        &config.redis_url,
+        Duration::from_secs(config.endpoint_cache_ttl_secs),
+    ).await?;
+    let deliverer = Arc::new(Deliverer::new(&config)?);
+    let delivery_slots = Arc::new(Semaphore::new(config.max_concurrent_deliveries.max(1)));
+
+    // Create Kafka consumer
+    let consumer = create_consumer(&config)?;
+    let topics: Vec<&str> = config.kafka_topics.iter().map(|s| s.as_str()).collect();
+    consumer.subscribe(&topics)?;
+
+    info!("Connected to Kafka, starting webhook delivery...");
+
+    loop {
+        let message = match consumer.recv().await {
+            Ok(message) => message,
+            Err(e) => {
+                error!("Error receiving message: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        let event: CrmEvent = match message.payload().map(serde_json::from_slice) {
+            Some(Ok(event)) => event,
+            Some(Err(e)) => {
+                warn!("Skipping undecodable event: {}", e);
+                continue;
+            }
+            None => {
+                warn!("Received empty message");
+                continue;
+            }
+        };
+
+        let endpoints = match registry.endpoints_for(&event.tenant_id, &event.event_type).await {
+            Ok(endpoints) => endpoints,
+            Err(e) => {
+                error!("Failed to load webhook endpoints for tenant {}: {}", event.tenant_id, e);
+                continue;
+            }
+        };
+
+        let event = Arc::new(event);
+        for endpoint in endpoints {
+            // Backpressure: stop consuming while all delivery slots are busy
+            let permit = Arc::clone(&delivery_slots).acquire_owned().await?;
+            let deliverer = Arc::clone(&deliverer);
+            let event = Arc::clone(&event);
+
+            tokio::spawn(async move {
+                deliverer.deliver(&endpoint, &event).await;
+                drop(permit);
+            });
+        }
+    }
+}
+
+fn create_consumer(config: &Config) -> Result<StreamConsumer, Box<dyn std::error::Error>> {
+    let consumer: StreamConsumer = ClientConfig::new()
+        .set("group.id", &config.kafka_group_id)
+        .set("bootstrap.servers", &config.kafka_brokers)
+        .set("enable.partition.eof", "false")
+        .set("session.timeout.ms", "6000")
+        .set("enable.auto.commit", "true")
+        .set("auto.offset.reset", "latest")
+        .create()?;
+
+    Ok(consumer)
+}
@@ -0,0 +1,59 @@
+use prometheus::{Histogram, IntCounterVec, IntGauge};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+static DELIVERIES: OnceLock<IntCounterVec> = OnceLock::new();
+static ATTEMPTS: OnceLock<IntCounterVec> = OnceLock::new();
+static LATENCY: OnceLock<Histogram> = OnceLock::new();
+static OPEN_CIRCUITS: OnceLock<IntGauge> = OnceLock::new();
+
+pub fn register() {
+    DELIVERIES.get_or_init(|| {
+        prometheus::register_int_counter_vec!(
+            "webhook_deliveries_total",
+            "Webhook deliveries by final status (delivered, dead_lettered, circuit_open)",
+            &["status"]
+        ).unwrap()
+    });
+    ATTEMPTS.get_or_init(|| {
+        prometheus::register_int_counter_vec!(
+            "webhook_delivery_attempts_total",
+            "Individual webhook HTTP attempts by outcome",
+            &["outcome"]
+        ).unwrap()
+    });
+    LATENCY.get_or_init(|| {
+        prometheus::register_histogram!(
+            "webhook_delivery_duration_seconds",
+            "Duration of webhook HTTP attempts in seconds",
+            vec![0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]
+        ).unwrap()
+    });
+    OPEN_CIRCUITS.get_or_init(|| {
+        prometheus::register_int_gauge!(
+            "webhook_open_circuits",
+            "Number of endpoints whose circuit breaker is open or half-open"
+        ).unwrap()
+    });
+}
+
+pub fn inc_delivery(status: &str) {
+    if let Some(counter) = DELIVERIES.get() {
+        counter.with_label_values(&[status]).inc();
+    }
+}
+
+pub fn observe_attempt(outcome: &str, latency: Duration) {
+    if let Some(counter) = ATTEMPTS.get() {
+        counter.with_label_values(&[outcome]).inc();
+    }
+    if let Some(histogram) = LATENCY.get() {
+        histogram.observe(latency.as_secs_f64());
+    }
+}
+
+pub fn set_open_circuits(count: usize) {
+    if let Some(gauge) = OPEN_CIRCUITS.get() {
+        gauge.set(count as i64);
+    }
+}
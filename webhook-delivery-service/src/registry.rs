@@ -0,0 +1,94 @@
+use redis::aio::Connection;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Instant;
+use tokio::sync::Mutex;
+use tokio::time::Duration;
+use tracing::{debug, warn};
+
+/// A tenant-registered webhook endpoint, stored as JSON in the Redis hash
+/// `webhooks:{tenant_id}` keyed by endpoint id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEndpoint {
+    pub id: String,
+    pub url: String,
+    pub secret: String,
+    /// Event types to deliver; empty means all.
+    #[serde(default)]
+    pub event_types: Vec<String>,
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+impl WebhookEndpoint {
+    pub fn accepts(&self, event_type: &str) -> bool {
+        self.active && (self.event_types.is_empty() || self.event_types.iter().any(|t| t == event_type))
+    }
+}
+
+pub struct EndpointRegistry {
+    redis_connection: Mutex<Connection>,
+    cache_ttl: Duration,
+    cache: Mutex<HashMap<String, (Vec<WebhookEndpoint>, Instant)>>,
+}
+
+impl EndpointRegistry {
+    pub async fn new(redis_url: &str, cache_ttl: Duration) -> Result<Self, Box<dyn std::error::Error>> {
+        let redis_client = redis::Client::open(redis_url)?;
+        let redis_connection = Mutex::new(redis_client.get_async_connection().await?);
+
+        Ok(EndpointRegistry {
+            redis_connection,
+            cache_ttl,
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub async fn endpoints_for(
+        &self,
+        tenant_id: &str,
+        event_type: &str,
+    ) -> Result<Vec<WebhookEndpoint>, Box<dyn std::error::Error>> {
+        let endpoints = self.tenant_endpoints(tenant_id).await?;
+        Ok(endpoints.into_iter().filter(|e| e.accepts(event_type)).collect())
+    }
+
+    async fn tenant_endpoints(&self, tenant_id: &str) -> Result<Vec<WebhookEndpoint>, Box<dyn std::error::Error>> {
+        if let Some((endpoints, fetched_at)) = self.cache.lock().await.get(tenant_id) {
+            if fetched_at.elapsed() < self.cache_ttl {
+                return Ok(endpoints.clone());
+            }
+        }
+
+        let raw: HashMap<String, String> = self
+            .redis_connection
+            .lock()
+            .await
+            .hgetall(format!("webhooks:{}", tenant_id))
+            .await?;
+
+        let endpoints: Vec<WebhookEndpoint> = raw
+            .into_iter()
+            .filter_map(|(id, json)| match serde_json::from_str(&json) {
+                Ok(endpoint) => Some(endpoint),
+                Err(e) => {
+                    warn!("Ignoring malformed webhook endpoint {} for tenant {}: {}", id, tenant_id, e);
+                    None
+                }
+            })
+            .collect();
+        debug!("Loaded {} webhook endpoints for tenant {}", endpoints.len(), tenant_id);
+
+        self.cache
+            .lock()
+            .await
+            .insert(tenant_id.to_string(), (endpoints.clone(), Instant::now()));
+
+        Ok(endpoints)
+    }
+}
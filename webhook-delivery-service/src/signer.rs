@@ -0,0 +1,18 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const SIGNATURE_HEADER: &str = "X-CRM-Signature";
+
+/// Signs `{timestamp}.{body}` with the endpoint secret, producing the
+/// `t=<timestamp>,v1=<hex hmac>` header value receivers verify against.
+/// Including the timestamp lets receivers reject replayed deliveries.
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    format!("t={},v1={}", timestamp, hex::encode(mac.finalize().into_bytes()))
+}
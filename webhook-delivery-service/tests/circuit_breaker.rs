@@ -0,0 +1,41 @@
+use std::time::Duration;
+use webhook_delivery_service::circuit_breaker::CircuitBreakers;
+
+fn opened(breakers: &CircuitBreakers) {
+    assert!(!breakers.record_failure("e1"));
+    assert!(breakers.record_failure("e1"));
+    assert!(!breakers.allow("e1"));
+}
+
+#[test]
+fn a_successful_probe_closes_the_circuit() {
+    let breakers = CircuitBreakers::new(2, Duration::from_millis(20));
+    opened(&breakers);
+    std::thread::sleep(Duration::from_millis(30));
+    assert!(breakers.allow("e1"));
+    // Only one probe at a time
+    assert!(!breakers.allow("e1"));
+    breakers.record_success("e1");
+    assert!(breakers.allow("e1"));
+    assert_eq!(breakers.open_count(), 0);
+}
+
+#[test]
+fn a_failed_probe_opens_the_circuit_again() {
+    let breakers = CircuitBreakers::new(2, Duration::from_millis(20));
+    opened(&breakers);
+    std::thread::sleep(Duration::from_millis(30));
+    assert!(breakers.allow("e1"));
+    assert!(breakers.record_failure("e1"));
+    assert!(!breakers.allow("e1"));
+    assert_eq!(breakers.open_count(), 1);
+}
+
+#[test]
+fn endpoints_have_their_own_circuits() {
+    let breakers = CircuitBreakers::new(1, Duration::from_secs(60));
+    assert!(breakers.record_failure("e1"));
+    assert!(!breakers.allow("e1"));
+    assert!(breakers.allow("e2"));
+    assert_eq!(breakers.open_count(), 1);
+}
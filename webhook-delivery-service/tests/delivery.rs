@@ -0,0 +1,124 @@
+use crm_events::CrmEvent;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use webhook_delivery_service::config::Config;
+use webhook_delivery_service::delivery::Deliverer;
+use webhook_delivery_service::registry::WebhookEndpoint;
+
+fn config(breaker_failure_threshold: u32, max_attempts: u32) -> Config {
+    Config {
+        // Nothing listens here: dead letters never leave the producer
+        kafka_brokers: "127.0.0.1:1".to_string(),
+        kafka_group_id: "webhook-delivery-test".to_string(),
+        kafka_topics: vec!["crm-events".to_string()],
+        dead_letter_topic: "webhooks-dlq".to_string(),
+        redis_url: "redis://127.0.0.1:1".to_string(),
+        endpoint_cache_ttl_secs: 30,
+        max_attempts,
+        initial_backoff_ms: 10,
+        max_backoff_ms: 10,
+        request_timeout_ms: 2000,
+        max_concurrent_deliveries: 1,
+        breaker_failure_threshold,
+        breaker_open_secs: 60,
+        metrics_port: 0,
+    }
+}
+
+/// A receiver answering every request with `status`, and how many it got.
+async fn receiver(status: &'static str) -> (WebhookEndpoint, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&requests);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            counted.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(answer(stream, status));
+        }
+    });
+
+    let endpoint = WebhookEndpoint {
+        id: "e1".to_string(),
+        url: format!("http://{}/hooks", address),
+        secret: "whsec".to_string(),
+        event_types: Vec::new(),
+        active: true,
+    };
+    (endpoint, requests)
+}
+
+async fn answer(mut stream: TcpStream, status: &str) {
+    // Read the whole request so the client sees the reply, not a reset
+    let mut request = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let read = stream.read(&mut chunk).await.unwrap_or(0);
+        if read == 0 {
+            return;
+        }
+        request.extend_from_slice(&chunk[..read]);
+        let text = String::from_utf8_lossy(&request);
+        if let Some(end) = text.find("\r\n\r\n") {
+            let length = text[..end]
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length").then(|| value.trim().parse::<usize>().ok())?
+                })
+                .unwrap_or(0);
+            if request.len() >= end + 4 + length {
+                break;
+            }
+        }
+    }
+    let reply = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+    let _ = stream.write_all(reply.as_bytes()).await;
+}
+
+fn event() -> CrmEvent {
+    CrmEvent::builder("t1", "contact.created").build()
+}
+
+/// Delivers, giving up once only the dead letter is left to publish.
+async fn deliver(deliverer: &Deliverer, endpoint: &WebhookEndpoint) {
+    let _ = tokio::time::timeout(Duration::from_millis(500), deliverer.deliver(endpoint, &event())).await;
+}
+
+#[tokio::test]
+async fn client_errors_count_against_the_circuit() {
+    let (endpoint, requests) = receiver("400 Bad Request").await;
+    let deliverer = Deliverer::new(&config(2, 6)).unwrap();
+
+    // Not retried
+    deliver(&deliverer, &endpoint).await;
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+    // The second rejection opens the circuit, so the third isn't sent
+    deliver(&deliverer, &endpoint).await;
+    deliver(&deliverer, &endpoint).await;
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn retries_throttled_deliveries() {
+    let (endpoint, requests) = receiver("429 Too Many Requests").await;
+    let deliverer = Deliverer::new(&config(10, 3)).unwrap();
+
+    deliver(&deliverer, &endpoint).await;
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn successful_deliveries_keep_the_circuit_closed() {
+    let (endpoint, requests) = receiver("204 No Content").await;
+    let deliverer = Deliverer::new(&config(1, 6)).unwrap();
+
+    for _ in 0..3 {
+        deliver(&deliverer, &endpoint).await;
+    }
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+}
@@ -0,0 +1,121 @@
+use crate::config::Config;
+use crate::state::WorkflowRun;
+use crm_events::CrmEvent;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::ClientConfig;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::time::Duration;
+
+pub type ActionError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Prefix of the `source` on events emitted by a workflow, so the engine can
+/// keep a workflow from re-triggering itself.
+pub const WORKFLOW_SOURCE_PREFIX: &str = "workflow:";
+
+#[derive(Debug, Deserialize)]
+struct PluginResponse {
+    success: bool,
+    #[serde(default)]
+    error: Option<PluginError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PluginError {
+    code: String,
+    message: String,
+}
+
+pub struct ActionExecutor {
+    http: reqwest::Client,
+    producer: FutureProducer,
+    extension_runtime_url: String,
+    output_topic: String,
+}
+
+impl ActionExecutor {
+    pub fn new(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.action_timeout_ms))
+            .build()?;
+
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", &config.kafka_brokers)
+            .set("message.timeout.ms", "5000")
+            .create()?;
+
+        Ok(ActionExecutor {
+            http,
+            producer,
+            extension_runtime_url: config.extension_runtime_url.trim_end_matches('/').to_string(),
+            output_topic: config.output_topic.clone(),
+        })
+    }
+
+    pub async fn invoke_plugin(
+        &self,
+        run: &WorkflowRun,
+        module_path: &str,
+        function_name: &str,
+        timeout_seconds: Option<u64>,
+    ) -> Result<(), ActionError> {
+        let request = json!({
+            "module_path": module_path,
+            "function_name": function_name,
+            "params": serde_json::to_value(&run.event)?,
+            "timeout_seconds": timeout_seconds,
+        });
+
+        let response = self
+            .http
+            .post(format!("{}/execute", self.extension_runtime_url))
+            .json(&request)
+            .send()
+            .await?;
+        // Failed executions come with an error status and their error
+        let status = response.status();
+        let response: PluginResponse = match response.json().await {
+            Ok(response) => response,
+            Err(_) if !status.is_success() => return Err(format!("plugin execution failed with {}", status).into()),
+            Err(e) => return Err(e.into()),
+        };
+
+        if !response.success {
+            let error = match response.error {
+                Some(error) => format!("{}: {}", error.code, error.message),
+                None => "plugin execution failed".to_string(),
+            };
+            return Err(error.into());
+        }
+        Ok(())
+    }
+
+    pub async fn emit_event(&self, run: &WorkflowRun, event_type: &str, payload: &Value) -> Result<(), ActionError> {
+        let event = CrmEvent::builder(&run.tenant_id, event_type)
+            .payload(payload.clone())
+            .source(format!("{}{}", WORKFLOW_SOURCE_PREFIX, run.workflow_id))
+            .build();
+        let body = serde_json::to_string(&event)?;
+
+        let record = FutureRecord::to(&self.output_topic)
+            .key(&run.tenant_id)
+            .payload(&body);
+        self.producer
+            .send(record, Duration::from_secs(5))
+            .await
+            .map_err(|(e, _)| e)?;
+        Ok(())
+    }
+
+    pub async fn call_webhook(&self, run: &WorkflowRun, url: &str) -> Result<(), ActionError> {
+        self.http
+            .post(url)
+            .header("X-CRM-Workflow-Id", &run.workflow_id)
+            .header("X-CRM-Workflow-Run-Id", &run.run_id)
+            .json(&run.event)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use std::env;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub kafka_brokers: String,
+    pub kafka_group_id: String,
+    pub kafka_topics: Vec<String>,
+    pub output_topic: String,
+    pub redis_url: String,
+    pub extension_runtime_url: String,
+    pub action_timeout_ms: u64,
+    pub workflow_cache_ttl_secs: u64,
+    pub scheduler_poll_interval_ms: u64,
+    /// Upper bound on steps run in one pass, guarding against runaway rules.
+    pub max_steps_per_run: usize,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Config {
+            kafka_brokers: env::var("KAFKA_BROKERS")
+                .unwrap_or_else(|_| "localhost:9092".to_string()),
+            kafka_group_id: env::var("KAFKA_GROUP_ID")
+                .unwrap_or_else(|_| "workflow-engine-group".to_string()),
+            kafka_topics: env::var("KAFKA_TOPICS")
+                .unwrap_or_else(|_| "crm-events".to_string())
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .collect(),
+            output_topic: env::var("OUTPUT_TOPIC")
+                .unwrap_or_else(|_| "crm-events".to_string()),
+            redis_url: env::var("REDIS_URL")
+                .unwrap_or_else(|_| "redis://localhost:6379".to_string()),
+            extension_runtime_url: env::var("EXTENSION_RUNTIME_URL")
+                .unwrap_or_else(|_| "http://localhost:8080".to_string()),
+            action_timeout_ms: env::var("ACTION_TIMEOUT_MS")
+                .unwrap_or_else(|_| "10000".to_string())
+                .parse()
+                .unwrap_or(10000),
+            workflow_cache_ttl_secs: env::var("WORKFLOW_CACHE_TTL_SECS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
+            scheduler_poll_interval_ms: env::var("SCHEDULER_POLL_INTERVAL_MS")
+                .unwrap_or_else(|_| "1000".to_string())
+                .parse()
+                .unwrap_or(1000),
+            max_steps_per_run: env::var("MAX_STEPS_PER_RUN")
+                .unwrap_or_else(|_| "50".to_string())
+                .parse()
+                .unwrap_or(50),
+        })
+    }
+}
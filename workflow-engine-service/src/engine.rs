@@ -0,0 +1,135 @@
+use crate::actions::{ActionError, ActionExecutor, WORKFLOW_SOURCE_PREFIX};
+use crate::rules::{Step, Workflow, WorkflowRegistry};
+use crate::state::{RunStore, WorkflowRun};
+use crm_events::CrmEvent;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, warn};
+
+pub struct WorkflowEngine {
+    registry: WorkflowRegistry,
+    runs: RunStore,
+    actions: ActionExecutor,
+    max_steps_per_run: usize,
+}
+
+impl WorkflowEngine {
+    pub fn new(registry: WorkflowRegistry, runs: RunStore, actions: ActionExecutor, max_steps_per_run: usize) -> Self {
+        WorkflowEngine {
+            registry,
+            runs,
+            actions,
+            max_steps_per_run: max_steps_per_run.max(1),
+        }
+    }
+
+    /// Starts a run for every workflow the event triggers.
+    pub async fn handle_event(&self, event: &CrmEvent) {
+        let workflows = match self.registry.matching(event).await {
+            Ok(workflows) => workflows,
+            Err(e) => {
+                error!("Failed to load workflows for tenant {}: {}", event.tenant_id, e);
+                return;
+            }
+        };
+
+        for workflow in workflows {
+            if is_emitted_by(event, &workflow) {
+                debug!("Skipping workflow {} triggered by its own event", workflow.id);
+                continue;
+            }
+
+            let run = WorkflowRun {
+                run_id: uuid::Uuid::new_v4().to_string(),
+                tenant_id: event.tenant_id.clone(),
+                workflow_id: workflow.id.clone(),
+                event: event.clone(),
+                next_step: 0,
+            };
+            info!("Starting workflow {} run {} for tenant {}", workflow.id, run.run_id, run.tenant_id);
+            self.advance(&workflow, run).await;
+        }
+    }
+
+    /// Resumes runs whose delay has elapsed.
+    pub async fn resume_due(&self, limit: isize) {
+        let runs = match self.runs.claim_due(now(), limit).await {
+            Ok(runs) => runs,
+            Err(e) => {
+                error!("Failed to claim due workflow runs: {}", e);
+                return;
+            }
+        };
+
+        for run in runs {
+            match self.registry.get(&run.tenant_id, &run.workflow_id).await {
+                Ok(Some(workflow)) if workflow.active => self.advance(&workflow, run).await,
+                Ok(_) => warn!(
+                    "Dropping run {}: workflow {} was removed or deactivated",
+                    run.run_id, run.workflow_id
+                ),
+                Err(e) => error!("Failed to load workflow {} for run {}: {}", run.workflow_id, run.run_id, e),
+            }
+        }
+    }
+
+    /// Executes steps from `run.next_step` until the workflow finishes, fails,
+    /// or reaches a delay, in which case the run is persisted for later.
+    async fn advance(&self, workflow: &Workflow, mut run: WorkflowRun) {
+        let mut executed = 0;
+
+        while let Some(step) = workflow.steps.get(run.next_step) {
+            if executed == self.max_steps_per_run {
+                error!("Aborting run {}: exceeded {} steps", run.run_id, self.max_steps_per_run);
+                return;
+            }
+            executed += 1;
+            run.next_step += 1;
+
+            if let Step::Delay { seconds } = step {
+                let resume_at = now() + *seconds as i64;
+                if let Err(e) = self.runs.suspend(&run, resume_at).await {
+                    error!("Failed to persist run {}: {}", run.run_id, e);
+                } else {
+                    debug!("Run {} suspended until {}", run.run_id, resume_at);
+                }
+                return;
+            }
+
+            if let Err(e) = self.execute(step, &run).await {
+                error!(
+                    "Workflow {} run {} failed at step {}: {}",
+                    workflow.id, run.run_id, run.next_step - 1, e
+                );
+                return;
+            }
+        }
+
+        info!("Workflow {} run {} completed", workflow.id, run.run_id);
+    }
+
+    async fn execute(&self, step: &Step, run: &WorkflowRun) -> Result<(), ActionError> {
+        match step {
+            Step::InvokePlugin { module_path, function_name, timeout_seconds } => {
+                self.actions.invoke_plugin(run, module_path, function_name, *timeout_seconds).await
+            }
+            Step::EmitEvent { event_type, payload } => self.actions.emit_event(run, event_type, payload).await,
+            Step::CallWebhook { url } => self.actions.call_webhook(run, url).await,
+            Step::Delay { .. } => Ok(()),
+        }
+    }
+}
+
+fn is_emitted_by(event: &CrmEvent, workflow: &Workflow) -> bool {
+    event
+        .source
+        .as_deref()
+        .and_then(|source| source.strip_prefix(WORKFLOW_SOURCE_PREFIX))
+        == Some(workflow.id.as_str())
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,97 @@
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::{ClientConfig, Message};
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::{info, error, warn};
+
+mod actions;
+mod config;
+mod engine;
+mod rules;
+mod state;
+
+use actions::ActionExecutor;
+use config::Config;
+use crm_events::CrmEvent;
+use engine::WorkflowEngine;
+use rules::WorkflowRegistry;
+use state::RunStore;
+
+/// Runs resumed per scheduler tick.
+const RESUME_BATCH_SIZE: isize = 100;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Logs, traces and panic capture
+    let _observability = crm_observability::init("workflow-engine-service")?;
+
+    info!("Starting Workflow Engine Service");
+
+    // Load configuration
+    let config = Config::from_env()?;
+
+    let redis_client = redis::Client::open(config.redis_url.as_str())?;
+    let registry = WorkflowRegistry::new(
+        redis_client.get_async_connection().await?,
+        Duration::from_secs(config.workflow_cache_ttl_secs),
+    );
+    let runs = RunStore::new(redis_client.get_async_connection().await?);
+    let actions = ActionExecutor::new(&config)?;
+    let engine = Arc::new(WorkflowEngine::new(registry, runs, actions, config.max_steps_per_run));
+
+    // Resume delayed runs in the background
+    let scheduler_engine = Arc::clone(&engine);
+    let poll_interval = Duration::from_millis(config.scheduler_poll_interval_ms);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(poll_interval);
+        loop {
+            interval.tick().await;
+            scheduler_engine.resume_due(RESUME_BATCH_SIZE).await;
+        }
+    });
+
+    // Create Kafka consumer
+    let consumer = create_consumer(&config)?;
+    let topics: Vec<&str> = config.kafka_topics.iter().map(|s| s.as_str()).collect();
+    consumer.subscribe(&topics)?;
+
+    info!("Connected to Kafka, evaluating workflows...");
+
+    loop {
+        let message = match consumer.recv().await {
+            Ok(message) => message,
+            Err(e) => {
+                error!("Error receiving message: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        let event: CrmEvent = match message.payload().map(serde_json::from_slice) {
+            Some(Ok(event)) => event,
+            Some(Err(e)) => {
+                warn!("Skipping undecodable event: {}", e);
+                continue;
+            }
+            None => {
+                warn!("Received empty message");
+                continue;
+            }
+        };
+
+        engine.handle_event(&event).await;
+    }
+}
+
+fn create_consumer(config: &Config) -> Result<StreamConsumer, Box<dyn std::error::Error>> {
+    let consumer: StreamConsumer = ClientConfig::new()
+        .set("group.id", &config.kafka_group_id)
+        .set("bootstrap.servers", &config.kafka_brokers)
+        .set("enable.partition.eof", "false")
+        .set("session.timeout.ms", "6000")
+        .set("enable.auto.commit", "true")
+        .set("auto.offset.reset", "latest")
+        .create()?;
+
+    Ok(consumer)
+}
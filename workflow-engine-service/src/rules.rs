@@ -0,0 +1,179 @@
+use crm_events::CrmEvent;
+use redis::aio::Connection;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Instant;
+use tokio::sync::Mutex;
+use tokio::time::Duration;
+use tracing::{debug, warn};
+
+/// A tenant-defined trigger→condition→action rule, stored as JSON in the
+/// Redis hash `workflows:{tenant_id}` keyed by workflow id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workflow {
+    pub id: String,
+    /// Event type that starts a run.
+    pub trigger: String,
+    /// All conditions must hold for the run to start.
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    pub steps: Vec<Step>,
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Condition {
+    /// `event_type`, `source`, `user_id`, or a dotted path under `payload.`.
+    pub field: String,
+    pub op: Operator,
+    #[serde(default)]
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operator {
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    Contains,
+    Exists,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Step {
+    /// Runs a plugin function on the extension runtime with the triggering
+    /// event as its parameters.
+    InvokePlugin {
+        module_path: String,
+        function_name: String,
+        #[serde(default)]
+        timeout_seconds: Option<u64>,
+    },
+    /// Publishes a new event for the same tenant.
+    EmitEvent {
+        event_type: String,
+        #[serde(default)]
+        payload: Value,
+    },
+    /// POSTs the triggering event to a URL.
+    CallWebhook { url: String },
+    /// Suspends the run; it resumes from the next step once the delay elapses.
+    Delay { seconds: u64 },
+}
+
+impl Workflow {
+    pub fn matches(&self, event: &CrmEvent) -> bool {
+        self.active
+            && self.trigger == event.event_type
+            && self.conditions.iter().all(|c| c.holds(event))
+    }
+}
+
+impl Condition {
+    pub fn holds(&self, event: &CrmEvent) -> bool {
+        let actual = resolve_field(event, &self.field);
+
+        match self.op {
+            Operator::Exists => actual.is_some_and(|v| !v.is_null()),
+            Operator::Eq => actual.as_ref() == Some(&self.value),
+            Operator::Ne => actual.as_ref() != Some(&self.value),
+            Operator::Gt => compare(actual.as_ref(), &self.value).is_some_and(|(a, b)| a > b),
+            Operator::Lt => compare(actual.as_ref(), &self.value).is_some_and(|(a, b)| a < b),
+            Operator::Contains => match (actual, &self.value) {
+                (Some(Value::String(s)), Value::String(needle)) => s.contains(needle.as_str()),
+                (Some(Value::Array(items)), needle) => items.contains(needle),
+                _ => false,
+            },
+        }
+    }
+}
+
+fn resolve_field(event: &CrmEvent, field: &str) -> Option<Value> {
+    match field {
+        "event_type" => Some(Value::String(event.event_type.clone())),
+        "tenant_id" => Some(Value::String(event.tenant_id.clone())),
+        "source" => event.source.clone().map(Value::String),
+        "user_id" => event.user_id.clone().map(Value::String),
+        _ => {
+            let path = field.strip_prefix("payload.")?;
+            path.split('.')
+                .try_fold(&event.payload, |value, key| value.get(key))
+                .cloned()
+        }
+    }
+}
+
+fn compare(actual: Option<&Value>, expected: &Value) -> Option<(f64, f64)> {
+    Some((actual?.as_f64()?, expected.as_f64()?))
+}
+
+pub struct WorkflowRegistry {
+    redis_connection: Mutex<Connection>,
+    cache_ttl: Duration,
+    cache: Mutex<HashMap<String, (Vec<Workflow>, Instant)>>,
+}
+
+impl WorkflowRegistry {
+    pub fn new(redis_connection: Connection, cache_ttl: Duration) -> Self {
+        WorkflowRegistry {
+            redis_connection: Mutex::new(redis_connection),
+            cache_ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn matching(&self, event: &CrmEvent) -> Result<Vec<Workflow>, redis::RedisError> {
+        let workflows = self.tenant_workflows(&event.tenant_id).await?;
+        Ok(workflows.into_iter().filter(|w| w.matches(event)).collect())
+    }
+
+    /// Looks up one workflow, used when resuming a persisted run.
+    pub async fn get(&self, tenant_id: &str, workflow_id: &str) -> Result<Option<Workflow>, redis::RedisError> {
+        let workflows = self.tenant_workflows(tenant_id).await?;
+        Ok(workflows.into_iter().find(|w| w.id == workflow_id))
+    }
+
+    async fn tenant_workflows(&self, tenant_id: &str) -> Result<Vec<Workflow>, redis::RedisError> {
+        if let Some((workflows, fetched_at)) = self.cache.lock().await.get(tenant_id) {
+            if fetched_at.elapsed() < self.cache_ttl {
+                return Ok(workflows.clone());
+            }
+        }
+
+        let raw: HashMap<String, String> = self
+            .redis_connection
+            .lock()
+            .await
+            .hgetall(format!("workflows:{}", tenant_id))
+            .await?;
+
+        let workflows: Vec<Workflow> = raw
+            .into_iter()
+            .filter_map(|(id, json)| match serde_json::from_str(&json) {
+                Ok(workflow) => Some(workflow),
+                Err(e) => {
+                    warn!("Ignoring malformed workflow {} for tenant {}: {}", id, tenant_id, e);
+                    None
+                }
+            })
+            .collect();
+        debug!("Loaded {} workflows for tenant {}", workflows.len(), tenant_id);
+
+        self.cache
+            .lock()
+            .await
+            .insert(tenant_id.to_string(), (workflows.clone(), Instant::now()));
+
+        Ok(workflows)
+    }
+}
@@ -0,0 +1,66 @@
+use crm_events::CrmEvent;
+use redis::aio::Connection;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+const DUE_RUNS_KEY: &str = "workflow_runs:due";
+
+/// A workflow run suspended at a delay step. The run body lives at
+/// `workflow_run:{run_id}` and its resume time is the score in the
+/// `workflow_runs:due` sorted set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowRun {
+    pub run_id: String,
+    pub tenant_id: String,
+    pub workflow_id: String,
+    /// The event that started the run; every step sees the same trigger.
+    pub event: CrmEvent,
+    /// Index of the step to execute on resume.
+    pub next_step: usize,
+}
+
+pub struct RunStore {
+    redis_connection: Mutex<Connection>,
+}
+
+impl RunStore {
+    pub fn new(redis_connection: Connection) -> Self {
+        RunStore {
+            redis_connection: Mutex::new(redis_connection),
+        }
+    }
+
+    pub async fn suspend(&self, run: &WorkflowRun, resume_at: i64) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let body = serde_json::to_string(run)?;
+        let mut conn = self.redis_connection.lock().await;
+
+        let _: () = conn.set(format!("workflow_run:{}", run.run_id), body).await?;
+        let _: () = conn.zadd(DUE_RUNS_KEY, &run.run_id, resume_at).await?;
+        Ok(())
+    }
+
+    /// Claims up to `limit` runs whose resume time has passed. Removing a run
+    /// from the due set is the claim, so only one replica resumes each run.
+    pub async fn claim_due(&self, now: i64, limit: isize) -> Result<Vec<WorkflowRun>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut conn = self.redis_connection.lock().await;
+        let due: Vec<String> = conn.zrangebyscore_limit(DUE_RUNS_KEY, "-inf", now, 0, limit).await?;
+
+        let mut runs = Vec::new();
+        for run_id in due {
+            let claimed: i64 = conn.zrem(DUE_RUNS_KEY, &run_id).await?;
+            if claimed == 0 {
+                continue;
+            }
+
+            let key = format!("workflow_run:{}", run_id);
+            let body: Option<String> = conn.get(&key).await?;
+            let _: () = conn.del(&key).await?;
+            if let Some(body) = body {
+                runs.push(serde_json::from_str(&body)?);
+            }
+        }
+
+        Ok(runs)
+    }
+}